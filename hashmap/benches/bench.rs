@@ -1,10 +1,16 @@
 use core::hint::black_box;
 use core::time::Duration;
+use std::collections::hash_map::RandomState;
 use std::collections::{HashMap, HashSet};
 
 use criterion::measurement::Measurement;
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-use hashmap::open_addressing::{cuckoo, linear_probing, quadratic_probing, robin_hood};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use hashmap::hash::{FnvBuildHasher, FxBuildHasher};
+use hashmap::open_addressing::{
+    coalesced, cuckoo, double_hashing, hopscotch, incremental, linear_probing, quadratic_probing,
+    robin_hood, soa, swiss_table,
+};
+use hashmap::small_map::SmallMap;
 use rand::seq::IteratorRandom;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
@@ -85,6 +91,13 @@ fn insert<M: Measurement>(c: &mut Criterion<M>) {
                 lf,
                 linear_probing::HashMap
             );
+            bench!(
+                lf "incremental_linear_probing",
+                count,
+                keys.clone(),
+                lf,
+                incremental::HashMap
+            );
             bench!(
                 lf "quadratic_probing",
                 count,
@@ -92,8 +105,37 @@ fn insert<M: Measurement>(c: &mut Criterion<M>) {
                 lf,
                 quadratic_probing::HashMap
             );
+            bench!(
+                lf "double_hashing",
+                count,
+                keys.clone(),
+                lf,
+                double_hashing::HashMap
+            );
+            bench!(
+                lf "hopscotch",
+                count,
+                keys.clone(),
+                lf,
+                hopscotch::HashMap
+            );
+            bench!(
+                lf "swiss_table",
+                count,
+                keys.clone(),
+                lf,
+                swiss_table::HashMap
+            );
+            bench!(
+                lf "coalesced",
+                count,
+                keys.clone(),
+                lf,
+                coalesced::HashMap
+            );
             bench!(lf "robin_hood", count, keys.clone(), lf, robin_hood::HashMap);
-            bench!(lf "cuckoo", count, keys.clone(), lf, cuckoo::HashMap);
+            bench!(lf "cuckoo", count, keys.clone(), lf, cuckoo::HashMap::<_, _, 1>);
+            bench!(lf "cuckoo_4", count, keys.clone(), lf, cuckoo::HashMap::<_, _, 4>);
         }
 
         bench!(
@@ -102,6 +144,18 @@ fn insert<M: Measurement>(c: &mut Criterion<M>) {
             keys.clone(),
             hashmap::chaining::vecs::HashMap
         );
+        bench!(
+            new "chaining_linked_lists",
+            count,
+            keys.clone(),
+            hashmap::chaining::linked_lists::HashMap
+        );
+        bench!(
+            new "chaining_linear_hashing",
+            count,
+            keys.clone(),
+            hashmap::chaining::linear_hashing::HashMap
+        );
         count = (count as f64 * 1.05) as usize;
     }
 }
@@ -160,6 +214,15 @@ fn get<M: Measurement>(c: &mut Criterion<M>) {
                 lf,
                 linear_probing::HashMap
             );
+            bench_get!(lf
+                g,
+                "incremental_linear_probing",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                incremental::HashMap
+            );
             bench_get!(lf
                 g,
                 "quadratic_probing",
@@ -169,6 +232,42 @@ fn get<M: Measurement>(c: &mut Criterion<M>) {
                 lf,
                 quadratic_probing::HashMap
             );
+            bench_get!(lf
+                g,
+                "double_hashing",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                double_hashing::HashMap
+            );
+            bench_get!(lf
+                g,
+                "hopscotch",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                hopscotch::HashMap
+            );
+            bench_get!(lf
+                g,
+                "swiss_table",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                swiss_table::HashMap
+            );
+            bench_get!(lf
+                g,
+                "coalesced",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                coalesced::HashMap
+            );
             bench_get!(lf
                 g,
                 "robin_hood",
@@ -185,7 +284,16 @@ fn get<M: Measurement>(c: &mut Criterion<M>) {
                 keys.clone(),
                 access_keys,
                 lf,
-                cuckoo::HashMap
+                cuckoo::HashMap::<_, _, 1>
+            );
+            bench_get!(lf
+                g,
+                "cuckoo_4",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                cuckoo::HashMap::<_, _, 4>
             );
         }
         bench_get!(new
@@ -196,6 +304,22 @@ fn get<M: Measurement>(c: &mut Criterion<M>) {
             access_keys,
             hashmap::chaining::vecs::HashMap
         );
+        bench_get!(new
+            g,
+            "chaining_linked_lists",
+            count,
+            keys.clone(),
+            access_keys,
+            hashmap::chaining::linked_lists::HashMap
+        );
+        bench_get!(new
+            g,
+            "chaining_linear_hashing",
+            count,
+            keys.clone(),
+            access_keys,
+            hashmap::chaining::linear_hashing::HashMap
+        );
         count = (count as f64 * 1.05) as usize;
     }
 }
@@ -220,6 +344,15 @@ fn get_non_existing<M: Measurement>(c: &mut Criterion<M>) {
                 lf,
                 linear_probing::HashMap
             );
+            bench_get!(lf
+                g,
+                "incremental_linear_probing",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                incremental::HashMap
+            );
             bench_get!(lf
                 g,
                 "quadratic_probing",
@@ -229,6 +362,42 @@ fn get_non_existing<M: Measurement>(c: &mut Criterion<M>) {
                 lf,
                 quadratic_probing::HashMap
             );
+            bench_get!(lf
+                g,
+                "double_hashing",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                double_hashing::HashMap
+            );
+            bench_get!(lf
+                g,
+                "hopscotch",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                hopscotch::HashMap
+            );
+            bench_get!(lf
+                g,
+                "swiss_table",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                swiss_table::HashMap
+            );
+            bench_get!(lf
+                g,
+                "coalesced",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                coalesced::HashMap
+            );
             bench_get!(lf
                 g,
                 "robin_hood",
@@ -245,7 +414,16 @@ fn get_non_existing<M: Measurement>(c: &mut Criterion<M>) {
                 keys.clone(),
                 access_keys,
                 lf,
-                cuckoo::HashMap
+                cuckoo::HashMap::<_, _, 1>
+            );
+            bench_get!(lf
+                g,
+                "cuckoo_4",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                cuckoo::HashMap::<_, _, 4>
             );
         }
         bench_get!(new
@@ -256,6 +434,22 @@ fn get_non_existing<M: Measurement>(c: &mut Criterion<M>) {
             access_keys,
             hashmap::chaining::vecs::HashMap
         );
+        bench_get!(new
+            g,
+            "chaining_linked_lists",
+            count,
+            keys.clone(),
+            access_keys,
+            hashmap::chaining::linked_lists::HashMap
+        );
+        bench_get!(new
+            g,
+            "chaining_linear_hashing",
+            count,
+            keys.clone(),
+            access_keys,
+            hashmap::chaining::linear_hashing::HashMap
+        );
         count = (count as f64 * 1.05) as usize;
     }
 }
@@ -319,6 +513,15 @@ fn remove<M: Measurement>(c: &mut Criterion<M>) {
                 lf,
                 linear_probing::HashMap
             );
+            bench!(
+                lf
+                "incremental_linear_probing",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                incremental::HashMap
+            );
             bench!(
                 lf
                 "quadratic_probing",
@@ -328,6 +531,42 @@ fn remove<M: Measurement>(c: &mut Criterion<M>) {
                 lf,
                 quadratic_probing::HashMap
             );
+            bench!(
+                lf
+                "double_hashing",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                double_hashing::HashMap
+            );
+            bench!(
+                lf
+                "hopscotch",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                hopscotch::HashMap
+            );
+            bench!(
+                lf
+                "swiss_table",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                swiss_table::HashMap
+            );
+            bench!(
+                lf
+                "coalesced",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                coalesced::HashMap
+            );
             bench!(lf
                 "robin_hood",
                 count,
@@ -343,7 +582,16 @@ fn remove<M: Measurement>(c: &mut Criterion<M>) {
                 keys.clone(),
                 access_keys,
                 lf,
-                cuckoo::HashMap
+                cuckoo::HashMap::<_, _, 1>
+            );
+            bench_get!(lf
+                g,
+                "cuckoo_4",
+                count,
+                keys.clone(),
+                access_keys,
+                lf,
+                cuckoo::HashMap::<_, _, 4>
             );
         }
         bench!(
@@ -353,10 +601,324 @@ fn remove<M: Measurement>(c: &mut Criterion<M>) {
             access_keys,
             hashmap::chaining::vecs::HashMap
         );
+        bench!(
+            "chaining_linked_lists",
+            count,
+            keys.clone(),
+            access_keys,
+            hashmap::chaining::linked_lists::HashMap
+        );
+        bench!(
+            "chaining_linear_hashing",
+            count,
+            keys.clone(),
+            access_keys,
+            hashmap::chaining::linear_hashing::HashMap
+        );
+        count = (count as f64 * 1.05) as usize;
+    }
+}
+
+fn clone<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("clone_{}", MEASUREMENT_KIND));
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $keys:expr, $($map:tt)*) => {
+            let mut map = $($map)*::with_capacity($count);
+            for x in $keys {
+                map.insert(x, x);
+            }
+
+            g.bench_with_input(BenchmarkId::new($name, $count), &$count, |b, _c| {
+                b.iter(|| black_box(&map).clone())
+            });
+        };
+    }
+
+    let mut count = 1000;
+    for _ in 0..40 {
+        let keys = gen_unique_keys_int(count, true, i32::MAX / 2);
+        let keys = keys.iter().copied();
+
+        bench!("std", count, keys.clone(), HashMap);
+        bench!(
+            "linear_probing",
+            count,
+            keys.clone(),
+            linear_probing::HashMap
+        );
+        bench!(
+            "quadratic_probing",
+            count,
+            keys.clone(),
+            quadratic_probing::HashMap
+        );
+        bench!(
+            "double_hashing",
+            count,
+            keys.clone(),
+            double_hashing::HashMap
+        );
+        bench!("hopscotch", count, keys.clone(), hopscotch::HashMap);
+        bench!("swiss_table", count, keys.clone(), swiss_table::HashMap);
+        bench!("coalesced", count, keys.clone(), coalesced::HashMap);
+        bench!("robin_hood", count, keys.clone(), robin_hood::HashMap);
+        bench!("cuckoo", count, keys.clone(), cuckoo::HashMap::<_, _, 1>);
+        bench!("cuckoo_4", count, keys.clone(), cuckoo::HashMap::<_, _, 4>);
+        count = (count as f64 * 1.05) as usize;
+    }
+}
+
+/// Same maps with different hashers, the difference to `RandomState` is the
+/// cost of hashing.
+fn hashers<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("hashers_{}", MEASUREMENT_KIND));
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $keys:expr, $access_keys:expr, $hasher:expr, $($map:tt)*) => {
+            g.bench_with_input(BenchmarkId::new(format!("insert_{}", $name), $count), &$count, |b, _i| {
+                b.iter(|| {
+                    let mut map = $($map)*::with_hasher($hasher);
+                    for x in $keys {
+                        map.insert(x, x);
+                    }
+                    map
+                })
+            });
+
+            let mut map = $($map)*::with_capacity_and_hasher($count, $hasher);
+            for x in $keys {
+                map.insert(x, x);
+            }
+            g.bench_with_input(BenchmarkId::new(format!("get_{}", $name), $count), &$count, |b, _c| {
+                b.iter(|| {
+                    for k in $access_keys.iter() {
+                        black_box(map.get(black_box(k)));
+                    }
+                })
+            });
+        };
+    }
+
+    let mut count = 1000;
+    for _ in 0..40 {
+        let keys = gen_unique_keys_int(count, true, i32::MAX / 2);
+        let keys = keys.iter().copied();
+        let access_keys = sample_nonoverlapping_keys_valid(keys.clone(), count);
+
+        bench!(
+            "std_sip",
+            count,
+            keys.clone(),
+            access_keys,
+            RandomState::new(),
+            HashMap
+        );
+        bench!(
+            "std_fx",
+            count,
+            keys.clone(),
+            access_keys,
+            FxBuildHasher::default(),
+            HashMap
+        );
+        bench!(
+            "std_fnv",
+            count,
+            keys.clone(),
+            access_keys,
+            FnvBuildHasher::default(),
+            HashMap
+        );
+        bench!(
+            "linear_probing_sip",
+            count,
+            keys.clone(),
+            access_keys,
+            RandomState::new(),
+            linear_probing::HashMap
+        );
+        bench!(
+            "linear_probing_fx",
+            count,
+            keys.clone(),
+            access_keys,
+            FxBuildHasher::default(),
+            linear_probing::HashMap
+        );
+        bench!(
+            "linear_probing_fnv",
+            count,
+            keys.clone(),
+            access_keys,
+            FnvBuildHasher::default(),
+            linear_probing::HashMap
+        );
+        bench!(
+            "robin_hood_sip",
+            count,
+            keys.clone(),
+            access_keys,
+            RandomState::new(),
+            robin_hood::HashMap
+        );
+        bench!(
+            "robin_hood_fx",
+            count,
+            keys.clone(),
+            access_keys,
+            FxBuildHasher::default(),
+            robin_hood::HashMap
+        );
+        bench!(
+            "robin_hood_fnv",
+            count,
+            keys.clone(),
+            access_keys,
+            FnvBuildHasher::default(),
+            robin_hood::HashMap
+        );
+        bench!(
+            "swiss_table_sip",
+            count,
+            keys.clone(),
+            access_keys,
+            RandomState::new(),
+            swiss_table::HashMap
+        );
+        bench!(
+            "swiss_table_fx",
+            count,
+            keys.clone(),
+            access_keys,
+            FxBuildHasher::default(),
+            swiss_table::HashMap
+        );
+        bench!(
+            "swiss_table_fnv",
+            count,
+            keys.clone(),
+            access_keys,
+            FnvBuildHasher::default(),
+            swiss_table::HashMap
+        );
         count = (count as f64 * 1.05) as usize;
     }
 }
 
+/// Array-of-structs vs structure-of-arrays layout of the same linear probing
+/// scheme, the values are large so that only few buckets fit in a cache line
+/// with the former.
+fn layout<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("layout_{}", MEASUREMENT_KIND));
+
+    type Value = [u64; 16];
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $keys:expr, $access_keys:expr, $invalid_keys:expr, $($map:tt)*) => {
+            g.bench_with_input(BenchmarkId::new(format!("insert_{}", $name), $count), &$count, |b, _i| {
+                b.iter(|| {
+                    let mut map = $($map)*::new();
+                    for x in $keys {
+                        map.insert(x, [x as u64; 16]);
+                    }
+                    map
+                })
+            });
+
+            let mut map = $($map)*::<i32, Value>::with_capacity($count);
+            for x in $keys {
+                map.insert(x, [x as u64; 16]);
+            }
+            g.bench_with_input(BenchmarkId::new(format!("get_{}", $name), $count), &$count, |b, _c| {
+                b.iter(|| {
+                    for k in $access_keys.iter() {
+                        black_box(map.get(black_box(k)));
+                    }
+                })
+            });
+            g.bench_with_input(BenchmarkId::new(format!("get_non_existing_{}", $name), $count), &$count, |b, _c| {
+                b.iter(|| {
+                    for k in $invalid_keys.iter() {
+                        black_box(map.get(black_box(k)));
+                    }
+                })
+            });
+        };
+    }
+
+    let mut count = 1000;
+    for _ in 0..40 {
+        let unique_keys = gen_unique_keys_int(count, true, i32::MAX / 2);
+        let keys = unique_keys.iter().copied();
+        let access_keys = sample_nonoverlapping_keys_valid(keys.clone(), count);
+        let invalid_keys = sample_nonoverlapping_keys_invalid(&unique_keys, count);
+
+        bench!(
+            "std",
+            count,
+            keys.clone(),
+            access_keys,
+            invalid_keys,
+            HashMap
+        );
+        bench!(
+            "linear_probing",
+            count,
+            keys.clone(),
+            access_keys,
+            invalid_keys,
+            linear_probing::HashMap
+        );
+        bench!(
+            "soa",
+            count,
+            keys.clone(),
+            access_keys,
+            invalid_keys,
+            soa::HashMap
+        );
+        count = (count as f64 * 1.05) as usize;
+    }
+}
+
+/// Tiny maps where the cost of hashing dominates, `SmallMap` stays inline up
+/// to 8 pairs.
+fn small<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("small_{}", MEASUREMENT_KIND));
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $keys:expr, $($map:tt)*) => {
+            g.bench_with_input(BenchmarkId::new($name, $count), &$count, |b, _i| {
+                b.iter(|| {
+                    let mut map = $($map)*::new();
+                    for x in $keys {
+                        map.insert(x, x);
+                    }
+                    for x in $keys {
+                        black_box(map.get(black_box(&x)));
+                    }
+                    map
+                })
+            });
+        };
+    }
+
+    for count in 1..=16 {
+        let keys = gen_unique_keys_int(count, true, i32::MAX / 2);
+        let keys = keys.iter().copied();
+
+        bench!("std", count, keys.clone(), HashMap);
+        bench!(
+            "linear_probing",
+            count,
+            keys.clone(),
+            linear_probing::HashMap
+        );
+        bench!("small_map", count, keys.clone(), SmallMap::<_, _, 8>);
+    }
+}
+
 pub fn gen_unique_keys_int(count: usize, random: bool, key_max: i32) -> HashSet<i32> {
     let mut set = HashSet::with_capacity(count);
     if random {
@@ -406,6 +968,6 @@ criterion_group!(
         .warm_up_time(Duration::from_millis(1000))
         .with_measurement(create_measurement())
         ;
-    targets = get, get_non_existing, insert, remove
+    targets = clone, get, get_non_existing, hashers, insert, layout, remove, small
 );
 criterion_main!(benches);
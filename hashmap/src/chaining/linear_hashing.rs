@@ -0,0 +1,378 @@
+//! Hash map with linear hashing
+//!
+//! The table grows one bucket at a time. The `split` pointer walks over the
+//! buckets of the current round and each step splits the bucket under it into
+//! itself and one new bucket at the end of the table. Keys whose bucket under
+//! the current round's mask is before `split` use the next round's mask.
+//! Thus no insert ever rehashes more than a single chain.
+//!
+//! The bucket directory is a `Vec` which may still reallocate, but that only
+//! moves the chain headers, no keys are rehashed.
+
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::mem;
+use std::collections::hash_map::RandomState;
+
+type Chain<K, V> = Vec<(K, V)>;
+
+#[derive(Debug, Clone)]
+pub struct HashMap<K, V> {
+    // INVARIANTS:
+    //  * `buf.len() == (INITIAL_CAP << level) + split` once the map has
+    //    allocated, 0 before that
+    //  * `split < INITIAL_CAP << level`
+    buf: Vec<Chain<K, V>>,
+    /// Number of times the table has doubled
+    level: u32,
+    /// Next bucket to split
+    split: usize,
+    len: usize,
+    hash_builder: RandomState,
+    marker: PhantomData<Chain<K, V>>,
+}
+
+impl<K, V> Default for HashMap<K, V>
+where
+    K: Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> HashMap<K, V>
+where
+    K: Hash,
+{
+    const CRIT_LOAD_FACTOR: f64 = 2.0;
+    const INITIAL_CAP: usize = 4;
+
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            level: 0,
+            split: 0,
+            len: 0,
+            hash_builder: RandomState::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates a new hash map whose bucket directory can grow to hold at
+    /// least `capacity` pairs without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let buckets = (capacity as f64 / Self::CRIT_LOAD_FACTOR).ceil() as usize + 1;
+        Self {
+            buf: Vec::with_capacity(buckets),
+            level: 0,
+            split: 0,
+            len: 0,
+            hash_builder: RandomState::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes allocated for the buckets and the chains, including
+    /// their unused capacity.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        let chains: usize = self
+            .buf
+            .iter()
+            .map(|c| c.capacity() * mem::size_of::<(K, V)>())
+            .sum();
+        self.buf.capacity() * mem::size_of::<Chain<K, V>>() + chains
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)>
+    where
+        K: Eq,
+    {
+        if self.buf.is_empty() {
+            self.buf
+                .extend((0..Self::INITIAL_CAP).map(|_| Chain::new()));
+        }
+
+        let hash = self.hash_key(&key);
+        let index = self.get_index(hash);
+        let chain = &mut self.buf[index];
+        let pair = (key, value);
+        match chain.iter_mut().find(|(k, _)| k == &pair.0) {
+            Some(existing) => {
+                let old = mem::replace(existing, pair);
+                Some(old)
+            }
+            None => {
+                chain.push(pair);
+                self.len += 1;
+                if self.load_factor() > Self::CRIT_LOAD_FACTOR {
+                    self.split_next();
+                }
+                None
+            }
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let index = self.get_index(hash);
+        let chain = &self.buf[index];
+        chain
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(k, v)| (k, v))
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let index = self.get_index(hash);
+        let chain = &mut self.buf[index];
+        chain
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let index = self.get_index(hash);
+        let chain = &mut self.buf[index];
+
+        let pos = chain.iter().position(|(k, _)| k.borrow() == key);
+        pos.map(|pos| {
+            self.len -= 1;
+            chain.swap_remove(pos)
+        })
+    }
+
+    /// Number of buckets at the start of the current round.
+    fn round_cap(&self) -> usize {
+        Self::INITIAL_CAP << self.level
+    }
+
+    fn get_index(&self, hash: u64) -> usize {
+        let round_cap = self.round_cap();
+        let index = (hash & (round_cap - 1) as u64) as usize;
+        if index < self.split {
+            // already split in this round
+            (hash & (2 * round_cap - 1) as u64) as usize
+        } else {
+            index
+        }
+    }
+
+    fn hash_key<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash,
+    {
+        self.hash_builder.hash_one(key)
+    }
+
+    fn load_factor(&self) -> f64 {
+        if self.buf.is_empty() {
+            return f64::INFINITY;
+        }
+
+        self.len as f64 / self.buf.len() as f64
+    }
+
+    /// Splits the bucket at `self.split` into itself and a new bucket at the end.
+    fn split_next(&mut self) {
+        let round_cap = self.round_cap();
+        debug_assert_eq!(self.buf.len(), round_cap + self.split);
+
+        let old = mem::take(&mut self.buf[self.split]);
+        self.buf.push(Chain::new());
+        self.split += 1;
+        if self.split == round_cap {
+            self.level += 1;
+            self.split = 0;
+        }
+
+        for (k, v) in old {
+            let hash = self.hash_key(&k);
+            let index = self.get_index(hash);
+            self.buf[index].push((k, v));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn insert() {
+        let mut m = HashMap::<i32, i32>::new();
+        assert!(m.is_empty());
+        m.insert(1, 11);
+        assert_eq!(m.len(), 1);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        assert_eq!(m.len(), 4);
+        m.insert(4, 41);
+        println!("{m:?}");
+
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+
+        assert_eq!(m.insert(4, 42), Some((4, 41)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn remove() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.remove(&2), Some((2, 21)));
+        assert_eq!(m.remove(&2), None);
+
+        assert_eq!(m.remove(&1), Some((1, 11)));
+        assert_eq!(m.remove(&1), None);
+
+        assert_eq!(m.remove(&3), Some((3, 31)));
+        assert_eq!(m.remove(&3), None);
+
+        assert_eq!(m.remove(&4), Some((4, 41)));
+        assert_eq!(m.remove(&4), None);
+
+        assert_eq!(m.remove(&5), Some((5, 51)));
+        assert_eq!(m.remove(&5), None);
+
+        assert!(m.is_empty())
+    }
+
+    #[test]
+    fn get() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get_key_value(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn grows_one_bucket_at_a_time() {
+        let mut m = HashMap::new();
+        let mut buckets = 0;
+        for i in 0..1000 {
+            m.insert(i, i);
+            assert!(m.buf.len() <= buckets.max(HashMap::<i32, i32>::INITIAL_CAP) + 1);
+            assert_eq!(m.buf.len(), m.round_cap() + m.split);
+            buckets = m.buf.len();
+        }
+        assert!(m.load_factor() <= HashMap::<i32, i32>::CRIT_LOAD_FACTOR);
+
+        // every key is in the bucket it's looked up from
+        for (index, chain) in m.buf.iter().enumerate() {
+            for (k, _) in chain {
+                assert_eq!(m.get_index(m.hash_key(k)), index);
+            }
+        }
+        for i in 0..1000 {
+            assert_eq!(m.get(&i), Some(&i));
+        }
+        for i in 0..1000 {
+            assert_eq!(m.remove(&i), Some((i, i)));
+        }
+        assert!(m.is_empty());
+    }
+}
@@ -0,0 +1,377 @@
+//! Hash map with chaining linked lists
+
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::mem;
+use std::collections::hash_map::RandomState;
+
+use linked_list::SinglyLinkedList;
+
+type Chain<K, V> = SinglyLinkedList<(K, V)>;
+
+#[derive(Debug)]
+pub struct HashMap<K, V> {
+    buf: Vec<Chain<K, V>>,
+    cap: usize,
+    len: usize,
+    hash_builder: RandomState,
+    marker: PhantomData<Chain<K, V>>,
+}
+
+impl<K, V> Default for HashMap<K, V>
+where
+    K: Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Clone for HashMap<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        let buf = self
+            .buf
+            .iter()
+            .map(|chain| {
+                let mut new = Chain::new();
+                for (k, v) in chain {
+                    new.push_front((k.clone(), v.clone()));
+                }
+                new
+            })
+            .collect();
+
+        Self {
+            buf,
+            cap: self.cap,
+            len: self.len,
+            hash_builder: self.hash_builder.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V> HashMap<K, V>
+where
+    K: Hash,
+{
+    const CRIT_LOAD_FACTOR: f64 = 2.0;
+    const INITIAL_CAP: usize = 4;
+
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            cap: 0,
+            len: 0,
+            hash_builder: RandomState::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(capacity),
+            cap: 0,
+            len: 0,
+            hash_builder: RandomState::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes allocated for the buckets and the nodes of the chains.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        let chains: usize = self.buf.iter().map(Chain::allocated_bytes).sum();
+        self.buf.capacity() * mem::size_of::<Chain<K, V>>() + chains
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)>
+    where
+        K: Eq,
+    {
+        if self.load_factor() > Self::CRIT_LOAD_FACTOR {
+            self.grow()
+        }
+
+        let hash = self.hash_key(&key);
+        let index = self.get_index(hash);
+        let chain = &mut self.buf[index];
+        let pair = (key, value);
+        match chain.iter_mut().find(|(k, _)| k == &pair.0) {
+            Some(existing) => {
+                let old = mem::replace(existing, pair);
+                Some(old)
+            }
+            None => {
+                chain.push_front(pair);
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let index = self.get_index(hash);
+        let chain = &self.buf[index];
+        chain
+            .iter()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(k, v)| (k, v))
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let index = self.get_index(hash);
+        let chain = &mut self.buf[index];
+        chain
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let index = self.get_index(hash);
+        let mut cursor = self.buf[index].cursor_front_mut();
+
+        while let Some((k, _)) = cursor.current() {
+            if (*k).borrow() == key {
+                self.len -= 1;
+                return cursor.remove_current();
+            }
+            cursor.move_next();
+        }
+
+        None
+    }
+
+    #[inline]
+    fn mask(&self) -> usize {
+        self.cap - 1
+    }
+
+    fn get_index(&self, hash: u64) -> usize {
+        debug_assert!(self.cap < isize::MAX as usize);
+        debug_assert!(self.cap.is_power_of_two());
+        // SAFETY: cap <= isize::MAX, hence the result after modulo must be < isize::MAX
+        (hash & self.mask() as u64) as usize
+    }
+
+    fn hash_key<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash,
+    {
+        self.hash_builder.hash_one(key)
+    }
+
+    fn load_factor(&self) -> f64 {
+        if self.cap == 0 {
+            return f64::INFINITY;
+        }
+
+        self.len as f64 / self.cap as f64
+    }
+
+    fn grow(&mut self) {
+        let new_cap = if self.cap == 0 {
+            Self::INITIAL_CAP
+        } else {
+            2 * self.cap
+        };
+
+        let mut new_buf = Vec::new();
+        new_buf.reserve_exact(new_cap);
+
+        self.cap = new_cap;
+        assert!(self.cap <= new_buf.capacity());
+
+        for _ in 0..self.cap {
+            new_buf.push(Chain::new());
+        }
+
+        let old_buf = mem::replace(&mut self.buf, new_buf);
+        for mut chain in old_buf {
+            while let Some((k, v)) = chain.pop_front() {
+                let hash = self.hash_key(&k);
+                let index = self.get_index(hash);
+                self.buf[index].push_front((k, v));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn insert() {
+        let mut m = HashMap::<i32, i32>::new();
+        assert!(m.is_empty());
+        m.insert(1, 11);
+        assert_eq!(m.len(), 1);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        assert_eq!(m.len(), 4);
+        m.insert(4, 41);
+        println!("{m:?}");
+
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+
+        assert_eq!(m.insert(4, 42), Some((4, 41)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn remove() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.remove(&2), Some((2, 21)));
+        assert_eq!(m.remove(&2), None);
+
+        assert_eq!(m.remove(&1), Some((1, 11)));
+        assert_eq!(m.remove(&1), None);
+
+        assert_eq!(m.remove(&3), Some((3, 31)));
+        assert_eq!(m.remove(&3), None);
+
+        assert_eq!(m.remove(&4), Some((4, 41)));
+        assert_eq!(m.remove(&4), None);
+
+        assert_eq!(m.remove(&5), Some((5, 51)));
+        assert_eq!(m.remove(&5), None);
+
+        assert!(m.is_empty())
+    }
+
+    #[test]
+    fn get() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get_key_value(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn grow_and_clone() {
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i, i);
+        }
+        for i in (0..1000).step_by(3) {
+            assert_eq!(m.remove(&i), Some((i, i)));
+        }
+
+        let c = m.clone();
+        assert_eq!(c.len(), m.len());
+        for i in 0..1000 {
+            let expected = (i % 3 != 0).then_some(&i);
+            assert_eq!(m.get(&i), expected);
+            assert_eq!(c.get(&i), expected);
+        }
+    }
+}
@@ -1 +1,3 @@
+pub mod linear_hashing;
+pub mod linked_lists;
 pub mod vecs;
@@ -1,7 +1,7 @@
 //! Hash map with chaining vecs
 
 use core::borrow::Borrow;
-use core::hash::{BuildHasher, Hash, Hasher};
+use core::hash::{BuildHasher, Hash};
 use core::marker::PhantomData;
 use core::mem;
 use std::collections::hash_map::RandomState;
@@ -17,6 +17,15 @@ pub struct HashMap<K, V> {
     marker: PhantomData<Chain<K, V>>,
 }
 
+impl<K, V> Default for HashMap<K, V>
+where
+    K: Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K, V> HashMap<K, V>
 where
     K: Hash,
@@ -52,6 +61,19 @@ where
         self.len == 0
     }
 
+    /// Number of bytes allocated for the buckets and the chains, including
+    /// their unused capacity.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        let chains: usize = self
+            .buf
+            .iter()
+            .map(|c| c.capacity() * mem::size_of::<(K, V)>())
+            .sum();
+        self.buf.capacity() * mem::size_of::<Chain<K, V>>() + chains
+    }
+
     pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)>
     where
         K: Eq,
@@ -77,7 +99,17 @@ where
         }
     }
 
-    pub fn get<Q>(&mut self, key: &Q) -> Option<(&K, &V)>
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
     where
         K: Borrow<Q>,
         Q: Eq + Hash,
@@ -95,6 +127,33 @@ where
             .map(|(k, v)| (k, v))
     }
 
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let index = self.get_index(hash);
+        let chain = &mut self.buf[index];
+        chain
+            .iter_mut()
+            .find(|(k, _)| k.borrow() == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).is_some()
+    }
+
     pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
     where
         K: Borrow<Q>,
@@ -131,9 +190,7 @@ where
     where
         Q: Hash,
     {
-        let mut hasher = self.hash_builder.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.hash_builder.hash_one(key)
     }
 
     fn load_factor(&self) -> f64 {
@@ -192,15 +249,29 @@ mod tests {
         m.insert(4, 41);
         println!("{m:?}");
 
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
 
         assert_eq!(m.insert(4, 42), Some((4, 41)));
-        assert_eq!(m.get(&4), Some((&4, &42)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn allocated_bytes() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        let chains: usize = m.buf.iter().map(Vec::capacity).sum();
+        assert!(chains >= 100);
+        assert_eq!(
+            m.allocated_bytes(),
+            m.buf.capacity() * mem::size_of::<Chain<i32, i32>>() + chains * 8
+        );
     }
 
     #[test]
@@ -235,7 +306,7 @@ mod tests {
     #[test]
     fn get() {
         let mut m = HashMap::new();
-        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_key_value(&1), None);
 
         m.insert(1, 11);
         m.insert(2, 21);
@@ -243,11 +314,34 @@ mod tests {
         m.insert(5, 51);
         m.insert(4, 41);
 
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
     }
 }
@@ -0,0 +1,291 @@
+//! Multiset which counts the occurrences of every key
+
+use core::borrow::Borrow;
+use core::cmp::Reverse;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+
+use crate::open_addressing::robin_hood::HashMap;
+
+/// Map from keys to the number of times they have been added.
+///
+/// Keys whose count drops to zero are removed from the map.
+#[derive(Clone)]
+pub struct CountingMap<K, S = RandomState> {
+    // INVARIANTS:
+    //  * every count in `map` is > 0
+    //  * `total` is the sum of all counts
+    map: HashMap<K, usize, S>,
+    total: usize,
+}
+
+impl<K> Default for CountingMap<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> CountingMap<K> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, S> CountingMap<K, S> {
+    /// Creates a new map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            map: HashMap::with_hasher(hash_builder),
+            total: 0,
+        }
+    }
+
+    /// Number of distinct keys
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Sum of the counts of all keys
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Iterate over all distinct keys and their counts in arbitrary order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&K, usize)> + '_ {
+        self.map.iter().map(|(k, c)| (k, *c))
+    }
+
+    /// Returns up to `n` keys with the largest counts, from the largest count
+    /// to the smallest.
+    ///
+    /// The order of keys with equal counts is unspecified.
+    pub fn most_common(&self, n: usize) -> Vec<(&K, usize)> {
+        let entries: Vec<_> = self.iter().collect();
+        // sort the positions instead of the entries so that K doesn't need to be Ord
+        let mut order: Vec<_> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, (_, c))| (Reverse(*c), i))
+            .collect();
+
+        let n = n.min(order.len());
+        sort::heapsort::partial_sort(&mut order, n);
+        order[..n].iter().map(|(_, i)| entries[*i]).collect()
+    }
+}
+
+impl<K, S> CountingMap<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Adds one occurrence of `key` and returns its new count.
+    pub fn add(&mut self, key: K) -> usize {
+        self.add_n(key, 1)
+    }
+
+    /// Adds `n` occurrences of `key` and returns its new count.
+    pub fn add_n(&mut self, key: K, n: usize) -> usize {
+        if n == 0 {
+            return self.count(&key);
+        }
+
+        self.total += n;
+        let count = self.map.entry(key).or_insert(0);
+        *count += n;
+        *count
+    }
+
+    /// Returns the number of occurrences of `key`, 0 if it's not present.
+    pub fn count<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.map.get(key).copied().unwrap_or(0)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Removes one occurrence of `key` and returns its remaining count.
+    ///
+    /// Returns `None` if the key is not present.
+    pub fn remove_one<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let count = self.map.get_mut(key)?;
+        *count -= 1;
+        let count = *count;
+        if count == 0 {
+            self.map.remove(key);
+        }
+        self.total -= 1;
+        Some(count)
+    }
+
+    /// Removes all occurrences of `key` and returns how many there were.
+    pub fn remove_all<Q>(&mut self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let count = self.map.remove(key).map_or(0, |(_, c)| c);
+        self.total -= count;
+        count
+    }
+}
+
+impl<K, S> fmt::Debug for CountingMap<K, S>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, S> FromIterator<K> for CountingMap<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut map = Self::with_hasher(S::default());
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, S> Extend<K> for CountingMap<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for k in iter {
+            self.add(k);
+        }
+    }
+}
+
+impl<K, S> Extend<(K, usize)> for CountingMap<K, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = (K, usize)>>(&mut self, iter: I) {
+        for (k, n) in iter {
+            self.add_n(k, n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_count_remove() {
+        let mut m = CountingMap::new();
+        assert!(m.is_empty());
+        assert_eq!(m.count(&"a"), 0);
+
+        assert_eq!(m.add("a"), 1);
+        assert_eq!(m.add("b"), 1);
+        assert_eq!(m.add("a"), 2);
+        assert_eq!(m.add_n("c", 3), 3);
+        assert_eq!(m.add_n("d", 0), 0);
+        assert!(!m.contains_key(&"d"));
+        assert_eq!(m.len(), 3);
+        assert_eq!(m.total(), 6);
+
+        assert_eq!(m.remove_one(&"a"), Some(1));
+        assert_eq!(m.remove_one(&"a"), Some(0));
+        assert!(!m.contains_key(&"a"));
+        assert_eq!(m.remove_one(&"a"), None);
+
+        assert_eq!(m.remove_all(&"c"), 3);
+        assert_eq!(m.remove_all(&"c"), 0);
+        assert_eq!(m.total(), 1);
+        assert_eq!(m.iter().collect::<Vec<_>>(), [(&"b", 1)]);
+    }
+
+    #[test]
+    fn most_common() {
+        let m: CountingMap<_> = "abracadabra".chars().collect();
+        let top = m.most_common(3);
+        assert_eq!(top[0], (&'a', 5));
+        assert!(top[1..] == [(&'b', 2), (&'r', 2)] || top[1..] == [(&'r', 2), (&'b', 2)]);
+        assert_eq!(m.most_common(1), [(&'a', 5)]);
+        assert_eq!(m.most_common(0), []);
+        assert_eq!(m.most_common(10).len(), 5);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const OPS: usize = 1000;
+        #[cfg(miri)]
+        const OPS: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_array_model(
+                ops in proptest::collection::vec((any::<bool>(), 0..20i32), 0..OPS),
+                n in 0..25usize,
+            ) {
+                // model holds the count of key `i` at index `i`
+                let mut model = [0usize; 20];
+                let mut map = CountingMap::new();
+
+                for (is_add, key) in ops {
+                    let count = &mut model[key as usize];
+                    if is_add {
+                        *count += 1;
+                        assert_eq!(map.add(key), *count);
+                    } else if *count == 0 {
+                        assert_eq!(map.remove_one(&key), None);
+                    } else {
+                        *count -= 1;
+                        assert_eq!(map.remove_one(&key), Some(*count));
+                    }
+                }
+
+                assert_eq!(map.total(), model.iter().sum::<usize>());
+                assert_eq!(map.len(), model.iter().filter(|c| **c > 0).count());
+
+                let mut expected: Vec<_> = model.iter().copied().filter(|c| *c > 0).collect();
+                expected.sort_by(|a, b| b.cmp(a));
+                expected.truncate(n);
+                let top = map.most_common(n);
+                assert!(top.iter().map(|(_, c)| *c).eq(expected));
+                for (k, c) in top {
+                    assert_eq!(model[*k as usize], c);
+                }
+            }
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Bloom filter
+//!
+//! Every item sets `k` bits in a bit array of `m` bits. The `k` bit positions
+//! are derived from a single 64 bit hash by double hashing
+//! `g_i = h1 + i * h2 (mod m)`, where `h1` and `h2` are the low and high halves
+//! of the hash (Kirsch and Mitzenmacher, "Less Hashing, Same Performance").
+//! Items cannot be removed since the bits are shared between items.
+
+use core::f64::consts::LN_2;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use std::collections::hash_map::RandomState;
+
+#[derive(Debug, Clone)]
+pub struct BloomFilter<T: ?Sized, S = RandomState> {
+    // INVARIANTS:
+    //  * `bits.len() == m.div_ceil(64)`, `m > 0`
+    //  * `k > 0`
+    bits: Vec<u64>,
+    m: usize,
+    k: u32,
+    len: usize,
+    hash_builder: S,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: ?Sized> BloomFilter<T> {
+    /// Creates a filter sized for `expected_items` items with the false
+    /// positive rate of `fp_rate`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `fp_rate` is not in `(0, 1)`
+    pub fn new(expected_items: usize, fp_rate: f64) -> Self {
+        Self::with_hasher(expected_items, fp_rate, RandomState::new())
+    }
+}
+
+impl<T: ?Sized, S> BloomFilter<T, S> {
+    /// Creates a filter sized for `expected_items` items with the false
+    /// positive rate of `fp_rate`, which uses `hash_builder` to hash the items.
+    ///
+    /// The optimal number of bits is `m = -n ln(p) / ln(2)^2` and the optimal
+    /// number of hash functions `k = m / n ln(2)`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `fp_rate` is not in `(0, 1)`
+    pub fn with_hasher(expected_items: usize, fp_rate: f64, hash_builder: S) -> Self {
+        assert!(
+            fp_rate > 0.0 && fp_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+
+        let n = expected_items.max(1) as f64;
+        let m = (-n * fp_rate.ln() / (LN_2 * LN_2)).ceil().max(1.0);
+        let k = (m / n * LN_2).round().max(1.0);
+        Self::with_bits_and_hasher(m as usize, k as u32, hash_builder)
+    }
+
+    /// Creates a filter with `m` bits which sets `k` bits for every item.
+    ///
+    /// # PANICS
+    ///
+    /// * if `m == 0` or `k == 0`
+    pub fn with_bits_and_hasher(m: usize, k: u32, hash_builder: S) -> Self {
+        assert!(m > 0, "BloomFilter must have at least 1 bit");
+        assert!(k > 0, "BloomFilter must use at least 1 hash function");
+        Self {
+            bits: vec![0; m.div_ceil(64)],
+            m,
+            k,
+            len: 0,
+            hash_builder,
+            marker: PhantomData,
+        }
+    }
+
+    /// Number of insertions which set at least one new bit
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bits in the filter
+    pub fn bits(&self) -> usize {
+        self.m
+    }
+
+    /// Number of bits set for every item
+    pub fn hashes(&self) -> u32 {
+        self.k
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+        self.len = 0;
+    }
+
+    /// Estimated false positive rate with the current number of items,
+    /// `(1 - e^(-k n / m))^k`.
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let k = self.k as f64;
+        (1.0 - (-k * self.len as f64 / self.m as f64).exp()).powf(k)
+    }
+
+    /// Bit positions of the item with the given hash.
+    fn positions(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let m = self.m as u64;
+        let h1 = hash & 0xffff_ffff;
+        // odd, so that it's never 0 and all the positions differ if m is a power of two
+        let h2 = (hash >> 32) | 1;
+        (0..self.k as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % m) as usize)
+    }
+}
+
+impl<T, S> BloomFilter<T, S>
+where
+    T: ?Sized + Hash,
+    S: BuildHasher,
+{
+    /// Adds `item` to the filter.
+    ///
+    /// Returns `false` if all of its bits were already set, that is the item
+    /// was possibly already present.
+    pub fn insert(&mut self, item: &T) -> bool {
+        let hash = self.hash_builder.hash_one(item);
+        let mut new = false;
+        for pos in self.positions(hash) {
+            let (word, bit) = (pos / 64, 1 << (pos % 64));
+            new |= self.bits[word] & bit == 0;
+            self.bits[word] |= bit;
+        }
+
+        if new {
+            self.len += 1;
+        }
+        new
+    }
+
+    /// Returns `true` if the `item` may be in the filter, `false` if it
+    /// definitely isn't.
+    pub fn contains(&self, item: &T) -> bool {
+        let hash = self.hash_builder.hash_one(item);
+        self.positions(hash)
+            .all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizing() {
+        let f = BloomFilter::<i32>::new(1000, 0.01);
+        // ~9.6 bits per item and 7 hashes for 1%
+        assert_eq!(f.bits(), 9586);
+        assert_eq!(f.hashes(), 7);
+        assert!(f.is_empty());
+    }
+
+    #[test]
+    fn insert_contains() {
+        let mut f = BloomFilter::new(100, 0.01);
+        assert!(!f.contains("a"));
+        assert!(f.insert("a"));
+        assert!(!f.insert("a"));
+        assert!(f.insert("b"));
+        assert!(f.contains("a"));
+        assert!(f.contains("b"));
+        assert_eq!(f.len(), 2);
+
+        f.clear();
+        assert!(!f.contains("a"));
+        assert!(f.is_empty());
+    }
+
+    #[test]
+    fn fp_rate() {
+        const N: i32 = 10_000;
+        let mut f = BloomFilter::new(N as usize, 0.01);
+        for i in 0..N {
+            f.insert(&i);
+        }
+        for i in 0..N {
+            assert!(f.contains(&i));
+        }
+
+        let fps = (N..2 * N).filter(|i| f.contains(i)).count();
+        let rate = fps as f64 / N as f64;
+        assert!(rate < 0.02, "{rate}");
+        assert!(f.estimated_fp_rate() < 0.02, "{}", f.estimated_fp_rate());
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const SIZE: usize = 1000;
+        #[cfg(miri)]
+        const SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn no_false_negatives(
+                items in proptest::collection::vec(any::<u64>(), 0..SIZE),
+                m in 1..5000usize,
+                k in 1..10u32,
+            ) {
+                let mut f = BloomFilter::with_bits_and_hasher(m, k, RandomState::new());
+                for i in &items {
+                    f.insert(i);
+                }
+                for i in &items {
+                    assert!(f.contains(i));
+                }
+            }
+        );
+    }
+}
@@ -0,0 +1,373 @@
+//! Cuckoo filter
+//!
+//! Stores small fingerprints of the items in a cuckoo hash table with buckets
+//! of [`BUCKET_SIZE`] slots (Fan et al., "Cuckoo Filter: Practically Better
+//! Than Bloom"). Only the fingerprint is known when an item is displaced, so
+//! its alternative bucket is derived from the fingerprint alone by partial-key
+//! cuckoo hashing: `i2 = i1 ^ hash(fingerprint)`. This works in both
+//! directions, hence any fingerprint can be moved to its other bucket.
+//!
+//! When both buckets are full, a fingerprint is evicted to its other bucket,
+//! the evicted slot is varied between the rounds. After `MAX_KICKS` rounds
+//! the fingerprint which is left without a bucket is kept in a one item
+//! stash and further inserts fail.
+//!
+//! Unlike a Bloom filter, items can be removed. But only items which were
+//! inserted may be removed, otherwise a fingerprint of another item with the
+//! same fingerprint could be removed.
+
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use std::collections::hash_map::RandomState;
+
+/// Number of fingerprints in one bucket
+pub const BUCKET_SIZE: usize = 4;
+
+/// Fingerprints are never 0, that marks an empty slot.
+type Bucket = [u32; BUCKET_SIZE];
+
+/// The error type for [`CuckooFilter::insert`], the filter is too full to
+/// place any more fingerprints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterFullError;
+
+impl fmt::Display for FilterFullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cuckoo filter is full")
+    }
+}
+
+impl std::error::Error for FilterFullError {}
+
+#[derive(Debug, Clone)]
+pub struct CuckooFilter<T: ?Sized, S = RandomState> {
+    // INVARIANTS:
+    //  * `buckets.len()` is a power of two
+    //  * every fingerprint is in one of its two buckets or in `stash`
+    //  * `len` is the number of fingerprints including the stash
+    buckets: Vec<Bucket>,
+    fp_mask: u32,
+    /// Fingerprint which couldn't be placed and the bucket it was evicted from.
+    /// While it's occupied, no new fingerprints are accepted.
+    stash: Option<(usize, u32)>,
+    len: usize,
+    hash_builder: S,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: ?Sized> CuckooFilter<T> {
+    /// Creates a filter which can hold at least `capacity` items with the
+    /// false positive rate of at most `fp_rate`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `fp_rate` is not in `(0, 1)`
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_hasher(capacity, fp_rate, RandomState::new())
+    }
+}
+
+impl<T: ?Sized, S> CuckooFilter<T, S> {
+    const MAX_KICKS: usize = 500;
+    /// Load factor which the filter reaches reliably with 4 slots per bucket
+    const MAX_LOAD: f64 = 0.95;
+
+    /// Creates a filter which can hold at least `capacity` items with the
+    /// false positive rate of at most `fp_rate`, which uses `hash_builder` to
+    /// hash the items.
+    ///
+    /// A lookup compares the fingerprint against `2 * BUCKET_SIZE` slots,
+    /// thus `f` bit fingerprints give the false positive rate of about
+    /// `2 * BUCKET_SIZE / 2^f`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `fp_rate` is not in `(0, 1)`
+    pub fn with_hasher(capacity: usize, fp_rate: f64, hash_builder: S) -> Self {
+        assert!(
+            fp_rate > 0.0 && fp_rate < 1.0,
+            "false positive rate must be in (0, 1)"
+        );
+
+        let fp_bits = (2.0 * BUCKET_SIZE as f64 / fp_rate)
+            .log2()
+            .ceil()
+            .clamp(1.0, 32.0) as u32;
+        let buckets = (capacity as f64 / (BUCKET_SIZE as f64 * Self::MAX_LOAD)).ceil() as usize;
+        Self {
+            buckets: vec![[0; BUCKET_SIZE]; buckets.max(1).next_power_of_two()],
+            fp_mask: u32::MAX >> (32 - fp_bits),
+            stash: None,
+            len: 0,
+            hash_builder,
+            marker: PhantomData,
+        }
+    }
+
+    /// Number of fingerprints in the filter
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of fingerprint slots
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    /// Number of bits in one fingerprint
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.fp_mask.count_ones()
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.fill([0; BUCKET_SIZE]);
+        self.stash = None;
+        self.len = 0;
+    }
+
+    /// Returns the fingerprint and the first bucket of the item with `hash`.
+    fn fingerprint_and_index(&self, hash: u64) -> (u32, usize) {
+        let fp = (hash >> 32) as u32 & self.fp_mask;
+        let fp = if fp == 0 { 1 } else { fp };
+        (fp, hash as usize & self.mask())
+    }
+
+    /// The other bucket of the fingerprint `fp` in bucket `index`.
+    fn alt_index(&self, index: usize, fp: u32) -> usize {
+        // xor with the same value twice is identity, so this maps both buckets
+        // to each other
+        (index ^ (fp as u64).wrapping_mul(0x5bd1_e995) as usize) & self.mask()
+    }
+
+    fn mask(&self) -> usize {
+        self.buckets.len() - 1
+    }
+
+    fn bucket_contains(&self, index: usize, fp: u32) -> bool {
+        self.buckets[index].contains(&fp)
+    }
+
+    /// Puts `fp` into an empty slot of the bucket. Returns `false` if the
+    /// bucket is full.
+    fn put(&mut self, index: usize, fp: u32) -> bool {
+        match self.buckets[index].iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fp;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes one copy of `fp` from the bucket. Returns `false` if it's not
+    /// in the bucket.
+    fn take(&mut self, index: usize, fp: u32) -> bool {
+        match self.buckets[index].iter_mut().find(|slot| **slot == fp) {
+            Some(slot) => {
+                *slot = 0;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T, S> CuckooFilter<T, S>
+where
+    T: ?Sized + Hash,
+    S: BuildHasher,
+{
+    /// Adds `item` to the filter.
+    ///
+    /// Inserting the same item multiple times stores multiple copies of its
+    /// fingerprint, so it needs to be removed as many times. At most
+    /// `2 * BUCKET_SIZE` copies fit into the filter.
+    pub fn insert(&mut self, item: &T) -> Result<(), FilterFullError> {
+        if self.stash.is_some() {
+            return Err(FilterFullError);
+        }
+
+        let hash = self.hash_builder.hash_one(item);
+        let (mut fp, i1) = self.fingerprint_and_index(hash);
+        let i2 = self.alt_index(i1, fp);
+        self.len += 1;
+        if self.put(i1, fp) || self.put(i2, fp) {
+            return Ok(());
+        }
+
+        let mut index = if hash & (1 << 31) == 0 { i1 } else { i2 };
+        for round in 0..Self::MAX_KICKS {
+            let victim = (fp as usize).wrapping_add(round) % BUCKET_SIZE;
+            core::mem::swap(&mut fp, &mut self.buckets[index][victim]);
+            index = self.alt_index(index, fp);
+            if self.put(index, fp) {
+                return Ok(());
+            }
+        }
+
+        // The item itself is in the table now, only some other fingerprint is
+        // left over. It's still in the filter, so this insert succeeded.
+        self.stash = Some((index, fp));
+        Ok(())
+    }
+
+    /// Returns `true` if the `item` may be in the filter, `false` if it
+    /// definitely isn't.
+    pub fn contains(&self, item: &T) -> bool {
+        let hash = self.hash_builder.hash_one(item);
+        let (fp, i1) = self.fingerprint_and_index(hash);
+        let i2 = self.alt_index(i1, fp);
+        let in_stash = matches!(self.stash, Some((i, f)) if f == fp && (i == i1 || i == i2));
+        in_stash || self.bucket_contains(i1, fp) || self.bucket_contains(i2, fp)
+    }
+
+    /// Removes one copy of `item` from the filter. Returns `false` if it
+    /// wasn't found.
+    ///
+    /// Only items which were inserted may be removed.
+    pub fn remove(&mut self, item: &T) -> bool {
+        let hash = self.hash_builder.hash_one(item);
+        let (fp, i1) = self.fingerprint_and_index(hash);
+        let i2 = self.alt_index(i1, fp);
+
+        let removed = match self.stash {
+            Some((i, f)) if f == fp && (i == i1 || i == i2) => {
+                self.stash = None;
+                true
+            }
+            _ => self.take(i1, fp) || self.take(i2, fp),
+        };
+        if !removed {
+            return false;
+        }
+        self.len -= 1;
+
+        // a slot may have been freed for the stashed fingerprint
+        if let Some((i, f)) = self.stash {
+            let alt = self.alt_index(i, f);
+            if self.put(i, f) || self.put(alt, f) {
+                self.stash = None;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizing() {
+        let f = CuckooFilter::<i32>::new(1000, 0.01);
+        assert_eq!(f.fingerprint_bits(), 10);
+        assert_eq!(f.capacity(), 512 * BUCKET_SIZE);
+        assert!(f.is_empty());
+    }
+
+    #[test]
+    fn insert_contains_remove() {
+        let mut f = CuckooFilter::new(100, 0.001);
+        assert!(!f.contains("a"));
+        assert_eq!(f.insert("a"), Ok(()));
+        assert_eq!(f.insert("a"), Ok(()));
+        assert_eq!(f.insert("b"), Ok(()));
+        assert!(f.contains("a"));
+        assert!(f.contains("b"));
+        assert_eq!(f.len(), 3);
+
+        assert!(f.remove("a"));
+        assert!(f.contains("a"));
+        assert!(f.remove("a"));
+        assert!(!f.contains("a"));
+        assert!(!f.remove("a"));
+        assert!(f.contains("b"));
+
+        f.clear();
+        assert!(!f.contains("b"));
+        assert!(f.is_empty());
+    }
+
+    #[test]
+    fn fill_and_fp_rate() {
+        const N: u32 = 10_000;
+        let mut f = CuckooFilter::new(N as usize, 0.01);
+        let mut inserted = 0;
+        while f.insert(&inserted).is_ok() {
+            inserted += 1;
+        }
+        assert!(inserted >= N, "{inserted}");
+        assert!(f.load_factor() > 0.9, "{}", f.load_factor());
+        for i in 0..inserted {
+            assert!(f.contains(&i));
+        }
+
+        let fps = (inserted..inserted + N).filter(|i| f.contains(i)).count();
+        let rate = fps as f64 / N as f64;
+        assert!(rate < 0.02, "{rate}");
+
+        // removing makes room again
+        for i in 0..N / 2 {
+            assert!(f.remove(&i));
+        }
+        assert_eq!(f.insert(&0), Ok(()));
+        for i in N / 2..inserted {
+            assert!(f.contains(&i));
+        }
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const OPS: usize = 1000;
+        #[cfg(miri)]
+        const OPS: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn no_false_negatives(
+                ops in proptest::collection::vec((any::<bool>(), 0..500u32), 0..OPS),
+                fp_rate in 0.0001..0.5f64,
+            ) {
+                // model holds the number of copies of key `i` at index `i`
+                let mut model = vec![0u32; 500];
+                let mut f = CuckooFilter::new(2 * OPS, fp_rate);
+
+                for (is_insert, key) in ops {
+                    let copies = &mut model[key as usize];
+                    if is_insert && *copies < 2 {
+                        assert_eq!(f.insert(&key), Ok(()));
+                        *copies += 1;
+                    } else if !is_insert && *copies > 0 {
+                        assert!(f.remove(&key));
+                        *copies -= 1;
+                    }
+
+                    assert_eq!(f.len(), model.iter().sum::<u32>() as usize);
+                    for (k, _) in model.iter().enumerate().filter(|(_, c)| **c > 0) {
+                        assert!(f.contains(&(k as u32)));
+                    }
+                }
+            }
+        );
+    }
+}
@@ -0,0 +1,8 @@
+//! Probabilistic set membership filters
+//!
+//! The filters store only a few bits per item instead of the items themselves.
+//! `contains` never returns false for an inserted item, but it may return true
+//! for an item which was never inserted with a small, configurable probability.
+
+pub mod bloom;
+pub mod cuckoo;
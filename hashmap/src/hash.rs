@@ -0,0 +1,223 @@
+//! Fast non-cryptographic hashers
+//!
+//! [`RandomState`](std::collections::hash_map::RandomState) uses SipHash
+//! which is robust but slow for small keys. These hashers are much faster,
+//! but they are not seeded, so an adversary can easily pick colliding keys.
+//! They are useful to separate the cost of hashing from the cost of probing.
+//!
+//! The open addressing maps take the `BuildHasher` as the `S` parameter.
+//! Cuckoo hashing is the exception, it needs to reseed its two hashers when
+//! the pairs cannot be placed, so it always uses `RandomState`.
+//!
+//! ```
+//! use hashmap::hash::FxBuildHasher;
+//! use hashmap::open_addressing::linear_probing::HashMap;
+//!
+//! let mut map = HashMap::with_hasher(FxBuildHasher::default());
+//! map.insert(1, 2);
+//! assert_eq!(map.get(&1), Some(&2));
+//! ```
+
+use core::hash::{BuildHasherDefault, Hasher};
+
+/// `BuildHasher` of [`FxHasher`]
+pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+/// `BuildHasher` of [`FnvHasher`]
+pub type FnvBuildHasher = BuildHasherDefault<FnvHasher>;
+
+/// The hasher used by rustc.
+///
+/// Every word of input is mixed in with a rotate, xor and multiply. Low bits
+/// of the product only depend on the low bits of the input, so the result is
+/// rotated in `finish` to bring the well mixed high bits down, the maps use
+/// the low bits to pick the buckets.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FxHasher {
+    hash: u64,
+}
+
+impl FxHasher {
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+    #[inline]
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(Self::SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.add_to_hash(u64::from_le_bytes(chunk.try_into().unwrap()));
+        }
+
+        let rest = chunks.remainder();
+        if !rest.is_empty() {
+            let mut word = [0; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            // mix in the length so that trailing zeros are not lost
+            self.add_to_hash(u64::from_le_bytes(word) ^ ((rest.len() as u64) << 56));
+        }
+    }
+
+    #[inline]
+    fn write_u8(&mut self, i: u8) {
+        self.add_to_hash(i as u64);
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16) {
+        self.add_to_hash(i as u64);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.add_to_hash(i as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.add_to_hash(i);
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.add_to_hash(i as u64);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.hash.rotate_left(26)
+    }
+}
+
+/// 64 bit FNV-1a hasher.
+///
+/// Hashes one byte at a time, so it's fast for short keys but slow for long
+/// ones.
+#[derive(Debug, Clone, Copy)]
+pub struct FnvHasher {
+    hash: u64,
+}
+
+impl FnvHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+}
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self {
+            hash: Self::OFFSET_BASIS,
+        }
+    }
+}
+
+impl Hasher for FnvHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.hash ^= b as u64;
+            self.hash = self.hash.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// `BuildHasher` of [`PrehashedHasher`]
+pub(crate) type PrehashedBuildHasher = BuildHasherDefault<PrehashedHasher>;
+
+/// Passes an already computed hash written with `write_u64` through as is.
+///
+/// Used for the internal index maps whose keys store the hash of some other
+/// value, so that growing them doesn't need to hash those values again.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PrehashedHasher(u64);
+
+impl Hasher for PrehashedHasher {
+    fn write(&mut self, _bytes: &[u8]) {
+        unreachable!("only precomputed hashes are written")
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i;
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::BuildHasher;
+
+    use super::*;
+    use crate::open_addressing::{linear_probing, robin_hood, swiss_table};
+
+    fn fnv(bytes: &[u8]) -> u64 {
+        let mut h = FnvHasher::default();
+        h.write(bytes);
+        h.finish()
+    }
+
+    #[test]
+    fn fnv_reference_values() {
+        assert_eq!(fnv(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn fx_is_deterministic() {
+        let a = FxBuildHasher::default();
+        let b = FxBuildHasher::default();
+        assert_eq!(a.hash_one("hello"), b.hash_one("hello"));
+        assert_eq!(a.hash_one(42u64), b.hash_one(42u64));
+        assert_ne!(a.hash_one("hello"), a.hash_one("hellp"));
+        // trailing zero bytes change the hash
+        assert_ne!(
+            a.hash_one([1u8, 0].as_slice()),
+            a.hash_one([1u8].as_slice())
+        );
+    }
+
+    #[test]
+    fn fx_low_bits_differ() {
+        // aligned keys (like pointers) have their low bits all zeros, they
+        // must not all land in the same bucket
+        let h = FxBuildHasher::default();
+        let buckets = (0..64u64)
+            .map(|i| h.hash_one(i << 12) & 63)
+            .collect::<std::collections::HashSet<_>>();
+        assert!(buckets.len() > 32, "{}", buckets.len());
+    }
+
+    #[test]
+    fn maps_with_hasher() {
+        let mut lin = linear_probing::HashMap::with_hasher(FxBuildHasher::default());
+        let mut rh = robin_hood::HashMap::with_capacity_and_hasher(10, FnvBuildHasher::default());
+        let mut swiss: swiss_table::HashMap<_, _, FxBuildHasher> =
+            (0..10).map(|i| (i, i)).collect();
+        for i in 0..1000 {
+            lin.insert(i, i);
+            rh.insert(i, i);
+            swiss.insert(i, i);
+        }
+        for i in 0..1000 {
+            assert_eq!(lin.get(&i), Some(&i));
+            assert_eq!(rh.get(&i), Some(&i));
+            assert_eq!(swiss[&i], i);
+        }
+        let lin2 = lin.clone();
+        assert_eq!(lin2.get(&999), Some(&999));
+    }
+}
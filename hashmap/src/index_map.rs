@@ -0,0 +1,623 @@
+//! Insertion order preserving hash map
+//!
+//! The pairs are stored densely in a [`Vec2`] in the order they were inserted.
+//! A linear probing map acts as the index from the keys to the positions in
+//! that `Vec2`. The index only stores the hashes and the positions, the keys
+//! are compared through the raw entry API.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash, Hasher};
+use core::mem;
+use std::collections::hash_map::RandomState;
+
+use vec::Vec2;
+
+use crate::hash::PrehashedBuildHasher;
+use crate::open_addressing::linear_probing::{self, RawEntryMut};
+
+/// Position of a pair in `IndexMap::entries`
+///
+/// Only the hash of the key is hashed, so the index can find the slot by the
+/// key's hash and the position can be changed in place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+    hash: u64,
+    index: usize,
+}
+
+impl Hash for Slot {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+type Indices = linear_probing::HashMap<Slot, (), PrehashedBuildHasher>;
+
+#[derive(Debug, Clone)]
+struct Bucket<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// Hash map which iterates in the order the keys were inserted.
+///
+/// Removal either swaps the last pair into the removed position
+/// ([`IndexMap::swap_remove`], O(1)) or shifts all the following pairs
+/// ([`IndexMap::shift_remove`], O(n)) which keeps the order.
+#[derive(Clone)]
+pub struct IndexMap<K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * `indices` holds exactly one slot `{ hash, index }` for every
+    //    `entries[index]`, `hash` is the hash of its key
+    entries: Vec2<Bucket<K, V>>,
+    indices: Indices,
+    hash_builder: S,
+}
+
+impl<K, V> Default for IndexMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> IndexMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    /// Creates a new map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> IndexMap<K, V, S> {
+    /// Creates a new map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new map with capacity to store at least `capacity` pairs
+    /// without reallocation, which uses `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            entries: Vec2::with_capacity(capacity),
+            indices: Indices::with_capacity_and_hasher(capacity, Default::default()),
+            hash_builder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the pair at position `index` in the insertion order.
+    pub fn get_index(&self, index: usize) -> Option<(&K, &V)> {
+        self.entries.get(index).map(|b| (&b.key, &b.value))
+    }
+
+    /// Returns the pair at position `index` with a mutable reference to the value.
+    pub fn get_index_mut(&mut self, index: usize) -> Option<(&K, &mut V)> {
+        self.entries.get_mut(index).map(|b| (&b.key, &mut b.value))
+    }
+
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.get_index(0)
+    }
+
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.entries.as_slice().last().map(|b| (&b.key, &b.value))
+    }
+
+    /// Removes the last pair.
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        let b = self.entries.pop()?;
+        let index = self.entries.len();
+        self.indices.remove(&Slot {
+            hash: b.hash,
+            index,
+        });
+        Some((b.key, b.value))
+    }
+
+    /// Removes the pair at position `index`, the last pair takes its place.
+    pub fn swap_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        let hash = self.entries.get(index)?.hash;
+        self.indices.remove(&Slot { hash, index });
+
+        let last = self.entries.len() - 1;
+        if index != last {
+            self.move_slot(self.entries.as_slice()[last].hash, last, index);
+        }
+        let b = self.entries.swap_remove(index)?;
+        Some((b.key, b.value))
+    }
+
+    /// Removes the pair at position `index`, all the following pairs are
+    /// moved one position down.
+    pub fn shift_remove_index(&mut self, index: usize) -> Option<(K, V)> {
+        let hash = self.entries.get(index)?.hash;
+        self.indices.remove(&Slot { hash, index });
+
+        let b = self.entries.remove(index)?;
+        for i in index..self.entries.len() {
+            self.move_slot(self.entries.as_slice()[i].hash, i + 1, i);
+        }
+        Some((b.key, b.value))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.indices.drain();
+    }
+
+    /// Iterate over all key-value pairs in insertion order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.entries.as_slice().iter(),
+        }
+    }
+
+    /// Iterate over all key-value pairs in insertion order with mutable
+    /// references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            inner: self.entries.as_mut_slice().iter_mut(),
+        }
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator + '_ {
+        self.entries.as_slice().iter().map(|b| &b.key)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator + '_ {
+        self.entries.as_slice().iter().map(|b| &b.value)
+    }
+
+    pub fn values_mut(
+        &mut self,
+    ) -> impl DoubleEndedIterator<Item = &mut V> + ExactSizeIterator + '_ {
+        self.entries.as_mut_slice().iter_mut().map(|b| &mut b.value)
+    }
+
+    /// Changes the position in the slot of the pair with `hash` from `from`
+    /// to `to`.
+    fn move_slot(&mut self, hash: u64, from: usize, to: usize) {
+        let slot = Slot { hash, index: from };
+        match self.indices.raw_entry_mut().from_key(&slot) {
+            // the hash of the slot doesn't change, so it can be updated in place
+            RawEntryMut::Occupied(mut e) => e.get_key_value_mut().0.index = to,
+            RawEntryMut::Vacant(_) => unreachable!("every pair has a slot"),
+        }
+    }
+}
+
+impl<K, V, S> IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Inserts the pair at the end of the map.
+    ///
+    /// If the key is already present, the pair is replaced but it keeps its
+    /// position and the old pair is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let hash = self.hash_builder.hash_one(&key);
+        let entries = &mut self.entries;
+        let entry = self.indices.raw_entry_mut().from_hash(hash, |s| {
+            s.hash == hash && entries.as_slice()[s.index].key == key
+        });
+        match entry {
+            RawEntryMut::Occupied(e) => {
+                let b = &mut entries.as_mut_slice()[e.key().index];
+                let old_key = mem::replace(&mut b.key, key);
+                let old_value = mem::replace(&mut b.value, value);
+                Some((old_key, old_value))
+            }
+            RawEntryMut::Vacant(e) => {
+                e.insert(
+                    Slot {
+                        hash,
+                        index: entries.len(),
+                    },
+                    (),
+                );
+                entries.push(Bucket { hash, key, value });
+                None
+            }
+        }
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.get_index_of(key)?;
+        self.get_index(index)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.get_index_of(key)?;
+        Some(&mut self.entries.as_mut_slice()[index].value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_index_of(key).is_some()
+    }
+
+    /// Returns the position of the key in the insertion order.
+    pub fn get_index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_builder.hash_one(key);
+        let entries = &self.entries;
+        self.indices
+            .raw_entry()
+            .from_hash(hash, |s| {
+                s.hash == hash && entries.as_slice()[s.index].key.borrow() == key
+            })
+            .map(|(s, _)| s.index)
+    }
+
+    /// Removes the pair by swapping the last pair into its position.
+    ///
+    /// This is O(1) but it changes the order of the pairs. Use
+    /// [`IndexMap::shift_remove`] to keep the order.
+    pub fn swap_remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.get_index_of(key)?;
+        self.swap_remove_index(index)
+    }
+
+    /// Removes the pair by shifting all the following pairs one position down.
+    ///
+    /// This keeps the order of the pairs but it's O(n).
+    pub fn shift_remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.get_index_of(key)?;
+        self.shift_remove_index(index)
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional);
+        self.indices.reserve(additional);
+    }
+}
+
+impl<K, V, S> fmt::Debug for IndexMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut map = Self::with_capacity_and_hasher(iter.size_hint().0, S::default());
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let additional = if self.is_empty() {
+            iter.size_hint().0
+        } else {
+            iter.size_hint().0.div_ceil(2)
+        };
+        self.reserve(additional);
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K, V, Q, S> core::ops::Index<&Q> for IndexMap<K, V, S>
+where
+    K: Borrow<Q> + Hash + Eq,
+    Q: Hash + Eq,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    /// # PANICS
+    ///
+    /// * if the key is not present in the map
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("key not found")
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a IndexMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut IndexMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for IndexMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.entries.into_iter(),
+        }
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    inner: core::slice::Iter<'a, Bucket<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|b| (&b.key, &b.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|b| (&b.key, &b.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+pub struct IterMut<'a, K, V> {
+    inner: core::slice::IterMut<'a, Bucket<K, V>>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|b| (&b.key, &mut b.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|b| (&b.key, &mut b.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+pub struct IntoIter<K, V> {
+    inner: vec::IntoIter<Bucket<K, V>>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|b| (b.key, b.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|b| (b.key, b.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs<K: Clone, V: Clone, S>(m: &IndexMap<K, V, S>) -> Vec<(K, V)> {
+        m.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    #[test]
+    fn insert_keeps_order() {
+        let mut m = IndexMap::new();
+        assert!(m.is_empty());
+        assert_eq!(m.insert(3, 31), None);
+        assert_eq!(m.insert(1, 11), None);
+        assert_eq!(m.insert(2, 21), None);
+        assert_eq!(pairs(&m), [(3, 31), (1, 11), (2, 21)]);
+
+        // replacing keeps the position
+        assert_eq!(m.insert(1, 12), Some((1, 11)));
+        assert_eq!(pairs(&m), [(3, 31), (1, 12), (2, 21)]);
+        assert_eq!(m.len(), 3);
+
+        assert_eq!(m.get(&1), Some(&12));
+        assert_eq!(m.get_index_of(&2), Some(2));
+        assert_eq!(m.get_index(0), Some((&3, &31)));
+        assert_eq!(m.first(), Some((&3, &31)));
+        assert_eq!(m.last(), Some((&2, &21)));
+        assert_eq!(m.get(&4), None);
+        assert!(!m.contains_key(&4));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m[&3], 32);
+    }
+
+    #[test]
+    fn swap_remove() {
+        let mut m: IndexMap<_, _> = (0..5).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.swap_remove(&1), Some((1, 10)));
+        assert_eq!(pairs(&m), [(0, 0), (4, 40), (2, 20), (3, 30)]);
+        assert_eq!(m.get_index_of(&4), Some(1));
+        assert_eq!(m.swap_remove(&1), None);
+
+        // removing the last one doesn't move anything
+        assert_eq!(m.swap_remove(&3), Some((3, 30)));
+        assert_eq!(pairs(&m), [(0, 0), (4, 40), (2, 20)]);
+        assert_eq!(m.pop(), Some((2, 20)));
+        assert_eq!(m.get(&2), None);
+        assert_eq!(m.get(&4), Some(&40));
+    }
+
+    #[test]
+    fn shift_remove() {
+        let mut m: IndexMap<_, _> = (0..5).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.shift_remove(&1), Some((1, 10)));
+        assert_eq!(pairs(&m), [(0, 0), (2, 20), (3, 30), (4, 40)]);
+        for (i, k) in [0, 2, 3, 4].iter().enumerate() {
+            assert_eq!(m.get_index_of(k), Some(i));
+        }
+        assert_eq!(m.shift_remove_index(0), Some((0, 0)));
+        assert_eq!(m.shift_remove_index(3), None);
+        assert_eq!(pairs(&m), [(2, 20), (3, 30), (4, 40)]);
+    }
+
+    #[test]
+    fn iterators() {
+        let mut m: IndexMap<_, _> = [("c", 3), ("a", 1), ("b", 2)].into_iter().collect();
+        assert!(m.keys().eq(["c", "a", "b"].iter()));
+        assert!(m.values().rev().eq([2, 1, 3].iter()));
+        for v in m.values_mut() {
+            *v *= 10;
+        }
+        for (_, v) in &mut m {
+            *v += 1;
+        }
+        assert_eq!(format!("{m:?}"), r#"{"c": 31, "a": 11, "b": 21}"#);
+        assert_eq!(m.iter().len(), 3);
+
+        let c = m.clone();
+        m.clear();
+        assert!(m.is_empty());
+        assert_eq!(m.get(&"a"), None);
+        assert_eq!(
+            c.into_iter().collect::<Vec<_>>(),
+            [("c", 31), ("a", 11), ("b", 21)]
+        );
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const OPS: usize = 1000;
+        #[cfg(miri)]
+        const OPS: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_vec_model(
+                ops in proptest::collection::vec((0..3u8, 0..40i32), 0..OPS),
+            ) {
+                // model keeps the pairs in insertion order
+                let mut model: Vec<(i32, i32)> = Vec::new();
+                let mut map = IndexMap::new();
+
+                for (op, key) in ops {
+                    let pos = model.iter().position(|(k, _)| *k == key);
+                    match op {
+                        0 => {
+                            let old = match pos {
+                                Some(i) => Some(mem::replace(&mut model[i], (key, key * 2))),
+                                None => {
+                                    model.push((key, key * 2));
+                                    None
+                                }
+                            };
+                            assert_eq!(map.insert(key, key * 2), old);
+                        }
+                        1 => assert_eq!(map.swap_remove(&key), pos.map(|i| model.swap_remove(i))),
+                        _ => assert_eq!(map.shift_remove(&key), pos.map(|i| model.remove(i))),
+                    }
+
+                    assert!(map.iter().map(|(k, v)| (*k, *v)).eq(model.iter().copied()));
+                    for (i, (k, _)) in model.iter().enumerate() {
+                        assert_eq!(map.get_index_of(k), Some(i));
+                    }
+                }
+            }
+        );
+    }
+}
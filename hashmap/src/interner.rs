@@ -0,0 +1,286 @@
+//! String interner
+//!
+//! Every distinct string is stored once and identified by a small [`Symbol`].
+//! Comparing and hashing symbols is much cheaper than doing that for the
+//! strings.
+
+use core::fmt;
+use core::hash::{BuildHasher, Hash, Hasher};
+use std::collections::hash_map::RandomState;
+
+use crate::hash::PrehashedBuildHasher;
+use crate::open_addressing::linear_probing::{self, RawEntryMut};
+
+/// Id of an interned string.
+///
+/// Symbols are handed out in the order the strings are first interned,
+/// starting from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Key in the lookup map, the string itself is in `Interner::strings`.
+///
+/// Only the hash of the string is hashed, so the map can be searched by the
+/// hash of a `&str`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Key {
+    hash: u64,
+    symbol: Symbol,
+}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+#[derive(Clone)]
+pub struct Interner<S = RandomState> {
+    // INVARIANTS:
+    //  * `map` holds exactly one key `{ hash, symbol }` for every
+    //    `strings[symbol]`, `hash` is the hash of the string
+    //  * all strings are distinct
+    //
+    // Strings are boxed separately, so they don't move when `strings` grows.
+    strings: Vec<Box<str>>,
+    map: linear_probing::HashMap<Key, (), PrehashedBuildHasher>,
+    hash_builder: S,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    /// Creates a new interner with capacity to store at least `capacity`
+    /// strings without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<S> Interner<S> {
+    /// Creates a new interner which uses `hash_builder` to hash the strings.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new interner with capacity to store at least `capacity`
+    /// strings without reallocation, which uses `hash_builder` to hash the
+    /// strings.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            strings: Vec::with_capacity(capacity),
+            map: linear_probing::HashMap::with_capacity_and_hasher(capacity, Default::default()),
+            hash_builder,
+        }
+    }
+
+    /// Number of distinct strings
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Returns the string of `symbol`, `None` if the symbol is not from this
+    /// interner.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.strings.get(symbol.index()).map(|s| &**s)
+    }
+
+    /// Iterate over all symbols and their strings in the order of the symbols.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (Symbol, &str)> + '_ {
+        self.strings
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (Symbol(i as u32), &**s))
+    }
+}
+
+impl<S> Interner<S>
+where
+    S: BuildHasher,
+{
+    /// Returns the symbol of `s`, interning it if it's new.
+    ///
+    /// The string is only copied if it's not interned yet.
+    ///
+    /// # PANICS
+    ///
+    /// * if there are more than `u32::MAX` distinct strings
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        let hash = self.hash_builder.hash_one(s);
+        let strings = &mut self.strings;
+        let entry = self
+            .map
+            .raw_entry_mut()
+            .from_hash(hash, |k| k.hash == hash && *strings[k.symbol.index()] == *s);
+        match entry {
+            RawEntryMut::Occupied(e) => e.key().symbol,
+            RawEntryMut::Vacant(e) => {
+                let symbol = u32::try_from(strings.len()).expect("too many interned strings");
+                let symbol = Symbol(symbol);
+                strings.push(s.into());
+                e.insert(Key { hash, symbol }, ());
+                symbol
+            }
+        }
+    }
+
+    /// Returns the symbol of `s` if it's interned.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_builder.hash_one(s);
+        let strings = &self.strings;
+        self.map
+            .raw_entry()
+            .from_hash(hash, |k| k.hash == hash && *strings[k.symbol.index()] == *s)
+            .map(|(k, _)| k.symbol)
+    }
+}
+
+impl<S> fmt::Debug for Interner<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, S> Extend<&'a str> for Interner<S>
+where
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = &'a str>>(&mut self, iter: I) {
+        for s in iter {
+            self.intern(s);
+        }
+    }
+}
+
+impl<'a, S> FromIterator<&'a str> for Interner<S>
+where
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        let mut interner = Self::with_hasher(S::default());
+        interner.extend(iter);
+        interner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_resolve() {
+        let mut i = Interner::new();
+        assert!(i.is_empty());
+        assert_eq!(i.get("a"), None);
+
+        let a = i.intern("a");
+        let b = i.intern("b");
+        assert_ne!(a, b);
+        assert_eq!(i.intern("a"), a);
+        assert_eq!(i.intern(&String::from("b")), b);
+        assert_eq!(i.len(), 2);
+        assert_eq!((a.as_u32(), b.as_u32()), (0, 1));
+
+        assert_eq!(i.get("a"), Some(a));
+        assert_eq!(i.get("c"), None);
+        assert_eq!(i.resolve(a), Some("a"));
+        assert_eq!(i.resolve(b), Some("b"));
+        assert_eq!(i.resolve(Symbol(2)), None);
+
+        let empty = i.intern("");
+        assert_eq!(i.resolve(empty), Some(""));
+        assert_eq!(
+            format!("{i:?}"),
+            r#"{Symbol(0): "a", Symbol(1): "b", Symbol(2): ""}"#
+        );
+    }
+
+    #[test]
+    fn strings_dont_move() {
+        let mut i = Interner::new();
+        let first = i.intern("first");
+        let ptr = i.resolve(first).unwrap().as_ptr();
+        for n in 0..1000 {
+            i.intern(&n.to_string());
+        }
+        assert_eq!(i.resolve(first).unwrap().as_ptr(), ptr);
+        assert_eq!(i.len(), 1001);
+        for (sym, s) in i.iter().skip(1) {
+            assert_eq!(s.parse::<u32>().unwrap(), sym.as_u32() - 1);
+            assert_eq!(i.get(s), Some(sym));
+        }
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const OPS: usize = 1000;
+        #[cfg(miri)]
+        const OPS: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_vec_model(
+                strings in proptest::collection::vec("[a-c]{0,4}", 0..OPS),
+            ) {
+                // model holds the distinct strings in the order of their symbols
+                let mut model: Vec<String> = Vec::new();
+                let mut interner = Interner::new();
+
+                for s in strings {
+                    let expected = match model.iter().position(|m| *m == s) {
+                        Some(i) => i,
+                        None => {
+                            model.push(s.clone());
+                            model.len() - 1
+                        }
+                    };
+                    let symbol = interner.intern(&s);
+                    assert_eq!(symbol.as_u32() as usize, expected);
+                    assert_eq!(interner.resolve(symbol), Some(&*s));
+                }
+
+                assert_eq!(interner.len(), model.len());
+                for (i, s) in model.iter().enumerate() {
+                    assert_eq!(interner.get(s), Some(Symbol(i as u32)));
+                }
+            }
+        );
+    }
+}
@@ -3,4 +3,12 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
 pub mod chaining;
+pub mod counting_map;
+pub mod filter;
+pub mod hash;
+pub mod index_map;
+pub mod interner;
+pub mod lru;
 pub mod open_addressing;
+pub mod set;
+pub mod small_map;
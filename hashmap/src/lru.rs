@@ -0,0 +1,368 @@
+//! Least recently used (LRU) cache built from a doubly linked list and a hash map
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+
+use linked_list::LinkedList;
+use linked_list::doubly_linked_list::NodeHandle;
+
+use crate::open_addressing::robin_hood::HashMap;
+
+/// Cache which holds at most `cap` entries and evicts the least recently used
+/// entry when a new one is inserted into a full cache.
+///
+/// Both lookups and insertions are O(1): the map points to the list nodes,
+/// which are kept in the order of their last use.
+pub struct LruCache<K, V> {
+    // Robin hood map uses backward shift deletion, so the frequent removals
+    // don't leave tombstones behind which would slow down the lookups.
+    //
+    // INVARIANTS:
+    //  * every handle in `map` points to a node in `list` holding the same key
+    //  * `map.len() == list.len() <= cap`
+    map: HashMap<K, NodeHandle<(K, V)>>,
+    /// Front is the least recently used entry, back the most recently used
+    list: LinkedList<(K, V)>,
+    cap: usize,
+    on_evict: Option<Box<dyn FnMut(K, V)>>,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// # PANICS
+    ///
+    /// * if `cap == 0`
+    pub fn new(cap: usize) -> Self {
+        assert!(cap > 0, "LruCache capacity must be larger than 0");
+        Self {
+            map: HashMap::with_capacity(cap),
+            list: LinkedList::new(),
+            cap,
+            on_evict: None,
+        }
+    }
+
+    /// Creates a cache which calls `on_evict` with every entry which is evicted
+    /// due to the capacity limit. Entries removed explicitly are not passed to it.
+    ///
+    /// # PANICS
+    ///
+    /// * if `cap == 0`
+    pub fn with_eviction_callback<F>(cap: usize, on_evict: F) -> Self
+    where
+        F: FnMut(K, V) + 'static,
+    {
+        let mut s = Self::new(cap);
+        s.on_evict = Some(Box::new(on_evict));
+        s
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn cap(&self) -> usize {
+        self.cap
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Returns the value for `key` and marks it as the most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let handle = self.touch(key)?;
+        // SAFETY: all handles in `self.map` point to nodes in `self.list` (see INVARIANTS)
+        Some(unsafe { &self.list.get_handle(handle).1 })
+    }
+
+    /// Returns the value for `key` and marks it as the most recently used.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let handle = self.touch(key)?;
+        // SAFETY: all handles in `self.map` point to nodes in `self.list` (see INVARIANTS)
+        Some(unsafe { &mut self.list.get_handle_mut(handle).1 })
+    }
+
+    /// Returns the value for `key` without changing its recency.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let handle = self.map.get(key)?;
+        // SAFETY: all handles in `self.map` point to nodes in `self.list` (see INVARIANTS)
+        Some(unsafe { &self.list.get_handle(*handle).1 })
+    }
+
+    /// Returns the least recently used entry without changing its recency.
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        self.list.front().map(|(k, v)| (k, v))
+    }
+
+    /// Inserts `key` and `value` as the most recently used entry.
+    ///
+    /// If the key already exists, its value is replaced and the old value is returned.
+    /// Otherwise if the cache is full, the least recently used entry is evicted.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(handle) = self.touch(&key) {
+            // SAFETY: all handles in `self.map` point to nodes in `self.list` (see INVARIANTS)
+            let entry = unsafe { self.list.get_handle_mut(handle) };
+            return Some(core::mem::replace(&mut entry.1, value));
+        }
+
+        if self.len() == self.cap {
+            self.evict_lru();
+        }
+
+        let handle = self.list.push_back_handle((key.clone(), value));
+        self.map.insert(key, handle);
+        None
+    }
+
+    /// Removes the entry for `key`.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let (_, handle) = self.map.remove(key)?;
+        // SAFETY: all handles in `self.map` point to nodes in `self.list` (see INVARIANTS)
+        let (_, v) = unsafe { self.list.remove_handle(handle) };
+        Some(v)
+    }
+
+    /// Removes and returns the least recently used entry.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let (k, v) = self.list.pop_front()?;
+        self.map.remove(&k);
+        Some((k, v))
+    }
+
+    /// Changes the capacity of the cache.
+    ///
+    /// If the cache holds more than `cap` entries, the least recently used
+    /// ones are evicted and passed to the eviction callback.
+    ///
+    /// # PANICS
+    ///
+    /// * if `cap == 0`
+    pub fn set_cap(&mut self, cap: usize) {
+        assert!(cap > 0, "LruCache capacity must be larger than 0");
+        self.cap = cap;
+        while self.len() > cap {
+            self.evict_lru();
+        }
+    }
+
+    /// Iterate over entries from the least recently used to the most recently used.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, &V)> + '_ {
+        self.list.iter().map(|(k, v)| (k, v))
+    }
+
+    /// Removes the least recently used entry and passes it to the eviction callback.
+    fn evict_lru(&mut self) {
+        if let Some((k, v)) = self.pop_lru() {
+            if let Some(on_evict) = self.on_evict.as_mut() {
+                on_evict(k, v);
+            }
+        }
+    }
+
+    /// Marks `key` as the most recently used entry and returns its node.
+    fn touch<Q>(&mut self, key: &Q) -> Option<NodeHandle<(K, V)>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let handle = self.map.get(key)?;
+        let handle = *handle;
+        // SAFETY: all handles in `self.map` point to nodes in `self.list` (see INVARIANTS)
+        unsafe { self.list.move_to_back_handle(handle) };
+        Some(handle)
+    }
+}
+
+impl<K, V> fmt::Debug for LruCache<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LruCache")
+            .field("cap", &self.cap)
+            .field("entries", &self.list.iter())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    fn lru_order<K: Hash + Eq + Clone, V>(c: &LruCache<K, V>) -> Vec<K> {
+        c.iter().map(|(k, _)| k.clone()).collect()
+    }
+
+    #[test]
+    fn put_get() {
+        let mut c = LruCache::new(3);
+        assert!(c.is_empty());
+        assert_eq!(c.get(&1), None);
+
+        assert_eq!(c.put(1, 11), None);
+        assert_eq!(c.put(2, 21), None);
+        assert_eq!(c.put(3, 31), None);
+        assert_eq!(lru_order(&c), [1, 2, 3]);
+
+        assert_eq!(c.get(&1), Some(&11));
+        assert_eq!(lru_order(&c), [2, 3, 1]);
+
+        assert_eq!(c.peek(&2), Some(&21));
+        assert_eq!(lru_order(&c), [2, 3, 1]);
+
+        assert_eq!(c.put(3, 32), Some(31));
+        assert_eq!(lru_order(&c), [2, 1, 3]);
+
+        *c.get_mut(&2).unwrap() = 22;
+        assert_eq!(lru_order(&c), [1, 3, 2]);
+        assert_eq!(c.peek(&2), Some(&22));
+        assert_eq!(c.len(), 3);
+    }
+
+    #[test]
+    fn evict() {
+        let evicted = Rc::new(RefCell::new(Vec::new()));
+        let mut c = LruCache::with_eviction_callback(2, {
+            let evicted = Rc::clone(&evicted);
+            move |k, v| evicted.borrow_mut().push((k, v))
+        });
+
+        c.put(1, 11);
+        c.put(2, 21);
+        c.get(&1);
+        c.put(3, 31);
+        assert_eq!(*RefCell::borrow(&evicted), [(2, 21)]);
+        assert!(!c.contains_key(&2));
+        assert_eq!(lru_order(&c), [1, 3]);
+
+        c.put(4, 41);
+        assert_eq!(*RefCell::borrow(&evicted), [(2, 21), (1, 11)]);
+        assert_eq!(c.peek_lru(), Some((&3, &31)));
+        assert_eq!(c.len(), 2);
+    }
+
+    #[test]
+    fn set_cap() {
+        let evicted = Rc::new(RefCell::new(Vec::new()));
+        let mut c = LruCache::with_eviction_callback(4, {
+            let evicted = Rc::clone(&evicted);
+            move |k, _| evicted.borrow_mut().push(k)
+        });
+        for i in 0..4 {
+            c.put(i, i);
+        }
+        c.get(&0);
+
+        c.set_cap(2);
+        assert_eq!(c.cap(), 2);
+        assert_eq!(*RefCell::borrow(&evicted), [1, 2]);
+        assert_eq!(lru_order(&c), [3, 0]);
+
+        c.set_cap(3);
+        c.put(4, 4);
+        assert_eq!(lru_order(&c), [3, 0, 4]);
+        assert_eq!(RefCell::borrow(&evicted).len(), 2);
+    }
+
+    #[test]
+    fn remove() {
+        let mut c = LruCache::new(3);
+        c.put("a", 1);
+        c.put("b", 2);
+        c.put("c", 3);
+
+        assert_eq!(c.remove(&"b"), Some(2));
+        assert_eq!(c.remove(&"b"), None);
+        assert_eq!(c.pop_lru(), Some(("a", 1)));
+        assert_eq!(c.len(), 1);
+        assert!(c.contains_key(&"c"));
+        assert!(!c.contains_key(&"a"));
+
+        // removed entries don't count towards capacity
+        c.put("d", 4);
+        c.put("e", 5);
+        assert_eq!(lru_order(&c), ["c", "d", "e"]);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const OPS: usize = 1000;
+        #[cfg(miri)]
+        const OPS: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_vec_model(
+                cap in 1..20usize,
+                ops in proptest::collection::vec((any::<bool>(), 0..40i32), 0..OPS),
+            ) {
+                // model keeps entries from the least to the most recently used
+                let mut model: Vec<(i32, i32)> = Vec::new();
+                let mut cache = LruCache::new(cap);
+
+                for (is_put, key) in ops {
+                    if is_put {
+                        let old = model.iter().position(|(k, _)| *k == key).map(|i| model.remove(i).1);
+                        if old.is_none() && model.len() == cap {
+                            model.remove(0);
+                        }
+                        model.push((key, key * 2));
+                        assert_eq!(cache.put(key, key * 2), old);
+                    } else {
+                        let found = model.iter().position(|(k, _)| *k == key).map(|i| {
+                            let e = model.remove(i);
+                            model.push(e);
+                            e.1
+                        });
+                        assert_eq!(cache.get(&key).copied(), found);
+                    }
+
+                    assert!(cache.iter().map(|(k, v)| (*k, *v)).eq(model.iter().copied()));
+                }
+            }
+        );
+    }
+}
@@ -0,0 +1,1251 @@
+//! Hash map with coalesced hashing
+//!
+//! Collisions are chained like in separate chaining, but the chain nodes are
+//! free buckets of the same table linked with indices. The buffer consists of
+//! the address region which keys hash into and a cellar after it. Buckets for
+//! colliding keys are taken from the end of the buffer, so the cellar fills up
+//! first and delays chains from different home buckets merging (coalescing).
+//!
+//! Removed pairs leave tombstones which keep the chains linked.
+
+extern crate alloc as crate_alloc;
+
+use core::alloc::Layout;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+use core::{fmt, mem};
+use std::collections::hash_map::RandomState;
+
+use crate_alloc::alloc;
+
+use super::collect;
+use super::iter::{self, Slot};
+#[cfg(any(test, feature = "metrics"))]
+use super::metrics::MapMetrics;
+use super::{TryReserveError, buckets_for, clone_buckets, handle_reserve};
+
+/// End of a chain
+const NIL: usize = usize::MAX;
+
+pub struct HashMap<K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * every pair is on the chain starting at its home bucket
+    //  * chains only link `Occupied` and `Deleted` buckets
+    //  * all buckets in `buf[cap - free_scanned..cap]` are not `Empty`
+    buf: NonNull<Bucket<K, V>>,
+    /// Number of buckets in the address region and the cellar
+    cap: usize,
+    /// Number of buckets in the address region, 0 or a power of two
+    addr_cap: usize,
+    index_mask: usize,
+    len: usize,
+    /// Number of `Bucket::Deleted` tombstones
+    deleted: usize,
+    /// Number of buckets from the end already checked for a free bucket
+    free_scanned: usize,
+    hash_builder: S,
+    crit_load_factor: f64,
+    marker: PhantomData<(K, V)>,
+}
+
+#[derive(Debug, Clone)]
+enum Bucket<K, V> {
+    Occupied((K, V), usize),
+    Empty,
+    Deleted(usize),
+}
+
+impl<K, V> Bucket<K, V> {
+    /// Index of the next bucket on the chain, `NIL` if this is the last one.
+    fn next(&self) -> usize {
+        match self {
+            Bucket::Occupied(_, next) | Bucket::Deleted(next) => *next,
+            Bucket::Empty => NIL,
+        }
+    }
+}
+
+impl<K, V> Slot for Bucket<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn pair(&self) -> Option<(&K, &V)> {
+        match self {
+            Bucket::Occupied((k, v), _) => Some((k, v)),
+            Bucket::Empty | Bucket::Deleted(_) => None,
+        }
+    }
+
+    fn pair_mut(&mut self) -> Option<(&K, &mut V)> {
+        match self {
+            Bucket::Occupied((k, v), _) => Some((k, v)),
+            Bucket::Empty | Bucket::Deleted(_) => None,
+        }
+    }
+
+    fn take(&mut self) -> Option<(K, V)> {
+        match mem::replace(self, Bucket::Empty) {
+            Bucket::Occupied(pair, _) => Some(pair),
+            Bucket::Empty | Bucket::Deleted(_) => None,
+        }
+    }
+}
+
+impl<K, V, S> Drop for HashMap<K, V, S> {
+    fn drop(&mut self) {
+        if self.cap == 0 {
+            return;
+        }
+
+        for i in 0..self.cap {
+            let it = unsafe { self.buf.as_ptr().add(i) };
+            unsafe { ptr::drop_in_place(it) };
+        }
+
+        let layout = Self::layout(self.cap);
+        unsafe { alloc::dealloc(self.buf.as_ptr().cast::<u8>(), layout) }
+    }
+}
+
+impl<K, V, S> Clone for HashMap<K, V, S>
+where
+    K: Clone,
+    V: Clone,
+    S: Clone,
+{
+    /// Clones the buffer as is, no rehashing is needed as the hasher is
+    /// cloned too.
+    fn clone(&self) -> Self {
+        let mut s = Self {
+            buf: NonNull::dangling(),
+            cap: 0,
+            addr_cap: 0,
+            index_mask: 0,
+            len: 0,
+            deleted: 0,
+            free_scanned: 0,
+            crit_load_factor: self.crit_load_factor,
+            hash_builder: self.hash_builder.clone(),
+            marker: self.marker,
+        };
+        if self.cap == 0 {
+            return s;
+        }
+
+        // SAFETY: cap > 0
+        s.buf = unsafe { Self::alloc_new_buf_initialized(self.cap) };
+        s.cap = self.cap;
+        // SAFETY: both buffers have `cap` initialized buckets. `s` owns its
+        //  buffer, so the buckets cloned so far are dropped if a clone panics.
+        unsafe { clone_buckets(self.buf, s.buf, self.cap) };
+        s.addr_cap = self.addr_cap;
+        s.index_mask = self.index_mask;
+        s.len = self.len;
+        s.deleted = self.deleted;
+        s.free_scanned = self.free_scanned;
+
+        s
+    }
+}
+
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashMap")
+            .field(
+                "buf",
+                &DebugHashMapBuf {
+                    buf: self.buf,
+                    cap: self.cap,
+                    marker: PhantomData,
+                },
+            )
+            .field("cap", &self.cap)
+            .field("addr_cap", &self.addr_cap)
+            .field("len", &self.len)
+            .field("deleted", &self.deleted)
+            .field("hash_builder", &self.hash_builder)
+            .finish()
+    }
+}
+
+struct DebugHashMapBuf<'a, K, V> {
+    buf: NonNull<Bucket<K, V>>,
+    cap: usize,
+    marker: PhantomData<&'a Bucket<K, V>>,
+}
+
+impl<'a, K, V> fmt::Debug for DebugHashMapBuf<'a, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+
+        for i in 0..self.cap {
+            let it = unsafe { &*self.buf.as_ptr().add(i) };
+            list.entry(it);
+        }
+
+        list.finish()
+    }
+}
+
+impl<K, V, S> Default for HashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V> HashMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_load_factor(Self::DEF_CRIT_LOAD_FACTOR)
+    }
+
+    pub fn with_load_factor(load_factor: f64) -> Self {
+        Self::with_capacity_and_load_factor(0, load_factor)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_load_factor(capacity, Self::DEF_CRIT_LOAD_FACTOR)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    ///
+    /// The address region alone can hold `capacity` pairs, the cellar is on
+    /// top of it.
+    pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        Self::with_capacity_load_factor_and_hasher(capacity, lf, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    const DEF_CRIT_LOAD_FACTOR: f64 = 0.9;
+    const INITIAL_CAP: usize = 8;
+
+    /// Creates a new hash map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which uses `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_load_factor_and_hasher(
+            capacity,
+            Self::DEF_CRIT_LOAD_FACTOR,
+            hash_builder,
+        )
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which grows at load factor `lf` and uses
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_load_factor_and_hasher(capacity: usize, lf: f64, hash_builder: S) -> Self {
+        let mut s = Self {
+            buf: NonNull::dangling(),
+            cap: 0,
+            addr_cap: 0,
+            index_mask: 0,
+            len: 0,
+            deleted: 0,
+            free_scanned: 0,
+            hash_builder,
+            crit_load_factor: lf,
+            marker: PhantomData,
+        };
+        if capacity > 0 {
+            let addr_cap = handle_reserve(buckets_for(capacity, lf));
+            let cap = Self::total_cap(addr_cap);
+            s.buf = unsafe { Self::alloc_new_buf_initialized(cap) };
+            s.cap = cap;
+            s.addr_cap = addr_cap;
+            s.index_mask = addr_cap - 1;
+        }
+
+        s
+    }
+
+    /// Size of the whole buffer for an address region of `addr_cap` buckets.
+    ///
+    /// The cellar takes about 11% of the buffer, which is close to the
+    /// optimal ratio for successful lookups.
+    fn total_cap(addr_cap: usize) -> usize {
+        addr_cap + addr_cap / 8
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes allocated for the address region and the cellar,
+    /// including the empty and tombstoned ones.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        Self::layout(self.cap).size()
+    }
+
+    fn preferred_index(&self, hash: u64) -> usize {
+        debug_assert!(self.addr_cap.is_power_of_two());
+        (hash & self.index_mask as u64) as usize
+    }
+
+    /// # SAFETY
+    ///
+    /// * `index < self.cap`
+    unsafe fn bucket(&self, index: usize) -> *mut Bucket<K, V> {
+        debug_assert!(index < self.cap);
+        unsafe { self.buf.as_ptr().add(index) }
+    }
+
+    fn load_factor(&self) -> f64 {
+        if self.cap == 0 {
+            return f64::INFINITY;
+        }
+
+        self.len as f64 / self.cap as f64
+    }
+
+    /// Returns `true` if the map must grow before inserting one more item.
+    ///
+    /// Tombstones count as used buckets since they stay on the chains.
+    fn needs_grow(&self) -> bool {
+        let used = self.len + self.deleted;
+        used + 1 >= self.cap || used as f64 > self.crit_load_factor * self.cap as f64
+    }
+
+    /// Returns the last empty bucket which hasn't been checked yet.
+    ///
+    /// Buckets are checked from the end, none of the checked buckets can
+    /// become empty again, so every bucket is checked only once.
+    fn find_free(&mut self) -> Option<usize> {
+        while self.free_scanned < self.cap {
+            self.free_scanned += 1;
+            let index = self.cap - self.free_scanned;
+            // SAFETY: index < cap
+            if let Bucket::Empty = unsafe { &*self.bucket(index) } {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    fn layout(cap: usize) -> Layout {
+        Layout::array::<Bucket<K, V>>(cap).unwrap()
+    }
+
+    fn try_layout(cap: usize) -> Result<Layout, TryReserveError> {
+        Layout::array::<Bucket<K, V>>(cap).map_err(|_| TryReserveError::CapacityOverflow)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if self.needs_grow() {
+            self.grow();
+        }
+
+        let hash = self.hash_key(&key);
+        let home = self.preferred_index(hash);
+        let mut index = home;
+        let mut first_deleted = None;
+
+        // SAFETY (all `self.bucket` calls): indices are either < addr_cap or
+        //  links on the chains which are < cap, no references to the buckets
+        //  are kept across the calls
+        if let Bucket::Empty = unsafe { &*self.bucket(home) } {
+            unsafe { *self.bucket(home) = Bucket::Occupied((key, value), NIL) };
+            self.len += 1;
+            return None;
+        }
+
+        loop {
+            match unsafe { &mut *self.bucket(index) } {
+                Bucket::Occupied(pair, _) if pair.0 == key => {
+                    return Some(mem::replace(pair, (key, value)));
+                }
+                Bucket::Occupied(..) => {}
+                Bucket::Deleted(_) => {
+                    first_deleted.get_or_insert(index);
+                }
+                Bucket::Empty => unreachable!("chains only link non-empty buckets"),
+            }
+
+            let next = unsafe { (*self.bucket(index)).next() };
+            if next == NIL {
+                break;
+            }
+            index = next;
+        }
+
+        // reuse a tombstone on the chain if there is one
+        if let Some(deleted) = first_deleted {
+            let bucket = unsafe { &mut *self.bucket(deleted) };
+            *bucket = Bucket::Occupied((key, value), bucket.next());
+            self.deleted -= 1;
+            self.len += 1;
+            return None;
+        }
+
+        let free = self
+            .find_free()
+            .expect("a non-full map to have a free bucket");
+        unsafe {
+            *self.bucket(free) = Bucket::Occupied((key, value), NIL);
+            match &mut *self.bucket(index) {
+                Bucket::Occupied(_, next) | Bucket::Deleted(next) => *next = free,
+                Bucket::Empty => unreachable!(),
+            }
+        }
+        self.len += 1;
+        None
+    }
+
+    /// Inserts a key which is not present yet into a buffer without any
+    /// tombstones.
+    ///
+    /// # PANICS
+    ///
+    /// * if there are no empty buckets left for `find_free`
+    fn insert_unique(&mut self, key: K, value: V) {
+        let hash = self.hash_key(&key);
+        let home = self.preferred_index(hash);
+
+        // SAFETY: see `insert`
+        if let Bucket::Empty = unsafe { &*self.bucket(home) } {
+            unsafe { *self.bucket(home) = Bucket::Occupied((key, value), NIL) };
+        } else {
+            let mut index = home;
+            loop {
+                let next = unsafe { (*self.bucket(index)).next() };
+                if next == NIL {
+                    break;
+                }
+                index = next;
+            }
+
+            let free = self
+                .find_free()
+                .expect("a non-full map to have a free bucket");
+            unsafe {
+                *self.bucket(free) = Bucket::Occupied((key, value), NIL);
+                match &mut *self.bucket(index) {
+                    Bucket::Occupied(_, next) => *next = free,
+                    Bucket::Empty | Bucket::Deleted(_) => unreachable!(),
+                }
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptr = self.get_bucket(key);
+        if ptr.is_null() {
+            None
+        } else {
+            // SAFETY: non-null pointer from `get_bucket` points to an occupied bucket
+            unsafe { &*ptr }.pair()
+        }
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptr = self.get_bucket(key);
+        if ptr.is_null() {
+            None
+        } else {
+            // SAFETY: non-null pointer from `get_bucket` points to an occupied
+            //  bucket, the reference borrows `self` mutably
+            unsafe { &mut *ptr }.pair_mut().map(|(_, v)| v)
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        !self.get_bucket(key).is_null()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptr = self.get_bucket(key);
+        if ptr.is_null() {
+            None
+        } else {
+            // SAFETY: non-null pointer from `get_bucket` points to an occupied bucket
+            let next = unsafe { &*ptr }.next();
+            let b = unsafe { ptr::replace(ptr, Bucket::Deleted(next)) };
+            self.len -= 1;
+            self.deleted += 1;
+            match b {
+                Bucket::Occupied(pair, _) => Some(pair),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Return `ptr::null_mut()` if the key is not present,
+    /// a pointer to valid `Bucket::Occupied(..)` otherwise
+    fn get_bucket<Q>(&self, key: &Q) -> *mut Bucket<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return ptr::null_mut();
+        }
+
+        let hash = self.hash_key(key);
+        let mut index = self.preferred_index(hash);
+
+        while index != NIL {
+            // SAFETY: index is < addr_cap or a link on a chain which is < cap
+            let ptr = unsafe { self.bucket(index) };
+            match unsafe { &*ptr } {
+                Bucket::Occupied((k, _), _) if k.borrow() == key => return ptr,
+                b => index = b.next(),
+            }
+        }
+
+        ptr::null_mut()
+    }
+
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
+
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    ///
+    /// The map is unchanged if an error is returned.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_addr_cap = buckets_for(required, self.crit_load_factor)?;
+        if new_addr_cap > self.addr_cap {
+            self.try_rebuild(new_addr_cap)
+        } else if (required + self.deleted) as f64 / self.crit_load_factor + 1.0 > self.cap as f64 {
+            // fits only without the tombstones
+            self.try_rebuild(self.addr_cap)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn hash_key<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash,
+    {
+        self.hash_builder.hash_one(key)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    fn grow(&mut self)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        if self.cap == 0 {
+            self.rebuild(Self::INITIAL_CAP);
+        } else if (self.len + 1) as f64 <= self.crit_load_factor * self.cap as f64 / 2.0 {
+            // mostly tombstones, getting rid of them is enough
+            self.rebuild(self.addr_cap);
+        } else {
+            self.rebuild(2 * self.addr_cap);
+        }
+    }
+
+    /// Moves all items into a new buffer with an address region of
+    /// `new_addr_cap` buckets, dropping all tombstones.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_addr_cap` is not power of two or `new_addr_cap < self.addr_cap`
+    fn rebuild(&mut self, new_addr_cap: usize)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        handle_reserve(self.try_rebuild(new_addr_cap))
+    }
+
+    /// Same as `rebuild` but returns an error instead of aborting if the
+    /// allocation fails. The map is unchanged on error.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_addr_cap` is not power of two or `new_addr_cap < self.addr_cap`
+    fn try_rebuild(&mut self, new_addr_cap: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        assert!(new_addr_cap.is_power_of_two() && new_addr_cap >= self.addr_cap);
+
+        let new_cap = Self::total_cap(new_addr_cap);
+        // SAFETY: new_cap >= new_addr_cap > 0
+        let new_buf = unsafe { Self::try_alloc_new_buf_initialized(new_cap) }?;
+        let (old_buf, old_cap) = unsafe { self.swap_buf(new_buf, new_addr_cap) };
+
+        if old_cap != 0 {
+            // drop old buffer
+            let old_layout = Self::layout(old_cap);
+            unsafe { alloc::dealloc(old_buf.as_ptr().cast::<u8>(), old_layout) }
+        }
+
+        Ok(())
+    }
+
+    /// Allocates new buffer with capacity `new_cap` and initializes all the buckets to empty.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap * mem::size_of::<Bucket<K, V>>() > isize::MAX`
+    unsafe fn alloc_new_buf_initialized(new_cap: usize) -> NonNull<Bucket<K, V>> {
+        handle_reserve(unsafe { Self::try_alloc_new_buf_initialized(new_cap) })
+    }
+
+    /// Same as `alloc_new_buf_initialized` but returns an error if the
+    /// allocation fails or the buffer would be too large.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    unsafe fn try_alloc_new_buf_initialized(
+        new_cap: usize,
+    ) -> Result<NonNull<Bucket<K, V>>, TryReserveError> {
+        let new_layout = Self::try_layout(new_cap)?;
+        let new_buf = unsafe { alloc::alloc(new_layout) };
+        if new_buf.is_null() {
+            Err(TryReserveError::AllocError { layout: new_layout })
+        } else {
+            let new_buf = new_buf.cast::<Bucket<K, V>>();
+            for i in 0..new_cap {
+                unsafe { new_buf.add(i).write(Bucket::Empty) };
+            }
+
+            Ok(unsafe { NonNull::new_unchecked(new_buf) })
+        }
+    }
+
+    /// Swap current buffer with new one by moving all the items from old buffer into new
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_buf` must have capacity `Self::total_cap(new_addr_cap)` and all
+    ///   the buckets must be empty
+    /// * `new_addr_cap >= self.addr_cap`
+    unsafe fn swap_buf(
+        &mut self,
+        new_buf: NonNull<Bucket<K, V>>,
+        new_addr_cap: usize,
+    ) -> (NonNull<Bucket<K, V>>, usize)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let old_buf = mem::replace(&mut self.buf, new_buf);
+        let old_cap = mem::replace(&mut self.cap, Self::total_cap(new_addr_cap));
+        self.addr_cap = new_addr_cap;
+        self.index_mask = new_addr_cap - 1;
+        self.len = 0;
+        self.deleted = 0;
+        self.free_scanned = 0;
+
+        // insert all items into the new buffer
+        for i in 0..old_cap {
+            let it = unsafe { old_buf.as_ptr().add(i).read() };
+            if let Bucket::Occupied((k, v), _) = it {
+                self.insert_unique(k, v);
+            }
+        }
+
+        (old_buf, old_cap)
+    }
+}
+
+iter::impl_iterators!(Bucket<K, V>, deleted, free_scanned);
+collect::impl_collect!(hasher S);
+
+#[cfg(any(test, feature = "metrics"))]
+impl<K, V, S> MapMetrics<K, V> for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn get_with_metrics<Q>(&self, key: &Q) -> Option<(&K, &V, usize)>
+    where
+        Q: Eq + Hash,
+        K: Borrow<Q>,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let mut index = self.preferred_index(hash);
+        let mut probe_len = 0;
+
+        while index != NIL {
+            match unsafe { &*self.bucket(index) } {
+                Bucket::Occupied((k, v), _) if k.borrow() == key => {
+                    return Some((k, v, probe_len));
+                }
+                b => index = b.next(),
+            }
+            probe_len += 1;
+        }
+
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn cap(&self) -> usize {
+        self.cap
+    }
+
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes()
+    }
+
+    fn load_factor(&self) -> f64 {
+        self.load_factor()
+    }
+
+    fn name(&self) -> &'static str {
+        "Coalesced hashing"
+    }
+
+    fn tombstones(&self) -> usize {
+        self.deleted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::Hasher;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    struct SameHash(i32);
+
+    // They all hash to same value, so they must be on the same chain
+    impl Hash for SameHash {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            1.hash(state);
+        }
+    }
+
+    impl<K: Hash + Eq, V> HashMap<K, V> {
+        fn check_invariants(&self) {
+            let mut occupied = 0;
+            let mut deleted = 0;
+            for i in 0..self.cap {
+                match unsafe { &*self.bucket(i) } {
+                    Bucket::Occupied((k, _), _) => {
+                        occupied += 1;
+                        assert_eq!(self.get_bucket(k), unsafe { self.bucket(i) });
+                    }
+                    Bucket::Deleted(_) => deleted += 1,
+                    Bucket::Empty => assert!(i < self.cap - self.free_scanned),
+                }
+            }
+            assert_eq!(occupied, self.len);
+            assert_eq!(deleted, self.deleted);
+        }
+    }
+
+    #[test]
+    fn insert() {
+        let mut m = HashMap::<i32, i32>::new();
+        assert!(m.is_empty());
+        m.insert(1, 11);
+        assert_eq!(m.len(), 1);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        assert_eq!(m.len(), 4);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+
+        assert_eq!(m.insert(4, 42), Some((4, 41)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+        m.check_invariants();
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let dups: HashMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[&5], 3);
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn clone() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in (0..100).step_by(3) {
+            m.remove(&i);
+        }
+
+        let mut c = m.clone();
+        assert_eq!(c.len(), m.len());
+        for i in 0..100 {
+            assert_eq!(c.get(&i), m.get(&i));
+        }
+
+        // the clone is independent of the original
+        c.insert(0, "new".to_owned());
+        c.remove(&1);
+        assert_eq!(m.get(&0), None);
+        assert_eq!(m.get(&1), Some(&"1".to_owned()));
+        assert_eq!(c.get(&0), Some(&"new".to_owned()));
+        assert_eq!(c.get(&1), None);
+    }
+
+    #[test]
+    fn remove() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.remove(&2), Some((2, 21)));
+        assert_eq!(m.remove(&2), None);
+        assert_eq!(m.remove(&1), Some((1, 11)));
+        assert_eq!(m.remove(&1), None);
+        assert_eq!(m.remove(&3), Some((3, 31)));
+        assert_eq!(m.remove(&3), None);
+        assert_eq!(m.remove(&4), Some((4, 41)));
+        assert_eq!(m.remove(&4), None);
+        assert_eq!(m.remove(&5), Some((5, 51)));
+        assert_eq!(m.remove(&5), None);
+
+        assert!(m.is_empty());
+        m.check_invariants();
+    }
+
+    #[test]
+    fn remove_same_hash() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&SameHash(1)), None);
+
+        for i in 0..20 {
+            m.insert(SameHash(i), i);
+        }
+        // remove from the middle of the chain, the rest must still be found
+        assert_eq!(m.remove(&SameHash(5)), Some((SameHash(5), 5)));
+        assert_eq!(m.remove(&SameHash(0)), Some((SameHash(0), 0)));
+        m.check_invariants();
+        assert_eq!(m.get_key_value(&SameHash(19)), Some((&SameHash(19), &19)));
+
+        // tombstone on the chain is reused
+        let deleted = m.deleted;
+        m.insert(SameHash(100), 100);
+        assert_eq!(m.deleted, deleted - 1);
+
+        for i in (1..20).filter(|i| *i != 5) {
+            assert_eq!(m.remove(&SameHash(i)), Some((SameHash(i), i)));
+        }
+        assert_eq!(m.remove(&SameHash(100)), Some((SameHash(100), 100)));
+
+        assert!(m.is_empty());
+        m.check_invariants();
+    }
+
+    #[test]
+    fn collisions_use_cellar_first() {
+        let mut m = HashMap::with_capacity(100);
+        m.insert(SameHash(0), 0);
+        m.insert(SameHash(1), 1);
+        let last = m.cap - 1;
+        assert!(last >= m.addr_cap);
+        assert_eq!(unsafe { &*m.bucket(last) }.pair(), Some((&SameHash(1), &1)));
+    }
+
+    #[test]
+    fn get() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get_key_value(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn iterators() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+        m.remove(&3);
+        m.remove(&7);
+
+        let mut items: Vec<_> = m.iter().map(|(k, v)| (*k, *v)).collect();
+        items.sort();
+        let expected: Vec<_> = [0, 1, 2, 4, 5, 6, 8, 9].map(|i| (i, i * 10)).into();
+        assert_eq!(items, expected);
+        assert_eq!(m.iter().len(), 8);
+        assert_eq!(m.keys().sum::<i32>(), 35);
+        assert_eq!(m.values().sum::<i32>(), 350);
+
+        for v in m.values_mut() {
+            *v += 1;
+        }
+        for (k, v) in &mut m {
+            *v += k;
+        }
+        assert_eq!(m.get_key_value(&4), Some((&4, &45)));
+
+        let mut drained: Vec<_> = m.drain().collect();
+        drained.sort();
+        assert_eq!(drained.len(), 8);
+        assert_eq!(drained[0], (0, 1));
+        assert!(m.is_empty());
+        assert_eq!(m.deleted, 0);
+        assert_eq!(m.free_scanned, 0);
+        assert_eq!(m.iter().next(), None);
+        m.check_invariants();
+
+        // map is still usable after the drain
+        m.insert(1, 1);
+        m.insert(2, 2);
+        let mut owned: Vec<_> = m.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, [(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn iterators_drop_unconsumed() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+
+        let mut drain = m.drain();
+        drain.next();
+        drop(drain);
+        assert!(m.is_empty());
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+        let mut into_iter = m.into_iter();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 9);
+        drop(into_iter);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut m = HashMap::new();
+        m.insert(-1, -1);
+        m.reserve(100);
+        let cap = m.cap;
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.cap, cap, "grew after reserve");
+        assert_eq!(m.len(), 101);
+
+        assert_eq!(
+            m.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            m.try_reserve(usize::MAX / 2),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(m.cap, cap);
+        assert_eq!(m.get_key_value(&50), Some((&50, &50)));
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+        use rand::seq::SliceRandom;
+        use rand::thread_rng;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn insert_get(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..10)
+            ) {
+                let ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
+
+                let mut hmap = HashMap::with_capacity(ref_hmap.len());
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+                hmap.check_invariants();
+
+                inserts.shuffle(&mut thread_rng());
+                for key in inserts.iter().chain(access.iter()) {
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
+                }
+            }
+
+            #[test]
+            fn iter_drain(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::new();
+                for v in &inserts {
+                    ref_hmap.insert(*v, *v);
+                    hmap.insert(*v, *v);
+                }
+                for v in &removes {
+                    assert_eq!(ref_hmap.remove_entry(v), hmap.remove(v));
+                }
+                hmap.check_invariants();
+
+                let mut expected: Vec<_> = ref_hmap.into_iter().collect();
+                expected.sort();
+                let mut items: Vec<_> = hmap.iter().map(|(k, v)| (*k, *v)).collect();
+                items.sort();
+                assert_eq!(&items, &expected);
+
+                let mut drained: Vec<_> = hmap.drain().collect();
+                drained.sort();
+                assert_eq!(drained, expected);
+                assert!(hmap.is_empty());
+                hmap.check_invariants();
+
+                // the map must be fully reusable after the drain
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+                let mut owned: Vec<_> = hmap.into_iter().map(|(k, _)| k).collect();
+                owned.sort();
+                owned.dedup();
+                let mut inserts = inserts;
+                inserts.sort();
+                inserts.dedup();
+                assert_eq!(owned, inserts);
+            }
+
+            #[test]
+            fn remove(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..10)
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
+                let mut hmap = HashMap::with_capacity(ref_hmap.len());
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+
+                inserts.shuffle(&mut thread_rng());
+                for key in access.iter().chain(inserts.iter()) {
+                    assert_eq!(ref_hmap.remove_entry(key), hmap.remove(key));
+                }
+                hmap.check_invariants();
+            }
+
+            #[test]
+            fn insert_remove_mixed(
+                ops in proptest::collection::vec((any::<bool>(), 0..500i32), 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::new();
+                for (insert, key) in ops {
+                    if insert {
+                        assert_eq!(ref_hmap.insert(key, key).map(|v| (key, v)), hmap.insert(key, key));
+                    } else {
+                        assert_eq!(ref_hmap.remove_entry(&key), hmap.remove(&key));
+                    }
+                }
+                hmap.check_invariants();
+            }
+
+            #[test]
+            #[cfg_attr(miri, ignore = "nothing for miri to really check, no need to waste time")]
+            fn with_cap(cap in 0..100_000usize, lf in 0.5..0.999) {
+                let map = HashMap::<u8, ()>::with_capacity_and_load_factor(cap, lf);
+                if cap > 0 {
+                    let will_be_lf = cap as f64/map.addr_cap as f64;
+                    assert!(will_be_lf < lf);
+                    assert!(map.addr_cap.is_power_of_two());
+                    assert!(map.cap > cap);
+                } else {
+                    assert_eq!(map.cap, 0);
+                }
+            }
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! `FromIterator`, `Extend`, `Index` and serde support shared by the open
+//! addressing maps
+
+/// Upper limit of the capacity reserved up front when deserializing a map.
+///
+/// Don't trust the size hint blindly, malicious input could make us allocate
+/// huge buffers up front.
+#[cfg(feature = "serde")]
+pub(super) const MAX_PREALLOC: usize = 4096;
+
+/// Implements `FromIterator<(K, V)>`, `Extend<(K, V)>` and `Index<&Q>` for a
+/// `HashMap<K, V>`, or for `HashMap<K, V, B>` if called with `const B`.
+/// With the `serde` feature also `Serialize` and `Deserialize`, the maps are
+/// serialized as maps.
+///
+/// The map must have methods `with_capacity`, `reserve`, `insert`, `get` and
+/// `iter` with the usual signatures.
+///
+/// If called with `hasher S` it's implemented for `HashMap<K, V, S>` instead,
+/// which must have `with_capacity_and_hasher`.
+macro_rules! impl_collect {
+    (hasher $s:ident) => {
+        impl<K, V, $s> FromIterator<(K, V)> for HashMap<K, V, $s>
+        where
+            K: Hash + Eq,
+            $s: BuildHasher + Default,
+        {
+            fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+                let iter = iter.into_iter();
+                let mut map = Self::with_capacity_and_hasher(iter.size_hint().0, $s::default());
+                map.extend(iter);
+                map
+            }
+        }
+
+        impl<K, V, $s> Extend<(K, V)> for HashMap<K, V, $s>
+        where
+            K: Hash + Eq,
+            $s: BuildHasher,
+        {
+            fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+                let iter = iter.into_iter();
+                let additional = if self.is_empty() {
+                    iter.size_hint().0
+                } else {
+                    iter.size_hint().0.div_ceil(2)
+                };
+                self.reserve(additional);
+                for (k, v) in iter {
+                    self.insert(k, v);
+                }
+            }
+        }
+
+        impl<K, V, Q, $s> core::ops::Index<&Q> for HashMap<K, V, $s>
+        where
+            K: Borrow<Q> + Hash + Eq,
+            Q: Hash + Eq,
+            $s: BuildHasher,
+        {
+            type Output = V;
+
+            /// # PANICS
+            ///
+            /// * if the key is not present in the map
+            fn index(&self, key: &Q) -> &V {
+                self.get(key).expect("key not found")
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<K, V, $s> serde::Serialize for HashMap<K, V, $s>
+        where
+            K: serde::Serialize,
+            V: serde::Serialize,
+        {
+            fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+            where
+                Ser: serde::Serializer,
+            {
+                serializer.collect_map(self.iter())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de, K, V, $s> serde::Deserialize<'de> for HashMap<K, V, $s>
+        where
+            K: serde::Deserialize<'de> + Hash + Eq,
+            V: serde::Deserialize<'de>,
+            $s: BuildHasher + Default,
+        {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct MapVisitor<K, V, $s>(core::marker::PhantomData<HashMap<K, V, $s>>);
+
+                impl<'de, K, V, $s> serde::de::Visitor<'de> for MapVisitor<K, V, $s>
+                where
+                    K: serde::Deserialize<'de> + Hash + Eq,
+                    V: serde::Deserialize<'de>,
+                    $s: BuildHasher + Default,
+                {
+                    type Value = HashMap<K, V, $s>;
+
+                    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                        f.write_str("a map")
+                    }
+
+                    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: serde::de::MapAccess<'de>,
+                    {
+                        let cap = access.size_hint().unwrap_or(0).min(collect::MAX_PREALLOC);
+                        let mut map = HashMap::with_capacity_and_hasher(cap, $s::default());
+                        while let Some((k, v)) = access.next_entry()? {
+                            map.insert(k, v);
+                        }
+                        Ok(map)
+                    }
+                }
+
+                deserializer.deserialize_map(MapVisitor(core::marker::PhantomData))
+            }
+        }
+    };
+    ($(const $b:ident)?) => {
+        impl<K, V $(, const $b: usize)?> FromIterator<(K, V)> for HashMap<K, V $(, $b)?>
+        where
+            K: Hash + Eq,
+        {
+            fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+                let iter = iter.into_iter();
+                let mut map = Self::with_capacity(iter.size_hint().0);
+                map.extend(iter);
+                map
+            }
+        }
+
+        impl<K, V $(, const $b: usize)?> Extend<(K, V)> for HashMap<K, V $(, $b)?>
+        where
+            K: Hash + Eq,
+        {
+            fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+                let iter = iter.into_iter();
+                // Some of the keys may already be present, so only reserve for
+                // half of them if the map is not empty. Same as std does.
+                let additional = if self.is_empty() {
+                    iter.size_hint().0
+                } else {
+                    iter.size_hint().0.div_ceil(2)
+                };
+                self.reserve(additional);
+                for (k, v) in iter {
+                    self.insert(k, v);
+                }
+            }
+        }
+
+        impl<K, V, Q $(, const $b: usize)?> core::ops::Index<&Q> for HashMap<K, V $(, $b)?>
+        where
+            K: Borrow<Q> + Hash + Eq,
+            Q: Hash + Eq,
+        {
+            type Output = V;
+
+            /// # PANICS
+            ///
+            /// * if the key is not present in the map
+            fn index(&self, key: &Q) -> &V {
+                self.get(key).expect("key not found")
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<K, V $(, const $b: usize)?> serde::Serialize for HashMap<K, V $(, $b)?>
+        where
+            K: serde::Serialize,
+            V: serde::Serialize,
+        {
+            fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+            where
+                Ser: serde::Serializer,
+            {
+                serializer.collect_map(self.iter())
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de, K, V $(, const $b: usize)?> serde::Deserialize<'de> for HashMap<K, V $(, $b)?>
+        where
+            K: serde::Deserialize<'de> + Hash + Eq,
+            V: serde::Deserialize<'de>,
+        {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct MapVisitor<K, V $(, const $b: usize)?>(core::marker::PhantomData<HashMap<K, V $(, $b)?>>);
+
+                impl<'de, K, V $(, const $b: usize)?> serde::de::Visitor<'de> for MapVisitor<K, V $(, $b)?>
+                where
+                    K: serde::Deserialize<'de> + Hash + Eq,
+                    V: serde::Deserialize<'de>,
+                {
+                    type Value = HashMap<K, V $(, $b)?>;
+
+                    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                        f.write_str("a map")
+                    }
+
+                    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: serde::de::MapAccess<'de>,
+                    {
+                        let cap = access.size_hint().unwrap_or(0).min(collect::MAX_PREALLOC);
+                        let mut map = HashMap::with_capacity(cap);
+                        while let Some((k, v)) = access.next_entry()? {
+                            map.insert(k, v);
+                        }
+                        Ok(map)
+                    }
+                }
+
+                deserializer.deserialize_map(MapVisitor(core::marker::PhantomData))
+            }
+        }
+    };
+}
+
+pub(super) use impl_collect;
@@ -1,46 +1,63 @@
 //! Hash map with cuckoo hashing
+//!
+//! Each of the two buffers is split into buckets of `B` slots. A key can be
+//! in any slot of its bucket in either buffer. With `B = 1` this is classic
+//! cuckoo hashing which cannot get much above 50% load, a few slots per
+//! bucket allow load factors well above 90% and shorten the eviction chains.
 
 extern crate alloc as crate_alloc;
 
 use core::alloc::Layout;
 use core::borrow::Borrow;
-use core::hash::{BuildHasher, Hash, Hasher};
+use core::hash::{BuildHasher, Hash};
 use core::marker::PhantomData;
 use core::ptr::{self, NonNull};
-use core::{fmt, mem};
+use core::{fmt, mem, slice};
 use std::collections::hash_map::RandomState;
 
 use crate_alloc::alloc;
 
-#[cfg(test)]
+use super::collect;
+#[cfg(any(test, feature = "metrics"))]
 use super::metrics::MapMetrics;
-use super::round_up_to_power_of_two;
+use super::{TryReserveError, buckets_for, clone_buckets, handle_reserve};
+
+type Buf<K, V> = NonNull<Option<(K, V)>>;
 
-pub struct HashMap<K, V> {
+pub struct HashMap<K, V, const B: usize = 1> {
+    // INVARIANTS:
+    //  * both buffers have `cap * B` slots, the slots of bucket `i` are at
+    //    `i * B..(i + 1) * B`
     buf1: NonNull<Option<(K, V)>>,
     buf2: NonNull<Option<(K, V)>>,
-    /// Capacity of one buffer, total map capacity is 2*cap
+    /// Number of buckets in one buffer, total map capacity is 2*cap*B
     cap: usize,
     index_mask: usize,
+    /// Number of pairs in both buffers and the stash
     len: usize,
+    /// Pairs which didn't find a bucket because of an eviction cycle
+    stash: Vec<(K, V)>,
+    /// An overflowing stash doesn't rehash the map before `len` reaches this.
+    /// Set when a rehash couldn't empty the stash.
+    rehash_len: usize,
     hash_builder1: RandomState,
     hash_builder2: RandomState,
     crit_load_factor: f64,
     marker: PhantomData<(K, V)>,
 }
 
-impl<K, V> Drop for HashMap<K, V> {
+impl<K, V, const B: usize> Drop for HashMap<K, V, B> {
     fn drop(&mut self) {
         if self.cap == 0 {
             return;
         }
 
-        for i in 0..self.cap {
+        for i in 0..self.cap * B {
             let it = unsafe { self.buf1.as_ptr().add(i) };
             unsafe { ptr::drop_in_place(it) };
         }
 
-        for i in 0..self.cap {
+        for i in 0..self.cap * B {
             let it = unsafe { self.buf2.as_ptr().add(i) };
             unsafe { ptr::drop_in_place(it) };
         }
@@ -51,44 +68,50 @@ impl<K, V> Drop for HashMap<K, V> {
     }
 }
 
-impl<K, V> Clone for HashMap<K, V>
+impl<K, V, const B: usize> Clone for HashMap<K, V, B>
 where
-    K: Eq + Hash + Clone,
+    K: Clone,
     V: Clone,
 {
+    /// Clones the buffers as is, no rehashing is needed as the hashers are
+    /// cloned too.
     fn clone(&self) -> Self {
-        // TODO: improve it
         let mut s = Self {
             buf1: NonNull::dangling(),
             buf2: NonNull::dangling(),
             cap: 0,
             index_mask: 0,
             len: 0,
+            stash: self.stash.clone(),
+            rehash_len: self.rehash_len,
             crit_load_factor: self.crit_load_factor,
             hash_builder1: self.hash_builder1.clone(),
             hash_builder2: self.hash_builder2.clone(),
             marker: self.marker,
         };
-        s.grow_to(self.cap);
-        for i in 0..self.cap {
-            let it = unsafe { &*self.buf1.as_ptr().add(i) };
-            if let Some((k, v)) = it {
-                s.insert(k.clone(), v.clone());
-            }
+        if self.cap == 0 {
+            return s;
         }
 
-        for i in 0..self.cap {
-            let it = unsafe { &*self.buf2.as_ptr().add(i) };
-            if let Some((k, v)) = it {
-                s.insert(k.clone(), v.clone());
-            }
+        // SAFETY: cap > 0
+        s.buf1 = unsafe { Self::alloc_new_buf_initialized(self.cap) };
+        // SAFETY: cap > 0
+        s.buf2 = unsafe { Self::alloc_new_buf_initialized(self.cap) };
+        s.cap = self.cap;
+        // SAFETY: all buffers have `cap * B` initialized slots. `s` owns its
+        //  buffers, so the slots cloned so far are dropped if a clone panics.
+        unsafe {
+            clone_buckets(self.buf1, s.buf1, self.cap * B);
+            clone_buckets(self.buf2, s.buf2, self.cap * B);
         }
+        s.index_mask = self.index_mask;
+        s.len = self.len;
 
         s
     }
 }
 
-impl<K, V> fmt::Debug for HashMap<K, V>
+impl<K, V, const B: usize> fmt::Debug for HashMap<K, V, B>
 where
     K: fmt::Debug,
     V: fmt::Debug,
@@ -99,7 +122,7 @@ where
                 "buf1",
                 &DebugHashMapBuf {
                     buf: self.buf1,
-                    cap: self.cap,
+                    cap: self.cap * B,
                     marker: PhantomData,
                 },
             )
@@ -107,12 +130,13 @@ where
                 "buf2",
                 &DebugHashMapBuf {
                     buf: self.buf2,
-                    cap: self.cap,
+                    cap: self.cap * B,
                     marker: PhantomData,
                 },
             )
             .field("cap", &self.cap)
             .field("len", &self.len)
+            .field("stash", &self.stash)
             .field("hash_builder1", &self.hash_builder1)
             .field("hash_builder2", &self.hash_builder2)
             .finish()
@@ -142,9 +166,28 @@ where
     }
 }
 
-impl<K, V> HashMap<K, V> {
+impl<K, V, const B: usize> Default for HashMap<K, V, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, const B: usize> HashMap<K, V, B> {
     const DEF_CRIT_LOAD_FACTOR: f64 = 0.7;
     const INITIAL_CAP: usize = 4;
+    /// Maximum number of pairs in the stash before the map is rehashed
+    const MAX_STASH: usize = 4;
+    /// Load factor up to which all the pairs can usually be placed.
+    ///
+    /// It's 50% with single slot buckets but rises quickly with more slots.
+    const MAX_LOAD: f64 = match B {
+        1 => 0.5,
+        2 => 0.85,
+        3 => 0.9,
+        _ => 0.95,
+    };
+    /// Number of times to try new hash builders if the stash overflows
+    const MAX_REHASHES: usize = 4;
 
     pub fn new() -> Self {
         Self::with_load_factor(Self::DEF_CRIT_LOAD_FACTOR)
@@ -163,9 +206,9 @@ impl<K, V> HashMap<K, V> {
     /// Creates a new hash map with capacity to store at least `capacity` pairs
     /// without reallocation.
     pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        const { assert!(B > 0, "buckets must have at least one slot") };
         let (buf1, buf2, cap, index_mask) = if capacity > 0 {
-            let capacity = (capacity as f64 / lf / 2.0 + 1.0) as usize;
-            let capacity = round_up_to_power_of_two(capacity);
+            let capacity = handle_reserve(Self::buf_cap_for(capacity, lf));
             debug_assert!(capacity.is_power_of_two());
             debug_assert!(capacity > 0);
             let buf1 = unsafe { Self::alloc_new_buf_initialized(capacity) };
@@ -180,6 +223,8 @@ impl<K, V> HashMap<K, V> {
             cap,
             index_mask,
             len: 0,
+            stash: Vec::new(),
+            rehash_len: 0,
             hash_builder1: RandomState::new(),
             hash_builder2: RandomState::new(),
             crit_load_factor: lf,
@@ -195,8 +240,34 @@ impl<K, V> HashMap<K, V> {
         self.len == 0
     }
 
+    /// Number of bytes allocated for both buffers and the stash, including the
+    /// empty slots.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        2 * Self::layout(self.cap).size() + self.stash.capacity() * mem::size_of::<(K, V)>()
+    }
+
     pub fn capacity(&self) -> usize {
-        self.cap * 2
+        self.cap * 2 * B
+    }
+
+    /// Iterate over all key-value pairs in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        // SAFETY: both buffers have `cap * B` initialized slots, before the
+        //  first allocation they are dangling with `cap == 0` which is a valid
+        //  empty slice
+        let (buf1, buf2) = unsafe {
+            (
+                slice::from_raw_parts(self.buf1.as_ptr(), self.cap * B),
+                slice::from_raw_parts(self.buf2.as_ptr(), self.cap * B),
+            )
+        };
+        buf1.iter()
+            .chain(buf2)
+            .filter_map(|slot| slot.as_ref())
+            .chain(&self.stash)
+            .map(|(k, v)| (k, v))
     }
 
     fn preferred_index(&self, hash: u64) -> usize {
@@ -214,12 +285,73 @@ impl<K, V> HashMap<K, V> {
         self.len as f64 / (self.capacity() as f64)
     }
 
+    /// Layout of a buffer with `cap` buckets.
     fn layout(cap: usize) -> Layout {
-        Layout::array::<Option<(K, V)>>(cap).unwrap()
+        Self::try_layout(cap).unwrap()
+    }
+
+    fn try_layout(cap: usize) -> Result<Layout, TryReserveError> {
+        let slots = cap
+            .checked_mul(B)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        Layout::array::<Option<(K, V)>>(slots).map_err(|_| TryReserveError::CapacityOverflow)
+    }
+
+    /// Returns the number of buckets in one buffer needed to store `items`
+    /// pairs.
+    fn buf_cap_for(items: usize, lf: f64) -> Result<usize, TryReserveError> {
+        // half of the items go into each buffer, `B` into each bucket
+        buckets_for(items, 2.0 * B as f64 * lf)
+    }
+
+    /// Returns a pointer to the slot of `bucket` in `buf` which holds `key`.
+    ///
+    /// # SAFETY
+    ///
+    /// * `buf` must be one of the map's buffers and `bucket < self.cap`
+    unsafe fn find_in_bucket<Q>(
+        buf: Buf<K, V>,
+        bucket: usize,
+        key: &Q,
+    ) -> Option<*mut Option<(K, V)>>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        (0..B)
+            // SAFETY: the bucket's slots are in bounds (see INVARIANTS)
+            .map(|i| unsafe { buf.as_ptr().add(bucket * B + i) })
+            .find(|&slot| matches!(unsafe { &*slot }, Some((k, _)) if k.borrow() == key))
+    }
+
+    /// Puts `pair` into a free slot of `bucket` in `buf`. If the bucket is
+    /// full, the pair in slot `evict` is replaced and returned.
+    ///
+    /// # SAFETY
+    ///
+    /// * `buf` must be one of the map's buffers, `bucket < self.cap` and
+    ///   `evict < B`
+    unsafe fn put_or_evict(
+        buf: Buf<K, V>,
+        bucket: usize,
+        evict: usize,
+        pair: (K, V),
+    ) -> Option<(K, V)> {
+        // SAFETY: the bucket's slots are in bounds (see INVARIANTS)
+        let slots = unsafe { buf.as_ptr().add(bucket * B) };
+        for i in 0..B {
+            let slot = unsafe { &mut *slots.add(i) };
+            if slot.is_none() {
+                *slot = Some(pair);
+                return None;
+            }
+        }
+
+        unsafe { (*slots.add(evict)).replace(pair) }
     }
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V, const B: usize> HashMap<K, V, B>
 where
     K: Hash + Eq,
 {
@@ -228,7 +360,7 @@ where
             self.grow()
         }
 
-        debug_assert!(self.len < self.cap * 2);
+        debug_assert!(self.len < self.capacity());
         unsafe { self.insert_unchecked(key, value) }
     }
 
@@ -237,87 +369,191 @@ where
     /// * Self must have the capacity for 1 more item
     ///   (ideally we would also not exceed `load_factor > Self::CRIT_LOAD_FACTOR`
     ///   but that's not a safety requirement)
-    unsafe fn insert_unchecked(&mut self, mut key: K, mut value: V) -> Option<(K, V)> {
-        // We need to check both buffers to see if key already exists.
-        // Start with buf2 so that buf1 would be the first one we try to insert new items.
-        let hash = self.hash_key2(&key);
-        let index = self.preferred_index(hash);
-        let maybe_val = unsafe { &mut *self.buf2.as_ptr().add(index) };
-        match maybe_val {
-            Some(val) if val.0 == key => {
-                let old = mem::replace(val, (key, value));
-                return Some(old);
+    unsafe fn insert_unchecked(&mut self, key: K, value: V) -> Option<(K, V)> {
+        // We need to check both buffers and the stash to see if key already exists.
+        let ptr = self.get_bucket(&key);
+        if !ptr.is_null() {
+            // SAFETY: non-null pointer from `get_bucket` points to an occupied bucket
+            return unsafe { ptr::replace(ptr, Some((key, value))) };
+        }
+
+        if let Some(i) = self.stash_index(&key) {
+            return Some(mem::replace(&mut self.stash[i], (key, value)));
+        }
+
+        self.len += 1;
+        if let Some(homeless) = self.place(key, value) {
+            self.stash.push(homeless);
+            if self.stash.len() > Self::MAX_STASH {
+                self.rehash();
             }
-            _ => {}
         }
 
-        let mut i = 0;
-        loop {
+        None
+    }
+
+    /// Places a pair which is not in the map into the buffers by evicting
+    /// existing pairs to their alternative buckets.
+    ///
+    /// Returns the pair which was left without a bucket if the evictions
+    /// didn't terminate in `self.max_rounds()` rounds. That's most likely a
+    /// cycle where the pairs keep evicting each other (for example if all of
+    /// them hash to the same buckets), so growing wouldn't help.
+    ///
+    /// Doesn't change `self.len`.
+    fn place(&mut self, mut key: K, mut value: V) -> Option<(K, V)> {
+        debug_assert!(self.cap > 0);
+        for round in 0..self.max_rounds() {
             let hash = self.hash_key1(&key);
-            let index = self.preferred_index(hash);
-            let maybe_val = unsafe { &mut *self.buf1.as_ptr().add(index) };
-            match maybe_val {
-                Some(val) if val.0 == key => {
-                    let old = mem::replace(val, (key, value));
-                    break Some(old);
-                }
-                Some(val) => {
-                    (key, value) = mem::replace(val, (key, value));
-                }
-                None => {
-                    *maybe_val = Some((key, value));
-                    self.len += 1;
-                    break None;
-                }
+            // SAFETY: preferred index < cap and victim < B
+            match unsafe {
+                Self::put_or_evict(
+                    self.buf1,
+                    self.preferred_index(hash),
+                    Self::victim(hash, round),
+                    (key, value),
+                )
+            } {
+                Some(evicted) => (key, value) = evicted,
+                None => return None,
             }
 
             let hash = self.hash_key2(&key);
-            let index = self.preferred_index(hash);
-            let maybe_val = unsafe { &mut *self.buf2.as_ptr().add(index) };
-            match maybe_val {
-                Some(val) if val.0 == key => {
-                    let old = mem::replace(val, (key, value));
-                    break Some(old);
-                }
-                Some(val) => {
-                    (key, value) = mem::replace(val, (key, value));
-                }
-                None => {
-                    *maybe_val = Some((key, value));
-                    self.len += 1;
-                    break None;
-                }
+            // SAFETY: preferred index < cap and victim < B
+            match unsafe {
+                Self::put_or_evict(
+                    self.buf2,
+                    self.preferred_index(hash),
+                    Self::victim(hash, round),
+                    (key, value),
+                )
+            } {
+                Some(evicted) => (key, value) = evicted,
+                None => return None,
             }
-            i += 1;
+        }
+
+        Some((key, value))
+    }
+
+    /// Maximum number of eviction rounds in `place`.
+    ///
+    /// Unless there is a cycle, a pair is placed after O(1) evictions on
+    /// average and O(log n) with high probability.
+    fn max_rounds(&self) -> usize {
+        (8 * (self.cap.ilog2() as usize + 1)).min(self.cap)
+    }
+
+    /// Slot of a full bucket to evict in the given eviction round.
+    ///
+    /// Always evicting the same slot would let a few pairs keep evicting each
+    /// other, so the slot is varied by the hash and the round.
+    fn victim(hash: u64, round: usize) -> usize {
+        ((hash >> 32) as usize).wrapping_add(round) % B
+    }
+
+    /// Rebuilds the map with freshly seeded hash builders until the stash
+    /// fits into `Self::MAX_STASH`.
+    ///
+    /// After `Self::MAX_REHASHES` tries the map is grown if it's fuller than
+    /// `Self::MAX_LOAD`, since above that two hash functions often cannot
+    /// place all the pairs with any seeds. A sparser map is left as is, keys which
+    /// collide with every seed (e.g. they have identical hashes) cannot be
+    /// separated by growing either, they stay in the oversized stash.
+    ///
+    /// After a failed rehash the next one waits until the map grows or `len`
+    /// grows by half. Otherwise such keys would rebuild the map on every
+    /// insert, which makes inserting them quadratic.
+    fn rehash(&mut self) {
+        if self.len < self.rehash_len {
+            return;
+        }
 
-            if i == self.cap {
-                self.grow();
-                i = 0;
+        for _ in 0..Self::MAX_REHASHES {
+            self.hash_builder1 = RandomState::new();
+            self.hash_builder2 = RandomState::new();
+            // SAFETY: self.cap > 0 as we have items in the stash
+            unsafe { self.rebuild(self.cap) };
+            if self.stash.len() <= Self::MAX_STASH {
+                self.rehash_len = 0;
+                return;
             }
         }
+
+        self.rehash_len = self.len + self.len / 2;
+        if self.len as f64 > Self::MAX_LOAD * self.capacity() as f64 {
+            self.grow();
+        }
     }
 
-    pub fn get<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    fn stash_index<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        self.stash.iter().position(|(k, _)| k.borrow() == key)
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
     where
         K: Borrow<Q>,
         Q: Eq + Hash,
     {
         let ptr = self.get_bucket(key);
         if ptr.is_null() {
-            None
+            let i = self.stash_index(key)?;
+            let (k, v) = &self.stash[i];
+            Some((k, v))
         } else {
             unsafe { &*ptr }.as_ref().map(|(k, v)| (k, v))
         }
     }
 
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptr = self.get_bucket(key);
+        if ptr.is_null() {
+            let i = self.stash_index(key)?;
+            Some(&mut self.stash[i].1)
+        } else {
+            // SAFETY: non-null pointer from `get_bucket` points to a bucket in
+            //  one of the buffers, the reference borrows `self` mutably
+            unsafe { &mut *ptr }.as_mut().map(|(_, v)| v)
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        !self.get_bucket(key).is_null() || self.stash_index(key).is_some()
+    }
+
     pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
     where
         K: Borrow<Q>,
-        Q: Eq + Hash + fmt::Debug,
+        Q: Eq + Hash,
     {
         let ptr = self.get_bucket(key);
         if ptr.is_null() {
-            None
+            let i = self.stash_index(key)?;
+            self.len -= 1;
+            Some(self.stash.swap_remove(i))
         } else {
             self.len -= 1;
             unsafe { ptr::replace(ptr, None) }
@@ -333,57 +569,68 @@ where
             return ptr::null_mut();
         }
 
-        let hash = self.hash_key1(key);
-        let index = self.preferred_index(hash);
-        let maybe_val = unsafe { self.buf1.as_ptr().add(index) };
-        match unsafe { &*maybe_val } {
-            Some((ref k, _)) if k.borrow() == key => return maybe_val,
-            _ => {}
+        let bucket = self.preferred_index(self.hash_key1(key));
+        // SAFETY: bucket < cap
+        if let Some(slot) = unsafe { Self::find_in_bucket(self.buf1, bucket, key) } {
+            return slot;
         }
 
-        let hash = self.hash_key2(key);
-        let index = self.preferred_index(hash);
-        let maybe_val = unsafe { self.buf2.as_ptr().add(index) };
-        match unsafe { &*maybe_val } {
-            Some((ref k, _)) if k.borrow() == key => maybe_val,
-            _ => ptr::null_mut(),
-        }
+        let bucket = self.preferred_index(self.hash_key2(key));
+        // SAFETY: bucket < cap
+        unsafe { Self::find_in_bucket(self.buf2, bucket, key) }.unwrap_or(ptr::null_mut())
     }
 
-    fn hash_key<Q>(&self, key: &Q) -> (u64, u64)
-    where
-        Q: Hash,
-    {
-        let mut hasher = self.hash_builder1.build_hasher();
-        key.hash(&mut hasher);
-        let h1 = hasher.finish();
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
 
-        let mut hasher = self.hash_builder1.build_hasher();
-        key.hash(&mut hasher);
-        let h2 = hasher.finish();
-        (h1, h2)
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    ///
+    /// The map is unchanged if an error is returned.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_cap = Self::buf_cap_for(required, self.crit_load_factor)?;
+        if new_cap > self.cap {
+            // SAFETY: new_cap > self.cap >= 0
+            unsafe { self.try_rebuild(new_cap) }?;
+            if self.stash.len() > Self::MAX_STASH {
+                self.rehash();
+            }
+        }
+
+        Ok(())
     }
 
     fn hash_key1<Q>(&self, key: &Q) -> u64
     where
         Q: Hash,
     {
-        let mut hasher = self.hash_builder1.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.hash_builder1.hash_one(key)
     }
 
     fn hash_key2<Q>(&self, key: &Q) -> u64
     where
         Q: Hash,
     {
-        let mut hasher = self.hash_builder2.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.hash_builder2.hash_one(key)
     }
 }
 
-impl<K, V> HashMap<K, V> {
+impl<K, V, const B: usize> HashMap<K, V, B> {
     fn grow(&mut self)
     where
         K: Eq + Hash,
@@ -409,10 +656,61 @@ impl<K, V> HashMap<K, V> {
             return;
         }
 
-        // SAFETY: TODO
-        let new_buf1 = unsafe { Self::alloc_new_buf_initialized(new_cap) };
-        let new_buf2 = unsafe { Self::alloc_new_buf_initialized(new_cap) };
+        // SAFETY: new_cap > self.cap >= 0
+        unsafe { self.rebuild(new_cap) };
+        if self.stash.len() > Self::MAX_STASH {
+            self.rehash();
+        }
+    }
+
+    /// Moves all the pairs into new buffers with capacity `new_cap` using the
+    /// current hash builders.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two
+    unsafe fn rebuild(&mut self, new_cap: usize)
+    where
+        K: Eq + Hash,
+    {
+        handle_reserve(unsafe { self.try_rebuild(new_cap) })
+    }
+
+    /// Same as `rebuild` but returns an error instead of aborting if the
+    /// allocation fails. The map is unchanged on error.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two
+    unsafe fn try_rebuild(&mut self, new_cap: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+    {
+        assert!(new_cap.is_power_of_two());
+        // SAFETY: new_cap > 0
+        let new_buf1 = unsafe { Self::try_alloc_new_buf_initialized(new_cap) }?;
+        let new_buf2 = match unsafe { Self::try_alloc_new_buf_initialized(new_cap) } {
+            Ok(buf) => buf,
+            Err(err) => {
+                // SAFETY: new_buf1 was just allocated with the same layout and
+                //  contains only `None`s which don't need to be dropped
+                unsafe { alloc::dealloc(new_buf1.as_ptr().cast::<u8>(), Self::layout(new_cap)) };
+                return Err(err);
+            }
+        };
         let (old_buf1, old_buf2, old_cap) = unsafe { self.swap_buf(new_buf1, new_buf2, new_cap) };
+        if new_cap > old_cap {
+            // more buckets may be enough to empty the stash
+            self.rehash_len = 0;
+        }
 
         if old_cap != 0 {
             // drop old buffer
@@ -420,6 +718,8 @@ impl<K, V> HashMap<K, V> {
             unsafe { alloc::dealloc(old_buf1.as_ptr().cast::<u8>(), old_layout) }
             unsafe { alloc::dealloc(old_buf2.as_ptr().cast::<u8>(), old_layout) }
         }
+
+        Ok(())
     }
 
     /// Allocates new buffer with capacity `new_cap` and initializes all the values to `None`.
@@ -436,18 +736,30 @@ impl<K, V> HashMap<K, V> {
     ///
     /// * if `new_cap * mem::size_of::<Option<Bucket<K, V>>>() > isize::MAX`
     unsafe fn alloc_new_buf_initialized(new_cap: usize) -> NonNull<Option<(K, V)>> {
-        let new_layout = Self::layout(new_cap);
+        handle_reserve(unsafe { Self::try_alloc_new_buf_initialized(new_cap) })
+    }
+
+    /// Same as `alloc_new_buf_initialized` but returns an error if the
+    /// allocation fails or the buffer would be too large.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    unsafe fn try_alloc_new_buf_initialized(
+        new_cap: usize,
+    ) -> Result<NonNull<Option<(K, V)>>, TryReserveError> {
+        let new_layout = Self::try_layout(new_cap)?;
         let new_buf = unsafe { alloc::alloc(new_layout) };
         if new_buf.is_null() {
-            alloc::handle_alloc_error(new_layout);
+            Err(TryReserveError::AllocError { layout: new_layout })
         } else {
             let new_buf = new_buf.cast::<Option<(K, V)>>();
             // init to `None`s
-            for i in 0..new_cap {
+            for i in 0..new_cap * B {
                 unsafe { new_buf.add(i).write(None) };
             }
 
-            unsafe { NonNull::new_unchecked(new_buf) }
+            Ok(unsafe { NonNull::new_unchecked(new_buf) })
         }
     }
 
@@ -456,13 +768,13 @@ impl<K, V> HashMap<K, V> {
     /// # SAFETY
     ///
     /// * `new_buf` must have capacity `new_cap` and all the values must be initialized to `None`
-    /// * `new_cap >= self.cap`
+    /// * `new_cap > 0`
     unsafe fn swap_buf(
         &mut self,
         new_buf1: NonNull<Option<(K, V)>>,
         new_buf2: NonNull<Option<(K, V)>>,
         new_cap: usize,
-    ) -> (NonNull<Option<(K, V)>>, NonNull<Option<(K, V)>>, usize)
+    ) -> (Buf<K, V>, Buf<K, V>, usize)
     where
         K: Eq + Hash,
     {
@@ -470,26 +782,20 @@ impl<K, V> HashMap<K, V> {
         let old_buf2 = mem::replace(&mut self.buf2, new_buf2);
         let old_cap = mem::replace(&mut self.cap, new_cap);
         self.index_mask = self.cap - 1;
-        self.len = 0;
-
-        // insert all items into the new buffers
-        for i in 0..old_cap {
-            let it = unsafe { old_buf1.as_ptr().add(i).read() };
-            match it {
-                Some((k, v)) => {
-                    unsafe { self.insert_unchecked(k, v) };
-                }
-                _ => continue,
-            }
-        }
 
-        for i in 0..old_cap {
-            let it = unsafe { old_buf2.as_ptr().add(i).read() };
-            match it {
-                Some((k, v)) => {
-                    unsafe { self.insert_unchecked(k, v) };
-                }
-                _ => continue,
+        // Move all items into the new buffers, the keys are known to be unique.
+        // Stashed pairs get a new chance to find a bucket too.
+        let old_stash = mem::take(&mut self.stash);
+        // SAFETY: i < old_cap and every bucket is read only once, the old
+        //  buffers are deallocated without dropping the items
+        let old_items = (0..old_cap * B)
+            .map(|i| unsafe { old_buf1.as_ptr().add(i).read() })
+            .chain((0..old_cap * B).map(|i| unsafe { old_buf2.as_ptr().add(i).read() }))
+            .flatten()
+            .chain(old_stash);
+        for (k, v) in old_items {
+            if let Some(homeless) = self.place(k, v) {
+                self.stash.push(homeless);
             }
         }
 
@@ -497,8 +803,10 @@ impl<K, V> HashMap<K, V> {
     }
 }
 
-#[cfg(test)]
-impl<K, V> MapMetrics<K, V> for HashMap<K, V>
+collect::impl_collect!(const B);
+
+#[cfg(any(test, feature = "metrics"))]
+impl<K, V, const B: usize> MapMetrics<K, V> for HashMap<K, V, B>
 where
     K: Hash + Eq,
 {
@@ -511,21 +819,24 @@ where
             return None;
         }
 
-        let hash = self.hash_key1(key);
-        let index = self.preferred_index(hash);
-        let maybe_val = unsafe { self.buf1.as_ptr().add(index) };
-        match unsafe { &*maybe_val } {
-            Some((ref k, v)) if k.borrow() == key => return Some((k, v, 0)),
-            _ => {}
+        let bucket1 = self.preferred_index(self.hash_key1(key));
+        let bucket2 = self.preferred_index(self.hash_key2(key));
+        let found = unsafe {
+            [(self.buf1, bucket1), (self.buf2, bucket2)]
+                .into_iter()
+                .enumerate()
+                .find_map(|(probes, (buf, bucket))| {
+                    Some((Self::find_in_bucket(buf, bucket, key)?, probes))
+                })
+        };
+        if let Some((slot, probes)) = found {
+            let (k, v) = unsafe { &*slot }.as_ref().unwrap();
+            return Some((k, v, probes));
         }
 
-        let hash = self.hash_key2(key);
-        let index = self.preferred_index(hash);
-        let maybe_val = unsafe { self.buf2.as_ptr().add(index) };
-        match unsafe { &*maybe_val } {
-            Some((ref k, v)) if k.borrow() == key => Some((k, v, 1)),
-            _ => None,
-        }
+        let i = self.stash_index(key)?;
+        let (k, v) = &self.stash[i];
+        Some((k, v, 2 + i))
     }
 
     fn len(&self) -> usize {
@@ -536,19 +847,33 @@ where
         self.capacity()
     }
 
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes()
+    }
+
     fn load_factor(&self) -> f64 {
         self.load_factor()
     }
 
     fn name(&self) -> &'static str {
-        "Cuckoo hashing"
+        match B {
+            1 => "Cuckoo hashing",
+            2 => "Cuckoo hashing (2 slots per bucket)",
+            4 => "Cuckoo hashing (4 slots per bucket)",
+            _ => "Cuckoo hashing (multi-slot buckets)",
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use core::cell::Cell;
+    use core::hash::Hasher;
+
     use super::*;
 
+    type HashMap<K, V> = super::HashMap<K, V, 1>;
+
     #[test]
     fn insert() {
         let mut m = HashMap::<i32, i32>::new();
@@ -561,15 +886,93 @@ mod tests {
         assert_eq!(m.len(), 4);
         m.insert(4, 41);
 
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
 
         assert_eq!(m.insert(4, 42), Some((4, 41)));
-        assert_eq!(m.get(&4), Some((&4, &42)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[test]
+    fn allocated_bytes() {
+        let mut m = super::HashMap::<i32, i32, 4>::new();
+        assert_eq!(m.allocated_bytes(), 0);
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        let slots = m.capacity() * mem::size_of::<Option<(i32, i32)>>();
+        let stash = m.stash.capacity() * mem::size_of::<(i32, i32)>();
+        assert_eq!(m.allocated_bytes(), slots + stash);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let dups: HashMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[&5], 3);
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn clone() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in (0..100).step_by(3) {
+            m.remove(&i);
+        }
+
+        let mut c = m.clone();
+        assert_eq!(c.len(), m.len());
+        for i in 0..100 {
+            assert_eq!(c.get(&i), m.get(&i));
+        }
+
+        // the clone is independent of the original
+        c.insert(0, "new".to_owned());
+        c.remove(&1);
+        assert_eq!(m.get(&0), None);
+        assert_eq!(m.get(&1), Some(&"1".to_owned()));
+        assert_eq!(c.get(&0), Some(&"new".to_owned()));
+        assert_eq!(c.get(&1), None);
     }
 
     #[test]
@@ -597,51 +1000,121 @@ mod tests {
         assert!(m.is_empty())
     }
 
-    #[test]
-    #[ignore = "broken, don't know right know how to fix"]
-    fn remove_same_hash() {
-        // The issue here is that is all values hash to same hash then we
-        // always hit the same two buckets in both buffers and thus end up
-        // in infinite loop.
-
-        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-        struct SameHash(i32);
-
-        // They all hash to same value, so they must hit the same index in the
-        // map and thus are part of same probe chain
-        impl Hash for SameHash {
-            fn hash<H: Hasher>(&self, state: &mut H) {
-                1.hash(state);
-            }
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    struct SameHash(i32);
+
+    // They all hash to same value, so they must hit the same two buckets with
+    // any hash builders. Only two of them fit into the buffers, rest must go
+    // to the stash.
+    impl Hash for SameHash {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            1.hash(state);
         }
+    }
 
+    #[test]
+    fn remove_same_hash() {
         let mut m = HashMap::new();
         assert_eq!(m.remove(&SameHash(1)), None);
 
         m.insert(SameHash(1), 11);
-        println!("{m:#?}");
         m.insert(SameHash(2), 21);
-        println!("{m:#?}");
         m.insert(SameHash(3), 31);
-        println!("{m:#?}");
         m.insert(SameHash(5), 51);
-        println!("{m:#?}");
         m.insert(SameHash(4), 41);
-        println!("{m:#?}");
+        assert_eq!(m.len(), 5);
+        assert_eq!(m.stash.len(), 3);
+        assert_eq!(m.insert(SameHash(3), 32), Some((SameHash(3), 31)));
+        assert_eq!(m.get_key_value(&SameHash(3)), Some((&SameHash(3), &32)));
 
         assert_eq!(m.remove(&SameHash(2)), Some((SameHash(2), 21)));
         assert_eq!(m.remove(&SameHash(1)), Some((SameHash(1), 11)));
-        assert_eq!(m.remove(&SameHash(3)), Some((SameHash(3), 31)));
+        assert_eq!(m.remove(&SameHash(3)), Some((SameHash(3), 32)));
         assert_eq!(m.remove(&SameHash(4)), Some((SameHash(4), 41)));
         assert_eq!(m.remove(&SameHash(5)), Some((SameHash(5), 51)));
 
         assert!(m.is_empty());
     }
 
+    thread_local! {
+        static HASH_CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Same as `SameHash` but counts how many times it's hashed
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct CountedSameHash(i32);
+
+    impl Hash for CountedSameHash {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            HASH_CALLS.with(|c| c.set(c.get() + 1));
+            1.hash(state);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        miri,
+        ignore = "nothing for miri to really check, no need to waste time"
+    )]
+    fn many_same_hash() {
+        // No seed can separate these, rebuilding the map on every insert to
+        // try new seeds would make this quadratic.
+        let count = 1000;
+        let mut m = HashMap::new();
+        HASH_CALLS.with(|c| c.set(0));
+        for i in 0..count {
+            m.insert(CountedSameHash(i), i);
+        }
+        // about 2000 per key, rebuilding on every insert takes millions
+        let calls = HASH_CALLS.with(Cell::get);
+        assert!(calls < 5000 * count as usize, "hashed {calls} times");
+
+        assert_eq!(m.len(), count as usize);
+        for i in 0..count {
+            assert_eq!(m.get(&CountedSameHash(i)), Some(&i));
+        }
+    }
+
+    #[test]
+    fn same_hash_bucketized() {
+        // 2 buckets of 4 slots can hold all of them without the stash
+        let mut m = super::HashMap::<_, _, 4>::new();
+        for i in 0..8 {
+            m.insert(SameHash(i), i);
+        }
+        assert_eq!(m.len(), 8);
+        assert!(m.stash.is_empty());
+
+        m.insert(SameHash(8), 8);
+        assert_eq!(m.stash.len(), 1);
+        for i in 0..9 {
+            assert_eq!(m.get(&SameHash(i)), Some(&i));
+        }
+        for i in 0..9 {
+            assert_eq!(m.remove(&SameHash(i)), Some((SameHash(i), i)));
+        }
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn bucketized_high_load() {
+        let count = 7500;
+        let mut m = super::HashMap::<_, _, 4>::with_capacity_and_load_factor(count, 0.95);
+        let cap = m.capacity();
+        for i in 0..count {
+            m.insert(i, i);
+        }
+        assert_eq!(m.capacity(), cap, "grew before reaching the load factor");
+        assert!(m.len() as f64 / m.capacity() as f64 > 0.9);
+        for i in 0..count {
+            assert_eq!(m.get(&i), Some(&i));
+        }
+    }
+
     #[test]
     fn get() {
         let mut m = HashMap::new();
-        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_key_value(&1), None);
 
         m.insert(1, 11);
         m.insert(2, 21);
@@ -653,16 +1126,67 @@ mod tests {
         m.insert(8, 41);
         m.insert(9, 41);
 
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), Some((&6, &41)));
-        assert_eq!(m.get(&7), Some((&7, &41)));
-        assert_eq!(m.get(&8), Some((&8, &41)));
-        assert_eq!(m.get(&9), Some((&9, &41)));
-        assert_eq!(m.get(&10), None);
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), Some((&6, &41)));
+        assert_eq!(m.get_key_value(&7), Some((&7, &41)));
+        assert_eq!(m.get_key_value(&8), Some((&8, &41)));
+        assert_eq!(m.get_key_value(&9), Some((&9, &41)));
+        assert_eq!(m.get_key_value(&10), None);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut m = HashMap::new();
+        m.insert(-1, -1);
+        m.reserve(100);
+        let cap = m.cap;
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.cap, cap, "grew after reserve");
+        assert_eq!(m.len(), 101);
+
+        // nothing to do if there is enough room already
+        m.reserve(0);
+        assert_eq!(m.cap, cap);
+
+        assert_eq!(
+            m.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            m.try_reserve(usize::MAX / 2),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(m.cap, cap);
+        assert_eq!(m.get_key_value(&50), Some((&50, &50)));
     }
 
     mod proptests {
@@ -701,7 +1225,7 @@ mod tests {
 
                 inserts.shuffle(&mut thread_rng());
                 for key in inserts.iter().chain(access.iter()) {
-                    assert_eq!(ref_hmap.get_key_value(key), hmap.get(key));
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
                 }
             }
 
@@ -724,13 +1248,68 @@ mod tests {
                 }
             }
 
+            #[test]
+            fn remove_colliding(
+                mut inserts in proptest::collection::vec(0..200i32, 0..MAP_SIZE / 10),
+                same_hash in proptest::collection::vec(0..20i32, 0..20),
+            ) {
+                // Mix well distributed keys with keys that all collide, every
+                // insert must terminate and stashed keys must be found.
+                let mut ref_hmap = std::collections::HashMap::new();
+                let mut hmap = HashMap::new();
+                for (i, v) in inserts.iter().enumerate() {
+                    let key = (*v, SameHash(0));
+                    assert_eq!(ref_hmap.insert(key, i).map(|old| (key, old)), hmap.insert(key, i));
+                }
+                for (i, v) in same_hash.iter().enumerate() {
+                    let key = (0, SameHash(*v));
+                    assert_eq!(ref_hmap.insert(key, i).map(|old| (key, old)), hmap.insert(key, i));
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+
+                inserts.shuffle(&mut thread_rng());
+                let keys = inserts.iter().map(|v| (*v, SameHash(0)))
+                    .chain(same_hash.iter().map(|v| (0, SameHash(*v))));
+                for key in keys {
+                    assert_eq!(ref_hmap.get_key_value(&key), hmap.get_key_value(&key));
+                    assert_eq!(ref_hmap.remove_entry(&key), hmap.remove(&key));
+                }
+                assert!(hmap.is_empty());
+            }
+
+            #[test]
+            fn bucketized_insert_remove(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..10)
+            ) {
+                let mut ref_hmap = std::collections::HashMap::new();
+                let mut hmap = super::super::HashMap::<_, _, 4>::with_load_factor(0.95);
+                for (i, v) in inserts.iter().enumerate() {
+                    assert_eq!(ref_hmap.insert(*v, i).map(|old| (*v, old)), hmap.insert(*v, i));
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+
+                inserts.shuffle(&mut thread_rng());
+                for key in access.iter().chain(inserts.iter()) {
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
+                    assert_eq!(ref_hmap.remove_entry(key), hmap.remove(key));
+                }
+                assert!(hmap.is_empty());
+            }
+
             #[test]
             #[cfg_attr(miri, ignore = "nothing for miri to really check, no need to waste time")]
             fn with_cap(cap in 0..100_000usize, lf in 0.5..0.999) {
                 let map = HashMap::<u8, ()>::with_capacity_and_load_factor(cap, lf);
-                let will_be_lf = cap as f64/map.capacity() as f64;
-                assert!(will_be_lf < lf);
-                assert!(map.cap.is_power_of_two());
+                if cap > 0 {
+                    let will_be_lf = cap as f64/map.capacity() as f64;
+                    assert!(will_be_lf < lf);
+                    assert!(map.cap.is_power_of_two());
+                } else {
+                    assert_eq!(map.cap, 0);
+                }
             }
         );
     }
@@ -0,0 +1,1181 @@
+//! Hash map with hopscotch hashing
+//!
+//! Every pair is kept within `H` buckets of its preferred (home) bucket. Each
+//! bucket has a bitmap of the buckets in its neighborhood which hold pairs
+//! with it as home, so a lookup only checks those buckets. If the free bucket
+//! found on insert is too far from the home bucket, pairs between them are
+//! moved into the free bucket until it falls into the neighborhood.
+
+extern crate alloc as crate_alloc;
+
+use core::alloc::Layout;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+use core::{fmt, mem};
+use std::collections::hash_map::RandomState;
+
+use crate_alloc::alloc;
+
+use super::collect;
+use super::iter::{self, Slot};
+#[cfg(any(test, feature = "metrics"))]
+use super::metrics::MapMetrics;
+use super::{TryReserveError, buckets_for, clone_buckets, handle_reserve};
+
+/// Size of the neighborhood, the number of bits in `Bucket::hop`
+const H: usize = 32;
+
+pub struct HashMap<K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * every pair is at distance `d < self.neighborhood()` from its home
+    //    bucket `h` (wrapping around the end of the buffer) and bit `d` is set
+    //    in `buf[h].hop`
+    //  * all other bits in all `hop` bitmaps are unset
+    buf: NonNull<Bucket<K, V>>,
+    cap: usize,
+    index_mask: usize,
+    len: usize,
+    hash_builder: S,
+    crit_load_factor: f64,
+    marker: PhantomData<(K, V)>,
+}
+
+#[derive(Debug, Clone)]
+struct Bucket<K, V> {
+    /// Bit `i` is set if bucket `self + i` holds a pair with this bucket as home
+    hop: u32,
+    pair: Option<(K, V)>,
+}
+
+impl<K, V> Bucket<K, V> {
+    const EMPTY: Self = Self { hop: 0, pair: None };
+}
+
+impl<K, V> Slot for Bucket<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn pair(&self) -> Option<(&K, &V)> {
+        self.pair.as_ref().map(|(k, v)| (k, v))
+    }
+
+    fn pair_mut(&mut self) -> Option<(&K, &mut V)> {
+        self.pair.as_mut().map(|(k, v)| (&*k, v))
+    }
+
+    fn take(&mut self) -> Option<(K, V)> {
+        // Once every bucket is taken, all bitmaps are reset as well
+        self.hop = 0;
+        self.pair.take()
+    }
+}
+
+iter::impl_iterators!(Bucket<K, V>);
+collect::impl_collect!(hasher S);
+
+impl<K, V, S> Drop for HashMap<K, V, S> {
+    fn drop(&mut self) {
+        if self.cap == 0 {
+            return;
+        }
+
+        for i in 0..self.cap {
+            let it = unsafe { self.buf.as_ptr().add(i) };
+            unsafe { ptr::drop_in_place(it) };
+        }
+
+        let layout = Self::layout(self.cap);
+        unsafe { alloc::dealloc(self.buf.as_ptr().cast::<u8>(), layout) }
+    }
+}
+
+impl<K, V, S> Clone for HashMap<K, V, S>
+where
+    K: Clone,
+    V: Clone,
+    S: Clone,
+{
+    /// Clones the buffer as is, no rehashing is needed as the hasher is
+    /// cloned too.
+    fn clone(&self) -> Self {
+        let mut s = Self {
+            buf: NonNull::dangling(),
+            cap: 0,
+            index_mask: 0,
+            len: 0,
+            crit_load_factor: self.crit_load_factor,
+            hash_builder: self.hash_builder.clone(),
+            marker: self.marker,
+        };
+        if self.cap == 0 {
+            return s;
+        }
+
+        // SAFETY: cap > 0
+        s.buf = unsafe { Self::alloc_new_buf_initialized(self.cap) };
+        s.cap = self.cap;
+        // SAFETY: both buffers have `cap` initialized buckets. `s` owns its
+        //  buffer, so the buckets cloned so far are dropped if a clone panics.
+        unsafe { clone_buckets(self.buf, s.buf, self.cap) };
+        s.index_mask = self.index_mask;
+        s.len = self.len;
+
+        s
+    }
+}
+
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashMap")
+            .field(
+                "buf",
+                &DebugHashMapBuf {
+                    buf: self.buf,
+                    cap: self.cap,
+                    marker: PhantomData,
+                },
+            )
+            .field("cap", &self.cap)
+            .field("len", &self.len)
+            .field("hash_builder", &self.hash_builder)
+            .finish()
+    }
+}
+
+struct DebugHashMapBuf<'a, K, V> {
+    buf: NonNull<Bucket<K, V>>,
+    cap: usize,
+    marker: PhantomData<&'a Bucket<K, V>>,
+}
+
+impl<'a, K, V> fmt::Debug for DebugHashMapBuf<'a, K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+
+        for i in 0..self.cap {
+            let it = unsafe { &*self.buf.as_ptr().add(i) };
+            list.entry(it);
+        }
+
+        list.finish()
+    }
+}
+
+impl<K, V, S> Default for HashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V> HashMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_load_factor(Self::DEF_CRIT_LOAD_FACTOR)
+    }
+
+    pub fn with_load_factor(load_factor: f64) -> Self {
+        Self::with_capacity_and_load_factor(0, load_factor)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_load_factor(capacity, Self::DEF_CRIT_LOAD_FACTOR)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        Self::with_capacity_load_factor_and_hasher(capacity, lf, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Hopscotch hashing keeps the lookups short even at high load factors
+    const DEF_CRIT_LOAD_FACTOR: f64 = 0.9;
+    const INITIAL_CAP: usize = 4;
+
+    /// Creates a new hash map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which uses `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_load_factor_and_hasher(
+            capacity,
+            Self::DEF_CRIT_LOAD_FACTOR,
+            hash_builder,
+        )
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which grows at load factor `lf` and uses
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_load_factor_and_hasher(capacity: usize, lf: f64, hash_builder: S) -> Self {
+        let (buf, cap, index_mask) = if capacity > 0 {
+            let capacity = handle_reserve(buckets_for(capacity, lf));
+            debug_assert!(capacity.is_power_of_two());
+            debug_assert!(capacity > 0);
+            let new_buf = unsafe { Self::alloc_new_buf_initialized(capacity) };
+            (new_buf, capacity, capacity - 1)
+        } else {
+            (NonNull::dangling(), 0, 0)
+        };
+        Self {
+            buf,
+            cap,
+            index_mask,
+            len: 0,
+            hash_builder,
+            crit_load_factor: lf,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes allocated for the buckets, including the empty and
+    /// tombstoned ones.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        Self::layout(self.cap).size()
+    }
+
+    fn preferred_index(&self, hash: u64) -> usize {
+        debug_assert!(self.cap < isize::MAX as usize);
+        debug_assert!(self.cap.is_power_of_two());
+        // SAFETY: cap <= isize::MAX, hence the result after modulo must be < isize::MAX
+        (hash & self.index_mask as u64) as usize
+    }
+
+    /// Number of buckets starting from the home bucket where a pair can be.
+    ///
+    /// Small maps have fewer buckets than `H` and the neighborhood must not
+    /// wrap around onto itself.
+    fn neighborhood(&self) -> usize {
+        H.min(self.cap)
+    }
+
+    /// Returns the bucket at `index`.
+    ///
+    /// # SAFETY
+    ///
+    /// * `index < self.cap`
+    /// * the caller must ensure there are no other references to the same
+    ///   bucket while the returned pointer is used mutably
+    unsafe fn bucket(&self, index: usize) -> *mut Bucket<K, V> {
+        debug_assert!(index < self.cap);
+        unsafe { self.buf.as_ptr().add(index) }
+    }
+
+    fn load_factor(&self) -> f64 {
+        if self.cap == 0 {
+            return f64::INFINITY;
+        }
+
+        self.len as f64 / self.cap as f64
+    }
+
+    fn layout(cap: usize) -> Layout {
+        Layout::array::<Bucket<K, V>>(cap).unwrap()
+    }
+
+    fn try_layout(cap: usize) -> Result<Layout, TryReserveError> {
+        Layout::array::<Bucket<K, V>>(cap).map_err(|_| TryReserveError::CapacityOverflow)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// # PANICS
+    ///
+    /// * if more than `H` keys have the same hash, they can never fit into
+    ///   one neighborhood
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some((home, offset)) = self.find(&key) {
+            let index = (home + offset) & self.index_mask;
+            // SAFETY: index < cap, we have unique access to the map
+            let bucket = unsafe { &mut *self.bucket(index) };
+            return bucket.pair.replace((key, value));
+        }
+
+        if self.load_factor() > self.crit_load_factor {
+            self.grow()
+        }
+
+        let hash = self.hash_key(&key);
+        self.insert_new(hash, (key, value));
+        None
+    }
+
+    /// Inserts a pair whose key is not in the map, grows the map until the
+    /// pair fits into its neighborhood.
+    fn insert_new(&mut self, hash: u64, mut pair: (K, V)) {
+        loop {
+            match self.try_place(hash, pair) {
+                Ok(()) => {
+                    self.len += 1;
+                    return;
+                }
+                Err(p) => pair = p,
+            }
+
+            // With any reasonable hash function a sparse map has no full
+            // neighborhoods, growing further wouldn't help.
+            assert!(
+                self.len >= self.cap / 4,
+                "more than {H} keys with the same hash"
+            );
+            self.grow();
+        }
+    }
+
+    /// Puts `pair` into the neighborhood of its home bucket, returns it back
+    /// if there is no free bucket or pairs cannot be moved to make room.
+    ///
+    /// Doesn't change `self.len`.
+    fn try_place(&mut self, hash: u64, pair: (K, V)) -> Result<(), (K, V)> {
+        if self.cap == 0 {
+            return Err(pair);
+        }
+
+        let home = self.preferred_index(hash);
+        // SAFETY (all `self.bucket` calls): indices are masked to be < cap
+        //  and no references to buckets are kept across the calls
+        let Some(mut dist) = (0..self.cap).find(|dist| unsafe {
+            (*self.bucket((home + dist) & self.index_mask))
+                .pair
+                .is_none()
+        }) else {
+            return Err(pair);
+        };
+
+        while dist >= self.neighborhood() {
+            let free = (home + dist) & self.index_mask;
+            match self.move_into(free) {
+                Some(moved_from) => dist -= free.wrapping_sub(moved_from) & self.index_mask,
+                None => return Err(pair),
+            }
+        }
+
+        let index = (home + dist) & self.index_mask;
+        unsafe {
+            (*self.bucket(index)).pair = Some(pair);
+            (*self.bucket(home)).hop |= 1 << dist;
+        }
+        Ok(())
+    }
+
+    /// Moves a pair from the buckets before the empty bucket `free` into it,
+    /// so that the pair stays in its neighborhood.
+    ///
+    /// Returns the index of the bucket the pair was moved from, which is now
+    /// empty and closer to the start of the buffer, or `None` if no pair can
+    /// be moved.
+    fn move_into(&mut self, free: usize) -> Option<usize> {
+        // Start from the farthest home bucket which can reach `free`, so
+        // that the free bucket moves back as much as possible.
+        for back in (1..self.neighborhood()).rev() {
+            let home = free.wrapping_sub(back) & self.index_mask;
+            // SAFETY: home < cap and no other references to the bucket exist
+            let hop = unsafe { (*self.bucket(home)).hop };
+            // only the pairs before `free` can be moved forward
+            let movable = hop & ((1 << back) - 1);
+            if movable == 0 {
+                continue;
+            }
+
+            let offset = movable.trailing_zeros() as usize;
+            let from = (home + offset) & self.index_mask;
+            // SAFETY: indices are < cap, `from != free` as `offset < back`
+            //  and no references to the buckets are kept
+            unsafe {
+                let pair = (*self.bucket(from)).pair.take();
+                debug_assert!(pair.is_some());
+                (*self.bucket(free)).pair = pair;
+                (*self.bucket(home)).hop = hop & !(1 << offset) | (1 << back);
+            }
+            return Some(from);
+        }
+
+        None
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let (home, offset) = self.find(key)?;
+        // SAFETY: index < cap, the reference borrows `self`
+        let bucket = unsafe { &*self.bucket((home + offset) & self.index_mask) };
+        bucket.pair()
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let (home, offset) = self.find(key)?;
+        // SAFETY: index < cap, the reference borrows `self` mutably
+        let bucket = unsafe { &mut *self.bucket((home + offset) & self.index_mask) };
+        bucket.pair_mut().map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.find(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let (home, offset) = self.find(key)?;
+        let index = (home + offset) & self.index_mask;
+        // SAFETY: indices are < cap and no references to the buckets are kept
+        let pair = unsafe {
+            (*self.bucket(home)).hop &= !(1 << offset);
+            (*self.bucket(index)).pair.take()
+        };
+        self.len -= 1;
+        pair
+    }
+
+    /// Returns the home bucket of `key` and the offset of the bucket holding
+    /// it from the home bucket, or `None` if the key is not present.
+    fn find<Q>(&self, key: &Q) -> Option<(usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let home = self.preferred_index(hash);
+        // SAFETY: home < cap, the reference doesn't outlive this function
+        let mut hop = unsafe { (*self.bucket(home)).hop };
+        while hop != 0 {
+            let offset = hop.trailing_zeros() as usize;
+            // SAFETY: index < cap, the reference doesn't outlive this function
+            let bucket = unsafe { &*self.bucket((home + offset) & self.index_mask) };
+            if matches!(&bucket.pair, Some((k, _)) if k.borrow() == key) {
+                return Some((home, offset));
+            }
+            hop &= hop - 1;
+        }
+
+        None
+    }
+
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
+
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    ///
+    /// The map is unchanged if an error is returned.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_cap = buckets_for(required, self.crit_load_factor)?;
+        self.try_grow_to(new_cap)
+    }
+
+    fn hash_key<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash,
+    {
+        self.hash_builder.hash_one(key)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    fn grow(&mut self)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let new_cap = if self.cap == 0 {
+            Self::INITIAL_CAP
+        } else {
+            2 * self.cap
+        };
+
+        self.grow_to(new_cap);
+    }
+
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two
+    fn grow_to(&mut self, new_cap: usize)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        handle_reserve(self.try_grow_to(new_cap))
+    }
+
+    /// Same as `grow_to` but returns an error instead of aborting if the
+    /// allocation fails. The map is unchanged on error.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two
+    fn try_grow_to(&mut self, new_cap: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        assert!(new_cap.is_power_of_two());
+        if new_cap <= self.cap {
+            return Ok(());
+        }
+
+        // SAFETY: new_cap > self.cap >= 0
+        let new_buf = unsafe { Self::try_alloc_new_buf_initialized(new_cap) }?;
+        let (old_buf, old_cap) = unsafe { self.swap_buf(new_buf, new_cap) };
+
+        if old_cap != 0 {
+            // drop old buffer
+            let old_layout = Self::layout(old_cap);
+            unsafe { alloc::dealloc(old_buf.as_ptr().cast::<u8>(), old_layout) }
+        }
+
+        Ok(())
+    }
+
+    /// Allocates new buffer with capacity `new_cap` and initializes all the buckets to empty.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap * mem::size_of::<Bucket<K, V>>() > isize::MAX`
+    unsafe fn alloc_new_buf_initialized(new_cap: usize) -> NonNull<Bucket<K, V>> {
+        handle_reserve(unsafe { Self::try_alloc_new_buf_initialized(new_cap) })
+    }
+
+    /// Same as `alloc_new_buf_initialized` but returns an error if the
+    /// allocation fails or the buffer would be too large.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    unsafe fn try_alloc_new_buf_initialized(
+        new_cap: usize,
+    ) -> Result<NonNull<Bucket<K, V>>, TryReserveError> {
+        let new_layout = Self::try_layout(new_cap)?;
+        let new_buf = unsafe { alloc::alloc(new_layout) };
+        if new_buf.is_null() {
+            Err(TryReserveError::AllocError { layout: new_layout })
+        } else {
+            let new_buf = new_buf.cast::<Bucket<K, V>>();
+            for i in 0..new_cap {
+                unsafe { new_buf.add(i).write(Bucket::EMPTY) };
+            }
+
+            Ok(unsafe { NonNull::new_unchecked(new_buf) })
+        }
+    }
+
+    /// Swap current buffer with new one by moving all the items from old buffer into new
+    ///
+    /// If some pair doesn't fit into the new buffer, the map grows again
+    /// while moving the items.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_buf` must have capacity `new_cap` and all the buckets must be empty
+    /// * `new_cap >= self.cap`
+    unsafe fn swap_buf(
+        &mut self,
+        new_buf: NonNull<Bucket<K, V>>,
+        new_cap: usize,
+    ) -> (NonNull<Bucket<K, V>>, usize)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let old_buf = mem::replace(&mut self.buf, new_buf);
+        let old_cap = mem::replace(&mut self.cap, new_cap);
+        self.index_mask = self.cap - 1;
+        self.len = 0;
+
+        // insert all items into the new buffer
+        for i in 0..old_cap {
+            let it = unsafe { old_buf.as_ptr().add(i).read() };
+            if let Some((k, v)) = it.pair {
+                let hash = self.hash_key(&k);
+                self.insert_new(hash, (k, v));
+            }
+        }
+
+        (old_buf, old_cap)
+    }
+}
+
+#[cfg(any(test, feature = "metrics"))]
+impl<K, V, S> MapMetrics<K, V> for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn get_with_metrics<Q>(&self, key: &Q) -> Option<(&K, &V, usize)>
+    where
+        Q: Eq + Hash,
+        K: Borrow<Q>,
+    {
+        let (home, offset) = self.find(key)?;
+        // SAFETY: home < cap
+        let hop = unsafe { (*self.bucket(home)).hop };
+        // buckets checked before the right one
+        let probes = (hop & ((1 << offset) - 1)).count_ones() as usize;
+        let (k, v) = self.get_key_value(key)?;
+        Some((k, v, probes))
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn cap(&self) -> usize {
+        self.cap
+    }
+
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes()
+    }
+
+    fn load_factor(&self) -> f64 {
+        self.load_factor()
+    }
+
+    fn name(&self) -> &'static str {
+        "Hopscotch hashing"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::Hasher;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    struct SameHash(i32);
+
+    // They all hash to same value, so they must hit the same home bucket
+    impl Hash for SameHash {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            1.hash(state);
+        }
+    }
+
+    impl<K: Hash + Eq, V> HashMap<K, V> {
+        fn check_invariants(&self) {
+            let mut bits = 0;
+            for i in 0..self.cap {
+                let bucket = unsafe { &*self.bucket(i) };
+                bits += bucket.hop.count_ones() as usize;
+                assert_eq!(
+                    bucket
+                        .hop
+                        .checked_shr(self.neighborhood() as u32)
+                        .unwrap_or(0),
+                    0
+                );
+                if let Some((k, _)) = &bucket.pair {
+                    let home = self.preferred_index(self.hash_key(k));
+                    let dist = i.wrapping_sub(home) & self.index_mask;
+                    assert!(dist < self.neighborhood());
+                    let home_hop = unsafe { (*self.bucket(home)).hop };
+                    assert_ne!(home_hop & (1 << dist), 0);
+                }
+            }
+            assert_eq!(bits, self.len);
+        }
+    }
+
+    #[test]
+    fn insert() {
+        let mut m = HashMap::<i32, i32>::new();
+        assert!(m.is_empty());
+        m.insert(1, 11);
+        assert_eq!(m.len(), 1);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        assert_eq!(m.len(), 4);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+
+        assert_eq!(m.insert(4, 42), Some((4, 41)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+        m.check_invariants();
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let dups: HashMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[&5], 3);
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn clone() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in (0..100).step_by(3) {
+            m.remove(&i);
+        }
+
+        let mut c = m.clone();
+        assert_eq!(c.len(), m.len());
+        for i in 0..100 {
+            assert_eq!(c.get(&i), m.get(&i));
+        }
+
+        // the clone is independent of the original
+        c.insert(0, "new".to_owned());
+        c.remove(&1);
+        assert_eq!(m.get(&0), None);
+        assert_eq!(m.get(&1), Some(&"1".to_owned()));
+        assert_eq!(c.get(&0), Some(&"new".to_owned()));
+        assert_eq!(c.get(&1), None);
+    }
+
+    #[test]
+    fn remove() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.remove(&2), Some((2, 21)));
+        assert_eq!(m.remove(&2), None);
+        assert_eq!(m.remove(&1), Some((1, 11)));
+        assert_eq!(m.remove(&1), None);
+        assert_eq!(m.remove(&3), Some((3, 31)));
+        assert_eq!(m.remove(&3), None);
+        assert_eq!(m.remove(&4), Some((4, 41)));
+        assert_eq!(m.remove(&4), None);
+        assert_eq!(m.remove(&5), Some((5, 51)));
+        assert_eq!(m.remove(&5), None);
+
+        assert!(m.is_empty());
+        m.check_invariants();
+    }
+
+    #[test]
+    fn remove_same_hash() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&SameHash(1)), None);
+
+        // all of them must be in the same neighborhood
+        for i in 0..H as i32 {
+            m.insert(SameHash(i), i);
+        }
+        m.check_invariants();
+
+        for i in 0..H as i32 {
+            assert_eq!(m.remove(&SameHash(i)), Some((SameHash(i), i)));
+        }
+
+        assert!(m.is_empty());
+        m.check_invariants();
+    }
+
+    #[test]
+    #[should_panic = "keys with the same hash"]
+    fn too_many_same_hash() {
+        let mut m = HashMap::new();
+        for i in 0..=H as i32 {
+            m.insert(SameHash(i), i);
+        }
+    }
+
+    #[test]
+    fn get() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get_key_value(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn displacement() {
+        // Fill a map close to full, the pairs must be moved around to keep
+        // them in their neighborhoods.
+        let mut m = HashMap::with_capacity_and_load_factor(900, 0.95);
+        for i in 0..900 {
+            m.insert(i, i);
+        }
+        m.check_invariants();
+        for i in 0..900 {
+            assert_eq!(m.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn iterators() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+        m.remove(&3);
+        m.remove(&7);
+
+        let mut items: Vec<_> = m.iter().map(|(k, v)| (*k, *v)).collect();
+        items.sort();
+        let expected: Vec<_> = [0, 1, 2, 4, 5, 6, 8, 9].map(|i| (i, i * 10)).into();
+        assert_eq!(items, expected);
+        assert_eq!(m.iter().len(), 8);
+        assert_eq!(m.keys().sum::<i32>(), 35);
+        assert_eq!(m.values().sum::<i32>(), 350);
+
+        for v in m.values_mut() {
+            *v += 1;
+        }
+        for (k, v) in &mut m {
+            *v += k;
+        }
+        assert_eq!(m.get_key_value(&4), Some((&4, &45)));
+
+        let mut drained: Vec<_> = m.drain().collect();
+        drained.sort();
+        assert_eq!(drained.len(), 8);
+        assert_eq!(drained[0], (0, 1));
+        assert!(m.is_empty());
+        assert_eq!(m.iter().next(), None);
+        m.check_invariants();
+
+        // map is still usable after the drain
+        m.insert(1, 1);
+        m.insert(2, 2);
+        let mut owned: Vec<_> = m.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, [(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn iterators_drop_unconsumed() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+
+        let mut drain = m.drain();
+        drain.next();
+        drop(drain);
+        assert!(m.is_empty());
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+        let mut into_iter = m.into_iter();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 9);
+        drop(into_iter);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut m = HashMap::new();
+        m.insert(-1, -1);
+        m.reserve(100);
+        let cap = m.cap;
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.cap, cap, "grew after reserve");
+        assert_eq!(m.len(), 101);
+
+        assert_eq!(
+            m.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(m.cap, cap);
+        assert_eq!(m.get_key_value(&50), Some((&50, &50)));
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+        use rand::seq::SliceRandom;
+        use rand::thread_rng;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn insert_get(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..10)
+            ) {
+                let ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
+
+                let mut hmap = HashMap::with_capacity(ref_hmap.len());
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+                hmap.check_invariants();
+
+                inserts.shuffle(&mut thread_rng());
+                for key in inserts.iter().chain(access.iter()) {
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
+                }
+            }
+
+            #[test]
+            fn iter_drain(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::new();
+                for v in &inserts {
+                    ref_hmap.insert(*v, *v);
+                    hmap.insert(*v, *v);
+                }
+                for v in &removes {
+                    assert_eq!(ref_hmap.remove_entry(v), hmap.remove(v));
+                }
+                hmap.check_invariants();
+
+                let mut expected: Vec<_> = ref_hmap.into_iter().collect();
+                expected.sort();
+                let mut items: Vec<_> = hmap.iter().map(|(k, v)| (*k, *v)).collect();
+                items.sort();
+                assert_eq!(&items, &expected);
+
+                let mut drained: Vec<_> = hmap.drain().collect();
+                drained.sort();
+                assert_eq!(drained, expected);
+                assert!(hmap.is_empty());
+                hmap.check_invariants();
+
+                // the map must be fully reusable after the drain
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+                let mut owned: Vec<_> = hmap.into_iter().map(|(k, _)| k).collect();
+                owned.sort();
+                owned.dedup();
+                let mut inserts = inserts;
+                inserts.sort();
+                inserts.dedup();
+                assert_eq!(owned, inserts);
+            }
+
+            #[test]
+            fn remove(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..10)
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
+                let mut hmap = HashMap::with_capacity(ref_hmap.len());
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+
+                inserts.shuffle(&mut thread_rng());
+                for key in access.iter().chain(inserts.iter()) {
+                    assert_eq!(ref_hmap.remove_entry(key), hmap.remove(key));
+                }
+                hmap.check_invariants();
+            }
+
+            #[test]
+            fn high_load(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                lf in 0.9..0.999,
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::with_load_factor(lf);
+                for v in &inserts {
+                    assert_eq!(ref_hmap.insert(*v, *v).map(|old| (*v, old)), hmap.insert(*v, *v));
+                }
+                hmap.check_invariants();
+                for key in &inserts {
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
+                }
+            }
+
+            #[test]
+            #[cfg_attr(miri, ignore = "nothing for miri to really check, no need to waste time")]
+            fn with_cap(cap in 0..100_000usize, lf in 0.5..0.999) {
+                let map = HashMap::<u8, ()>::with_capacity_and_load_factor(cap, lf);
+                if cap > 0 {
+                    let will_be_lf = cap as f64/map.cap as f64;
+                    assert!(will_be_lf < lf);
+                    assert!(map.cap.is_power_of_two());
+                } else {
+                    assert_eq!(map.cap, 0);
+                }
+            }
+        );
+    }
+}
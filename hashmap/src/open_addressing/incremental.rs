@@ -0,0 +1,479 @@
+//! Linear probing hash map with incremental resizing
+//!
+//! Growing a map usually moves every pair into the new buffer inside a single
+//! insert. Here the old map is kept around instead and every following insert
+//! or remove moves a few pairs from the old map into the new one. Lookups
+//! check both maps until the old one is empty.
+//!
+//! The new map has twice the capacity, so the move is always done before the
+//! new map fills up and needs to grow again.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+use core::iter::FusedIterator;
+use core::mem;
+
+use super::collect;
+use super::linear_probing;
+use super::{TryReserveError, handle_reserve};
+
+pub struct HashMap<K, V> {
+    // INVARIANTS:
+    //  * a key is either in `current` or `old`, never in both
+    //  * `old` is empty if there is no resize in progress
+    current: linear_probing::HashMap<K, V>,
+    old: linear_probing::HashMap<K, V>,
+    /// All pairs in `old` before this bucket have been moved already
+    migrate_index: usize,
+}
+
+impl<K, V> Clone for HashMap<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+            old: self.old.clone(),
+            // the clone of `old` has no tombstones, so all of it must be moved
+            migrate_index: 0,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for HashMap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashMap")
+            .field("current", &self.current)
+            .field("old", &self.old)
+            .field("migrate_index", &self.migrate_index)
+            .finish()
+    }
+}
+
+impl<K, V> Default for HashMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> HashMap<K, V> {
+    /// Number of pairs moved from the old map on every insert and remove
+    const MIGRATE_STEP: usize = 4;
+
+    pub fn new() -> Self {
+        Self::from_current(linear_probing::HashMap::new())
+    }
+
+    pub fn with_load_factor(load_factor: f64) -> Self {
+        Self::from_current(linear_probing::HashMap::with_load_factor(load_factor))
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_current(linear_probing::HashMap::with_capacity(capacity))
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        Self::from_current(linear_probing::HashMap::with_capacity_and_load_factor(
+            capacity, lf,
+        ))
+    }
+
+    fn from_current(current: linear_probing::HashMap<K, V>) -> Self {
+        let old = linear_probing::HashMap::with_load_factor(current.crit_load_factor());
+        Self {
+            current,
+            old,
+            migrate_index: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.len() + self.old.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of bytes allocated for the buckets of both tables, the old one
+    /// is only allocated while a resize is in progress.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        self.current.allocated_bytes() + self.old.allocated_bytes()
+    }
+
+    /// Returns `true` if pairs are still being moved from the old map.
+    pub fn is_resizing(&self) -> bool {
+        !self.old.is_empty()
+    }
+
+    /// Iterate over all key-value pairs in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            current: self.current.iter(),
+            old: self.old.iter(),
+        }
+    }
+}
+
+impl<K, V> HashMap<K, V>
+where
+    K: Hash + Eq,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        self.migrate(Self::MIGRATE_STEP);
+
+        if !self.is_resizing() && self.current.needs_grow() {
+            self.start_resize();
+        }
+
+        if let Some(old) = self.old.remove(&key) {
+            self.current.insert(key, value);
+            return Some(old);
+        }
+
+        self.current.insert(key, value)
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.current
+            .get_key_value(key)
+            .or_else(|| self.old.get_key_value(key))
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        match self.current.get_mut(key) {
+            Some(v) => Some(v),
+            None => self.old.get_mut(key),
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.current.contains_key(key) || self.old.contains_key(key)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.migrate(Self::MIGRATE_STEP);
+        self.current.remove(key).or_else(|| self.old.remove(key))
+    }
+
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// Finishes a resize in progress first, so this may move all the pairs.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
+
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len()
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        self.current.try_reserve(required - self.current.len())?;
+        self.migrate(usize::MAX);
+        Ok(())
+    }
+
+    /// Moves the pairs into a new map with twice the capacity, the pairs are
+    /// moved by the following operations.
+    fn start_resize(&mut self) {
+        debug_assert!(!self.is_resizing());
+        let new_cap = 2 * self.current.bucket_count().max(4);
+        let lf = self.current.crit_load_factor();
+        // capacity is given in pairs, ask for slightly less than the load
+        // factor allows so that we get exactly `new_cap` buckets
+        let pairs = ((new_cap - 1) as f64 * lf) as usize;
+        let new = linear_probing::HashMap::with_capacity_and_load_factor(pairs, lf);
+        debug_assert!(new.bucket_count() >= new_cap);
+
+        self.old = mem::replace(&mut self.current, new);
+        self.migrate_index = 0;
+    }
+
+    /// Moves at most `count` pairs from the old map into the current one.
+    fn migrate(&mut self, count: usize) {
+        if self.old.bucket_count() == 0 {
+            return;
+        }
+
+        for _ in 0..count {
+            match self
+                .old
+                .take_next_leaving_tombstone(&mut self.migrate_index)
+            {
+                Some((k, v)) => {
+                    self.current.insert(k, v);
+                }
+                None => break,
+            }
+        }
+
+        if self.old.is_empty() {
+            // drop the old buffer
+            self.old = linear_probing::HashMap::with_load_factor(self.current.crit_load_factor());
+            self.migrate_index = 0;
+        }
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    current: linear_probing::Iter<'a, K, V>,
+    old: linear_probing::Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.current.next().or_else(|| self.old.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.current.len() + self.old.len();
+        (len, Some(len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+collect::impl_collect!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert() {
+        let mut m = HashMap::<i32, i32>::new();
+        assert!(m.is_empty());
+        m.insert(1, 11);
+        assert_eq!(m.len(), 1);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        assert_eq!(m.len(), 4);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+
+        assert_eq!(m.insert(4, 42), Some((4, 41)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let dups: HashMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[&5], 3);
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn remove() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.remove(&2), Some((2, 21)));
+        assert_eq!(m.remove(&2), None);
+        assert_eq!(m.remove(&1), Some((1, 11)));
+        assert_eq!(m.remove(&1), None);
+        assert_eq!(m.remove(&3), Some((3, 31)));
+        assert_eq!(m.remove(&3), None);
+        assert_eq!(m.remove(&4), Some((4, 41)));
+        assert_eq!(m.remove(&4), None);
+        assert_eq!(m.remove(&5), Some((5, 51)));
+        assert_eq!(m.remove(&5), None);
+
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn resize_is_spread_over_inserts() {
+        let mut m = HashMap::new();
+        let mut resizes = 0;
+        for i in 0..10_000 {
+            let was_resizing = m.is_resizing();
+            m.insert(i, i);
+            if m.is_resizing() && !was_resizing {
+                resizes += 1;
+                // only the just inserted pair is in the new map
+                assert_eq!(m.current.len(), 1);
+            }
+            // the old map is emptied before the new map must grow
+            assert!(!(m.is_resizing() && m.current.needs_grow()));
+        }
+        assert!(resizes > 5);
+        assert_eq!(m.len(), 10_000);
+        assert_eq!(m.iter().len(), 10_000);
+        for i in 0..10_000 {
+            assert_eq!(m.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn ops_during_resize() {
+        let mut m = HashMap::new();
+        let mut i = 0;
+        while !m.is_resizing() {
+            m.insert(i, i);
+            i += 1;
+        }
+        assert!(!m.old.is_empty());
+
+        // keys in the old map are updated, moved and removed correctly
+        let in_old = *m.old.keys().next().unwrap();
+        assert_eq!(m.insert(in_old, -1), Some((in_old, in_old)));
+        assert!(!m.old.contains_key(&in_old));
+        assert_eq!(m.get(&in_old), Some(&-1));
+
+        let in_old = *m.old.keys().next().unwrap();
+        *m.get_mut(&in_old).unwrap() = -2;
+        assert_eq!(m.get(&in_old), Some(&-2));
+        assert_eq!(m.remove(&in_old), Some((in_old, -2)));
+        assert!(!m.contains_key(&in_old));
+
+        m.reserve(100);
+        assert!(!m.is_resizing());
+        assert_eq!(m.len(), i as usize - 1);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn insert_remove_mixed(
+                ops in proptest::collection::vec((any::<bool>(), 0..500i32), 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::new();
+                let mut hmap = HashMap::new();
+                for (insert, key) in ops {
+                    if insert {
+                        assert_eq!(ref_hmap.insert(key, key).map(|v| (key, v)), hmap.insert(key, key));
+                    } else {
+                        assert_eq!(ref_hmap.remove_entry(&key), hmap.remove(&key));
+                    }
+                    assert_eq!(ref_hmap.len(), hmap.len());
+                }
+
+                for key in 0..500 {
+                    assert_eq!(ref_hmap.get_key_value(&key), hmap.get_key_value(&key));
+                }
+                let mut items: Vec<_> = hmap.iter().map(|(k, v)| (*k, *v)).collect();
+                items.sort();
+                let mut expected: Vec<_> = ref_hmap.into_iter().collect();
+                expected.sort();
+                assert_eq!(items, expected);
+            }
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! Iterators shared by the single buffer open addressing maps
+
+use core::ptr::NonNull;
+
+/// A bucket of an open addressing map which may hold a key-value pair.
+pub(super) trait Slot {
+    type Key;
+    type Value;
+
+    fn pair(&self) -> Option<(&Self::Key, &Self::Value)>;
+    fn pair_mut(&mut self) -> Option<(&Self::Key, &mut Self::Value)>;
+
+    /// Makes the bucket empty (also removes tombstones) and returns the pair
+    /// if the bucket was occupied.
+    fn take(&mut self) -> Option<(Self::Key, Self::Value)>;
+}
+
+/// Walks over the occupied buckets of a buffer.
+pub(super) struct RawIter<B> {
+    // INVARIANTS:
+    //  * `buf[index..cap]` are initialized buckets which remain valid and are
+    //    not mutated through other references while the iterator is alive
+    //  * `remaining` is the number of occupied buckets in `buf[index..cap]`
+    buf: NonNull<B>,
+    index: usize,
+    cap: usize,
+    remaining: usize,
+}
+
+impl<B: Slot> RawIter<B> {
+    /// # SAFETY
+    ///
+    /// * `buf` must point to `cap` initialized buckets of which `len` are occupied
+    /// * buckets must remain valid and not be mutated through other references
+    ///   while the iterator or any reference returned from it is alive
+    pub(super) unsafe fn new(buf: NonNull<B>, cap: usize, len: usize) -> Self {
+        Self {
+            buf,
+            index: 0,
+            cap,
+            remaining: len,
+        }
+    }
+
+    /// Returns a pointer to the next occupied bucket.
+    pub(super) fn next(&mut self) -> Option<NonNull<B>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        while self.index < self.cap {
+            // SAFETY: index < cap, so the pointer is in bounds of the buffer
+            let bucket = unsafe { NonNull::new_unchecked(self.buf.as_ptr().add(self.index)) };
+            self.index += 1;
+            // SAFETY: bucket is initialized and not mutated elsewhere (see INVARIANTS)
+            if unsafe { bucket.as_ref() }.pair().is_some() {
+                self.remaining -= 1;
+                return Some(bucket);
+            }
+        }
+
+        None
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.remaining
+    }
+}
+
+/// Empties buckets starting from `*index` until an occupied one is found and
+/// returns its pair. Tombstones on the way are emptied as well.
+///
+/// # SAFETY
+///
+/// * `buf` must point to `cap` initialized buckets which are not borrowed elsewhere
+pub(super) unsafe fn take_next<B: Slot>(
+    buf: NonNull<B>,
+    cap: usize,
+    index: &mut usize,
+) -> Option<(B::Key, B::Value)> {
+    while *index < cap {
+        // SAFETY: index < cap and the bucket is not borrowed elsewhere
+        let bucket = unsafe { &mut *buf.as_ptr().add(*index) };
+        *index += 1;
+        if let Some(pair) = bucket.take() {
+            return Some(pair);
+        }
+    }
+
+    None
+}
+
+/// Implements `iter`, `iter_mut`, `keys`, `values`, `values_mut` and `drain`
+/// and the iterator types for a `HashMap<K, V, S>` with a single bucket buffer.
+///
+/// The map must have fields `buf: NonNull<$bucket>`, `cap` and `len`,
+/// and `$bucket` must implement [`Slot`]. The optional `$counter`s are fields
+/// of the map which count tombstones, they are reset once a drain completes.
+macro_rules! impl_iterators {
+    ($bucket:ty $(, $counter:ident)*) => {
+        impl<K, V, S> HashMap<K, V, S> {
+            /// Iterate over all key-value pairs in arbitrary order.
+            pub fn iter(&self) -> Iter<'_, K, V> {
+                Iter {
+                    // SAFETY: buffer has `cap` initialized buckets and `len`
+                    //  pairs, the iterator borrows `self` immutably
+                    raw: unsafe { super::iter::RawIter::new(self.buf, self.cap, self.len) },
+                    marker: PhantomData,
+                }
+            }
+
+            /// Iterate over all key-value pairs with mutable references to the values.
+            pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+                IterMut {
+                    // SAFETY: same as in `iter`, the iterator borrows `self`
+                    //  mutably and every bucket is returned at most once
+                    raw: unsafe { super::iter::RawIter::new(self.buf, self.cap, self.len) },
+                    marker: PhantomData,
+                }
+            }
+
+            pub fn keys(&self) -> Keys<'_, K, V> {
+                Keys { inner: self.iter() }
+            }
+
+            pub fn values(&self) -> Values<'_, K, V> {
+                Values { inner: self.iter() }
+            }
+
+            pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+                ValuesMut {
+                    inner: self.iter_mut(),
+                }
+            }
+
+            /// Removes all pairs from the map and returns them in an iterator.
+            ///
+            /// Pairs that are not consumed are dropped when the iterator is dropped.
+            /// The capacity of the map is kept.
+            pub fn drain(&mut self) -> Drain<'_, K, V, S> {
+                Drain {
+                    map: self,
+                    index: 0,
+                }
+            }
+        }
+
+        impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
+            type Item = (&'a K, &'a V);
+            type IntoIter = Iter<'a, K, V>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter()
+            }
+        }
+
+        impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+            type Item = (&'a K, &'a mut V);
+            type IntoIter = IterMut<'a, K, V>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                self.iter_mut()
+            }
+        }
+
+        impl<K, V, S> IntoIterator for HashMap<K, V, S> {
+            type Item = (K, V);
+            type IntoIter = IntoIter<K, V, S>;
+
+            fn into_iter(self) -> Self::IntoIter {
+                IntoIter {
+                    map: self,
+                    index: 0,
+                }
+            }
+        }
+
+        pub struct Iter<'a, K, V> {
+            raw: super::iter::RawIter<$bucket>,
+            marker: PhantomData<&'a (K, V)>,
+        }
+
+        impl<'a, K, V> Iterator for Iter<'a, K, V> {
+            type Item = (&'a K, &'a V);
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let bucket = self.raw.next()?;
+                // SAFETY: the map is borrowed immutably for 'a
+                let bucket = unsafe { &*bucket.as_ptr() };
+                super::iter::Slot::pair(bucket)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.raw.len(), Some(self.raw.len()))
+            }
+        }
+
+        impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+        impl<K, V> core::iter::FusedIterator for Iter<'_, K, V> {}
+
+        pub struct IterMut<'a, K, V> {
+            raw: super::iter::RawIter<$bucket>,
+            marker: PhantomData<&'a mut (K, V)>,
+        }
+
+        impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+            type Item = (&'a K, &'a mut V);
+
+            fn next(&mut self) -> Option<Self::Item> {
+                let bucket = self.raw.next()?;
+                // SAFETY: the map is borrowed mutably for 'a and every bucket
+                //  is returned only once, so there are no aliasing references
+                let bucket = unsafe { &mut *bucket.as_ptr() };
+                super::iter::Slot::pair_mut(bucket)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.raw.len(), Some(self.raw.len()))
+            }
+        }
+
+        impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+        impl<K, V> core::iter::FusedIterator for IterMut<'_, K, V> {}
+
+        pub struct Keys<'a, K, V> {
+            inner: Iter<'a, K, V>,
+        }
+
+        impl<'a, K, V> Iterator for Keys<'a, K, V> {
+            type Item = &'a K;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.inner.next().map(|(k, _)| k)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+        pub struct Values<'a, K, V> {
+            inner: Iter<'a, K, V>,
+        }
+
+        impl<'a, K, V> Iterator for Values<'a, K, V> {
+            type Item = &'a V;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.inner.next().map(|(_, v)| v)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
+
+        pub struct ValuesMut<'a, K, V> {
+            inner: IterMut<'a, K, V>,
+        }
+
+        impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+            type Item = &'a mut V;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.inner.next().map(|(_, v)| v)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<K, V> ExactSizeIterator for ValuesMut<'_, K, V> {}
+
+        pub struct Drain<'a, K, V, S = RandomState> {
+            // INVARIANTS:
+            //  * all buckets before `index` are empty
+            //  * `map.len` is the number of pairs in buckets after `index`
+            map: &'a mut HashMap<K, V, S>,
+            index: usize,
+        }
+
+        impl<K, V, S> Iterator for Drain<'_, K, V, S> {
+            type Item = (K, V);
+
+            fn next(&mut self) -> Option<Self::Item> {
+                // SAFETY: the map is borrowed mutably for the lifetime of the drain
+                let pair = unsafe {
+                    super::iter::take_next(self.map.buf, self.map.cap, &mut self.index)
+                }?;
+                self.map.len -= 1;
+                Some(pair)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.map.len, Some(self.map.len))
+            }
+        }
+
+        impl<K, V, S> ExactSizeIterator for Drain<'_, K, V, S> {}
+
+        impl<K, V, S> Drop for Drain<'_, K, V, S> {
+            fn drop(&mut self) {
+                // Empty all the remaining buckets including the tombstones.
+                // If a pair panics on drop, the rest are dropped by the map.
+                self.by_ref().for_each(drop);
+                $(self.map.$counter = 0;)*
+            }
+        }
+
+        pub struct IntoIter<K, V, S = RandomState> {
+            // INVARIANTS: same as in `Drain`
+            map: HashMap<K, V, S>,
+            index: usize,
+        }
+
+        impl<K, V, S> Iterator for IntoIter<K, V, S> {
+            type Item = (K, V);
+
+            fn next(&mut self) -> Option<Self::Item> {
+                // SAFETY: we own the map
+                let pair = unsafe {
+                    super::iter::take_next(self.map.buf, self.map.cap, &mut self.index)
+                }?;
+                self.map.len -= 1;
+                Some(pair)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.map.len, Some(self.map.len))
+            }
+        }
+
+        impl<K, V, S> ExactSizeIterator for IntoIter<K, V, S> {}
+    };
+}
+
+pub(super) use impl_iterators;
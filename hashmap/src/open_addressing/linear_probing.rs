@@ -4,7 +4,7 @@ extern crate alloc as crate_alloc;
 
 use core::alloc::Layout;
 use core::borrow::Borrow;
-use core::hash::{BuildHasher, Hash, Hasher};
+use core::hash::{BuildHasher, Hash};
 use core::marker::PhantomData;
 use core::ptr::{self, NonNull};
 use core::{fmt, mem};
@@ -12,16 +12,20 @@ use std::collections::hash_map::RandomState;
 
 use crate_alloc::alloc;
 
-#[cfg(test)]
+use super::collect;
+use super::iter::{self, Slot};
+#[cfg(any(test, feature = "metrics"))]
 use super::metrics::MapMetrics;
-use super::round_up_to_power_of_two;
+use super::{TryReserveError, buckets_for, clone_buckets, distinct_non_null, handle_reserve};
 
-pub struct HashMap<K, V> {
+pub struct HashMap<K, V, S = RandomState> {
     buf: NonNull<Bucket<K, V>>,
     cap: usize,
     index_mask: usize,
     len: usize,
-    hash_builder: RandomState,
+    /// Number of `Bucket::Deleted` tombstones
+    deleted: usize,
+    hash_builder: S,
     crit_load_factor: f64,
     marker: PhantomData<(K, V)>,
 }
@@ -33,7 +37,33 @@ enum Bucket<K, V> {
     Deleted,
 }
 
-impl<K, V> Drop for HashMap<K, V> {
+impl<K, V> Slot for Bucket<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn pair(&self) -> Option<(&K, &V)> {
+        match self {
+            Bucket::Occupied((k, v)) => Some((k, v)),
+            Bucket::Empty | Bucket::Deleted => None,
+        }
+    }
+
+    fn pair_mut(&mut self) -> Option<(&K, &mut V)> {
+        match self {
+            Bucket::Occupied((k, v)) => Some((k, v)),
+            Bucket::Empty | Bucket::Deleted => None,
+        }
+    }
+
+    fn take(&mut self) -> Option<(K, V)> {
+        match mem::replace(self, Bucket::Empty) {
+            Bucket::Occupied(pair) => Some(pair),
+            Bucket::Empty | Bucket::Deleted => None,
+        }
+    }
+}
+
+impl<K, V, S> Drop for HashMap<K, V, S> {
     fn drop(&mut self) {
         if self.cap == 0 {
             return;
@@ -49,38 +79,48 @@ impl<K, V> Drop for HashMap<K, V> {
     }
 }
 
-impl<K, V> Clone for HashMap<K, V>
+impl<K, V, S> Clone for HashMap<K, V, S>
 where
-    K: Eq + Hash + Clone,
+    K: Clone,
     V: Clone,
+    S: Clone,
 {
+    /// Clones the buffer as is, no rehashing is needed as the hasher is
+    /// cloned too.
     fn clone(&self) -> Self {
-        // TODO: improve it
         let mut s = Self {
             buf: NonNull::dangling(),
             cap: 0,
             index_mask: 0,
             len: 0,
+            deleted: 0,
             crit_load_factor: self.crit_load_factor,
             hash_builder: self.hash_builder.clone(),
             marker: self.marker,
         };
-        s.grow_to(self.cap);
-        for i in 0..self.cap {
-            let it = unsafe { &*self.buf.as_ptr().add(i) };
-            if let Bucket::Occupied((k, v)) = it {
-                s.insert(k.clone(), v.clone());
-            }
+        if self.cap == 0 {
+            return s;
         }
 
+        // SAFETY: cap > 0
+        s.buf = unsafe { Self::alloc_new_buf_initialized(self.cap) };
+        s.cap = self.cap;
+        // SAFETY: both buffers have `cap` initialized buckets. `s` owns its
+        //  buffer, so the buckets cloned so far are dropped if a clone panics.
+        unsafe { clone_buckets(self.buf, s.buf, self.cap) };
+        s.index_mask = self.index_mask;
+        s.len = self.len;
+        s.deleted = self.deleted;
+
         s
     }
 }
 
-impl<K, V> fmt::Debug for HashMap<K, V>
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
 where
     K: fmt::Debug,
     V: fmt::Debug,
+    S: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("HashMap")
@@ -122,10 +162,16 @@ where
     }
 }
 
-impl<K, V> HashMap<K, V> {
-    const DEF_CRIT_LOAD_FACTOR: f64 = 0.7;
-    const INITIAL_CAP: usize = 4;
+impl<K, V, S> Default for HashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
 
+impl<K, V> HashMap<K, V> {
     pub fn new() -> Self {
         Self::with_load_factor(Self::DEF_CRIT_LOAD_FACTOR)
     }
@@ -143,9 +189,35 @@ impl<K, V> HashMap<K, V> {
     /// Creates a new hash map with capacity to store at least `capacity` pairs
     /// without reallocation.
     pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        Self::with_capacity_load_factor_and_hasher(capacity, lf, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    const DEF_CRIT_LOAD_FACTOR: f64 = 0.7;
+    const INITIAL_CAP: usize = 4;
+
+    /// Creates a new hash map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which uses `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_load_factor_and_hasher(
+            capacity,
+            Self::DEF_CRIT_LOAD_FACTOR,
+            hash_builder,
+        )
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which grows at load factor `lf` and uses
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_load_factor_and_hasher(capacity: usize, lf: f64, hash_builder: S) -> Self {
         let (buf, cap, index_mask) = if capacity > 0 {
-            let capacity = (capacity as f64 / lf + 1.0) as usize;
-            let capacity = round_up_to_power_of_two(capacity);
+            let capacity = handle_reserve(buckets_for(capacity, lf));
             debug_assert!(capacity.is_power_of_two());
             debug_assert!(capacity > 0);
             let new_buf = unsafe { Self::alloc_new_buf_initialized(capacity) };
@@ -158,7 +230,8 @@ impl<K, V> HashMap<K, V> {
             cap,
             index_mask,
             len: 0,
-            hash_builder: RandomState::new(),
+            deleted: 0,
+            hash_builder,
             crit_load_factor: lf,
             marker: PhantomData,
         }
@@ -172,6 +245,14 @@ impl<K, V> HashMap<K, V> {
         self.len == 0
     }
 
+    /// Number of bytes allocated for the buckets, including the empty and
+    /// tombstoned ones.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        Self::layout(self.cap).size()
+    }
+
     fn preferred_index(&self, hash: u64) -> usize {
         debug_assert!(self.cap < isize::MAX as usize);
         debug_assert!(self.cap.is_power_of_two());
@@ -179,6 +260,43 @@ impl<K, V> HashMap<K, V> {
         (hash & self.index_mask as u64) as usize
     }
 
+    /// Returns the hasher used to hash the keys.
+    ///
+    /// Use it to precompute hashes for [`HashMap::raw_entry`] and
+    /// [`HashMap::raw_entry_mut`].
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Returns a builder for lookups by a precomputed hash and a key
+    /// equivalence closure.
+    pub fn raw_entry(&self) -> RawEntryBuilder<'_, K, V, S> {
+        RawEntryBuilder { map: self }
+    }
+
+    /// Returns the index of the occupied bucket on the probe chain of `hash`
+    /// whose key matches `is_match`.
+    fn find<F>(&self, hash: u64, mut is_match: F) -> Option<usize>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut index = self.preferred_index(hash);
+
+        // there is always an empty bucket, see `needs_grow`
+        loop {
+            match unsafe { &*self.buf.as_ptr().add(index) } {
+                Bucket::Occupied((k, _)) if is_match(k) => break Some(index),
+                Bucket::Occupied(_) | Bucket::Deleted => {}
+                Bucket::Empty => break None,
+            }
+            index = (index + 1) & self.index_mask;
+        }
+    }
+
     fn load_factor(&self) -> f64 {
         if self.cap == 0 {
             return f64::INFINITY;
@@ -187,29 +305,49 @@ impl<K, V> HashMap<K, V> {
         self.len as f64 / self.cap as f64
     }
 
+    /// Returns `true` if the map must grow before inserting one more item.
+    ///
+    /// Tombstones count as used buckets since they lengthen the probe chains
+    /// just like occupied buckets. At least one bucket is always kept empty,
+    /// otherwise a probe for a missing key would never terminate.
+    pub(super) fn needs_grow(&self) -> bool {
+        let used = self.len + self.deleted;
+        used + 1 >= self.cap || used as f64 > self.crit_load_factor * self.cap as f64
+    }
+
     fn layout(cap: usize) -> Layout {
         Layout::array::<Bucket<K, V>>(cap).unwrap()
     }
+
+    fn try_layout(cap: usize) -> Result<Layout, TryReserveError> {
+        Layout::array::<Bucket<K, V>>(cap).map_err(|_| TryReserveError::CapacityOverflow)
+    }
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V, S> HashMap<K, V, S>
 where
     K: Hash + Eq,
+    S: BuildHasher,
 {
     pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
-        if self.load_factor() > self.crit_load_factor {
-            self.grow()
+        match self.entry(key) {
+            Entry::Occupied(e) => Some(e.replace_entry(value)),
+            Entry::Vacant(e) => {
+                e.insert(value);
+                None
+            }
         }
-
-        debug_assert!(self.len < self.cap);
-        unsafe { self.insert_unchecked(key, value) }
     }
 
+    /// Inserts into a buffer without any tombstones.
+    ///
     /// # SAFETY
     ///
     /// * Self must have the capacity for 1 more item
     ///   (ideally we would also not exceed `load_factor > Self::CRIT_LOAD_FACTOR`
     ///   but that's not a safety requirement)
+    /// * buffer must not contain `Bucket::Deleted`, otherwise `key` could be
+    ///   inserted twice if it's already present after a tombstone
     unsafe fn insert_unchecked(&mut self, key: K, value: V) -> Option<(K, V)> {
         let hash = self.hash_key(&key);
         let mut index = self.preferred_index(hash);
@@ -232,7 +370,32 @@ where
         }
     }
 
-    pub fn get<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptr = self.get_bucket(key);
+        if ptr.is_null() {
+            None
+        } else {
+            // SAFETY: non-null pointer from `get_bucket` points to an occupied bucket
+            unsafe { &*ptr }.pair()
+        }
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
     where
         K: Borrow<Q>,
         Q: Eq + Hash,
@@ -241,17 +404,43 @@ where
         if ptr.is_null() {
             None
         } else {
-            match unsafe { &*ptr } {
-                Bucket::Occupied((k, v)) => Some((k, v)),
-                _ => unreachable!(),
-            }
+            // SAFETY: non-null pointer from `get_bucket` points to an occupied
+            //  bucket, the reference borrows `self` mutably
+            unsafe { &mut *ptr }.pair_mut().map(|(_, v)| v)
         }
     }
 
+    /// Returns mutable references to the values of all `keys` at once.
+    ///
+    /// Returns `None` if any of the keys is missing or if the same key is
+    /// given more than once.
+    pub fn get_many_mut<Q, const N: usize>(&mut self, keys: [&Q; N]) -> Option<[&mut V; N]>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptrs = keys.map(|key| self.get_bucket(key));
+        if !distinct_non_null(&ptrs) {
+            return None;
+        }
+
+        // SAFETY: the pointers from `get_bucket` point to distinct occupied
+        //  buckets, the references borrow `self` mutably
+        Some(ptrs.map(|ptr| unsafe { &mut *ptr }.pair_mut().map(|(_, v)| v).unwrap()))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        !self.get_bucket(key).is_null()
+    }
+
     pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
     where
         K: Borrow<Q>,
-        Q: Eq + Hash + fmt::Debug,
+        Q: Eq + Hash,
     {
         let ptr = self.get_bucket(key);
         if ptr.is_null() {
@@ -259,6 +448,7 @@ where
         } else {
             let b = unsafe { ptr::replace(ptr, Bucket::Deleted) };
             self.len -= 1;
+            self.deleted += 1;
             match b {
                 Bucket::Occupied((k, v)) => Some((k, v)),
                 _ => unreachable!(),
@@ -278,41 +468,132 @@ where
         }
 
         let hash = self.hash_key(key);
+        match self.find(hash, |k| k.borrow() == key) {
+            // SAFETY: `find` returns indices in bounds
+            Some(index) => unsafe { self.buf.as_ptr().add(index) },
+            None => ptr::null_mut(),
+        }
+    }
+
+    /// Returns the entry for `key` for in-place manipulation.
+    ///
+    /// The key is hashed and the probe chain is searched only once, any
+    /// following operation on the entry uses the found bucket directly.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        let hash = self.hash_key(&key);
+        match self.find_or_free(hash, |k| *k == key) {
+            Ok(index) => Entry::Occupied(OccupiedEntry {
+                map: self,
+                index,
+                key,
+            }),
+            Err(index) => Entry::Vacant(VacantEntry {
+                map: self,
+                index,
+                key,
+            }),
+        }
+    }
+
+    /// Returns a builder for lookups by a precomputed hash and a key
+    /// equivalence closure that may insert new pairs.
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V, S> {
+        RawEntryBuilderMut { map: self }
+    }
+
+    /// Searches the probe chain of `hash` for a key that matches `is_match`.
+    ///
+    /// Returns `Ok(index)` of the matching bucket or `Err(index)` of the
+    /// bucket where a new pair with this hash must be inserted. The map is
+    /// grown before searching so that inserting into the free bucket cannot
+    /// invalidate the found index.
+    fn find_or_free<F>(&mut self, hash: u64, mut is_match: F) -> Result<usize, usize>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if self.needs_grow() {
+            self.grow()
+        }
+
         let mut index = self.preferred_index(hash);
+        let mut first_deleted = None;
 
-        loop {
-            let maybe_val = unsafe { self.buf.as_ptr().add(index) };
-            match unsafe { &*maybe_val } {
-                Bucket::Occupied((ref k, _)) if k.borrow() == key => break maybe_val,
-                Bucket::Occupied(_) | Bucket::Deleted => {}
-                Bucket::Empty => break ptr::null_mut(),
+        // Probe at most `cap` buckets, there is always an empty bucket so
+        // this is only a safeguard
+        for _ in 0..self.cap {
+            match unsafe { &*self.buf.as_ptr().add(index) } {
+                Bucket::Occupied((k, _)) if is_match(k) => return Ok(index),
+                Bucket::Occupied(_) => {}
+                Bucket::Deleted => {
+                    first_deleted.get_or_insert(index);
+                }
+                Bucket::Empty => {
+                    // reuse the first tombstone on the chain if there is one
+                    return Err(first_deleted.unwrap_or(index));
+                }
             }
             index = (index + 1) & self.index_mask;
         }
+
+        Err(first_deleted.expect("a non-full map to have a free bucket"))
+    }
+
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
+
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    ///
+    /// The map is unchanged if an error is returned.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_cap = buckets_for(required, self.crit_load_factor)?;
+        if new_cap > self.cap {
+            self.try_rebuild(new_cap)
+        } else if (required + self.deleted) as f64 / self.crit_load_factor + 1.0 > self.cap as f64 {
+            // fits only without the tombstones
+            self.try_rebuild(self.cap)
+        } else {
+            Ok(())
+        }
     }
 
     fn hash_key<Q>(&self, key: &Q) -> u64
     where
         Q: Hash,
     {
-        let mut hasher = self.hash_builder.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.hash_builder.hash_one(key)
     }
 }
 
-impl<K, V> HashMap<K, V> {
+impl<K, V, S> HashMap<K, V, S> {
     fn grow(&mut self)
     where
         K: Eq + Hash,
+        S: BuildHasher,
     {
-        let new_cap = if self.cap == 0 {
-            Self::INITIAL_CAP
+        if self.cap == 0 {
+            self.grow_to(Self::INITIAL_CAP);
+        } else if (self.len + 1) as f64 <= self.crit_load_factor * self.cap as f64 / 2.0 {
+            // mostly tombstones, getting rid of them is enough
+            self.rebuild(self.cap);
         } else {
-            2 * self.cap
-        };
-
-        self.grow_to(new_cap);
+            self.grow_to(2 * self.cap);
+        }
     }
 
     /// # PANICS
@@ -321,14 +602,44 @@ impl<K, V> HashMap<K, V> {
     fn grow_to(&mut self, new_cap: usize)
     where
         K: Eq + Hash,
+        S: BuildHasher,
     {
         assert!(new_cap.is_power_of_two());
         if new_cap <= self.cap {
             return;
         }
 
-        // SAFETY: TODO
-        let new_buf = unsafe { Self::alloc_new_buf_initialized(new_cap) };
+        self.rebuild(new_cap);
+    }
+
+    /// Moves all items into a new buffer of `new_cap` buckets, dropping all tombstones.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two or `new_cap < self.cap`
+    fn rebuild(&mut self, new_cap: usize)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        handle_reserve(self.try_rebuild(new_cap))
+    }
+
+    /// Same as `rebuild` but returns an error instead of aborting if the
+    /// allocation fails. The map is unchanged on error.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two or `new_cap < self.cap`
+    fn try_rebuild(&mut self, new_cap: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        assert!(new_cap.is_power_of_two() && new_cap >= self.cap);
+
+        // SAFETY: new_cap is a power of two, hence > 0
+        let new_buf = unsafe { Self::try_alloc_new_buf_initialized(new_cap) }?;
         let (old_buf, old_cap) = unsafe { self.swap_buf(new_buf, new_cap) };
 
         if old_cap != 0 {
@@ -336,6 +647,8 @@ impl<K, V> HashMap<K, V> {
             let old_layout = Self::layout(old_cap);
             unsafe { alloc::dealloc(old_buf.as_ptr().cast::<u8>(), old_layout) }
         }
+
+        Ok(())
     }
 
     /// Allocates new buffer with capacity `new_cap` and initializes all the values to `None`.
@@ -352,10 +665,22 @@ impl<K, V> HashMap<K, V> {
     ///
     /// * if `new_cap * mem::size_of::<Option<Bucket<K, V>>>() > isize::MAX`
     unsafe fn alloc_new_buf_initialized(new_cap: usize) -> NonNull<Bucket<K, V>> {
-        let new_layout = Self::layout(new_cap);
+        handle_reserve(unsafe { Self::try_alloc_new_buf_initialized(new_cap) })
+    }
+
+    /// Same as `alloc_new_buf_initialized` but returns an error if the
+    /// allocation fails or the buffer would be too large.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    unsafe fn try_alloc_new_buf_initialized(
+        new_cap: usize,
+    ) -> Result<NonNull<Bucket<K, V>>, TryReserveError> {
+        let new_layout = Self::try_layout(new_cap)?;
         let new_buf = unsafe { alloc::alloc(new_layout) };
         if new_buf.is_null() {
-            alloc::handle_alloc_error(new_layout);
+            Err(TryReserveError::AllocError { layout: new_layout })
         } else {
             let new_buf = new_buf.cast::<Bucket<K, V>>();
             // init to `None`s
@@ -363,7 +688,7 @@ impl<K, V> HashMap<K, V> {
                 unsafe { new_buf.add(i).write(Bucket::Empty) };
             }
 
-            unsafe { NonNull::new_unchecked(new_buf) }
+            Ok(unsafe { NonNull::new_unchecked(new_buf) })
         }
     }
 
@@ -380,11 +705,13 @@ impl<K, V> HashMap<K, V> {
     ) -> (NonNull<Bucket<K, V>>, usize)
     where
         K: Eq + Hash,
+        S: BuildHasher,
     {
         let old_buf = mem::replace(&mut self.buf, new_buf);
         let old_cap = mem::replace(&mut self.cap, new_cap);
         self.index_mask = self.cap - 1;
         self.len = 0;
+        self.deleted = 0;
 
         // insert all items into the new buffer
         for i in 0..old_cap {
@@ -401,10 +728,462 @@ impl<K, V> HashMap<K, V> {
     }
 }
 
-#[cfg(test)]
-impl<K, V> MapMetrics<K, V> for HashMap<K, V>
+/// Used by [`super::incremental::HashMap`] which moves the pairs out of the
+/// old map a few at a time.
+impl<K, V, S> HashMap<K, V, S> {
+    pub(super) fn bucket_count(&self) -> usize {
+        self.cap
+    }
+
+    pub(super) fn crit_load_factor(&self) -> f64 {
+        self.crit_load_factor
+    }
+
+    /// Removes the first pair in buckets starting from `*index` and leaves a
+    /// tombstone in its place, so that the remaining pairs can still be found.
+    pub(super) fn take_next_leaving_tombstone(&mut self, index: &mut usize) -> Option<(K, V)> {
+        while *index < self.cap {
+            // SAFETY: index < cap and we have unique access to the map
+            let bucket = unsafe { &mut *self.buf.as_ptr().add(*index) };
+            *index += 1;
+            if let Bucket::Occupied(_) = bucket {
+                self.len -= 1;
+                self.deleted += 1;
+                return mem::replace(bucket, Bucket::Deleted).take();
+            }
+        }
+
+        None
+    }
+}
+
+/// A view into a single entry of [`HashMap`], created by [`HashMap::entry`].
+pub enum Entry<'a, K, V, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * `map.buf[index]` is `Bucket::Occupied`
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+    // the key used to look up this entry
+    key: K,
+}
+
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * `map.buf[index]` is `Bucket::Empty` or `Bucket::Deleted` and it's the
+    //    bucket where `key` would be inserted
+    //  * `map.needs_grow()` was `false` when the entry was created
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+    key: K,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant and returns a mutable reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant and returns a mutable reference to the value.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
+    fn pair(&self) -> &(K, V) {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS)
+        match unsafe { &*self.map.buf.as_ptr().add(self.index) } {
+            Bucket::Occupied(pair) => pair,
+            _ => unreachable!(),
+        }
+    }
+
+    fn into_pair_mut(self) -> &'a mut (K, V) {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS),
+        //  the returned reference borrows the map mutably for 'a
+        match unsafe { &mut *self.map.buf.as_ptr().add(self.index) } {
+            Bucket::Occupied(pair) => pair,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the key stored in the map.
+    pub fn key(&self) -> &K {
+        &self.pair().0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.pair().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        // SAFETY: see `into_pair_mut`, the reference borrows `self` mutably
+        match unsafe { &mut *self.map.buf.as_ptr().add(self.index) } {
+            Bucket::Occupied((_, v)) => v,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.into_pair_mut().1
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Replaces the stored pair with the lookup key and `value`, returns the old pair.
+    fn replace_entry(self, value: V) -> (K, V) {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS)
+        match unsafe { &mut *self.map.buf.as_ptr().add(self.index) } {
+            Bucket::Occupied(pair) => mem::replace(pair, (self.key, value)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Removes the entry from the map.
+    pub fn remove(self) -> (K, V) {
+        // SAFETY: index is in bounds and no-one else has references to the bucket
+        let b = unsafe { ptr::replace(self.map.buf.as_ptr().add(self.index), Bucket::Deleted) };
+        self.map.len -= 1;
+        self.map.deleted += 1;
+        match b {
+            Bucket::Occupied(pair) => pair,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        // SAFETY: index is in bounds and the bucket is free (see INVARIANTS)
+        let bucket = unsafe { &mut *self.map.buf.as_ptr().add(self.index) };
+        if let Bucket::Deleted = bucket {
+            self.map.deleted -= 1;
+        }
+        *bucket = Bucket::Occupied((self.key, value));
+        self.map.len += 1;
+        match bucket {
+            Bucket::Occupied((_, v)) => v,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// A builder for raw lookups, created by [`HashMap::raw_entry`].
+pub struct RawEntryBuilder<'a, K, V, S = RandomState> {
+    map: &'a HashMap<K, V, S>,
+}
+
+impl<'a, K, V, S> RawEntryBuilder<'a, K, V, S> {
+    /// Looks up `key`, same as [`HashMap::get_key_value`].
+    pub fn from_key<Q>(self, key: &Q) -> Option<(&'a K, &'a V)>
+    where
+        K: Borrow<Q> + Hash + Eq,
+        Q: Hash + Eq,
+        S: BuildHasher,
+    {
+        let hash = self.map.hash_key(key);
+        self.from_key_hashed_nocheck(hash, key)
+    }
+
+    /// Looks up `key` whose hash is `hash`.
+    ///
+    /// If `hash` is not the hash of `key` the pair will not be found.
+    pub fn from_key_hashed_nocheck<Q>(self, hash: u64, key: &Q) -> Option<(&'a K, &'a V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        self.from_hash(hash, |k| k.borrow() == key)
+    }
+
+    /// Looks up the pair whose key hashes to `hash` and matches `is_match`.
+    pub fn from_hash<F>(self, hash: u64, is_match: F) -> Option<(&'a K, &'a V)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let index = self.map.find(hash, is_match)?;
+        // SAFETY: `find` returns indices of occupied buckets in bounds
+        match unsafe { &*self.map.buf.as_ptr().add(index) } {
+            Bucket::Occupied((k, v)) => Some((k, v)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// A builder for raw lookups that may insert, created by
+/// [`HashMap::raw_entry_mut`].
+pub struct RawEntryBuilderMut<'a, K, V, S = RandomState> {
+    map: &'a mut HashMap<K, V, S>,
+}
+
+impl<'a, K, V, S> RawEntryBuilderMut<'a, K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns the raw entry for `key`.
+    pub fn from_key<Q>(self, key: &Q) -> RawEntryMut<'a, K, V, S>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let hash = self.map.hash_key(key);
+        self.from_key_hashed_nocheck(hash, key)
+    }
+
+    /// Returns the raw entry for `key` whose hash is `hash`.
+    ///
+    /// If `hash` is not the hash of `key` the pair will not be found.
+    pub fn from_key_hashed_nocheck<Q>(self, hash: u64, key: &Q) -> RawEntryMut<'a, K, V, S>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        self.from_hash(hash, |k| k.borrow() == key)
+    }
+
+    /// Returns the raw entry for the key that hashes to `hash` and matches
+    /// `is_match`.
+    pub fn from_hash<F>(self, hash: u64, is_match: F) -> RawEntryMut<'a, K, V, S>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        match self.map.find_or_free(hash, is_match) {
+            Ok(index) => RawEntryMut::Occupied(RawOccupiedEntryMut {
+                map: self.map,
+                index,
+            }),
+            Err(index) => RawEntryMut::Vacant(RawVacantEntryMut {
+                map: self.map,
+                index,
+                hash,
+            }),
+        }
+    }
+}
+
+/// A view into a single entry of [`HashMap`], created by
+/// [`HashMap::raw_entry_mut`].
+pub enum RawEntryMut<'a, K, V, S = RandomState> {
+    Occupied(RawOccupiedEntryMut<'a, K, V, S>),
+    Vacant(RawVacantEntryMut<'a, K, V, S>),
+}
+
+pub struct RawOccupiedEntryMut<'a, K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * `map.buf[index]` is `Bucket::Occupied`
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+}
+
+pub struct RawVacantEntryMut<'a, K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * `map.buf[index]` is `Bucket::Empty` or `Bucket::Deleted` and it's the
+    //    bucket where a key with `hash` would be inserted
+    //  * `map.needs_grow()` was `false` when the entry was created
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+    hash: u64,
+}
+
+impl<'a, K, V, S> RawEntryMut<'a, K, V, S>
+where
+    K: Hash,
+    S: BuildHasher,
+{
+    /// Inserts the pair if the entry is vacant and returns mutable
+    /// references to the stored key and value.
+    pub fn or_insert(self, key: K, value: V) -> (&'a mut K, &'a mut V) {
+        self.or_insert_with(|| (key, value))
+    }
+
+    /// Inserts the result of `default` if the entry is vacant and returns
+    /// mutable references to the stored key and value.
+    pub fn or_insert_with<F>(self, default: F) -> (&'a mut K, &'a mut V)
+    where
+        F: FnOnce() -> (K, V),
+    {
+        match self {
+            RawEntryMut::Occupied(e) => e.into_key_value(),
+            RawEntryMut::Vacant(e) => {
+                let (k, v) = default();
+                e.insert(k, v)
+            }
+        }
+    }
+
+    /// Calls `f` with the key and value if the entry is occupied.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut K, &mut V),
+    {
+        if let RawEntryMut::Occupied(e) = &mut self {
+            let (k, v) = e.get_key_value_mut();
+            f(k, v);
+        }
+        self
+    }
+}
+
+impl<'a, K, V, S> RawOccupiedEntryMut<'a, K, V, S> {
+    fn pair(&self) -> &(K, V) {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS)
+        match unsafe { &*self.map.buf.as_ptr().add(self.index) } {
+            Bucket::Occupied(pair) => pair,
+            _ => unreachable!(),
+        }
+    }
+
+    fn pair_mut(&mut self) -> &mut (K, V) {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS),
+        //  the reference borrows `self` mutably
+        match unsafe { &mut *self.map.buf.as_ptr().add(self.index) } {
+            Bucket::Occupied(pair) => pair,
+            _ => unreachable!(),
+        }
+    }
+
+    fn into_pair_mut(self) -> &'a mut (K, V) {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS),
+        //  the returned reference borrows the map mutably for 'a
+        match unsafe { &mut *self.map.buf.as_ptr().add(self.index) } {
+            Bucket::Occupied(pair) => pair,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.pair().0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.pair().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.pair_mut().1
+    }
+
+    /// Returns mutable references to both the key and the value.
+    ///
+    /// The key must not be changed in a way that changes its hash or
+    /// equality, otherwise the pair cannot be found anymore.
+    pub fn get_key_value_mut(&mut self) -> (&mut K, &mut V) {
+        let (k, v) = self.pair_mut();
+        (k, v)
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.into_pair_mut().1
+    }
+
+    /// Same as [`Self::get_key_value_mut`] but borrows the map for `'a`.
+    pub fn into_key_value(self) -> (&'a mut K, &'a mut V) {
+        let (k, v) = self.into_pair_mut();
+        (k, v)
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the map.
+    pub fn remove_entry(self) -> (K, V) {
+        // SAFETY: index is in bounds and no-one else has references to the bucket
+        let b = unsafe { ptr::replace(self.map.buf.as_ptr().add(self.index), Bucket::Deleted) };
+        self.map.len -= 1;
+        self.map.deleted += 1;
+        match b {
+            Bucket::Occupied(pair) => pair,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<'a, K, V, S> RawVacantEntryMut<'a, K, V, S>
+where
+    K: Hash,
+    S: BuildHasher,
+{
+    /// Inserts the pair and returns mutable references to the stored key and
+    /// value.
+    ///
+    /// # PANICS
+    ///
+    /// * in debug builds if `key` doesn't hash to the hash used for the lookup,
+    ///   such a pair could never be found again
+    pub fn insert(self, key: K, value: V) -> (&'a mut K, &'a mut V) {
+        debug_assert_eq!(self.map.hash_builder.hash_one(&key), self.hash);
+        // SAFETY: index is in bounds and the bucket is free (see INVARIANTS)
+        let bucket = unsafe { &mut *self.map.buf.as_ptr().add(self.index) };
+        if let Bucket::Deleted = bucket {
+            self.map.deleted -= 1;
+        }
+        *bucket = Bucket::Occupied((key, value));
+        self.map.len += 1;
+        match bucket {
+            Bucket::Occupied((k, v)) => (k, v),
+            _ => unreachable!(),
+        }
+    }
+}
+
+iter::impl_iterators!(Bucket<K, V>, deleted);
+collect::impl_collect!(hasher S);
+
+#[cfg(any(test, feature = "metrics"))]
+impl<K, V, S> MapMetrics<K, V> for HashMap<K, V, S>
 where
     K: Hash + Eq,
+    S: BuildHasher,
 {
     fn get_with_metrics<Q>(&self, key: &Q) -> Option<(&K, &V, usize)>
     where
@@ -439,6 +1218,10 @@ where
         self.cap
     }
 
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes()
+    }
+
     fn load_factor(&self) -> f64 {
         self.load_factor()
     }
@@ -446,10 +1229,16 @@ where
     fn name(&self) -> &'static str {
         "Linear probing"
     }
+
+    fn tombstones(&self) -> usize {
+        self.deleted
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use core::hash::Hasher;
+
     use super::*;
 
     #[test]
@@ -464,15 +1253,129 @@ mod tests {
         assert_eq!(m.len(), 4);
         m.insert(4, 41);
 
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
 
         assert_eq!(m.insert(4, 42), Some((4, 41)));
-        assert_eq!(m.get(&4), Some((&4, &42)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[test]
+    fn allocated_bytes() {
+        let mut m = HashMap::new();
+        assert_eq!(m.allocated_bytes(), 0);
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        let bytes = m.allocated_bytes();
+        assert_eq!(bytes, m.cap * mem::size_of::<Bucket<i32, i32>>());
+
+        // tombstones still take up space
+        for i in 0..50 {
+            m.remove(&i);
+        }
+        assert_eq!(m.allocated_bytes(), bytes);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let dups: HashMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[&5], 3);
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn clone() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in (0..100).step_by(3) {
+            m.remove(&i);
+        }
+
+        let mut c = m.clone();
+        assert_eq!(c.len(), m.len());
+        for i in 0..100 {
+            assert_eq!(c.get(&i), m.get(&i));
+        }
+
+        // the clone is independent of the original
+        c.insert(0, "new".to_owned());
+        c.remove(&1);
+        assert_eq!(m.get(&0), None);
+        assert_eq!(m.get(&1), Some(&"1".to_owned()));
+        assert_eq!(c.get(&0), Some(&"new".to_owned()));
+        assert_eq!(c.get(&1), None);
+    }
+
+    #[test]
+    fn clone_panic_drops_cloned() {
+        use std::rc::Rc;
+
+        /// Panics on the `n`th clone
+        struct PanicOnClone(Rc<()>, usize);
+
+        impl Clone for PanicOnClone {
+            fn clone(&self) -> Self {
+                if Rc::strong_count(&self.0) == self.1 {
+                    panic!("clone");
+                }
+                Self(self.0.clone(), self.1)
+            }
+        }
+
+        let counter = Rc::new(());
+        let mut m = HashMap::new();
+        for i in 0..20 {
+            m.insert(i, PanicOnClone(counter.clone(), 30));
+        }
+        assert_eq!(Rc::strong_count(&counter), 21);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| m.clone()));
+        assert!(result.is_err());
+        // the pairs cloned before the panic were dropped
+        assert_eq!(Rc::strong_count(&counter), 21);
+        drop(m);
+        assert_eq!(Rc::strong_count(&counter), 1);
     }
 
     #[test]
@@ -534,7 +1437,7 @@ mod tests {
     #[test]
     fn get() {
         let mut m = HashMap::new();
-        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_key_value(&1), None);
 
         m.insert(1, 11);
         m.insert(2, 21);
@@ -542,12 +1445,242 @@ mod tests {
         m.insert(5, 51);
         m.insert(4, 41);
 
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn entry() {
+        let mut m = HashMap::new();
+        *m.entry(5).or_insert(0) += 1;
+        *m.entry(5).or_insert(0) += 1;
+        assert_eq!(m.get_key_value(&5), Some((&5, &2)));
+        assert_eq!(*m.entry(7).or_insert_with(|| 70), 70);
+        assert_eq!(*m.entry(8).or_default(), 0);
+        assert_eq!(m.len(), 3);
+
+        assert_eq!(*m.entry(7).and_modify(|v| *v *= 2).or_insert(0), 140);
+        assert_eq!(*m.entry(1).and_modify(|v| *v *= 2).or_insert(10), 10);
+        assert_eq!(m.entry(30).key(), &30);
+
+        match m.entry(5) {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), &5);
+                assert_eq!(e.insert(50), 2);
+                assert_eq!(e.get(), &50);
+                assert_eq!(e.remove(), (5, 50));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match m.entry(5) {
+            Entry::Occupied(_) => unreachable!(),
+            Entry::Vacant(e) => assert_eq!(e.into_key(), 5),
+        }
+        assert_eq!(m.len(), 3);
+        assert_eq!(m.get_key_value(&5), None);
+    }
+
+    #[test]
+    fn raw_entry() {
+        let mut m = HashMap::new();
+        for i in 0..10u32 {
+            m.insert((format!("key{i}"), i), i * 10);
+        }
+
+        // look up a `(String, u32)` key with a borrowed `(&str, u32)`, both
+        // hash the same way
+        let hash = m.hasher().hash_one(("key3", 3u32));
+        let found = m
+            .raw_entry()
+            .from_hash(hash, |(s, i)| s == "key3" && *i == 3);
+        assert_eq!(found, Some((&("key3".to_owned(), 3), &30)));
+        let hash = m.hasher().hash_one(("key3", 4u32));
+        assert_eq!(
+            m.raw_entry()
+                .from_hash(hash, |(s, i)| s == "key3" && *i == 4),
+            None
+        );
+        assert_eq!(
+            m.raw_entry().from_key(&("key4".to_owned(), 4)),
+            Some((&("key4".to_owned(), 4), &40))
+        );
+
+        let hash = m.hasher().hash_one(("key5", 5u32));
+        match m
+            .raw_entry_mut()
+            .from_hash(hash, |(s, i)| s == "key5" && *i == 5)
+        {
+            RawEntryMut::Occupied(mut e) => {
+                assert_eq!(e.key(), &("key5".to_owned(), 5));
+                assert_eq!(e.insert(55), 50);
+                assert_eq!(e.get(), &55);
+                assert_eq!(e.remove_entry(), (("key5".to_owned(), 5), 55));
+            }
+            RawEntryMut::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(m.len(), 9);
+
+        // vacant entries only allocate the key on insert
+        let hash = m.hasher().hash_one(("new", 1u32));
+        let (k, v) = m
+            .raw_entry_mut()
+            .from_hash(hash, |(s, i)| s == "new" && *i == 1)
+            .or_insert_with(|| (("new".to_owned(), 1), 1));
+        assert_eq!((&*k, *v), (&("new".to_owned(), 1), 1));
+        m.raw_entry_mut()
+            .from_key(&("new".to_owned(), 1))
+            .and_modify(|_, v| *v += 1)
+            .or_insert(("new".to_owned(), 1), 0);
+        assert_eq!(m.get(&("new".to_owned(), 1)), Some(&2));
+        assert_eq!(m.len(), 10);
+    }
+
+    #[test]
+    fn iterators() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+        m.remove(&3);
+        m.remove(&7);
+
+        let mut items: Vec<_> = m.iter().map(|(k, v)| (*k, *v)).collect();
+        items.sort();
+        let expected: Vec<_> = [0, 1, 2, 4, 5, 6, 8, 9].map(|i| (i, i * 10)).into();
+        assert_eq!(items, expected);
+        assert_eq!(m.iter().len(), 8);
+        assert_eq!(m.keys().sum::<i32>(), 35);
+        assert_eq!(m.values().sum::<i32>(), 350);
+
+        for v in m.values_mut() {
+            *v += 1;
+        }
+        for (k, v) in &mut m {
+            *v += k;
+        }
+        assert_eq!(m.get_key_value(&4), Some((&4, &45)));
+
+        let mut drained: Vec<_> = m.drain().collect();
+        drained.sort();
+        assert_eq!(drained.len(), 8);
+        assert_eq!(drained[0], (0, 1));
+        assert!(m.is_empty());
+        assert_eq!(m.iter().next(), None);
+
+        // map is still usable after the drain
+        m.insert(1, 1);
+        m.insert(2, 2);
+        let mut owned: Vec<_> = m.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, [(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn iterators_drop_unconsumed() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+
+        let mut drain = m.drain();
+        drain.next();
+        drop(drain);
+        assert!(m.is_empty());
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+        let mut into_iter = m.into_iter();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 9);
+        drop(into_iter);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn get_many_mut() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        let [a, b] = m.get_many_mut([&1, &2]).unwrap();
+        mem::swap(a, b);
+        assert_eq!(m.get(&1), Some(&20));
+        assert_eq!(m.get(&2), Some(&10));
+
+        assert_eq!(m.get_many_mut([&1, &1]), None);
+        assert_eq!(m.get_many_mut([&1, &10]), None);
+        assert_eq!(m.get_many_mut::<i32, 0>([]), Some([]));
+    }
+
+    #[test]
+    fn reserve() {
+        let mut m = HashMap::new();
+        m.insert(-1, -1);
+        m.reserve(100);
+        let cap = m.cap;
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.cap, cap, "grew after reserve");
+        assert_eq!(m.len(), 101);
+
+        // nothing to do if there is enough room already
+        m.reserve(0);
+        assert_eq!(m.cap, cap);
+
+        assert_eq!(
+            m.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            m.try_reserve(usize::MAX / 2),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(m.cap, cap);
+        assert_eq!(m.get_key_value(&50), Some((&50, &50)));
+
+        // the pairs fit only if the tombstones are cleared
+        for i in 0..100 {
+            m.remove(&i);
+        }
+        assert_eq!(m.deleted, 100);
+        m.reserve(100);
+        assert_eq!(m.deleted, 0);
+        assert_eq!(m.cap, cap);
+        assert_eq!(m.get_key_value(&-1), Some((&-1, &-1)));
     }
 
     mod proptests {
@@ -586,10 +1719,49 @@ mod tests {
 
                 inserts.shuffle(&mut thread_rng());
                 for key in inserts.iter().chain(access.iter()) {
-                    assert_eq!(ref_hmap.get_key_value(key), hmap.get(key));
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
                 }
             }
 
+            #[test]
+            fn iter_drain(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::new();
+                for v in &inserts {
+                    ref_hmap.insert(*v, *v);
+                    hmap.insert(*v, *v);
+                }
+                for v in &removes {
+                    assert_eq!(ref_hmap.remove_entry(v), hmap.remove(v));
+                }
+
+                let mut expected: Vec<_> = ref_hmap.into_iter().collect();
+                expected.sort();
+                let mut items: Vec<_> = hmap.iter().map(|(k, v)| (*k, *v)).collect();
+                items.sort();
+                assert_eq!(&items, &expected);
+
+                let mut drained: Vec<_> = hmap.drain().collect();
+                drained.sort();
+                assert_eq!(drained, expected);
+                assert!(hmap.is_empty());
+
+                // the map must be fully reusable after the drain
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+                let mut owned: Vec<_> = hmap.into_iter().map(|(k, _)| k).collect();
+                owned.sort();
+                owned.dedup();
+                let mut inserts = inserts;
+                inserts.sort();
+                inserts.dedup();
+                assert_eq!(owned, inserts);
+            }
+
             #[test]
             fn remove(
                 mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
@@ -609,6 +1781,28 @@ mod tests {
                 }
             }
 
+            #[test]
+            fn entry_counts(
+                keys in proptest::collection::vec(0..100i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..100i32, 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, usize, RandomState>::new();
+                let mut hmap = HashMap::new();
+                // interleave removes to create tombstones which the entries can reuse
+                for (i, key) in keys.iter().enumerate() {
+                    *ref_hmap.entry(*key).or_default() += 1;
+                    *hmap.entry(*key).or_default() += 1;
+                    if let Some(r) = removes.get(i) {
+                        assert_eq!(ref_hmap.remove_entry(r), hmap.remove(r));
+                    }
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+                for key in 0..100 {
+                    assert_eq!(ref_hmap.get_key_value(&key), hmap.get_key_value(&key));
+                }
+            }
+
             #[test]
             #[cfg_attr(miri, ignore = "nothing for miri to really check, no need to waste time")]
             fn with_cap(cap in 0..100_000usize, lf in 0.5..0.999) {
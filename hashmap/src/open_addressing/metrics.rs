@@ -1,12 +1,23 @@
+//! Probe length metrics of the open addressing maps
+//!
+//! Enabled by the `metrics` feature. Every map implements [`MapMetrics`],
+//! which looks up a set of keys and collects the number of probes each of
+//! them took into a [`Report`] together with the memory used by the map.
+//! Reports of several maps can be printed side
+//! by side with [`print_comparison`].
+
 use core::borrow::Borrow;
+use core::fmt::{self, Write};
 use core::hash::Hash;
+#[cfg(test)]
 use std::collections::HashSet;
 
-use hdrhistogram::Histogram;
+#[cfg(test)]
 use rand::SeedableRng;
+#[cfg(test)]
 use rand_chacha::ChaCha8Rng;
 
-pub(super) trait MapMetrics<K, V> {
+pub trait MapMetrics<K, V> {
     /// Return (key, value, number of probes)
     ///
     /// Note that number of probes starts from 0, so if you get it at preferred index then it's 0
@@ -15,11 +26,190 @@ pub(super) trait MapMetrics<K, V> {
         Q: Eq + Hash,
         K: Borrow<Q>;
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     fn cap(&self) -> usize;
+    /// Number of bytes allocated for the buffers of the map, see the
+    /// `allocated_bytes` methods of the maps
+    fn allocated_bytes(&self) -> usize;
     fn load_factor(&self) -> f64;
     fn name(&self) -> &'static str;
+
+    /// Number of removed buckets which still take up space in the buffer.
+    ///
+    /// Maps which don't leave tombstones behind always return 0.
+    fn tombstones(&self) -> usize {
+        0
+    }
+
+    /// Looks up all the `keys` and collects the probe lengths of the ones
+    /// which are in the map.
+    fn report<'a, I>(&self, keys: I) -> Report
+    where
+        I: IntoIterator<Item = &'a K>,
+        K: Eq + Hash + 'a,
+    {
+        let mut probes = ProbeHistogram::new();
+        for key in keys {
+            if let Some((_, _, p)) = self.get_with_metrics(key) {
+                probes.record(p);
+            }
+        }
+
+        Report {
+            name: self.name(),
+            len: self.len(),
+            cap: self.cap(),
+            allocated_bytes: self.allocated_bytes(),
+            load_factor: self.load_factor(),
+            tombstones: self.tombstones(),
+            probes,
+        }
+    }
+}
+
+/// Number of keys found with each probe length.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbeHistogram {
+    /// `counts[p]` is the number of keys found after `p` probes
+    counts: Vec<usize>,
+    total: usize,
 }
 
+impl ProbeHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, probes: usize) {
+        if self.counts.len() <= probes {
+            self.counts.resize(probes + 1, 0);
+        }
+        self.counts[probes] += 1;
+        self.total += 1;
+    }
+
+    /// Returns the number of keys found with each probe length, index is
+    /// the probe length.
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    /// Number of recorded lookups.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Returns the longest recorded probe length.
+    pub fn max(&self) -> Option<usize> {
+        self.counts.iter().rposition(|&c| c > 0)
+    }
+
+    /// Returns the average probe length or `NaN` if nothing was recorded.
+    pub fn mean(&self) -> f64 {
+        let sum: usize = self.counts.iter().enumerate().map(|(p, &c)| p * c).sum();
+        sum as f64 / self.total as f64
+    }
+
+    /// Returns the smallest probe length which at least `q` of the lookups
+    /// didn't exceed.
+    ///
+    /// # PANICS
+    ///
+    /// * if `q` is not in `[0, 1]`
+    pub fn quantile(&self, q: f64) -> Option<usize> {
+        assert!((0.0..=1.0).contains(&q), "quantile must be in [0, 1]");
+        if self.total == 0 {
+            return None;
+        }
+
+        let target = ((q * self.total as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        self.counts.iter().position(|&c| {
+            seen += c;
+            seen >= target
+        })
+    }
+}
+
+/// Probe metrics of a single map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub name: &'static str,
+    pub len: usize,
+    pub cap: usize,
+    pub allocated_bytes: usize,
+    pub load_factor: f64,
+    pub tombstones: usize,
+    pub probes: ProbeHistogram,
+}
+
+/// Writes the reports as a table with one row per map.
+pub fn write_comparison(w: &mut impl Write, reports: &[Report]) -> fmt::Result {
+    let name_width = reports
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    writeln!(
+        w,
+        "{:<name_width$} {:>9} {:>9} {:>11} {:>8} {:>6} {:>10} {:>7} {:>4} {:>4} {:>4} {:>6}",
+        "name",
+        "len",
+        "cap",
+        "bytes",
+        "B/item",
+        "lf",
+        "tombstones",
+        "mean",
+        "p50",
+        "p90",
+        "p99",
+        "max"
+    )?;
+    for r in reports {
+        let quantile = |q| r.probes.quantile(q).map_or(0, |p| p);
+        // empty maps have nothing to divide by
+        let bytes_per_item = if r.len == 0 {
+            String::from("-")
+        } else {
+            format!("{:.1}", r.allocated_bytes as f64 / r.len as f64)
+        };
+        let mean = if r.probes.total() == 0 {
+            String::from("-")
+        } else {
+            format!("{:.3}", r.probes.mean())
+        };
+        writeln!(
+            w,
+            "{:<name_width$} {:>9} {:>9} {:>11} {:>8} {:>6.3} {:>10} {:>7} {:>4} {:>4} {:>4} {:>6}",
+            r.name,
+            r.len,
+            r.cap,
+            r.allocated_bytes,
+            bytes_per_item,
+            r.load_factor,
+            r.tombstones,
+            mean,
+            quantile(0.5),
+            quantile(0.9),
+            quantile(0.99),
+            r.probes.max().unwrap_or(0),
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints the reports as a table with one row per map.
+pub fn print_comparison(reports: &[Report]) {
+    let mut table = String::new();
+    write_comparison(&mut table, reports).unwrap();
+    println!("{table}");
+}
+
+#[cfg(test)]
 fn gen_unique_keys_int(count: usize, random: bool, key_max: u64) -> HashSet<u64> {
     let mut set = HashSet::with_capacity(count);
     if random {
@@ -35,80 +225,61 @@ fn gen_unique_keys_int(count: usize, random: bool, key_max: u64) -> HashSet<u64>
 }
 
 #[test]
-#[ignore = "not really a test but prints some metrics about different maps"]
-fn metrics() {
-    struct Stats {
-        probes: Histogram<u64>,
-    }
-
-    fn calc_stats<'a, K: 'a, V>(
-        keys: impl Iterator<Item = &'a K>,
-        map: &impl MapMetrics<K, V>,
-    ) -> Stats
-    where
-        K: Eq + Hash,
-    {
-        let mut probes_hist = Histogram::new(3).unwrap();
+fn histogram() {
+    let mut h = ProbeHistogram::new();
+    assert_eq!(h.max(), None);
+    assert_eq!(h.quantile(0.5), None);
 
-        for key in keys {
-            let (_, _, probes) = match map.get_with_metrics(key) {
-                Some(v) => v,
-                None => {
-                    continue;
-                }
-            };
-            probes_hist.record(probes as u64).unwrap();
-        }
+    for p in [0, 0, 0, 1, 1, 3] {
+        h.record(p);
+    }
+    assert_eq!(h.counts(), [3, 2, 0, 1]);
+    assert_eq!(h.total(), 6);
+    assert_eq!(h.max(), Some(3));
+    assert_eq!(h.mean(), 5.0 / 6.0);
+    assert_eq!(h.quantile(0.0), Some(0));
+    assert_eq!(h.quantile(0.5), Some(0));
+    assert_eq!(h.quantile(0.6), Some(1));
+    assert_eq!(h.quantile(1.0), Some(3));
+}
 
-        Stats {
-            probes: probes_hist,
-        }
+#[test]
+fn report_and_table() {
+    let mut m = super::linear_probing::HashMap::new();
+    for i in 0..100 {
+        m.insert(i, i);
+    }
+    for i in 0..10 {
+        m.remove(&i);
     }
 
-    fn print_stats<'a, K: 'a, V>(keys: impl Iterator<Item = &'a K>, map: &impl MapMetrics<K, V>)
-    where
-        K: Eq + Hash,
-    {
-        #[derive(Debug)]
-        struct StatsPrint {
-            min: u64,
-            p10: u64,
-            p25: u64,
-            p50: u64,
-            p75: u64,
-            p90: u64,
-            max: u64,
-            mean: f64,
-            std: f64,
-        }
+    let keys = (0..200).collect::<Vec<_>>();
+    let report = m.report(&keys);
+    assert_eq!(report.name, "Linear probing");
+    assert_eq!(report.len, 90);
+    assert_eq!(report.allocated_bytes, m.allocated_bytes());
+    assert_eq!(report.allocated_bytes, m.cap() * 12);
+    assert_eq!(report.probes.total(), 90);
+    assert_eq!(report.tombstones, m.tombstones());
+    assert!(report.tombstones <= 10);
 
-        impl StatsPrint {
-            fn new(stats: &Histogram<u64>) -> Self {
-                Self {
-                    min: stats.min(),
-                    p10: stats.value_at_quantile(0.10),
-                    p25: stats.value_at_quantile(0.25),
-                    p50: stats.value_at_quantile(0.5),
-                    p75: stats.value_at_quantile(0.75),
-                    p90: stats.value_at_quantile(0.9),
-                    max: stats.max(),
-                    mean: stats.mean(),
-                    std: stats.stdev(),
-                }
-            }
-        }
+    let mut table = String::new();
+    write_comparison(&mut table, &[report.clone(), report]).unwrap();
+    assert_eq!(table.lines().count(), 3);
+    assert!(table.lines().nth(1).unwrap().starts_with("Linear probing "));
 
-        let stats = calc_stats(keys, map);
-        println!(
-            "\n{}\nmetrics @ load factor={}/{}={:.3}\n  probes={:#?}",
-            map.name(),
-            map.len(),
-            map.cap(),
-            map.load_factor(),
-            StatsPrint::new(&stats.probes)
-        );
-    }
+    let empty = super::linear_probing::HashMap::<i32, i32>::with_capacity(10).report(&keys);
+    let mut table = String::new();
+    write_comparison(&mut table, &[empty]).unwrap();
+    let row = table.lines().nth(1).unwrap();
+    assert!(!row.contains("NaN") && !row.contains("inf"));
+    assert_eq!(row.split_whitespace().nth(5), Some("-"));
+    assert_eq!(row.split_whitespace().nth(8), Some("-"));
+}
 
+#[test]
+#[ignore = "not really a test but prints some metrics about different maps"]
+fn metrics() {
     let cap = 2_usize.pow(17);
     let count_at_0999 = (cap as f64 * 0.999) as usize;
     let count_at_099 = (cap as f64 * 0.99) as usize;
@@ -118,28 +289,54 @@ fn metrics() {
     let mut rh = super::robin_hood::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
     let mut lin =
         super::linear_probing::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
+    let mut soa = super::soa::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
     let mut quad =
         super::quadratic_probing::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
-    let mut cuckoo = super::cuckoo::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
+    let mut double =
+        super::double_hashing::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
+    let mut hop = super::hopscotch::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
+    let mut swiss =
+        super::swiss_table::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
+    // the cellar is on top of the address region, its capacity isn't `cap`
+    let mut coalesced =
+        super::coalesced::HashMap::with_capacity_and_load_factor(cap - 1, load_factor);
+    let mut cuckoo =
+        super::cuckoo::HashMap::<_, _, 1>::with_capacity_and_load_factor(cap - 1, load_factor);
+    let mut cuckoo4 =
+        super::cuckoo::HashMap::<_, _, 4>::with_capacity_and_load_factor(cap - 1, load_factor);
     assert_eq!(rh.cap(), cap);
     assert_eq!(lin.cap(), cap);
+    assert_eq!(soa.cap(), cap);
     assert_eq!(quad.cap(), cap);
+    assert_eq!(double.cap(), cap);
+    assert_eq!(hop.cap(), cap);
+    assert_eq!(swiss.cap(), cap);
     assert_eq!(cuckoo.cap(), cap);
+    assert_eq!(cuckoo4.cap(), cap);
     for k in keys.iter().copied() {
         rh.insert(k, k);
         lin.insert(k, k);
+        soa.insert(k, k);
         quad.insert(k, k);
+        double.insert(k, k);
+        hop.insert(k, k);
+        swiss.insert(k, k);
+        coalesced.insert(k, k);
         cuckoo.insert(k, k);
-        if rh.len() == count_at_090 || rh.len() == count_at_099 {
-            print_stats(keys.iter(), &lin);
-            print_stats(keys.iter(), &rh);
-            print_stats(keys.iter(), &quad);
-            print_stats(keys.iter(), &cuckoo);
+        cuckoo4.insert(k, k);
+        if rh.len() == count_at_090 || rh.len() == count_at_099 || rh.len() == keys.len() {
+            print_comparison(&[
+                lin.report(&keys),
+                soa.report(&keys),
+                rh.report(&keys),
+                quad.report(&keys),
+                double.report(&keys),
+                hop.report(&keys),
+                swiss.report(&keys),
+                coalesced.report(&keys),
+                cuckoo.report(&keys),
+                cuckoo4.report(&keys),
+            ]);
         }
     }
-
-    print_stats(keys.iter(), &lin);
-    print_stats(keys.iter(), &rh);
-    print_stats(keys.iter(), &quad);
-    print_stats(keys.iter(), &cuckoo);
 }
@@ -1,15 +1,96 @@
+pub mod coalesced;
+mod collect;
 pub mod cuckoo;
+pub mod double_hashing;
+pub mod hopscotch;
+pub mod incremental;
+mod iter;
 pub mod linear_probing;
 pub mod quadratic_probing;
 pub mod robin_hood;
+pub mod soa;
+pub mod swiss_table;
 
-#[cfg(test)]
-mod metrics;
+#[cfg(any(test, feature = "metrics"))]
+pub mod metrics;
 
-fn round_up_to_power_of_two(v: usize) -> usize {
-    if v.is_power_of_two() {
-        v
-    } else {
-        2usize.pow(v.ilog2() + 1)
+use core::alloc::Layout;
+use core::fmt;
+use core::ptr::NonNull;
+
+extern crate alloc as crate_alloc;
+
+use crate_alloc::alloc;
+
+/// The error type for `try_reserve` methods of the hash maps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The required capacity overflows `usize` or the buffer would be larger
+    /// than `isize::MAX` bytes.
+    CapacityOverflow,
+    /// The allocator failed to allocate a buffer with `layout`.
+    AllocError { layout: Layout },
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityOverflow => f.write_str("capacity overflow"),
+            Self::AllocError { layout } => {
+                write!(f, "failed to allocate {} bytes", layout.size())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TryReserveError {}
+
+/// Turns a failed reservation into a panic or an allocation error like the
+/// infallible methods of std collections.
+fn handle_reserve<T>(result: Result<T, TryReserveError>) -> T {
+    match result {
+        Ok(v) => v,
+        Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+        Err(TryReserveError::AllocError { layout }) => alloc::handle_alloc_error(layout),
+    }
+}
+
+/// Returns the smallest power of two number of buckets which can hold `items`
+/// pairs without exceeding the load factor `lf`.
+fn buckets_for(items: usize, lf: f64) -> Result<usize, TryReserveError> {
+    let buckets = items as f64 / lf + 1.0;
+    // `usize::MAX as f64` rounds up to 2^64, anything at or above it doesn't fit
+    if buckets >= usize::MAX as f64 {
+        return Err(TryReserveError::CapacityOverflow);
+    }
+
+    (buckets as usize)
+        .checked_next_power_of_two()
+        .ok_or(TryReserveError::CapacityOverflow)
+}
+
+/// Returns `true` if all `ptrs` are non-null and no two of them are equal.
+///
+/// Used by `get_many_mut` methods to check that the returned mutable
+/// references don't alias.
+fn distinct_non_null<T, const N: usize>(ptrs: &[*mut T; N]) -> bool {
+    ptrs.iter()
+        .enumerate()
+        .all(|(i, p)| !p.is_null() && !ptrs[..i].contains(p))
+}
+
+/// Clones `cap` buckets from `src` into `dst` one by one.
+///
+/// The buckets of `dst` are replaced, so if a clone panics `dst` holds the
+/// buckets cloned so far followed by its original buckets.
+///
+/// # SAFETY
+///
+/// * `src` and `dst` must both be valid for `cap` initialized buckets and must
+///   not overlap
+unsafe fn clone_buckets<B: Clone>(src: NonNull<B>, dst: NonNull<B>, cap: usize) {
+    for i in 0..cap {
+        // SAFETY: i < cap and both buffers are valid for `cap` buckets
+        unsafe { *dst.as_ptr().add(i) = (*src.as_ptr().add(i)).clone() };
     }
 }
@@ -4,7 +4,7 @@ extern crate alloc as crate_alloc;
 
 use core::alloc::Layout;
 use core::borrow::Borrow;
-use core::hash::{BuildHasher, Hash, Hasher};
+use core::hash::{BuildHasher, Hash};
 use core::marker::PhantomData;
 use core::ptr::{self, NonNull};
 use core::{fmt, mem};
@@ -12,16 +12,20 @@ use std::collections::hash_map::RandomState;
 
 use crate_alloc::alloc;
 
-#[cfg(test)]
+use super::collect;
+use super::iter::{self, Slot};
+#[cfg(any(test, feature = "metrics"))]
 use super::metrics::MapMetrics;
-use super::round_up_to_power_of_two;
+use super::{TryReserveError, buckets_for, clone_buckets, distinct_non_null, handle_reserve};
 
-pub struct HashMap<K, V> {
+pub struct HashMap<K, V, S = RandomState> {
     buf: NonNull<Bucket<K, V>>,
     cap: usize,
     index_mask: usize,
     len: usize,
-    hash_builder: RandomState,
+    /// Number of `Bucket::Deleted` tombstones
+    deleted: usize,
+    hash_builder: S,
     crit_load_factor: f64,
     marker: PhantomData<(K, V)>,
 }
@@ -33,7 +37,33 @@ enum Bucket<K, V> {
     Deleted,
 }
 
-impl<K, V> Drop for HashMap<K, V> {
+impl<K, V> Slot for Bucket<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn pair(&self) -> Option<(&K, &V)> {
+        match self {
+            Bucket::Occupied((k, v)) => Some((k, v)),
+            Bucket::Empty | Bucket::Deleted => None,
+        }
+    }
+
+    fn pair_mut(&mut self) -> Option<(&K, &mut V)> {
+        match self {
+            Bucket::Occupied((k, v)) => Some((k, v)),
+            Bucket::Empty | Bucket::Deleted => None,
+        }
+    }
+
+    fn take(&mut self) -> Option<(K, V)> {
+        match mem::replace(self, Bucket::Empty) {
+            Bucket::Occupied(pair) => Some(pair),
+            Bucket::Empty | Bucket::Deleted => None,
+        }
+    }
+}
+
+impl<K, V, S> Drop for HashMap<K, V, S> {
     fn drop(&mut self) {
         if self.cap == 0 {
             return;
@@ -49,38 +79,48 @@ impl<K, V> Drop for HashMap<K, V> {
     }
 }
 
-impl<K, V> Clone for HashMap<K, V>
+impl<K, V, S> Clone for HashMap<K, V, S>
 where
-    K: Eq + Hash + Clone,
+    K: Clone,
     V: Clone,
+    S: Clone,
 {
+    /// Clones the buffer as is, no rehashing is needed as the hasher is
+    /// cloned too.
     fn clone(&self) -> Self {
-        // TODO: improve it
         let mut s = Self {
             buf: NonNull::dangling(),
             cap: 0,
             index_mask: 0,
             len: 0,
+            deleted: 0,
             crit_load_factor: self.crit_load_factor,
             hash_builder: self.hash_builder.clone(),
             marker: self.marker,
         };
-        s.grow_to(self.cap);
-        for i in 0..self.cap {
-            let it = unsafe { &*self.buf.as_ptr().add(i) };
-            if let Bucket::Occupied((k, v)) = it {
-                s.insert(k.clone(), v.clone());
-            }
+        if self.cap == 0 {
+            return s;
         }
 
+        // SAFETY: cap > 0
+        s.buf = unsafe { Self::alloc_new_buf_initialized(self.cap) };
+        s.cap = self.cap;
+        // SAFETY: both buffers have `cap` initialized buckets. `s` owns its
+        //  buffer, so the buckets cloned so far are dropped if a clone panics.
+        unsafe { clone_buckets(self.buf, s.buf, self.cap) };
+        s.index_mask = self.index_mask;
+        s.len = self.len;
+        s.deleted = self.deleted;
+
         s
     }
 }
 
-impl<K, V> fmt::Debug for HashMap<K, V>
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
 where
     K: fmt::Debug,
     V: fmt::Debug,
+    S: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("HashMap")
@@ -122,10 +162,16 @@ where
     }
 }
 
-impl<K, V> HashMap<K, V> {
-    const DEF_CRIT_LOAD_FACTOR: f64 = 0.7;
-    const INITIAL_CAP: usize = 4;
+impl<K, V, S> Default for HashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
 
+impl<K, V> HashMap<K, V> {
     pub fn new() -> Self {
         Self::with_load_factor(Self::DEF_CRIT_LOAD_FACTOR)
     }
@@ -143,9 +189,35 @@ impl<K, V> HashMap<K, V> {
     /// Creates a new hash map with capacity to store at least `capacity` pairs
     /// without reallocation.
     pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        Self::with_capacity_load_factor_and_hasher(capacity, lf, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    const DEF_CRIT_LOAD_FACTOR: f64 = 0.7;
+    const INITIAL_CAP: usize = 4;
+
+    /// Creates a new hash map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which uses `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_load_factor_and_hasher(
+            capacity,
+            Self::DEF_CRIT_LOAD_FACTOR,
+            hash_builder,
+        )
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which grows at load factor `lf` and uses
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_load_factor_and_hasher(capacity: usize, lf: f64, hash_builder: S) -> Self {
         let (buf, cap, index_mask) = if capacity > 0 {
-            let capacity = (capacity as f64 / lf + 1.0) as usize;
-            let capacity = round_up_to_power_of_two(capacity);
+            let capacity = handle_reserve(buckets_for(capacity, lf));
             debug_assert!(capacity.is_power_of_two());
             debug_assert!(capacity > 0);
             let new_buf = unsafe { Self::alloc_new_buf_initialized(capacity) };
@@ -158,7 +230,8 @@ impl<K, V> HashMap<K, V> {
             cap,
             index_mask,
             len: 0,
-            hash_builder: RandomState::new(),
+            deleted: 0,
+            hash_builder,
             crit_load_factor: lf,
             marker: PhantomData,
         }
@@ -172,6 +245,14 @@ impl<K, V> HashMap<K, V> {
         self.len == 0
     }
 
+    /// Number of bytes allocated for the buckets, including the empty and
+    /// tombstoned ones.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        Self::layout(self.cap).size()
+    }
+
     fn preferred_index(&self, hash: u64) -> usize {
         debug_assert!(self.cap < isize::MAX as usize);
         debug_assert!(self.cap.is_power_of_two());
@@ -187,21 +268,52 @@ impl<K, V> HashMap<K, V> {
         self.len as f64 / self.cap as f64
     }
 
+    /// Returns `true` if the map must grow before inserting one more item.
+    ///
+    /// Tombstones count as used buckets since they lengthen the probe chains
+    /// just like occupied buckets. At least one bucket is always kept empty,
+    /// so a table which is genuinely full (for example with load factor 1.0)
+    /// grows instead of making every probe for a missing key visit all buckets.
+    fn needs_grow(&self) -> bool {
+        let used = self.len + self.deleted;
+        used + 1 >= self.cap || used as f64 > self.crit_load_factor * self.cap as f64
+    }
+
+    /// Indices of the buckets to probe for a key whose preferred index is `orig_index`.
+    ///
+    /// The offsets from `orig_index` are the triangular numbers `i * (i + 1) / 2`.
+    /// Modulo a power of two they are all distinct for `i < cap`, so the first
+    /// `cap` probes visit every bucket exactly once and the sequence ends there.
+    fn probe_seq(&self, orig_index: usize) -> impl Iterator<Item = usize> {
+        let mask = self.index_mask;
+        let mut index = orig_index;
+        // offset(i) = offset(i - 1) + i
+        (0..self.cap).map(move |i| {
+            index = (index + i) & mask;
+            index
+        })
+    }
+
     fn layout(cap: usize) -> Layout {
         Layout::array::<Bucket<K, V>>(cap).unwrap()
     }
+
+    fn try_layout(cap: usize) -> Result<Layout, TryReserveError> {
+        Layout::array::<Bucket<K, V>>(cap).map_err(|_| TryReserveError::CapacityOverflow)
+    }
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V, S> HashMap<K, V, S>
 where
     K: Hash + Eq,
+    S: BuildHasher,
 {
     pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
-        if self.load_factor() > self.crit_load_factor {
+        if self.needs_grow() {
             self.grow()
         }
 
-        debug_assert!(self.len < self.cap);
+        debug_assert!(self.len + self.deleted < self.cap);
         unsafe { self.insert_unchecked(key, value) }
     }
 
@@ -212,29 +324,48 @@ where
     ///   but that's not a safety requirement)
     unsafe fn insert_unchecked(&mut self, key: K, value: V) -> Option<(K, V)> {
         let hash = self.hash_key(&key);
-        let orig_index = self.preferred_index(hash);
-        let mut index = orig_index;
-        let mut i: usize = 0;
-        loop {
-            let maybe_val = unsafe { &mut *self.buf.as_ptr().add(index) };
-            match maybe_val {
+        // The key may be after a tombstone, so the whole chain must be
+        // searched before the first free bucket can be used.
+        let mut free = None;
+        for index in self.probe_seq(self.preferred_index(hash)) {
+            match unsafe { &mut *self.buf.as_ptr().add(index) } {
                 Bucket::Occupied(val) if val.0 == key => {
-                    let old = mem::replace(val, (key, value));
-                    break Some(old);
+                    return Some(mem::replace(val, (key, value)));
                 }
                 Bucket::Occupied(_) => {}
-                Bucket::Empty | Bucket::Deleted => {
-                    *maybe_val = Bucket::Occupied((key, value));
-                    self.len += 1;
-                    break None;
+                Bucket::Deleted => {
+                    free.get_or_insert(index);
+                }
+                Bucket::Empty => {
+                    free.get_or_insert(index);
+                    break;
                 }
             }
-            i += 1;
-            index = (orig_index + (i * i + i) / 2) & self.index_mask;
         }
+
+        // The probe sequence visits every bucket, so a map with capacity for
+        // one more item always has a free bucket in it
+        let index = free.expect("a non-full map to have a free bucket");
+        let bucket = unsafe { &mut *self.buf.as_ptr().add(index) };
+        if let Bucket::Deleted = bucket {
+            self.deleted -= 1;
+        }
+        *bucket = Bucket::Occupied((key, value));
+        self.len += 1;
+        None
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
     }
 
-    pub fn get<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
     where
         K: Borrow<Q>,
         Q: Eq + Hash,
@@ -243,17 +374,58 @@ where
         if ptr.is_null() {
             None
         } else {
-            match unsafe { &*ptr } {
-                Bucket::Occupied((k, v)) => Some((k, v)),
-                _ => unreachable!(),
-            }
+            // SAFETY: non-null pointer from `get_bucket` points to an occupied bucket
+            unsafe { &*ptr }.pair()
         }
     }
 
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptr = self.get_bucket(key);
+        if ptr.is_null() {
+            None
+        } else {
+            // SAFETY: non-null pointer from `get_bucket` points to an occupied
+            //  bucket, the reference borrows `self` mutably
+            unsafe { &mut *ptr }.pair_mut().map(|(_, v)| v)
+        }
+    }
+
+    /// Returns mutable references to the values of all `keys` at once.
+    ///
+    /// Returns `None` if any of the keys is missing or if the same key is
+    /// given more than once.
+    pub fn get_many_mut<Q, const N: usize>(&mut self, keys: [&Q; N]) -> Option<[&mut V; N]>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptrs = keys.map(|key| self.get_bucket(key));
+        if !distinct_non_null(&ptrs) {
+            return None;
+        }
+
+        // SAFETY: the pointers from `get_bucket` point to distinct occupied
+        //  buckets, the references borrow `self` mutably
+        Some(ptrs.map(|ptr| unsafe { &mut *ptr }.pair_mut().map(|(_, v)| v).unwrap()))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        !self.get_bucket(key).is_null()
+    }
+
     pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
     where
         K: Borrow<Q>,
-        Q: Eq + Hash + fmt::Debug,
+        Q: Eq + Hash,
     {
         let ptr = self.get_bucket(key);
         if ptr.is_null() {
@@ -261,6 +433,7 @@ where
         } else {
             let b = unsafe { ptr::replace(ptr, Bucket::Deleted) };
             self.len -= 1;
+            self.deleted += 1;
             match b {
                 Bucket::Occupied((k, v)) => Some((k, v)),
                 _ => unreachable!(),
@@ -280,19 +453,49 @@ where
         }
 
         let hash = self.hash_key(key);
-        let orig_index = self.preferred_index(hash);
-        let mut index = orig_index;
-        let mut i: usize = 0;
-        loop {
+        for index in self.probe_seq(self.preferred_index(hash)) {
             let maybe_val = unsafe { self.buf.as_ptr().add(index) };
             match unsafe { &*maybe_val } {
-                Bucket::Occupied((k, _)) if k.borrow() == key => break maybe_val,
+                Bucket::Occupied((k, _)) if k.borrow() == key => return maybe_val,
                 Bucket::Occupied(_) | Bucket::Deleted => {}
-                Bucket::Empty => break ptr::null_mut(),
+                Bucket::Empty => break,
             }
+        }
+
+        ptr::null_mut()
+    }
 
-            i += 1;
-            index = (orig_index + (i * i + i) / 2) & self.index_mask;
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
+
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    ///
+    /// The map is unchanged if an error is returned.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_cap = buckets_for(required, self.crit_load_factor)?;
+        if new_cap > self.cap {
+            self.try_rebuild(new_cap)
+        } else if (required + self.deleted) as f64 / self.crit_load_factor + 1.0 > self.cap as f64 {
+            // fits only without the tombstones
+            self.try_rebuild(self.cap)
+        } else {
+            Ok(())
         }
     }
 
@@ -300,24 +503,24 @@ where
     where
         Q: Hash,
     {
-        let mut hasher = self.hash_builder.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.hash_builder.hash_one(key)
     }
 }
 
-impl<K, V> HashMap<K, V> {
+impl<K, V, S> HashMap<K, V, S> {
     fn grow(&mut self)
     where
         K: Eq + Hash,
+        S: BuildHasher,
     {
-        let new_cap = if self.cap == 0 {
-            Self::INITIAL_CAP
+        if self.cap == 0 {
+            self.grow_to(Self::INITIAL_CAP);
+        } else if (self.len + 1) as f64 <= self.crit_load_factor * self.cap as f64 / 2.0 {
+            // mostly tombstones, getting rid of them is enough
+            handle_reserve(self.try_rebuild(self.cap));
         } else {
-            2 * self.cap
-        };
-
-        self.grow_to(new_cap);
+            self.grow_to(2 * self.cap);
+        }
     }
 
     /// # PANICS
@@ -326,14 +529,46 @@ impl<K, V> HashMap<K, V> {
     fn grow_to(&mut self, new_cap: usize)
     where
         K: Eq + Hash,
+        S: BuildHasher,
+    {
+        assert!(new_cap.is_power_of_two());
+        handle_reserve(self.try_grow_to(new_cap))
+    }
+
+    /// Same as `grow_to` but returns an error instead of aborting if the
+    /// allocation fails. The map is unchanged on error.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two
+    fn try_grow_to(&mut self, new_cap: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
     {
         assert!(new_cap.is_power_of_two());
         if new_cap <= self.cap {
-            return;
+            return Ok(());
         }
 
-        // SAFETY: TODO
-        let new_buf = unsafe { Self::alloc_new_buf_initialized(new_cap) };
+        self.try_rebuild(new_cap)
+    }
+
+    /// Moves all items into a new buffer of `new_cap` buckets, dropping all
+    /// tombstones. The map is unchanged on error.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two or `new_cap < self.cap`
+    fn try_rebuild(&mut self, new_cap: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        assert!(new_cap.is_power_of_two() && new_cap >= self.cap);
+
+        // SAFETY: new_cap is a power of two, hence > 0
+        let new_buf = unsafe { Self::try_alloc_new_buf_initialized(new_cap) }?;
         let (old_buf, old_cap) = unsafe { self.swap_buf(new_buf, new_cap) };
 
         if old_cap != 0 {
@@ -341,6 +576,8 @@ impl<K, V> HashMap<K, V> {
             let old_layout = Self::layout(old_cap);
             unsafe { alloc::dealloc(old_buf.as_ptr().cast::<u8>(), old_layout) }
         }
+
+        Ok(())
     }
 
     /// Allocates new buffer with capacity `new_cap` and initializes all the values to `None`.
@@ -357,10 +594,22 @@ impl<K, V> HashMap<K, V> {
     ///
     /// * if `new_cap * mem::size_of::<Option<Bucket<K, V>>>() > isize::MAX`
     unsafe fn alloc_new_buf_initialized(new_cap: usize) -> NonNull<Bucket<K, V>> {
-        let new_layout = Self::layout(new_cap);
+        handle_reserve(unsafe { Self::try_alloc_new_buf_initialized(new_cap) })
+    }
+
+    /// Same as `alloc_new_buf_initialized` but returns an error if the
+    /// allocation fails or the buffer would be too large.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    unsafe fn try_alloc_new_buf_initialized(
+        new_cap: usize,
+    ) -> Result<NonNull<Bucket<K, V>>, TryReserveError> {
+        let new_layout = Self::try_layout(new_cap)?;
         let new_buf = unsafe { alloc::alloc(new_layout) };
         if new_buf.is_null() {
-            alloc::handle_alloc_error(new_layout);
+            Err(TryReserveError::AllocError { layout: new_layout })
         } else {
             let new_buf = new_buf.cast::<Bucket<K, V>>();
             // init to `None`s
@@ -368,7 +617,7 @@ impl<K, V> HashMap<K, V> {
                 unsafe { new_buf.add(i).write(Bucket::Empty) };
             }
 
-            unsafe { NonNull::new_unchecked(new_buf) }
+            Ok(unsafe { NonNull::new_unchecked(new_buf) })
         }
     }
 
@@ -385,11 +634,13 @@ impl<K, V> HashMap<K, V> {
     ) -> (NonNull<Bucket<K, V>>, usize)
     where
         K: Eq + Hash,
+        S: BuildHasher,
     {
         let old_buf = mem::replace(&mut self.buf, new_buf);
         let old_cap = mem::replace(&mut self.cap, new_cap);
         self.index_mask = self.cap - 1;
         self.len = 0;
+        self.deleted = 0;
 
         // insert all items into the new buffer
         for i in 0..old_cap {
@@ -406,10 +657,14 @@ impl<K, V> HashMap<K, V> {
     }
 }
 
-#[cfg(test)]
-impl<K, V> MapMetrics<K, V> for HashMap<K, V>
+iter::impl_iterators!(Bucket<K, V>, deleted);
+collect::impl_collect!(hasher S);
+
+#[cfg(any(test, feature = "metrics"))]
+impl<K, V, S> MapMetrics<K, V> for HashMap<K, V, S>
 where
     K: Hash + Eq,
+    S: BuildHasher,
 {
     fn get_with_metrics<Q>(&self, key: &Q) -> Option<(&K, &V, usize)>
     where
@@ -421,19 +676,15 @@ where
         }
 
         let hash = self.hash_key(key);
-        let orig_index = self.preferred_index(hash);
-        let mut index = orig_index;
-        let mut i: usize = 0;
-        loop {
-            let maybe_val = unsafe { self.buf.as_ptr().add(index) };
-            match unsafe { &*maybe_val } {
-                Bucket::Occupied((k, v)) if k.borrow() == key => break Some((k, v, i)),
+        for (i, index) in self.probe_seq(self.preferred_index(hash)).enumerate() {
+            match unsafe { &*self.buf.as_ptr().add(index) } {
+                Bucket::Occupied((k, v)) if k.borrow() == key => return Some((k, v, i)),
                 Bucket::Occupied(_) | Bucket::Deleted => {}
-                Bucket::Empty => break None,
+                Bucket::Empty => break,
             }
-            i += 1;
-            index = (orig_index + (i * i + i) / 2) & self.index_mask;
         }
+
+        None
     }
 
     fn len(&self) -> usize {
@@ -444,6 +695,10 @@ where
         self.cap
     }
 
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes()
+    }
+
     fn load_factor(&self) -> f64 {
         self.load_factor()
     }
@@ -451,10 +706,16 @@ where
     fn name(&self) -> &'static str {
         "Quadratic probing"
     }
+
+    fn tombstones(&self) -> usize {
+        self.deleted
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use core::hash::Hasher;
+
     use super::*;
 
     #[test]
@@ -469,15 +730,81 @@ mod tests {
         assert_eq!(m.len(), 4);
         m.insert(4, 41);
 
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
 
         assert_eq!(m.insert(4, 42), Some((4, 41)));
-        assert_eq!(m.get(&4), Some((&4, &42)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let dups: HashMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[&5], 3);
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn clone() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in (0..100).step_by(3) {
+            m.remove(&i);
+        }
+
+        let mut c = m.clone();
+        assert_eq!(c.len(), m.len());
+        for i in 0..100 {
+            assert_eq!(c.get(&i), m.get(&i));
+        }
+
+        // the clone is independent of the original
+        c.insert(0, "new".to_owned());
+        c.remove(&1);
+        assert_eq!(m.get(&0), None);
+        assert_eq!(m.get(&1), Some(&"1".to_owned()));
+        assert_eq!(c.get(&0), Some(&"new".to_owned()));
+        assert_eq!(c.get(&1), None);
     }
 
     #[test]
@@ -536,10 +863,80 @@ mod tests {
         assert!(m.is_empty());
     }
 
+    #[test]
+    fn insert_after_tombstone_same_hash() {
+        #[derive(Debug, PartialEq, Eq)]
+        struct SameHash(i32);
+
+        impl Hash for SameHash {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                1.hash(state);
+            }
+        }
+
+        let mut m = HashMap::new();
+        m.insert(SameHash(1), 11);
+        m.insert(SameHash(2), 21);
+        m.remove(&SameHash(1));
+
+        // 2 is after the tombstone of 1 in the probe chain, it must be
+        // replaced instead of inserted again into the tombstone
+        assert_eq!(m.insert(SameHash(2), 22), Some((SameHash(2), 21)));
+        assert_eq!(m.len(), 1);
+        assert_eq!(m.remove(&SameHash(2)), Some((SameHash(2), 22)));
+        assert_eq!(m.get(&SameHash(2)), None);
+    }
+
+    #[test]
+    fn probe_seq_visits_every_bucket() {
+        for cap in [4, 8, 64, 1024] {
+            let m = HashMap::<i32, i32>::with_capacity_and_load_factor(cap / 2 - 1, 0.5);
+            assert_eq!(m.cap, cap);
+            for orig in [0, 1, cap - 1] {
+                let mut seen = vec![false; cap];
+                for index in m.probe_seq(orig) {
+                    assert!(!seen[index]);
+                    seen[index] = true;
+                }
+                assert!(seen.iter().all(|s| *s));
+            }
+        }
+    }
+
+    #[test]
+    fn tombstones_dont_fill_the_table() {
+        let mut m = HashMap::new();
+        for i in 0..10_000 {
+            m.insert(i, i);
+            m.insert(i + 1, i);
+            assert_eq!(m.remove(&i), Some((i, i)));
+            assert_eq!(m.remove(&(i + 1)), Some((i + 1, i)));
+            // lookups of missing keys must terminate
+            assert_eq!(m.get(&-1), None);
+        }
+        assert!(m.is_empty());
+        assert!(m.cap <= 8, "{}", m.cap);
+        assert!(m.deleted < m.cap);
+    }
+
+    #[test]
+    fn full_load_factor() {
+        let mut m = HashMap::with_load_factor(1.0);
+        for i in 0..100 {
+            m.insert(i, i);
+            // one bucket is always kept empty
+            assert!(m.len < m.cap);
+        }
+        for i in 0..100 {
+            assert_eq!(m.get(&i), Some(&i));
+        }
+        assert_eq!(m.get(&100), None);
+    }
+
     #[test]
     fn get() {
         let mut m = HashMap::new();
-        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_key_value(&1), None);
 
         m.insert(1, 11);
         m.insert(2, 21);
@@ -547,12 +944,146 @@ mod tests {
         m.insert(5, 51);
         m.insert(4, 41);
 
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn iterators() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+        m.remove(&3);
+        m.remove(&7);
+
+        let mut items: Vec<_> = m.iter().map(|(k, v)| (*k, *v)).collect();
+        items.sort();
+        let expected: Vec<_> = [0, 1, 2, 4, 5, 6, 8, 9].map(|i| (i, i * 10)).into();
+        assert_eq!(items, expected);
+        assert_eq!(m.iter().len(), 8);
+        assert_eq!(m.keys().sum::<i32>(), 35);
+        assert_eq!(m.values().sum::<i32>(), 350);
+
+        for v in m.values_mut() {
+            *v += 1;
+        }
+        for (k, v) in &mut m {
+            *v += k;
+        }
+        assert_eq!(m.get_key_value(&4), Some((&4, &45)));
+
+        let mut drained: Vec<_> = m.drain().collect();
+        drained.sort();
+        assert_eq!(drained.len(), 8);
+        assert_eq!(drained[0], (0, 1));
+        assert!(m.is_empty());
+        assert_eq!(m.iter().next(), None);
+
+        // map is still usable after the drain
+        m.insert(1, 1);
+        m.insert(2, 2);
+        let mut owned: Vec<_> = m.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, [(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn iterators_drop_unconsumed() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+
+        let mut drain = m.drain();
+        drain.next();
+        drop(drain);
+        assert!(m.is_empty());
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+        let mut into_iter = m.into_iter();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 9);
+        drop(into_iter);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn get_many_mut() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        let [a, b] = m.get_many_mut([&1, &2]).unwrap();
+        mem::swap(a, b);
+        assert_eq!(m.get(&1), Some(&20));
+        assert_eq!(m.get(&2), Some(&10));
+
+        assert_eq!(m.get_many_mut([&1, &1]), None);
+        assert_eq!(m.get_many_mut([&1, &10]), None);
+        assert_eq!(m.get_many_mut::<i32, 0>([]), Some([]));
+    }
+
+    #[test]
+    fn reserve() {
+        let mut m = HashMap::new();
+        m.insert(-1, -1);
+        m.reserve(100);
+        let cap = m.cap;
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.cap, cap, "grew after reserve");
+        assert_eq!(m.len(), 101);
+
+        // nothing to do if there is enough room already
+        m.reserve(0);
+        assert_eq!(m.cap, cap);
+
+        assert_eq!(
+            m.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            m.try_reserve(usize::MAX / 2),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(m.cap, cap);
+        assert_eq!(m.get_key_value(&50), Some((&50, &50)));
     }
 
     mod proptests {
@@ -589,8 +1120,47 @@ mod tests {
 
                 inserts.shuffle(&mut thread_rng());
                 for key in inserts.iter().chain(access.iter()) {
-                    assert_eq!(ref_hmap.get_key_value(key), hmap.get(key));
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
+                }
+            }
+
+            #[test]
+            fn iter_drain(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::new();
+                for v in &inserts {
+                    ref_hmap.insert(*v, *v);
+                    hmap.insert(*v, *v);
+                }
+                for v in &removes {
+                    assert_eq!(ref_hmap.remove_entry(v), hmap.remove(v));
+                }
+
+                let mut expected: Vec<_> = ref_hmap.into_iter().collect();
+                expected.sort();
+                let mut items: Vec<_> = hmap.iter().map(|(k, v)| (*k, *v)).collect();
+                items.sort();
+                assert_eq!(&items, &expected);
+
+                let mut drained: Vec<_> = hmap.drain().collect();
+                drained.sort();
+                assert_eq!(drained, expected);
+                assert!(hmap.is_empty());
+
+                // the map must be fully reusable after the drain
+                for v in &inserts {
+                    hmap.insert(*v, *v);
                 }
+                let mut owned: Vec<_> = hmap.into_iter().map(|(k, _)| k).collect();
+                owned.sort();
+                owned.dedup();
+                let mut inserts = inserts;
+                inserts.sort();
+                inserts.dedup();
+                assert_eq!(owned, inserts);
             }
 
             #[test]
@@ -1,10 +1,14 @@
 //! Hash map with Robin Hood hashing variant of linear probing
+//!
+//! Every bucket stores a 32 bit tag of the hash and its 32 bit distance from
+//! the preferred index. Tags are compared before the keys and removal shifts
+//! the following buckets back using only the distances.
 
 extern crate alloc as crate_alloc;
 
 use core::alloc::Layout;
 use core::borrow::Borrow;
-use core::hash::{BuildHasher, Hash, Hasher};
+use core::hash::{BuildHasher, Hash};
 use core::marker::PhantomData;
 use core::ptr::{self, NonNull};
 use core::{fmt, mem};
@@ -12,30 +16,55 @@ use std::collections::hash_map::RandomState;
 
 use crate_alloc::alloc;
 
-#[cfg(test)]
+use super::collect;
+use super::iter::{self, Slot};
+#[cfg(any(test, feature = "metrics"))]
 use super::metrics::MapMetrics;
-use super::round_up_to_power_of_two;
+use super::{TryReserveError, buckets_for, clone_buckets, distinct_non_null, handle_reserve};
 
-type HashValue = u64;
+/// Low bits of the hash of a key
+type Tag = u32;
 
 #[derive(Debug, Clone)]
 struct Bucket<K, V> {
     key: K,
     value: V,
-    hash: HashValue,
+    /// Compared before the keys. It's also the preferred index in maps with
+    /// at most `2^32` buckets, so growing doesn't need to rehash the keys.
+    tag: Tag,
+    /// Distance from the preferred index. Same size as the tag so that it
+    /// fits into the padding after it for most keys and values.
+    dist: u32,
 }
 
-pub struct HashMap<K, V> {
+pub struct HashMap<K, V, S = RandomState> {
     buf: NonNull<Option<Bucket<K, V>>>,
     cap: usize,
     index_mask: usize,
     len: usize,
-    hash_builder: RandomState,
+    hash_builder: S,
     crit_load_factor: f64,
     marker: PhantomData<(K, V)>,
 }
 
-impl<K, V> Drop for HashMap<K, V> {
+impl<K, V> Slot for Option<Bucket<K, V>> {
+    type Key = K;
+    type Value = V;
+
+    fn pair(&self) -> Option<(&K, &V)> {
+        self.as_ref().map(|b| (&b.key, &b.value))
+    }
+
+    fn pair_mut(&mut self) -> Option<(&K, &mut V)> {
+        self.as_mut().map(|b| (&b.key, &mut b.value))
+    }
+
+    fn take(&mut self) -> Option<(K, V)> {
+        Option::take(self).map(|b| (b.key, b.value))
+    }
+}
+
+impl<K, V, S> Drop for HashMap<K, V, S> {
     fn drop(&mut self) {
         if self.cap == 0 {
             return;
@@ -51,13 +80,15 @@ impl<K, V> Drop for HashMap<K, V> {
     }
 }
 
-impl<K, V> Clone for HashMap<K, V>
+impl<K, V, S> Clone for HashMap<K, V, S>
 where
-    K: Eq + Hash + Clone,
+    K: Clone,
     V: Clone,
+    S: Clone,
 {
+    /// Clones the buffer as is, no rehashing is needed as the hasher is
+    /// cloned too.
     fn clone(&self) -> Self {
-        // TODO: improve it
         let mut s = Self {
             buf: NonNull::dangling(),
             cap: 0,
@@ -67,22 +98,28 @@ where
             hash_builder: self.hash_builder.clone(),
             marker: self.marker,
         };
-        s.grow_to(self.cap);
-        for i in 0..self.cap {
-            let it = unsafe { &*self.buf.as_ptr().add(i) };
-            if let Some(bucket) = it {
-                unsafe { s.insert_unchecked(bucket.clone()) };
-            }
+        if self.cap == 0 {
+            return s;
         }
 
+        // SAFETY: cap > 0
+        s.buf = unsafe { Self::alloc_new_buf_initialized(self.cap) };
+        s.cap = self.cap;
+        // SAFETY: both buffers have `cap` initialized buckets. `s` owns its
+        //  buffer, so the buckets cloned so far are dropped if a clone panics.
+        unsafe { clone_buckets(self.buf, s.buf, self.cap) };
+        s.index_mask = self.index_mask;
+        s.len = self.len;
+
         s
     }
 }
 
-impl<K, V> fmt::Debug for HashMap<K, V>
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
 where
-    K: fmt::Debug + Hash + Eq,
+    K: fmt::Debug,
     V: fmt::Debug,
+    S: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("HashMap")
@@ -94,13 +131,13 @@ where
     }
 }
 
-struct DebugHashMapBuf<'a, K, V> {
-    map: &'a HashMap<K, V>,
+struct DebugHashMapBuf<'a, K, V, S> {
+    map: &'a HashMap<K, V, S>,
 }
 
-impl<'a, K, V> fmt::Debug for DebugHashMapBuf<'a, K, V>
+impl<'a, K, V, S> fmt::Debug for DebugHashMapBuf<'a, K, V, S>
 where
-    K: fmt::Debug + Hash + Eq,
+    K: fmt::Debug,
     V: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -108,16 +145,9 @@ where
 
         for i in 0..self.map.cap {
             let it = unsafe { &*self.map.buf.as_ptr().add(i) };
-            let it = it.as_ref().map(|b| {
-                let hash = self.map.hash_key(&b.key);
-                let orig_index = self.map.preferred_index(hash);
-                (
-                    &b.key,
-                    &b.value,
-                    orig_index,
-                    self.map.probe_len(orig_index, i),
-                )
-            });
+            let it = it
+                .as_ref()
+                .map(|b| (&b.key, &b.value, self.map.home_index(i, b), b.dist));
             list.entry(&it);
         }
 
@@ -125,10 +155,16 @@ where
     }
 }
 
-impl<K, V> HashMap<K, V> {
-    const DEF_CRIT_LOAD_FACTOR: f64 = 0.7;
-    const INITIAL_CAP: usize = 4;
+impl<K, V, S> Default for HashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
 
+impl<K, V> HashMap<K, V> {
     pub fn new() -> Self {
         Self::with_load_factor(Self::DEF_CRIT_LOAD_FACTOR)
     }
@@ -146,9 +182,38 @@ impl<K, V> HashMap<K, V> {
     /// Creates a new hash map with capacity to store at least `capacity` pairs
     /// without reallocation.
     pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        Self::with_capacity_load_factor_and_hasher(capacity, lf, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    const DEF_CRIT_LOAD_FACTOR: f64 = 0.7;
+    const INITIAL_CAP: usize = 4;
+    /// Largest distance of a bucket from its preferred index, it must fit in
+    /// `Bucket::dist`
+    const MAX_DIST: usize = u32::MAX as usize;
+
+    /// Creates a new hash map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which uses `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_load_factor_and_hasher(
+            capacity,
+            Self::DEF_CRIT_LOAD_FACTOR,
+            hash_builder,
+        )
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which grows at load factor `lf` and uses
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_load_factor_and_hasher(capacity: usize, lf: f64, hash_builder: S) -> Self {
         let (buf, cap, index_mask) = if capacity > 0 {
-            let capacity = (capacity as f64 / lf + 1.0) as usize;
-            let capacity = round_up_to_power_of_two(capacity);
+            let capacity = handle_reserve(buckets_for(capacity, lf));
             debug_assert!(capacity.is_power_of_two());
             debug_assert!(capacity > 0);
             let new_buf = unsafe { Self::alloc_new_buf_initialized(capacity) };
@@ -161,7 +226,7 @@ impl<K, V> HashMap<K, V> {
             cap,
             index_mask,
             len: 0,
-            hash_builder: RandomState::new(),
+            hash_builder,
             crit_load_factor: lf,
             marker: PhantomData,
         }
@@ -175,13 +240,12 @@ impl<K, V> HashMap<K, V> {
         self.len == 0
     }
 
-    fn probe_len(&self, orig_index: usize, actual_index: usize) -> usize {
-        if actual_index < orig_index {
-            // probe must wrap around
-            (self.cap - orig_index) + actual_index
-        } else {
-            actual_index - orig_index
-        }
+    /// Number of bytes allocated for the buckets, including the empty and
+    /// tombstoned ones.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        Self::layout(self.cap).size()
     }
 
     fn preferred_index(&self, hash: u64) -> usize {
@@ -191,6 +255,128 @@ impl<K, V> HashMap<K, V> {
         (hash & (self.index_mask as u64)) as usize
     }
 
+    fn tag(hash: u64) -> Tag {
+        hash as Tag
+    }
+
+    /// Returns the preferred index of bucket `b` which is at `index`.
+    fn home_index(&self, index: usize, b: &Bucket<K, V>) -> usize {
+        index.wrapping_sub(b.dist as usize) & self.index_mask
+    }
+
+    /// Searches the probe chain of `hash` for `key`.
+    ///
+    /// Returns `Ok(index)` of the bucket with `key` or `Err((index, dist))`
+    /// where the search stopped. That is where a new bucket for `key` belongs,
+    /// the bucket at `index` is either empty or closer to its preferred index
+    /// than `dist`.
+    ///
+    /// The map must have at least one bucket.
+    fn probe<Q>(&self, hash: u64, key: &Q) -> Result<usize, (usize, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+    {
+        debug_assert!(self.cap > 0);
+        let tag = Self::tag(hash);
+        let mut index = self.preferred_index(hash);
+        let mut dist = 0;
+
+        // Terminates even if the map is full, since `dist` eventually
+        // exceeds the distance of every bucket
+        loop {
+            // SAFETY: index is masked, hence < cap
+            match unsafe { &*self.buf.as_ptr().add(index) } {
+                Some(b) if b.tag == tag && b.key.borrow() == key => break Ok(index),
+                Some(b) if dist <= b.dist as usize => {}
+                // either empty or `key` would have displaced this bucket
+                _ => break Err((index, dist)),
+            }
+            index = (index + 1) & self.index_mask;
+            dist += 1;
+        }
+    }
+
+    /// Puts `bucket` at `index` which is `dist` away from its preferred index
+    /// and moves the run of buckets starting at `index` one step forward.
+    ///
+    /// The bucket is given back without changing the map if it or any of the
+    /// moved buckets would end up further than `MAX_DIST` from its preferred
+    /// index.
+    ///
+    /// # SAFETY
+    ///
+    /// * `index < cap` and the map has at least one empty bucket
+    /// * `index` and `dist` are where the probe for `bucket` stopped (see `probe`)
+    unsafe fn place(
+        &mut self,
+        index: usize,
+        dist: usize,
+        mut bucket: Bucket<K, V>,
+    ) -> Result<(), Bucket<K, V>> {
+        if dist > Self::MAX_DIST {
+            return Err(bucket);
+        }
+
+        let mut end = index;
+        loop {
+            // SAFETY: end is masked, hence < cap
+            match unsafe { &*self.buf.as_ptr().add(end) } {
+                None => break,
+                Some(b) if b.dist as usize == Self::MAX_DIST => return Err(bucket),
+                Some(_) => {}
+            }
+            end = (end + 1) & self.index_mask;
+        }
+
+        while end != index {
+            let prev = end.wrapping_sub(1) & self.index_mask;
+            // SAFETY: both indices are masked, `end` is empty and `prev` is
+            //  occupied since it's in the run before `end`
+            unsafe {
+                let mut moved = (*self.buf.as_ptr().add(prev)).take();
+                moved.as_mut().unwrap().dist += 1;
+                *self.buf.as_ptr().add(end) = moved;
+            }
+            end = prev;
+        }
+
+        bucket.dist = dist as u32;
+        // SAFETY: index < cap and the bucket was emptied above
+        unsafe { *self.buf.as_ptr().add(index) = Some(bucket) };
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the bucket at `index` and moves the following buckets back by
+    /// one until one which is at its preferred index.
+    ///
+    /// # SAFETY
+    ///
+    /// * `index < cap` and the bucket at `index` is occupied
+    unsafe fn remove_at(&mut self, index: usize) -> Bucket<K, V> {
+        // SAFETY: caller guarantees that the bucket is occupied
+        let removed = unsafe { (*self.buf.as_ptr().add(index)).take() }.unwrap();
+        self.len -= 1;
+
+        let mut hole = index;
+        loop {
+            let next = (hole + 1) & self.index_mask;
+            // SAFETY: both indices are masked and `hole` is empty
+            unsafe {
+                let next_bucket = &mut *self.buf.as_ptr().add(next);
+                match next_bucket {
+                    Some(b) if b.dist > 0 => {
+                        b.dist -= 1;
+                        *self.buf.as_ptr().add(hole) = next_bucket.take();
+                        hole = next;
+                    }
+                    _ => break removed,
+                }
+            }
+        }
+    }
+
     fn load_factor(&self) -> f64 {
         if self.cap == 0 {
             return f64::INFINITY;
@@ -202,11 +388,16 @@ impl<K, V> HashMap<K, V> {
     fn layout(cap: usize) -> Layout {
         Layout::array::<Option<Bucket<K, V>>>(cap).unwrap()
     }
+
+    fn try_layout(cap: usize) -> Result<Layout, TryReserveError> {
+        Layout::array::<Option<Bucket<K, V>>>(cap).map_err(|_| TryReserveError::CapacityOverflow)
+    }
 }
 
-impl<K, V> HashMap<K, V>
+impl<K, V, S> HashMap<K, V, S>
 where
     K: Hash + Eq,
+    S: BuildHasher,
 {
     pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
         if self.load_factor() > self.crit_load_factor {
@@ -215,46 +406,107 @@ where
 
         debug_assert!(self.len < self.cap);
         let hash = self.hash_key(&key);
-        unsafe { self.insert_unchecked(Bucket { key, value, hash }) }
+        match self.probe(hash, &key) {
+            Ok(index) => {
+                // SAFETY: `probe` returns indices of occupied buckets in bounds
+                let b = unsafe { &mut *self.buf.as_ptr().add(index) }
+                    .as_mut()
+                    .unwrap();
+                let old_key = mem::replace(&mut b.key, key);
+                let old_value = mem::replace(&mut b.value, value);
+                Some((old_key, old_value))
+            }
+            Err((index, dist)) => {
+                let bucket = Bucket {
+                    key,
+                    value,
+                    tag: Self::tag(hash),
+                    dist: 0,
+                };
+                // SAFETY: the map was grown above if needed, so it has an
+                //  empty bucket
+                unsafe { self.insert_new(index, dist, bucket) };
+                None
+            }
+        }
     }
 
+    /// Inserts a bucket whose key is not in the map yet, `index` and `dist`
+    /// are where the probe for it stopped. Returns the index of the new bucket.
+    ///
+    /// If some bucket would end up too far from its preferred index, the map
+    /// is grown first. That takes a run of more than `MAX_DIST` buckets, so
+    /// in practice it doesn't happen.
+    ///
     /// # SAFETY
     ///
-    /// * Self must have the capacity for 1 more item
-    ///   (ideally we would also not exceed `load_factor > Self::CRIT_LOAD_FACTOR`
-    ///   but that's not a safety requirement)
-    unsafe fn insert_unchecked(&mut self, mut bucket: Bucket<K, V>) -> Option<(K, V)> {
-        let mut index = self.preferred_index(bucket.hash);
-        let mut probe_len = 0usize;
-
+    /// * the map has at least one empty bucket
+    /// * `index` and `dist` are where the probe for `bucket` stopped (see `probe`)
+    unsafe fn insert_new(
+        &mut self,
+        mut index: usize,
+        mut dist: usize,
+        mut bucket: Bucket<K, V>,
+    ) -> usize {
         loop {
-            let maybe_val = unsafe { &mut *self.buf.as_ptr().add(index) };
-            match maybe_val {
-                Some(val) if val.key == bucket.key => {
-                    let old = mem::replace(val, bucket);
-                    break Some((old.key, old.value));
-                }
-                Some(val) => {
-                    let this_index = self.preferred_index(val.hash);
-                    let this_probe_len = self.probe_len(this_index, index);
+            // SAFETY: see the function's safety requirements, after growing
+            //  they are upheld by the new probe
+            match unsafe { self.place(index, dist, bucket) } {
+                Ok(()) => break index,
+                Err(b) => bucket = b,
+            }
 
-                    if probe_len > this_probe_len {
-                        bucket = mem::replace(val, bucket);
-                        probe_len = this_probe_len;
-                    }
-                }
-                None => {
-                    *maybe_val = Some(bucket);
-                    self.len += 1;
-                    break None;
-                }
+            self.grow();
+            let hash = self.hash_key(&bucket.key);
+            (index, dist) = match self.probe(hash, &bucket.key) {
+                Ok(_) => unreachable!("the key is not in the map"),
+                Err(stop) => stop,
+            };
+        }
+    }
+
+    /// Inserts a bucket whose key is not in the map without comparing any
+    /// keys, used when moving all the buckets into a new buffer.
+    ///
+    /// # SAFETY
+    ///
+    /// * the map has at least one empty bucket
+    unsafe fn insert_unique(&mut self, bucket: Bucket<K, V>) {
+        let mut index = if self.index_mask <= Tag::MAX as usize {
+            bucket.tag as usize & self.index_mask
+        } else {
+            self.preferred_index(self.hash_key(&bucket.key))
+        };
+        let mut dist = 0;
+        loop {
+            // SAFETY: index is masked, hence < cap
+            match unsafe { &*self.buf.as_ptr().add(index) } {
+                Some(b) if dist <= b.dist as usize => {}
+                _ => break,
             }
             index = (index + 1) & self.index_mask;
-            probe_len += 1;
+            dist += 1;
+        }
+
+        // SAFETY: caller guarantees an empty bucket, we just probed for `bucket`
+        if unsafe { self.place(index, dist, bucket) }.is_err() {
+            // The buckets are moved into a larger buffer, which can only
+            // split the runs of the old buffer
+            unreachable!("probe distance to not grow in a larger buffer");
         }
     }
 
-    pub fn get<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
     where
         K: Borrow<Q>,
         Q: Eq + Hash,
@@ -267,18 +519,61 @@ where
         }
     }
 
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let (ptr, _) = self.get_bucket(key);
+        if ptr.is_null() {
+            None
+        } else {
+            // SAFETY: non-null pointer from `get_bucket` points to a bucket in
+            //  the buffer, the reference borrows `self` mutably
+            unsafe { &mut *ptr }.as_mut().map(|b| &mut b.value)
+        }
+    }
+
+    /// Returns mutable references to the values of all `keys` at once.
+    ///
+    /// Returns `None` if any of the keys is missing or if the same key is
+    /// given more than once.
+    pub fn get_many_mut<Q, const N: usize>(&mut self, keys: [&Q; N]) -> Option<[&mut V; N]>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let ptrs = keys.map(|key| self.get_bucket(key).0);
+        if !distinct_non_null(&ptrs) {
+            return None;
+        }
+
+        // SAFETY: the pointers from `get_bucket` point to distinct occupied
+        //  buckets, the references borrow `self` mutably
+        Some(ptrs.map(|ptr| unsafe { &mut *ptr }.as_mut().map(|b| &mut b.value).unwrap()))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        !self.get_bucket(key).0.is_null()
+    }
+
     pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
     where
         K: Borrow<Q>,
-        Q: Eq + Hash + fmt::Debug,
+        Q: Eq + Hash,
     {
         let (ptr, index) = self.get_bucket(key);
         if ptr.is_null() {
             None
         } else {
-            let b = unsafe { ptr::replace(ptr, None) }.unwrap();
-            self.shift_probe_chain_down(index);
-            self.len -= 1;
+            // SAFETY: non-null pointer from `get_bucket` points to the
+            //  occupied bucket at `index`
+            let b = unsafe { self.remove_at(index) };
             Some((b.key, b.value))
         }
     }
@@ -293,71 +588,77 @@ where
         }
 
         let hash = self.hash_key(key);
-        let mut index = self.preferred_index(hash);
-        let mut probe_len = 0;
-
-        loop {
-            let maybe_val = unsafe { self.buf.as_ptr().add(index) };
-            match unsafe { &*maybe_val } {
-                Some(b) if b.key.borrow() == key => break (maybe_val, index),
-                Some(Bucket { hash, .. }) => {
-                    let this_index = self.preferred_index(*hash);
-                    let this_probe_len = self.probe_len(this_index, index);
-
-                    if this_probe_len < probe_len {
-                        break (ptr::null_mut(), index);
-                    }
-                }
-                None => break (ptr::null_mut(), index),
-            }
-            index = (index + 1) & self.index_mask;
-            probe_len += 1;
+        match self.probe(hash, key) {
+            // SAFETY: `probe` returns indices in bounds
+            Ok(index) => (unsafe { self.buf.as_ptr().add(index) }, index),
+            Err((index, _)) => (ptr::null_mut(), index),
         }
     }
 
-    /// This function assumes that the value at `self.buf[start_index]` can be overwritten
-    fn shift_probe_chain_down(&mut self, start_index: usize) {
-        // Search through the probe chain and move every following item in chain down by one
-        let mut index = start_index;
-        let mut to_overwrite = unsafe { &mut *self.buf.as_ptr().add(index) };
-        *to_overwrite = None;
-        loop {
-            index = (index + 1) & self.index_mask;
-            let next = unsafe { &mut *self.buf.as_ptr().add(index) };
-            match next {
-                Some(Bucket { key, .. }) => {
-                    let preferred_index = self.preferred_index(self.hash_key(&key));
-                    if preferred_index != index {
-                        *to_overwrite = next.take();
-                        to_overwrite = next;
-                    } else {
-                        // There cannot be more items to shift down, otherwise
-                        // this item couldn't be on it's preferred spot
-                        break;
-                    }
-                }
-                // at least to_overwrite is None, so we are bound to hit it at some point
-                // more realistically since the load_factor is < 1, there are other empty slots as well,
-                // above condition could still be hit for very small capacities
-                None => break,
-            }
+    /// Returns the entry for `key` for in-place manipulation.
+    ///
+    /// The key is hashed and the probe chain is searched only once, any
+    /// following operation on the entry continues from the found bucket.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        // grow before searching so that inserting into the vacant entry
+        // cannot invalidate the found index
+        if self.load_factor() > self.crit_load_factor {
+            self.grow()
+        }
+
+        let hash = self.hash_key(&key);
+        match self.probe(hash, &key) {
+            Ok(index) => Entry::Occupied(OccupiedEntry { map: self, index }),
+            Err((index, dist)) => Entry::Vacant(VacantEntry {
+                map: self,
+                index,
+                dist,
+                key,
+                hash,
+            }),
         }
     }
 
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
+
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    ///
+    /// The map is unchanged if an error is returned.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_cap = buckets_for(required, self.crit_load_factor)?;
+        self.try_grow_to(new_cap)
+    }
+
     fn hash_key<Q>(&self, key: &Q) -> u64
     where
         Q: Hash,
     {
-        let mut hasher = self.hash_builder.build_hasher();
-        key.hash(&mut hasher);
-        hasher.finish()
+        self.hash_builder.hash_one(key)
     }
 }
 
-impl<K, V> HashMap<K, V> {
+impl<K, V, S> HashMap<K, V, S> {
     fn grow(&mut self)
     where
         K: Eq + Hash,
+        S: BuildHasher,
     {
         let new_cap = if self.cap == 0 {
             Self::INITIAL_CAP
@@ -374,14 +675,30 @@ impl<K, V> HashMap<K, V> {
     fn grow_to(&mut self, new_cap: usize)
     where
         K: Eq + Hash,
+        S: BuildHasher,
+    {
+        assert!(new_cap.is_power_of_two());
+        handle_reserve(self.try_grow_to(new_cap))
+    }
+
+    /// Same as `grow_to` but returns an error instead of aborting if the
+    /// allocation fails. The map is unchanged on error.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two
+    fn try_grow_to(&mut self, new_cap: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
     {
         assert!(new_cap.is_power_of_two());
         if new_cap <= self.cap {
-            return;
+            return Ok(());
         }
 
-        // SAFETY: TODO
-        let new_buf = unsafe { Self::alloc_new_buf_initialized(new_cap) };
+        // SAFETY: new_cap > self.cap >= 0
+        let new_buf = unsafe { Self::try_alloc_new_buf_initialized(new_cap) }?;
         let (old_buf, old_cap) = unsafe { self.swap_buf(new_buf, new_cap) };
 
         if old_cap != 0 {
@@ -389,6 +706,8 @@ impl<K, V> HashMap<K, V> {
             let old_layout = Self::layout(old_cap);
             unsafe { alloc::dealloc(old_buf.as_ptr().cast::<u8>(), old_layout) }
         }
+
+        Ok(())
     }
 
     /// Allocates new buffer with capacity `new_cap` and initializes all the values to `None`.
@@ -405,10 +724,22 @@ impl<K, V> HashMap<K, V> {
     ///
     /// * if `new_cap * mem::size_of::<Option<Bucket<K, V>>>() > isize::MAX`
     unsafe fn alloc_new_buf_initialized(new_cap: usize) -> NonNull<Option<Bucket<K, V>>> {
-        let new_layout = Self::layout(new_cap);
+        handle_reserve(unsafe { Self::try_alloc_new_buf_initialized(new_cap) })
+    }
+
+    /// Same as `alloc_new_buf_initialized` but returns an error if the
+    /// allocation fails or the buffer would be too large.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    unsafe fn try_alloc_new_buf_initialized(
+        new_cap: usize,
+    ) -> Result<NonNull<Option<Bucket<K, V>>>, TryReserveError> {
+        let new_layout = Self::try_layout(new_cap)?;
         let new_buf = unsafe { alloc::alloc(new_layout) };
         if new_buf.is_null() {
-            alloc::handle_alloc_error(new_layout);
+            Err(TryReserveError::AllocError { layout: new_layout })
         } else {
             let new_buf = new_buf.cast::<Option<Bucket<K, V>>>();
             // init to `None`s
@@ -416,7 +747,7 @@ impl<K, V> HashMap<K, V> {
                 unsafe { new_buf.add(i).write(None) };
             }
 
-            unsafe { NonNull::new_unchecked(new_buf) }
+            Ok(unsafe { NonNull::new_unchecked(new_buf) })
         }
     }
 
@@ -433,6 +764,7 @@ impl<K, V> HashMap<K, V> {
     ) -> (NonNull<Option<Bucket<K, V>>>, usize)
     where
         K: Eq + Hash,
+        S: BuildHasher,
     {
         let old_buf = mem::replace(&mut self.buf, new_buf);
         let old_cap = mem::replace(&mut self.cap, new_cap);
@@ -442,11 +774,9 @@ impl<K, V> HashMap<K, V> {
         // insert all items into the new buffer
         for i in 0..old_cap {
             let it = unsafe { old_buf.as_ptr().add(i).read() };
-            match it {
-                Some(b) => {
-                    unsafe { self.insert_unchecked(b) };
-                }
-                None => continue,
+            if let Some(b) = it {
+                // SAFETY: the new buffer is larger than the number of pairs
+                unsafe { self.insert_unique(b) };
             }
         }
 
@@ -454,10 +784,168 @@ impl<K, V> HashMap<K, V> {
     }
 }
 
-#[cfg(test)]
-impl<K, V> MapMetrics<K, V> for HashMap<K, V>
+/// A view into a single entry of [`HashMap`], created by [`HashMap::entry`].
+pub enum Entry<'a, K, V, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * `map.buf[index]` is `Some`
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+}
+
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * `index` and `dist` are where the probe for `key` stopped,
+    //    `map.buf[index]` is either `None` or a bucket closer to its
+    //    preferred index than `dist`
+    //  * `map.len < map.cap`
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+    dist: usize,
+    key: K,
+    hash: u64,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant and returns a mutable reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant and returns a mutable reference to the value.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        K: Hash + Eq,
+        F: FnOnce() -> V,
+        S: BuildHasher,
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        K: Hash + Eq,
+        V: Default,
+        S: BuildHasher,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
+    fn bucket(&self) -> &Bucket<K, V> {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS)
+        unsafe { &*self.map.buf.as_ptr().add(self.index) }
+            .as_ref()
+            .unwrap()
+    }
+
+    /// Returns the key stored in the map.
+    pub fn key(&self) -> &K {
+        &self.bucket().key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.bucket().value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS),
+        //  the reference borrows `self` mutably
+        let bucket = unsafe { &mut *self.map.buf.as_ptr().add(self.index) };
+        &mut bucket.as_mut().unwrap().value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        // SAFETY: same as `get_mut` but the map is borrowed mutably for 'a
+        let bucket = unsafe { &mut *self.map.buf.as_ptr().add(self.index) };
+        &mut bucket.as_mut().unwrap().value
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the map.
+    pub fn remove(self) -> (K, V)
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        // SAFETY: index is in bounds and the bucket is occupied (see INVARIANTS)
+        let b = unsafe { self.map.remove_at(self.index) };
+        (b.key, b.value)
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        let bucket = Bucket {
+            key: self.key,
+            value,
+            tag: HashMap::<K, V>::tag(self.hash),
+            dist: 0,
+        };
+        // SAFETY: map has room for one more item since it was grown if needed
+        //  before the search, `index` and `dist` are from the probe (see INVARIANTS)
+        let index = unsafe { self.map.insert_new(self.index, self.dist, bucket) };
+
+        // SAFETY: `insert_new` returns the index of the new bucket
+        let bucket = unsafe { &mut *self.map.buf.as_ptr().add(index) };
+        &mut bucket.as_mut().unwrap().value
+    }
+}
+
+iter::impl_iterators!(Option<Bucket<K, V>>);
+collect::impl_collect!(hasher S);
+
+#[cfg(any(test, feature = "metrics"))]
+impl<K, V, S> MapMetrics<K, V> for HashMap<K, V, S>
 where
     K: Hash + Eq,
+    S: BuildHasher,
 {
     fn get_with_metrics<Q>(&self, key: &Q) -> Option<(&K, &V, usize)>
     where
@@ -469,26 +957,9 @@ where
         }
 
         let hash = self.hash_key(key);
-        let mut index = self.preferred_index(hash);
-        let mut probe_len = 0;
-
-        loop {
-            let maybe_val = unsafe { self.buf.as_ptr().add(index) };
-            match unsafe { &*maybe_val } {
-                Some(b) if b.key.borrow() == key => break Some((&b.key, &b.value, probe_len)),
-                Some(Bucket { hash, .. }) => {
-                    let this_index = self.preferred_index(*hash);
-                    let this_probe_len = self.probe_len(this_index, index);
-
-                    if this_probe_len < probe_len {
-                        break None;
-                    }
-                }
-                None => break None,
-            }
-            index = (index + 1) & self.index_mask;
-            probe_len += 1;
-        }
+        let index = self.probe(hash, key).ok()?;
+        let b = unsafe { &*self.buf.as_ptr().add(index) }.as_ref().unwrap();
+        Some((&b.key, &b.value, b.dist as usize))
     }
 
     fn len(&self) -> usize {
@@ -499,6 +970,10 @@ where
         self.cap
     }
 
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes()
+    }
+
     fn load_factor(&self) -> f64 {
         self.load_factor()
     }
@@ -510,6 +985,8 @@ where
 
 #[cfg(test)]
 mod tests {
+    use core::hash::Hasher;
+
     use super::*;
 
     #[test]
@@ -524,15 +1001,81 @@ mod tests {
         assert_eq!(m.len(), 4);
         m.insert(4, 41);
 
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
 
         assert_eq!(m.insert(4, 42), Some((4, 41)));
-        assert_eq!(m.get(&4), Some((&4, &42)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let dups: HashMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[&5], 3);
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn clone() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in (0..100).step_by(3) {
+            m.remove(&i);
+        }
+
+        let mut c = m.clone();
+        assert_eq!(c.len(), m.len());
+        for i in 0..100 {
+            assert_eq!(c.get(&i), m.get(&i));
+        }
+
+        // the clone is independent of the original
+        c.insert(0, "new".to_owned());
+        c.remove(&1);
+        assert_eq!(m.get(&0), None);
+        assert_eq!(m.get(&1), Some(&"1".to_owned()));
+        assert_eq!(c.get(&0), Some(&"new".to_owned()));
+        assert_eq!(c.get(&1), None);
     }
 
     #[test]
@@ -594,10 +1137,82 @@ mod tests {
         assert!(m.is_empty())
     }
 
+    /// Checks that the tags and distances agree with the hashes of the keys
+    /// and that no bucket is further from its preferred index than the
+    /// bucket before it plus one.
+    fn check_invariants<K: Hash + Eq, V>(m: &HashMap<K, V>) {
+        let mut len = 0;
+        for i in 0..m.cap {
+            let Some(b) = (unsafe { &*m.buf.as_ptr().add(i) }) else {
+                continue;
+            };
+            len += 1;
+            let hash = m.hash_key(&b.key);
+            assert_eq!(b.tag, HashMap::<K, V>::tag(hash));
+            assert_eq!(m.home_index(i, b), m.preferred_index(hash));
+            if b.dist > 0 {
+                let prev = i.wrapping_sub(1) & m.index_mask;
+                let prev = unsafe { &*m.buf.as_ptr().add(prev) }.as_ref();
+                assert!(prev.is_some_and(|p| b.dist <= p.dist + 1));
+            }
+        }
+        assert_eq!(len, m.len);
+    }
+
+    #[test]
+    fn distances_after_removes() {
+        let mut m = HashMap::with_load_factor(0.95);
+        for i in 0..1000 {
+            m.insert(i, i);
+        }
+        check_invariants(&m);
+
+        for i in (0..1000).step_by(3) {
+            assert_eq!(m.remove(&i), Some((i, i)));
+        }
+        check_invariants(&m);
+
+        for i in 0..1000 {
+            let expected = (i % 3 != 0).then_some(&i);
+            assert_eq!(m.get(&i), expected);
+        }
+    }
+
+    #[test]
+    fn many_same_hash() {
+        #[derive(Debug, PartialEq, Eq)]
+        struct SameHash(i32);
+
+        impl Hash for SameHash {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                1.hash(state);
+            }
+        }
+
+        // one probe chain longer than a byte can count
+        let mut m = HashMap::new();
+        for i in 0..300 {
+            m.insert(SameHash(i), i);
+        }
+        check_invariants(&m);
+        assert_eq!(m.len(), 300);
+        for i in 0..300 {
+            assert_eq!(m.get(&SameHash(i)), Some(&i));
+        }
+
+        for i in (0..300).step_by(2) {
+            assert_eq!(m.remove(&SameHash(i)), Some((SameHash(i), i)));
+        }
+        check_invariants(&m);
+        for i in 0..300 {
+            assert_eq!(m.get(&SameHash(i)), (i % 2 == 1).then_some(&i));
+        }
+    }
+
     #[test]
     fn get() {
         let mut m = HashMap::new();
-        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_key_value(&1), None);
 
         m.insert(1, 11);
         m.insert(2, 21);
@@ -605,12 +1220,43 @@ mod tests {
         m.insert(5, 51);
         m.insert(4, 41);
 
-        assert_eq!(m.get(&2), Some((&2, &21)));
-        assert_eq!(m.get(&1), Some((&1, &11)));
-        assert_eq!(m.get(&3), Some((&3, &31)));
-        assert_eq!(m.get(&4), Some((&4, &41)));
-        assert_eq!(m.get(&5), Some((&5, &51)));
-        assert_eq!(m.get(&6), None);
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn entry() {
+        let mut m = HashMap::new();
+        *m.entry(5).or_insert(0) += 1;
+        *m.entry(5).or_insert(0) += 1;
+        assert_eq!(m.get_key_value(&5), Some((&5, &2)));
+        assert_eq!(*m.entry(7).or_insert_with(|| 70), 70);
+        assert_eq!(*m.entry(8).or_default(), 0);
+        assert_eq!(m.len(), 3);
+
+        assert_eq!(*m.entry(7).and_modify(|v| *v *= 2).or_insert(0), 140);
+        assert_eq!(*m.entry(1).and_modify(|v| *v *= 2).or_insert(10), 10);
+        assert_eq!(m.entry(30).key(), &30);
+
+        match m.entry(5) {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), &5);
+                assert_eq!(e.insert(50), 2);
+                assert_eq!(e.get(), &50);
+                assert_eq!(e.remove(), (5, 50));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match m.entry(5) {
+            Entry::Occupied(_) => unreachable!(),
+            Entry::Vacant(e) => assert_eq!(e.into_key(), 5),
+        }
+        assert_eq!(m.len(), 3);
+        assert_eq!(m.get_key_value(&5), None);
     }
 
     #[test]
@@ -619,6 +1265,140 @@ mod tests {
         drop(m);
     }
 
+    #[test]
+    fn iterators() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+        m.remove(&3);
+        m.remove(&7);
+
+        let mut items: Vec<_> = m.iter().map(|(k, v)| (*k, *v)).collect();
+        items.sort();
+        let expected: Vec<_> = [0, 1, 2, 4, 5, 6, 8, 9].map(|i| (i, i * 10)).into();
+        assert_eq!(items, expected);
+        assert_eq!(m.iter().len(), 8);
+        assert_eq!(m.keys().sum::<i32>(), 35);
+        assert_eq!(m.values().sum::<i32>(), 350);
+
+        for v in m.values_mut() {
+            *v += 1;
+        }
+        for (k, v) in &mut m {
+            *v += k;
+        }
+        assert_eq!(m.get_key_value(&4), Some((&4, &45)));
+
+        let mut drained: Vec<_> = m.drain().collect();
+        drained.sort();
+        assert_eq!(drained.len(), 8);
+        assert_eq!(drained[0], (0, 1));
+        assert!(m.is_empty());
+        assert_eq!(m.iter().next(), None);
+
+        // map is still usable after the drain
+        m.insert(1, 1);
+        m.insert(2, 2);
+        let mut owned: Vec<_> = m.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, [(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn iterators_drop_unconsumed() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+
+        let mut drain = m.drain();
+        drain.next();
+        drop(drain);
+        assert!(m.is_empty());
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+        let mut into_iter = m.into_iter();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 9);
+        drop(into_iter);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn get_many_mut() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        let [a, b] = m.get_many_mut([&1, &2]).unwrap();
+        mem::swap(a, b);
+        assert_eq!(m.get(&1), Some(&20));
+        assert_eq!(m.get(&2), Some(&10));
+
+        assert_eq!(m.get_many_mut([&1, &1]), None);
+        assert_eq!(m.get_many_mut([&1, &10]), None);
+        assert_eq!(m.get_many_mut::<i32, 0>([]), Some([]));
+    }
+
+    #[test]
+    fn reserve() {
+        let mut m = HashMap::new();
+        m.insert(-1, -1);
+        m.reserve(100);
+        let cap = m.cap;
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.cap, cap, "grew after reserve");
+        assert_eq!(m.len(), 101);
+
+        // nothing to do if there is enough room already
+        m.reserve(0);
+        assert_eq!(m.cap, cap);
+
+        assert_eq!(
+            m.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            m.try_reserve(usize::MAX / 2),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(m.cap, cap);
+        assert_eq!(m.get_key_value(&50), Some((&50, &50)));
+    }
+
     mod proptests {
         use proptest::prelude::*;
         use rand::seq::SliceRandom;
@@ -653,8 +1433,69 @@ mod tests {
 
                 inserts.shuffle(&mut thread_rng());
                 for key in inserts.iter().chain(access.iter()) {
-                    assert_eq!(ref_hmap.get_key_value(key), hmap.get(key));
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
+                }
+            }
+
+            #[test]
+            fn entry_counts(
+                keys in proptest::collection::vec(0..100i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..100i32, 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, usize, RandomState>::new();
+                let mut hmap = HashMap::new();
+                // interleave removes so that vacant entries land in shifted probe chains
+                for (i, key) in keys.iter().enumerate() {
+                    *ref_hmap.entry(*key).or_default() += 1;
+                    *hmap.entry(*key).or_default() += 1;
+                    if let Some(r) = removes.get(i) {
+                        assert_eq!(ref_hmap.remove_entry(r), hmap.remove(r));
+                    }
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+                for key in 0..100 {
+                    assert_eq!(ref_hmap.get_key_value(&key), hmap.get_key_value(&key));
+                }
+            }
+
+            #[test]
+            fn iter_drain(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::new();
+                for v in &inserts {
+                    ref_hmap.insert(*v, *v);
+                    hmap.insert(*v, *v);
+                }
+                for v in &removes {
+                    assert_eq!(ref_hmap.remove_entry(v), hmap.remove(v));
+                }
+
+                let mut expected: Vec<_> = ref_hmap.into_iter().collect();
+                expected.sort();
+                let mut items: Vec<_> = hmap.iter().map(|(k, v)| (*k, *v)).collect();
+                items.sort();
+                assert_eq!(&items, &expected);
+
+                let mut drained: Vec<_> = hmap.drain().collect();
+                drained.sort();
+                assert_eq!(drained, expected);
+                assert!(hmap.is_empty());
+
+                // the map must be fully reusable after the drain
+                for v in &inserts {
+                    hmap.insert(*v, *v);
                 }
+                let mut owned: Vec<_> = hmap.into_iter().map(|(k, _)| k).collect();
+                owned.sort();
+                owned.dedup();
+                let mut inserts = inserts;
+                inserts.sort();
+                inserts.dedup();
+                assert_eq!(owned, inserts);
             }
 
             #[test]
@@ -0,0 +1,1066 @@
+//! Hash map with linear probing and a structure-of-arrays layout
+//!
+//! The other maps keep the pairs in a single array of `(K, V)` buckets. Here
+//! the hashes, keys and values live in three parallel arrays. A probe reads
+//! only the densely packed hashes and compares a key only if its full hash
+//! matches, the values are touched once the key is found. With large keys or
+//! values many more buckets fit into a cache line than with the
+//! array-of-structs layout.
+//!
+//! The stored hashes also mean that growing never hashes the keys again.
+//! Removal uses backward shift deletion, so no tombstones are left behind.
+
+extern crate alloc as crate_alloc;
+
+use core::alloc::Layout;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ptr::{self, NonNull};
+use std::collections::hash_map::RandomState;
+
+use crate_alloc::alloc;
+
+use super::collect;
+#[cfg(any(test, feature = "metrics"))]
+use super::metrics::MapMetrics;
+use super::{TryReserveError, buckets_for, handle_reserve};
+
+/// Hash of an empty bucket, stored hashes always have the top bit set
+const EMPTY: u64 = 0;
+
+pub struct HashMap<K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * all three arrays have `cap` elements
+    //  * `keys[i]` and `values[i]` are initialized iff `hashes[i] != EMPTY`,
+    //    `hashes[i]` is then the stored hash of `keys[i]`
+    //  * there are no empty buckets between the preferred index of a pair and
+    //    its actual index
+    //  * at least one bucket is empty if `cap > 0`
+    hashes: NonNull<u64>,
+    keys: NonNull<MaybeUninit<K>>,
+    values: NonNull<MaybeUninit<V>>,
+    cap: usize,
+    index_mask: usize,
+    len: usize,
+    hash_builder: S,
+    crit_load_factor: f64,
+    marker: PhantomData<(K, V)>,
+}
+
+impl<K, V, S> Drop for HashMap<K, V, S> {
+    fn drop(&mut self) {
+        if self.cap == 0 {
+            return;
+        }
+
+        for i in 0..self.cap {
+            if self.hash_at(i) != EMPTY {
+                // SAFETY: bucket i is occupied, hence the key and value are initialized
+                unsafe {
+                    ptr::drop_in_place(self.key_ptr(i));
+                    ptr::drop_in_place(self.value_ptr(i));
+                }
+            }
+        }
+
+        // SAFETY: the arrays were allocated with capacity `cap`
+        unsafe { Self::dealloc_arrays(self.hashes, self.keys, self.values, self.cap) }
+    }
+}
+
+impl<K, V, S> Clone for HashMap<K, V, S>
+where
+    K: Clone,
+    V: Clone,
+    S: Clone,
+{
+    /// Clones the arrays as is, no rehashing is needed as the hasher is
+    /// cloned too.
+    fn clone(&self) -> Self {
+        let mut s = Self {
+            hashes: NonNull::dangling(),
+            keys: NonNull::dangling(),
+            values: NonNull::dangling(),
+            cap: 0,
+            index_mask: 0,
+            len: 0,
+            hash_builder: self.hash_builder.clone(),
+            crit_load_factor: self.crit_load_factor,
+            marker: PhantomData,
+        };
+        if self.cap == 0 {
+            return s;
+        }
+
+        // SAFETY: cap > 0
+        (s.hashes, s.keys, s.values) = handle_reserve(unsafe { Self::try_alloc_arrays(self.cap) });
+        s.cap = self.cap;
+        s.index_mask = self.index_mask;
+        for i in 0..self.cap {
+            let hash = self.hash_at(i);
+            if hash != EMPTY {
+                // SAFETY: bucket i is occupied in `self`. The hash is written
+                //  last, so if a clone panics `s` drops only the complete pairs.
+                unsafe {
+                    s.key_ptr(i).write((*self.key_ptr(i)).clone());
+                    s.value_ptr(i).write((*self.value_ptr(i)).clone());
+                    s.hashes.as_ptr().add(i).write(hash);
+                }
+                s.len += 1;
+            }
+        }
+
+        s
+    }
+}
+
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashMap")
+            .field("buf", &DebugHashMapBuf { map: self })
+            .field("cap", &self.cap)
+            .field("len", &self.len)
+            .field("hash_builder", &self.hash_builder)
+            .finish()
+    }
+}
+
+struct DebugHashMapBuf<'a, K, V, S> {
+    map: &'a HashMap<K, V, S>,
+}
+
+impl<K, V, S> fmt::Debug for DebugHashMapBuf<'_, K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+
+        for i in 0..self.map.cap {
+            list.entry(&self.map.pair_at(i));
+        }
+
+        list.finish()
+    }
+}
+
+impl<K, V, S> Default for HashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V> HashMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_load_factor(Self::DEF_CRIT_LOAD_FACTOR)
+    }
+
+    pub fn with_load_factor(load_factor: f64) -> Self {
+        Self::with_capacity_and_load_factor(0, load_factor)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_load_factor(capacity, Self::DEF_CRIT_LOAD_FACTOR)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        Self::with_capacity_load_factor_and_hasher(capacity, lf, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    const DEF_CRIT_LOAD_FACTOR: f64 = 0.7;
+    const INITIAL_CAP: usize = 4;
+
+    /// Creates a new hash map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which uses `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_load_factor_and_hasher(
+            capacity,
+            Self::DEF_CRIT_LOAD_FACTOR,
+            hash_builder,
+        )
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which grows at load factor `lf` and uses
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_load_factor_and_hasher(capacity: usize, lf: f64, hash_builder: S) -> Self {
+        let mut s = Self {
+            hashes: NonNull::dangling(),
+            keys: NonNull::dangling(),
+            values: NonNull::dangling(),
+            cap: 0,
+            index_mask: 0,
+            len: 0,
+            hash_builder,
+            crit_load_factor: lf,
+            marker: PhantomData,
+        };
+        if capacity > 0 {
+            let capacity = handle_reserve(buckets_for(capacity, lf));
+            debug_assert!(capacity.is_power_of_two());
+            // SAFETY: capacity > 0
+            (s.hashes, s.keys, s.values) =
+                handle_reserve(unsafe { Self::try_alloc_arrays(capacity) });
+            s.cap = capacity;
+            s.index_mask = capacity - 1;
+        }
+        s
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes allocated for the hashes, keys and values, including
+    /// the empty buckets.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        Self::try_layouts(self.cap)
+            .unwrap()
+            .iter()
+            .map(Layout::size)
+            .sum()
+    }
+
+    /// Iterate over all key-value pairs in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            hashes: self.hashes,
+            keys: self.keys,
+            values: self.values,
+            index: 0,
+            remaining: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate over all key-value pairs with mutable references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            hashes: self.hashes,
+            keys: self.keys,
+            values: self.values,
+            index: 0,
+            remaining: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Marks the hash as stored, so that it's never `EMPTY`.
+    fn stored_hash(hash: u64) -> u64 {
+        hash | (1 << 63)
+    }
+
+    fn preferred_index(&self, hash: u64) -> usize {
+        debug_assert!(self.cap < isize::MAX as usize);
+        debug_assert!(self.cap.is_power_of_two());
+        // SAFETY: cap <= isize::MAX, hence the result after modulo must be < isize::MAX
+        (hash & self.index_mask as u64) as usize
+    }
+
+    fn hash_at(&self, index: usize) -> u64 {
+        debug_assert!(index < self.cap);
+        // SAFETY: index < cap and all hashes are initialized
+        unsafe { *self.hashes.as_ptr().add(index) }
+    }
+
+    fn key_ptr(&self, index: usize) -> *mut K {
+        debug_assert!(index < self.cap);
+        // SAFETY: index < cap
+        unsafe { self.keys.as_ptr().add(index).cast::<K>() }
+    }
+
+    fn value_ptr(&self, index: usize) -> *mut V {
+        debug_assert!(index < self.cap);
+        // SAFETY: index < cap
+        unsafe { self.values.as_ptr().add(index).cast::<V>() }
+    }
+
+    fn pair_at(&self, index: usize) -> Option<(&K, &V)> {
+        if self.hash_at(index) == EMPTY {
+            None
+        } else {
+            // SAFETY: bucket is occupied, hence the key and value are initialized
+            Some(unsafe { (&*self.key_ptr(index), &*self.value_ptr(index)) })
+        }
+    }
+
+    /// Returns the index of the occupied bucket on the probe chain of `hash`
+    /// whose key matches `is_match`.
+    fn find<F>(&self, hash: u64, mut is_match: F) -> Option<usize>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = Self::stored_hash(hash);
+        let mut index = self.preferred_index(hash);
+        // there is always an empty bucket, see `needs_grow`
+        loop {
+            match self.hash_at(index) {
+                EMPTY => break None,
+                // SAFETY: bucket is occupied
+                h if h == hash && is_match(unsafe { &*self.key_ptr(index) }) => break Some(index),
+                _ => {}
+            }
+            index = (index + 1) & self.index_mask;
+        }
+    }
+
+    /// Returns `true` if the map must grow before inserting one more item.
+    ///
+    /// At least one bucket is always kept empty, otherwise a probe for a
+    /// missing key would never terminate.
+    fn needs_grow(&self) -> bool {
+        self.len + 1 >= self.cap || self.len as f64 > self.crit_load_factor * self.cap as f64
+    }
+
+    fn load_factor(&self) -> f64 {
+        if self.cap == 0 {
+            return f64::INFINITY;
+        }
+
+        self.len as f64 / self.cap as f64
+    }
+
+    fn try_layouts(cap: usize) -> Result<[Layout; 3], TryReserveError> {
+        let err = |_| TryReserveError::CapacityOverflow;
+        Ok([
+            Layout::array::<u64>(cap).map_err(err)?,
+            Layout::array::<K>(cap).map_err(err)?,
+            Layout::array::<V>(cap).map_err(err)?,
+        ])
+    }
+
+    /// Allocates the three arrays with capacity `cap` and marks all the buckets empty.
+    ///
+    /// # SAFETY
+    ///
+    /// * `cap > 0`
+    #[allow(clippy::type_complexity)]
+    unsafe fn try_alloc_arrays(
+        cap: usize,
+    ) -> Result<
+        (
+            NonNull<u64>,
+            NonNull<MaybeUninit<K>>,
+            NonNull<MaybeUninit<V>>,
+        ),
+        TryReserveError,
+    > {
+        let [hashes_layout, keys_layout, values_layout] = Self::try_layouts(cap)?;
+        // SAFETY: the layouts are the array layouts of the respective types,
+        //  already allocated arrays are freed if a later allocation fails
+        let hashes = unsafe { alloc_array::<u64>(hashes_layout) }?;
+        let keys = match unsafe { alloc_array::<MaybeUninit<K>>(keys_layout) } {
+            Ok(keys) => keys,
+            Err(e) => {
+                unsafe { dealloc_array(hashes, hashes_layout) };
+                return Err(e);
+            }
+        };
+        let values = match unsafe { alloc_array::<MaybeUninit<V>>(values_layout) } {
+            Ok(values) => values,
+            Err(e) => {
+                unsafe { dealloc_array(hashes, hashes_layout) };
+                unsafe { dealloc_array(keys, keys_layout) };
+                return Err(e);
+            }
+        };
+
+        for i in 0..cap {
+            // SAFETY: i < cap
+            unsafe { hashes.as_ptr().add(i).write(EMPTY) };
+        }
+        Ok((hashes, keys, values))
+    }
+
+    /// # SAFETY
+    ///
+    /// * the arrays must have been allocated by `try_alloc_arrays(cap)`
+    unsafe fn dealloc_arrays(
+        hashes: NonNull<u64>,
+        keys: NonNull<MaybeUninit<K>>,
+        values: NonNull<MaybeUninit<V>>,
+        cap: usize,
+    ) {
+        let [hashes_layout, keys_layout, values_layout] = Self::try_layouts(cap).unwrap();
+        // SAFETY: the arrays were allocated with these layouts
+        unsafe {
+            dealloc_array(hashes, hashes_layout);
+            dealloc_array(keys, keys_layout);
+            dealloc_array(values, values_layout);
+        }
+    }
+
+    /// Writes a pair which is not in the map into the first empty bucket on
+    /// its probe chain.
+    ///
+    /// # SAFETY
+    ///
+    /// * the map must have at least one empty bucket
+    /// * `hash` must be the stored hash of `key`
+    unsafe fn insert_new_unchecked(&mut self, hash: u64, key: K, value: V) -> usize {
+        let mut index = self.preferred_index(hash);
+        while self.hash_at(index) != EMPTY {
+            index = (index + 1) & self.index_mask;
+        }
+
+        // SAFETY: bucket is empty, hence the key and value are uninitialized
+        unsafe {
+            self.key_ptr(index).write(key);
+            self.value_ptr(index).write(value);
+            self.hashes.as_ptr().add(index).write(hash);
+        }
+        self.len += 1;
+        index
+    }
+
+    fn grow(&mut self) {
+        let new_cap = if self.cap == 0 {
+            Self::INITIAL_CAP
+        } else {
+            2 * self.cap
+        };
+
+        handle_reserve(self.try_grow_to(new_cap));
+    }
+
+    /// Moves all pairs into new arrays of `new_cap` buckets.
+    ///
+    /// The hashes are stored, so the keys are not hashed again. The map is
+    /// unchanged on error.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two
+    fn try_grow_to(&mut self, new_cap: usize) -> Result<(), TryReserveError> {
+        assert!(new_cap.is_power_of_two());
+        if new_cap <= self.cap {
+            return Ok(());
+        }
+
+        // SAFETY: new_cap is a power of two, hence > 0
+        let (hashes, keys, values) = unsafe { Self::try_alloc_arrays(new_cap) }?;
+        let old_hashes = mem::replace(&mut self.hashes, hashes);
+        let old_keys = mem::replace(&mut self.keys, keys);
+        let old_values = mem::replace(&mut self.values, values);
+        let old_cap = mem::replace(&mut self.cap, new_cap);
+        self.index_mask = new_cap - 1;
+        self.len = 0;
+
+        for i in 0..old_cap {
+            // SAFETY: i < old_cap and the old arrays are valid for `old_cap` items
+            let hash = unsafe { *old_hashes.as_ptr().add(i) };
+            if hash != EMPTY {
+                // SAFETY: bucket was occupied, the pair is moved out of the
+                //  old arrays which are deallocated without dropping anything.
+                //  New arrays have more buckets than there are pairs.
+                unsafe {
+                    let key = old_keys.as_ptr().add(i).read().assume_init();
+                    let value = old_values.as_ptr().add(i).read().assume_init();
+                    self.insert_new_unchecked(hash, key, value);
+                }
+            }
+        }
+
+        if old_cap != 0 {
+            // SAFETY: old arrays were allocated with `old_cap`
+            unsafe { Self::dealloc_arrays(old_hashes, old_keys, old_values, old_cap) }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the pair at `index` and shifts the following pairs on the
+    /// chain backwards to fill the hole.
+    ///
+    /// # SAFETY
+    ///
+    /// * bucket at `index` must be occupied
+    unsafe fn remove_at(&mut self, index: usize) -> (K, V) {
+        // SAFETY: bucket is occupied, it's marked empty or overwritten below
+        let pair = unsafe { (self.key_ptr(index).read(), self.value_ptr(index).read()) };
+        self.len -= 1;
+
+        let mut hole = index;
+        let mut next = (hole + 1) & self.index_mask;
+        loop {
+            let hash = self.hash_at(next);
+            if hash == EMPTY {
+                break;
+            }
+
+            // The pair at `next` can be moved into the hole if the hole is on
+            // its probe chain, i.e. between its preferred index and `next`.
+            let dist_from_preferred =
+                next.wrapping_sub(self.preferred_index(hash)) & self.index_mask;
+            let dist_from_hole = next.wrapping_sub(hole) & self.index_mask;
+            if dist_from_preferred >= dist_from_hole {
+                // SAFETY: `next` is occupied, `hole` is logically empty and they are different
+                unsafe {
+                    ptr::copy_nonoverlapping(self.key_ptr(next), self.key_ptr(hole), 1);
+                    ptr::copy_nonoverlapping(self.value_ptr(next), self.value_ptr(hole), 1);
+                    self.hashes.as_ptr().add(hole).write(hash);
+                }
+                hole = next;
+            }
+            next = (next + 1) & self.index_mask;
+        }
+
+        // SAFETY: hole < cap
+        unsafe { self.hashes.as_ptr().add(hole).write(EMPTY) };
+        pair
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let hash = self.hash_builder.hash_one(&key);
+        if let Some(index) = self.find(hash, |k| *k == key) {
+            // SAFETY: bucket is occupied
+            let (k, v) = unsafe { (&mut *self.key_ptr(index), &mut *self.value_ptr(index)) };
+            return Some((mem::replace(k, key), mem::replace(v, value)));
+        }
+
+        if self.needs_grow() {
+            self.grow();
+        }
+        // SAFETY: the map has an empty bucket after growing, the key is new
+        unsafe { self.insert_new_unchecked(Self::stored_hash(hash), key, value) };
+        None
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.find_key(key)?;
+        self.pair_at(index)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.find_key(key)?;
+        // SAFETY: bucket is occupied, the reference borrows `self` mutably
+        Some(unsafe { &mut *self.value_ptr(index) })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.find_key(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.find_key(key)?;
+        // SAFETY: bucket is occupied
+        Some(unsafe { self.remove_at(index) })
+    }
+
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
+
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    ///
+    /// The map is unchanged if an error is returned.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_cap = buckets_for(required, self.crit_load_factor)?;
+        self.try_grow_to(new_cap)
+    }
+
+    fn find_key<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        self.find(hash, |k| k.borrow() == key)
+    }
+}
+
+/// # SAFETY
+///
+/// * `layout` must be the layout of an array of `T`
+unsafe fn alloc_array<T>(layout: Layout) -> Result<NonNull<T>, TryReserveError> {
+    if layout.size() == 0 {
+        return Ok(NonNull::dangling());
+    }
+
+    // SAFETY: layout has non-zero size
+    let ptr = unsafe { alloc::alloc(layout) };
+    NonNull::new(ptr.cast::<T>()).ok_or(TryReserveError::AllocError { layout })
+}
+
+/// # SAFETY
+///
+/// * `ptr` must have been allocated by `alloc_array` with the same `layout`
+unsafe fn dealloc_array<T>(ptr: NonNull<T>, layout: Layout) {
+    if layout.size() != 0 {
+        unsafe { alloc::dealloc(ptr.as_ptr().cast::<u8>(), layout) }
+    }
+}
+
+collect::impl_collect!(hasher S);
+
+impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    hashes: NonNull<u64>,
+    keys: NonNull<MaybeUninit<K>>,
+    values: NonNull<MaybeUninit<V>>,
+    index: usize,
+    /// Number of pairs not yet returned
+    remaining: usize,
+    marker: PhantomData<&'a (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        // SAFETY: there are `remaining` occupied buckets at or after `index`,
+        //  so the loop stops before the end of the arrays. The map is
+        //  borrowed immutably for 'a.
+        unsafe {
+            while *self.hashes.as_ptr().add(self.index) == EMPTY {
+                self.index += 1;
+            }
+            let i = self.index;
+            self.index += 1;
+            self.remaining -= 1;
+            Some((
+                (*self.keys.as_ptr().add(i)).assume_init_ref(),
+                (*self.values.as_ptr().add(i)).assume_init_ref(),
+            ))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> core::iter::FusedIterator for Iter<'_, K, V> {}
+
+pub struct IterMut<'a, K, V> {
+    hashes: NonNull<u64>,
+    keys: NonNull<MaybeUninit<K>>,
+    values: NonNull<MaybeUninit<V>>,
+    index: usize,
+    /// Number of pairs not yet returned
+    remaining: usize,
+    marker: PhantomData<&'a mut (K, V)>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        // SAFETY: same as in `Iter::next`, the map is borrowed mutably for 'a
+        //  and every bucket is returned at most once
+        unsafe {
+            while *self.hashes.as_ptr().add(self.index) == EMPTY {
+                self.index += 1;
+            }
+            let i = self.index;
+            self.index += 1;
+            self.remaining -= 1;
+            Some((
+                (*self.keys.as_ptr().add(i)).assume_init_ref(),
+                (*self.values.as_ptr().add(i)).assume_init_mut(),
+            ))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+impl<K, V> core::iter::FusedIterator for IterMut<'_, K, V> {}
+
+#[cfg(any(test, feature = "metrics"))]
+impl<K, V, S> MapMetrics<K, V> for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn get_with_metrics<Q>(&self, key: &Q) -> Option<(&K, &V, usize)>
+    where
+        Q: Eq + Hash,
+        K: Borrow<Q>,
+    {
+        let index = self.find_key(key)?;
+        let hash = self.hash_at(index);
+        let probe_len = index.wrapping_sub(self.preferred_index(hash)) & self.index_mask;
+        self.pair_at(index).map(|(k, v)| (k, v, probe_len))
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn cap(&self) -> usize {
+        self.cap
+    }
+
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes()
+    }
+
+    fn load_factor(&self) -> f64 {
+        self.load_factor()
+    }
+
+    fn name(&self) -> &'static str {
+        "Linear probing SoA"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::Hasher;
+
+    use super::*;
+
+    #[test]
+    fn insert() {
+        let mut m = HashMap::<i32, i32>::new();
+        assert!(m.is_empty());
+        m.insert(1, 11);
+        assert_eq!(m.len(), 1);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        assert_eq!(m.len(), 4);
+        m.insert(4, 41);
+        println!("{m:?}");
+
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+
+        assert_eq!(m.insert(4, 42), Some((4, 41)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+    }
+
+    #[test]
+    fn remove() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&1), None);
+
+        for i in 0..100 {
+            m.insert(i, i * 10);
+        }
+        for i in (0..100).step_by(2) {
+            assert_eq!(m.remove(&i), Some((i, i * 10)));
+            assert_eq!(m.remove(&i), None);
+        }
+        assert_eq!(m.len(), 50);
+        for i in 0..100 {
+            let expected = (i % 2 == 1).then_some(i * 10);
+            assert_eq!(m.get(&i).copied(), expected);
+        }
+    }
+
+    #[test]
+    fn remove_same_hash() {
+        #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct SameHash(i32);
+
+        // They all hash to same value, so they must hit the same index in the
+        // map and thus are part of same probe chain
+        impl Hash for SameHash {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                1.hash(state);
+            }
+        }
+
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&SameHash(1)), None);
+
+        m.insert(SameHash(1), 11);
+        m.insert(SameHash(2), 21);
+        m.insert(SameHash(3), 31);
+        m.insert(SameHash(5), 51);
+        m.insert(SameHash(4), 41);
+
+        // the rest of the chain is shifted back into the hole
+        assert_eq!(m.remove(&SameHash(2)), Some((SameHash(2), 21)));
+        assert_eq!(m.get(&SameHash(5)), Some(&51));
+        assert_eq!(m.remove(&SameHash(1)), Some((SameHash(1), 11)));
+        assert_eq!(m.remove(&SameHash(3)), Some((SameHash(3), 31)));
+        assert_eq!(m.remove(&SameHash(4)), Some((SameHash(4), 41)));
+        assert_eq!(m.remove(&SameHash(5)), Some((SameHash(5), 51)));
+
+        assert!(m.is_empty());
+        assert!((0..m.cap).all(|i| m.hash_at(i) == EMPTY));
+    }
+
+    #[test]
+    fn get_mut_contains_key_iter() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert!(m.contains_key(&9));
+        assert!(!m.contains_key(&10));
+
+        for (_, v) in &mut m {
+            *v += 1;
+        }
+        let mut items: Vec<_> = m.iter().map(|(k, v)| (*k, *v)).collect();
+        items.sort();
+        assert_eq!(items.len(), 10);
+        assert_eq!(items[3], (3, 32));
+        assert_eq!(m.iter().len(), 10);
+    }
+
+    #[test]
+    fn zero_sized() {
+        let mut m = HashMap::new();
+        assert_eq!(m.insert((), ()), None);
+        assert_eq!(m.insert((), ()), Some(((), ())));
+        assert_eq!(m.get(&()), Some(&()));
+        assert_eq!(m.remove(&()), Some(((), ())));
+        assert!(m.is_empty());
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[test]
+    fn allocated_bytes() {
+        let mut m = HashMap::<u8, u64>::new();
+        assert_eq!(m.allocated_bytes(), 0);
+        for i in 0..100 {
+            m.insert(i, 0);
+        }
+        // the hash, the key and the value of every bucket
+        assert_eq!(m.allocated_bytes(), m.cap * (8 + 1 + 8));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn clone() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in (0..100).step_by(3) {
+            m.remove(&i);
+        }
+
+        let mut c = m.clone();
+        assert_eq!(c.len(), m.len());
+        for i in 0..100 {
+            assert_eq!(c.get(&i), m.get(&i));
+        }
+
+        // the clone is independent of the original
+        c.insert(0, "new".to_owned());
+        c.remove(&1);
+        assert_eq!(m.get(&0), None);
+        assert_eq!(m.get(&1), Some(&"1".to_owned()));
+        assert_eq!(c.get(&0), Some(&"new".to_owned()));
+        assert_eq!(c.get(&1), None);
+    }
+
+    #[test]
+    fn try_reserve() {
+        let mut m = HashMap::new();
+        m.insert(1, 1);
+        assert_eq!(m.try_reserve(100), Ok(()));
+        let cap = m.cap;
+        for i in 2..=101 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.cap, cap);
+        assert_eq!(
+            m.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(m.get(&50), Some(&50));
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const OPS: usize = 1000;
+        #[cfg(miri)]
+        const OPS: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_std(
+                ops in proptest::collection::vec((any::<bool>(), 0..200i32), 0..OPS),
+                lf in 0.1..1.0f64,
+            ) {
+                let mut m = HashMap::with_load_factor(lf);
+                let mut model = std::collections::HashMap::new();
+
+                for (is_insert, key) in ops {
+                    if is_insert {
+                        assert_eq!(m.insert(key, key * 2).map(|(_, v)| v), model.insert(key, key * 2));
+                    } else {
+                        assert_eq!(m.remove(&key), model.remove_entry(&key));
+                    }
+                    assert_eq!(m.len(), model.len());
+                }
+
+                for key in 0..200 {
+                    assert_eq!(m.get(&key), model.get(&key));
+                }
+                assert!(m.iter().all(|(k, v)| model.get(k) == Some(v)));
+            }
+        );
+    }
+}
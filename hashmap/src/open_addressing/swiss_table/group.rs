@@ -0,0 +1,256 @@
+//! Scanning of control byte groups
+//!
+//! A group is `WIDTH` consecutive control bytes which are compared all at once.
+//! SSE2 and NEON versions are used where available, other targets use a
+//! portable byte by byte version.
+
+/// Control byte of an empty bucket
+pub(super) const EMPTY: u8 = 0b1111_1111;
+/// Control byte of a removed bucket (tombstone)
+pub(super) const DELETED: u8 = 0b1000_0000;
+
+/// Number of control bytes in a group
+pub(super) const WIDTH: usize = 16;
+
+/// Returns `true` if the control byte belongs to an occupied bucket.
+///
+/// Occupied buckets store the 7 bit `h2` hash, so their highest bit is unset.
+#[inline]
+pub(super) fn is_full(ctrl: u8) -> bool {
+    ctrl & 0x80 == 0
+}
+
+/// Set of bucket offsets inside a group, bit `i` is set if the byte `i`
+/// matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct BitMask(pub(super) u16);
+
+impl BitMask {
+    #[inline]
+    pub(super) fn any(self) -> bool {
+        self.0 != 0
+    }
+
+    #[inline]
+    pub(super) fn lowest(self) -> Option<usize> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(self.0.trailing_zeros() as usize)
+        }
+    }
+}
+
+impl Iterator for BitMask {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        let bit = self.lowest()?;
+        self.0 &= self.0 - 1;
+        Some(bit)
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+pub(super) use sse2::Group;
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+pub(super) use neon::Group;
+
+#[cfg(not(any(
+    all(target_arch = "x86_64", target_feature = "sse2"),
+    all(target_arch = "aarch64", target_feature = "neon")
+)))]
+pub(super) use generic::Group;
+
+#[cfg(all(target_arch = "x86_64", target_feature = "sse2"))]
+mod sse2 {
+    use core::arch::x86_64::*;
+
+    use super::{BitMask, EMPTY};
+
+    #[derive(Clone, Copy)]
+    pub(in super::super) struct Group(__m128i);
+
+    impl Group {
+        /// # SAFETY
+        ///
+        /// * `ptr` must be valid for reads of `WIDTH` bytes
+        #[inline]
+        pub(in super::super) unsafe fn load(ptr: *const u8) -> Self {
+            // SAFETY: caller guarantees `WIDTH` readable bytes, the load is unaligned
+            Self(unsafe { _mm_loadu_si128(ptr.cast::<__m128i>()) })
+        }
+
+        #[inline]
+        pub(in super::super) fn match_byte(self, byte: u8) -> BitMask {
+            // SAFETY: sse2 is enabled for this target
+            unsafe {
+                let cmp = _mm_cmpeq_epi8(self.0, _mm_set1_epi8(byte as i8));
+                BitMask(_mm_movemask_epi8(cmp) as u16)
+            }
+        }
+
+        #[inline]
+        pub(in super::super) fn match_empty(self) -> BitMask {
+            self.match_byte(EMPTY)
+        }
+
+        /// Both `EMPTY` and `DELETED` have their highest bit set.
+        #[inline]
+        pub(in super::super) fn match_empty_or_deleted(self) -> BitMask {
+            // SAFETY: sse2 is enabled for this target
+            BitMask(unsafe { _mm_movemask_epi8(self.0) } as u16)
+        }
+
+        #[inline]
+        pub(in super::super) fn match_full(self) -> BitMask {
+            BitMask(!self.match_empty_or_deleted().0)
+        }
+    }
+}
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+mod neon {
+    use core::arch::aarch64::*;
+
+    use super::{BitMask, EMPTY};
+
+    #[derive(Clone, Copy)]
+    pub(in super::super) struct Group(uint8x16_t);
+
+    impl Group {
+        /// # SAFETY
+        ///
+        /// * `ptr` must be valid for reads of `WIDTH` bytes
+        #[inline]
+        pub(in super::super) unsafe fn load(ptr: *const u8) -> Self {
+            // SAFETY: caller guarantees `WIDTH` readable bytes, the load is unaligned
+            Self(unsafe { vld1q_u8(ptr) })
+        }
+
+        /// NEON has no `movemask`, each matching lane keeps its own bit and
+        /// the halves are summed horizontally.
+        #[inline]
+        fn to_bitmask(cmp: uint8x16_t) -> BitMask {
+            const BITS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+            // SAFETY: neon is enabled for this target
+            unsafe {
+                let bits = vandq_u8(cmp, vld1q_u8(BITS.as_ptr()));
+                let lo = vaddv_u8(vget_low_u8(bits)) as u16;
+                let hi = vaddv_u8(vget_high_u8(bits)) as u16;
+                BitMask(lo | (hi << 8))
+            }
+        }
+
+        #[inline]
+        pub(in super::super) fn match_byte(self, byte: u8) -> BitMask {
+            // SAFETY: neon is enabled for this target
+            Self::to_bitmask(unsafe { vceqq_u8(self.0, vdupq_n_u8(byte)) })
+        }
+
+        #[inline]
+        pub(in super::super) fn match_empty(self) -> BitMask {
+            self.match_byte(EMPTY)
+        }
+
+        /// Both `EMPTY` and `DELETED` have their highest bit set.
+        #[inline]
+        pub(in super::super) fn match_empty_or_deleted(self) -> BitMask {
+            // SAFETY: neon is enabled for this target
+            Self::to_bitmask(unsafe { vcltzq_s8(vreinterpretq_s8_u8(self.0)) })
+        }
+
+        #[inline]
+        pub(in super::super) fn match_full(self) -> BitMask {
+            BitMask(!self.match_empty_or_deleted().0)
+        }
+    }
+}
+
+/// Portable version, always compiled so that it can be tested against the
+/// SIMD ones.
+pub(super) mod generic {
+    use super::{BitMask, EMPTY, WIDTH, is_full};
+
+    #[derive(Clone, Copy)]
+    pub(in super::super) struct Group([u8; WIDTH]);
+
+    impl Group {
+        /// # SAFETY
+        ///
+        /// * `ptr` must be valid for reads of `WIDTH` bytes
+        #[inline]
+        pub(in super::super) unsafe fn load(ptr: *const u8) -> Self {
+            // SAFETY: caller guarantees `WIDTH` readable bytes, the read is unaligned
+            Self(unsafe { ptr.cast::<[u8; WIDTH]>().read_unaligned() })
+        }
+
+        #[inline]
+        fn mask_where(self, f: impl Fn(u8) -> bool) -> BitMask {
+            let mut mask = 0;
+            for (i, &b) in self.0.iter().enumerate() {
+                mask |= (f(b) as u16) << i;
+            }
+            BitMask(mask)
+        }
+
+        #[inline]
+        pub(in super::super) fn match_byte(self, byte: u8) -> BitMask {
+            self.mask_where(|b| b == byte)
+        }
+
+        #[inline]
+        pub(in super::super) fn match_empty(self) -> BitMask {
+            self.match_byte(EMPTY)
+        }
+
+        #[inline]
+        pub(in super::super) fn match_empty_or_deleted(self) -> BitMask {
+            self.mask_where(|b| !is_full(b))
+        }
+
+        #[inline]
+        pub(in super::super) fn match_full(self) -> BitMask {
+            self.mask_where(is_full)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn ctrl_byte() -> impl Strategy<Value = u8> {
+        prop_oneof![Just(EMPTY), Just(DELETED), 0..0x80u8]
+    }
+
+    #[test]
+    fn bitmask_iter() {
+        assert_eq!(BitMask(0).count(), 0);
+        assert_eq!(BitMask(0b1010_0001).collect::<Vec<_>>(), [0, 5, 7]);
+        assert_eq!(BitMask(0x8000).lowest(), Some(15));
+    }
+
+    proptest!(
+        #[test]
+        fn matches_generic(
+            bytes in proptest::array::uniform16(ctrl_byte()),
+            h2 in 0..0x80u8
+        ) {
+            let simd = unsafe { Group::load(bytes.as_ptr()) };
+            let generic = unsafe { generic::Group::load(bytes.as_ptr()) };
+
+            assert_eq!(simd.match_byte(h2), generic.match_byte(h2));
+            assert_eq!(simd.match_empty(), generic.match_empty());
+            assert_eq!(simd.match_empty_or_deleted(), generic.match_empty_or_deleted());
+            assert_eq!(simd.match_full(), generic.match_full());
+
+            let expected = bytes.iter().enumerate().filter(|(_, b)| **b == h2).map(|(i, _)| i);
+            assert!(generic.match_byte(h2).eq(expected));
+        }
+    );
+}
@@ -0,0 +1,1591 @@
+//! Swiss table style hash map
+//!
+//! Keeps one control byte per bucket in a separate array: either `EMPTY`,
+//! `DELETED` or the top 7 bits of the hash (`h2`) of the stored key. Lookups
+//! compare whole groups of control bytes against `h2` at once (with SIMD where
+//! available) and only touch the pairs whose control byte matched. The pairs
+//! are stored in a parallel array in the same allocation.
+//!
+//! Groups are probed quadratically, a lookup stops at the first group which
+//! has an empty bucket.
+
+extern crate alloc as crate_alloc;
+
+mod group;
+
+use core::alloc::Layout;
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+use core::{fmt, mem};
+use std::collections::hash_map::RandomState;
+
+use crate_alloc::alloc;
+
+use self::group::{BitMask, DELETED, EMPTY, Group, WIDTH, is_full};
+use super::collect;
+#[cfg(any(test, feature = "metrics"))]
+use super::metrics::MapMetrics;
+use super::{TryReserveError, buckets_for, handle_reserve};
+
+pub struct HashMap<K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * if `cap > 0`, `ctrl` points to `cap + WIDTH` initialized control
+    //    bytes, the last `WIDTH` bytes mirror the first `WIDTH` bytes so that
+    //    a group can be loaded starting from any bucket
+    //  * `slots` points to `cap` pairs, the pair at `i` is initialized iff
+    //    `ctrl[i]` is full
+    //  * `cap` is 0 or a power of two `>= WIDTH`
+    //  * there is always at least one `EMPTY` control byte if `cap > 0`
+    ctrl: NonNull<u8>,
+    slots: NonNull<(K, V)>,
+    cap: usize,
+    index_mask: usize,
+    len: usize,
+    /// Number of `DELETED` control bytes
+    deleted: usize,
+    hash_builder: S,
+    crit_load_factor: f64,
+    marker: PhantomData<(K, V)>,
+}
+
+/// Pointers to the control bytes and the pairs of a buffer
+type RawBuf<K, V> = (NonNull<u8>, NonNull<(K, V)>);
+
+/// Quadratic (triangular) probe sequence over the groups.
+///
+/// With power of two capacity it visits every group exactly once in
+/// `cap / WIDTH` steps.
+struct ProbeSeq {
+    pos: usize,
+    stride: usize,
+}
+
+impl ProbeSeq {
+    fn move_next(&mut self, index_mask: usize) {
+        self.stride += WIDTH;
+        self.pos = (self.pos + self.stride) & index_mask;
+    }
+}
+
+/// Returns the top 7 bits of the hash which are stored in the control bytes.
+fn h2(hash: u64) -> u8 {
+    (hash >> 57) as u8
+}
+
+impl<K, V, S> Drop for HashMap<K, V, S> {
+    fn drop(&mut self) {
+        if self.cap == 0 {
+            return;
+        }
+
+        if mem::needs_drop::<(K, V)>() {
+            for i in 0..self.cap {
+                // SAFETY: i < cap, the pair is initialized if the control byte is full
+                unsafe {
+                    if is_full(*self.ctrl(i)) {
+                        ptr::drop_in_place(self.slot(i));
+                    }
+                }
+            }
+        }
+
+        let (layout, _) = Self::layout(self.cap);
+        unsafe { alloc::dealloc(self.slots.as_ptr().cast::<u8>(), layout) }
+    }
+}
+
+impl<K, V, S> Clone for HashMap<K, V, S>
+where
+    K: Clone,
+    V: Clone,
+    S: Clone,
+{
+    /// Clones the buffer as is, no rehashing is needed as the hasher is
+    /// cloned too.
+    fn clone(&self) -> Self {
+        let mut s = Self {
+            ctrl: NonNull::dangling(),
+            slots: NonNull::dangling(),
+            cap: 0,
+            index_mask: 0,
+            len: 0,
+            deleted: 0,
+            crit_load_factor: self.crit_load_factor,
+            hash_builder: self.hash_builder.clone(),
+            marker: self.marker,
+        };
+        if self.cap == 0 {
+            return s;
+        }
+
+        let (ctrl, slots) = unsafe { Self::alloc_new_buf_initialized(self.cap) };
+        s.ctrl = ctrl;
+        s.slots = slots;
+        s.cap = self.cap;
+        s.index_mask = self.index_mask;
+
+        for i in 0..self.cap {
+            // SAFETY: i < cap for both maps, the pair is initialized if the
+            //  control byte is full. The control byte is set after the clone
+            //  so that `s` stays valid if `clone` panics.
+            unsafe {
+                let c = *self.ctrl(i);
+                if is_full(c) {
+                    let (k, v) = &*self.slot(i);
+                    s.slot(i).write((k.clone(), v.clone()));
+                    s.len += 1;
+                }
+                s.set_ctrl(i, c);
+            }
+        }
+        s.deleted = self.deleted;
+
+        s
+    }
+}
+
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashMap")
+            .field("buf", &DebugHashMapBuf { map: self })
+            .field("cap", &self.cap)
+            .field("len", &self.len)
+            .field("deleted", &self.deleted)
+            .field("hash_builder", &self.hash_builder)
+            .finish()
+    }
+}
+
+struct DebugHashMapBuf<'a, K, V, S> {
+    map: &'a HashMap<K, V, S>,
+}
+
+impl<K, V, S> fmt::Debug for DebugHashMapBuf<'_, K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+
+        for i in 0..self.map.cap {
+            // SAFETY: i < cap, the pair is initialized if the control byte is full
+            let c = unsafe { *self.map.ctrl(i) };
+            if is_full(c) {
+                list.entry(unsafe { &*self.map.slot(i) });
+            } else if c == DELETED {
+                list.entry(&"Deleted");
+            } else {
+                list.entry(&"Empty");
+            }
+        }
+
+        list.finish()
+    }
+}
+
+impl<K, V, S> Default for HashMap<K, V, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K, V> HashMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_load_factor(Self::DEF_CRIT_LOAD_FACTOR)
+    }
+
+    pub fn with_load_factor(load_factor: f64) -> Self {
+        Self::with_capacity_and_load_factor(0, load_factor)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_load_factor(capacity, Self::DEF_CRIT_LOAD_FACTOR)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation.
+    pub fn with_capacity_and_load_factor(capacity: usize, lf: f64) -> Self {
+        Self::with_capacity_load_factor_and_hasher(capacity, lf, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    const DEF_CRIT_LOAD_FACTOR: f64 = 0.875;
+    const INITIAL_CAP: usize = WIDTH;
+
+    /// Creates a new hash map which uses `hash_builder` to hash the keys.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which uses `hash_builder` to hash the keys.
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_load_factor_and_hasher(
+            capacity,
+            Self::DEF_CRIT_LOAD_FACTOR,
+            hash_builder,
+        )
+    }
+
+    /// Creates a new hash map with capacity to store at least `capacity` pairs
+    /// without reallocation, which grows at load factor `lf` and uses
+    /// `hash_builder` to hash the keys.
+    pub fn with_capacity_load_factor_and_hasher(capacity: usize, lf: f64, hash_builder: S) -> Self {
+        let mut s = Self {
+            ctrl: NonNull::dangling(),
+            slots: NonNull::dangling(),
+            cap: 0,
+            index_mask: 0,
+            len: 0,
+            deleted: 0,
+            hash_builder,
+            crit_load_factor: lf,
+            marker: PhantomData,
+        };
+        if capacity > 0 {
+            let capacity = handle_reserve(buckets_for(capacity, lf)).max(Self::INITIAL_CAP);
+            debug_assert!(capacity.is_power_of_two());
+            let (ctrl, slots) = unsafe { Self::alloc_new_buf_initialized(capacity) };
+            s.ctrl = ctrl;
+            s.slots = slots;
+            s.cap = capacity;
+            s.index_mask = capacity - 1;
+        }
+
+        s
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of bytes allocated for the control bytes and the slots,
+    /// including the empty and deleted ones.
+    ///
+    /// Heap memory owned by the keys and values themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        if self.cap == 0 {
+            return 0;
+        }
+
+        Self::layout(self.cap).0.size()
+    }
+
+    fn probe_seq(&self, hash: u64) -> ProbeSeq {
+        debug_assert!(self.cap.is_power_of_two());
+        ProbeSeq {
+            pos: (hash & self.index_mask as u64) as usize,
+            stride: 0,
+        }
+    }
+
+    /// # SAFETY
+    ///
+    /// * `index < self.cap + WIDTH`
+    unsafe fn ctrl(&self, index: usize) -> *mut u8 {
+        debug_assert!(index < self.cap + WIDTH);
+        unsafe { self.ctrl.as_ptr().add(index) }
+    }
+
+    /// # SAFETY
+    ///
+    /// * `index < self.cap`
+    unsafe fn slot(&self, index: usize) -> *mut (K, V) {
+        debug_assert!(index < self.cap);
+        unsafe { self.slots.as_ptr().add(index) }
+    }
+
+    /// Loads the group of control bytes starting from `index`.
+    ///
+    /// # SAFETY
+    ///
+    /// * `index < self.cap`
+    unsafe fn group(&self, index: usize) -> Group {
+        // SAFETY: there are `WIDTH` mirrored bytes after the last bucket
+        unsafe { Group::load(self.ctrl(index)) }
+    }
+
+    /// Sets the control byte of bucket `index` and its mirror.
+    ///
+    /// # SAFETY
+    ///
+    /// * `index < self.cap`
+    unsafe fn set_ctrl(&mut self, index: usize, c: u8) {
+        debug_assert!(index < self.cap);
+        unsafe {
+            *self.ctrl(index) = c;
+            if index < WIDTH {
+                *self.ctrl(self.cap + index) = c;
+            }
+        }
+    }
+
+    fn load_factor(&self) -> f64 {
+        if self.cap == 0 {
+            return f64::INFINITY;
+        }
+
+        self.len as f64 / self.cap as f64
+    }
+
+    /// Returns `true` if the map must grow before inserting one more item.
+    ///
+    /// Tombstones count as used buckets since they lengthen the probe chains
+    /// just like occupied buckets. At least one bucket is always kept empty,
+    /// otherwise a probe for a missing key would never terminate.
+    fn needs_grow(&self) -> bool {
+        let used = self.len + self.deleted;
+        used + 1 >= self.cap || used as f64 > self.crit_load_factor * self.cap as f64
+    }
+
+    /// Returns the layout of the whole buffer and the offset of the control
+    /// bytes in it.
+    fn layout(cap: usize) -> (Layout, usize) {
+        Self::try_layout(cap).unwrap()
+    }
+
+    fn try_layout(cap: usize) -> Result<(Layout, usize), TryReserveError> {
+        let slots = Layout::array::<(K, V)>(cap).map_err(|_| TryReserveError::CapacityOverflow)?;
+        let ctrl_len = cap
+            .checked_add(WIDTH)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let ctrl = Layout::array::<u8>(ctrl_len).map_err(|_| TryReserveError::CapacityOverflow)?;
+        slots
+            .extend(ctrl)
+            .map_err(|_| TryReserveError::CapacityOverflow)
+    }
+
+    /// Returns the index of the first empty or deleted bucket on the probe
+    /// sequence of `hash`.
+    ///
+    /// # SAFETY
+    ///
+    /// * `self.cap > 0`
+    unsafe fn find_insert_slot(&self, hash: u64) -> usize {
+        let mut seq = self.probe_seq(hash);
+        loop {
+            // SAFETY: pos < cap
+            let group = unsafe { self.group(seq.pos) };
+            if let Some(bit) = group.match_empty_or_deleted().lowest() {
+                return (seq.pos + bit) & self.index_mask;
+            }
+            seq.move_next(self.index_mask);
+        }
+    }
+
+    /// Writes the pair into the empty or deleted bucket `index`.
+    ///
+    /// # SAFETY
+    ///
+    /// * `index < self.cap` and the bucket must not be full
+    unsafe fn write_pair(&mut self, index: usize, hash: u64, pair: (K, V)) {
+        unsafe {
+            if *self.ctrl(index) == DELETED {
+                self.deleted -= 1;
+            }
+            self.slot(index).write(pair);
+            self.set_ctrl(index, h2(hash));
+        }
+        self.len += 1;
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let hash = self.hash_key(&key);
+        if let Some(index) = self.find(hash, &key) {
+            // SAFETY: `find` returns an index of a full bucket
+            let pair = unsafe { &mut *self.slot(index) };
+            return Some(mem::replace(pair, (key, value)));
+        }
+
+        if self.needs_grow() {
+            self.grow();
+        }
+
+        // SAFETY: cap > 0 after growing, the returned index is not full
+        unsafe {
+            let index = self.find_insert_slot(hash);
+            self.write_pair(index, hash, (key, value));
+        }
+        None
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.find(self.hash_key(key), key)?;
+        // SAFETY: `find` returns an index of a full bucket
+        let (k, v) = unsafe { &*self.slot(index) };
+        Some((k, v))
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.find(self.hash_key(key), key)?;
+        // SAFETY: `find` returns an index of a full bucket, the reference
+        //  borrows `self` mutably
+        Some(unsafe { &mut (*self.slot(index)).1 })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.find(self.hash_key(key), key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        let index = self.find(self.hash_key(key), key)?;
+        // SAFETY: `find` returns an index of a full bucket, it's marked as
+        //  deleted so the pair is not read again
+        let pair = unsafe {
+            self.set_ctrl(index, DELETED);
+            self.slot(index).read()
+        };
+        self.len -= 1;
+        self.deleted += 1;
+        Some(pair)
+    }
+
+    /// Returns the index of the bucket holding `key`.
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let h2 = h2(hash);
+        let mut seq = self.probe_seq(hash);
+        loop {
+            // SAFETY: pos < cap
+            let group = unsafe { self.group(seq.pos) };
+            for bit in group.match_byte(h2) {
+                let index = (seq.pos + bit) & self.index_mask;
+                // SAFETY: index < cap and the control byte is full
+                let (k, _) = unsafe { &*self.slot(index) };
+                if k.borrow() == key {
+                    return Some(index);
+                }
+            }
+            if group.match_empty().any() {
+                return None;
+            }
+            seq.move_next(self.index_mask);
+        }
+    }
+
+    /// Reserves capacity for at least `additional` more pairs so that they
+    /// can be inserted without reallocation.
+    ///
+    /// # PANICS
+    ///
+    /// * if the new capacity overflows `usize`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    pub fn reserve(&mut self, additional: usize) {
+        handle_reserve(self.try_reserve(additional))
+    }
+
+    /// Tries to reserve capacity for at least `additional` more pairs so that
+    /// they can be inserted without reallocation.
+    ///
+    /// The map is unchanged if an error is returned.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+        let new_cap = buckets_for(required, self.crit_load_factor)?.max(Self::INITIAL_CAP);
+        if new_cap > self.cap {
+            self.try_rebuild(new_cap)
+        } else if (required + self.deleted) as f64 / self.crit_load_factor + 1.0 > self.cap as f64 {
+            // fits only without the tombstones
+            self.try_rebuild(self.cap)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn hash_key<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash,
+    {
+        self.hash_builder.hash_one(key)
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    fn grow(&mut self)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        if self.cap == 0 {
+            self.rebuild(Self::INITIAL_CAP);
+        } else if (self.len + 1) as f64 <= self.crit_load_factor * self.cap as f64 / 2.0 {
+            // mostly tombstones, getting rid of them is enough
+            self.rebuild(self.cap);
+        } else {
+            self.rebuild(2 * self.cap);
+        }
+    }
+
+    /// Moves all items into a new buffer of `new_cap` buckets, dropping all tombstones.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two or `new_cap < self.cap`
+    fn rebuild(&mut self, new_cap: usize)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        handle_reserve(self.try_rebuild(new_cap))
+    }
+
+    /// Same as `rebuild` but returns an error instead of aborting if the
+    /// allocation fails. The map is unchanged on error.
+    ///
+    /// # PANICS
+    ///
+    /// * if `new_cap` is not power of two or `new_cap < self.cap`
+    fn try_rebuild(&mut self, new_cap: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        assert!(new_cap.is_power_of_two() && new_cap >= self.cap.max(WIDTH));
+
+        // SAFETY: new_cap >= WIDTH > 0
+        let (new_ctrl, new_slots) = unsafe { Self::try_alloc_new_buf_initialized(new_cap) }?;
+        let old_slots = self.slots;
+        let old_cap = unsafe { self.swap_buf(new_ctrl, new_slots, new_cap) };
+
+        if old_cap != 0 {
+            // drop old buffer, all the pairs were moved out
+            let (old_layout, _) = Self::layout(old_cap);
+            unsafe { alloc::dealloc(old_slots.as_ptr().cast::<u8>(), old_layout) }
+        }
+
+        Ok(())
+    }
+
+    /// Allocates new buffer with capacity `new_cap` and initializes all the
+    /// control bytes to `EMPTY`. Returns pointers to the control bytes and
+    /// the pairs.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    ///
+    /// # ABORTS
+    ///
+    /// * if allocation fails
+    ///
+    /// # PANICS
+    ///
+    /// * if the buffer would be larger than `isize::MAX` bytes
+    unsafe fn alloc_new_buf_initialized(new_cap: usize) -> RawBuf<K, V> {
+        handle_reserve(unsafe { Self::try_alloc_new_buf_initialized(new_cap) })
+    }
+
+    /// Same as `alloc_new_buf_initialized` but returns an error if the
+    /// allocation fails or the buffer would be too large.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_cap > 0`
+    unsafe fn try_alloc_new_buf_initialized(
+        new_cap: usize,
+    ) -> Result<RawBuf<K, V>, TryReserveError> {
+        let (new_layout, ctrl_offset) = Self::try_layout(new_cap)?;
+        // SAFETY: layout has non-zero size as there are at least `WIDTH` control bytes
+        let new_buf = unsafe { alloc::alloc(new_layout) };
+        if new_buf.is_null() {
+            Err(TryReserveError::AllocError { layout: new_layout })
+        } else {
+            unsafe {
+                let ctrl = new_buf.add(ctrl_offset);
+                ptr::write_bytes(ctrl, EMPTY, new_cap + WIDTH);
+                Ok((
+                    NonNull::new_unchecked(ctrl),
+                    NonNull::new_unchecked(new_buf.cast::<(K, V)>()),
+                ))
+            }
+        }
+    }
+
+    /// Swap current buffer with new one by moving all the items from old
+    /// buffer into new. Returns the old capacity, the old buffer must be
+    /// deallocated by the caller.
+    ///
+    /// # SAFETY
+    ///
+    /// * `new_ctrl` and `new_slots` must be from `alloc_new_buf_initialized(new_cap)`
+    /// * `new_cap >= self.cap`
+    unsafe fn swap_buf(
+        &mut self,
+        new_ctrl: NonNull<u8>,
+        new_slots: NonNull<(K, V)>,
+        new_cap: usize,
+    ) -> usize
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let old_ctrl = mem::replace(&mut self.ctrl, new_ctrl);
+        let old_slots = mem::replace(&mut self.slots, new_slots);
+        let old_cap = mem::replace(&mut self.cap, new_cap);
+        self.index_mask = self.cap - 1;
+        self.len = 0;
+        self.deleted = 0;
+
+        // insert all items into the new buffer, all keys are unique so
+        // there is no need to compare them
+        for i in 0..old_cap {
+            unsafe {
+                if is_full(*old_ctrl.as_ptr().add(i)) {
+                    let pair = old_slots.as_ptr().add(i).read();
+                    let hash = self.hash_key(&pair.0);
+                    let index = self.find_insert_slot(hash);
+                    self.write_pair(index, hash, pair);
+                }
+            }
+        }
+
+        old_cap
+    }
+}
+
+/// Walks over the full buckets a group at a time.
+struct RawIter<K, V> {
+    // INVARIANTS:
+    //  * `ctrl` and `slots` are from a map with capacity `cap` which remains
+    //    valid and is not mutated through other references while the
+    //    iterator is alive
+    //  * `current` are the full buckets in the group at `group_start` which
+    //    are not returned yet
+    //  * `remaining` is the number of full buckets not returned yet
+    ctrl: NonNull<u8>,
+    slots: NonNull<(K, V)>,
+    cap: usize,
+    group_start: usize,
+    current: BitMask,
+    remaining: usize,
+}
+
+impl<K, V> RawIter<K, V> {
+    /// # SAFETY
+    ///
+    /// * the map must remain valid and not be mutated through other references
+    ///   while the iterator or any reference returned from it is alive
+    unsafe fn new<S>(map: &HashMap<K, V, S>) -> Self {
+        let current = if map.cap == 0 {
+            BitMask(0)
+        } else {
+            // SAFETY: 0 < cap
+            unsafe { map.group(0) }.match_full()
+        };
+        Self {
+            ctrl: map.ctrl,
+            slots: map.slots,
+            cap: map.cap,
+            group_start: 0,
+            current,
+            remaining: map.len,
+        }
+    }
+
+    fn next(&mut self) -> Option<NonNull<(K, V)>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        loop {
+            if let Some(bit) = self.current.next() {
+                self.remaining -= 1;
+                // SAFETY: group_start + bit < cap as groups are aligned to
+                //  `WIDTH` and cap is a multiple of `WIDTH`
+                return Some(unsafe {
+                    NonNull::new_unchecked(self.slots.as_ptr().add(self.group_start + bit))
+                });
+            }
+
+            self.group_start += WIDTH;
+            debug_assert!(self.group_start < self.cap);
+            // SAFETY: there are remaining full buckets, so group_start < cap
+            self.current =
+                unsafe { Group::load(self.ctrl.as_ptr().add(self.group_start)) }.match_full();
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    /// Iterate over all key-value pairs in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            // SAFETY: the iterator borrows `self` immutably
+            raw: unsafe { RawIter::new(self) },
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate over all key-value pairs with mutable references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            // SAFETY: the iterator borrows `self` mutably and every bucket is
+            //  returned at most once
+            raw: unsafe { RawIter::new(self) },
+            marker: PhantomData,
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut {
+            inner: self.iter_mut(),
+        }
+    }
+
+    /// Removes all pairs from the map and returns them in an iterator.
+    ///
+    /// Pairs that are not consumed are dropped when the iterator is dropped.
+    /// The capacity of the map is kept.
+    pub fn drain(&mut self) -> Drain<'_, K, V, S> {
+        Drain {
+            map: self,
+            index: 0,
+        }
+    }
+
+    /// Empties buckets starting from `*index` until a full one is found and
+    /// returns its pair.
+    fn take_next(&mut self, index: &mut usize) -> Option<(K, V)> {
+        while *index < self.cap {
+            let i = *index;
+            *index += 1;
+            // SAFETY: i < cap, the pair is initialized if the control byte is
+            //  full and it's marked empty before it's read out
+            unsafe {
+                if is_full(*self.ctrl(i)) {
+                    self.set_ctrl(i, EMPTY);
+                    self.len -= 1;
+                    return Some(self.slot(i).read());
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Marks all buckets empty, the map must not have any full buckets.
+    fn clear_ctrl(&mut self) {
+        debug_assert_eq!(self.len, 0);
+        if self.cap > 0 {
+            // SAFETY: there are `cap + WIDTH` control bytes
+            unsafe { ptr::write_bytes(self.ctrl.as_ptr(), EMPTY, self.cap + WIDTH) };
+        }
+        self.deleted = 0;
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, S> IntoIterator for HashMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            map: self,
+            index: 0,
+        }
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    raw: RawIter<K, V>,
+    marker: PhantomData<&'a (K, V)>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pair = self.raw.next()?;
+        // SAFETY: the map is borrowed immutably for 'a
+        let (k, v) = unsafe { &*pair.as_ptr() };
+        Some((k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.raw.remaining, Some(self.raw.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> core::iter::FusedIterator for Iter<'_, K, V> {}
+
+pub struct IterMut<'a, K, V> {
+    raw: RawIter<K, V>,
+    marker: PhantomData<&'a mut (K, V)>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pair = self.raw.next()?;
+        // SAFETY: the map is borrowed mutably for 'a and every bucket
+        //  is returned only once, so there are no aliasing references
+        let (k, v) = unsafe { &mut *pair.as_ptr() };
+        Some((k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.raw.remaining, Some(self.raw.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+impl<K, V> core::iter::FusedIterator for IterMut<'_, K, V> {}
+
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
+
+pub struct ValuesMut<'a, K, V> {
+    inner: IterMut<'a, K, V>,
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for ValuesMut<'_, K, V> {}
+
+pub struct Drain<'a, K, V, S = RandomState> {
+    // INVARIANTS:
+    //  * all buckets before `index` are not full
+    //  * `map.len` is the number of pairs in buckets after `index`
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+}
+
+impl<K, V, S> Iterator for Drain<'_, K, V, S> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.map.take_next(&mut self.index)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.map.len, Some(self.map.len))
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for Drain<'_, K, V, S> {}
+
+impl<K, V, S> Drop for Drain<'_, K, V, S> {
+    fn drop(&mut self) {
+        // If a pair panics on drop, the rest are dropped by the map.
+        self.by_ref().for_each(drop);
+        self.map.clear_ctrl();
+    }
+}
+
+pub struct IntoIter<K, V, S = RandomState> {
+    // INVARIANTS: same as in `Drain`
+    map: HashMap<K, V, S>,
+    index: usize,
+}
+
+impl<K, V, S> Iterator for IntoIter<K, V, S> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.map.take_next(&mut self.index)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.map.len, Some(self.map.len))
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for IntoIter<K, V, S> {}
+
+collect::impl_collect!(hasher S);
+
+#[cfg(any(test, feature = "metrics"))]
+impl<K, V, S> MapMetrics<K, V> for HashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Probe length is the number of key comparisons before the right one.
+    fn get_with_metrics<Q>(&self, key: &Q) -> Option<(&K, &V, usize)>
+    where
+        Q: Eq + Hash,
+        K: Borrow<Q>,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let hash = self.hash_key(key);
+        let mut seq = self.probe_seq(hash);
+        let mut probe_len = 0;
+        loop {
+            let group = unsafe { self.group(seq.pos) };
+            for bit in group.match_byte(h2(hash)) {
+                let index = (seq.pos + bit) & self.index_mask;
+                let (k, v) = unsafe { &*self.slot(index) };
+                if k.borrow() == key {
+                    return Some((k, v, probe_len));
+                }
+                probe_len += 1;
+            }
+            if group.match_empty().any() {
+                return None;
+            }
+            seq.move_next(self.index_mask);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn cap(&self) -> usize {
+        self.cap
+    }
+
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes()
+    }
+
+    fn load_factor(&self) -> f64 {
+        self.load_factor()
+    }
+
+    fn name(&self) -> &'static str {
+        "Swiss table"
+    }
+
+    fn tombstones(&self) -> usize {
+        self.deleted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::Hasher;
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    struct SameHash(i32);
+
+    // They all hash to same value, so they share the probe sequence and `h2`
+    impl Hash for SameHash {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            1.hash(state);
+        }
+    }
+
+    impl<K: Hash + Eq, V> HashMap<K, V> {
+        fn check_invariants(&self) {
+            if self.cap == 0 {
+                assert_eq!(self.len, 0);
+                return;
+            }
+
+            let mut full = 0;
+            let mut deleted = 0;
+            for i in 0..self.cap {
+                let c = unsafe { *self.ctrl(i) };
+                if is_full(c) {
+                    full += 1;
+                    let (k, _) = unsafe { &*self.slot(i) };
+                    assert_eq!(c, h2(self.hash_key(k)));
+                    assert_eq!(self.find(self.hash_key(k), k), Some(i));
+                } else if c == DELETED {
+                    deleted += 1;
+                } else {
+                    assert_eq!(c, EMPTY);
+                }
+            }
+            for i in 0..WIDTH {
+                assert_eq!(unsafe { *self.ctrl(i) }, unsafe {
+                    *self.ctrl(self.cap + i)
+                });
+            }
+            assert_eq!(full, self.len);
+            assert_eq!(deleted, self.deleted);
+        }
+    }
+
+    #[test]
+    fn insert() {
+        let mut m = HashMap::<i32, i32>::new();
+        assert!(m.is_empty());
+        m.insert(1, 11);
+        assert_eq!(m.len(), 1);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        assert_eq!(m.len(), 4);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+
+        assert_eq!(m.insert(4, 42), Some((4, 41)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &42)));
+        m.check_invariants();
+    }
+
+    #[test]
+    fn collect_extend_index() {
+        let mut m: HashMap<i32, i32> = (0..10).map(|i| (i, i * 10)).collect();
+        assert_eq!(m.len(), 10);
+        assert_eq!(m[&3], 30);
+
+        m.extend((5..15).map(|i| (i, i * 100)));
+        assert_eq!(m.len(), 15);
+        assert_eq!(m[&3], 30);
+        assert_eq!(m[&5], 500);
+        assert_eq!(m[&14], 1400);
+    }
+
+    #[test]
+    #[should_panic(expected = "key not found")]
+    fn index_missing_key() {
+        let m: HashMap<i32, i32> = (0..10).map(|i| (i, i)).collect();
+        let _ = m[&10];
+    }
+
+    #[test]
+    fn allocated_bytes() {
+        let mut m = HashMap::new();
+        assert_eq!(m.allocated_bytes(), 0);
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        // the slots and one control byte per bucket plus the mirrored group
+        let expected = m.cap * mem::size_of::<(i32, i32)>() + m.cap + WIDTH;
+        assert_eq!(m.allocated_bytes(), expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let m: HashMap<i32, String> = (0..20).map(|i| (i, i.to_string())).collect();
+
+        let json = serde_json::to_string(&m).unwrap();
+        let de: HashMap<i32, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.len(), 20);
+        for i in 0..20 {
+            assert_eq!(de.get(&i), Some(&i.to_string()));
+        }
+
+        let dups: HashMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[&5], 3);
+
+        let empty: HashMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(serde_json::to_string(&empty).unwrap(), "{}");
+    }
+
+    #[test]
+    fn remove() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.remove(&2), Some((2, 21)));
+        assert_eq!(m.remove(&2), None);
+        assert_eq!(m.remove(&1), Some((1, 11)));
+        assert_eq!(m.remove(&1), None);
+        assert_eq!(m.remove(&3), Some((3, 31)));
+        assert_eq!(m.remove(&3), None);
+        assert_eq!(m.remove(&4), Some((4, 41)));
+        assert_eq!(m.remove(&4), None);
+        assert_eq!(m.remove(&5), Some((5, 51)));
+        assert_eq!(m.remove(&5), None);
+
+        assert!(m.is_empty());
+        m.check_invariants();
+    }
+
+    #[test]
+    fn remove_same_hash() {
+        let mut m = HashMap::new();
+        assert_eq!(m.remove(&SameHash(1)), None);
+
+        // spans multiple groups
+        for i in 0..100 {
+            m.insert(SameHash(i), i);
+        }
+        m.check_invariants();
+
+        for i in (0..100).step_by(2) {
+            assert_eq!(m.remove(&SameHash(i)), Some((SameHash(i), i)));
+        }
+        m.check_invariants();
+        for i in 0..100 {
+            let key = SameHash(i);
+            let expected = (i % 2 == 1).then_some((&key, &i));
+            assert_eq!(m.get_key_value(&key), expected);
+        }
+        for i in (1..100).step_by(2) {
+            assert_eq!(m.remove(&SameHash(i)), Some((SameHash(i), i)));
+        }
+
+        assert!(m.is_empty());
+        m.check_invariants();
+    }
+
+    #[test]
+    fn get() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get_key_value(&1), None);
+
+        m.insert(1, 11);
+        m.insert(2, 21);
+        m.insert(3, 31);
+        m.insert(5, 51);
+        m.insert(4, 41);
+
+        assert_eq!(m.get_key_value(&2), Some((&2, &21)));
+        assert_eq!(m.get_key_value(&1), Some((&1, &11)));
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert_eq!(m.get_key_value(&4), Some((&4, &41)));
+        assert_eq!(m.get_key_value(&5), Some((&5, &51)));
+        assert_eq!(m.get_key_value(&6), None);
+    }
+
+    #[test]
+    fn probe_sequence_visits_all_groups() {
+        for cap in [16, 32, 64, 1024] {
+            let index_mask = cap - 1;
+            let mut seq = ProbeSeq { pos: 5, stride: 0 };
+            let mut visited = vec![false; cap / WIDTH];
+            for _ in 0..cap / WIDTH {
+                visited[(seq.pos - 5) / WIDTH] = true;
+                seq.move_next(index_mask);
+            }
+            assert!(visited.iter().all(|v| *v), "cap {cap}");
+        }
+    }
+
+    #[test]
+    fn tombstones_are_reused() {
+        let mut m = HashMap::with_capacity(10);
+        let cap = m.cap;
+        for _ in 0..100 {
+            for i in 0..10 {
+                m.insert(i, i);
+            }
+            for i in 0..10 {
+                m.remove(&i);
+            }
+        }
+        assert_eq!(m.cap, cap);
+        assert!(m.deleted < cap);
+        m.check_invariants();
+    }
+
+    #[test]
+    fn clone() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i, i.to_string());
+        }
+        for i in 0..50 {
+            m.remove(&i);
+        }
+
+        let c = m.clone();
+        c.check_invariants();
+        assert_eq!(c.len(), 50);
+        assert_eq!(c.deleted, m.deleted);
+        for i in 0..100 {
+            assert_eq!(c.get(&i), m.get(&i));
+        }
+
+        let empty = HashMap::<i32, i32>::new().clone();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn iterators() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+        m.remove(&3);
+        m.remove(&7);
+
+        let mut items: Vec<_> = m.iter().map(|(k, v)| (*k, *v)).collect();
+        items.sort();
+        let expected: Vec<_> = [0, 1, 2, 4, 5, 6, 8, 9].map(|i| (i, i * 10)).into();
+        assert_eq!(items, expected);
+        assert_eq!(m.iter().len(), 8);
+        assert_eq!(m.keys().sum::<i32>(), 35);
+        assert_eq!(m.values().sum::<i32>(), 350);
+
+        for v in m.values_mut() {
+            *v += 1;
+        }
+        for (k, v) in &mut m {
+            *v += k;
+        }
+        assert_eq!(m.get_key_value(&4), Some((&4, &45)));
+
+        let mut drained: Vec<_> = m.drain().collect();
+        drained.sort();
+        assert_eq!(drained.len(), 8);
+        assert_eq!(drained[0], (0, 1));
+        assert!(m.is_empty());
+        assert_eq!(m.deleted, 0);
+        assert_eq!(m.iter().next(), None);
+        m.check_invariants();
+
+        // map is still usable after the drain
+        m.insert(1, 1);
+        m.insert(2, 2);
+        let mut owned: Vec<_> = m.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, [(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn iterators_drop_unconsumed() {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+
+        let mut drain = m.drain();
+        drain.next();
+        drop(drain);
+        assert!(m.is_empty());
+        assert_eq!(Rc::strong_count(&value), 1);
+
+        for i in 0..10 {
+            m.insert(i, Rc::clone(&value));
+        }
+        let mut into_iter = m.into_iter();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 9);
+        drop(into_iter);
+        assert_eq!(Rc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn get_mut_contains_key() {
+        let mut m = HashMap::new();
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.get_mut(&1), None);
+        assert!(!m.contains_key(&1));
+
+        for i in 0..10 {
+            m.insert(i, i * 10);
+        }
+
+        // lookups only need a shared reference
+        let (a, b) = (&m, &m);
+        assert_eq!(a.get(&3), Some(&30));
+        assert_eq!(b.get_key_value(&4), Some((&4, &40)));
+        assert!(a.contains_key(&9));
+        assert!(!b.contains_key(&10));
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get(&3), Some(&31));
+        assert_eq!(m.get_mut(&10), None);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut m = HashMap::new();
+        m.insert(-1, -1);
+        m.reserve(100);
+        let cap = m.cap;
+        for i in 0..100 {
+            m.insert(i, i);
+        }
+        assert_eq!(m.cap, cap, "grew after reserve");
+        assert_eq!(m.len(), 101);
+
+        assert_eq!(
+            m.try_reserve(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            m.try_reserve(usize::MAX / 2),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(m.cap, cap);
+        assert_eq!(m.get_key_value(&50), Some((&50, &50)));
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+        use rand::seq::SliceRandom;
+        use rand::thread_rng;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn insert_get(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..10)
+            ) {
+                let ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
+
+                let mut hmap = HashMap::with_capacity(ref_hmap.len());
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+                hmap.check_invariants();
+
+                inserts.shuffle(&mut thread_rng());
+                for key in inserts.iter().chain(access.iter()) {
+                    assert_eq!(ref_hmap.get_key_value(key), hmap.get_key_value(key));
+                }
+            }
+
+            #[test]
+            fn iter_drain(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::new();
+                for v in &inserts {
+                    ref_hmap.insert(*v, *v);
+                    hmap.insert(*v, *v);
+                }
+                for v in &removes {
+                    assert_eq!(ref_hmap.remove_entry(v), hmap.remove(v));
+                }
+                hmap.check_invariants();
+
+                let mut expected: Vec<_> = ref_hmap.into_iter().collect();
+                expected.sort();
+                let mut items: Vec<_> = hmap.iter().map(|(k, v)| (*k, *v)).collect();
+                items.sort();
+                assert_eq!(&items, &expected);
+
+                let mut drained: Vec<_> = hmap.drain().collect();
+                drained.sort();
+                assert_eq!(drained, expected);
+                assert!(hmap.is_empty());
+                hmap.check_invariants();
+
+                // the map must be fully reusable after the drain
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+                let mut owned: Vec<_> = hmap.into_iter().map(|(k, _)| k).collect();
+                owned.sort();
+                owned.dedup();
+                let mut inserts = inserts;
+                inserts.sort();
+                inserts.dedup();
+                assert_eq!(owned, inserts);
+            }
+
+            #[test]
+            fn remove(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..10)
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
+                let mut hmap = HashMap::with_capacity(ref_hmap.len());
+                for v in &inserts {
+                    hmap.insert(*v, *v);
+                }
+
+                assert_eq!(ref_hmap.len(), hmap.len());
+
+                inserts.shuffle(&mut thread_rng());
+                for key in access.iter().chain(inserts.iter()) {
+                    assert_eq!(ref_hmap.remove_entry(key), hmap.remove(key));
+                }
+                hmap.check_invariants();
+            }
+
+            #[test]
+            fn insert_remove_mixed(
+                ops in proptest::collection::vec((any::<bool>(), 0..500i32), 0..MAP_SIZE),
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::new();
+                let mut hmap = HashMap::new();
+                for (insert, key) in ops {
+                    if insert {
+                        assert_eq!(ref_hmap.insert(key, key).map(|v| (key, v)), hmap.insert(key, key));
+                    } else {
+                        assert_eq!(ref_hmap.remove_entry(&key), hmap.remove(&key));
+                    }
+                }
+                hmap.check_invariants();
+            }
+
+            #[test]
+            #[cfg_attr(miri, ignore = "nothing for miri to really check, no need to waste time")]
+            fn with_cap(cap in 0..100_000usize, lf in 0.5..0.999) {
+                let map = HashMap::<u8, ()>::with_capacity_and_load_factor(cap, lf);
+                if cap > 0 {
+                    let will_be_lf = cap as f64/map.cap as f64;
+                    assert!(will_be_lf < lf);
+                    assert!(map.cap.is_power_of_two());
+                    assert!(map.cap >= WIDTH);
+                } else {
+                    assert_eq!(map.cap, 0);
+                }
+            }
+        );
+    }
+}
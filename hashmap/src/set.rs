@@ -0,0 +1,602 @@
+//! Hash set on top of any of the open addressing hash maps
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+
+use crate::open_addressing::{
+    coalesced, double_hashing, hopscotch, linear_probing, quadratic_probing, robin_hood,
+    swiss_table,
+};
+
+/// A hash map which can be used as the backing storage of a [`HashSet`].
+///
+/// Implemented for `HashMap<T, ()>` of the open addressing maps which support
+/// iteration.
+pub trait SetMap<T>: Default {
+    type Keys<'a>: Iterator<Item = &'a T>
+    where
+        Self: 'a,
+        T: 'a;
+    type IntoKeys: Iterator<Item = T>;
+
+    fn with_capacity(capacity: usize) -> Self;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts `key`, returns `true` if it wasn't present yet.
+    ///
+    /// An equal key which is already present is replaced.
+    fn insert(&mut self, key: T) -> bool;
+    fn get<Q>(&self, key: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash;
+    fn remove<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash;
+    fn keys(&self) -> Self::Keys<'_>;
+    fn into_keys(self) -> Self::IntoKeys;
+}
+
+macro_rules! impl_set_map {
+    ($($map:ident),*) => {
+        $(
+            impl<T> SetMap<T> for $map::HashMap<T, ()>
+            where
+                T: Hash + Eq,
+            {
+                type Keys<'a> = $map::Keys<'a, T, ()> where T: 'a;
+                type IntoKeys = core::iter::Map<$map::IntoIter<T, ()>, fn((T, ())) -> T>;
+
+                fn with_capacity(capacity: usize) -> Self {
+                    Self::with_capacity(capacity)
+                }
+
+                fn len(&self) -> usize {
+                    self.len()
+                }
+
+                fn insert(&mut self, key: T) -> bool {
+                    self.insert(key, ()).is_none()
+                }
+
+                fn get<Q>(&self, key: &Q) -> Option<&T>
+                where
+                    T: Borrow<Q>,
+                    Q: Eq + Hash,
+                {
+                    self.get_key_value(key).map(|(k, _)| k)
+                }
+
+                fn remove<Q>(&mut self, key: &Q) -> Option<T>
+                where
+                    T: Borrow<Q>,
+                    Q: Eq + Hash,
+                {
+                    self.remove(key).map(|(k, _)| k)
+                }
+
+                fn keys(&self) -> Self::Keys<'_> {
+                    self.keys()
+                }
+
+                fn into_keys(self) -> Self::IntoKeys {
+                    self.into_iter().map(|(k, _)| k)
+                }
+            }
+        )*
+    };
+}
+
+impl_set_map!(
+    coalesced,
+    double_hashing,
+    hopscotch,
+    linear_probing,
+    quadratic_probing,
+    robin_hood,
+    swiss_table
+);
+
+/// A hash set implemented as a map with `()` values.
+///
+/// `S` selects the backing hash map, for example
+/// `HashSet<T, linear_probing::HashMap<T, ()>>`.
+pub struct HashSet<T, S = robin_hood::HashMap<T, ()>> {
+    map: S,
+    marker: PhantomData<T>,
+}
+
+impl<T, S> HashSet<T, S>
+where
+    S: SetMap<T>,
+{
+    pub fn new() -> Self {
+        Self {
+            map: S::default(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates a new set with capacity to store at least `capacity` items
+    /// without reallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: S::with_capacity(capacity),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Adds `value` to the set, returns `true` if it wasn't present yet.
+    ///
+    /// If the set already contains an equal value, it's replaced by `value`.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.map.insert(value)
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.map.get(value).is_some()
+    }
+
+    /// Returns a reference to the value in the set which is equal to `value`.
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.map.get(value)
+    }
+
+    /// Removes `value` from the set, returns `true` if it was present.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Removes and returns the value in the set which is equal to `value`.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.map.remove(value)
+    }
+
+    /// Iterate over all values in arbitrary order.
+    pub fn iter(&self) -> Iter<'_, T, S> {
+        Iter {
+            inner: self.map.keys(),
+        }
+    }
+
+    /// Values that are in `self` or `other`, without duplicates.
+    pub fn union<'a>(&'a self, other: &'a Self) -> Union<'a, T, S>
+    where
+        T: Eq + Hash,
+    {
+        Union {
+            iter: self.iter(),
+            other: other.difference(self),
+        }
+    }
+
+    /// Values that are both in `self` and `other`.
+    pub fn intersection<'a>(&'a self, other: &'a Self) -> Intersection<'a, T, S>
+    where
+        T: Eq + Hash,
+    {
+        // iterate over the smaller set
+        let (iter, other) = if self.len() <= other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        Intersection {
+            iter: iter.iter(),
+            other,
+        }
+    }
+
+    /// Values that are in `self` but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a Self) -> Difference<'a, T, S>
+    where
+        T: Eq + Hash,
+    {
+        Difference {
+            iter: self.iter(),
+            other,
+        }
+    }
+
+    /// Values that are in `self` or `other` but not in both.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, T, S>
+    where
+        T: Eq + Hash,
+    {
+        SymmetricDifference {
+            a: self.difference(other),
+            b: other.difference(self),
+        }
+    }
+
+    pub fn is_subset(&self, other: &Self) -> bool
+    where
+        T: Eq + Hash,
+    {
+        self.len() <= other.len() && self.iter().all(|v| other.contains(v))
+    }
+
+    pub fn is_superset(&self, other: &Self) -> bool
+    where
+        T: Eq + Hash,
+    {
+        other.is_subset(self)
+    }
+
+    pub fn is_disjoint(&self, other: &Self) -> bool
+    where
+        T: Eq + Hash,
+    {
+        self.intersection(other).next().is_none()
+    }
+}
+
+impl<T, S> Default for HashSet<T, S>
+where
+    S: SetMap<T>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, S> Clone for HashSet<T, S>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, S> fmt::Debug for HashSet<T, S>
+where
+    T: fmt::Debug,
+    S: SetMap<T>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, S> PartialEq for HashSet<T, S>
+where
+    T: Eq + Hash,
+    S: SetMap<T>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.is_subset(other)
+    }
+}
+
+impl<T, S> Eq for HashSet<T, S>
+where
+    T: Eq + Hash,
+    S: SetMap<T>,
+{
+}
+
+impl<T, S> FromIterator<T> for HashSet<T, S>
+where
+    S: SetMap<T>,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<T, S> Extend<T> for HashSet<T, S>
+where
+    S: SetMap<T>,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<'a, T, S> IntoIterator for &'a HashSet<T, S>
+where
+    S: SetMap<T>,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, S> IntoIterator for HashSet<T, S>
+where
+    S: SetMap<T>,
+{
+    type Item = T;
+    type IntoIter = S::IntoKeys;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_keys()
+    }
+}
+
+pub struct Iter<'a, T, S>
+where
+    S: SetMap<T> + 'a,
+    T: 'a,
+{
+    inner: S::Keys<'a>,
+}
+
+impl<'a, T, S> Iterator for Iter<'a, T, S>
+where
+    S: SetMap<T>,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T, S> Clone for Iter<'a, T, S>
+where
+    S: SetMap<T>,
+    S::Keys<'a>: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+pub struct Union<'a, T, S>
+where
+    S: SetMap<T>,
+{
+    iter: Iter<'a, T, S>,
+    other: Difference<'a, T, S>,
+}
+
+impl<'a, T, S> Iterator for Union<'a, T, S>
+where
+    T: Eq + Hash,
+    S: SetMap<T>,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().or_else(|| self.other.next())
+    }
+}
+
+impl<T, S> FusedIterator for Union<'_, T, S>
+where
+    T: Eq + Hash,
+    S: SetMap<T>,
+{
+}
+
+pub struct Intersection<'a, T, S>
+where
+    S: SetMap<T>,
+{
+    iter: Iter<'a, T, S>,
+    other: &'a HashSet<T, S>,
+}
+
+impl<'a, T, S> Iterator for Intersection<'a, T, S>
+where
+    T: Eq + Hash,
+    S: SetMap<T>,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.find(|v| other.contains(*v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+pub struct Difference<'a, T, S>
+where
+    S: SetMap<T>,
+{
+    iter: Iter<'a, T, S>,
+    other: &'a HashSet<T, S>,
+}
+
+impl<'a, T, S> Iterator for Difference<'a, T, S>
+where
+    T: Eq + Hash,
+    S: SetMap<T>,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.find(|v| !other.contains(*v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.iter.size_hint().1)
+    }
+}
+
+pub struct SymmetricDifference<'a, T, S>
+where
+    S: SetMap<T>,
+{
+    a: Difference<'a, T, S>,
+    b: Difference<'a, T, S>,
+}
+
+impl<'a, T, S> Iterator for SymmetricDifference<'a, T, S>
+where
+    T: Eq + Hash,
+    S: SetMap<T>,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.a.next().or_else(|| self.b.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    fn sorted<'a>(iter: impl Iterator<Item = &'a i32>) -> Vec<i32> {
+        let mut v: Vec<_> = iter.copied().collect();
+        v.sort();
+        v
+    }
+
+    fn basic_ops<S: SetMap<i32>>() {
+        let mut s = HashSet::<i32, S>::new();
+        assert!(s.is_empty());
+        assert!(s.insert(1));
+        assert!(s.insert(2));
+        assert!(!s.insert(1));
+        assert_eq!(s.len(), 2);
+        assert!(s.contains(&1));
+        assert!(!s.contains(&3));
+        assert_eq!(s.get(&2), Some(&2));
+
+        assert!(s.remove(&1));
+        assert!(!s.remove(&1));
+        assert_eq!(s.take(&2), Some(2));
+        assert!(s.is_empty());
+
+        s.extend(0..10);
+        assert_eq!(sorted(s.iter()), (0..10).collect::<Vec<_>>());
+        let mut owned: Vec<_> = s.into_iter().collect();
+        owned.sort();
+        assert_eq!(owned, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn basic_ops_all_maps() {
+        basic_ops::<linear_probing::HashMap<i32, ()>>();
+        basic_ops::<coalesced::HashMap<i32, ()>>();
+        basic_ops::<quadratic_probing::HashMap<i32, ()>>();
+        basic_ops::<double_hashing::HashMap<i32, ()>>();
+        basic_ops::<hopscotch::HashMap<i32, ()>>();
+        basic_ops::<robin_hood::HashMap<i32, ()>>();
+        basic_ops::<swiss_table::HashMap<i32, ()>>();
+    }
+
+    #[test]
+    fn set_operations() {
+        let a: HashSet<i32> = [1, 2, 3, 4].into_iter().collect();
+        let b: HashSet<i32> = [3, 4, 5].into_iter().collect();
+
+        assert_eq!(sorted(a.union(&b)), [1, 2, 3, 4, 5]);
+        assert_eq!(sorted(a.intersection(&b)), [3, 4]);
+        assert_eq!(sorted(a.difference(&b)), [1, 2]);
+        assert_eq!(sorted(b.difference(&a)), [5]);
+        assert_eq!(sorted(a.symmetric_difference(&b)), [1, 2, 5]);
+
+        let c: HashSet<i32> = [3, 4].into_iter().collect();
+        assert!(c.is_subset(&a));
+        assert!(a.is_superset(&c));
+        assert!(!a.is_subset(&b));
+        assert!(!a.is_disjoint(&b));
+        assert!(c.is_disjoint(&[1, 2].into_iter().collect()));
+        assert_eq!(a, [4, 3, 2, 1].into_iter().collect());
+        assert_eq!(c.intersection(&b).count(), 2);
+        assert_eq!(format!("{c:?}").len(), "{3, 4}".len());
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const SET_SIZE: usize = 200;
+        #[cfg(miri)]
+        const SET_SIZE: usize = 20;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 500;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_btree_set(
+                a in proptest::collection::vec(0..300i32, 0..SET_SIZE),
+                b in proptest::collection::vec(0..300i32, 0..SET_SIZE),
+            ) {
+                let set_a: HashSet<i32, linear_probing::HashMap<i32, ()>> = a.iter().copied().collect();
+                let set_b: HashSet<i32, linear_probing::HashMap<i32, ()>> = b.iter().copied().collect();
+                let ref_a: BTreeSet<i32> = a.into_iter().collect();
+                let ref_b: BTreeSet<i32> = b.into_iter().collect();
+
+                assert_eq!(set_a.len(), ref_a.len());
+                assert_eq!(sorted(set_a.union(&set_b)), sorted(ref_a.union(&ref_b)));
+                assert_eq!(sorted(set_a.intersection(&set_b)), sorted(ref_a.intersection(&ref_b)));
+                assert_eq!(sorted(set_a.difference(&set_b)), sorted(ref_a.difference(&ref_b)));
+                assert_eq!(
+                    sorted(set_a.symmetric_difference(&set_b)),
+                    sorted(ref_a.symmetric_difference(&ref_b))
+                );
+                assert_eq!(set_a.is_subset(&set_b), ref_a.is_subset(&ref_b));
+                assert_eq!(set_a.is_disjoint(&set_b), ref_a.is_disjoint(&ref_b));
+                assert_eq!(set_a == set_b, ref_a == ref_b);
+            }
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! Map which stores a few pairs inline
+//!
+//! Up to `N` pairs are kept in an array inside the map and looked up by a
+//! linear scan, so small maps neither allocate nor hash the keys. Once the
+//! `N + 1`th pair is inserted all pairs are moved into a
+//! [`linear_probing::HashMap`] and the map stays there from then on.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::mem;
+use std::collections::hash_map::RandomState;
+
+use crate::open_addressing::linear_probing;
+
+pub struct SmallMap<K, V, const N: usize, S = RandomState> {
+    // INVARIANTS:
+    //  * if `spilled`, all pairs are in `map` and `len == 0`
+    //  * otherwise `inline[..len]` are `Some`, the rest of `inline` is `None`
+    //    and `map` is empty
+    //  * keys are unique
+    //
+    // `map` holds the hash builder, it doesn't allocate before spilling.
+    inline: [Option<(K, V)>; N],
+    len: usize,
+    map: linear_probing::HashMap<K, V, S>,
+    spilled: bool,
+}
+
+impl<K, V, const N: usize> Default for SmallMap<K, V, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, const N: usize> SmallMap<K, V, N> {
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V, const N: usize, S> SmallMap<K, V, N, S> {
+    /// Creates a new map which uses `hash_builder` to hash the keys once it
+    /// has spilled.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            inline: core::array::from_fn(|_| None),
+            len: 0,
+            map: linear_probing::HashMap::with_hasher(hash_builder),
+            spilled: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        if self.spilled {
+            self.map.len()
+        } else {
+            self.len
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the pairs have been moved into a hash map.
+    pub fn spilled(&self) -> bool {
+        self.spilled
+    }
+
+    /// Iterate over all key-value pairs in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        // one of the two is always empty
+        self.inline_pairs()
+            .iter()
+            .map(|p| {
+                let (k, v) = p.as_ref().unwrap();
+                (k, v)
+            })
+            .chain(self.map.iter())
+    }
+
+    /// Iterate over all key-value pairs with mutable references to the values.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> + '_ {
+        self.inline[..self.len]
+            .iter_mut()
+            .map(|p| {
+                let (k, v) = p.as_mut().unwrap();
+                (&*k, v)
+            })
+            .chain(self.map.iter_mut())
+    }
+
+    fn inline_pairs(&self) -> &[Option<(K, V)>] {
+        &self.inline[..self.len]
+    }
+
+    fn inline_position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq,
+    {
+        self.inline_pairs()
+            .iter()
+            .position(|p| p.as_ref().unwrap().0.borrow() == key)
+    }
+}
+
+impl<K, V, const N: usize, S> SmallMap<K, V, N, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if self.spilled {
+            return self.map.insert(key, value);
+        }
+
+        if let Some(i) = self.inline_position(&key) {
+            let (k, v) = self.inline[i].as_mut().unwrap();
+            return Some((mem::replace(k, key), mem::replace(v, value)));
+        }
+
+        if self.len < N {
+            self.inline[self.len] = Some((key, value));
+            self.len += 1;
+        } else {
+            self.spill();
+            self.map.insert(key, value);
+        }
+        None
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.spilled {
+            return self.map.get_key_value(key);
+        }
+
+        let i = self.inline_position(key)?;
+        let (k, v) = self.inline[i].as_ref().unwrap();
+        Some((k, v))
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.spilled {
+            return self.map.get_mut(key);
+        }
+
+        let i = self.inline_position(key)?;
+        self.inline[i].as_mut().map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    /// Removes the pair corresponding to the key.
+    ///
+    /// The map doesn't move back inline if it has spilled.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash,
+    {
+        if self.spilled {
+            return self.map.remove(key);
+        }
+
+        let i = self.inline_position(key)?;
+        // move the last pair into the hole to keep the pairs at the front
+        self.inline.swap(i, self.len - 1);
+        self.len -= 1;
+        self.inline[self.len].take()
+    }
+
+    /// Moves all inline pairs into the hash map.
+    fn spill(&mut self) {
+        debug_assert!(!self.spilled);
+        self.map.reserve(2 * self.len.max(1));
+        for p in &mut self.inline[..self.len] {
+            let (k, v) = p.take().unwrap();
+            self.map.insert(k, v);
+        }
+        self.len = 0;
+        self.spilled = true;
+    }
+}
+
+impl<K, V, const N: usize, S> Clone for SmallMap<K, V, N, S>
+where
+    K: Clone,
+    V: Clone,
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inline: self.inline.clone(),
+            len: self.len,
+            map: self.map.clone(),
+            spilled: self.spilled,
+        }
+    }
+}
+
+impl<K, V, const N: usize, S> fmt::Debug for SmallMap<K, V, N, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, const N: usize, S> Extend<(K, V)> for SmallMap<K, V, N, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K, V, const N: usize, S> FromIterator<(K, V)> for SmallMap<K, V, N, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::with_hasher(S::default());
+        map.extend(iter);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_get_remove_inline() {
+        let mut m = SmallMap::<_, _, 4>::new();
+        assert!(m.is_empty());
+        assert_eq!(m.get(&1), None);
+        assert_eq!(m.remove(&1), None);
+
+        for i in 0..4 {
+            assert_eq!(m.insert(i, i * 10), None);
+        }
+        assert_eq!(m.insert(2, 21), Some((2, 20)));
+        assert!(!m.spilled());
+        assert_eq!(m.len(), 4);
+
+        *m.get_mut(&3).unwrap() += 1;
+        assert_eq!(m.get_key_value(&3), Some((&3, &31)));
+        assert!(m.contains_key(&0));
+        assert!(!m.contains_key(&4));
+
+        // the last pair moves into the hole
+        assert_eq!(m.remove(&0), Some((0, 0)));
+        assert_eq!(m.remove(&0), None);
+        assert_eq!(m.len(), 3);
+        assert_eq!(m.inline_pairs()[0], Some((3, 31)));
+        assert!(m.inline[3].is_none());
+        assert_eq!(format!("{m:?}"), "{3: 31, 1: 10, 2: 21}");
+    }
+
+    #[test]
+    fn spill() {
+        let mut m: SmallMap<_, _, 2> = [(1, 10), (2, 20)].into_iter().collect();
+        assert!(!m.spilled());
+        m.insert(3, 30);
+        assert!(m.spilled());
+        assert_eq!(m.len(), 3);
+        assert!(m.inline.iter().all(Option::is_none));
+        for i in 1..=3 {
+            assert_eq!(m.get(&i), Some(&(i * 10)));
+        }
+
+        for (_, v) in m.iter_mut() {
+            *v += 1;
+        }
+        assert_eq!(m.insert(1, 12), Some((1, 11)));
+        assert_eq!(m.remove(&2), Some((2, 21)));
+        assert_eq!(m.remove(&3), Some((3, 31)));
+        assert_eq!(m.len(), 1);
+        // stays in the hash map
+        assert!(m.spilled());
+
+        let c = m.clone();
+        assert_eq!(c.iter().collect::<Vec<_>>(), [(&1, &12)]);
+    }
+
+    #[test]
+    fn zero_inline_capacity() {
+        let mut m = SmallMap::<_, _, 0>::new();
+        assert!(!m.spilled());
+        m.insert("a", 1);
+        assert!(m.spilled());
+        assert_eq!(m.get(&"a"), Some(&1));
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const OPS: usize = 1000;
+        #[cfg(miri)]
+        const OPS: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_std(
+                ops in proptest::collection::vec((any::<bool>(), 0..12i32), 0..OPS),
+            ) {
+                let mut m = SmallMap::<_, _, 8>::new();
+                let mut model = std::collections::HashMap::new();
+
+                for (is_insert, key) in ops {
+                    if is_insert {
+                        assert_eq!(m.insert(key, key * 2).map(|(_, v)| v), model.insert(key, key * 2));
+                    } else {
+                        assert_eq!(m.remove(&key), model.remove_entry(&key));
+                    }
+                    assert_eq!(m.len(), model.len());
+                }
+
+                for key in 0..12 {
+                    assert_eq!(m.get(&key), model.get(&key));
+                }
+                assert!(m.iter().all(|(k, v)| model.get(k) == Some(v)));
+            }
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use core::hint::black_box;
+use core::time::Duration;
+
+use criterion::measurement::Measurement;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use linked_list::{LinkedList, SinglyLinkedList, SkipListMap};
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use tree::RbTreeMap;
+
+macro_rules! select_measurement {
+    (walltime) => {
+        pub const MEASUREMENT_KIND: &str = "walltime";
+
+        pub fn create_measurement() -> impl ::criterion::measurement::Measurement {
+            ::criterion::measurement::WallTime
+        }
+    };
+}
+
+select_measurement!(walltime);
+
+const COUNTS: [usize; 4] = [100, 1000, 10_000, 100_000];
+
+fn push_pop_front<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("push_pop_front_{}", MEASUREMENT_KIND));
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $($list:tt)*) => {
+            g.bench_with_input(BenchmarkId::new($name, $count), &$count, |b, &count| {
+                b.iter(|| {
+                    let mut list = $($list)*::new();
+                    for x in 0..count {
+                        list.push_front(x);
+                    }
+                    while let Some(x) = list.pop_front() {
+                        black_box(x);
+                    }
+                })
+            });
+        };
+    }
+
+    for count in COUNTS {
+        bench!("std", count, std::collections::LinkedList);
+        bench!("doubly", count, LinkedList);
+        bench!("singly", count, SinglyLinkedList);
+    }
+}
+
+fn iter<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("iter_{}", MEASUREMENT_KIND));
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $($list:tt)*) => {
+            let mut list = $($list)*::new();
+            for x in 0..$count {
+                list.push_front(x);
+            }
+            g.bench_with_input(BenchmarkId::new($name, $count), &$count, |b, _| {
+                b.iter(|| list.iter().sum::<usize>())
+            });
+        };
+    }
+
+    for count in COUNTS {
+        bench!("std", count, std::collections::LinkedList);
+        bench!("doubly", count, LinkedList);
+        bench!("singly", count, SinglyLinkedList);
+    }
+}
+
+fn ordered_map<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("ordered_map_{}", MEASUREMENT_KIND));
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $keys:expr, $($map:tt)*) => {
+            g.bench_with_input(BenchmarkId::new(concat!($name, "_insert"), $count), &$keys, |b, keys| {
+                b.iter(|| {
+                    let mut map = $($map)*::new();
+                    for &k in keys {
+                        map.insert(k, k);
+                    }
+                    map
+                })
+            });
+
+            let mut map = $($map)*::new();
+            for &k in &$keys {
+                map.insert(k, k);
+            }
+            g.bench_with_input(BenchmarkId::new(concat!($name, "_get"), $count), &$keys, |b, keys| {
+                b.iter(|| {
+                    for k in keys {
+                        black_box(map.get(k));
+                    }
+                })
+            });
+        };
+    }
+
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(42);
+    for count in COUNTS {
+        let mut keys = (0..count).collect::<Vec<_>>();
+        keys.shuffle(&mut rng);
+
+        bench!("btree_map", count, keys, std::collections::BTreeMap);
+        bench!("rbt", count, keys, RbTreeMap);
+        bench!("skip_list", count, keys, SkipListMap);
+    }
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default()
+        .measurement_time(Duration::from_secs(5))
+        .warm_up_time(Duration::from_millis(1000))
+        .with_measurement(create_measurement())
+        ;
+    targets = push_pop_front, iter, ordered_map
+);
+criterion_main!(benches);
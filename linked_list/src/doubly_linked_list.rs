@@ -1,14 +1,20 @@
+use core::cmp::Ordering;
 use core::marker::PhantomData;
 use core::ptr::NonNull;
-use core::{fmt, ptr};
+#[cfg(debug_assertions)]
+use core::sync::atomic::{self, AtomicUsize};
+use core::{fmt, mem};
 
-use self::iter::{Iter, IterMut};
+pub use self::iter::{ExtractIf, IntoIter, Iter, IterMut};
 
-struct LinkedList<T> {
+pub struct LinkedList<T> {
     // Head and tail can only be None both at once (when count == 0).
     // If count == 1 both point to the same item.
     head_tail: Option<HeadTail<T>>,
     count: usize,
+    /// Unique id of this list, stored in every node to check handles
+    #[cfg(debug_assertions)]
+    id: usize,
     marker: PhantomData<T>,
 }
 
@@ -52,6 +58,12 @@ where
     }
 }
 
+impl<T> Default for LinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Drop for LinkedList<T> {
     fn drop(&mut self) {
         /// Guard in case `T::drop` panics.
@@ -95,6 +107,16 @@ struct Node<T> {
     data: T,
     next: Option<NonNull<Node<T>>>,
     prev: Option<NonNull<Node<T>>>,
+    /// Id of the list this node is in
+    #[cfg(debug_assertions)]
+    owner: usize,
+}
+
+/// Returns a new unique list id.
+#[cfg(debug_assertions)]
+fn next_list_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    NEXT_ID.fetch_add(1, atomic::Ordering::Relaxed)
 }
 
 impl<T> LinkedList<T> {
@@ -109,6 +131,8 @@ impl<T> LinkedList<T> {
         Self {
             head_tail: None,
             count: 0,
+            #[cfg(debug_assertions)]
+            id: next_list_id(),
             marker: PhantomData,
         }
     }
@@ -117,6 +141,10 @@ impl<T> LinkedList<T> {
         self.count
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
     fn tail_ptr(&self) -> Option<NonNull<Node<T>>> {
         self.head_tail.as_ref().map(|a| a.tail)
     }
@@ -142,10 +170,23 @@ impl<T> LinkedList<T> {
     }
 
     pub fn push_back(&mut self, val: T) {
+        self.push_back_node(val);
+    }
+
+    /// Push `val` to the back of the list and return a handle to it.
+    ///
+    /// See [`NodeHandle`].
+    pub fn push_back_handle(&mut self, val: T) -> NodeHandle<T> {
+        NodeHandle::new(self.push_back_node(val))
+    }
+
+    fn push_back_node(&mut self, val: T) -> NonNull<Node<T>> {
         let new = Node {
             data: val,
             next: None,
             prev: self.tail_ptr(),
+            #[cfg(debug_assertions)]
+            owner: self.id,
         };
 
         let new = non_null_from_box(Box::new(new));
@@ -168,13 +209,27 @@ impl<T> LinkedList<T> {
         }
 
         self.count += 1;
+        new
     }
 
     pub fn push_front(&mut self, val: T) {
+        self.push_front_node(val);
+    }
+
+    /// Push `val` to the front of the list and return a handle to it.
+    ///
+    /// See [`NodeHandle`].
+    pub fn push_front_handle(&mut self, val: T) -> NodeHandle<T> {
+        NodeHandle::new(self.push_front_node(val))
+    }
+
+    fn push_front_node(&mut self, val: T) -> NonNull<Node<T>> {
         let new = Node {
             data: val,
             next: self.head_ptr(),
             prev: None,
+            #[cfg(debug_assertions)]
+            owner: self.id,
         };
         let new = non_null_from_box(Box::new(new));
 
@@ -197,6 +252,7 @@ impl<T> LinkedList<T> {
         }
 
         self.count += 1;
+        new
     }
 
     pub fn insert(&mut self, index: usize, val: T) -> Result<(), T> {
@@ -221,6 +277,8 @@ impl<T> LinkedList<T> {
                     data: val,
                     next: Some(current),
                     prev: Some(prev),
+                    #[cfg(debug_assertions)]
+                    owner: self.id,
                 };
                 let new = non_null_from_box(Box::new(new));
 
@@ -244,7 +302,9 @@ impl<T> LinkedList<T> {
     unsafe fn remove_node(&mut self, val: NonNull<Node<T>>) -> T {
         // SAFETY: all nodes are constructed from Box::into_raw
         let val = unsafe { Box::from_raw(val.as_ptr()) };
-        let Node { data, next, prev } = *val;
+        let Node {
+            data, next, prev, ..
+        } = *val;
         match (prev, next) {
             (None, None) => {
                 // only item
@@ -360,40 +420,610 @@ impl<T> LinkedList<T> {
             .map(|ht| unsafe { &mut (*ht.tail.as_ptr()).data })
     }
 
+    /// Move all items from `other` to the back of `self`, leaving `other` empty.
+    ///
+    /// Runs in O(1), only the links at the boundary are updated. In debug
+    /// builds the moved nodes are also relabeled as ours, which is O(other.len()).
+    pub fn append(&mut self, other: &mut Self) {
+        let Some(other_ht) = other.head_tail.take() else {
+            return;
+        };
+        let other_count = mem::replace(&mut other.count, 0);
+        #[cfg(debug_assertions)]
+        self.set_owner(Some(other_ht.head));
+
+        match &mut self.head_tail {
+            Some(HeadTail { tail, .. }) => {
+                // SAFETY:
+                //  * &mut self and &mut other invalidate any previously out given references
+                //    (hence no-one else can have reference to `tail` or `other_ht.head`)
+                //  * all node pointers are valid to deref (see safety doc on top of this impl block)
+                unsafe {
+                    (*tail.as_ptr()).next = Some(other_ht.head);
+                    (*other_ht.head.as_ptr()).prev = Some(*tail);
+                }
+                *tail = other_ht.tail;
+            }
+            None => {
+                debug_assert_eq!(self.count, 0);
+                self.head_tail = Some(other_ht);
+            }
+        }
+
+        self.count += other_count;
+    }
+
+    /// Split the list into two at the given index.
+    ///
+    /// Returns a new list containing items `[at, len)`, `self` is left with
+    /// items `[0, at)`.
+    ///
+    /// # Panics
+    ///
+    /// If `at > self.len()`.
+    pub fn split_off(&mut self, at: usize) -> Self {
+        assert!(
+            at <= self.count,
+            "cannot split off at index `{at}` of a list of length `{}`",
+            self.count
+        );
+
+        if at == 0 {
+            return mem::take(self);
+        }
+
+        let Some(new_head) = self.get_node(at) else {
+            // at == self.count
+            return Self::new();
+        };
+        // SAFETY:
+        //  * &mut self invalidates any previously out given references
+        //    (hence no-one else can have reference to `new_head` and `new_tail`)
+        //  * all node pointers are valid to deref (see safety doc on top of this impl block)
+        let new_tail = unsafe {
+            (*new_head.as_ptr()).prev.take().unwrap_or_else(|| {
+                panic!("expected a node at `index = {at} > 0` to have a previous node")
+            })
+        };
+        unsafe { (*new_tail.as_ptr()).next = None };
+
+        let old_tail = mem::replace(
+            &mut self
+                .head_tail
+                .as_mut()
+                .expect("expected `head_tail` to be `Some(..)` for non-empty list")
+                .tail,
+            new_tail,
+        );
+        let other_count = self.count - at;
+        self.count = at;
+
+        let mut other = Self::new();
+        other.head_tail = Some(HeadTail {
+            head: new_head,
+            tail: old_tail,
+        });
+        other.count = other_count;
+        #[cfg(debug_assertions)]
+        other.set_owner(Some(new_head));
+        other
+    }
+
+    /// Sets the owner of the nodes from `maybe_current` to the end to us.
+    #[cfg(debug_assertions)]
+    fn set_owner(&mut self, mut maybe_current: Option<NonNull<Node<T>>>) {
+        while let Some(current) = maybe_current {
+            // SAFETY:
+            //  * caller has `&mut` access to the list containing the nodes
+            //    (hence no-one else can have reference to `current`)
+            //  * all node pointers are valid to deref (see safety doc on top of this impl block)
+            unsafe {
+                (*current.as_ptr()).owner = self.id;
+                maybe_current = (*current.as_ptr()).next;
+            }
+        }
+    }
+
+    /// Returns an iterator that removes and yields all items for which `pred` returns `true`.
+    ///
+    /// Items are removed lazily as the iterator is advanced. If the iterator
+    /// is dropped before it's exhausted, the remaining items are kept in the list.
+    /// If `pred` panics, the list is left in a valid state with every not
+    /// yet yielded item still in it.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, T, F>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        ExtractIf::new(self, pred)
+    }
+
+    /// Keep only the items for which `f` returns `true`, removing others in a single pass.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.extract_if(|item| !f(item)).for_each(drop);
+    }
+
+    /// Reverse the order of items in place.
+    ///
+    /// Only the `next` and `prev` pointers of each node are swapped, no items are moved.
+    pub fn reverse(&mut self) {
+        let Some(ht) = self.head_tail.as_mut() else {
+            return;
+        };
+
+        let mut maybe_current = Some(ht.head);
+        while let Some(current) = maybe_current {
+            // SAFETY:
+            //  * &mut self invalidates any previously out given references
+            //    (hence no-one else can have reference to `current`)
+            //  * all node pointers are valid to deref (see safety doc on top of this impl block)
+            let node = unsafe { &mut *current.as_ptr() };
+            mem::swap(&mut node.next, &mut node.prev);
+            // old next is now in prev
+            maybe_current = node.prev;
+        }
+
+        mem::swap(&mut ht.head, &mut ht.tail);
+    }
+
+    /// Returns `true` if the list contains an item equal to `val`.
+    pub fn contains(&self, val: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.iter().any(|item| item == val)
+    }
+
+    /// Returns the first item for which `pred` returns `true`.
+    pub fn find<F>(&self, mut pred: F) -> Option<&T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.iter().find(|item| pred(item))
+    }
+
+    /// Returns the index of the first item for which `pred` returns `true`.
+    pub fn position<F>(&self, pred: F) -> Option<usize>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.iter().position(pred)
+    }
+
+    /// Returns a reference to the item pointed to by `handle`.
+    ///
+    /// # SAFETY
+    ///
+    /// * `handle` must point to a node which is currently in this list,
+    ///   that is it was returned by this list (or a list which has since been
+    ///   appended to this one) and the node hasn't been removed since.
+    ///
+    /// This is checked in debug builds.
+    pub unsafe fn get_handle(&self, handle: NodeHandle<T>) -> &T {
+        self.debug_assert_owns(handle);
+        // SAFETY:
+        //  * returned reference is bound to the borrow of self
+        //    since we own the data, it must be alive
+        //  * caller guarantees that `handle` points to a valid node in our list
+        unsafe { &(*handle.node.as_ptr()).data }
+    }
+
+    /// Returns a mutable reference to the item pointed to by `handle`.
+    ///
+    /// # SAFETY
+    ///
+    /// * see [`Self::get_handle`]
+    pub unsafe fn get_handle_mut(&mut self, handle: NodeHandle<T>) -> &mut T {
+        self.debug_assert_owns(handle);
+        // SAFETY:
+        //  * see `get_handle`
+        //  * Any previously returned references are invalidated by taking &mut self
+        unsafe { &mut (*handle.node.as_ptr()).data }
+    }
+
+    /// Removes the node pointed to by `handle` from the list in O(1) and returns its item.
+    ///
+    /// # SAFETY
+    ///
+    /// * see [`Self::get_handle`]
+    pub unsafe fn remove_handle(&mut self, handle: NodeHandle<T>) -> T {
+        self.debug_assert_owns(handle);
+        // SAFETY: caller guarantees that `handle` points to a valid node in our list
+        unsafe { self.remove_node(handle.node) }
+    }
+
+    /// Moves the node pointed to by `handle` to the back of the list in O(1).
+    ///
+    /// # SAFETY
+    ///
+    /// * see [`Self::get_handle`]
+    pub unsafe fn move_to_back_handle(&mut self, handle: NodeHandle<T>) {
+        self.debug_assert_owns(handle);
+        let node = handle.node;
+        let Some(HeadTail { tail, .. }) = self.head_tail.as_mut() else {
+            unreachable!("list owning a node cannot be empty")
+        };
+        if *tail == node {
+            return;
+        }
+        let old_tail = mem::replace(tail, node);
+
+        // SAFETY:
+        //  * caller guarantees that `handle` points to a valid node in our list
+        //  * &mut self invalidates any previously out given references
+        //    (hence no-one else can have reference to `node`, `next`, `prev` or `old_tail`)
+        //  * all node pointers are valid to deref (see safety doc on top of this impl block)
+        unsafe {
+            // `node` is not the tail, thus it must have a next node
+            let next = (*node.as_ptr())
+                .next
+                .take()
+                .expect("expected a node which is not the tail to have a next node");
+            let prev = (*node.as_ptr()).prev;
+            (*next.as_ptr()).prev = prev;
+            match prev {
+                Some(prev) => (*prev.as_ptr()).next = Some(next),
+                None => self.set_head(next),
+            }
+
+            (*node.as_ptr()).prev = Some(old_tail);
+            (*old_tail.as_ptr()).next = Some(node);
+        }
+    }
+
+    /// Moves the node pointed to by `handle` to the front of the list in O(1).
+    ///
+    /// # SAFETY
+    ///
+    /// * see [`Self::get_handle`]
+    pub unsafe fn move_to_front_handle(&mut self, handle: NodeHandle<T>) {
+        self.debug_assert_owns(handle);
+        let node = handle.node;
+        let Some(HeadTail { head, .. }) = self.head_tail.as_mut() else {
+            unreachable!("list owning a node cannot be empty")
+        };
+        if *head == node {
+            return;
+        }
+        let old_head = mem::replace(head, node);
+
+        // SAFETY: see `move_to_back_handle`
+        unsafe {
+            // `node` is not the head, thus it must have a prev node
+            let prev = (*node.as_ptr())
+                .prev
+                .take()
+                .expect("expected a node which is not the head to have a prev node");
+            let next = (*node.as_ptr()).next;
+            (*prev.as_ptr()).next = next;
+            match next {
+                Some(next) => (*next.as_ptr()).prev = Some(prev),
+                None => self.set_tail(prev),
+            }
+
+            (*node.as_ptr()).next = Some(old_head);
+            (*old_head.as_ptr()).prev = Some(node);
+        }
+    }
+
+    /// In debug builds, check that `handle` points to one of our nodes.
+    ///
+    /// Compares the owner stored in the node to our id in O(1). This catches
+    /// handles to nodes of other lists, but not handles to removed nodes,
+    /// those cannot be read at all.
+    #[inline]
+    fn debug_assert_owns(&self, handle: NodeHandle<T>) {
+        #[cfg(debug_assertions)]
+        {
+            // SAFETY: the handle ops require `handle` to point to a node in
+            //  this list, this check is for handles to nodes of other lists
+            //  which are alive and valid to deref as well
+            let owner = unsafe { (*handle.node.as_ptr()).owner };
+            assert_eq!(
+                owner, self.id,
+                "`NodeHandle` doesn't belong to this `LinkedList`"
+            );
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = handle;
+    }
+
+    /// Sort the list in ascending order.
+    ///
+    /// See [`Self::sort_by`].
+    pub fn sort(&mut self)
+    where
+        T: Ord,
+    {
+        self.sort_by(T::cmp)
+    }
+
+    /// Sort the list with a comparator function.
+    ///
+    /// This is a stable bottom-up merge sort which only relinks the nodes,
+    /// no items are moved and no additional memory is allocated.
+    /// Runs in O(n log n).
+    ///
+    /// If `cmp` panics, all items remain in the list but their order is unspecified.
+    pub fn sort_by<F>(&mut self, mut cmp: F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        if self.count < 2 {
+            return;
+        }
+
+        let rest = self.head_tail.take().map(|ht| ht.head);
+        let mut state = SortGuard {
+            list: self,
+            rest,
+            bins: [None; SORT_BINS],
+            a: None,
+            b: None,
+            out: None,
+        };
+        state.sort(&mut cmp);
+        // `state` relinks the sorted nodes back into `self` on drop
+    }
+
+    /// Returns the node at `index`.
+    ///
+    /// Walks from whichever end of the list is nearer to `index`,
+    /// so at most `self.count / 2` links are followed.
     fn get_node(&self, index: usize) -> Option<NonNull<Node<T>>> {
         if index >= self.count {
             return None;
         }
 
-        // Head must be Some if index < self.count (0 < index < 0 cannot be true)
-        let mut current = self
-            .head_ptr()
-            .expect("expected `head` to be `Some(..)` for non-empty list");
-        for i in 0..index {
-            // next must be Some since index < self.count and loop will terminate
-            // after we set current = tail
-            // SAFETY: all node pointers are valid to deref (see safety doc on top of this impl block)
-            current = unsafe {
-                (*current.as_ptr())
-                    .next
-                    .unwrap_or_else(|| {
-                        panic!("expected a node at index `{i}` to have a next pointer since there are `{}` items in the list", self.len())
+        // Head and tail must be Some if index < self.count (0 < index < 0 cannot be true)
+        let ht = self
+            .head_tail
+            .as_ref()
+            .expect("expected `head_tail` to be `Some(..)` for non-empty list");
+
+        if index <= self.count / 2 {
+            let mut current = ht.head;
+            for i in 0..index {
+                // next must be Some since index < self.count and loop will terminate
+                // after we set current = tail
+                // SAFETY: all node pointers are valid to deref (see safety doc on top of this impl block)
+                current = unsafe {
+                    (*current.as_ptr())
+                        .next
+                        .unwrap_or_else(|| {
+                            panic!("expected a node at index `{i}` to have a next pointer since there are `{}` items in the list", self.len())
+                        })
+                };
+            }
+            Some(current)
+        } else {
+            let mut current = ht.tail;
+            for i in (index + 1..self.count).rev() {
+                // prev must be Some since index >= 0 and loop will terminate
+                // after we set current = head
+                // SAFETY: all node pointers are valid to deref (see safety doc on top of this impl block)
+                current = unsafe {
+                    (*current.as_ptr()).prev.unwrap_or_else(|| {
+                        panic!("expected a node at index `{i}` to have a prev pointer")
                     })
-            };
+                };
+            }
+            Some(current)
         }
-
-        Some(current)
     }
 
-    fn iter(&self) -> Iter<'_, T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter::new(self)
     }
 
-    fn iter_mut(&mut self) -> IterMut<'_, T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut::new(self)
     }
 }
 
+impl<T> IntoIterator for LinkedList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a LinkedList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut LinkedList<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Opaque handle to a node in a `LinkedList`, allows O(1) access and removal of that node.
+///
+/// Handles are only valid while the node is in the list. Nodes never move in
+/// memory so a handle stays valid through any other operation on the list,
+/// including sorting and appending the list to another list.
+pub struct NodeHandle<T> {
+    node: NonNull<Node<T>>,
+}
+
+impl<T> NodeHandle<T> {
+    fn new(node: NonNull<Node<T>>) -> Self {
+        Self { node }
+    }
+}
+
+impl<T> Clone for NodeHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeHandle<T> {}
+
+impl<T> PartialEq for NodeHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T> Eq for NodeHandle<T> {}
+
+impl<T> core::hash::Hash for NodeHandle<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.node.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for NodeHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NodeHandle").field(&self.node).finish()
+    }
+}
+
+/// Number of merge bins in `LinkedList::sort_by`, enough for `usize::MAX` nodes.
+const SORT_BINS: usize = usize::BITS as usize;
+
+/// State of `LinkedList::sort_by`.
+///
+/// During the sort the nodes are only linked forward by `next` pointers.
+/// At any point every node is in exactly one of the null-terminated lists stored in here.
+/// On drop all of these are relinked (restoring `prev` pointers) back into `list`,
+/// which also covers the case where the comparator panics.
+struct SortGuard<'a, T> {
+    list: &'a mut LinkedList<T>,
+    /// Not yet sorted nodes
+    rest: Option<NonNull<Node<T>>>,
+    /// `bins[i]` is either empty or holds a sorted list of `2^i` nodes.
+    /// Higher bins always contain items which were earlier in the original list.
+    bins: [Option<NonNull<Node<T>>>; SORT_BINS],
+    /// Sorted lists being merged, items in `a` were earlier in the original list than items in `b`
+    a: Option<NonNull<Node<T>>>,
+    b: Option<NonNull<Node<T>>>,
+    /// Result of the last merge
+    out: Option<NonNull<Node<T>>>,
+}
+
+impl<T> SortGuard<'_, T> {
+    fn sort<F>(&mut self, cmp: &mut F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        while let Some(node) = self.rest {
+            // SAFETY:
+            //  * all node pointers are valid to deref (see safety doc on top of `impl LinkedList`)
+            //  * we hold `&mut LinkedList`, no-one else can have references into it
+            unsafe {
+                self.rest = (*node.as_ptr()).next.take();
+            }
+            self.out = Some(node);
+
+            // carry `self.out` up until we find an empty bin
+            let mut i = 0;
+            while self.bins[i].is_some() {
+                self.a = self.bins[i].take();
+                self.b = self.out.take();
+                self.merge(cmp);
+                i += 1;
+            }
+            self.bins[i] = self.out.take();
+        }
+
+        for i in 0..SORT_BINS {
+            if self.bins[i].is_some() {
+                self.a = self.bins[i].take();
+                self.b = self.out.take();
+                self.merge(cmp);
+            }
+        }
+    }
+
+    /// Merge sorted lists `self.a` and `self.b` into `self.out`.
+    fn merge<F>(&mut self, cmp: &mut F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        debug_assert!(self.out.is_none());
+        let mut out_tail: Option<NonNull<Node<T>>> = None;
+
+        while let (Some(a), Some(b)) = (self.a, self.b) {
+            // SAFETY:
+            //  * all node pointers are valid to deref (see safety doc on top of `impl LinkedList`)
+            //  * we hold `&mut LinkedList`, no-one else can have references into it
+            //  * nodes are moved between lists only after `cmp` returns,
+            //    if it panics every node is still in one of our lists
+            let take_b = unsafe { cmp(&(*b.as_ptr()).data, &(*a.as_ptr()).data) }.is_lt();
+            let node = if take_b {
+                self.b = unsafe { (*b.as_ptr()).next.take() };
+                b
+            } else {
+                self.a = unsafe { (*a.as_ptr()).next.take() };
+                a
+            };
+
+            match out_tail {
+                Some(tail) => unsafe { (*tail.as_ptr()).next = Some(node) },
+                None => self.out = Some(node),
+            }
+            out_tail = Some(node);
+        }
+
+        let remaining = self.a.take().or(self.b.take());
+        match out_tail {
+            // SAFETY: see above
+            Some(tail) => unsafe { (*tail.as_ptr()).next = remaining },
+            None => self.out = remaining,
+        }
+    }
+}
+
+impl<T> Drop for SortGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut head: Option<NonNull<Node<T>>> = None;
+        let mut tail: Option<NonNull<Node<T>>> = None;
+
+        let lists = [
+            self.out.take(),
+            self.a.take(),
+            self.b.take(),
+            self.rest.take(),
+        ]
+        .into_iter()
+        .chain(self.bins.iter_mut().rev().map(Option::take));
+        for list in lists {
+            let mut current = list;
+            while let Some(node) = current {
+                // SAFETY:
+                //  * all node pointers are valid to deref (see safety doc on top of `impl LinkedList`)
+                //  * we hold `&mut LinkedList`, no-one else can have references into it
+                unsafe {
+                    current = (*node.as_ptr()).next.take();
+                    (*node.as_ptr()).prev = tail;
+                    match tail {
+                        Some(tail) => (*tail.as_ptr()).next = Some(node),
+                        None => head = Some(node),
+                    }
+                }
+                tail = Some(node);
+            }
+        }
+
+        self.list.head_tail = head.zip(tail).map(|(head, tail)| HeadTail { head, tail });
+    }
+}
+
 fn non_null_from_box<T>(val: Box<T>) -> NonNull<T> {
     // SAFETY: Box::into_raw returns properly aligned and non-null pointer
     unsafe { NonNull::new_unchecked(Box::into_raw(val)) }
@@ -403,7 +1033,11 @@ mod iter {
     use super::*;
 
     pub struct Iter<'a, T> {
-        node: Option<NonNull<Node<T>>>,
+        // Both cursors are valid only while `len > 0`. They are allowed to
+        // cross each other since we stop as soon as `len` reaches 0.
+        head: Option<NonNull<Node<T>>>,
+        tail: Option<NonNull<Node<T>>>,
+        len: usize,
         marker: PhantomData<&'a T>,
     }
 
@@ -414,7 +1048,9 @@ mod iter {
             //   as the list owns the items they must remain live for 'a
             //  * invariants of `LinkedList` hold here too, see the comment on top of LinkedList impl block
             Self {
-                node: list.head_ptr(),
+                head: list.head_ptr(),
+                tail: list.tail_ptr(),
+                len: list.len(),
                 marker: PhantomData,
             }
         }
@@ -424,25 +1060,53 @@ mod iter {
         type Item = &'a T;
 
         fn next(&mut self) -> Option<Self::Item> {
-            match self.node {
-                Some(ptr) => {
-                    // SAFETY:
-                    //  * all node pointer are valid to dereference because they are from `LinkedList`
-                    //   (see the safety comment of top of `impl LinkedList` block)
-                    let data = unsafe { &(*ptr.as_ptr()).data };
-                    self.node = unsafe { (*ptr.as_ptr()).next };
-
-                    Some(data)
-                }
-                None => None,
+            if self.len == 0 {
+                return None;
             }
+
+            self.head.map(|ptr| {
+                // SAFETY:
+                //  * all node pointer are valid to dereference because they are from `LinkedList`
+                //   (see the safety comment of top of `impl LinkedList` block)
+                //  * `self.len > 0` thus we haven't yet yielded `ptr` from either end
+                let data = unsafe { &(*ptr.as_ptr()).data };
+                self.head = unsafe { (*ptr.as_ptr()).next };
+                self.len -= 1;
+
+                data
+            })
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
         }
     }
 
+    impl<T> DoubleEndedIterator for Iter<'_, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+
+            self.tail.map(|ptr| {
+                // SAFETY: see `Iter::next`
+                let data = unsafe { &(*ptr.as_ptr()).data };
+                self.tail = unsafe { (*ptr.as_ptr()).prev };
+                self.len -= 1;
+
+                data
+            })
+        }
+    }
+
+    impl<T> ExactSizeIterator for Iter<'_, T> {}
+
     impl<T> Clone for Iter<'_, T> {
         fn clone(&self) -> Self {
             Self {
-                node: self.node,
+                head: self.head,
+                tail: self.tail,
+                len: self.len,
                 marker: self.marker,
             }
         }
@@ -458,7 +1122,10 @@ mod iter {
     }
 
     pub struct IterMut<'a, T> {
-        node: Option<NonNull<Node<T>>>,
+        // See `Iter`
+        head: Option<NonNull<Node<T>>>,
+        tail: Option<NonNull<Node<T>>>,
+        len: usize,
         marker: PhantomData<&'a mut T>,
     }
 
@@ -471,7 +1138,9 @@ mod iter {
             //  * taking `LinkedList` by &mut will invalidate all previously returned
             //    references by the list since they are all bound to borrow of list
             Self {
-                node: list.head_ptr(),
+                head: list.head_ptr(),
+                tail: list.tail_ptr(),
+                len: list.len(),
                 marker: PhantomData,
             }
         }
@@ -481,21 +1150,140 @@ mod iter {
         type Item = &'a mut T;
 
         fn next(&mut self) -> Option<Self::Item> {
-            match self.node {
-                Some(ptr) => {
-                    // SAFETY:
-                    //  * all node pointer are valid to dereference because they are from `LinkedList`
-                    //   (see the safety comment of top of `impl LinkedList` block)
-                    //  * all nodes in `LinkedList` point to different nodes,
-                    //    thus we cannot return multiple unique references to same data
-                    let ptr = ptr.as_ptr();
-                    let data = unsafe { &mut (*ptr).data };
-                    self.node = unsafe { (*ptr).next };
-
-                    Some(data)
+            if self.len == 0 {
+                return None;
+            }
+
+            self.head.map(|ptr| {
+                // SAFETY:
+                //  * all node pointer are valid to dereference because they are from `LinkedList`
+                //   (see the safety comment of top of `impl LinkedList` block)
+                //  * all nodes in `LinkedList` point to different nodes and
+                //    `self.len` stops us before the cursors can cross,
+                //    thus we cannot return multiple unique references to same data
+                let ptr = ptr.as_ptr();
+                let data = unsafe { &mut (*ptr).data };
+                self.head = unsafe { (*ptr).next };
+                self.len -= 1;
+
+                data
+            })
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.len, Some(self.len))
+        }
+    }
+
+    impl<T> DoubleEndedIterator for IterMut<'_, T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            if self.len == 0 {
+                return None;
+            }
+
+            self.tail.map(|ptr| {
+                // SAFETY: see `IterMut::next`
+                let ptr = ptr.as_ptr();
+                let data = unsafe { &mut (*ptr).data };
+                self.tail = unsafe { (*ptr).prev };
+                self.len -= 1;
+
+                data
+            })
+        }
+    }
+
+    impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+    pub struct ExtractIf<'a, T, F> {
+        list: &'a mut LinkedList<T>,
+        // Next node to test
+        node: Option<NonNull<Node<T>>>,
+        pred: F,
+    }
+
+    impl<'a, T, F> ExtractIf<'a, T, F> {
+        pub(super) fn new(list: &'a mut LinkedList<T>, pred: F) -> Self {
+            Self {
+                node: list.head_ptr(),
+                list,
+                pred,
+            }
+        }
+    }
+
+    impl<T, F> Iterator for ExtractIf<'_, T, F>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        type Item = T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while let Some(current) = self.node {
+                // SAFETY:
+                //  * all node pointer are valid to dereference because they are from `LinkedList`
+                //   (see the safety comment of top of `impl LinkedList` block)
+                //  * we hold `&mut LinkedList` so no-one else can have references to the data
+                //
+                // Advance before calling the predicate, so that `current` may be
+                // removed below. If the predicate panics, the list hasn't been touched yet.
+                self.node = unsafe { (*current.as_ptr()).next };
+                let data = unsafe { &mut (*current.as_ptr()).data };
+                if (self.pred)(data) {
+                    // SAFETY: current is a valid node in our list
+                    return Some(unsafe { self.list.remove_node(current) });
                 }
-                None => None,
             }
+
+            None
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (0, Some(self.list.len()))
+        }
+    }
+
+    /// Owning iterator over the items of a `LinkedList`.
+    ///
+    /// Each node is freed as soon as its item is yielded. If the iterator is
+    /// dropped before it's exhausted, the remaining items are dropped by the
+    /// inner list.
+    pub struct IntoIter<T> {
+        list: LinkedList<T>,
+    }
+
+    impl<T> IntoIter<T> {
+        pub(super) fn new(list: LinkedList<T>) -> Self {
+            Self { list }
+        }
+    }
+
+    impl<T> Iterator for IntoIter<T> {
+        type Item = T;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.list.pop_front()
+        }
+
+        fn size_hint(&self) -> (usize, Option<usize>) {
+            (self.list.len(), Some(self.list.len()))
+        }
+    }
+
+    impl<T> DoubleEndedIterator for IntoIter<T> {
+        fn next_back(&mut self) -> Option<Self::Item> {
+            self.list.pop_back()
+        }
+    }
+
+    impl<T> ExactSizeIterator for IntoIter<T> {}
+
+    impl<T> fmt::Debug for IntoIter<T>
+    where
+        T: fmt::Debug,
+    {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_tuple("IntoIter").field(&self.list.iter()).finish()
         }
     }
 }
@@ -567,6 +1355,398 @@ mod tests {
         assert_eq!(vals, [&9, &11, &8, &5, &6]);
     }
 
+    #[test]
+    fn iters_rev() {
+        let mut ll = LinkedList::new();
+        assert_eq!(ll.iter().next_back(), None);
+
+        ll.push_back(5);
+        ll.push_back(6);
+        ll.push_front(8);
+        ll.push_front(9);
+
+        let vals: Vec<_> = ll.iter().rev().collect();
+        assert_eq!(vals, [&6, &5, &8, &9]);
+
+        ll.iter_mut().rev().for_each(|x| *x *= 10);
+        let vals: Vec<_> = ll.iter_mut().rev().collect();
+        assert_eq!(vals, [&60, &50, &80, &90]);
+    }
+
+    #[test]
+    fn iters_meet_in_middle() {
+        let mut ll = LinkedList::new();
+        for i in 0..5 {
+            ll.push_back(i);
+        }
+
+        let mut iter = ll.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&3));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next_back(), Some(&2));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        let mut iter = ll.iter_mut();
+        assert_eq!(iter.next_back(), Some(&mut 4));
+        assert_eq!(iter.next(), Some(&mut 0));
+        assert_eq!(iter.next_back(), Some(&mut 3));
+        assert_eq!(iter.next_back(), Some(&mut 2));
+        assert_eq!(iter.next_back(), Some(&mut 1));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut ll = LinkedList::new();
+        for i in 0..5 {
+            ll.push_back(i.to_string());
+        }
+
+        let mut iter = ll.into_iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next().as_deref(), Some("0"));
+        assert_eq!(iter.next_back().as_deref(), Some("4"));
+        let rest: Vec<_> = iter.collect();
+        assert_eq!(rest, ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn into_iter_drop_remaining() {
+        use std::rc::Rc;
+
+        let item = Rc::new(());
+        let mut ll = LinkedList::new();
+        for _ in 0..5 {
+            ll.push_back(Rc::clone(&item));
+        }
+
+        let mut iter = ll.into_iter();
+        iter.next();
+        iter.next_back();
+        assert_eq!(Rc::strong_count(&item), 4);
+        drop(iter);
+        assert_eq!(Rc::strong_count(&item), 1);
+    }
+
+    fn list_from<T>(items: impl IntoIterator<Item = T>) -> LinkedList<T> {
+        let mut ll = LinkedList::new();
+        for item in items {
+            ll.push_back(item);
+        }
+        ll
+    }
+
+    fn assert_links<T: fmt::Debug + PartialEq>(ll: &LinkedList<T>, expected: &[T]) {
+        assert_eq!(ll.len(), expected.len());
+        assert_eq!(
+            ll.iter().collect::<Vec<_>>(),
+            expected.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            ll.iter().rev().collect::<Vec<_>>(),
+            expected.iter().rev().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn append() {
+        let mut a = list_from([1, 2, 3]);
+        let mut b = list_from([4, 5]);
+        a.append(&mut b);
+        assert_links(&a, &[1, 2, 3, 4, 5]);
+        assert_links(&b, &[]);
+
+        // empty other
+        a.append(&mut b);
+        assert_links(&a, &[1, 2, 3, 4, 5]);
+
+        // empty self
+        b.append(&mut a);
+        assert_links(&b, &[1, 2, 3, 4, 5]);
+        assert_links(&a, &[]);
+
+        b.push_back(6);
+        b.push_front(0);
+        assert_links(&b, &[0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn split_off() {
+        let mut a = list_from([1, 2, 3, 4, 5]);
+        let b = a.split_off(2);
+        assert_links(&a, &[1, 2]);
+        assert_links(&b, &[3, 4, 5]);
+
+        let c = a.split_off(2);
+        assert_links(&a, &[1, 2]);
+        assert_links(&c, &[]);
+
+        let d = a.split_off(0);
+        assert_links(&a, &[]);
+        assert_links(&d, &[1, 2]);
+
+        let mut e = list_from([1, 2, 3]);
+        let f = e.split_off(2);
+        assert_links(&e, &[1, 2]);
+        assert_links(&f, &[3]);
+        e.push_back(10);
+        assert_links(&e, &[1, 2, 10]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_off_out_of_bounds() {
+        let mut a = list_from([1, 2, 3]);
+        a.split_off(4);
+    }
+
+    #[test]
+    fn extract_if() {
+        let mut ll = list_from(0..10);
+        {
+            // abandon the iterator before it's exhausted
+            let mut extracted = ll.extract_if(|x| *x % 3 == 0);
+            assert_eq!(extracted.next(), Some(0));
+            assert_eq!(extracted.next(), Some(3));
+        }
+        assert_links(&ll, &[1, 2, 4, 5, 6, 7, 8, 9]);
+
+        let extracted: Vec<_> = ll.extract_if(|x| *x % 3 == 0).collect();
+        assert_eq!(extracted, [6, 9]);
+        assert_links(&ll, &[1, 2, 4, 5, 7, 8]);
+
+        let extracted: Vec<_> = ll.extract_if(|_| true).collect();
+        assert_eq!(extracted, [1, 2, 4, 5, 7, 8]);
+        assert_links(&ll, &[]);
+    }
+
+    #[test]
+    fn retain() {
+        let mut ll = list_from(0..10);
+        ll.retain(|x| *x % 2 == 0);
+        assert_links(&ll, &[0, 2, 4, 6, 8]);
+
+        ll.retain(|x| *x > 0 && *x < 8);
+        assert_links(&ll, &[2, 4, 6]);
+
+        ll.retain(|_| false);
+        assert_links(&ll, &[]);
+    }
+
+    #[test]
+    fn retain_panic_in_pred() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        let mut ll = list_from(0..6);
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            ll.retain(|x| {
+                if *x == 3 {
+                    panic!("panic from pred")
+                }
+                *x % 2 == 0
+            })
+        }));
+        assert!(res.is_err());
+        assert_links(&ll, &[0, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn sort() {
+        let mut ll = list_from([5, 1, 4, 2, 8, 0, 2, 7, 3]);
+        ll.sort();
+        assert_links(&ll, &[0, 1, 2, 2, 3, 4, 5, 7, 8]);
+
+        ll.sort_by(|a, b| b.cmp(a));
+        assert_links(&ll, &[8, 7, 5, 4, 3, 2, 2, 1, 0]);
+
+        let mut ll = list_from([1]);
+        ll.sort();
+        assert_links(&ll, &[1]);
+
+        let mut ll = list_from::<i32>([]);
+        ll.sort();
+        assert_links(&ll, &[]);
+    }
+
+    #[test]
+    fn sort_is_stable() {
+        let items = [(3, 'a'), (1, 'b'), (3, 'c'), (2, 'd'), (1, 'e'), (3, 'f')];
+        let mut ll = list_from(items);
+        ll.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut expected = items;
+        expected.sort_by_key(|a| a.0);
+        assert_links(&ll, &expected);
+    }
+
+    #[test]
+    fn sort_panic_in_cmp() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        let mut ll = list_from(0..100);
+        let mut calls = 0;
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            ll.sort_by(|a, b| {
+                calls += 1;
+                if calls == 150 {
+                    panic!("panic from cmp")
+                }
+                b.cmp(a)
+            })
+        }));
+        assert!(res.is_err());
+
+        let mut items: Vec<_> = ll.iter().copied().collect();
+        items.sort();
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+        assert_eq!(ll.iter().rev().count(), 100);
+    }
+
+    #[test]
+    fn handles() {
+        let mut ll = LinkedList::new();
+        let h1 = ll.push_back_handle(1);
+        let h2 = ll.push_back_handle(2);
+        let h0 = ll.push_front_handle(0);
+        ll.push_back(3);
+
+        unsafe {
+            assert_eq!(ll.get_handle(h0), &0);
+            assert_eq!(ll.get_handle(h1), &1);
+            *ll.get_handle_mut(h2) = 20;
+
+            assert_eq!(ll.remove_handle(h1), 1);
+            assert_links(&ll, &[0, 20, 3]);
+            assert_eq!(ll.remove_handle(h0), 0);
+            assert_links(&ll, &[20, 3]);
+        }
+
+        // handles survive appending to another list and sorting
+        let mut other = list_from([30, 10]);
+        other.append(&mut ll);
+        other.sort();
+        unsafe {
+            assert_eq!(other.remove_handle(h2), 20);
+        }
+        assert_links(&other, &[3, 10, 30]);
+    }
+
+    #[test]
+    fn move_handles() {
+        let mut ll = LinkedList::new();
+        let h0 = ll.push_back_handle(0);
+        let h1 = ll.push_back_handle(1);
+        let h2 = ll.push_back_handle(2);
+
+        unsafe {
+            ll.move_to_back_handle(h2);
+            assert_links(&ll, &[0, 1, 2]);
+            ll.move_to_back_handle(h1);
+            assert_links(&ll, &[0, 2, 1]);
+            ll.move_to_back_handle(h0);
+            assert_links(&ll, &[2, 1, 0]);
+
+            ll.move_to_front_handle(h2);
+            assert_links(&ll, &[2, 1, 0]);
+            ll.move_to_front_handle(h1);
+            assert_links(&ll, &[1, 2, 0]);
+            ll.move_to_front_handle(h0);
+            assert_links(&ll, &[0, 1, 2]);
+        }
+
+        let mut ll = LinkedList::new();
+        let h = ll.push_back_handle(0);
+        unsafe {
+            ll.move_to_back_handle(h);
+            ll.move_to_front_handle(h);
+        }
+        assert_links(&ll, &[0]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "doesn't belong"]
+    fn handle_from_other_list() {
+        let mut a = LinkedList::new();
+        let mut b = LinkedList::new();
+        let h = a.push_back_handle(1);
+        b.push_back(1);
+        unsafe { b.remove_handle(h) };
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "doesn't belong"]
+    fn handle_after_split_off() {
+        let mut a = list_from([0, 1]);
+        let h = a.push_back_handle(2);
+        let mut b = a.split_off(1);
+        unsafe {
+            assert_eq!(b.get_handle_mut(h), &2);
+            a.remove_handle(h);
+        }
+    }
+
+    #[test]
+    fn contains_find_position() {
+        let ll = list_from([5, 3, 8, 3]);
+        assert!(ll.contains(&8));
+        assert!(!ll.contains(&1));
+        assert_eq!(ll.find(|x| *x > 4), Some(&5));
+        assert_eq!(ll.find(|x| *x > 10), None);
+        assert_eq!(ll.position(|x| *x == 3), Some(1));
+        assert_eq!(ll.position(|x| *x == 10), None);
+
+        let empty = list_from::<i32>([]);
+        assert!(!empty.contains(&1));
+        assert_eq!(empty.find(|_| true), None);
+        assert_eq!(empty.position(|_| true), None);
+    }
+
+    #[test]
+    fn reverse() {
+        let mut ll = list_from([1, 2, 3, 4]);
+        ll.reverse();
+        assert_links(&ll, &[4, 3, 2, 1]);
+        ll.push_back(0);
+        ll.push_front(5);
+        assert_links(&ll, &[5, 4, 3, 2, 1, 0]);
+
+        let mut ll = list_from([1]);
+        ll.reverse();
+        assert_links(&ll, &[1]);
+
+        let mut ll = list_from::<i32>([]);
+        ll.reverse();
+        assert_links(&ll, &[]);
+    }
+
+    #[test]
+    fn get_insert_remove_from_both_ends() {
+        let mut ll = list_from(0..9);
+        for i in 0..9 {
+            assert_eq!(ll.get(i), Some(&(i as i32)));
+        }
+        assert_eq!(ll.get(9), None);
+
+        ll.insert(7, 70).unwrap();
+        ll.insert(2, 20).unwrap();
+        assert_eq!(ll.insert(12, 120), Err(120));
+        assert_links(&ll, &[0, 1, 20, 2, 3, 4, 5, 6, 70, 7, 8]);
+
+        assert_eq!(ll.remove(8), Some(70));
+        assert_eq!(ll.remove(2), Some(20));
+        assert_eq!(ll.remove(8), Some(8));
+        assert_eq!(ll.remove(8), None);
+        assert_links(&ll, &[0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
     #[test]
     fn test_basic_front() {
         let mut list = LinkedList::new();
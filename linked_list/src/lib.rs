@@ -2,6 +2,13 @@
 #![deny(rust_2018_idioms)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
-mod doubly_linked_list;
+pub mod doubly_linked_list;
+pub mod mpsc_queue;
 mod queue;
+pub mod singly_linked_list;
+pub mod skip_list;
 mod stack;
+
+pub use crate::doubly_linked_list::LinkedList;
+pub use crate::singly_linked_list::SinglyLinkedList;
+pub use crate::skip_list::SkipListMap;
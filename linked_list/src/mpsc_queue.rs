@@ -0,0 +1,355 @@
+//! Lock-free multi-producer single-consumer queue
+//!
+//! Linked queue in the style of Michael and Scott, simplified for a single
+//! consumer (as in Dmitry Vyukov's MPSC queue). The list always contains a
+//! stub node at `head` whose value has already been taken.
+//!
+//! * producers atomically swap `tail` to their new node and then link the
+//!   previous tail to it
+//! * consumer is the only one touching `head` and frees the old stub after
+//!   moving to the next node
+//!
+//! Between the swap and the link of a push, the pushed node (and any node
+//! pushed after it) is not yet visible to the consumer, so `pop` may return
+//! `None` even though a push has already started.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ptr;
+#[cfg(not(loom))]
+use std::sync::Arc;
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// `head` is only touched by the consumer, so only the atomics and `Arc` need
+// to be tracked by loom
+#[cfg(loom)]
+use loom::sync::Arc;
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+/// Creates a new queue and returns the producer and consumer halves.
+///
+/// `Producer` can be cloned and shared between threads, there is only one `Consumer`.
+pub fn channel<T>() -> (Producer<T>, Consumer<T>) {
+    let queue = Arc::new(MpscQueue::new());
+    (
+        Producer {
+            queue: Arc::clone(&queue),
+        },
+        Consumer { queue },
+    )
+}
+
+struct MpscQueue<T> {
+    // Consumer side, only accessed by the `Consumer`. Always points to the stub node.
+    head: UnsafeCell<*mut Node<T>>,
+    // Producer side, last pushed node.
+    tail: AtomicPtr<Node<T>>,
+}
+
+// SAFETY: values are moved between threads, hence `T: Send`.
+// `head` is only ever accessed through the single `Consumer`,
+// `tail` and `next` links are atomics.
+unsafe impl<T: Send> Send for MpscQueue<T> {}
+unsafe impl<T: Send> Sync for MpscQueue<T> {}
+
+struct Node<T> {
+    // `None` only for the stub node
+    data: Option<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new_raw(data: Option<T>) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            data,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+impl<T> MpscQueue<T> {
+    // SAFETY INVARIANTS:
+    //   * all node pointers are created from a real `Box` and are freed only
+    //     by the consumer (in `pop`) or in `Drop` when no other handles exist
+    //   * `head` and `tail` are never null, when the queue is empty both point to the stub
+
+    fn new() -> Self {
+        let stub = Node::new_raw(None);
+        Self {
+            head: UnsafeCell::new(stub),
+            tail: AtomicPtr::new(stub),
+        }
+    }
+
+    fn push(&self, val: T) {
+        let new = Node::new_raw(Some(val));
+        // AcqRel: Release publishes the new node to the next producer which links to it,
+        // Acquire makes the previous node (created by another producer) visible to us
+        let prev = self.tail.swap(new, Ordering::AcqRel);
+        // SAFETY: `prev` cannot be freed before it's linked to the next node,
+        // as the consumer never moves past a node with null `next`
+        unsafe { (*prev).next.store(new, Ordering::Release) };
+    }
+
+    /// # SAFETY
+    ///
+    /// * must only be called by the single consumer
+    unsafe fn pop(&self) -> Option<T> {
+        // SAFETY: only the consumer accesses `head` (see the safety doc of this fn)
+        let head = unsafe { &mut *self.head.get() };
+        // SAFETY: `head` is always valid (see SAFETY INVARIANTS)
+        let next = unsafe { (**head).next.load(Ordering::Acquire) };
+        if next.is_null() {
+            return None;
+        }
+
+        // SAFETY: `next` was published with Release and we synchronized with it with Acquire.
+        // The old stub is fully linked so no producer will touch it anymore.
+        unsafe {
+            let data = (*next).data.take();
+            drop(Box::from_raw(*head));
+            *head = next;
+            debug_assert!(data.is_some());
+            data
+        }
+    }
+
+    /// # SAFETY
+    ///
+    /// * must only be called by the single consumer
+    unsafe fn is_empty(&self) -> bool {
+        // SAFETY: only the consumer accesses `head` and it's always valid
+        unsafe { (**self.head.get()).next.load(Ordering::Acquire).is_null() }
+    }
+}
+
+impl<T> Drop for MpscQueue<T> {
+    fn drop(&mut self) {
+        // We have unique access, no other producers or consumers exist.
+        let mut current = *self.head.get_mut();
+        while !current.is_null() {
+            // SAFETY: all nodes are from valid Boxes and are reachable from `head` exactly once
+            let node = unsafe { Box::from_raw(current) };
+            current = node.next.load(Ordering::Relaxed);
+            drop(node);
+        }
+    }
+}
+
+/// Pushing half of the queue, created by [`channel`].
+pub struct Producer<T> {
+    queue: Arc<MpscQueue<T>>,
+}
+
+impl<T> Producer<T> {
+    pub fn push(&self, val: T) {
+        self.queue.push(val)
+    }
+}
+
+impl<T> Clone for Producer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: Arc::clone(&self.queue),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Producer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Producer").finish_non_exhaustive()
+    }
+}
+
+/// Popping half of the queue, created by [`channel`].
+pub struct Consumer<T> {
+    queue: Arc<MpscQueue<T>>,
+}
+
+impl<T> Consumer<T> {
+    /// Pops the oldest fully pushed value.
+    ///
+    /// Returns `None` if the queue is empty or the next value is still being pushed.
+    pub fn pop(&mut self) -> Option<T> {
+        // SAFETY: `Consumer` is not `Clone` and `pop` takes `&mut self`, so there is only one consumer
+        unsafe { self.queue.pop() }
+    }
+
+    /// Returns `true` if there are no fully pushed values.
+    pub fn is_empty(&mut self) -> bool {
+        // SAFETY: see `Self::pop`
+        unsafe { self.queue.is_empty() }
+    }
+}
+
+impl<T> fmt::Debug for Consumer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Consumer").finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[cfg(not(miri))]
+    const ITEMS_PER_PRODUCER: usize = 10_000;
+    #[cfg(miri)]
+    const ITEMS_PER_PRODUCER: usize = 50;
+
+    #[test]
+    fn single_thread() {
+        let (tx, mut rx) = channel();
+        assert!(rx.is_empty());
+        assert_eq!(rx.pop(), None);
+
+        tx.push(1);
+        tx.clone().push(2);
+        assert!(!rx.is_empty());
+        assert_eq!(rx.pop(), Some(1));
+        tx.push(3);
+        assert_eq!(rx.pop(), Some(2));
+        assert_eq!(rx.pop(), Some(3));
+        assert_eq!(rx.pop(), None);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn drop_remaining() {
+        let (tx, rx) = channel();
+        let item = Arc::new(());
+        for _ in 0..5 {
+            tx.push(Arc::clone(&item));
+        }
+        drop(rx);
+        // queue is kept alive by the producer
+        tx.push(Arc::clone(&item));
+        assert_eq!(Arc::strong_count(&item), 7);
+        drop(tx);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn multiple_producers() {
+        const PRODUCERS: usize = 4;
+
+        let (tx, mut rx) = channel();
+        let handles = (0..PRODUCERS)
+            .map(|p| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..ITEMS_PER_PRODUCER {
+                        tx.push((p, i));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(tx);
+
+        // items from a single producer must come out in the order they were pushed
+        let mut next_expected = [0; PRODUCERS];
+        let mut received = 0;
+        while received < PRODUCERS * ITEMS_PER_PRODUCER {
+            match rx.pop() {
+                Some((p, i)) => {
+                    assert_eq!(next_expected[p], i);
+                    next_expected[p] += 1;
+                    received += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(rx.pop(), None);
+        assert_eq!(next_expected, [ITEMS_PER_PRODUCER; PRODUCERS]);
+    }
+
+    #[test]
+    fn consumer_on_other_thread() {
+        let (tx, mut rx) = channel();
+        let consumer = thread::spawn(move || {
+            let mut sum = 0;
+            let mut received = 0;
+            while received < ITEMS_PER_PRODUCER {
+                if let Some(v) = rx.pop() {
+                    sum += v;
+                    received += 1;
+                } else {
+                    thread::yield_now();
+                }
+            }
+            sum
+        });
+
+        for i in 0..ITEMS_PER_PRODUCER {
+            tx.push(i);
+        }
+        let sum = consumer.join().unwrap();
+        assert_eq!(sum, (0..ITEMS_PER_PRODUCER).sum::<usize>());
+    }
+}
+
+/// Model checked tests, run with
+/// `RUSTFLAGS="--cfg loom" cargo test --release --lib mpsc_queue`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::thread;
+
+    use super::*;
+
+    #[test]
+    fn concurrent_push_pop() {
+        loom::model(|| {
+            let (tx, mut rx) = channel();
+            let tx2 = tx.clone();
+            let t1 = thread::spawn(move || {
+                tx.push((0, 0));
+                tx.push((0, 1));
+            });
+            let t2 = thread::spawn(move || tx2.push((1, 0)));
+
+            // pop while the producers are running, then the rest after they are done
+            let mut received = Vec::new();
+            for _ in 0..2 {
+                received.extend(rx.pop());
+            }
+            t1.join().unwrap();
+            t2.join().unwrap();
+            while let Some(v) = rx.pop() {
+                received.push(v);
+            }
+            assert!(rx.is_empty());
+
+            // items from a single producer must come out in the order they were pushed
+            let mut next_expected = [0; 2];
+            for (p, i) in received {
+                assert_eq!(next_expected[p], i);
+                next_expected[p] += 1;
+            }
+            assert_eq!(next_expected, [2, 1]);
+        });
+    }
+
+    #[test]
+    fn drop_with_items_left() {
+        loom::model(|| {
+            let (tx, rx) = channel();
+            let item = Arc::new(());
+            let producer = {
+                let item = Arc::clone(&item);
+                thread::spawn(move || tx.push(item))
+            };
+            // the last handle to be dropped frees the queue and the item
+            drop(rx);
+            producer.join().unwrap();
+            assert_eq!(Arc::strong_count(&item), 1);
+        });
+    }
+}
@@ -0,0 +1,404 @@
+use core::{fmt, mem};
+
+/// Singly linked list with only a head pointer.
+///
+/// Nodes are chained with `Box`es, so unlike the doubly linked list this is
+/// implemented without any unsafe code.
+pub struct SinglyLinkedList<T> {
+    head: Link<T>,
+    count: usize,
+}
+
+type Link<T> = Option<Box<Node<T>>>;
+
+struct Node<T> {
+    data: T,
+    next: Link<T>,
+}
+
+impl<T> SinglyLinkedList<T> {
+    pub fn new() -> Self {
+        Self {
+            head: None,
+            count: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Number of bytes allocated for the nodes.
+    ///
+    /// Heap memory owned by the items themselves is not included.
+    pub fn allocated_bytes(&self) -> usize {
+        self.count * mem::size_of::<Node<T>>()
+    }
+
+    pub fn push_front(&mut self, val: T) {
+        let new = Box::new(Node {
+            data: val,
+            next: self.head.take(),
+        });
+        self.head = Some(new);
+        self.count += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.head.take().map(|head| {
+            let Node { data, next } = *head;
+            self.head = next;
+            self.count -= 1;
+            data
+        })
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.head.as_ref().map(|head| &head.data)
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        self.head.as_mut().map(|head| &mut head.data)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            node: self.head.as_deref(),
+            len: self.count,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            node: self.head.as_deref_mut(),
+            len: self.count,
+        }
+    }
+
+    /// Returns a cursor positioned at the first item (or past the end if the list is empty).
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            link: Some(&mut self.head),
+            count: &mut self.count,
+        }
+    }
+}
+
+impl<T> Default for SinglyLinkedList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for SinglyLinkedList<T> {
+    fn drop(&mut self) {
+        // Default drop would recurse through `Box<Node>`s and could overflow the stack for long lists.
+        //
+        // If `T::drop` panics the remaining nodes are still owned by `current`
+        // and will be dropped while unwinding.
+        let mut current = self.head.take();
+        self.count = 0;
+        while let Some(mut node) = current {
+            current = node.next.take();
+        }
+    }
+}
+
+impl<T> fmt::Debug for SinglyLinkedList<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SinglyLinkedList")
+            .field("count", &self.count)
+            .field("items", &self.iter())
+            .finish()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SinglyLinkedList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut SinglyLinkedList<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct Iter<'a, T> {
+    node: Option<&'a Node<T>>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.node.map(|node| {
+            self.node = node.next.as_deref();
+            self.len -= 1;
+            &node.data
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            node: self.node,
+            len: self.len,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Iter<'_, T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+pub struct IterMut<'a, T> {
+    node: Option<&'a mut Node<T>>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.node.take().map(|node| {
+            self.node = node.next.as_deref_mut();
+            self.len -= 1;
+            &mut node.data
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for IterMut<'_, T> {}
+
+/// Mutable cursor over a `SinglyLinkedList`.
+///
+/// The cursor points to a link in the list, that is either a node or the
+/// position past the end of the list (after the last node).
+pub struct CursorMut<'a, T> {
+    // Link which holds the current node, only `None` while we are in the middle of moving the cursor.
+    link: Option<&'a mut Link<T>>,
+    count: &'a mut usize,
+}
+
+impl<T> CursorMut<'_, T> {
+    fn link(&mut self) -> &mut Link<T> {
+        self.link
+            .as_mut()
+            .expect("`CursorMut::link` must always be `Some(..)`")
+    }
+
+    /// Returns the current item or `None` if the cursor is past the end.
+    pub fn current(&mut self) -> Option<&mut T> {
+        self.link().as_mut().map(|node| &mut node.data)
+    }
+
+    /// Returns the item after the current one.
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        self.link()
+            .as_mut()
+            .and_then(|node| node.next.as_mut())
+            .map(|node| &mut node.data)
+    }
+
+    pub fn is_past_end(&mut self) -> bool {
+        self.link().is_none()
+    }
+
+    /// Move to the next item. Does nothing if the cursor is already past the end.
+    pub fn move_next(&mut self) {
+        let link = self
+            .link
+            .take()
+            .expect("`CursorMut::link` must always be `Some(..)`");
+        match link {
+            Some(node) => self.link = Some(&mut node.next),
+            None => self.link = Some(link),
+        }
+    }
+
+    /// Insert `val` after the current item.
+    ///
+    /// If the cursor is past the end, `val` is appended to the end of the list
+    /// and it becomes the current item.
+    pub fn insert_after(&mut self, val: T) {
+        let link = self.link();
+        let slot = match link {
+            Some(node) => &mut node.next,
+            None => link,
+        };
+        let new = Box::new(Node {
+            data: val,
+            next: slot.take(),
+        });
+        *slot = Some(new);
+        *self.count += 1;
+    }
+
+    /// Remove the item after the current item.
+    pub fn remove_after(&mut self) -> Option<T> {
+        let next = &mut self.link().as_mut()?.next;
+        let removed = next.take().map(|removed| {
+            let Node { data, next: after } = *removed;
+            *next = after;
+            data
+        });
+        if removed.is_some() {
+            *self.count -= 1;
+        }
+        removed
+    }
+
+    /// Remove the current item, the cursor moves to the next item.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let link = self.link();
+        let removed = link.take().map(|removed| {
+            let Node { data, next } = *removed;
+            *link = next;
+            data
+        });
+        if removed.is_some() {
+            *self.count -= 1;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_from<T>(items: impl IntoIterator<Item = T>) -> SinglyLinkedList<T> {
+        let items: Vec<T> = items.into_iter().collect();
+        let mut ll = SinglyLinkedList::new();
+        for item in items.into_iter().rev() {
+            ll.push_front(item);
+        }
+        ll
+    }
+
+    fn assert_items<T: fmt::Debug + PartialEq>(ll: &SinglyLinkedList<T>, expected: &[T]) {
+        assert_eq!(ll.len(), expected.len());
+        assert_eq!(ll.iter().len(), expected.len());
+        assert_eq!(
+            ll.iter().collect::<Vec<_>>(),
+            expected.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_basic_front() {
+        let mut list = SinglyLinkedList::new();
+        assert_eq!(list.len(), 0);
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.front(), None);
+
+        list.push_front(10);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.pop_front(), Some(10));
+        assert_eq!(list.len(), 0);
+
+        list.push_front(10);
+        list.push_front(20);
+        list.push_front(30);
+        assert_eq!(list.front(), Some(&30));
+        *list.front_mut().unwrap() = 31;
+        assert_items(&list, &[31, 20, 10]);
+        assert_eq!(list.pop_front(), Some(31));
+        assert_eq!(list.pop_front(), Some(20));
+        assert_eq!(list.pop_front(), Some(10));
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn allocated_bytes() {
+        let mut list = SinglyLinkedList::<u64>::new();
+        assert_eq!(list.allocated_bytes(), 0);
+        list.push_front(1);
+        list.push_front(2);
+        // the item and the pointer to the next node
+        assert_eq!(list.allocated_bytes(), 2 * 16);
+    }
+
+    #[test]
+    fn iters() {
+        let mut ll = list_from([1, 2, 3]);
+        ll.iter_mut().for_each(|x| *x *= 10);
+        assert_items(&ll, &[10, 20, 30]);
+        assert_eq!(format!("{:?}", ll.iter()), "[10, 20, 30]");
+    }
+
+    #[test]
+    fn cursor() {
+        let mut ll = list_from([1, 2, 3]);
+        let mut c = ll.cursor_front_mut();
+        assert_eq!(c.current(), Some(&mut 1));
+        c.insert_after(10);
+        assert_eq!(c.peek_next(), Some(&mut 10));
+        c.move_next();
+        c.move_next();
+        assert_eq!(c.current(), Some(&mut 2));
+        assert_eq!(c.remove_after(), Some(3));
+        assert_eq!(c.remove_after(), None);
+        c.move_next();
+        assert!(c.is_past_end());
+        c.insert_after(4);
+        assert_eq!(c.current(), Some(&mut 4));
+        assert_items(&ll, &[1, 10, 2, 4]);
+
+        let mut c = ll.cursor_front_mut();
+        assert_eq!(c.remove_current(), Some(1));
+        c.move_next();
+        assert_eq!(c.remove_current(), Some(2));
+        assert_eq!(c.current(), Some(&mut 4));
+        assert_items(&ll, &[10, 4]);
+
+        let mut empty = SinglyLinkedList::new();
+        let mut c = empty.cursor_front_mut();
+        assert!(c.is_past_end());
+        assert_eq!(c.remove_current(), None);
+        c.insert_after(1);
+        assert_items(&empty, &[1]);
+    }
+
+    #[test]
+    fn drop_long_list() {
+        let mut ll = SinglyLinkedList::new();
+        for i in 0..200_000 {
+            ll.push_front(i);
+        }
+        drop(ll);
+    }
+}
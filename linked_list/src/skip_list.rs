@@ -0,0 +1,641 @@
+//! Probabilistic skip list
+//!
+//! Ordered map where each node is part of a random number of sorted linked lists (levels).
+//! Level 0 contains every node and each following level contains roughly `p` fraction of
+//! the nodes of the previous level, which gives expected O(log n) search, insert and remove.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hasher};
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+use core::ptr::NonNull;
+use std::collections::hash_map::RandomState;
+
+/// Largest supported `max_level`.
+pub const MAX_LEVEL: usize = 32;
+
+pub struct SkipListMap<K, V> {
+    // `head[i]` is the first node at level `i`, `head.len() == max_level`
+    head: Box<[Link<K, V>]>,
+    // Number of levels currently in use, always in `1..=max_level`.
+    level: usize,
+    len: usize,
+    p: f64,
+    rng: XorShift,
+    marker: PhantomData<Box<Node<K, V>>>,
+}
+
+type Link<K, V> = Option<NonNull<Node<K, V>>>;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    // `next[i]` is the next node at level `i`, the height of the node is `next.len()`
+    next: Box<[Link<K, V>]>,
+}
+
+impl<K, V> fmt::Debug for SkipListMap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> Default for SkipListMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for SkipListMap<K, V> {
+    fn drop(&mut self) {
+        /// Guard in case `K::drop` or `V::drop` panics.
+        ///
+        /// We try to clean up as much as possible after the panic, eg try to
+        /// drop the remaining items.
+        struct Guard<K, V>(Link<K, V>);
+
+        impl<K, V> Guard<K, V> {
+            fn drop_items(&mut self) {
+                while let Some(current) = self.0.take() {
+                    // SAFETY: All pointer are derived from valid Box and every node is
+                    // reachable exactly once through level 0
+                    let current = unsafe { Box::from_raw(current.as_ptr()) };
+                    self.0 = current.next[0];
+                    drop(current);
+                }
+            }
+        }
+
+        impl<K, V> Drop for Guard<K, V> {
+            fn drop(&mut self) {
+                self.drop_items()
+            }
+        }
+
+        self.len = 0;
+        let mut guard = Guard(self.head[0]);
+        self.head.fill(None);
+        guard.drop_items()
+    }
+}
+
+impl<K, V> SkipListMap<K, V> {
+    // SAFETY INVARIANTS:
+    //   * All node pointers reachable from `head` are:
+    //     - valid to dereference, they are created from a real `Box`
+    //     - stable, we never move any of the allocated nodes
+    //     - alive for the lifetime of self as they are deallocated only in
+    //       `Self::remove` (after being unlinked from all levels) or `Self::drop`
+    //   * Every node is reachable through level 0, and nodes at every level are sorted by key
+
+    const DEF_MAX_LEVEL: usize = 16;
+    const DEF_P: f64 = 0.5;
+
+    pub fn new() -> Self {
+        Self::with_params(Self::DEF_MAX_LEVEL, Self::DEF_P)
+    }
+
+    /// Creates a new skip list with at most `max_level` levels, where a node
+    /// is promoted to the next level with probability `p`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `max_level` is not in `1..=MAX_LEVEL`
+    /// * if `p` is not in `(0.0, 1.0)`
+    pub fn with_params(max_level: usize, p: f64) -> Self {
+        assert!(
+            (1..=MAX_LEVEL).contains(&max_level),
+            "`max_level` must be in 1..={MAX_LEVEL}"
+        );
+        assert!(p > 0.0 && p < 1.0, "`p` must be in (0.0, 1.0)");
+
+        Self {
+            head: vec![None; max_level].into_boxed_slice(),
+            level: 1,
+            len: 0,
+            p,
+            rng: XorShift::new(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn max_level(&self) -> usize {
+        self.head.len()
+    }
+
+    pub fn p(&self) -> f64 {
+        self.p
+    }
+
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        // SAFETY: see SAFETY INVARIANTS
+        self.head[0].map(|node| unsafe { node_refs(node) })
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            node: self.head[0],
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            node: self.head[0],
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        while level < self.max_level() && self.rng.next_f64() < self.p {
+            level += 1;
+        }
+        level
+    }
+
+    /// Returns the node after `node` at `level`, `None` node means the head.
+    fn next_of(&self, node: Link<K, V>, level: usize) -> Link<K, V> {
+        match node {
+            None => self.head[level],
+            // SAFETY: see SAFETY INVARIANTS
+            Some(node) => unsafe { (*node.as_ptr()).next[level] },
+        }
+    }
+
+    /// Sets the node after `node` at `level`, `None` node means the head.
+    fn set_next(&mut self, node: Link<K, V>, level: usize, next: Link<K, V>) {
+        match node {
+            None => self.head[level] = next,
+            // SAFETY: see SAFETY INVARIANTS
+            Some(node) => unsafe { (*node.as_ptr()).next[level] = next },
+        }
+    }
+
+    /// Returns the last node at each level for which `is_before(key)` holds.
+    ///
+    /// `is_before` must be monotone over the sorted keys (true for some prefix and false afterwards).
+    fn find_preds<F>(&self, mut is_before: F) -> [Link<K, V>; MAX_LEVEL]
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let mut preds = [None; MAX_LEVEL];
+        let mut pred = None;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.next_of(pred, level) {
+                // SAFETY: see SAFETY INVARIANTS
+                if is_before(unsafe { &(*next.as_ptr()).key }) {
+                    pred = Some(next);
+                } else {
+                    break;
+                }
+            }
+            preds[level] = pred;
+        }
+        preds
+    }
+
+    /// Returns the first node for which `is_before(key)` doesn't hold.
+    fn first_not_before<F>(&self, is_before: F) -> Link<K, V>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let preds = self.find_preds(is_before);
+        self.next_of(preds[0], 0)
+    }
+}
+
+impl<K, V> SkipListMap<K, V>
+where
+    K: Ord,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        // SAFETY: see SAFETY INVARIANTS
+        self.find(key)
+            .map(|node| unsafe { &(*node.as_ptr()).value })
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        // SAFETY: see SAFETY INVARIANTS, we have unique access through `&mut self`
+        self.find(key)
+            .map(|node| unsafe { &mut (*node.as_ptr()).value })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.find(key).is_some()
+    }
+
+    fn find<Q>(&self, key: &Q) -> Link<K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        // SAFETY: see SAFETY INVARIANTS
+        self.first_not_before(|k| k.borrow() < key)
+            .filter(|node| unsafe { (*node.as_ptr()).key.borrow() == key })
+    }
+
+    /// Inserts the key-value pair. If the key already exists, the old pair is replaced and returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        let preds = self.find_preds(|k| k < &key);
+
+        if let Some(node) = self.next_of(preds[0], 0) {
+            // SAFETY: see SAFETY INVARIANTS, we have unique access through `&mut self`
+            let node = unsafe { &mut *node.as_ptr() };
+            if node.key == key {
+                let old_key = core::mem::replace(&mut node.key, key);
+                let old_value = core::mem::replace(&mut node.value, value);
+                return Some((old_key, old_value));
+            }
+        }
+
+        let height = self.random_level();
+        // preds above current level are `None`, eg the head
+        self.level = self.level.max(height);
+
+        let node = Box::new(Node {
+            key,
+            value,
+            next: vec![None; height].into_boxed_slice(),
+        });
+        let node = NonNull::from(Box::leak(node));
+        for (level, &pred) in preds.iter().enumerate().take(height) {
+            let next = self.next_of(pred, level);
+            // SAFETY: node was just created from a Box
+            unsafe { (*node.as_ptr()).next[level] = next };
+            self.set_next(pred, level, Some(node));
+        }
+        self.len += 1;
+
+        None
+    }
+
+    /// Removes the key and returns the removed pair.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let preds = self.find_preds(|k| k.borrow() < key);
+        let target = self.next_of(preds[0], 0)?;
+        // SAFETY: see SAFETY INVARIANTS
+        let target_ref = unsafe { &*target.as_ptr() };
+        if target_ref.key.borrow() != key {
+            return None;
+        }
+
+        // target is the first node >= key, so it must directly follow the preds
+        // at every level it is part of
+        for (level, &pred) in preds.iter().enumerate().take(target_ref.next.len()) {
+            debug_assert_eq!(self.next_of(pred, level), Some(target));
+            self.set_next(pred, level, target_ref.next[level]);
+        }
+        while self.level > 1 && self.head[self.level - 1].is_none() {
+            self.level -= 1;
+        }
+        self.len -= 1;
+
+        // SAFETY: target is unlinked from all levels and thus not reachable anymore
+        let node = unsafe { Box::from_raw(target.as_ptr()) };
+        Some((node.key, node.value))
+    }
+
+    /// Iterate over the pairs whose key is in `range` in ascending key order.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+        R: RangeBounds<Q>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(s) => self.first_not_before(|k| k.borrow() < s),
+            Bound::Excluded(s) => self.first_not_before(|k| k.borrow() <= s),
+            Bound::Unbounded => self.head[0],
+        };
+        // first node past the range
+        let end = match range.end_bound() {
+            Bound::Included(e) => self.first_not_before(|k| k.borrow() <= e),
+            Bound::Excluded(e) => self.first_not_before(|k| k.borrow() < e),
+            Bound::Unbounded => None,
+        };
+
+        let start = match (start, end) {
+            // SAFETY: see SAFETY INVARIANTS
+            (Some(s), Some(e)) if unsafe { (*s.as_ptr()).key > (*e.as_ptr()).key } => None,
+            _ => start,
+        };
+
+        Range {
+            node: start,
+            end,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// # SAFETY
+///
+/// * `node` must be valid to dereference for `'a`
+unsafe fn node_refs<'a, K, V>(node: NonNull<Node<K, V>>) -> (&'a K, &'a V) {
+    let node = unsafe { &*node.as_ptr() };
+    (&node.key, &node.value)
+}
+
+impl<'a, K, V> IntoIterator for &'a SkipListMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut SkipListMap<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    node: Link<K, V>,
+    len: usize,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.node.map(|node| {
+            // SAFETY: nodes are valid for the lifetime of the borrowed list
+            let node = unsafe { &*node.as_ptr() };
+            self.node = node.next[0];
+            self.len -= 1;
+            (&node.key, &node.value)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            node: self.node,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+}
+
+pub struct IterMut<'a, K, V> {
+    node: Link<K, V>,
+    len: usize,
+    marker: PhantomData<&'a mut Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.node.map(|node| {
+            // SAFETY: nodes are valid for the lifetime of the borrowed list and
+            // each node is yielded only once
+            let node = unsafe { &mut *node.as_ptr() };
+            self.node = node.next[0];
+            self.len -= 1;
+            (&node.key, &mut node.value)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+pub struct Range<'a, K, V> {
+    node: Link<K, V>,
+    // first node past the range
+    end: Link<K, V>,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.node.filter(|&node| Some(node) != self.end)?;
+        // SAFETY: nodes are valid for the lifetime of the borrowed list
+        let node = unsafe { &*node.as_ptr() };
+        self.node = node.next[0];
+        Some((&node.key, &node.value))
+    }
+}
+
+/// Small xorshift64* generator used to pick node levels.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new() -> Self {
+        // only used to get a random seed
+        let seed = RandomState::new().build_hasher().finish();
+        Self(seed | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns uniformly distributed float in `[0, 1)`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that every level is sorted and a sub-list of the level below.
+    fn assert_levels<K: Ord + fmt::Debug, V>(list: &SkipListMap<K, V>) {
+        let level0 = collect_level(list, 0);
+        assert_eq!(level0.len(), list.len());
+        assert!(list.level >= 1 && list.level <= list.max_level());
+        for level in 0..list.max_level() {
+            let nodes = collect_level(list, level);
+            let keys = nodes
+                .iter()
+                .map(|n| unsafe { &(*n.as_ptr()).key })
+                .collect::<Vec<_>>();
+            assert!(keys.windows(2).all(|w| w[0] < w[1]), "{level}: {keys:?}");
+            assert!(nodes.iter().all(|n| level0.contains(n)));
+            if level >= list.level {
+                assert!(nodes.is_empty());
+            }
+        }
+    }
+
+    fn collect_level<K, V>(list: &SkipListMap<K, V>, level: usize) -> Vec<NonNull<Node<K, V>>> {
+        let mut out = Vec::new();
+        let mut node = list.head[level];
+        while let Some(n) = node {
+            out.push(n);
+            node = unsafe { (*n.as_ptr()).next[level] };
+        }
+        out
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let mut list = SkipListMap::new();
+        assert!(list.is_empty());
+        assert_eq!(list.get(&1), None);
+        assert_eq!(list.remove(&1), None);
+
+        for k in [5, 1, 9, 3, 7] {
+            assert_eq!(list.insert(k, k * 10), None);
+            assert_levels(&list);
+        }
+        assert_eq!(list.len(), 5);
+        assert_eq!(list.insert(3, 31), Some((3, 30)));
+        assert_eq!(list.get(&3), Some(&31));
+        *list.get_mut(&9).unwrap() = 91;
+        assert_eq!(list.first_key_value(), Some((&1, &10)));
+        assert_eq!(
+            list.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            [(1, 10), (3, 31), (5, 50), (7, 70), (9, 91)]
+        );
+
+        assert_eq!(list.remove(&5), Some((5, 50)));
+        assert_eq!(list.remove(&5), None);
+        assert!(!list.contains_key(&5));
+        assert_levels(&list);
+        assert_eq!(list.len(), 4);
+
+        for (_, v) in list.iter_mut() {
+            *v += 1;
+        }
+        assert_eq!(format!("{list:?}"), "{1: 11, 3: 32, 7: 71, 9: 92}");
+    }
+
+    #[test]
+    fn range() {
+        let mut list = SkipListMap::with_params(4, 0.25);
+        for k in (0..20).step_by(2) {
+            list.insert(k, ());
+        }
+        let keys = |r: Range<'_, i32, ()>| r.map(|(k, _)| *k).collect::<Vec<_>>();
+
+        assert_eq!(keys(list.range(3..9)), [4, 6, 8]);
+        assert_eq!(keys(list.range(4..=8)), [4, 6, 8]);
+        assert_eq!(
+            keys(list.range((Bound::Excluded(4), Bound::Excluded(8)))),
+            [6]
+        );
+        assert_eq!(keys(list.range(..3)), [0, 2]);
+        assert_eq!(keys(list.range(15..)), [16, 18]);
+        assert_eq!(keys(list.range(..)).len(), 10);
+        assert_eq!(keys(list.range(5..5)), []);
+        assert_eq!(
+            keys(list.range((Bound::Included(9), Bound::Excluded(3)))),
+            []
+        );
+        assert_eq!(keys(list.range(100..)), []);
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_p() {
+        SkipListMap::<i32, i32>::with_params(4, 1.0);
+    }
+
+    mod proptests {
+        use std::collections::BTreeMap;
+
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const OPS: usize = 500;
+        #[cfg(miri)]
+        const OPS: usize = 30;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 5;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_btree_map(
+                max_level in 1..8usize,
+                p in 0.1..0.9f64,
+                ops in proptest::collection::vec((any::<bool>(), 0..100i32), 0..OPS),
+                range in (0..100i32, 0..100i32),
+            ) {
+                let mut list = SkipListMap::with_params(max_level, p);
+                let mut expected = BTreeMap::new();
+
+                for (is_insert, key) in ops {
+                    if is_insert {
+                        prop_assert_eq!(
+                            list.insert(key, key).map(|(_, v)| v),
+                            expected.insert(key, key)
+                        );
+                    } else {
+                        prop_assert_eq!(list.remove(&key).map(|(_, v)| v), expected.remove(&key));
+                    }
+                    prop_assert_eq!(list.len(), expected.len());
+                }
+
+                assert_levels(&list);
+                prop_assert!(list.iter().eq(expected.iter()));
+                let (lo, hi) = (range.0.min(range.1), range.0.max(range.1));
+                prop_assert!(list.range(lo..hi).eq(expected.range(lo..hi)));
+                prop_assert!(list.range(lo..=hi).eq(expected.range(lo..=hi)));
+            }
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use core::hint::black_box;
+use core::time::Duration;
+
+use criterion::measurement::Measurement;
+use criterion::{
+    AxisScale, BenchmarkGroup, BenchmarkId, Criterion, PlotConfiguration, criterion_group,
+    criterion_main,
+};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use search::eytzinger::Eytzinger;
+use search::simd::linear_search_simd;
+use search::strings::boyer_moore::{BoyerMoore, Horspool};
+use search::strings::kmp::Kmp;
+use search::strings::naive;
+use search::{binary, linear};
+
+pub fn gen_ascending_ints_no_duplicates(count: usize, key_max: i32) -> Vec<i32> {
+    assert!(count < key_max as usize);
+    let mut vec = Vec::with_capacity(count);
+    let mut rng = ChaCha8Rng::seed_from_u64(3);
+    let a = rand::seq::index::sample(&mut rng, key_max as usize, count);
+    vec.extend(a.into_iter().map(|a| a as i32));
+    assert_eq!(vec.len(), count);
+    vec.sort();
+    vec
+}
+
+/// All items of `vec` in random order
+pub fn gen_needles(vec: &[i32]) -> Vec<i32> {
+    let mut needles = vec.to_vec();
+    let mut rng = ChaCha8Rng::seed_from_u64(4);
+    needles.shuffle(&mut rng);
+    needles
+}
+
+/// Random text of `len` bytes from `alphabet`
+pub fn gen_text(len: usize, alphabet: &[u8]) -> Vec<u8> {
+    let mut rng = ChaCha8Rng::seed_from_u64(5);
+    (0..len)
+        .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+        .collect()
+}
+
+macro_rules! select_measurement {
+    (refcycles) => {
+        pub const MEASUREMENT_KIND: &str = "refcycles";
+
+        pub fn create_measurement() -> impl ::criterion::measurement::Measurement {
+            ::criterion_perf_events::Perf::new(
+                ::perfcnt::linux::PerfCounterBuilderLinux::from_hardware_event(
+                    ::perfcnt::linux::HardwareEventType::RefCPUCycles,
+                ),
+            )
+        }
+    };
+    (instructions) => {
+        pub const MEASUREMENT_KIND: &str = "instructions";
+
+        pub fn create_measurement() -> impl ::criterion::measurement::Measurement {
+            ::criterion_perf_events::Perf::new(
+                ::perfcnt::linux::PerfCounterBuilderLinux::from_hardware_event(
+                    ::perfcnt::linux::HardwareEventType::Instructions,
+                ),
+            )
+        }
+    };
+    (walltime) => {
+        pub const MEASUREMENT_KIND: &str = "walltime";
+
+        pub fn create_measurement() -> impl ::criterion::measurement::Measurement {
+            ::criterion::measurement::WallTime
+        }
+    };
+}
+
+select_measurement!(walltime);
+
+fn linear_scalar(slice: &[i32], needle: i32) -> Option<usize> {
+    linear::search(slice, &needle)
+}
+
+fn linear_simd(slice: &[i32], needle: i32) -> Option<usize> {
+    linear_search_simd(slice, needle)
+}
+
+fn binary(slice: &[i32], needle: i32) -> Option<usize> {
+    binary::search(slice, &needle).ok()
+}
+
+fn std_binary(slice: &[i32], needle: i32) -> Option<usize> {
+    slice.binary_search(&needle).ok()
+}
+
+fn binary_branchless(slice: &[i32], needle: i32) -> Option<usize> {
+    binary::search_branchless(slice, &needle).ok()
+}
+
+/// Searches for every item of sorted slices, linear searches win on small
+/// slices.
+fn small_slices<M: Measurement>(c: &mut Criterion<M>) {
+    fn bench_one<M: Measurement>(
+        g: &mut BenchmarkGroup<'_, M>,
+        name: &str,
+        count: usize,
+        items: &[i32],
+        needles: &[i32],
+        search: fn(&[i32], i32) -> Option<usize>,
+    ) {
+        g.bench_with_input(BenchmarkId::new(name, count), &count, |b, _i| {
+            b.iter(|| {
+                for n in needles {
+                    black_box(search(black_box(items), black_box(*n)));
+                }
+            })
+        });
+    }
+
+    macro_rules! bench {
+        ($g:expr, $count:expr, $vec:expr, $needles:expr, $($search:path),+ $(,)?) => {
+           $(
+               bench_one($g, stringify!($search), $count, &$vec, &$needles, $search);
+            )+
+        };
+    }
+
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+    let mut g = c.benchmark_group(format!("small_slices_{}", MEASUREMENT_KIND));
+    g.plot_config(plot_config.clone());
+
+    for count in [4, 8, 16, 32, 64, 128, 256, 512, 1024] {
+        let vec = gen_ascending_ints_no_duplicates(count, i32::MAX);
+        let needles = gen_needles(&vec);
+        bench!(
+            &mut g,
+            count,
+            vec,
+            needles,
+            linear_scalar,
+            linear_simd,
+            binary,
+            std_binary,
+        );
+    }
+    g.finish();
+}
+
+/// Searches for random items in sorted slices and the same items in
+/// Eytzinger layout, from slices which fit in L1 to ones which don't fit in
+/// any cache.
+fn layout<M: Measurement>(c: &mut Criterion<M>) {
+    const NEEDLES: usize = 1000;
+
+    let plot_config = PlotConfiguration::default().summary_scale(AxisScale::Logarithmic);
+    let mut g = c.benchmark_group(format!("layout_{}", MEASUREMENT_KIND));
+    g.plot_config(plot_config.clone());
+
+    for count in [1 << 8, 1 << 12, 1 << 16, 1 << 20, 1 << 24] {
+        let vec = gen_ascending_ints_no_duplicates(count, i32::MAX);
+        let mut needles = gen_needles(&vec);
+        needles.truncate(NEEDLES);
+
+        for (name, search) in [
+            ("binary", binary as fn(&[i32], i32) -> Option<usize>),
+            ("binary_branchless", binary_branchless),
+            ("std_binary", std_binary),
+        ] {
+            g.bench_with_input(BenchmarkId::new(name, count), &count, |b, _i| {
+                b.iter(|| {
+                    for n in &needles {
+                        black_box(search(black_box(&vec), black_box(*n)));
+                    }
+                })
+            });
+        }
+
+        let eytzinger = Eytzinger::from_sorted(vec);
+        g.bench_with_input(BenchmarkId::new("eytzinger", count), &count, |b, _i| {
+            b.iter(|| {
+                for n in &needles {
+                    black_box(black_box(&eytzinger).search(black_box(n)));
+                }
+            })
+        });
+    }
+    g.finish();
+}
+
+/// Finds all matches of patterns of different lengths in a long haystack.
+///
+/// The patterns are taken from the haystack, so there is at least one match.
+fn strings<M: Measurement>(c: &mut Criterion<M>) {
+    const HAYSTACK_LEN: usize = 1 << 20;
+
+    for (alphabet_name, alphabet) in [
+        ("dna", &b"ACGT"[..]),
+        ("letters", &b"abcdefghijklmnopqrstuvwxyz"[..]),
+    ] {
+        let haystack = gen_text(HAYSTACK_LEN, alphabet);
+        let mut g = c.benchmark_group(format!("strings_{alphabet_name}_{}", MEASUREMENT_KIND));
+
+        for pattern_len in [4, 16, 64, 256] {
+            let start = HAYSTACK_LEN / 2;
+            let pattern = &haystack[start..start + pattern_len];
+            let kmp = Kmp::new(pattern);
+            let horspool = Horspool::new(pattern);
+            let boyer_moore = BoyerMoore::new(pattern);
+
+            g.bench_with_input(
+                BenchmarkId::new("naive", pattern_len),
+                &pattern_len,
+                |b, _i| b.iter(|| naive::find_all(black_box(&haystack), black_box(pattern)).len()),
+            );
+            g.bench_with_input(
+                BenchmarkId::new("kmp", pattern_len),
+                &pattern_len,
+                |b, _i| b.iter(|| black_box(&kmp).find_iter(black_box(&haystack)).count()),
+            );
+            g.bench_with_input(
+                BenchmarkId::new("horspool", pattern_len),
+                &pattern_len,
+                |b, _i| b.iter(|| black_box(&horspool).find_iter(black_box(&haystack)).count()),
+            );
+            g.bench_with_input(
+                BenchmarkId::new("boyer_moore", pattern_len),
+                &pattern_len,
+                |b, _i| {
+                    b.iter(|| {
+                        black_box(&boyer_moore)
+                            .find_iter(black_box(&haystack))
+                            .count()
+                    })
+                },
+            );
+        }
+        g.finish();
+    }
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default()
+        .measurement_time(Duration::from_secs(1))
+        .warm_up_time(Duration::from_millis(100))
+        .with_measurement(create_measurement())
+        ;
+    targets = small_slices, layout, strings
+);
+criterion_main!(benches);
@@ -0,0 +1,157 @@
+//! Binary search in a sorted slice
+
+use core::cmp::Ordering;
+use core::hint;
+
+/// Returns the index of an item equal to `needle`.
+///
+/// If there are several matches, any one of them may be returned. If there is
+/// no match, `Err` holds the index where `needle` could be inserted while
+/// keeping the slice sorted, same as [`slice::binary_search`].
+pub fn search<T: Ord>(slice: &[T], needle: &T) -> Result<usize, usize> {
+    search_by(slice, |it| it.cmp(needle))
+}
+
+/// Binary search with a comparator.
+///
+/// `f` returns the ordering of an item relative to the searched one, that is
+/// [`Ordering::Less`] if the item is before the target. The slice must be
+/// sorted consistently with `f`.
+///
+/// Returns `Err` with the insertion point if there is no match, see [`search`].
+pub fn search_by<T, F>(slice: &[T], mut f: F) -> Result<usize, usize>
+where
+    F: FnMut(&T) -> Ordering,
+{
+    let mut l = 0;
+    let mut r = slice.len();
+
+    while l < r {
+        let mid = l + (r - l) / 2;
+        match f(&slice[mid]) {
+            Ordering::Less => l = mid + 1,
+            Ordering::Equal => return Ok(mid),
+            Ordering::Greater => r = mid,
+        }
+    }
+
+    // all items before `l` are less and all after it are greater
+    Err(l)
+}
+
+/// Binary search for an item whose key extracted by `f` is equal to `key`.
+///
+/// The slice must be sorted by the key. Returns `Err` with the insertion point
+/// if there is no match, see [`search`].
+pub fn search_by_key<T, B, F>(slice: &[T], key: &B, mut f: F) -> Result<usize, usize>
+where
+    B: Ord,
+    F: FnMut(&T) -> B,
+{
+    search_by(slice, |it| f(it).cmp(key))
+}
+
+/// Binary search without unpredictable branches.
+///
+/// The loop always runs `log2(n)` times and only moves the lower bound, which
+/// is selected with [`hint::select_unpredictable`] so it compiles to a
+/// conditional move. It does more comparisons than
+/// [`search`] but doesn't stall on mispredicted branches, which is faster for
+/// slices that fit in the cache.
+///
+/// If there are several matches, the last one is returned. Returns `Err` with
+/// the insertion point if there is no match, see [`search`].
+pub fn search_branchless<T: Ord>(slice: &[T], needle: &T) -> Result<usize, usize> {
+    search_branchless_by(slice, |it| it.cmp(needle))
+}
+
+/// Branchless binary search with a comparator as for [`search_by`].
+///
+/// Returns the last match or `Err` with the insertion point, see
+/// [`search_branchless`].
+pub fn search_branchless_by<T, F>(slice: &[T], mut f: F) -> Result<usize, usize>
+where
+    F: FnMut(&T) -> Ordering,
+{
+    if slice.is_empty() {
+        return Err(0);
+    }
+
+    // INVARIANTS: items before `base` are not greater, the target is in
+    //  `base..base + size` if it's in the slice
+    let mut base = 0;
+    let mut size = slice.len();
+    while size > 1 {
+        let half = size / 2;
+        let mid = base + half;
+        base = hint::select_unpredictable(f(&slice[mid]) == Ordering::Greater, base, mid);
+        size -= half;
+    }
+
+    match f(&slice[base]) {
+        Ordering::Less => Err(base + 1),
+        Ordering::Equal => Ok(base),
+        // `base` only moves to items which are not greater, so it's 0
+        Ordering::Greater => Err(base),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generic() {
+        let v = ["a", "b", "d", "e"];
+        assert_eq!(search(&v, &"a"), Ok(0));
+        assert_eq!(search(&v, &"e"), Ok(3));
+        assert_eq!(search(&v, &"c"), Err(2));
+        assert_eq!(search(&v, &"f"), Err(4));
+        assert_eq!(search::<i32>(&[], &1), Err(0));
+
+        // reversed order
+        let v = [9, 7, 4, 1];
+        assert_eq!(search_by(&v, |it| 4.cmp(it)), Ok(2));
+        assert_eq!(search_by(&v, |it| 5.cmp(it)), Err(2));
+
+        let v = [(1, 'z'), (3, 'y'), (6, 'x')];
+        assert_eq!(search_by_key(&v, &3, |it| it.0), Ok(1));
+        assert_eq!(search_by_key(&v, &4, |it| it.0), Err(2));
+        assert_eq!(search_by_key(&v, &0, |it| it.0), Err(0));
+    }
+
+    #[test]
+    fn same_as_std() {
+        for len in 0..20 {
+            let v: Vec<_> = (0..len).map(|i| 2 * i).collect();
+            for needle in -1..2 * len + 1 {
+                assert_eq!(search(&v, &needle), v.binary_search(&needle));
+                assert_eq!(search_branchless(&v, &needle), v.binary_search(&needle));
+            }
+        }
+    }
+
+    #[test]
+    fn insert_sorted() {
+        let mut v = Vec::new();
+        for x in [5, 1, 4, 1, 3, 9, 2, 6] {
+            let (Ok(i) | Err(i)) = search(&v, &x);
+            v.insert(i, x);
+        }
+        assert_eq!(v, [1, 1, 2, 3, 4, 5, 6, 9]);
+    }
+
+    #[test]
+    fn branchless_duplicates() {
+        let v = [1, 2, 2, 2, 3, 3];
+        assert_eq!(search_branchless(&v, &2), Ok(3));
+        assert_eq!(search_branchless(&v, &3), Ok(5));
+        assert_eq!(search_branchless(&v, &0), Err(0));
+        assert_eq!(search_branchless(&v, &4), Err(6));
+        assert_eq!(search_branchless(&[7], &7), Ok(0));
+        assert_eq!(search_branchless::<i32>(&[], &7), Err(0));
+
+        let v = [9, 7, 4, 1];
+        assert_eq!(search_branchless_by(&v, |it| 5.cmp(it)), Err(2));
+    }
+}
@@ -0,0 +1,118 @@
+//! Exponential (galloping) search in a sorted slice
+//!
+//! The bound is doubled until it passes the target, then the last bracket is
+//! binary searched. Takes O(log i) comparisons where `i` is the index of the
+//! target, so it's faster than a plain binary search for targets near the
+//! start. This is what timsort and k-way merges use to find how many items to
+//! take from a run at once.
+
+use core::cmp::Ordering;
+
+use crate::binary;
+
+/// Returns the index of the first item for which `pred` returns `false`.
+///
+/// The slice must be partitioned by `pred`, all items for which it returns
+/// `true` must be before the items for which it returns `false`, same as for
+/// [`slice::partition_point`].
+///
+/// For example `gallop(slice, |x| x < key)` is the number of items less than
+/// `key` and `gallop(slice, |x| x <= key)` the number of items not greater
+/// than `key`.
+pub fn gallop<T, P>(slice: &[T], mut pred: P) -> usize
+where
+    P: FnMut(&T) -> bool,
+{
+    let len = slice.len();
+    let mut bound = 1;
+    while bound < len && pred(&slice[bound]) {
+        bound *= 2;
+    }
+
+    // `pred` is true at `bound / 2` (unless it's 0) and false at `bound`
+    let l = bound / 2;
+    let r = (bound + 1).min(len);
+    let (Ok(i) | Err(i)) = binary::search_by(&slice[l..r], |it| {
+        if pred(it) {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        }
+    });
+    l + i
+}
+
+/// Returns the index of the first item equal to `needle`.
+///
+/// If there is no match, `Err` holds the index where `needle` could be
+/// inserted while keeping the slice sorted.
+pub fn search<T: Ord>(slice: &[T], needle: &T) -> Result<usize, usize> {
+    search_by(slice, |it| it.cmp(needle))
+}
+
+/// Exponential search with a comparator as for [`binary::search_by`].
+///
+/// Returns the first match or `Err` with the insertion point, see
+/// [`search`].
+pub fn search_by<T, F>(slice: &[T], mut f: F) -> Result<usize, usize>
+where
+    F: FnMut(&T) -> Ordering,
+{
+    let i = gallop(slice, |it| f(it) == Ordering::Less);
+    match slice.get(i) {
+        Some(it) if f(it) == Ordering::Equal => Ok(i),
+        _ => Err(i),
+    }
+}
+
+/// Exponential search for an item whose key extracted by `f` is equal to
+/// `key`.
+///
+/// The slice must be sorted by the key. Returns the first match or `Err` with
+/// the insertion point, see [`search`].
+pub fn search_by_key<T, B, F>(slice: &[T], key: &B, mut f: F) -> Result<usize, usize>
+where
+    B: Ord,
+    F: FnMut(&T) -> B,
+{
+    search_by(slice, |it| f(it).cmp(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generic() {
+        let v = ["a", "b", "d", "e"];
+        assert_eq!(search(&v, &"a"), Ok(0));
+        assert_eq!(search(&v, &"e"), Ok(3));
+        assert_eq!(search(&v, &"c"), Err(2));
+        assert_eq!(search(&v, &"f"), Err(4));
+        assert_eq!(search::<i32>(&[], &1), Err(0));
+
+        let v = [(1, 'z'), (3, 'y'), (3, 'x'), (3, 'w'), (6, 'v')];
+        assert_eq!(search_by_key(&v, &3, |it| it.0), Ok(1));
+        assert_eq!(search_by_key(&v, &4, |it| it.0), Err(4));
+    }
+
+    #[test]
+    fn gallop_is_partition_point() {
+        for len in 0..70 {
+            // every value is repeated 3 times
+            let v: Vec<_> = (0..len).map(|i| i / 3).collect();
+            for key in -1..len / 3 + 2 {
+                assert_eq!(gallop(&v, |x| *x < key), v.partition_point(|x| *x < key));
+                assert_eq!(gallop(&v, |x| *x <= key), v.partition_point(|x| *x <= key));
+
+                let expected = v.partition_point(|x| *x < key);
+                let expected = if v.get(expected) == Some(&key) {
+                    Ok(expected)
+                } else {
+                    Err(expected)
+                };
+                assert_eq!(search(&v, &key), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Sorted items stored in Eytzinger (BFS) order
+//!
+//! The items are laid out like an implicit binary search tree: the root is at
+//! index 0 and the children of `k` are at `2k + 1` and `2k + 2`. A search
+//! walks down from the root, so the first few levels stay in the cache and
+//! the next nodes to visit are next to each other in memory, unlike the far
+//! apart midpoints of a binary search in a sorted slice.
+
+use core::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Eytzinger<T> {
+    // INVARIANTS: an in-order traversal of the implicit tree is sorted
+    data: Vec<T>,
+}
+
+impl<T> Default for Eytzinger<T> {
+    fn default() -> Self {
+        Self { data: Vec::new() }
+    }
+}
+
+impl<T> Eytzinger<T> {
+    /// Rearranges sorted items into BFS order.
+    ///
+    /// The items must be sorted, otherwise searches return unspecified
+    /// results.
+    pub fn from_sorted(sorted: Vec<T>) -> Self {
+        let n = sorted.len();
+        // `perm[k]` is the sorted index of the item at `k`
+        let mut perm = vec![0; n];
+        for (i, k) in in_order(n).into_iter().enumerate() {
+            perm[k] = i;
+        }
+
+        let mut sorted: Vec<_> = sorted.into_iter().map(Some).collect();
+        let data = perm.iter().map(|i| sorted[*i].take().unwrap()).collect();
+        Self { data }
+    }
+
+    /// Returns the items in sorted order.
+    pub fn into_sorted(self) -> Vec<T> {
+        let n = self.data.len();
+        let mut data: Vec<_> = self.data.into_iter().map(Some).collect();
+        in_order(n)
+            .into_iter()
+            .map(|k| data[k].take().unwrap())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Items in BFS order.
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// Iterate over the items in sorted order.
+    pub fn iter_sorted(&self) -> impl ExactSizeIterator<Item = &T> + '_ {
+        in_order(self.data.len()).into_iter().map(|k| &self.data[k])
+    }
+
+    /// Returns the index into [`as_slice`] of the first item for which `f`
+    /// doesn't return [`Ordering::Less`], `None` if there is no such item.
+    ///
+    /// [`as_slice`]: Self::as_slice
+    pub fn lower_bound_by<F>(&self, mut f: F) -> Option<usize>
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        // walk down to a leaf, go right if the node is less than the target
+        //
+        // `i` is the 1 based index, children of `i` are `2i` and `2i + 1`
+        let n = self.data.len();
+        let mut i = 1;
+        while i <= n {
+            i = 2 * i + usize::from(f(&self.data[i - 1]) == Ordering::Less);
+        }
+
+        // The path is the bits of `i`, 1 for going right. The answer is the
+        // last node where we went left, so drop the trailing right turns and
+        // the last left turn. If we always went right, `i` becomes 0.
+        i >>= i.trailing_ones() + 1;
+        i.checked_sub(1)
+    }
+
+    /// Returns the index into [`as_slice`] of an item equal to `needle`.
+    ///
+    /// If there are several matches, the first one in sorted order is
+    /// returned.
+    ///
+    /// [`as_slice`]: Self::as_slice
+    pub fn search(&self, needle: &T) -> Option<usize>
+    where
+        T: Ord,
+    {
+        self.search_by(|it| it.cmp(needle))
+    }
+
+    /// Search with a comparator as for [`binary::search_by`].
+    ///
+    /// [`binary::search_by`]: crate::binary::search_by
+    pub fn search_by<F>(&self, mut f: F) -> Option<usize>
+    where
+        F: FnMut(&T) -> Ordering,
+    {
+        let k = self.lower_bound_by(&mut f)?;
+        (f(&self.data[k]) == Ordering::Equal).then_some(k)
+    }
+
+    /// Returns the item equal to `needle`.
+    pub fn get(&self, needle: &T) -> Option<&T>
+    where
+        T: Ord,
+    {
+        self.search(needle).map(|k| &self.data[k])
+    }
+
+    pub fn contains(&self, needle: &T) -> bool
+    where
+        T: Ord,
+    {
+        self.search(needle).is_some()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for Eytzinger<T> {
+    /// Sorts the items and rearranges them into BFS order.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut sorted: Vec<_> = iter.into_iter().collect();
+        sorted.sort();
+        Self::from_sorted(sorted)
+    }
+}
+
+/// Returns the BFS indices of a tree with `n` nodes in sorted order.
+fn in_order(n: usize) -> Vec<usize> {
+    fn visit(n: usize, k: usize, out: &mut Vec<usize>) {
+        if k < n {
+            visit(n, 2 * k + 1, out);
+            out.push(k);
+            visit(n, 2 * k + 2, out);
+        }
+    }
+
+    let mut out = Vec::with_capacity(n);
+    visit(n, 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let e = Eytzinger::from_sorted((1..=7).collect());
+        assert_eq!(e.as_slice(), [4, 2, 6, 1, 3, 5, 7]);
+        let e = Eytzinger::from_sorted((1..=5).collect());
+        assert_eq!(e.as_slice(), [4, 2, 5, 1, 3]);
+        assert_eq!(
+            e.iter_sorted().copied().collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5]
+        );
+        assert_eq!(e.into_sorted(), [1, 2, 3, 4, 5]);
+
+        let e: Eytzinger<i32> = Eytzinger::default();
+        assert!(e.is_empty());
+        assert_eq!(e.search(&1), None);
+        assert_eq!(e.into_sorted(), []);
+    }
+
+    #[test]
+    fn search() {
+        let e: Eytzinger<_> = ["b", "d", "f", "h", "j", "l"].into_iter().collect();
+        assert_eq!(e.len(), 6);
+        for s in ["b", "d", "f", "h", "j", "l"] {
+            assert_eq!(e.get(&s), Some(&s));
+        }
+        for s in ["a", "c", "e", "g", "i", "k", "m"] {
+            assert!(!e.contains(&s));
+        }
+
+        assert_eq!(
+            e.lower_bound_by(|it| it.cmp(&"a")).map(|k| e.as_slice()[k]),
+            Some("b")
+        );
+        assert_eq!(
+            e.lower_bound_by(|it| it.cmp(&"g")).map(|k| e.as_slice()[k]),
+            Some("h")
+        );
+        assert_eq!(e.lower_bound_by(|it| it.cmp(&"m")), None);
+    }
+
+    #[test]
+    fn first_duplicate() {
+        let e = Eytzinger::from_sorted(vec![(1, 'a'), (2, 'b'), (2, 'c'), (2, 'd'), (3, 'e')]);
+        let k = e.search_by(|it| it.0.cmp(&2)).unwrap();
+        assert_eq!(e.as_slice()[k], (2, 'b'));
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 200;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_partition_point(
+                vec in proptest::collection::vec(0..100i32, 0..VEC_SIZE),
+                needle in 0..100i32,
+            ) {
+                let e: Eytzinger<_> = vec.iter().copied().collect();
+                let sorted = e.clone().into_sorted();
+                let mut expected = vec;
+                expected.sort();
+                assert_eq!(sorted, expected);
+                assert!(e.iter_sorted().eq(expected.iter()));
+
+                let i = expected.partition_point(|x| *x < needle);
+                assert_eq!(
+                    e.lower_bound_by(|it| it.cmp(&needle)).map(|k| e.as_slice()[k]),
+                    expected.get(i).copied()
+                );
+                assert_eq!(e.contains(&needle), expected.contains(&needle));
+            }
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Jump search in a sorted slice
+
+use core::cmp::Ordering;
+
+use crate::linear;
+
+/// Returns the index of the first item equal to `needle`.
+pub fn search<T: Ord>(slice: &[T], needle: &T) -> Option<usize> {
+    search_by(slice, |it| it.cmp(needle))
+}
+
+/// Jump search with block size sqrt(n).
+///
+/// Time complexity of O(sqrt(n)) since we are doing a maximum of sqrt(n) jumps
+/// + maximum of sqrt(n) steps in linear search
+///
+/// `f` is a comparator as for [`binary::search_by`].
+///
+/// [`binary::search_by`]: crate::binary::search_by
+pub fn search_by<T, F>(slice: &[T], f: F) -> Option<usize>
+where
+    F: FnMut(&T) -> Ordering,
+{
+    search_by_with_block_size(slice, default_block_size(slice.len()), f)
+}
+
+/// Jump search for an item whose key extracted by `f` is equal to `key`.
+///
+/// The slice must be sorted by the key.
+pub fn search_by_key<T, B, F>(slice: &[T], key: &B, mut f: F) -> Option<usize>
+where
+    B: Ord,
+    F: FnMut(&T) -> B,
+{
+    search_by(slice, |it| f(it).cmp(key))
+}
+
+/// Returns the index of the first item equal to `needle`, jumping over
+/// `block_size` items at a time.
+///
+/// # PANICS
+///
+/// * if `block_size == 0`
+pub fn search_with_block_size<T: Ord>(slice: &[T], needle: &T, block_size: usize) -> Option<usize> {
+    search_by_with_block_size(slice, block_size, |it| it.cmp(needle))
+}
+
+/// Jump search with a comparator which jumps over `block_size` items at a
+/// time.
+///
+/// Compares the last item of every block until it's not less than the target,
+/// then searches linearly within that block only. Takes at most
+/// `n / block_size + block_size` comparisons, which is the smallest with the
+/// default block size of sqrt(n).
+///
+/// # PANICS
+///
+/// * if `block_size == 0`
+pub fn search_by_with_block_size<T, F>(slice: &[T], block_size: usize, mut f: F) -> Option<usize>
+where
+    F: FnMut(&T) -> Ordering,
+{
+    assert!(block_size > 0, "block size must be at least 1");
+
+    let mut l = 0;
+    while l < slice.len() {
+        // the last block may be shorter
+        let last = l.saturating_add(block_size).min(slice.len()) - 1;
+        match f(&slice[last]) {
+            Ordering::Less => l = last + 1,
+            // all the items before `l` are less, so the first match is in this block
+            Ordering::Equal | Ordering::Greater => {
+                return linear::search_by(&slice[l..=last], f).map(|i| l + i);
+            }
+        }
+    }
+
+    None
+}
+
+/// Block size which minimizes the worst case number of comparisons.
+fn default_block_size(len: usize) -> usize {
+    (f64::sqrt(len as f64) as usize).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generic() {
+        let v = ["a", "b", "d", "e", "g", "h", "i", "k", "m"];
+        assert_eq!(search(&v, &"a"), Some(0));
+        assert_eq!(search(&v, &"g"), Some(4));
+        assert_eq!(search(&v, &"h"), Some(5));
+        assert_eq!(search(&v, &"c"), None);
+
+        let v = [(1, 'z'), (3, 'y'), (6, 'x'), (7, 'w'), (8, 'v')];
+        assert_eq!(search_by_key(&v, &7, |it| it.0), Some(3));
+        assert_eq!(search_by_key(&v, &5, |it| it.0), None);
+    }
+
+    #[test]
+    fn last_block() {
+        // 10 items with blocks of 3, the last block has only 1 item
+        let v: Vec<_> = (0..10).collect();
+        for x in 0..10 {
+            assert_eq!(search(&v, &x), Some(x as usize));
+            assert_eq!(search_with_block_size(&v, &x, 3), Some(x as usize));
+        }
+        assert_eq!(search(&v, &10), None);
+        assert_eq!(search(&v, &-1), None);
+        assert_eq!(search(&[1], &2), None);
+        assert_eq!(search::<i32>(&[], &2), None);
+        assert_eq!(search_with_block_size(&v, &9, usize::MAX), Some(9));
+    }
+
+    #[test]
+    fn first_match() {
+        let v = [1, 2, 2, 2, 2, 2, 2, 3];
+        assert_eq!(search_with_block_size(&v, &2, 3), Some(1));
+        assert_eq!(search_with_block_size(&v, &2, 1), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "block size must be at least 1")]
+    fn zero_block_size() {
+        search_with_block_size(&[1, 2], &1, 0);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+        use crate::binary;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn against_binary_search(
+                mut vec in proptest::collection::vec(0..500i32, 0..VEC_SIZE),
+                needle in -1..501i32,
+                block_size in 1..50usize,
+            ) {
+                vec.sort();
+                let expected = binary::search(&vec, &needle);
+                // jump search returns the first match
+                let first = vec.partition_point(|x| *x < needle);
+                let expected = expected.map(|_| first).ok();
+
+                assert_eq!(search(&vec, &needle), expected);
+                assert_eq!(search_with_block_size(&vec, &needle, block_size), expected);
+            }
+        );
+    }
+}
@@ -2,62 +2,46 @@
 #![deny(rust_2018_idioms)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
-pub fn linear_search(slice: &[i32], needle: i32) -> Option<usize> {
-    for (i, it) in slice.iter().enumerate() {
-        if *it == needle {
-            return Some(i);
-        }
-    }
+pub mod binary;
+pub mod exponential;
+pub mod eytzinger;
+pub mod jump;
+pub mod linear;
+pub mod rotated;
+pub mod saddleback;
+pub mod simd;
+pub mod strings;
 
-    None
+pub fn linear_search(slice: &[i32], needle: i32) -> Option<usize> {
+    linear::search(slice, &needle)
 }
 
+/// Binary search, see [`binary::search`] for a version which also returns the
+/// insertion point.
 pub fn binary_search(slice: &[i32], needle: i32) -> Option<usize> {
-    if slice.is_empty() {
-        return None;
-    }
-
-    let mut l = 0;
-    let mut r = slice.len();
-    let mut mid = r / 2;
+    binary::search(slice, &needle).ok()
+}
 
-    while l < r {
-        match needle.cmp(&slice[mid]) {
-            core::cmp::Ordering::Less => r = mid,
-            core::cmp::Ordering::Equal => return Some(mid),
-            core::cmp::Ordering::Greater => l = mid + 1,
-        }
+/// Binary search without unpredictable branches, see
+/// [`binary::search_branchless`].
+pub fn binary_search_branchless(slice: &[i32], needle: i32) -> Option<usize> {
+    binary::search_branchless(slice, &needle).ok()
+}
 
-        mid = l + (r - l) / 2;
-    }
+/// Binary search in a sorted slice rotated at an unknown index, see
+/// [`rotated::search`].
+pub fn binary_search_rotated(slice: &[i32], needle: i32) -> Option<usize> {
+    rotated::search(slice, &needle)
+}
 
-    None
+/// Exponential search, see [`exponential::search`].
+pub fn exponential_search(slice: &[i32], needle: i32) -> Option<usize> {
+    exponential::search(slice, &needle).ok()
 }
 
-/// Jump search with jump size sqrt(n).
-///
-/// Time complexity of O(sqrt(n)) since we are doing a maximum of sqrt(n) jumps
-/// + maximum of sqrt(n) steps in linear search
+/// Jump search with jump size sqrt(n), see [`jump::search_by`].
 pub fn jump_search(slice: &[i32], needle: i32) -> Option<usize> {
-    if slice.is_empty() {
-        return None;
-    }
-
-    let size = slice.len();
-    let jump_size = f64::sqrt(size as f64) as usize;
-    let mut l = 0;
-
-    while l < size {
-        let mid = l + jump_size;
-        match needle.cmp(&slice[mid]) {
-            core::cmp::Ordering::Less => return linear_search(&slice[l..], needle),
-            core::cmp::Ordering::Equal => return Some(mid),
-            core::cmp::Ordering::Greater => {}
-        }
-        l = mid;
-    }
-
-    None
+    jump::search(slice, &needle)
 }
 
 #[cfg(test)]
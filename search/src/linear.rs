@@ -0,0 +1,50 @@
+//! Linear search, the slice doesn't need to be sorted
+
+use core::cmp::Ordering;
+
+/// Returns the index of the first item equal to `needle`.
+pub fn search<T: PartialEq>(slice: &[T], needle: &T) -> Option<usize> {
+    slice.iter().position(|it| it == needle)
+}
+
+/// Returns the index of the first item for which `f` returns
+/// [`Ordering::Equal`].
+///
+/// `f` has the same signature as for [`binary::search_by`], so that the
+/// searches can be used interchangeably.
+///
+/// [`binary::search_by`]: crate::binary::search_by
+pub fn search_by<T, F>(slice: &[T], mut f: F) -> Option<usize>
+where
+    F: FnMut(&T) -> Ordering,
+{
+    slice.iter().position(|it| f(it) == Ordering::Equal)
+}
+
+/// Returns the index of the first item whose key extracted by `f` is equal
+/// to `key`.
+pub fn search_by_key<T, B, F>(slice: &[T], key: &B, mut f: F) -> Option<usize>
+where
+    B: PartialEq,
+    F: FnMut(&T) -> B,
+{
+    slice.iter().position(|it| f(it) == *key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsorted() {
+        let v = ["c", "a", "b", "a"];
+        assert_eq!(search(&v, &"a"), Some(1));
+        assert_eq!(search(&v, &"c"), Some(0));
+        assert_eq!(search(&v, &"d"), None);
+        assert_eq!(search::<i32>(&[], &1), None);
+
+        assert_eq!(search_by(&v, |it| it.cmp(&"b")), Some(2));
+        assert_eq!(search_by_key(&v, &1, |it| it.len()), Some(0));
+        assert_eq!(search_by_key(&v, &2, |it| it.len()), None);
+    }
+}
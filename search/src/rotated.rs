@@ -0,0 +1,129 @@
+//! Binary search in a sorted slice rotated at an unknown index
+//!
+//! For example `[4, 5, 6, 1, 2, 3]` is `[1, 2, 3, 4, 5, 6]` rotated left by
+//! 3. The rotation point is found by a binary search first, then both sorted
+//! parts are binary searched.
+
+use core::cmp::Ordering;
+
+use crate::binary;
+
+/// Returns the index where the sorted order starts, that is `slice[p..]`
+/// followed by `slice[..p]` is sorted.
+///
+/// The slice must be a rotation of a sorted slice. Takes O(log n) comparisons
+/// if the items are distinct. With duplicates equal to the last item of the
+/// searched range the range can only be shrunk by one, so it takes O(n) in the
+/// worst case.
+pub fn pivot<T: Ord>(slice: &[T]) -> usize {
+    if slice.is_empty() {
+        return 0;
+    }
+
+    let mut l = 0;
+    let mut r = slice.len() - 1;
+    // INVARIANTS: the pivot is in `l..=r`
+    while l < r {
+        let mid = l + (r - l) / 2;
+        match slice[mid].cmp(&slice[r]) {
+            // `mid` is before the pivot
+            Ordering::Greater => l = mid + 1,
+            // `slice[mid..=r]` is sorted, the pivot can't be after `mid`
+            Ordering::Less => r = mid,
+            Ordering::Equal => {
+                // `r` is the pivot only if it's the only decrease
+                if slice[r - 1] > slice[r] {
+                    return r;
+                }
+                // otherwise `slice[r]` is a duplicate of `slice[r - 1]` and
+                // can be dropped
+                r -= 1;
+            }
+        }
+    }
+
+    l
+}
+
+/// Returns the index of an item equal to `needle` in a rotated sorted slice.
+pub fn search<T: Ord>(slice: &[T], needle: &T) -> Option<usize> {
+    let p = pivot(slice);
+    let (before, after) = slice.split_at(p);
+    if let Ok(i) = binary::search(after, needle) {
+        return Some(p + i);
+    }
+    binary::search(before, needle).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotated() {
+        let v = [4, 5, 6, 1, 2, 3];
+        assert_eq!(pivot(&v), 3);
+        for (i, x) in v.iter().enumerate() {
+            assert_eq!(search(&v, x), Some(i));
+        }
+        assert_eq!(search(&v, &0), None);
+        assert_eq!(search(&v, &7), None);
+
+        assert_eq!(pivot(&[1, 2, 3]), 0);
+        assert_eq!(pivot(&[3, 1, 2]), 1);
+        assert_eq!(pivot(&[2, 3, 1]), 2);
+        assert_eq!(pivot::<i32>(&[]), 0);
+        assert_eq!(search::<i32>(&[], &1), None);
+    }
+
+    #[test]
+    fn duplicates() {
+        assert_eq!(pivot(&[1, 1, 1, 0, 1]), 3);
+        assert_eq!(pivot(&[1, 0, 1, 1, 1]), 1);
+        assert_eq!(pivot(&[2, 2, 2, 0, 2, 2]), 3);
+        assert_eq!(search(&[2, 2, 2, 0, 2, 2], &0), Some(3));
+        assert!(search(&[1, 1, 1], &1).is_some());
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 200;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn against_linear_search(
+                mut vec in proptest::collection::vec(0..20i32, 0..VEC_SIZE),
+                mid in any::<prop::sample::Index>(),
+                needle in -1..21i32,
+            ) {
+                vec.sort();
+                let mid = mid.index(vec.len() + 1);
+                vec.rotate_left(mid);
+
+                let p = pivot(&vec);
+                let mut unrotated = vec.clone();
+                unrotated.rotate_left(p);
+                assert!(unrotated.windows(2).all(|w| w[0] <= w[1]));
+
+                match search(&vec, &needle) {
+                    Some(i) => assert_eq!(vec[i], needle),
+                    None => assert!(!vec.contains(&needle)),
+                }
+            }
+        );
+    }
+}
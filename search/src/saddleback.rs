@@ -0,0 +1,139 @@
+//! Saddleback search in a matrix with sorted rows and columns
+//!
+//! The matrix is stored as a flat row-major slice. The search starts from the
+//! top right corner and moves either left or down on every step, so it takes
+//! at most `rows + cols` comparisons.
+
+use core::cmp::Ordering;
+
+/// Returns the `(row, col)` of an item equal to `needle`.
+///
+/// Every row and every column of the `rows` x `cols` row-major `matrix` must
+/// be sorted.
+///
+/// # PANICS
+///
+/// * if `matrix.len() != rows * cols`
+pub fn search<T: Ord>(
+    matrix: &[T],
+    rows: usize,
+    cols: usize,
+    needle: &T,
+) -> Option<(usize, usize)> {
+    search_by(matrix, rows, cols, |it| it.cmp(needle))
+}
+
+/// Saddleback search with a comparator as for [`binary::search_by`].
+///
+/// # PANICS
+///
+/// * if `matrix.len() != rows * cols`
+///
+/// [`binary::search_by`]: crate::binary::search_by
+pub fn search_by<T, F>(matrix: &[T], rows: usize, cols: usize, mut f: F) -> Option<(usize, usize)>
+where
+    F: FnMut(&T) -> Ordering,
+{
+    assert_eq!(
+        Some(matrix.len()),
+        rows.checked_mul(cols),
+        "matrix must have rows * cols items"
+    );
+
+    let mut row = 0;
+    let mut col = cols;
+    // INVARIANTS: the target isn't in rows before `row` or in columns from `col`
+    while row < rows && col > 0 {
+        match f(&matrix[row * cols + col - 1]) {
+            // everything below is greater too
+            Ordering::Greater => col -= 1,
+            // everything to the left is less too
+            Ordering::Less => row += 1,
+            Ordering::Equal => return Some((row, col - 1)),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small() {
+        #[rustfmt::skip]
+        let m = [
+            1, 4, 7, 11,
+            2, 5, 8, 12,
+            3, 6, 9, 16,
+            10, 13, 14, 17,
+        ];
+        assert_eq!(search(&m, 4, 4, &5), Some((1, 1)));
+        assert_eq!(search(&m, 4, 4, &1), Some((0, 0)));
+        assert_eq!(search(&m, 4, 4, &17), Some((3, 3)));
+        assert_eq!(search(&m, 4, 4, &10), Some((3, 0)));
+        assert_eq!(search(&m, 4, 4, &11), Some((0, 3)));
+        assert_eq!(search(&m, 4, 4, &15), None);
+        assert_eq!(search(&m, 4, 4, &0), None);
+        assert_eq!(search(&m, 4, 4, &18), None);
+
+        // not square
+        let m = [1, 3, 5, 2, 4, 6];
+        assert_eq!(search(&m, 2, 3, &4), Some((1, 1)));
+        assert_eq!(search(&m, 2, 3, &5), Some((0, 2)));
+        assert_eq!(search(&m, 2, 3, &7), None);
+        let m = [1, 4, 2, 5, 3, 6];
+        assert_eq!(search(&m, 3, 2, &3), Some((2, 0)));
+        assert_eq!(search::<i32>(&[], 0, 5, &1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "matrix must have rows * cols items")]
+    fn wrong_size() {
+        search(&[1, 2, 3], 2, 2, &1);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const SIZE: usize = 30;
+        #[cfg(miri)]
+        const SIZE: usize = 8;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn against_linear_search(
+                (rows, cols, steps) in (0..SIZE, 0..SIZE).prop_flat_map(|(r, c)| {
+                    (Just(r), Just(c), proptest::collection::vec(0..3i32, r * c))
+                }),
+                needle in 0..100i32,
+            ) {
+                // every item is at least as large as the items above and to the left
+                let mut m = steps;
+                for i in 0..rows * cols {
+                    let (r, c) = (i / cols, i % cols);
+                    let up = if r > 0 { m[i - cols] } else { 0 };
+                    let left = if c > 0 { m[i - 1] } else { 0 };
+                    m[i] += up.max(left);
+                }
+
+                match search(&m, rows, cols, &needle) {
+                    Some((r, c)) => assert_eq!(m[r * cols + c], needle),
+                    None => assert!(!m.contains(&needle)),
+                }
+            }
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Linear search comparing several items at once with SIMD instructions
+//!
+//! On x86_64 the slice is compared 16 bytes at a time with SSE2, which is
+//! always available there. Other targets fall back to the scalar
+//! [`linear::search`].
+//!
+//! [`linear::search`]: crate::linear::search
+
+/// Primitive integers which can be compared in SIMD lanes.
+pub trait SimdElement: Copy + PartialEq + private::Sealed {}
+
+macro_rules! impl_simd_element {
+    ($($t:ty),*) => {
+        $(
+            impl SimdElement for $t {}
+            impl private::Sealed for $t {}
+        )*
+    };
+}
+
+impl_simd_element!(u8, i8, u32, i32, u64, i64);
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Returns the index of the first item equal to `needle`.
+pub fn linear_search_simd<T: SimdElement>(slice: &[T], needle: T) -> Option<usize> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        sse2::search(slice, needle)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        crate::linear::search(slice, &needle)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use core::arch::x86_64::{
+        __m128i, _mm_and_si128, _mm_cmpeq_epi8, _mm_cmpeq_epi32, _mm_loadu_si128,
+        _mm_movemask_epi8, _mm_set1_epi8, _mm_set1_epi32, _mm_set1_epi64x, _mm_shuffle_epi32,
+    };
+    use core::mem;
+
+    use super::SimdElement;
+    use crate::linear;
+
+    const VECTOR_SIZE: usize = mem::size_of::<__m128i>();
+
+    pub(super) fn search<T: SimdElement>(slice: &[T], needle: T) -> Option<usize> {
+        let size = mem::size_of::<T>();
+        let lanes = VECTOR_SIZE / size;
+        // SAFETY: SSE2 is always available on x86_64
+        let splat = unsafe {
+            match size {
+                1 => _mm_set1_epi8(transmute_int::<T, i8>(needle)),
+                4 => _mm_set1_epi32(transmute_int::<T, i32>(needle)),
+                8 => _mm_set1_epi64x(transmute_int::<T, i64>(needle)),
+                _ => unreachable!("only 1, 4 and 8 byte integers implement SimdElement"),
+            }
+        };
+
+        let mut i = 0;
+        while i + lanes <= slice.len() {
+            // SAFETY: SSE2 is always available on x86_64, `slice[i..i + lanes]`
+            //  is in bounds and has `VECTOR_SIZE` bytes, `loadu` doesn't need
+            //  any alignment
+            let mask = unsafe {
+                let chunk = _mm_loadu_si128(slice.as_ptr().add(i).cast::<__m128i>());
+                let eq = match size {
+                    1 => _mm_cmpeq_epi8(chunk, splat),
+                    4 => _mm_cmpeq_epi32(chunk, splat),
+                    // SSE2 has no 64 bit compare, both 32 bit halves must be equal
+                    _ => {
+                        let eq = _mm_cmpeq_epi32(chunk, splat);
+                        _mm_and_si128(eq, _mm_shuffle_epi32::<0b10_11_00_01>(eq))
+                    }
+                };
+                // one bit for every byte, all bytes of an equal lane are set
+                _mm_movemask_epi8(eq)
+            };
+            if mask != 0 {
+                return Some(i + mask.trailing_zeros() as usize / size);
+            }
+            i += lanes;
+        }
+
+        linear::search(&slice[i..], &needle).map(|j| i + j)
+    }
+
+    /// Reinterprets an integer as another one of the same size.
+    ///
+    /// # SAFETY
+    ///
+    /// * `T` and `U` must be primitive integers of the same size
+    unsafe fn transmute_int<T: Copy, U: Copy>(x: T) -> U {
+        debug_assert_eq!(mem::size_of::<T>(), mem::size_of::<U>());
+        // SAFETY: all bit patterns are valid integers
+        unsafe { mem::transmute_copy(&x) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check<T: SimdElement + core::fmt::Debug>(slice: &[T], needle: T) {
+        let expected = slice.iter().position(|x| *x == needle);
+        assert_eq!(
+            linear_search_simd(slice, needle),
+            expected,
+            "{slice:?} {needle:?}"
+        );
+    }
+
+    #[test]
+    fn every_position() {
+        // positions in the vectors and in the scalar tail
+        for len in 0..40usize {
+            let v8: Vec<u8> = (0..len as u8).collect();
+            let v32: Vec<i32> = (0..len as i32).map(|x| -x).collect();
+            let v64: Vec<u64> = (0..len as u64).map(|x| x << 40).collect();
+            for x in 0..len + 1 {
+                check(&v8, x as u8);
+                check(&v32, -(x as i32));
+                check(&v64, (x as u64) << 40);
+            }
+        }
+    }
+
+    #[test]
+    fn first_match() {
+        let v = [1u8, 2, 3, 2, 1, 2, 3, 2, 1, 2, 3, 2, 1, 2, 3, 2, 1, 7, 7];
+        check(&v, 7);
+        check(&v, 3);
+        check(&[5i64, 5, 5], 5);
+        check(&[-1i8; 20], -1);
+    }
+
+    #[test]
+    fn halves_of_u64() {
+        // only one of the 32 bit halves matches
+        let v = [1u64 << 32, 1, 3 << 32 | 1, 1 << 32 | 1];
+        check(&v, 1 << 32 | 1);
+        check(&v, 1);
+        check(&[u64::MAX - 1], u64::MAX);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 200;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_linear_search(
+                vec in proptest::collection::vec(0..50u64, 0..VEC_SIZE),
+                needle in 0..50u64,
+            ) {
+                check(&vec, needle);
+                let vec32: Vec<_> = vec.iter().map(|x| *x as i32).collect();
+                check(&vec32, needle as i32);
+                let vec8: Vec<_> = vec.iter().map(|x| *x as u8).collect();
+                check(&vec8, needle as u8);
+            }
+        );
+    }
+}
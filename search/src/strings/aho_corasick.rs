@@ -0,0 +1,317 @@
+//! Aho–Corasick multi-pattern search
+//!
+//! The patterns are put into a trie with one node per byte. Every node also
+//! gets a failure link to the node of the longest proper suffix of its path
+//! which is in the trie, like the failure function of [KMP]. The haystack is
+//! then read one byte at a time following the trie edges and the failure
+//! links on mismatches, so all matches of all patterns are found in one pass
+//! in O(n + m + z) time, where `n` is the length of the haystack, `m` the total
+//! length of the patterns and `z` the number of matches.
+//!
+//! The trie is a plain one with a node for every byte. The tree crate's radix
+//! trie merges nodes with a single child, but the automaton needs a state
+//! between every two bytes for the failure links to point to.
+//!
+//! [KMP]: crate::strings::kmp
+
+const ROOT: usize = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Node {
+    /// Edges sorted by their byte
+    children: Vec<(u8, usize)>,
+    /// Node of the longest proper suffix of this node's path which is in the
+    /// trie
+    fail: usize,
+    /// Indices of the patterns which are suffixes of this node's path, the
+    /// longest first
+    matches: Vec<usize>,
+}
+
+impl Node {
+    fn new() -> Self {
+        Self {
+            children: Vec::new(),
+            fail: ROOT,
+            matches: Vec::new(),
+        }
+    }
+
+    fn child(&self, b: u8) -> Option<usize> {
+        self.children
+            .binary_search_by_key(&b, |(c, _)| *c)
+            .ok()
+            .map(|i| self.children[i].1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AhoCorasick {
+    // INVARIANTS:
+    //  * `nodes[ROOT]` is the root of the trie of all patterns
+    //  * `fail` and `matches` of all nodes are as described in `Node`
+    //  * `pattern_lens[i]` is the length of the `i`th pattern
+    nodes: Vec<Node>,
+    pattern_lens: Vec<usize>,
+}
+
+impl AhoCorasick {
+    /// Builds the automaton of `patterns`.
+    ///
+    /// Patterns are identified by their index in `patterns`. Duplicate and
+    /// empty patterns are allowed.
+    pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        let mut nodes = vec![Node::new()];
+        let mut pattern_lens = Vec::new();
+
+        for (index, pattern) in patterns.into_iter().enumerate() {
+            let pattern = pattern.as_ref();
+            let mut node = ROOT;
+            for b in pattern {
+                node = match nodes[node].children.binary_search_by_key(b, |(c, _)| *c) {
+                    Ok(i) => nodes[node].children[i].1,
+                    Err(i) => {
+                        nodes.push(Node::new());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.insert(i, (*b, child));
+                        child
+                    }
+                };
+            }
+            nodes[node].matches.push(index);
+            pattern_lens.push(pattern.len());
+        }
+
+        let mut ac = Self {
+            nodes,
+            pattern_lens,
+        };
+        ac.build_failure_links();
+        ac
+    }
+
+    /// Sets the failure links and merges the matches of the failure nodes
+    /// into the matches of each node.
+    fn build_failure_links(&mut self) {
+        // Failure links always go to shallower nodes, so going in BFS order
+        // the links and matches of the failure nodes are already final.
+        let mut queue = std::collections::VecDeque::new();
+        queue.push_back(ROOT);
+
+        while let Some(parent) = queue.pop_front() {
+            for i in 0..self.nodes[parent].children.len() {
+                let (b, child) = self.nodes[parent].children[i];
+                let fail = if parent == ROOT {
+                    ROOT
+                } else {
+                    self.next_state(self.nodes[parent].fail, b)
+                };
+
+                let inherited = self.nodes[fail].matches.clone();
+                let node = &mut self.nodes[child];
+                node.fail = fail;
+                node.matches.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    /// Returns the state after reading `b` in `state`.
+    fn next_state(&self, mut state: usize, b: u8) -> usize {
+        loop {
+            if let Some(child) = self.nodes[state].child(b) {
+                return child;
+            }
+            if state == ROOT {
+                return ROOT;
+            }
+            state = self.nodes[state].fail;
+        }
+    }
+
+    /// Number of patterns
+    pub fn pattern_count(&self) -> usize {
+        self.pattern_lens.len()
+    }
+
+    /// Length of the pattern at `index`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `index >= self.pattern_count()`
+    pub fn pattern_len(&self, index: usize) -> usize {
+        self.pattern_lens[index]
+    }
+
+    /// Returns `true` if any pattern occurs in `haystack`.
+    pub fn is_match<H: AsRef<[u8]> + ?Sized>(&self, haystack: &H) -> bool {
+        self.find_iter(haystack).next().is_some()
+    }
+
+    /// Iterate over all matches in `haystack` as `(pattern_index, position)`.
+    ///
+    /// See [`stream_find_iter`](Self::stream_find_iter) for the order of the
+    /// matches.
+    pub fn find_iter<'a, H: AsRef<[u8]> + ?Sized>(
+        &'a self,
+        haystack: &'a H,
+    ) -> FindIter<'a, core::iter::Copied<core::slice::Iter<'a, u8>>> {
+        self.stream_find_iter(haystack.as_ref().iter().copied())
+    }
+
+    /// Iterate over all matches in a stream of bytes as
+    /// `(pattern_index, position)`.
+    ///
+    /// Every byte is read once and nothing is buffered, so the haystack
+    /// doesn't need to be in memory. Matches are returned in the order of
+    /// their end positions, matches ending at the same position longest
+    /// first. Matches may overlap, an empty pattern matches at every position
+    /// from 0 to the length of the haystack.
+    pub fn stream_find_iter<I>(&self, bytes: I) -> FindIter<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        FindIter {
+            ac: self,
+            bytes: bytes.into_iter(),
+            state: ROOT,
+            end: 0,
+            // empty patterns match before the first byte
+            next_match: 0,
+        }
+    }
+}
+
+/// Iterator over `(pattern_index, position)` of matches, created by
+/// [`AhoCorasick::find_iter`] and [`AhoCorasick::stream_find_iter`].
+#[derive(Debug, Clone)]
+pub struct FindIter<'a, I> {
+    // INVARIANTS: `end` bytes have been read and the automaton is in `state`,
+    //  `matches[..next_match]` of `state` have been returned
+    ac: &'a AhoCorasick,
+    bytes: I,
+    state: usize,
+    end: usize,
+    next_match: usize,
+}
+
+impl<I> Iterator for FindIter<'_, I>
+where
+    I: Iterator<Item = u8>,
+{
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let matches = &self.ac.nodes[self.state].matches;
+            if let Some(index) = matches.get(self.next_match) {
+                self.next_match += 1;
+                return Some((*index, self.end - self.ac.pattern_lens[*index]));
+            }
+
+            let b = self.bytes.next()?;
+            self.state = self.ac.next_state(self.state, b);
+            self.end += 1;
+            self.next_match = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic() {
+        let ac = AhoCorasick::new(["he", "she", "his", "hers"]);
+        assert_eq!(ac.pattern_count(), 4);
+        assert_eq!(ac.pattern_len(3), 4);
+        assert_eq!(
+            ac.find_iter("ushers").collect::<Vec<_>>(),
+            [(1, 1), (0, 2), (3, 2)]
+        );
+        assert_eq!(
+            ac.find_iter("ahishers").collect::<Vec<_>>(),
+            [(2, 1), (1, 3), (0, 4), (3, 4)]
+        );
+        assert!(ac.is_match("this"));
+        assert!(!ac.is_match("hi ser"));
+    }
+
+    #[test]
+    fn overlapping_and_duplicates() {
+        let ac = AhoCorasick::new(["aa", "a", "aa"]);
+        assert_eq!(
+            ac.find_iter("aaa").collect::<Vec<_>>(),
+            [(1, 0), (0, 0), (2, 0), (1, 1), (0, 1), (2, 1), (1, 2)]
+        );
+
+        let ac = AhoCorasick::new(["", "b"]);
+        assert_eq!(
+            ac.find_iter("ab").collect::<Vec<_>>(),
+            [(0, 0), (0, 1), (1, 1), (0, 2)]
+        );
+
+        let ac = AhoCorasick::new(Vec::<&str>::new());
+        assert!(!ac.is_match("abc"));
+    }
+
+    #[test]
+    fn stream() {
+        let ac = AhoCorasick::new([b"\x01\x02".as_slice(), b"\x02"]);
+        let bytes = (0..10u8).cycle().take(25);
+        assert_eq!(
+            ac.stream_find_iter(bytes).collect::<Vec<_>>(),
+            [(0, 1), (1, 2), (0, 11), (1, 12), (0, 21), (1, 22)]
+        );
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+        use crate::strings::naive;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 200;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_naive(
+                haystack in proptest::collection::vec(0..3u8, 0..VEC_SIZE),
+                patterns in proptest::collection::vec(
+                    proptest::collection::vec(0..3u8, 0..6),
+                    0..10
+                ),
+            ) {
+                let ac = AhoCorasick::new(&patterns);
+                let mut expected: Vec<_> = patterns
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, p)| {
+                        naive::find_all(&haystack, p).into_iter().map(move |pos| (i, pos))
+                    })
+                    .collect();
+                // by the end, then longest first
+                expected.sort_by_key(|(i, pos)| {
+                    let len = patterns[*i].len();
+                    (pos + len, usize::MAX - len, *i)
+                });
+                assert_eq!(ac.find_iter(&haystack).collect::<Vec<_>>(), expected);
+            }
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! Boyer–Moore and Boyer–Moore–Horspool substring search
+//!
+//! The pattern is compared from its last byte backwards. On a mismatch the
+//! pattern is shifted forward using tables built from the pattern, often by
+//! its whole length, so most of the haystack is never looked at. This makes
+//! them sublinear on average for long patterns and large alphabets.
+//!
+//! * [`Horspool`] only uses the bad character table of the haystack byte under
+//!   the last pattern byte. It's simpler and usually as fast in practice.
+//! * [`BoyerMoore`] uses the bad character table of the mismatched byte and
+//!   the good suffix table, which guarantees O(n + m) comparisons for the
+//!   first match even for periodic patterns.
+
+/// Returns the Horspool shifts of `pattern`.
+///
+/// `shifts[b]` is the distance from the last occurrence of `b` in
+/// `pattern[..m - 1]` to the end of the pattern, or `m` if there is none.
+/// That's how far the pattern can move if `b` is under its last byte.
+pub fn horspool_table(pattern: &[u8]) -> [usize; 256] {
+    let m = pattern.len();
+    let mut shifts = [m; 256];
+    for (i, b) in pattern.iter().enumerate().take(m.saturating_sub(1)) {
+        shifts[*b as usize] = m - 1 - i;
+    }
+    shifts
+}
+
+/// Returns the bad character table of `pattern`.
+///
+/// `last[b]` is 1 + the index of the last occurrence of `b` in `pattern`, or
+/// 0 if there is none.
+pub fn bad_character_table(pattern: &[u8]) -> [usize; 256] {
+    let mut last = [0; 256];
+    for (i, b) in pattern.iter().enumerate() {
+        last[*b as usize] = i + 1;
+    }
+    last
+}
+
+/// Returns the strong good suffix table of `pattern`.
+///
+/// If `pattern[j..]` has matched and `pattern[j - 1]` mismatched, the pattern
+/// can be shifted by `shifts[j]` so that the matched suffix lines up with
+/// another occurrence of it in the pattern which isn't preceded by the same
+/// byte, or with a prefix of the pattern. `shifts[0]` is the shift after a
+/// full match, that's the period of the pattern.
+pub fn good_suffix_table(pattern: &[u8]) -> Vec<usize> {
+    let m = pattern.len();
+    let mut shifts = vec![0; m + 1];
+    // `border[i]` is the start of the widest border of `pattern[i..]`
+    let mut border = vec![0; m + 1];
+
+    let mut i = m;
+    let mut j = m + 1;
+    border[i] = j;
+    while i > 0 {
+        while j <= m && pattern[i - 1] != pattern[j - 1] {
+            // the border `pattern[j..]` can't be extended to the left,
+            // shift to it if mismatched at `j - 1`
+            if shifts[j] == 0 {
+                shifts[j] = j - i;
+            }
+            j = border[j];
+        }
+        i -= 1;
+        j -= 1;
+        border[i] = j;
+    }
+
+    // the rest shift to the widest border of the whole pattern which fits
+    // in the matched suffix
+    let mut j = border[0];
+    for (i, shift) in shifts.iter_mut().enumerate() {
+        if *shift == 0 {
+            *shift = j;
+        }
+        if i == j {
+            j = border[j];
+        }
+    }
+    shifts
+}
+
+/// Pattern prepared for Horspool search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Horspool {
+    // INVARIANTS: `shifts == horspool_table(&pattern)`
+    pattern: Vec<u8>,
+    shifts: [usize; 256],
+}
+
+impl Horspool {
+    pub fn new<P: AsRef<[u8]> + ?Sized>(pattern: &P) -> Self {
+        let pattern = pattern.as_ref().to_vec();
+        let shifts = horspool_table(&pattern);
+        Self { pattern, shifts }
+    }
+
+    pub fn pattern(&self) -> &[u8] {
+        &self.pattern
+    }
+
+    /// Returns the position of the first match in `haystack`.
+    pub fn find_first<H: AsRef<[u8]> + ?Sized>(&self, haystack: &H) -> Option<usize> {
+        self.find_iter(haystack).next()
+    }
+
+    /// Iterate over the positions of all matches in `haystack`.
+    ///
+    /// Matches may overlap, an empty pattern matches at every position from 0
+    /// to `haystack.len()`.
+    pub fn find_iter<'a, H: AsRef<[u8]> + ?Sized>(&'a self, haystack: &'a H) -> HorspoolIter<'a> {
+        HorspoolIter {
+            searcher: self,
+            haystack: haystack.as_ref(),
+            pos: 0,
+        }
+    }
+}
+
+/// Iterator over match positions, created by [`Horspool::find_iter`].
+#[derive(Debug, Clone)]
+pub struct HorspoolIter<'a> {
+    // INVARIANTS: there are no matches before `pos` which haven't been
+    //  returned yet
+    searcher: &'a Horspool,
+    haystack: &'a [u8],
+    pos: usize,
+}
+
+impl Iterator for HorspoolIter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pattern = &self.searcher.pattern;
+        let m = pattern.len();
+        let Some(last) = m.checked_sub(1) else {
+            return empty_pattern_next(&mut self.pos, self.haystack.len());
+        };
+
+        while self.pos + m <= self.haystack.len() {
+            let window = &self.haystack[self.pos..self.pos + m];
+            let pos = self.pos;
+            // A window between `pos` and `pos + shift` would have the last
+            // byte under a pattern byte which isn't equal to it, so shifting
+            // also doesn't skip overlapping matches.
+            self.pos += self.searcher.shifts[window[last] as usize];
+            if window[last] == pattern[last] && window[..last] == pattern[..last] {
+                return Some(pos);
+            }
+        }
+
+        None
+    }
+}
+
+/// Pattern prepared for Boyer–Moore search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoyerMoore {
+    // INVARIANTS: `last == bad_character_table(&pattern)` and
+    //  `good_suffix == good_suffix_table(&pattern)`
+    pattern: Vec<u8>,
+    last: [usize; 256],
+    good_suffix: Vec<usize>,
+}
+
+impl BoyerMoore {
+    pub fn new<P: AsRef<[u8]> + ?Sized>(pattern: &P) -> Self {
+        let pattern = pattern.as_ref().to_vec();
+        let last = bad_character_table(&pattern);
+        let good_suffix = good_suffix_table(&pattern);
+        Self {
+            pattern,
+            last,
+            good_suffix,
+        }
+    }
+
+    pub fn pattern(&self) -> &[u8] {
+        &self.pattern
+    }
+
+    /// Returns the position of the first match in `haystack`.
+    pub fn find_first<H: AsRef<[u8]> + ?Sized>(&self, haystack: &H) -> Option<usize> {
+        self.find_iter(haystack).next()
+    }
+
+    /// Iterate over the positions of all matches in `haystack`.
+    ///
+    /// Matches may overlap, an empty pattern matches at every position from 0
+    /// to `haystack.len()`.
+    pub fn find_iter<'a, H: AsRef<[u8]> + ?Sized>(&'a self, haystack: &'a H) -> BoyerMooreIter<'a> {
+        BoyerMooreIter {
+            searcher: self,
+            haystack: haystack.as_ref(),
+            pos: 0,
+        }
+    }
+}
+
+/// Iterator over match positions, created by [`BoyerMoore::find_iter`].
+#[derive(Debug, Clone)]
+pub struct BoyerMooreIter<'a> {
+    // INVARIANTS: there are no matches before `pos` which haven't been
+    //  returned yet
+    searcher: &'a BoyerMoore,
+    haystack: &'a [u8],
+    pos: usize,
+}
+
+impl Iterator for BoyerMooreIter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let BoyerMoore {
+            pattern,
+            last,
+            good_suffix,
+        } = self.searcher;
+        let m = pattern.len();
+        if m == 0 {
+            return empty_pattern_next(&mut self.pos, self.haystack.len());
+        }
+
+        while self.pos + m <= self.haystack.len() {
+            let window = &self.haystack[self.pos..self.pos + m];
+            // `pattern[j..]` matches
+            let mut j = m;
+            while j > 0 && pattern[j - 1] == window[j - 1] {
+                j -= 1;
+            }
+
+            if j == 0 {
+                let pos = self.pos;
+                self.pos += good_suffix[0];
+                return Some(pos);
+            }
+
+            // align the mismatched byte with its last occurrence in the
+            // pattern, or move past it if the occurrence is further right
+            let bad_char = j.saturating_sub(last[window[j - 1] as usize]).max(1);
+            self.pos += bad_char.max(good_suffix[j]);
+        }
+
+        None
+    }
+}
+
+fn empty_pattern_next(pos: &mut usize, haystack_len: usize) -> Option<usize> {
+    if *pos > haystack_len {
+        return None;
+    }
+    *pos += 1;
+    Some(*pos - 1)
+}
+
+/// Returns the position of the first match of `pattern` in `haystack` using
+/// Boyer–Moore.
+pub fn find_first<H, P>(haystack: &H, pattern: &P) -> Option<usize>
+where
+    H: AsRef<[u8]> + ?Sized,
+    P: AsRef<[u8]> + ?Sized,
+{
+    BoyerMoore::new(pattern).find_first(haystack)
+}
+
+/// Returns the positions of all, possibly overlapping, matches of `pattern`
+/// in `haystack` using Boyer–Moore, see [`BoyerMoore::find_iter`].
+pub fn find_all<H, P>(haystack: &H, pattern: &P) -> Vec<usize>
+where
+    H: AsRef<[u8]> + ?Sized,
+    P: AsRef<[u8]> + ?Sized,
+{
+    BoyerMoore::new(pattern).find_iter(haystack).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables() {
+        let shifts = horspool_table(b"abcab");
+        assert_eq!(shifts[b'a' as usize], 1);
+        assert_eq!(shifts[b'b' as usize], 3);
+        assert_eq!(shifts[b'c' as usize], 2);
+        assert_eq!(shifts[b'd' as usize], 5);
+
+        let last = bad_character_table(b"abcab");
+        assert_eq!(last[b'a' as usize], 4);
+        assert_eq!(last[b'b' as usize], 5);
+        assert_eq!(last[b'c' as usize], 3);
+        assert_eq!(last[b'd' as usize], 0);
+
+        assert_eq!(good_suffix_table(b"a"), [1, 1]);
+        assert_eq!(good_suffix_table(b"aaaa"), [1, 1, 2, 3, 4]);
+        assert_eq!(good_suffix_table(b"abcd"), [4, 4, 4, 4, 1]);
+        assert_eq!(good_suffix_table(b"abbabab"), [5, 5, 5, 5, 2, 5, 4, 1]);
+    }
+
+    #[test]
+    fn find() {
+        for (haystack, pattern, expected) in [
+            ("hello world", "world", &[6][..]),
+            ("hello world", "word", &[]),
+            ("abc", "abcd", &[]),
+            ("aaaa", "aa", &[0, 1, 2]),
+            ("abababa", "aba", &[0, 2, 4]),
+            ("aabaaabaaab", "aabaaab", &[0, 4]),
+            ("abc", "", &[0, 1, 2, 3]),
+            ("", "", &[0]),
+            ("", "a", &[]),
+            ("äöäö", "ä", &[0, 4]),
+        ] {
+            assert_eq!(
+                find_all(haystack, pattern),
+                expected,
+                "{haystack} {pattern}"
+            );
+            assert_eq!(
+                Horspool::new(pattern)
+                    .find_iter(haystack)
+                    .collect::<Vec<_>>(),
+                expected,
+                "{haystack} {pattern}"
+            );
+        }
+
+        assert_eq!(find_first("here is a simple example", "example"), Some(17));
+        assert_eq!(
+            Horspool::new("ple").find_first("here is a simple example"),
+            Some(13)
+        );
+        assert_eq!(BoyerMoore::new("ab").pattern(), b"ab");
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+        use crate::strings::naive;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 200;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_naive(
+                haystack in proptest::collection::vec(0..3u8, 0..VEC_SIZE),
+                pattern in proptest::collection::vec(0..3u8, 0..8),
+            ) {
+                let expected = naive::find_all(&haystack, &pattern);
+                assert_eq!(find_all(&haystack, &pattern), expected);
+                assert_eq!(
+                    Horspool::new(&pattern).find_iter(&haystack).collect::<Vec<_>>(),
+                    expected
+                );
+            }
+        );
+    }
+}
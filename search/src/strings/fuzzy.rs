@@ -0,0 +1,192 @@
+//! Approximate search with bounded edit distance
+//!
+//! Words are compared by their Levenshtein distance, the least number of
+//! single char insertions, deletions and substitutions which turn one into
+//! the other. Only distances up to a bound `k` are of interest, so the
+//! dynamic programming table is computed in a band of width `2k + 1` around
+//! its diagonal and given up on as soon as a whole row is over `k`. That
+//! takes O(k * n) time instead of O(n * m).
+//!
+//! Unlike the other substring searches, these work on chars instead of bytes,
+//! so a typo in a multi-byte char counts as a single edit.
+
+/// Returns the Levenshtein distance of `a` and `b` if it's at most `k`.
+pub fn bounded_levenshtein(a: &str, b: &str, k: usize) -> Option<usize> {
+    let a: Vec<_> = a.chars().collect();
+    let b: Vec<_> = b.chars().collect();
+    bounded_levenshtein_chars(&a, &b, k, &mut Vec::new())
+}
+
+/// Banded Levenshtein distance, `rows` is a buffer reused between calls.
+fn bounded_levenshtein_chars(
+    a: &[char],
+    b: &[char],
+    k: usize,
+    rows: &mut Vec<usize>,
+) -> Option<usize> {
+    let n = a.len();
+    let m = b.len();
+    // every extra char needs an insertion
+    if n.abs_diff(m) > k {
+        return None;
+    }
+    // no distance is larger, this also keeps `k + 1` and `i + k` from
+    // overflowing
+    let k = k.min(n.max(m));
+
+    // Values over `k` are clamped to `k + 1`, which is enough to know they
+    // are too far. Cells outside of the band are never computed, the ones
+    // right next to it are set to `k + 1` so that the band can read them.
+    let over = k + 1;
+    rows.clear();
+    rows.resize(2 * (m + 1), over);
+    let (mut prev, mut cur) = rows.split_at_mut(m + 1);
+    for (j, d) in prev.iter_mut().enumerate().take(over.min(m + 1)) {
+        *d = j;
+    }
+
+    for i in 1..=n {
+        let lo = i.saturating_sub(k).max(1);
+        let hi = (i + k).min(m);
+
+        cur[lo - 1] = if lo == 1 { i.min(over) } else { over };
+        let mut row_min = cur[lo - 1];
+        for j in lo..=hi {
+            let substitute = prev[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let delete = prev[j] + 1;
+            let insert = cur[j - 1] + 1;
+            cur[j] = substitute.min(delete).min(insert).min(over);
+            row_min = row_min.min(cur[j]);
+        }
+        if hi < m {
+            cur[hi + 1] = over;
+        }
+
+        // distances only grow going down
+        if row_min > k {
+            return None;
+        }
+        core::mem::swap(&mut prev, &mut cur);
+    }
+
+    (prev[m] <= k).then_some(prev[m])
+}
+
+/// Returns the words of `haystack_words` within edit distance `k` of
+/// `needle` together with their distances.
+///
+/// The words are sorted by the distance, words with equal distances are in
+/// the order of `haystack_words`.
+pub fn find_within_distance<'a, I>(
+    haystack_words: I,
+    needle: &str,
+    k: usize,
+) -> Vec<(&'a str, usize)>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let needle: Vec<_> = needle.chars().collect();
+    let mut word = Vec::new();
+    let mut rows = Vec::new();
+
+    let mut found = Vec::new();
+    for w in haystack_words {
+        word.clear();
+        word.extend(w.chars());
+        if let Some(d) = bounded_levenshtein_chars(&word, &needle, k, &mut rows) {
+            found.push((w, d));
+        }
+    }
+
+    found.sort_by_key(|(_, d)| *d);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Full dynamic programming table
+    fn levenshtein(a: &str, b: &str) -> usize {
+        let a: Vec<_> = a.chars().collect();
+        let b: Vec<_> = b.chars().collect();
+        let mut prev: Vec<_> = (0..=b.len()).collect();
+        for i in 1..=a.len() {
+            let mut cur = vec![i; b.len() + 1];
+            for j in 1..=b.len() {
+                cur[j] = (prev[j - 1] + usize::from(a[i - 1] != b[j - 1]))
+                    .min(prev[j] + 1)
+                    .min(cur[j - 1] + 1);
+            }
+            prev = cur;
+        }
+        prev[b.len()]
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(bounded_levenshtein("kitten", "sitting", 3), Some(3));
+        assert_eq!(bounded_levenshtein("kitten", "sitting", 2), None);
+        assert_eq!(bounded_levenshtein("", "", 0), Some(0));
+        assert_eq!(bounded_levenshtein("abc", "", 3), Some(3));
+        assert_eq!(bounded_levenshtein("abc", "", 2), None);
+        assert_eq!(bounded_levenshtein("flaw", "lawn", 2), Some(2));
+        assert_eq!(bounded_levenshtein("same", "same", 0), Some(0));
+        // one char, not two bytes
+        assert_eq!(bounded_levenshtein("mäh", "mah", 1), Some(1));
+        assert_eq!(
+            bounded_levenshtein("kitten", "sitting", usize::MAX),
+            Some(3)
+        );
+        assert_eq!(bounded_levenshtein("", "", usize::MAX), Some(0));
+    }
+
+    #[test]
+    fn find() {
+        let words = [
+            "apple", "apply", "ape", "maple", "apples", "banana", "appel",
+        ];
+        assert_eq!(
+            find_within_distance(words, "apple", 1),
+            [("apple", 0), ("apply", 1), ("apples", 1)]
+        );
+        assert_eq!(
+            find_within_distance(words, "appel", 2),
+            [
+                ("appel", 0),
+                ("apple", 2),
+                ("apply", 2),
+                ("ape", 2),
+                ("apples", 2)
+            ]
+        );
+        assert_eq!(find_within_distance(words, "xyz", 2), []);
+        assert_eq!(find_within_distance([], "a", 5), []);
+        assert_eq!(
+            find_within_distance(["ab", "b", ""], "ab", usize::MAX),
+            [("ab", 0), ("b", 1), ("", 2)]
+        );
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_full_table(a in "[abc]{0,12}", b in "[abc]{0,12}", k in 0..8usize) {
+                let d = levenshtein(&a, &b);
+                let expected = (d <= k).then_some(d);
+                assert_eq!(bounded_levenshtein(&a, &b, k), expected);
+            }
+        );
+    }
+}
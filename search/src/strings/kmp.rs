@@ -0,0 +1,215 @@
+//! Knuth–Morris–Pratt substring search
+//!
+//! After a mismatch the pattern is shifted by using the failure function
+//! instead of restarting from the next haystack position, so every haystack
+//! byte is looked at a bounded number of times. Takes O(n + m) time where `n`
+//! is the length of the haystack and `m` the length of the pattern.
+
+/// Returns the failure function of `pattern`.
+///
+/// `failure[i]` is the length of the longest proper prefix of
+/// `pattern[..=i]` which is also its suffix. If a match of `pattern[..=i]`
+/// fails at the next byte, the search can continue with that many bytes
+/// already matched.
+pub fn failure_function(pattern: &[u8]) -> Vec<usize> {
+    let mut failure = vec![0; pattern.len()];
+    // length of the current border
+    let mut k = 0;
+    for i in 1..pattern.len() {
+        while k > 0 && pattern[i] != pattern[k] {
+            k = failure[k - 1];
+        }
+        if pattern[i] == pattern[k] {
+            k += 1;
+        }
+        failure[i] = k;
+    }
+    failure
+}
+
+/// Pattern prepared for searching.
+///
+/// Build once to search for the same pattern in many haystacks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Kmp {
+    // INVARIANTS: `failure == failure_function(&pattern)`
+    pattern: Vec<u8>,
+    failure: Vec<usize>,
+}
+
+impl Kmp {
+    pub fn new<P: AsRef<[u8]> + ?Sized>(pattern: &P) -> Self {
+        let pattern = pattern.as_ref().to_vec();
+        let failure = failure_function(&pattern);
+        Self { pattern, failure }
+    }
+
+    pub fn pattern(&self) -> &[u8] {
+        &self.pattern
+    }
+
+    /// Failure function of the pattern, see [`failure_function`].
+    pub fn failure(&self) -> &[usize] {
+        &self.failure
+    }
+
+    /// Returns the position of the first match in `haystack`.
+    pub fn find_first<H: AsRef<[u8]> + ?Sized>(&self, haystack: &H) -> Option<usize> {
+        self.find_iter(haystack).next()
+    }
+
+    /// Iterate over the positions of all matches in `haystack`.
+    ///
+    /// Matches may overlap, searching for "aa" in "aaa" returns 0 and 1. An
+    /// empty pattern matches at every position from 0 to `haystack.len()`.
+    pub fn find_iter<'a, H: AsRef<[u8]> + ?Sized>(&'a self, haystack: &'a H) -> FindIter<'a> {
+        FindIter {
+            kmp: self,
+            haystack: haystack.as_ref(),
+            pos: 0,
+            matched: 0,
+        }
+    }
+}
+
+/// Iterator over match positions, created by [`Kmp::find_iter`].
+#[derive(Debug, Clone)]
+pub struct FindIter<'a> {
+    // INVARIANTS: `haystack[pos - matched..pos] == pattern[..matched]` and
+    //  `matched < pattern.len()` unless the pattern is empty
+    kmp: &'a Kmp,
+    haystack: &'a [u8],
+    pos: usize,
+    matched: usize,
+}
+
+impl Iterator for FindIter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pattern = &self.kmp.pattern;
+        let failure = &self.kmp.failure;
+
+        if pattern.is_empty() {
+            if self.pos > self.haystack.len() {
+                return None;
+            }
+            self.pos += 1;
+            return Some(self.pos - 1);
+        }
+
+        while self.pos < self.haystack.len() {
+            let b = self.haystack[self.pos];
+            while self.matched > 0 && b != pattern[self.matched] {
+                self.matched = failure[self.matched - 1];
+            }
+            if b == pattern[self.matched] {
+                self.matched += 1;
+            }
+            self.pos += 1;
+
+            if self.matched == pattern.len() {
+                let start = self.pos - self.matched;
+                // keep the border matched to find overlapping matches
+                self.matched = failure[self.matched - 1];
+                return Some(start);
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns the position of the first match of `pattern` in `haystack`.
+pub fn find_first<H, P>(haystack: &H, pattern: &P) -> Option<usize>
+where
+    H: AsRef<[u8]> + ?Sized,
+    P: AsRef<[u8]> + ?Sized,
+{
+    Kmp::new(pattern).find_first(haystack)
+}
+
+/// Returns the positions of all, possibly overlapping, matches of `pattern`
+/// in `haystack`, see [`Kmp::find_iter`].
+pub fn find_all<H, P>(haystack: &H, pattern: &P) -> Vec<usize>
+where
+    H: AsRef<[u8]> + ?Sized,
+    P: AsRef<[u8]> + ?Sized,
+{
+    Kmp::new(pattern).find_iter(haystack).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure() {
+        assert_eq!(failure_function(b""), []);
+        assert_eq!(failure_function(b"abcd"), [0, 0, 0, 0]);
+        assert_eq!(failure_function(b"aaaa"), [0, 1, 2, 3]);
+        assert_eq!(failure_function(b"abab"), [0, 0, 1, 2]);
+        assert_eq!(failure_function(b"aabaaab"), [0, 1, 0, 1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn find() {
+        assert_eq!(find_first("hello world", "world"), Some(6));
+        assert_eq!(find_first("hello world", "word"), None);
+        assert_eq!(find_first("abc", "abcd"), None);
+        assert_eq!(
+            find_first(b"\x00\x01\x00\x01".as_slice(), &[1u8, 0]),
+            Some(1)
+        );
+
+        assert_eq!(find_all("aaaa", "aa"), [0, 1, 2]);
+        assert_eq!(find_all("abababa", "aba"), [0, 2, 4]);
+        assert_eq!(find_all("aabaaabaaab", "aabaaab"), [0, 4]);
+        assert_eq!(find_all("abc", ""), [0, 1, 2, 3]);
+        assert_eq!(find_all("", ""), [0]);
+        assert_eq!(find_all("", "a"), []);
+
+        // byte offsets at char boundaries
+        assert_eq!(find_all("äöäö", "ä"), [0, 4]);
+
+        let kmp = Kmp::new("ab");
+        assert_eq!(kmp.pattern(), b"ab");
+        assert_eq!(kmp.find_iter("xabyab").collect::<Vec<_>>(), [1, 4]);
+        assert_eq!(kmp.find_first("ba"), None);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 200;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_naive(
+                haystack in proptest::collection::vec(0..3u8, 0..VEC_SIZE),
+                pattern in proptest::collection::vec(0..3u8, 1..6),
+            ) {
+                let expected: Vec<_> = haystack
+                    .windows(pattern.len())
+                    .enumerate()
+                    .filter(|(_, w)| *w == pattern)
+                    .map(|(i, _)| i)
+                    .collect();
+                assert_eq!(find_all(&haystack, &pattern), expected);
+                assert_eq!(find_first(&haystack, &pattern), expected.first().copied());
+            }
+        );
+    }
+}
@@ -0,0 +1,11 @@
+//! Substring search
+//!
+//! Haystacks and patterns are anything which can be viewed as bytes, so both
+//! `str` and `[u8]` work. Positions are byte offsets, for `str` they are
+//! always at char boundaries since the pattern is valid UTF-8 too.
+
+pub mod aho_corasick;
+pub mod boyer_moore;
+pub mod fuzzy;
+pub mod kmp;
+pub mod naive;
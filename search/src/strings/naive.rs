@@ -0,0 +1,48 @@
+//! Naive substring search
+//!
+//! Compares the pattern at every haystack position. Takes O(n * m) time in
+//! the worst case, but there is no preprocessing and mismatches are usually
+//! found at the first byte, so it's hard to beat for short patterns.
+
+/// Returns the position of the first match of `pattern` in `haystack`.
+pub fn find_first<H, P>(haystack: &H, pattern: &P) -> Option<usize>
+where
+    H: AsRef<[u8]> + ?Sized,
+    P: AsRef<[u8]> + ?Sized,
+{
+    find_iter(haystack.as_ref(), pattern.as_ref()).next()
+}
+
+/// Returns the positions of all, possibly overlapping, matches of `pattern`
+/// in `haystack`.
+///
+/// An empty pattern matches at every position from 0 to `haystack.len()`.
+pub fn find_all<H, P>(haystack: &H, pattern: &P) -> Vec<usize>
+where
+    H: AsRef<[u8]> + ?Sized,
+    P: AsRef<[u8]> + ?Sized,
+{
+    find_iter(haystack.as_ref(), pattern.as_ref()).collect()
+}
+
+fn find_iter<'a>(haystack: &'a [u8], pattern: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+    let last = haystack.len().checked_sub(pattern.len());
+    last.into_iter()
+        .flat_map(|last| 0..=last)
+        .filter(move |i| haystack[*i..].starts_with(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find() {
+        assert_eq!(find_first("hello world", "world"), Some(6));
+        assert_eq!(find_first("abc", "abcd"), None);
+        assert_eq!(find_all("aaaa", "aa"), [0, 1, 2]);
+        assert_eq!(find_all("abc", ""), [0, 1, 2, 3]);
+        assert_eq!(find_all("", ""), [0]);
+        assert_eq!(find_all("", "a"), []);
+    }
+}
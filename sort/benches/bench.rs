@@ -10,9 +10,12 @@ use rand_chacha::ChaCha8Rng;
 use sort::bubble_sort::{bubble_sort, bubble_sort2, bubble_sort2_unsafe};
 use sort::heapsort::heapsort;
 use sort::insertion_sort::{insertion_sort, insertion_sort2};
+use sort::introsort::introsort;
 use sort::merge_sort::{merge_sort, merge_sort_copy};
+use sort::pdqsort::pdqsort;
 use sort::quicksort::{quicksort_3way, quicksort_hoare, quicksort_lomuto};
 use sort::selection_sort::{selection_sort, selection_sort2};
+use sort::timsort::timsort;
 
 fn std_sort<T: Ord>(slice: &mut [T]) {
     slice.sort()
@@ -76,6 +79,40 @@ pub fn gen_descending_ints_no_duplicates(count: usize, key_max: i32) -> Vec<i32>
     vec
 }
 
+/// Ascending with a random pair swapped for every 32 items
+pub fn gen_partially_sorted(count: usize, key_max: i32) -> Vec<i32> {
+    let mut vec = gen_ascending_ints_maybe_duplicates(count, key_max);
+    let mut rng = ChaCha8Rng::seed_from_u64(6);
+    for _ in 0..count / 32 {
+        let a = rng.gen_range(0..count);
+        let b = rng.gen_range(0..count);
+        vec.swap(a, b);
+    }
+    vec
+}
+
+/// 8 ascending runs of random lengths one after another
+pub fn gen_sorted_runs(count: usize, key_max: i32) -> Vec<i32> {
+    let mut vec = gen_random_ints(count, key_max);
+    let mut rng = ChaCha8Rng::seed_from_u64(7);
+    let mut bounds: Vec<_> = (0..7).map(|_| rng.gen_range(0..=count)).collect();
+    bounds.push(0);
+    bounds.push(count);
+    bounds.sort();
+    for w in bounds.windows(2) {
+        vec[w[0]..w[1]].sort();
+    }
+    vec
+}
+
+/// Ascending first half and descending second half
+pub fn gen_organ_pipe(count: usize, key_max: i32) -> Vec<i32> {
+    let mut vec = gen_random_ints(count, key_max);
+    vec.sort();
+    vec[count / 2..].reverse();
+    vec
+}
+
 pub fn gen_equal(count: usize, key_max: i32) -> Vec<i32> {
     assert!(count < key_max as usize);
     vec![153; count]
@@ -124,12 +161,12 @@ fn bench_group<M: Measurement>(
         g: &mut BenchmarkGroup<'_, M>,
         name: &str,
         count: usize,
-        items: &Vec<i32>,
+        items: &[i32],
         sort: fn(&mut [i32]),
     ) {
         g.bench_with_input(BenchmarkId::new(name, count), &count, |b, _i| {
             b.iter_batched_ref(
-                || items.clone(),
+                || items.to_vec(),
                 |i| sort(i),
                 criterion::BatchSize::SmallInput,
             )
@@ -167,6 +204,9 @@ fn bench_group<M: Measurement>(
             quicksort_hoare,
             quicksort_lomuto,
             quicksort_3way,
+            introsort,
+            pdqsort,
+            timsort,
             std_sort,
             std_sort_unstable,
         );
@@ -179,6 +219,9 @@ fn bench<M: Measurement>(c: &mut Criterion<M>) {
     bench_group(c, "ascending", gen_ascending_ints_no_duplicates);
     bench_group(c, "descending", gen_descending_ints_no_duplicates);
     bench_group(c, "equal", gen_equal);
+    bench_group(c, "organ_pipe", gen_organ_pipe);
+    bench_group(c, "partially_sorted", gen_partially_sorted);
+    bench_group(c, "sorted_runs", gen_sorted_runs);
 }
 
 criterion_group!(
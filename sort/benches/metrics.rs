@@ -1,13 +1,12 @@
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
 
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 use sort::bubble_sort::bubble_sort;
 use sort::heapsort::heapsort;
 use sort::insertion_sort::{insertion_sort, insertion_sort2};
-use sort::merge_sort::{merge_sort, merge_sort_copy};
+use sort::merge_sort::merge_sort;
 use sort::quicksort::{quicksort_hoare, quicksort_lomuto};
 use sort::selection_sort::{selection_sort, selection_sort2};
 
@@ -1,8 +1,24 @@
+use crate::cmp::sort_by_variants;
+
 pub fn bubble_sort<T: Ord>(slice: &mut [T]) {
+    bubble_sort_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    bubble_sort,
+    bubble_sort_by,
+    bubble_sort_by_key,
+    bubble_sort_impl
+);
+
+fn bubble_sort_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
     for iteration in 0..slice.len() {
         let mut is_sorted = true;
         for i in 0..slice.len() - 1 - iteration {
-            if slice[i] > slice[i + 1] {
+            if is_less(&slice[i + 1], &slice[i]) {
                 slice.swap(i, i + 1);
                 is_sorted = false;
             }
@@ -13,7 +29,21 @@ pub fn bubble_sort<T: Ord>(slice: &mut [T]) {
     }
 }
 
-pub fn bubble_sort2<T: Ord>(mut slice: &mut [T]) {
+pub fn bubble_sort2<T: Ord>(slice: &mut [T]) {
+    bubble_sort2_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    bubble_sort2,
+    bubble_sort2_by,
+    bubble_sort2_by_key,
+    bubble_sort2_impl
+);
+
+fn bubble_sort2_impl<T, F>(mut slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
     if slice.len() < 2 {
         return;
     }
@@ -22,7 +52,7 @@ pub fn bubble_sort2<T: Ord>(mut slice: &mut [T]) {
         let mut new_unsorted_len = 0;
         for i in 0..slice.len() - 1 {
             let j = i + 1;
-            if slice[i] > slice[j] {
+            if is_less(&slice[j], &slice[i]) {
                 slice.swap(i, j);
                 new_unsorted_len = j;
             }
@@ -32,6 +62,20 @@ pub fn bubble_sort2<T: Ord>(mut slice: &mut [T]) {
 }
 
 pub fn bubble_sort2_unsafe<T: Ord>(slice: &mut [T]) {
+    bubble_sort2_unsafe_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    bubble_sort2_unsafe,
+    bubble_sort2_unsafe_by,
+    bubble_sort2_unsafe_by_key,
+    bubble_sort2_unsafe_impl
+);
+
+fn bubble_sort2_unsafe_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
     if slice.len() < 2 {
         return;
     }
@@ -48,7 +92,7 @@ pub fn bubble_sort2_unsafe<T: Ord>(slice: &mut [T]) {
                 let j = i + 1;
                 let i_ptr = ptr.add(i);
                 let j_ptr = ptr.add(j);
-                if *i_ptr > *j_ptr {
+                if is_less(&*j_ptr, &*i_ptr) {
                     core::ptr::swap(i_ptr, j_ptr);
                     new_unsorted_len = j + 1;
                 };
@@ -93,6 +137,27 @@ mod tests {
         assert_sorted(&arr);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn by_and_by_key() {
+        // stable, equal keys keep their order
+        let people = [("b", 3), ("a", 1), ("c", 3), ("d", 2)];
+        for sort in [
+            bubble_sort_by_key,
+            bubble_sort2_by_key,
+            bubble_sort2_unsafe_by_key,
+        ] {
+            let mut arr = people;
+            sort(&mut arr, |p: &(&str, i32)| p.1);
+            assert_eq!(arr, [("a", 1), ("d", 2), ("b", 3), ("c", 3)]);
+        }
+        for sort in [bubble_sort_by, bubble_sort2_by, bubble_sort2_unsafe_by] {
+            let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+            sort(&mut arr, |a: &i32, b: &i32| b.cmp(a));
+            assert_eq!(arr, [65, 45, 24, 4, 3, 3, 2, 1]);
+        }
+    }
+
     mod proptests {
         use proptest::prelude::*;
 
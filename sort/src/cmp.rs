@@ -0,0 +1,39 @@
+//! Comparator shared by all sorts
+//!
+//! Every sort is implemented once over an `is_less(a, b)` function which
+//! returns `true` if `a` must be before `b`, same as the sorts in std. The
+//! `Ord`, comparator and key variants only differ in the `is_less` they pass
+//! in.
+
+/// Defines the `*_by` and `*_by_key` variants of a sort.
+///
+/// `$core` is the sort over `is_less` with the signature
+/// `fn(&mut [T], &mut F) where F: FnMut(&T, &T) -> bool`. Extra bounds on `T`
+/// can be given after it.
+macro_rules! sort_by_variants {
+    ($sort:ident, $sort_by:ident, $sort_by_key:ident, $core:ident $(, $bound:path)* $(,)?) => {
+        #[doc = concat!("Sorts the slice with a comparator function, see [`", stringify!($sort), "`].")]
+        ///
+        /// `compare` must be a total order, otherwise the order of the items
+        /// is unspecified.
+        pub fn $sort_by<T $(: $bound)*, F>(slice: &mut [T], mut compare: F)
+        where
+            F: FnMut(&T, &T) -> core::cmp::Ordering,
+        {
+            $core(slice, &mut |a: &T, b: &T| compare(a, b) == core::cmp::Ordering::Less)
+        }
+
+        #[doc = concat!("Sorts the slice with a key extraction function, see [`", stringify!($sort), "`].")]
+        ///
+        /// The key is extracted again for every comparison.
+        pub fn $sort_by_key<T $(: $bound)*, K, F>(slice: &mut [T], mut f: F)
+        where
+            K: Ord,
+            F: FnMut(&T) -> K,
+        {
+            $core(slice, &mut |a: &T, b: &T| f(a) < f(b))
+        }
+    };
+}
+
+pub(crate) use sort_by_variants;
@@ -1,3 +1,7 @@
+use core::cmp::Ordering;
+
+use crate::cmp::sort_by_variants;
+
 // Indices in a heap
 //           0
 //     1            2
@@ -9,7 +13,16 @@
 // right_child = left_child + 1 = parent_index * 2 + 2
 
 pub fn heapsort<T: Ord>(slice: &mut [T]) {
-    build_max_heap(slice);
+    heapsort_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(heapsort, heapsort_by, heapsort_by_key, heapsort_impl);
+
+pub(crate) fn heapsort_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    build_max_heap(slice, is_less);
 
     for i in (1..slice.len()).rev() {
         // slice[..=i] is a max-heap, slice[0] is the largest item
@@ -22,12 +35,79 @@ pub fn heapsort<T: Ord>(slice: &mut [T]) {
         // Swap ruined our heap by moving smaller item to the front,
         // shift it down to restore heap
         // both child trees are still proper heaps
-        shift_down(&mut slice[..i], 0);
+        shift_down(&mut slice[..i], 0, is_less);
+    }
+}
+
+/// Sort the `k` smallest items of `slice` to the front.
+///
+/// After the call `slice[..k]` holds the `k` smallest items in sorted order,
+/// the order of `slice[k..]` is unspecified. Runs in O(n log k).
+///
+/// # PANICS
+///
+/// * if `k > slice.len()`
+pub fn partial_sort<T: Ord>(slice: &mut [T], k: usize) {
+    partial_sort_impl(slice, k, &mut T::lt);
+}
+
+/// Sort the `k` smallest items of `slice` by a comparator function to the
+/// front, see [`partial_sort`].
+///
+/// # PANICS
+///
+/// * if `k > slice.len()`
+pub fn partial_sort_by<T, F>(slice: &mut [T], k: usize, mut compare: F)
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    partial_sort_impl(slice, k, &mut |a: &T, b: &T| {
+        compare(a, b) == Ordering::Less
+    });
+}
+
+/// Sort the `k` items of `slice` with the smallest keys to the front, see
+/// [`partial_sort`].
+///
+/// # PANICS
+///
+/// * if `k > slice.len()`
+pub fn partial_sort_by_key<T, K, F>(slice: &mut [T], k: usize, mut f: F)
+where
+    K: Ord,
+    F: FnMut(&T) -> K,
+{
+    partial_sort_impl(slice, k, &mut |a: &T, b: &T| f(a) < f(b));
+}
+
+fn partial_sort_impl<T, F>(slice: &mut [T], k: usize, is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    assert!(k <= slice.len(), "k must be at most slice.len()");
+    if k == 0 {
+        return;
+    }
+
+    let (front, rest) = slice.split_at_mut(k);
+    build_max_heap(front, is_less);
+    for item in rest {
+        // front is a max-heap of the k smallest items seen so far,
+        // front[0] is the largest of them
+        if is_less(item, &front[0]) {
+            core::mem::swap(item, &mut front[0]);
+            shift_down(front, 0, is_less);
+        }
     }
+
+    heapsort_impl(front, is_less);
 }
 
 /// Build a max-heap from any slice in-place.
-fn build_max_heap<T: Ord>(slice: &mut [T]) {
+fn build_max_heap<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
     if slice.len() < 2 {
         // empty or 1-element slice, is already a heap
         return;
@@ -41,7 +121,7 @@ fn build_max_heap<T: Ord>(slice: &mut [T]) {
     // Thus the last_parent is at index (last_index - 1)/2 = (slice.len() - 1 - 1)/2
     let last_parent = (slice.len() - 2) / 2;
     for i in (0..=last_parent).rev() {
-        shift_down(slice, i);
+        shift_down(slice, i, is_less);
     }
 }
 
@@ -49,7 +129,10 @@ fn build_max_heap<T: Ord>(slice: &mut [T]) {
 /// the tree to restore max-heap.
 ///
 /// Assumes that both child trees of `parent` are proper max-heaps.
-fn shift_down<T: Ord>(slice: &mut [T], mut parent_index: usize) {
+fn shift_down<T, F>(slice: &mut [T], mut parent_index: usize, is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
     // * Find the largest value of parent, left child, right child.
     // * If parent was largest, whole tree starting from parent is a max-heap, we are done.
     // * If not, swap parent with the largest children.
@@ -61,14 +144,14 @@ fn shift_down<T: Ord>(slice: &mut [T], mut parent_index: usize) {
 
         let left_index = 2 * parent_index + 1;
         let (largest, largest_index) = match slice.get(left_index) {
-            Some(left) if left > parent => (left, left_index),
+            Some(left) if is_less(parent, left) => (left, left_index),
             Some(_) => (parent, parent_index),
             None => return, // parent has no children
         };
 
         let right_index = left_index + 1;
         let largest_index = match slice.get(right_index) {
-            Some(right) if right > largest => right_index,
+            Some(right) if is_less(largest, right) => right_index,
             _ => largest_index,
         };
 
@@ -110,6 +193,36 @@ mod tests {
         assert_sorted(&arr);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn partial_sort_test() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        partial_sort(&mut arr, 4);
+        assert_eq!(arr[..4], [1, 2, 3, 3]);
+
+        partial_sort(&mut arr, 0);
+        partial_sort(&mut arr, 8);
+        assert_sorted(&arr);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn by_and_by_key() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        heapsort_by(&mut arr, |a, b| b.cmp(a));
+        assert_eq!(arr, [65, 45, 24, 4, 3, 3, 2, 1]);
+
+        let mut arr = vec![-3, 1, -2, 4];
+        heapsort_by_key(&mut arr, |a: &i32| a.abs());
+        assert_eq!(arr, [1, -2, -3, 4]);
+
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        partial_sort_by(&mut arr, 3, |a, b| b.cmp(a));
+        assert_eq!(arr[..3], [65, 45, 24]);
+        partial_sort_by_key(&mut arr, 2, |a: &i32| a % 10);
+        assert_eq!(arr[..2], [1, 2]);
+    }
+
     mod proptests {
         use proptest::prelude::*;
 
@@ -137,6 +250,20 @@ mod tests {
                assert_sorted(&vec);
             }
 
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn partial_sort_test(
+                mut vec in proptest::collection::vec(0..10000i32, 0..VEC_SIZE),
+                k in any::<prop::sample::Index>(),
+            ) {
+               let k = k.index(vec.len() + 1);
+               let mut expected = vec.clone();
+               expected.sort();
+
+               partial_sort(vec.as_mut_slice(), k);
+               assert_eq!(vec[..k], expected[..k]);
+            }
+
         );
     }
 }
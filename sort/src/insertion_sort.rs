@@ -1,12 +1,28 @@
+use crate::cmp::sort_by_variants;
+
 pub fn insertion_sort<T>(slice: &mut [T])
 where
     T: Ord,
+{
+    insertion_sort_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    insertion_sort,
+    insertion_sort_by,
+    insertion_sort_by_key,
+    insertion_sort_impl
+);
+
+pub(crate) fn insertion_sort_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
 {
     for j in 1..slice.len() {
         let to_sort = &slice[j];
         let mut new_index = 0;
         for i in (0..j).rev() {
-            if &slice[i] < to_sort {
+            if is_less(&slice[i], to_sort) {
                 new_index = i + 1;
                 break;
             }
@@ -18,10 +34,24 @@ where
 pub fn insertion_sort2<T>(slice: &mut [T])
 where
     T: Ord,
+{
+    insertion_sort2_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    insertion_sort2,
+    insertion_sort2_by,
+    insertion_sort2_by_key,
+    insertion_sort2_impl
+);
+
+fn insertion_sort2_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
 {
     for j in 1..slice.len() {
         let to_sort = &slice[j];
-        let new_index = slice[..j].partition_point(|a| a < to_sort);
+        let new_index = slice[..j].partition_point(|a| is_less(a, to_sort));
         slice[new_index..=j].rotate_right(1);
     }
 }
@@ -54,6 +84,22 @@ mod tests {
         assert_sorted(&arr);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn by_and_by_key() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        insertion_sort_by(&mut arr, |a, b| b.cmp(a));
+        assert_eq!(arr, [65, 45, 24, 4, 3, 3, 2, 1]);
+
+        let mut arr = vec![-3, 1, -2, 4];
+        insertion_sort2_by_key(&mut arr, |a: &i32| a.abs());
+        assert_eq!(arr, [1, -2, -3, 4]);
+        insertion_sort2_by(&mut arr, |a, b| b.cmp(a));
+        assert_eq!(arr, [4, 1, -2, -3]);
+        insertion_sort_by_key(&mut arr, |a: &i32| -a);
+        assert_eq!(arr, [4, 1, -2, -3]);
+    }
+
     mod proptests {
         use proptest::prelude::*;
 
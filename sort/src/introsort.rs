@@ -0,0 +1,140 @@
+//! Introsort, quicksort which can't go quadratic
+//!
+//! Sorts with the Hoare quicksort until the recursion gets deeper than
+//! 2 * log2(n), which only happens if the pivots keep being bad, and sorts the
+//! rest of that partition with heapsort. Small partitions are finished with
+//! insertion sort, which is faster than partitioning them further.
+//!
+//! That's O(n log n) in the worst case while being as fast as quicksort on
+//! typical inputs, so this is the sort to use if stability isn't needed.
+
+use crate::cmp::sort_by_variants;
+use crate::heapsort::heapsort_impl;
+use crate::insertion_sort::insertion_sort_impl;
+use crate::quicksort::partition_hoare;
+
+/// Partitions of at most this many items are sorted with insertion sort
+const INSERTION_SORT_THRESHOLD: usize = 16;
+
+pub fn introsort<T: Ord>(slice: &mut [T]) {
+    introsort_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(introsort, introsort_by, introsort_by_key, introsort_impl);
+
+fn introsort_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let depth_limit = 2 * slice.len().max(1).ilog2() as usize;
+    introsort_core(slice, is_less, depth_limit);
+}
+
+/// Quicksort which switches to heapsort after `depth_limit` partitions.
+fn introsort_core<T, F>(mut slice: &mut [T], is_less: &mut F, mut depth_limit: usize)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    while slice.len() > INSERTION_SORT_THRESHOLD {
+        if depth_limit == 0 {
+            heapsort_impl(slice, is_less);
+            return;
+        }
+        depth_limit -= 1;
+
+        // recurse into the smaller side to keep the stack small, as in
+        // `quicksort::quicksort_with`
+        let (l, r) = partition_hoare(slice, is_less);
+        let (smaller, larger) = if l.len() < r.len() { (l, r) } else { (r, l) };
+        introsort_core(smaller, is_less, depth_limit);
+        slice = larger;
+    }
+
+    insertion_sort_impl(slice, is_less);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_sorted(slice: &[i32]) {
+        slice.windows(2).for_each(|arr| {
+            let a = arr[0];
+            let b = arr[1];
+            assert!(a <= b);
+        })
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn test() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        introsort(&mut arr);
+        assert_sorted(&arr);
+
+        let mut arr: Vec<_> = (0..1000).rev().collect();
+        introsort(&mut arr);
+        assert_sorted(&arr);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn falls_back_to_heapsort() {
+        // Hoare partitions of equal items are as unbalanced as they get, plain
+        // quicksort would take minutes on this
+        let mut arr = vec![7; 200_000];
+        arr.push(1);
+        introsort(&mut arr);
+        assert_sorted(&arr);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn by_and_by_key() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        introsort_by(&mut arr, |a, b| b.cmp(a));
+        assert_eq!(arr, [65, 45, 24, 4, 3, 3, 2, 1]);
+
+        let mut arr = vec![-3, 1, -2, 4, 0];
+        introsort_by_key(&mut arr, |a: &i32| a.abs());
+        assert_eq!(arr, [0, 1, -2, -3, 4]);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn test(
+                mut vec in proptest::collection::vec(0..10000i32, 0..VEC_SIZE),
+            ) {
+               introsort(vec.as_mut_slice());
+               assert_sorted(&vec);
+            }
+
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn few_distinct(
+                mut vec in proptest::collection::vec(0..4i32, 0..VEC_SIZE),
+            ) {
+               introsort(vec.as_mut_slice());
+               assert_sorted(&vec);
+            }
+        );
+    }
+}
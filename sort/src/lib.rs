@@ -3,8 +3,12 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
 pub mod bubble_sort;
+mod cmp;
 pub mod heapsort;
 pub mod insertion_sort;
+pub mod introsort;
 pub mod merge_sort;
+pub mod pdqsort;
 pub mod quicksort;
 pub mod selection_sort;
+pub mod timsort;
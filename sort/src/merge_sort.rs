@@ -1,24 +1,44 @@
-use core::mem::{self, MaybeUninit};
+use core::{mem, ptr};
+
+use crate::cmp::sort_by_variants;
 
 /// Merge sort that works with only `Copy` types
 pub fn merge_sort_copy<T: Ord + Copy>(slice: &mut [T]) {
+    merge_sort_copy_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    merge_sort_copy,
+    merge_sort_copy_by,
+    merge_sort_copy_by_key,
+    merge_sort_copy_impl,
+    Copy
+);
+
+fn merge_sort_copy_impl<T: Copy, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
     let mut tmp = Vec::with_capacity(slice.len());
     tmp.extend(slice.iter().copied());
-    merge_sort_copy_core(slice, &mut tmp);
+    merge_sort_copy_core(slice, &mut tmp, is_less);
 }
 
 /// As a result all items in output are sorted.
-fn merge_sort_copy_core<T: Ord>(output: &mut [T], tmp: &mut [T]) {
+fn merge_sort_copy_core<T, F>(output: &mut [T], tmp: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
     if output.len() > 1 {
         let mid = output.len() / 2;
         let (l, r) = output.split_at_mut(mid);
         let (tmpl, tmpr) = tmp.split_at_mut(mid);
 
         // sort into temporary arrays
-        merge_sort_copy_core(tmpl, l);
-        merge_sort_copy_core(tmpr, r);
+        merge_sort_copy_core(tmpl, l, is_less);
+        merge_sort_copy_core(tmpr, r, is_less);
         // merge into actual array we want to sort
-        merge_copy(output, tmpl, tmpr);
+        merge_copy(output, tmpl, tmpr, is_less);
     } else {
         // single item, must be sorted
     }
@@ -27,7 +47,10 @@ fn merge_sort_copy_core<T: Ord>(output: &mut [T], tmp: &mut [T]) {
 /// Merge sorted slices l and r into output.
 ///
 /// Note that following must hold: `l.len() + r.len() == output.len()`
-fn merge_copy<T: Ord>(output: &mut [T], l: &mut [T], r: &mut [T]) {
+fn merge_copy<T, F>(output: &mut [T], l: &mut [T], r: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
     debug_assert_eq!(l.len() + r.len(), output.len());
     let mut l_iter = l.iter_mut();
     let mut r_iter = r.iter_mut();
@@ -48,7 +71,8 @@ fn merge_copy<T: Ord>(output: &mut [T], l: &mut [T], r: &mut [T]) {
                 l_head = l_iter.next();
             }
             (Some(l), Some(r)) => {
-                if l <= r {
+                // take from the left on ties to keep the sort stable
+                if !is_less(r, l) {
                     mem::swap(it, l);
                     l_head = l_iter.next();
                 } else {
@@ -62,50 +86,68 @@ fn merge_copy<T: Ord>(output: &mut [T], l: &mut [T], r: &mut [T]) {
 
 /// Generic merge sort that also works with non-`Copy` types.
 pub fn merge_sort<T: Ord>(slice: &mut [T]) {
-    let mut tmp = Vec::with_capacity(slice.len());
-    for _ in 0..slice.len() {
-        tmp.push(MaybeUninit::<T>::uninit());
-    }
+    merge_sort_impl(slice, &mut T::lt);
+}
 
-    // SAFETY: `MaybeUninit<T>` is `#[repr(transparent)]` which guarantees that
-    //   it has the same layout as `T`. This in turn guarantees that `&mut [T]`
-    //   and `&mut [MaybeUninit<T>]` have same layouts.
-    let slice = unsafe {
-        let len = slice.len();
-        let ptr = slice.as_mut_ptr().cast::<MaybeUninit<T>>();
-        core::slice::from_raw_parts_mut(ptr, len)
-    };
+sort_by_variants!(
+    merge_sort,
+    merge_sort_by,
+    merge_sort_by_key,
+    merge_sort_impl
+);
+
+fn merge_sort_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let len = slice.len();
+    // only used as scratch space, its length stays 0 so it never drops any
+    // of the items
+    let mut tmp = Vec::<T>::with_capacity(len);
 
-    // SAFETY: all items in slice are initialized
-    unsafe { merge_sort_core(slice, &mut tmp, 0) };
     // SAFETY:
-    //  * `merge_sort_core` guarantees that all items in `slice` are initialized
-    //     after it returns. Thus the original reference to slice is OK to be
-    //     used now after we return.
-    //  * `tmp` now contains only uninitialized data. Thus we don't need to drop
-    //     any `T`s and `Vec` can safely `drop` itself.
-    //
+    //  * all items in `slice` are initialized and `tmp` has room for `len`
+    //    items
+    //  * `merge_sort_core` guarantees that all items are in `slice` after it
+    //    returns, also if `is_less` panics. Thus the original reference to
+    //    slice is OK to be used now after we return.
+    unsafe { merge_sort_core(slice.as_mut_ptr(), tmp.as_mut_ptr(), len, 0, is_less) };
 }
 
-/// Sort initialized values into `output`.
+/// Sort `len` initialized values into `output`.
 ///
 /// As a result all items in `output` will be initialized
 /// and all items in `tmp` will be uninitialized.
+/// If `is_less` panics, all items are moved to `output` in unspecified order
+/// before unwinding.
 ///
 /// # SAFETY:
 ///
+/// * `output` and `tmp` must be valid for reads and writes of `len` items and
+///   must not overlap
 /// * outer call must start at `depth == 0`
 /// * at even (including 0) `depth`, all items in `output` must be initialized
 /// * at odd `depth`, all items in `tmp` must be initialized
-unsafe fn merge_sort_core<T: Ord>(
-    output: &mut [MaybeUninit<T>],
-    tmp: &mut [MaybeUninit<T>],
+unsafe fn merge_sort_core<T, F>(
+    output: *mut T,
+    tmp: *mut T,
+    len: usize,
     depth: usize,
-) {
-    if output.len() > 1 {
-        let mid = output.len() / 2;
-        let (l, r) = output.split_at_mut(mid);
-        let (tmpl, tmpr) = tmp.split_at_mut(mid);
+    is_less: &mut F,
+) where
+    F: FnMut(&T, &T) -> bool,
+{
+    if len > 1 {
+        let mid = len / 2;
+
+        // If `is_less` panics, move the halves which are in `tmp` back to
+        // `output`. At even depths the right half is in `output` until it's
+        // sorted into `tmp`.
+        let mut guard = CopyOnDrop {
+            src: tmp,
+            dest: output,
+            len: if depth.is_multiple_of(2) { mid } else { len },
+        };
 
         // sort into temporary arrays
 
@@ -113,66 +155,113 @@ unsafe fn merge_sort_core<T: Ord>(
         //  If at `depth==0` `output` is initialized,
         //  then at even depths `output` is initialized
         //  and at odd depths `tmp` is initialized.
-        unsafe { merge_sort_core(tmpl, l, depth + 1) };
-        unsafe { merge_sort_core(tmpr, r, depth + 1) };
+        unsafe { merge_sort_core(tmp, output, mid, depth + 1, is_less) };
+        guard.len = len;
+        unsafe { merge_sort_core(tmp.add(mid), output.add(mid), len - mid, depth + 1, is_less) };
+        mem::forget(guard);
 
         // merge into actual array we want to sort
-        unsafe { merge(output, tmpl, tmpr) };
-    } else if depth % 2 != 0 {
+        unsafe { merge(output, tmp, mid, tmp.add(mid), len - mid, is_less) };
+    } else if !depth.is_multiple_of(2) {
         // odd depth with single item
-        // tmp is initialized, swap with output
-        mem::swap(&mut output[0], &mut tmp[0])
+        // tmp is initialized, move it to output
+        unsafe { ptr::copy_nonoverlapping(tmp, output, 1) };
     } else {
         // even depth with single item
         // output is already sorted and initialized
     }
 }
 
-/// Merge sorted slices l and r into output.
-///
-/// Note that following must hold: `l.len() + r.len() == output.len()`.
+/// Copies `len` items from `src` to `dest` when dropped.
+struct CopyOnDrop<T> {
+    src: *const T,
+    dest: *mut T,
+    len: usize,
+}
+
+impl<T> Drop for CopyOnDrop<T> {
+    fn drop(&mut self) {
+        // SAFETY: `src[..len]` are initialized and `dest[..len]` is
+        //  uninitialized, by the invariants of `merge_sort_core`
+        unsafe { ptr::copy_nonoverlapping(self.src, self.dest, self.len) };
+    }
+}
+
+/// Items of the two runs of a merge which aren't merged yet
+struct MergeHole<T> {
+    left: *const T,
+    left_len: usize,
+    right: *const T,
+    right_len: usize,
+    dest: *mut T,
+}
+
+impl<T> Drop for MergeHole<T> {
+    fn drop(&mut self) {
+        // SAFETY: `dest` has room for the items left in both runs, by the
+        //  invariants of `merge`
+        unsafe {
+            ptr::copy_nonoverlapping(self.left, self.dest, self.left_len);
+            ptr::copy_nonoverlapping(self.right, self.dest.add(self.left_len), self.right_len);
+        }
+    }
+}
+
+/// Merge sorted runs `l[..l_len]` and `r[..r_len]` into output.
 ///
-/// As a result all items in output will be initialized and sorted.
+/// As a result all items in output will be initialized and sorted, or in
+/// unspecified order if `is_less` panics.
 /// All items in l and r will be uninitialized.
 ///
 /// # SAFETY
 ///
 /// * all items in l and r must be initialized at start
-unsafe fn merge<T: Ord>(
-    output: &mut [MaybeUninit<T>],
-    l: &mut [MaybeUninit<T>],
-    r: &mut [MaybeUninit<T>],
-) {
-    debug_assert_eq!(l.len() + r.len(), output.len());
-    let mut l_iter = l.iter_mut();
-    let mut r_iter = r.iter_mut();
+/// * `output` must be valid for writes of `l_len + r_len` items and must not
+///   overlap l or r
+unsafe fn merge<T, F>(
+    output: *mut T,
+    l: *const T,
+    l_len: usize,
+    r: *const T,
+    r_len: usize,
+    is_less: &mut F,
+) where
+    F: FnMut(&T, &T) -> bool,
+{
+    // INVARIANTS: `hole.left[..hole.left_len]` and
+    //  `hole.right[..hole.right_len]` are the items not merged yet and
+    //  `hole.dest` is the next position to merge into. If `is_less` panics,
+    //  dropping `hole` moves them after the merged items.
+    let mut hole = MergeHole {
+        left: l,
+        left_len: l_len,
+        right: r,
+        right_len: r_len,
+        dest: output,
+    };
 
-    let mut l_head = l_iter.next();
-    let mut r_head = r_iter.next();
     // take items from left and right one at the time
-    // put the smaller of lhead and rhead as the next item in slice
-    for it in output.iter_mut() {
-        match (&mut l_head, &mut r_head) {
-            (None, None) => unreachable!(),
-            (None, Some(r)) => {
-                mem::swap(it, r);
-                r_head = r_iter.next();
-            }
-            (Some(l), None) => {
-                mem::swap(it, l);
-                l_head = l_iter.next();
-            }
-            (Some(l), Some(r)) => {
-                if unsafe { l.assume_init_ref() <= r.assume_init_ref() } {
-                    mem::swap(it, l);
-                    l_head = l_iter.next();
-                } else {
-                    mem::swap(it, r);
-                    r_head = r_iter.next();
-                }
-            }
+    // put the smaller of the heads as the next item in output
+    while hole.left_len > 0 && hole.right_len > 0 {
+        // SAFETY: both runs have items left, by the invariants
+        unsafe {
+            // take from the left on ties to keep the sort stable
+            let src = if is_less(&*hole.right, &*hole.left) {
+                let src = hole.right;
+                hole.right = src.add(1);
+                hole.right_len -= 1;
+                src
+            } else {
+                let src = hole.left;
+                hole.left = src.add(1);
+                hole.left_len -= 1;
+                src
+            };
+            ptr::copy_nonoverlapping(src, hole.dest, 1);
+            hole.dest = hole.dest.add(1);
         }
     }
+    // dropping `hole` moves the rest of the run which has items left
 }
 
 #[cfg(test)]
@@ -206,6 +295,50 @@ mod tests {
         assert_eq!(arr, sorted);
     }
 
+    #[test]
+    fn by_and_by_key() {
+        // stable, equal keys keep their order
+        let people = [("b", 3), ("a", 1), ("c", 3), ("d", 2)];
+        let mut arr = people;
+        merge_sort_by_key(&mut arr, |p| p.1);
+        assert_eq!(arr, [("a", 1), ("d", 2), ("b", 3), ("c", 3)]);
+        let mut arr = people;
+        merge_sort_copy_by(&mut arr, |a, b| b.1.cmp(&a.1));
+        assert_eq!(arr, [("b", 3), ("c", 3), ("d", 2), ("a", 1)]);
+
+        let mut arr = vec![String::from("ccc"), String::from("a"), String::from("bb")];
+        merge_sort_by(&mut arr, |a, b| b.cmp(a));
+        assert_eq!(arr, ["ccc", "bb", "a"]);
+    }
+
+    #[test]
+    fn panic_in_comparison() {
+        let input: Vec<_> = (0..200).map(|i| (i * 37) % 200).collect();
+        let mut total = 0;
+        merge_sort_by(&mut input.clone(), |a: &i32, b| {
+            total += 1;
+            a.cmp(b)
+        });
+
+        for panic_at in (0..total).step_by(total / 50) {
+            let mut arr: Vec<_> = input.iter().map(|&i| Box::new(i)).collect();
+            let mut comparisons = 0;
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                merge_sort_by(&mut arr, |a, b| {
+                    assert!(comparisons != panic_at, "comparator panic");
+                    comparisons += 1;
+                    a.cmp(b)
+                })
+            }));
+            assert!(res.is_err());
+
+            // every item must be in the slice exactly once after a panic
+            let mut values: Vec<_> = arr.iter().map(|b| **b).collect();
+            values.sort();
+            assert!(values.into_iter().eq(0..200));
+        }
+    }
+
     mod proptests {
         use proptest::prelude::*;
 
@@ -246,6 +379,22 @@ mod tests {
                merge_sort(vec.as_mut_slice());
                assert_eq!(vec, sorted);
             }
+
+            #[test]
+            fn test_linked_list(
+                vec in proptest::collection::vec((0..100i32, any::<u8>()), 0..VEC_SIZE),
+            ) {
+               // sort by the first item only to also check stability against `slice::sort_by`
+               let mut list = linked_list::LinkedList::new();
+               for item in vec.iter().copied() {
+                   list.push_back(item);
+               }
+               list.sort_by(|a, b| a.0.cmp(&b.0));
+               let mut sorted = vec.clone();
+               sorted.sort_by_key(|a| a.0);
+               assert!(list.iter().eq(sorted.iter()));
+               assert!(list.iter().rev().eq(sorted.iter().rev()));
+            }
         );
     }
 }
@@ -0,0 +1,467 @@
+//! Pattern-defeating quicksort
+//!
+//! Quicksort with a few tricks so that the inputs which hurt the other
+//! quicksorts aren't a problem:
+//!
+//! * The pivot is the median of 3 items, or the median of 3 medians of 3
+//!   (ninther) for larger partitions. If those samples are already in order,
+//!   the partition is probably sorted and is checked with a short insertion
+//!   sort first.
+//! * A partition whose pivot is equal to the pivot of its parent partition
+//!   (its predecessor) has many equal items. These are put to the left in a
+//!   single pass and never looked at again, so few distinct items sort in
+//!   O(n * k) time where `k` is the number of distinct items.
+//! * After an unbalanced partition some items are swapped to break up the
+//!   pattern which caused it. The swaps are pseudorandom with a fixed seed,
+//!   so the sort is deterministic. After log2(n) unbalanced partitions the
+//!   rest is sorted with heapsort, which keeps the worst case O(n log n).
+//!
+//! Sorted, reversed and all equal inputs take O(n) time. Organ pipe inputs,
+//! whose middle item is the largest one and which make the partitions of the
+//! Lomuto and Hoare quicksorts unbalanced, take O(n log n).
+
+use crate::cmp::sort_by_variants;
+use crate::heapsort::heapsort_impl;
+use crate::insertion_sort::insertion_sort_impl;
+
+/// Partitions of at most this many items are sorted with insertion sort
+const INSERTION_SORT_THRESHOLD: usize = 24;
+
+/// Partitions of at least this many items use the ninther as the pivot
+const NINTHER_THRESHOLD: usize = 128;
+
+pub fn pdqsort<T: Ord>(slice: &mut [T]) {
+    pdqsort_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(pdqsort, pdqsort_by, pdqsort_by_key, pdqsort_impl);
+
+fn pdqsort_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let limit = (usize::BITS - slice.len().leading_zeros()) as usize;
+    pdqsort_core(slice, is_less, None, limit);
+}
+
+/// Sorts `slice` whose items are all greater than or equal to `pred`, the
+/// pivot of the partition before it.
+///
+/// `limit` is the number of unbalanced partitions allowed before switching to
+/// heapsort.
+fn pdqsort_core<'a, T, F>(
+    mut slice: &'a mut [T],
+    is_less: &mut F,
+    mut pred: Option<&'a T>,
+    mut limit: usize,
+) where
+    F: FnMut(&T, &T) -> bool,
+{
+    // was the last partition balanced and already partitioned
+    let mut was_balanced = true;
+    let mut was_partitioned = true;
+
+    loop {
+        let len = slice.len();
+        if len <= INSERTION_SORT_THRESHOLD {
+            insertion_sort_impl(slice, is_less);
+            return;
+        }
+
+        if limit == 0 {
+            heapsort_impl(slice, is_less);
+            return;
+        }
+
+        if !was_balanced {
+            break_patterns(slice);
+            limit -= 1;
+        }
+
+        let (pivot, likely_sorted) = choose_pivot(slice, is_less);
+
+        // The samples were in order and the last partition didn't move
+        // anything, try finishing it with a few insertions.
+        if was_balanced
+            && was_partitioned
+            && likely_sorted
+            && partial_insertion_sort(slice, is_less)
+        {
+            return;
+        }
+
+        slice.swap(0, pivot);
+
+        // All items are >= `pred`, so if the pivot isn't greater than it,
+        // they are equal. Put all of the items equal to the pivot to the left
+        // and continue with the greater ones.
+        if let Some(p) = pred {
+            if !is_less(p, &slice[0]) {
+                let mid = partition_equal(slice, is_less);
+                slice = &mut slice[mid..];
+                continue;
+            }
+        }
+
+        let (mid, partitioned) = partition(slice, is_less);
+        was_balanced = mid.min(len - mid) >= len / 8;
+        was_partitioned = partitioned;
+
+        let (left, rest) = slice.split_at_mut(mid);
+        let (pivot, right) = rest.split_first_mut().unwrap();
+        let pivot = &*pivot;
+
+        // recurse into the smaller side to keep the stack small, as in
+        // `quicksort::quicksort_with`
+        if left.len() < right.len() {
+            pdqsort_core(left, is_less, pred, limit);
+            slice = right;
+            pred = Some(pivot);
+        } else {
+            pdqsort_core(right, is_less, Some(pivot), limit);
+            slice = left;
+        }
+    }
+}
+
+/// Returns the index of the pivot and whether the slice is likely sorted.
+///
+/// A reversed slice is reversed first, so that it's likely sorted as well.
+///
+/// # PANICS
+///
+/// * if `slice.len() < 8`
+fn choose_pivot<T, F>(slice: &mut [T], is_less: &mut F) -> (usize, bool)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    const MAX_SWAPS: usize = 4 * 3;
+
+    let len = slice.len();
+    let mut a = len / 4;
+    let mut b = len / 2;
+    let mut c = len / 4 * 3;
+    let mut swaps = 0;
+
+    {
+        // sorts the indices by the items they point to
+        let mut sort2 = |a: &mut usize, b: &mut usize| {
+            if is_less(&slice[*b], &slice[*a]) {
+                core::mem::swap(a, b);
+                swaps += 1;
+            }
+        };
+        let mut sort3 = |a: &mut usize, b: &mut usize, c: &mut usize| {
+            sort2(a, b);
+            sort2(b, c);
+            sort2(a, b);
+        };
+
+        if len >= NINTHER_THRESHOLD {
+            // medians of the neighbourhoods
+            let mut median = |i: &mut usize| {
+                let (mut l, mut r) = (*i - 1, *i + 1);
+                sort3(&mut l, i, &mut r);
+            };
+            median(&mut a);
+            median(&mut b);
+            median(&mut c);
+        }
+        sort3(&mut a, &mut b, &mut c);
+    }
+
+    if swaps == 0 {
+        (b, true)
+    } else if swaps == MAX_SWAPS {
+        // every comparison was descending
+        slice.reverse();
+        (len - 1 - b, true)
+    } else {
+        (b, false)
+    }
+}
+
+/// Partitions the slice around the pivot at `slice[0]`.
+///
+/// Returns the new index `mid` of the pivot and whether the slice was already
+/// partitioned. Items in `slice[..mid]` are less than the pivot and items in
+/// `slice[mid + 1..]` are greater than or equal to it.
+fn partition<T, F>(slice: &mut [T], is_less: &mut F) -> (usize, bool)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let (pivot, rest) = slice.split_first_mut().unwrap();
+    let mut l = 0;
+    let mut r = rest.len();
+
+    // skip the items already on the right side
+    while l < r && is_less(&rest[l], pivot) {
+        l += 1;
+    }
+    while l < r && !is_less(&rest[r - 1], pivot) {
+        r -= 1;
+    }
+    let was_partitioned = l >= r;
+
+    loop {
+        while l < r && is_less(&rest[l], pivot) {
+            l += 1;
+        }
+        while l < r && !is_less(&rest[r - 1], pivot) {
+            r -= 1;
+        }
+        if l >= r {
+            break;
+        }
+        r -= 1;
+        rest.swap(l, r);
+        l += 1;
+    }
+
+    slice.swap(0, l);
+    (l, was_partitioned)
+}
+
+/// Partitions the slice into items equal to the pivot at `slice[0]` and
+/// items greater than it, given that no item is less than it.
+///
+/// Returns the number of items equal to the pivot.
+fn partition_equal<T, F>(slice: &mut [T], is_less: &mut F) -> usize
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let (pivot, rest) = slice.split_first_mut().unwrap();
+    let mut l = 0;
+    let mut r = rest.len();
+
+    loop {
+        while l < r && !is_less(pivot, &rest[l]) {
+            l += 1;
+        }
+        while l < r && is_less(pivot, &rest[r - 1]) {
+            r -= 1;
+        }
+        if l >= r {
+            break;
+        }
+        r -= 1;
+        rest.swap(l, r);
+        l += 1;
+    }
+
+    // and the pivot itself
+    l + 1
+}
+
+/// Sorts the slice with insertion sort if only a few items are out of place.
+///
+/// Returns `true` if the slice was sorted. Gives up after moving 5 items,
+/// leaving the slice partially sorted.
+fn partial_insertion_sort<T, F>(slice: &mut [T], is_less: &mut F) -> bool
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    const MAX_STEPS: usize = 5;
+    // moving items in shorter slices isn't worth it, they are partitioned
+    // next anyway
+    const SHORTEST_SHIFTING: usize = 50;
+
+    let len = slice.len();
+    let mut i = 1;
+    for _ in 0..MAX_STEPS {
+        while i < len && !is_less(&slice[i], &slice[i - 1]) {
+            i += 1;
+        }
+        if i == len {
+            return true;
+        }
+        if len < SHORTEST_SHIFTING {
+            return false;
+        }
+
+        slice.swap(i - 1, i);
+        // the smaller item to the left and the greater one to the right
+        for j in (1..i).rev() {
+            if !is_less(&slice[j], &slice[j - 1]) {
+                break;
+            }
+            slice.swap(j, j - 1);
+        }
+        for j in i + 1..len {
+            if !is_less(&slice[j], &slice[j - 1]) {
+                break;
+            }
+            slice.swap(j, j - 1);
+        }
+    }
+
+    false
+}
+
+/// Swaps a few items around the middle of the slice with pseudorandom items.
+///
+/// The seed is fixed so the sort is deterministic.
+fn break_patterns<T>(slice: &mut [T]) {
+    let len = slice.len();
+    if len < 8 {
+        return;
+    }
+
+    // xorshift
+    let mut random = len as u32;
+    let mut gen_u32 = || {
+        random ^= random << 13;
+        random ^= random >> 17;
+        random ^= random << 5;
+        random
+    };
+    let mask = len.next_power_of_two() - 1;
+
+    let pos = len / 4 * 2;
+    for i in 0..3 {
+        // faster than `% len` and close enough to uniform
+        let mut other = gen_u32() as usize & mask;
+        if other >= len {
+            other -= len;
+        }
+        slice.swap(pos - 1 + i, other);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_sorted(slice: &[i32]) {
+        slice.windows(2).for_each(|arr| {
+            let a = arr[0];
+            let b = arr[1];
+            assert!(a <= b);
+        })
+    }
+
+    /// Returns the number of comparisons to sort `slice`.
+    fn count_comparisons(slice: &mut [i32]) -> usize {
+        let mut count = 0;
+        pdqsort_by(slice, |a, b| {
+            count += 1;
+            a.cmp(b)
+        });
+        count
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn test() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        pdqsort(&mut arr);
+        assert_sorted(&arr);
+
+        let mut arr: Vec<_> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+        pdqsort(&mut arr);
+        assert!(arr.iter().copied().eq(0..1000));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn patterns() {
+        let n = 100_000;
+        let inputs: [(&str, Vec<i32>); 3] = [
+            ("ascending", (0..n).collect()),
+            ("descending", (0..n).rev().collect()),
+            ("equal", vec![7; n as usize]),
+        ];
+        for (name, mut arr) in inputs {
+            let comparisons = count_comparisons(&mut arr);
+            assert_sorted(&arr);
+            assert!(
+                comparisons < 4 * n as usize,
+                "{name}: {comparisons} comparisons"
+            );
+        }
+
+        // the middle item is the largest, a bad pivot
+        let mut arr: Vec<_> = (0..n / 2).chain((0..n / 2).rev()).collect();
+        let comparisons = count_comparisons(&mut arr);
+        assert_sorted(&arr);
+        let n_log_n = (n * n.ilog2() as i32) as usize;
+        assert!(comparisons < 2 * n_log_n, "{comparisons} comparisons");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn few_distinct_items() {
+        // equal items are partitioned out once
+        let n = 100_000;
+        let mut arr: Vec<_> = (0..n).map(|i| (i * 7919) % 4).collect();
+        let comparisons = count_comparisons(&mut arr);
+        assert_sorted(&arr);
+        assert!(comparisons < 8 * n as usize, "{comparisons} comparisons");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn by_and_by_key() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        pdqsort_by(&mut arr, |a, b| b.cmp(a));
+        assert_eq!(arr, [65, 45, 24, 4, 3, 3, 2, 1]);
+
+        let mut arr = vec![-3, 1, -2, 4, 0];
+        pdqsort_by_key(&mut arr, |a: &i32| a.abs());
+        assert_eq!(arr, [0, 1, -2, -3, 4]);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn test(
+                mut vec in proptest::collection::vec(0..10000i32, 0..VEC_SIZE),
+            ) {
+               pdqsort(vec.as_mut_slice());
+               assert_sorted(&vec);
+            }
+
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn few_distinct(
+                mut vec in proptest::collection::vec(0..4i32, 0..VEC_SIZE),
+            ) {
+               pdqsort(vec.as_mut_slice());
+               assert_sorted(&vec);
+            }
+
+            #[test]
+            #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+            fn nearly_sorted(
+                len in 0..VEC_SIZE,
+                swaps in proptest::collection::vec((any::<usize>(), any::<usize>()), 0..5),
+            ) {
+               let mut vec: Vec<_> = (0..len as i32).collect();
+               if len > 0 {
+                   for (a, b) in swaps {
+                       vec.swap(a % len, b % len);
+                   }
+               }
+               pdqsort(vec.as_mut_slice());
+               assert!(vec.into_iter().eq(0..len as i32));
+            }
+        );
+    }
+}
@@ -1,19 +1,49 @@
 use core::mem;
 
-pub fn quicksort_lomuto<T: Ord>(slice: &mut [T]) {
-    if slice.len() < 2 {
-        return;
-    }
+use crate::cmp::sort_by_variants;
 
-    let (l, r) = partition_lomuto(slice);
-    if l.len() > 1 {
-        quicksort_lomuto(l);
-    }
-    if r.len() > 1 {
-        quicksort_lomuto(r);
+/// Partitions a slice and returns the items before and after the pivot(s).
+type Partition<T, F> = for<'a> fn(&'a mut [T], &mut F) -> (&'a mut [T], &'a mut [T]);
+
+/// Quicksort driver shared by all partition schemes.
+///
+/// Only the smaller side of a partition is sorted recursively, the larger one
+/// is sorted in the loop. The smaller side is at most half of the slice, so
+/// the recursion depth is at most log2(n) even if the partitions are
+/// unbalanced.
+///
+/// `partition` must return the items before and after the pivot(s), see
+/// [`partition_lomuto`].
+fn quicksort_with<T, F>(mut slice: &mut [T], is_less: &mut F, partition: Partition<T, F>)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    while slice.len() > 1 {
+        let (l, r) = partition(slice, is_less);
+        let (smaller, larger) = if l.len() < r.len() { (l, r) } else { (r, l) };
+        quicksort_with(smaller, is_less, partition);
+        slice = larger;
     }
 }
 
+pub fn quicksort_lomuto<T: Ord>(slice: &mut [T]) {
+    quicksort_lomuto_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    quicksort_lomuto,
+    quicksort_lomuto_by,
+    quicksort_lomuto_by_key,
+    quicksort_lomuto_impl
+);
+
+fn quicksort_lomuto_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    quicksort_with(slice, is_less, partition_lomuto);
+}
+
 /// Partition the slice around the value of last item in-place using Lomuto's scheme.
 ///
 /// Returns two slices, where first contains items smaller than or equal the last and
@@ -23,7 +53,10 @@ pub fn quicksort_lomuto<T: Ord>(slice: &mut [T]) {
 /// # Panics
 ///
 /// * if `slice` is empty
-fn partition_lomuto<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
+fn partition_lomuto<'a, T, F>(slice: &'a mut [T], is_less: &mut F) -> (&'a mut [T], &'a mut [T])
+where
+    F: FnMut(&T, &T) -> bool,
+{
     // Move every item thats smaller than pivot to left.
 
     // use middle element as pivot to not fall to worst case perf for already sorted slices
@@ -36,7 +69,7 @@ fn partition_lomuto<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
 
     let mut count_smaller_than_pivot = 0;
     for i in 0..rest.len() {
-        if &rest[i] <= pivot {
+        if !is_less(pivot, &rest[i]) {
             if i != count_smaller_than_pivot {
                 rest.swap(count_smaller_than_pivot, i);
             }
@@ -56,17 +89,21 @@ fn partition_lomuto<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
 }
 
 pub fn quicksort_hoare<T: Ord>(slice: &mut [T]) {
-    if slice.len() < 2 {
-        return;
-    }
+    quicksort_hoare_impl(slice, &mut T::lt);
+}
 
-    let (l, r) = partition_hoare(slice);
-    if l.len() > 1 {
-        quicksort_hoare(l);
-    }
-    if r.len() > 1 {
-        quicksort_hoare(r);
-    }
+sort_by_variants!(
+    quicksort_hoare,
+    quicksort_hoare_by,
+    quicksort_hoare_by_key,
+    quicksort_hoare_impl
+);
+
+fn quicksort_hoare_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    quicksort_with(slice, is_less, partition_hoare);
 }
 
 /// Partition the slice around the value of first item in-place using Hoare's scheme.
@@ -78,7 +115,13 @@ pub fn quicksort_hoare<T: Ord>(slice: &mut [T]) {
 /// # Panics
 ///
 /// * if `slice` is empty
-fn partition_hoare<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
+pub(crate) fn partition_hoare<'a, T, F>(
+    slice: &'a mut [T],
+    is_less: &mut F,
+) -> (&'a mut [T], &'a mut [T])
+where
+    F: FnMut(&T, &T) -> bool,
+{
     // Overall idea here is to look for smaller items on the right and larger
     // items on the left and swap them. We do that by looking first from the
     // back/right for the smaller items than pivot and then from the left for
@@ -96,7 +139,7 @@ fn partition_hoare<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
     let mut left = 0;
     let mut right = rest.len() - 1;
 
-    while &rest[right] > pivot {
+    while is_less(pivot, &rest[right]) {
         if right == 0 {
             // all items on the right are already larger than pivot
             return (&mut [], &mut slice[1..]);
@@ -106,7 +149,7 @@ fn partition_hoare<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
 
     // If left == right, then right point
     while left < right {
-        debug_assert!(&rest[right] <= pivot);
+        debug_assert!(!is_less(pivot, &rest[right]));
         debug_assert!(right != 0);
         // Invariants:
         //  `rest[..left]` is `<= pivot`
@@ -119,7 +162,7 @@ fn partition_hoare<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
         //   and we have partitioned tha slice
 
         // find next item that's larger than `pivot`
-        if &rest[left] <= pivot {
+        if !is_less(pivot, &rest[left]) {
             // left is on the correct side
             left += 1
         } else {
@@ -128,7 +171,7 @@ fn partition_hoare<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
             // now `rest[right..]` is `> pivot`
             // `rest[..=left]` is `<= pivot`
             // look for the next smaller than `pivot` from the back
-            while &rest[right] > pivot {
+            while is_less(pivot, &rest[right]) {
                 right -= 1;
             }
         }
@@ -138,7 +181,7 @@ fn partition_hoare<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
 
     // swap `pivot` to correct position, `right` points to the last item that's `<= pivot`
     // swap with it so that left to `pivot` is `<= pivot` and right to pivot is `> pivot`
-    debug_assert!(&rest[right] <= pivot);
+    debug_assert!(!is_less(pivot, &rest[right]));
     mem::swap(pivot, &mut rest[right]);
     let (a, b) = slice.split_at_mut(right + 1);
     // exclude `pivot` from the returned slices
@@ -146,17 +189,21 @@ fn partition_hoare<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
 }
 
 pub fn quicksort_3way<T: Ord>(slice: &mut [T]) {
-    if slice.len() < 2 {
-        return;
-    }
+    quicksort_3way_impl(slice, &mut T::lt);
+}
 
-    let (l, r) = partition_3way(slice);
-    if l.len() > 1 {
-        quicksort_3way(l);
-    }
-    if r.len() > 1 {
-        quicksort_3way(r);
-    }
+sort_by_variants!(
+    quicksort_3way,
+    quicksort_3way_by,
+    quicksort_3way_by_key,
+    quicksort_3way_impl
+);
+
+fn quicksort_3way_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    quicksort_with(slice, is_less, partition_3way);
 }
 
 /// Partition the slice around the value of first item in-place using Hoare's scheme.
@@ -168,7 +215,10 @@ pub fn quicksort_3way<T: Ord>(slice: &mut [T]) {
 /// # Panics
 ///
 /// * if `slice` is empty
-fn partition_3way<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
+fn partition_3way<'a, T, F>(slice: &'a mut [T], is_less: &mut F) -> (&'a mut [T], &'a mut [T])
+where
+    F: FnMut(&T, &T) -> bool,
+{
     // Overall idea here is to look for smaller items on the right and larger
     // items on the left and swap them. We do that by looking first from the
     // back/right for the smaller items than pivot and then from the left for
@@ -188,7 +238,7 @@ fn partition_3way<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
     let mut equals_left = 0_usize;
     let mut right = rest.len() - 1;
 
-    while &rest[right] > pivot {
+    while is_less(pivot, &rest[right]) {
         if right == 0 {
             // all items on the right are already larger than pivot
             return (&mut [], &mut slice[1..]);
@@ -198,7 +248,7 @@ fn partition_3way<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
 
     // If left == right, then right point
     while left < right {
-        debug_assert!(&rest[right] <= pivot);
+        debug_assert!(!is_less(pivot, &rest[right]));
         debug_assert!(right != 0);
         // Invariants:
         //  `rest[..left]` is `<= pivot`
@@ -212,16 +262,15 @@ fn partition_3way<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
 
         // find next item that's larger than `pivot`
         let left_val = &rest[left];
-        #[allow(clippy::comparison_chain)]
-        if left_val < pivot {
+        if is_less(left_val, pivot) {
             left += 1;
-        } else if left_val > pivot {
+        } else if is_less(pivot, left_val) {
             // left > pivot, need to be moved
             rest.swap(left, right);
             // now `rest[right..]` is `> pivot`
             // `rest[..=left]` is `<= pivot`
             // look for the next smaller than `pivot` from the back
-            while &rest[right] > pivot {
+            while is_less(pivot, &rest[right]) {
                 right -= 1;
             }
         } else {
@@ -240,7 +289,7 @@ fn partition_3way<T: Ord>(slice: &mut [T]) -> (&mut [T], &mut [T]) {
 
     // swap `pivot` to correct position, `right` points to the last item that's `<= pivot`
     // swap with it so that left to `pivot` is `<= pivot` and right to pivot is `> pivot`
-    debug_assert!(&rest[right] <= pivot);
+    debug_assert!(!is_less(pivot, &rest[right]));
     mem::swap(pivot, &mut rest[right]);
 
     // swap all items that `== pivot` to the center before pivot
@@ -292,6 +341,36 @@ mod tests {
         assert_sorted(&arr);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn by_and_by_key() {
+        for sort in [quicksort_lomuto_by, quicksort_hoare_by, quicksort_3way_by] {
+            let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+            sort(&mut arr, |a: &i32, b: &i32| b.cmp(a));
+            assert_eq!(arr, [65, 45, 24, 4, 3, 3, 2, 1]);
+        }
+        for sort in [
+            quicksort_lomuto_by_key,
+            quicksort_hoare_by_key,
+            quicksort_3way_by_key,
+        ] {
+            let mut arr = vec![-3, 1, -2, 4, 0];
+            sort(&mut arr, |a: &i32| a.abs());
+            assert_eq!(arr, [0, 1, -2, -3, 4]);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn unbalanced_partitions() {
+        // Lomuto and Hoare put all items equal to the pivot on the same side,
+        // recursing into that side would need a stack frame for every item.
+        let mut arr = vec![7; 20_000];
+        quicksort_lomuto(&mut arr);
+        quicksort_hoare(&mut arr);
+        assert_sorted(&arr);
+    }
+
     mod proptests {
         use proptest::prelude::*;
 
@@ -1,15 +1,31 @@
 use std::mem;
 
+use crate::cmp::sort_by_variants;
+
 pub fn selection_sort<T>(slice: &mut [T])
 where
     T: Ord,
+{
+    selection_sort_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    selection_sort,
+    selection_sort_by,
+    selection_sort_by_key,
+    selection_sort_impl
+);
+
+fn selection_sort_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
 {
     // raw impl with indices
     for i in 0..slice.len() {
         let mut min_index = i;
         let mut min = &slice[i];
         for (j, it) in (i + 1..).zip(&slice[i + 1..]) {
-            if it < min {
+            if is_less(it, min) {
                 min_index = j;
                 min = it;
             }
@@ -24,6 +40,20 @@ where
 pub fn selection_sort2<T>(slice: &mut [T])
 where
     T: Ord,
+{
+    selection_sort2_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(
+    selection_sort2,
+    selection_sort2_by,
+    selection_sort2_by_key,
+    selection_sort2_impl
+);
+
+fn selection_sort2_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
 {
     // more idiomatic impl
     for i in 0..slice.len() {
@@ -36,10 +66,13 @@ where
         let (almost_sorted, unsorted) = slice.split_at_mut(i + 1);
         let first_unsorted = almost_sorted.last_mut().unwrap();
 
-        let min = unsorted.iter_mut().min();
+        // first of the smallest items, same as `Iterator::min`
+        let min = unsorted
+            .iter_mut()
+            .reduce(|min, it| if is_less(it, min) { it } else { min });
         match min {
             // min <= first_unsorted, no need to swap if it's already the smallest one
-            Some(min) if min < first_unsorted => mem::swap(first_unsorted, min),
+            Some(min) if is_less(min, first_unsorted) => mem::swap(first_unsorted, min),
             _ => {}
         }
     }
@@ -73,6 +106,22 @@ mod tests {
         assert_sorted(&arr);
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn by_and_by_key() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        selection_sort_by(&mut arr, |a, b| b.cmp(a));
+        assert_eq!(arr, [65, 45, 24, 4, 3, 3, 2, 1]);
+        selection_sort2_by(&mut arr, |a, b| a.cmp(b));
+        assert_eq!(arr, [1, 2, 3, 3, 4, 24, 45, 65]);
+
+        let mut arr = vec![-3, 1, -2, 4];
+        selection_sort_by_key(&mut arr, |a: &i32| a.abs());
+        assert_eq!(arr, [1, -2, -3, 4]);
+        selection_sort2_by_key(&mut arr, |a: &i32| -a);
+        assert_eq!(arr, [4, 1, -2, -3]);
+    }
+
     mod proptests {
         use proptest::prelude::*;
 
@@ -0,0 +1,666 @@
+//! Timsort, a stable adaptive merge sort
+//!
+//! The slice is split into natural runs, already ascending or strictly
+//! descending sequences. Descending runs are reversed and short runs are
+//! extended to `min_run` items with binary insertion sort. The runs are put
+//! on a stack and merged with their neighbours so that the run lengths grow
+//! exponentially along the stack, which keeps the merges balanced.
+//!
+//! If one run keeps winning during a merge, the merge switches to galloping:
+//! it finds how many items to take from that run at once with
+//! [`search::exponential::gallop`]. Together with the natural runs, sorting
+//! sorted, reversed and partially sorted data takes close to O(n) time while
+//! random data is still O(n log n).
+
+use core::{ptr, slice};
+
+use search::exponential::gallop;
+
+use crate::cmp::sort_by_variants;
+
+/// Slices shorter than this are sorted with binary insertion sort only
+const MIN_MERGE: usize = 32;
+
+/// Initial number of consecutive wins of a run after which a merge starts
+/// galloping
+const MIN_GALLOP: usize = 7;
+
+/// Stable sort, see the module docs.
+pub fn timsort<T: Ord>(slice: &mut [T]) {
+    timsort_impl(slice, &mut T::lt);
+}
+
+sort_by_variants!(timsort, timsort_by, timsort_by_key, timsort_impl);
+
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    start: usize,
+    len: usize,
+}
+
+/// State of a sort
+struct Merger<'a, T, F> {
+    // INVARIANTS:
+    //  * `runs` are sorted, adjacent and start at 0
+    //  * `buf` has length 0, its capacity is used as scratch space of merges
+    slice: &'a mut [T],
+    runs: Vec<Run>,
+    buf: Vec<T>,
+    min_gallop: usize,
+    is_less: &'a mut F,
+}
+
+fn timsort_impl<T, F>(slice: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let n = slice.len();
+    if n < 2 {
+        return;
+    }
+
+    if n < MIN_MERGE {
+        let run = count_run_and_make_ascending(slice, is_less);
+        binary_insertion_sort(slice, run, is_less);
+        return;
+    }
+
+    let min_run = min_run_length(n);
+    let mut merger = Merger {
+        slice,
+        runs: Vec::new(),
+        buf: Vec::new(),
+        min_gallop: MIN_GALLOP,
+        is_less,
+    };
+
+    let mut start = 0;
+    while start < n {
+        let rest = &mut merger.slice[start..];
+        let mut len = count_run_and_make_ascending(rest, merger.is_less);
+        if len < min_run {
+            let forced = min_run.min(rest.len());
+            binary_insertion_sort(&mut rest[..forced], len, merger.is_less);
+            len = forced;
+        }
+
+        merger.runs.push(Run { start, len });
+        start += len;
+        merger.merge_collapse();
+    }
+
+    merger.merge_force_collapse();
+    debug_assert_eq!(merger.runs.len(), 1);
+}
+
+/// Returns the minimum run length for a slice of length `n`.
+///
+/// Chosen so that `n / min_run` is a power of 2 or slightly less than one,
+/// which makes the final merges balanced.
+fn min_run_length(mut n: usize) -> usize {
+    // becomes 1 if any of the shifted out bits are 1
+    let mut r = 0;
+    while n >= MIN_MERGE {
+        r |= n & 1;
+        n >>= 1;
+    }
+    n + r
+}
+
+/// Returns the length of the run at the start of `slice`, reversing it if it's
+/// descending.
+///
+/// Descending runs must be strictly descending, otherwise reversing them
+/// would swap equal items.
+fn count_run_and_make_ascending<T, F>(slice: &mut [T], is_less: &mut F) -> usize
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let n = slice.len();
+    if n < 2 {
+        return n;
+    }
+
+    let mut end = 2;
+    if is_less(&slice[1], &slice[0]) {
+        while end < n && is_less(&slice[end], &slice[end - 1]) {
+            end += 1;
+        }
+        slice[..end].reverse();
+    } else {
+        while end < n && !is_less(&slice[end], &slice[end - 1]) {
+            end += 1;
+        }
+    }
+    end
+}
+
+/// Sorts `slice` whose first `sorted` items are already sorted.
+///
+/// Every item is inserted after the equal items before it to keep the sort
+/// stable.
+fn binary_insertion_sort<T, F>(slice: &mut [T], sorted: usize, is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    for i in sorted.max(1)..slice.len() {
+        let (sorted, rest) = slice.split_at(i);
+        let to_sort = &rest[0];
+        let new_index = sorted.partition_point(|x| !is_less(to_sort, x));
+        slice[new_index..=i].rotate_right(1);
+    }
+}
+
+impl<T, F> Merger<'_, T, F>
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    /// Merges runs at the top of the stack until the invariants hold:
+    ///
+    /// * `runs[i - 2].len > runs[i - 1].len + runs[i].len`
+    /// * `runs[i - 1].len > runs[i].len`
+    ///
+    /// The first one is checked for the top 4 runs, only checking the top 3
+    /// as in the original timsort misses some violations.
+    fn merge_collapse(&mut self) {
+        while self.runs.len() > 1 {
+            let runs_len = |i: usize| self.runs[i].len;
+            let mut n = self.runs.len() - 2;
+            if (n > 0 && runs_len(n - 1) <= runs_len(n) + runs_len(n + 1))
+                || (n > 1 && runs_len(n - 2) <= runs_len(n - 1) + runs_len(n))
+            {
+                // merge the middle run with the shorter of its neighbours
+                if runs_len(n - 1) < runs_len(n + 1) {
+                    n -= 1;
+                }
+            } else if runs_len(n) > runs_len(n + 1) {
+                break;
+            }
+            self.merge_at(n);
+        }
+    }
+
+    /// Merges all runs into one.
+    fn merge_force_collapse(&mut self) {
+        while self.runs.len() > 1 {
+            let mut n = self.runs.len() - 2;
+            if n > 0 && self.runs[n - 1].len < self.runs[n + 1].len {
+                n -= 1;
+            }
+            self.merge_at(n);
+        }
+    }
+
+    /// Merges the runs at `i` and `i + 1`.
+    fn merge_at(&mut self, i: usize) {
+        let a = self.runs[i];
+        let b = self.runs[i + 1];
+        debug_assert_eq!(a.start + a.len, b.start);
+        self.runs[i].len += b.len;
+        self.runs.remove(i + 1);
+
+        let is_less = &mut *self.is_less;
+        let merged = &mut self.slice[a.start..b.start + b.len];
+        let (left, right) = merged.split_at(a.len);
+
+        // Items at the start of `left` which aren't greater than the first
+        // item of `right` and items at the end of `right` which aren't less
+        // than the last item of `left` are already in place.
+        let skip = gallop(left, |x| !is_less(&right[0], x));
+        if skip == a.len {
+            return;
+        }
+        let left_last = &left[a.len - 1];
+        let right_len = gallop(right, |x| is_less(x, left_last));
+        let left_len = a.len - skip;
+
+        // copy the shorter run to `buf`
+        self.buf.reserve(left_len.min(right_len));
+        let base = merged[skip..].as_mut_ptr();
+        let buf = self.buf.as_mut_ptr();
+        // SAFETY:
+        //  * `base[..left_len + right_len]` is in `merged`, it's two sorted
+        //    runs of `left_len` and `right_len` items
+        //  * `buf` has capacity for the shorter of them and nothing else
+        //    points to it
+        unsafe {
+            if left_len <= right_len {
+                merge_lo(
+                    base,
+                    left_len,
+                    right_len,
+                    buf,
+                    &mut self.min_gallop,
+                    is_less,
+                );
+            } else {
+                merge_hi(
+                    base,
+                    left_len,
+                    right_len,
+                    buf,
+                    &mut self.min_gallop,
+                    is_less,
+                );
+            }
+        }
+    }
+}
+
+/// Items in `src[..len]` which must be moved to `dest[..len]`
+///
+/// A merge moves the shorter run to a buffer, which leaves a hole in the
+/// slice of the same length. The hole moves as the items are merged, the
+/// items left in the buffer are moved into it when the merge ends or panics.
+struct MergeHole<T> {
+    src: *const T,
+    dest: *mut T,
+    len: usize,
+}
+
+impl<T> Drop for MergeHole<T> {
+    fn drop(&mut self) {
+        // SAFETY: `src[..len]` are the items left in the buffer and
+        //  `dest[..len]` is the hole, by the invariants of the merges
+        unsafe { ptr::copy_nonoverlapping(self.src, self.dest, self.len) };
+    }
+}
+
+/// Merges `base[..len1]` and `base[len1..len1 + len2]` from the front,
+/// `len1 <= len2`.
+///
+/// # SAFETY
+///
+/// * `base[..len1 + len2]` must be valid for reads and writes, both halves
+///   must be sorted by `is_less`
+/// * `buf` must be valid for writes of `len1` items and not overlap `base`
+/// * the first item of the right run must be less than the first item of
+///   the left run and the last item of the left run must be greater than
+///   the last item of the right run
+unsafe fn merge_lo<T, F>(
+    base: *mut T,
+    len1: usize,
+    len2: usize,
+    buf: *mut T,
+    min_gallop: &mut usize,
+    is_less: &mut F,
+) where
+    F: FnMut(&T, &T) -> bool,
+{
+    // INVARIANTS: `hole.src[..hole.len]` are the left items not merged yet,
+    //  `hole.dest` is the next position to merge into, `right..right_end`
+    //  are the right items not merged yet and `right == hole.dest + hole.len`
+    //
+    // SAFETY (for the whole function): the invariants keep all pointers in
+    //  `base[..len1 + len2]` and `buf[..len1]`, every item is in exactly one
+    //  of `hole.src[..hole.len]`, `base[..hole.dest]` or `right..right_end`.
+    //  If `is_less` panics `hole` moves the rest of the left items into the
+    //  hole.
+    unsafe {
+        ptr::copy_nonoverlapping(base, buf, len1);
+        let mut hole = MergeHole {
+            src: buf,
+            dest: base,
+            len: len1,
+        };
+        let mut right = base.add(len1);
+        let right_end = right.add(len2);
+
+        'outer: loop {
+            let mut count1 = 0;
+            let mut count2 = 0;
+
+            // one item at a time until a run wins `min_gallop` times in a row
+            while count1 < *min_gallop && count2 < *min_gallop {
+                if is_less(&*right, &*hole.src) {
+                    ptr::copy_nonoverlapping(right, hole.dest, 1);
+                    right = right.add(1);
+                    hole.dest = hole.dest.add(1);
+                    count1 = 0;
+                    count2 += 1;
+                    if right == right_end {
+                        break 'outer;
+                    }
+                } else {
+                    take_front(&mut hole, 1);
+                    count1 += 1;
+                    count2 = 0;
+                    if hole.len == 0 {
+                        break 'outer;
+                    }
+                }
+            }
+
+            // gallop until neither run wins by much
+            loop {
+                // left items not greater than the next right item
+                let left = slice::from_raw_parts(hole.src, hole.len);
+                count1 = gallop(left, |x| !is_less(&*right, x));
+                take_front(&mut hole, count1);
+                if hole.len == 0 {
+                    break 'outer;
+                }
+
+                // the next right item is less than the next left item
+                ptr::copy_nonoverlapping(right, hole.dest, 1);
+                right = right.add(1);
+                hole.dest = hole.dest.add(1);
+                if right == right_end {
+                    break 'outer;
+                }
+
+                // right items less than the next left item
+                let rest = slice::from_raw_parts(right, right_end.offset_from(right) as usize);
+                count2 = gallop(rest, |x| is_less(x, &*hole.src));
+                ptr::copy(right, hole.dest, count2);
+                right = right.add(count2);
+                hole.dest = hole.dest.add(count2);
+                if right == right_end {
+                    break 'outer;
+                }
+
+                // the next left item is not greater than the next right item
+                take_front(&mut hole, 1);
+                if hole.len == 0 {
+                    break 'outer;
+                }
+
+                *min_gallop = min_gallop.saturating_sub(1);
+                if count1 < MIN_GALLOP && count2 < MIN_GALLOP {
+                    // penalize leaving galloping mode
+                    *min_gallop += 2;
+                    break;
+                }
+            }
+        }
+        // `hole` moves the remaining left items in place
+    }
+}
+
+/// Moves `count` items from the front of the buffer into the hole.
+///
+/// # SAFETY
+///
+/// * `count <= hole.len`
+unsafe fn take_front<T>(hole: &mut MergeHole<T>, count: usize) {
+    debug_assert!(count <= hole.len);
+    // SAFETY: `hole.src[..count]` and `hole.dest[..count]` are valid by the
+    //  invariants of `merge_lo` and the caller
+    unsafe {
+        ptr::copy_nonoverlapping(hole.src, hole.dest, count);
+        hole.src = hole.src.add(count);
+        hole.dest = hole.dest.add(count);
+    }
+    hole.len -= count;
+}
+
+/// Merges `base[..len1]` and `base[len1..len1 + len2]` from the back,
+/// `len1 > len2`.
+///
+/// # SAFETY
+///
+/// * same as for [`merge_lo`], except that `buf` must be valid for writes of
+///   `len2` items
+unsafe fn merge_hi<T, F>(
+    base: *mut T,
+    len1: usize,
+    len2: usize,
+    buf: *mut T,
+    min_gallop: &mut usize,
+    is_less: &mut F,
+) where
+    F: FnMut(&T, &T) -> bool,
+{
+    // INVARIANTS: `hole.src[..hole.len]` are the right items not merged yet,
+    //  `base[..hole.dest]` are the left items not merged yet and the merged
+    //  items are in `hole.dest[hole.len..]`, so the next position to merge
+    //  into is `hole.dest[hole.len - 1]`
+    //
+    // SAFETY (for the whole function): same as in `merge_lo`
+    unsafe {
+        let right = base.add(len1);
+        ptr::copy_nonoverlapping(right, buf, len2);
+        let mut hole = MergeHole {
+            src: buf,
+            dest: right,
+            len: len2,
+        };
+
+        'outer: loop {
+            let mut count1 = 0;
+            let mut count2 = 0;
+
+            // one item at a time until a run wins `min_gallop` times in a row
+            while count1 < *min_gallop && count2 < *min_gallop {
+                let left_last = hole.dest.sub(1);
+                let right_last = hole.src.add(hole.len - 1);
+                if is_less(&*right_last, &*left_last) {
+                    ptr::copy_nonoverlapping(left_last, left_last.add(hole.len), 1);
+                    hole.dest = left_last;
+                    count1 += 1;
+                    count2 = 0;
+                    if hole.dest == base {
+                        break 'outer;
+                    }
+                } else {
+                    take_back(&mut hole, 1);
+                    count1 = 0;
+                    count2 += 1;
+                    if hole.len == 0 {
+                        break 'outer;
+                    }
+                }
+            }
+
+            // gallop until neither run wins by much
+            loop {
+                // left items greater than the last right item
+                let right_last = &*hole.src.add(hole.len - 1);
+                let left = slice::from_raw_parts(base, hole.dest.offset_from(base) as usize);
+                count1 = left.len() - gallop(left, |x| !is_less(right_last, x));
+                let from = hole.dest.sub(count1);
+                ptr::copy(from, from.add(hole.len), count1);
+                hole.dest = from;
+                if hole.dest == base {
+                    break 'outer;
+                }
+
+                // the last right item is not less than the last left item
+                take_back(&mut hole, 1);
+                if hole.len == 0 {
+                    break 'outer;
+                }
+
+                // right items not less than the last left item
+                let left_last = &*hole.dest.sub(1);
+                let right = slice::from_raw_parts(hole.src, hole.len);
+                count2 = right.len() - gallop(right, |x| is_less(x, left_last));
+                take_back(&mut hole, count2);
+                if hole.len == 0 {
+                    break 'outer;
+                }
+
+                // the last left item is greater than the last right item
+                let left_last = hole.dest.sub(1);
+                ptr::copy_nonoverlapping(left_last, left_last.add(hole.len), 1);
+                hole.dest = left_last;
+                if hole.dest == base {
+                    break 'outer;
+                }
+
+                *min_gallop = min_gallop.saturating_sub(1);
+                if count1 < MIN_GALLOP && count2 < MIN_GALLOP {
+                    // penalize leaving galloping mode
+                    *min_gallop += 2;
+                    break;
+                }
+            }
+        }
+        // `hole` moves the remaining right items in place
+    }
+}
+
+/// Moves `count` items from the back of the buffer into the hole.
+///
+/// # SAFETY
+///
+/// * `count <= hole.len`
+unsafe fn take_back<T>(hole: &mut MergeHole<T>, count: usize) {
+    debug_assert!(count <= hole.len);
+    hole.len -= count;
+    // SAFETY: `hole.src[hole.len..hole.len + count]` and
+    //  `hole.dest[hole.len..hole.len + count]` are valid by the invariants
+    //  of `merge_hi` and the caller
+    unsafe {
+        ptr::copy_nonoverlapping(hole.src.add(hole.len), hole.dest.add(hole.len), count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_sorted(slice: &[i32]) {
+        slice.windows(2).for_each(|arr| {
+            let a = arr[0];
+            let b = arr[1];
+            assert!(a <= b);
+        })
+    }
+
+    #[test]
+    fn test() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        timsort(&mut arr);
+        assert_sorted(&arr);
+
+        let mut arr: Vec<_> = (0..1000).rev().chain(0..1000).collect();
+        timsort(&mut arr);
+        assert_sorted(&arr);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn min_run() {
+        for n in 0..MIN_MERGE {
+            assert_eq!(min_run_length(n), n);
+        }
+        assert_eq!(min_run_length(32), 16);
+        assert_eq!(min_run_length(33), 17);
+        assert_eq!(min_run_length(64), 16);
+        assert_eq!(min_run_length(65), 17);
+        assert_eq!(min_run_length(1000), 32);
+        assert_eq!(min_run_length(1024), 16);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "no unsafe code, nothing for miri to check")]
+    fn runs() {
+        let mut arr = [5, 4, 3, 3, 1, 2];
+        // strictly descending only
+        assert_eq!(count_run_and_make_ascending(&mut arr, &mut i32::lt), 3);
+        assert_eq!(arr[..3], [3, 4, 5]);
+        assert_eq!(count_run_and_make_ascending(&mut arr[3..], &mut i32::lt), 2);
+        assert_eq!(count_run_and_make_ascending(&mut arr[5..], &mut i32::lt), 1);
+
+        let mut arr = [1, 3, 5, 0, 4, 3, 5];
+        binary_insertion_sort(&mut arr, 3, &mut i32::lt);
+        assert_eq!(arr, [0, 1, 3, 3, 4, 5, 5]);
+    }
+
+    #[test]
+    fn by_and_by_key() {
+        let mut arr = vec![1, 4, 2, 24, 65, 3, 3, 45];
+        timsort_by(&mut arr, |a, b| b.cmp(a));
+        assert_eq!(arr, [65, 45, 24, 4, 3, 3, 2, 1]);
+
+        // stable, equal keys keep their order
+        let mut arr: Vec<_> = (0..200).map(|i| (i % 7, i)).collect();
+        timsort_by_key(&mut arr, |a| a.0);
+        let mut expected = arr.clone();
+        expected.sort_by_key(|a| a.0);
+        assert_eq!(arr, expected);
+    }
+
+    #[test]
+    fn panic_in_comparison() {
+        // sorted chunks so that the panics also happen in galloping merges
+        let input: Vec<_> = (0..500).map(|i| (i % 100) * 5 + i / 100).collect();
+        let mut total = 0;
+        timsort_by(&mut input.clone(), |a: &i32, b| {
+            total += 1;
+            a.cmp(b)
+        });
+
+        for panic_at in (0..total).step_by(total / 50) {
+            let mut arr: Vec<_> = input.iter().map(|&i| Box::new(i)).collect();
+            let mut comparisons = 0;
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                timsort_by(&mut arr, |a, b| {
+                    assert!(comparisons != panic_at, "comparator panic");
+                    comparisons += 1;
+                    a.cmp(b)
+                })
+            }));
+            assert!(res.is_err());
+
+            // every item must be in the slice exactly once after a panic
+            let mut values: Vec<_> = arr.iter().map(|b| **b).collect();
+            values.sort();
+            assert!(values.into_iter().eq(0..500));
+        }
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const VEC_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const VEC_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn stable(
+                mut vec in proptest::collection::vec((0..100i32, any::<u8>()), 0..VEC_SIZE),
+            ) {
+               // sort by the first item only to check stability against `slice::sort_by`
+               let mut sorted = vec.clone();
+               sorted.sort_by_key(|a| a.0);
+               timsort_by_key(vec.as_mut_slice(), |a| a.0);
+               assert_eq!(vec, sorted);
+            }
+
+            #[test]
+            fn sorted_runs(
+                runs in proptest::collection::vec(
+                    (proptest::collection::vec(0..1000i32, 0..200), any::<bool>()),
+                    0..10,
+                ),
+            ) {
+               // long runs in both directions make the merges gallop
+               let mut vec = Vec::new();
+               for (mut run, descending) in runs {
+                   run.sort();
+                   if descending {
+                       run.reverse();
+                   }
+                   vec.extend(run);
+               }
+               let mut sorted = vec.clone();
+               sorted.sort();
+               timsort(vec.as_mut_slice());
+               assert_eq!(vec, sorted);
+            }
+        );
+    }
+}
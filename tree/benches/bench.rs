@@ -0,0 +1,123 @@
+use core::hint::black_box;
+use core::time::Duration;
+use std::collections::HashMap;
+
+use criterion::measurement::Measurement;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use hashmap::open_addressing::{quadratic_probing, robin_hood};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use tree::{RadixTrie, RbTreeMap};
+
+macro_rules! select_measurement {
+    (walltime) => {
+        pub const MEASUREMENT_KIND: &str = "walltime";
+
+        pub fn create_measurement() -> impl ::criterion::measurement::Measurement {
+            ::criterion::measurement::WallTime
+        }
+    };
+}
+
+select_measurement!(walltime);
+
+const COUNTS: [usize; 3] = [100, 1000, 10_000];
+
+/// Repeatedly removes and reinserts all keys of a full tree in random order.
+fn churn<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("rbt_churn_{}", MEASUREMENT_KIND));
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $keys:expr, $new:expr) => {
+            let mut tree = $new;
+            for k in $keys.iter() {
+                tree.insert(*k, *k);
+            }
+            g.bench_with_input(BenchmarkId::new($name, $count), &$count, |b, _| {
+                b.iter(|| {
+                    for k in $keys.iter() {
+                        black_box(tree.delete(k));
+                    }
+                    for k in $keys.iter() {
+                        tree.insert(*k, *k);
+                    }
+                })
+            });
+        };
+    }
+
+    for count in COUNTS {
+        let mut keys = (0..count).collect::<Vec<_>>();
+        keys.shuffle(&mut ChaCha8Rng::seed_from_u64(42));
+
+        bench!("boxed", count, keys, RbTreeMap::new());
+        bench!("pooled", count, keys, RbTreeMap::with_node_pool());
+    }
+}
+
+/// Looks up all keys of a map with random url-like byte-string keys, which share many prefixes.
+fn byte_string_lookup<M: Measurement>(c: &mut Criterion<M>) {
+    let mut g = c.benchmark_group(format!("byte_string_lookup_{}", MEASUREMENT_KIND));
+
+    macro_rules! bench {
+        ($name:expr, $count:expr, $keys:expr, $map:expr, $to_key:expr) => {
+            let mut map = $map;
+            for (i, k) in $keys.iter().enumerate() {
+                map.insert($to_key(k), i);
+            }
+            g.bench_with_input(BenchmarkId::new($name, $count), &$count, |b, _| {
+                b.iter(|| {
+                    for k in $keys.iter() {
+                        black_box(map.get(k));
+                    }
+                })
+            });
+        };
+    }
+
+    for count in COUNTS {
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        let keys = (0..count)
+            .map(|_| {
+                format!(
+                    "https://example.com/{}/{}/{}",
+                    rng.gen_range(0..10),
+                    rng.gen_range(0..100),
+                    rng.gen::<u32>()
+                )
+                .into_bytes()
+            })
+            .collect::<Vec<_>>();
+
+        // the trie doesn't store the keys, the others own them
+        bench!("radix_trie", count, keys, RadixTrie::new(), Vec::as_slice);
+        bench!("std", count, keys, HashMap::new(), Vec::clone);
+        bench!(
+            "robin_hood",
+            count,
+            keys,
+            robin_hood::HashMap::new(),
+            Vec::clone
+        );
+        bench!(
+            "quadratic_probing",
+            count,
+            keys,
+            quadratic_probing::HashMap::new(),
+            Vec::clone
+        );
+        bench!("rbt", count, keys, RbTreeMap::new(), Vec::clone);
+    }
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default()
+        .measurement_time(Duration::from_secs(5))
+        .warm_up_time(Duration::from_millis(1000))
+        .with_measurement(create_measurement());
+    targets = churn, byte_string_lookup
+);
+
+criterion_main!(benches);
@@ -1,11 +1,18 @@
 use core::fmt;
 use std::borrow::Borrow;
 use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
 use std::ptr::{self, NonNull};
 
+use vec::{Queue, Stack};
+
+type Link<K, V> = Option<NonNull<Node<K, V>>>;
+
 struct Node<K, V> {
     key: K,
     value: V,
+    // number of nodes in the subtree rooted at this node (including itself)
+    size: usize,
     parent: Option<NonNull<Node<K, V>>>,
     left: Option<NonNull<Node<K, V>>>,
     right: Option<NonNull<Node<K, V>>>,
@@ -18,7 +25,9 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut f = f.debug_struct("Node");
-        f.field("key", &self.key).field("value", &self.value);
+        f.field("key", &self.key)
+            .field("value", &self.value)
+            .field("size", &self.size);
 
         let mut dbg_opt_node = |name: &str, node: &Option<NonNull<Node<K, V>>>| match node {
             Some(node) => {
@@ -40,8 +49,9 @@ where
 
 /// A binary search tree based map.
 ///
-/// For simplicity we don't allow duplicate keys.
-struct BinarySearchTree<K, V> {
+/// For simplicity we don't allow duplicate keys,
+/// see [`BstMultiMap`](crate::bst_multi_map::BstMultiMap) for a map which does.
+pub struct BinarySearchTree<K, V> {
     // INVARIANTS:
     //  * if `len > 0` then root is valid pointer to `Node`
     root: NonNull<Node<K, V>>,
@@ -55,23 +65,108 @@ impl<K, V> Drop for BinarySearchTree<K, V> {
             return;
         }
 
-        // TODO: handle panics in `K::drop` or `V::drop`
-
-        unsafe fn inner<K, V>(node: NonNull<Node<K, V>>) {
-            if let Some(l) = unsafe { (*node.as_ptr()).left } {
-                unsafe { inner(l) };
+        /// Guard in case `K::drop` or `V::drop` panics.
+        ///
+        /// We try to clean up as much as possible after the panic, eg try to
+        /// drop the remaining nodes.
+        struct Guard<K, V>(Link<K, V>);
+
+        impl<K, V> Guard<K, V> {
+            // Recursing into both subtrees would overflow the stack on degenerate trees.
+            // Instead rotate left children up until the current node has no left child
+            // (effectively flattening the tree into a right leaning list), then free
+            // the node and move to its right child. Every rotation moves one node
+            // out of the left spine, so this is O(n) and doesn't need any extra memory.
+            fn drop_nodes(&mut self) {
+                while let Some(node) = self.0 {
+                    let node = node.as_ptr();
+                    // SAFETY: all nodes reachable from root are valid and each is freed exactly once,
+                    // parent links are not used, so we don't need to keep them in sync
+                    unsafe {
+                        match (*node).left {
+                            Some(left) => {
+                                // rotate right: `left` becomes the parent of `node`
+                                (*node).left = (*left.as_ptr()).right;
+                                (*left.as_ptr()).right = Some(NonNull::new_unchecked(node));
+                                self.0 = Some(left);
+                            }
+                            None => {
+                                // Move to the next node before dropping this one, so
+                                // if `K::drop` or `V::drop` panics, the guard's
+                                // drop can continue from the next node.
+                                self.0 = (*node).right;
+                                drop(Box::from_raw(node));
+                            }
+                        }
+                    }
+                }
             }
-            if let Some(r) = unsafe { (*node.as_ptr()).right } {
-                unsafe { inner(r) };
+        }
+
+        impl<K, V> Drop for Guard<K, V> {
+            fn drop(&mut self) {
+                self.drop_nodes()
             }
-            let _ = unsafe { Box::from_raw(node.as_ptr()) };
         }
 
         self.len = 0;
-        unsafe { inner(self.root) }
+        let mut guard = Guard(Some(self.root));
+        guard.drop_nodes()
+    }
+}
+
+impl<K, V> Clone for BinarySearchTree<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    /// Deep clone which preserves the structure of the tree.
+    fn clone(&self) -> Self {
+        let mut new = Self::new();
+        if self.is_empty() {
+            return new;
+        }
+
+        // Nodes are added to `new` one by one, so if `K::clone` or `V::clone`
+        // panics, the already cloned nodes are freed by the `new`'s drop.
+        let mut stack = Stack::new();
+        stack.push((self.root, Slot::Root));
+        while let Some((node, slot)) = stack.pop() {
+            let node = unsafe { node.as_ref() };
+            // SAFETY: `slot` mirrors the position of `node` in `self`, thus it's empty and ordered.
+            //  The subtree sizes are copied from `self`, they are correct once all nodes are cloned.
+            let new_node =
+                unsafe { new.attach_leaf(slot, node.key.clone(), node.value.clone(), node.size) };
+            if let Some(l) = node.left {
+                stack.push((l, Slot::Left(new_node)));
+            }
+            if let Some(r) = node.right {
+                stack.push((r, Slot::Right(new_node)));
+            }
+        }
+
+        new
+    }
+}
+
+/// Trees are equal if they contain the same items, the shape of the trees doesn't matter.
+impl<K, V> PartialEq for BinarySearchTree<K, V>
+where
+    K: PartialEq,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
     }
 }
 
+impl<K, V> Eq for BinarySearchTree<K, V>
+where
+    K: Eq,
+    V: Eq,
+{
+}
+
 impl<K, V> fmt::Debug for BinarySearchTree<K, V>
 where
     K: fmt::Debug,
@@ -127,6 +222,12 @@ where
     }
 }
 
+impl<K, V> Default for BinarySearchTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K, V> BinarySearchTree<K, V> {
     pub fn new() -> Self {
         Self {
@@ -136,6 +237,72 @@ impl<K, V> BinarySearchTree<K, V> {
         }
     }
 
+    /// Builds a perfectly balanced tree from items sorted by key in O(n).
+    ///
+    /// Inserting sorted items one by one would degenerate into a list.
+    /// If there are duplicate keys, the last one wins.
+    ///
+    /// # PANICS
+    ///
+    /// * if the items are not sorted by key
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Ord,
+    {
+        let mut items: Vec<(K, V)> = Vec::new();
+        for (k, v) in iter {
+            match items.last_mut() {
+                Some(last) if last.0 == k => *last = (k, v),
+                Some(last) => {
+                    assert!(last.0 < k, "items must be sorted by key");
+                    items.push((k, v));
+                }
+                None => items.push((k, v)),
+            }
+        }
+
+        /// Builds a balanced subtree from the next `len` items of `items`.
+        ///
+        /// Recursion depth is `log2(len)` as the tree is balanced.
+        fn build<K, V>(
+            items: &mut std::vec::IntoIter<(K, V)>,
+            len: usize,
+        ) -> Option<NonNull<Node<K, V>>> {
+            if len == 0 {
+                return None;
+            }
+
+            // consume items in order: left subtree, the midpoint and then the right subtree
+            let left_len = len / 2;
+            let left = build(items, left_len);
+            let (key, value) = items.next().expect("`items` must contain `len` items");
+            let right = build(items, len - left_len - 1);
+
+            let node = Box::new(Node {
+                key,
+                value,
+                size: len,
+                parent: None,
+                left,
+                right,
+            });
+            let node = unsafe { NonNull::new_unchecked(Box::into_raw(node)) };
+            for child in [left, right].into_iter().flatten() {
+                unsafe { (*child.as_ptr()).parent = Some(node) };
+            }
+            Some(node)
+        }
+
+        let len = items.len();
+        let mut tree = Self::new();
+        if let Some(root) = build(&mut items.into_iter(), len) {
+            tree.root = root;
+            tree.len = len;
+        }
+        tree
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         self.len
@@ -174,6 +341,272 @@ impl<K, V> BinarySearchTree<K, V> {
         }
     }
 
+    /// Visit every node before its subtrees (node, left, right).
+    pub fn preorder_for_each<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V),
+    {
+        if self.is_empty() {
+            return;
+        }
+
+        let mut stack = Stack::new();
+        stack.push(self.root);
+        while let Some(node) = stack.pop() {
+            let node = unsafe { &mut *node.as_ptr() };
+            f(&node.key, &mut node.value);
+            // left must be popped first
+            if let Some(r) = node.right {
+                stack.push(r);
+            }
+            if let Some(l) = node.left {
+                stack.push(l);
+            }
+        }
+    }
+
+    /// Visit every node after its subtrees (left, right, node).
+    pub fn postorder_for_each<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V),
+    {
+        if self.is_empty() {
+            return;
+        }
+
+        // second item tells if the children have already been pushed to the stack
+        let mut stack = Stack::new();
+        stack.push((self.root, false));
+        while let Some((node, children_visited)) = stack.pop() {
+            let node_ref = unsafe { &mut *node.as_ptr() };
+            if children_visited {
+                f(&node_ref.key, &mut node_ref.value);
+            } else {
+                stack.push((node, true));
+                if let Some(r) = node_ref.right {
+                    stack.push((r, false));
+                }
+                if let Some(l) = node_ref.left {
+                    stack.push((l, false));
+                }
+            }
+        }
+    }
+
+    /// Iterate over the items level by level, from left to right on each level.
+    pub fn breadth_first_iter(&self) -> BreadthFirstIter<'_, K, V> {
+        let mut queue = Queue::new();
+        if !self.is_empty() {
+            queue.enqueue(self.root);
+        }
+
+        BreadthFirstIter {
+            queue,
+            marker: PhantomData,
+        }
+    }
+
+    /// Number of levels in the tree, that is number of nodes on the longest
+    /// path from the root to a leaf. Empty tree has height 0.
+    pub fn height(&self) -> usize {
+        let mut height = 0;
+        self.for_each_node_with_depth(|_, depth| height = height.max(depth + 1));
+        height
+    }
+
+    /// Number of nodes without any children.
+    pub fn leaf_count(&self) -> usize {
+        let mut count = 0;
+        self.for_each_node_with_depth(|node, _| {
+            let node = unsafe { node.as_ref() };
+            if node.left.is_none() && node.right.is_none() {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    /// Sum of the depths of all nodes.
+    ///
+    /// `internal_path_length / len` is the average number of steps needed to
+    /// reach a node from the root, that is the average cost of a successful search.
+    pub fn internal_path_length(&self) -> usize {
+        let mut sum = 0;
+        self.for_each_node_with_depth(|_, depth| sum += depth);
+        sum
+    }
+
+    /// Returns the depth of the node with `key`, root is at depth 0.
+    pub fn depth_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut node = self.get_raw(key)?;
+        let mut depth = 0;
+        while let Some(parent) = unsafe { node.as_ref().parent } {
+            node = parent;
+            depth += 1;
+        }
+        Some(depth)
+    }
+
+    /// Visit every node in level order with its depth.
+    fn for_each_node_with_depth<F>(&self, mut f: F)
+    where
+        F: FnMut(NonNull<Node<K, V>>, usize),
+    {
+        if self.is_empty() {
+            return;
+        }
+
+        let mut queue = Queue::new();
+        queue.enqueue((self.root, 0));
+        while let Some((node, depth)) = queue.dequeue() {
+            f(node, depth);
+            let node = unsafe { node.as_ref() };
+            for child in [node.left, node.right].into_iter().flatten() {
+                queue.enqueue((child, depth + 1));
+            }
+        }
+    }
+
+    /// Iterate over the items in ascending key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let (front, back) = self.min_max_nodes();
+        Iter {
+            front,
+            back,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate over the items in ascending key order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        let (front, back) = self.min_max_nodes();
+        IterMut {
+            front,
+            back,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate over the keys in ascending order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys(self.iter())
+    }
+
+    /// Iterate over the values in ascending key order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values(self.iter())
+    }
+
+    /// Iterate over the values in ascending key order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut(self.iter_mut())
+    }
+
+    fn min_max_nodes(&self) -> (Link<K, V>, Link<K, V>) {
+        if self.is_empty() {
+            return (None, None);
+        }
+        unsafe { (Some(Self::min_of(self.root)), Some(Self::max_of(self.root))) }
+    }
+
+    /// Iterate over the items whose keys are in `range` in ascending key order.
+    ///
+    /// Only the subtrees which can intersect the range are visited.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+        R: RangeBounds<Q>,
+    {
+        let front = match range.start_bound() {
+            Bound::Included(start) => self.first_node_where(|k| k.borrow() >= start),
+            Bound::Excluded(start) => self.first_node_where(|k| k.borrow() > start),
+            Bound::Unbounded => self.min_max_nodes().0,
+        };
+        let back = match range.end_bound() {
+            Bound::Included(end) => self.last_node_where(|k| k.borrow() <= end),
+            Bound::Excluded(end) => self.last_node_where(|k| k.borrow() < end),
+            Bound::Unbounded => self.min_max_nodes().1,
+        };
+
+        // `start > end` gives `front` after `back`
+        let is_valid = |front: NonNull<Node<K, V>>, back: NonNull<Node<K, V>>| {
+            let front: &Q = unsafe { (*front.as_ptr()).key.borrow() };
+            let back: &Q = unsafe { (*back.as_ptr()).key.borrow() };
+            front <= back
+        };
+        let (front, back) = match (front, back) {
+            (Some(f), Some(b)) if is_valid(f, b) => (Some(f), Some(b)),
+            _ => (None, None),
+        };
+
+        Range {
+            front,
+            back,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the first node in key order for which `pred` is `true`.
+    ///
+    /// `pred` must be `false` for some prefix of the keys and `true` for the rest.
+    fn first_node_where<F>(&self, mut pred: F) -> Link<K, V>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut found = None;
+        let mut maybe_node = Some(self.root);
+        while let Some(node) = maybe_node {
+            let node_ref = unsafe { &*node.as_ptr() };
+            if pred(&node_ref.key) {
+                // everything in the right subtree is larger, but there may
+                // be a smaller key which matches in the left subtree
+                found = Some(node);
+                maybe_node = node_ref.left;
+            } else {
+                maybe_node = node_ref.right;
+            }
+        }
+
+        found
+    }
+
+    /// Returns the last node in key order for which `pred` is `true`.
+    ///
+    /// `pred` must be `true` for some prefix of the keys and `false` for the rest.
+    fn last_node_where<F>(&self, mut pred: F) -> Link<K, V>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut found = None;
+        let mut maybe_node = Some(self.root);
+        while let Some(node) = maybe_node {
+            let node_ref = unsafe { &*node.as_ptr() };
+            if pred(&node_ref.key) {
+                found = Some(node);
+                maybe_node = node_ref.right;
+            } else {
+                maybe_node = node_ref.left;
+            }
+        }
+
+        found
+    }
+
     pub fn get<Q>(&self, key: &Q) -> Option<(&K, &V)>
     where
         K: Borrow<Q>,
@@ -223,15 +656,33 @@ impl<K, V> BinarySearchTree<K, V> {
         None
     }
 
+    /// Removes and returns the item with the smallest key.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let min = unsafe { Self::min_of(self.root) };
+        Some(self.delete_core(min))
+    }
+
+    /// Removes and returns the item with the largest key.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let max = unsafe { Self::max_of(self.root) };
+        Some(self.delete_core(max))
+    }
+
     pub fn min(&self) -> Option<(&K, &V)> {
         if self.is_empty() {
             return None;
         }
-        let min = unsafe { self.min_of(self.root) };
+        let min = unsafe { Self::min_of(self.root) };
         unsafe { Some(self.node_as_refs(min)) }
     }
 
-    unsafe fn min_of(&self, root: NonNull<Node<K, V>>) -> NonNull<Node<K, V>> {
+    unsafe fn min_of(root: NonNull<Node<K, V>>) -> NonNull<Node<K, V>> {
         let mut x = root;
         while let Some(left) = unsafe { (*x.as_ptr()).left } {
             x = left;
@@ -244,11 +695,11 @@ impl<K, V> BinarySearchTree<K, V> {
         if self.is_empty() {
             return None;
         }
-        let max = unsafe { self.max_of(self.root) };
+        let max = unsafe { Self::max_of(self.root) };
         unsafe { Some(self.node_as_refs(max)) }
     }
 
-    unsafe fn max_of(&self, root: NonNull<Node<K, V>>) -> NonNull<Node<K, V>> {
+    unsafe fn max_of(root: NonNull<Node<K, V>>) -> NonNull<Node<K, V>> {
         let mut x = root;
         while let Some(right) = unsafe { (*x.as_ptr()).right } {
             x = right;
@@ -263,18 +714,12 @@ impl<K, V> BinarySearchTree<K, V> {
         Q: Ord,
     {
         match self.get_raw(key) {
-            Some(node) => unsafe {
-                self.successor_core(node)
-                    .map(|node| self.node_as_refs(node))
-            },
+            Some(node) => unsafe { Self::successor_core(node).map(|node| self.node_as_refs(node)) },
             None => None,
         }
     }
 
-    unsafe fn successor_core(&self, node: NonNull<Node<K, V>>) -> Option<NonNull<Node<K, V>>>
-    where
-        K: Eq,
-    {
+    unsafe fn successor_core(node: NonNull<Node<K, V>>) -> Option<NonNull<Node<K, V>>> {
         //       ┌────────── 34 ─────────┐
         //       │                       │
         // ┌──── 2 ────┐                 58 ────┐
@@ -299,7 +744,7 @@ impl<K, V> BinarySearchTree<K, V> {
             // after the node. When we move down the tree and the new item is larger than any other item before the node,
             // it would end up in their right subtree whereas the node is in it's left subtree. Thus the item that ends up
             // it the node's right subtree must be larger than node but smaller than any other item that's larger than the node.
-            Some(right) => unsafe { Some(self.min_of(right)) },
+            Some(right) => unsafe { Some(Self::min_of(right)) },
             _ => {
                 // 6 -> 9, 1 -> 2, 13 -> 20, 24 -> 34 ...
                 // Move up the parents and find the first node which is the left child of it's parent.
@@ -338,17 +783,13 @@ impl<K, V> BinarySearchTree<K, V> {
     {
         match self.get_raw(key) {
             Some(node) => unsafe {
-                self.predecessor_core(node)
-                    .map(|node| self.node_as_refs(node))
+                Self::predecessor_core(node).map(|node| self.node_as_refs(node))
             },
             None => None,
         }
     }
 
-    fn predecessor_core(&self, node: NonNull<Node<K, V>>) -> Option<NonNull<Node<K, V>>>
-    where
-        K: Eq,
-    {
+    unsafe fn predecessor_core(node: NonNull<Node<K, V>>) -> Option<NonNull<Node<K, V>>> {
         //       ┌────────── 34 ─────────┐
         //       │                       │
         // ┌──── 2 ────┐                 58 ────┐
@@ -364,7 +805,7 @@ impl<K, V> BinarySearchTree<K, V> {
         let mut node = node.as_ptr();
         match unsafe { (*node).left } {
             // 2 -> 1, 9 -> 6, 20 -> 13, 77 -> 75
-            Some(left) => unsafe { Some(self.max_of(left)) },
+            Some(left) => unsafe { Some(Self::max_of(left)) },
             _ => {
                 // 12 -> 9, 58 -> 34, 67 -> 58
                 // Move up the parents and find the first node which is the right child of it's parent.
@@ -391,59 +832,146 @@ impl<K, V> BinarySearchTree<K, V> {
     where
         K: Eq + Ord,
     {
-        let mut new_node = Node {
-            key,
-            value,
-            parent: None,
-            left: None,
-            right: None,
-        };
+        match self.entry(key) {
+            Entry::Occupied(entry) => {
+                let node = entry.node.as_ptr();
+                unsafe {
+                    (*node).key = entry.key;
+                    (*node).value = value;
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
 
-        // Move left/right down the tree until we find empty slot
-        let mut parent = None;
+    /// Returns the entry for `key` for in-place manipulation.
+    ///
+    /// Finding the entry requires a single descent down the tree, any following
+    /// operation on it doesn't need to search the tree again.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V>
+    where
+        K: Ord,
+    {
+        // Move left/right down the tree until we find the key or an empty slot
+        let mut slot = Slot::Root;
         let mut maybe_node = if self.is_empty() {
             None
         } else {
             Some(self.root)
         };
         while let Some(node) = maybe_node {
-            parent = maybe_node;
-            let node = node.as_ptr();
+            let node_ptr = node.as_ptr();
             unsafe {
-                match (new_node.key).cmp(&(*node).key) {
-                    std::cmp::Ordering::Less => maybe_node = (*node).left,
+                match key.cmp(&(*node_ptr).key) {
+                    std::cmp::Ordering::Less => {
+                        slot = Slot::Left(node);
+                        maybe_node = (*node_ptr).left;
+                    }
                     std::cmp::Ordering::Equal => {
-                        (*node).key = new_node.key;
-                        (*node).value = new_node.value;
-                        return;
+                        return Entry::Occupied(OccupiedEntry {
+                            tree: self,
+                            node,
+                            key,
+                        });
+                    }
+                    std::cmp::Ordering::Greater => {
+                        slot = Slot::Right(node);
+                        maybe_node = (*node_ptr).right;
                     }
-                    std::cmp::Ordering::Greater => maybe_node = (*node).right,
                 }
             }
         }
 
-        new_node.parent = parent;
-        // new_node is a left, it cannot have left or right subtrees
-        let new_node = Box::new(new_node);
+        Entry::Vacant(VacantEntry {
+            tree: self,
+            slot,
+            key,
+        })
+    }
+
+    /// Creates a new leaf node at the empty `slot`.
+    ///
+    /// # SAFETY
+    ///
+    /// * `slot` must be empty and inserting `key` there must keep the tree ordered
+    unsafe fn insert_leaf(&mut self, slot: Slot<K, V>, key: K, value: V) -> NonNull<Node<K, V>> {
+        let new_node = unsafe { self.attach_leaf(slot, key, value, 1) };
+
+        // all ancestors got one more node in their subtrees
+        let mut maybe_node = unsafe { new_node.as_ref().parent };
+        while let Some(node) = maybe_node {
+            unsafe {
+                (*node.as_ptr()).size += 1;
+                maybe_node = (*node.as_ptr()).parent;
+            }
+        }
+
+        new_node
+    }
+
+    /// Creates a new leaf node with subtree size `size` at the empty `slot`.
+    ///
+    /// The sizes of its ancestors are not updated.
+    ///
+    /// # SAFETY
+    ///
+    /// * `slot` must be empty and inserting `key` there must keep the tree ordered
+    unsafe fn attach_leaf(
+        &mut self,
+        slot: Slot<K, V>,
+        key: K,
+        value: V,
+        size: usize,
+    ) -> NonNull<Node<K, V>> {
+        let parent = match slot {
+            Slot::Root => None,
+            Slot::Left(p) | Slot::Right(p) => Some(p),
+        };
+        // new_node is a leaf, it cannot have left or right subtrees
+        let new_node = Box::new(Node {
+            key,
+            value,
+            size,
+            parent,
+            left: None,
+            right: None,
+        });
         let new_node = unsafe { NonNull::new_unchecked(Box::into_raw(new_node)) };
         // update parent to point to the new node
-        match parent {
-            Some(parent) => {
-                let parent = parent.as_ptr();
-                unsafe {
-                    if (*new_node.as_ptr()).key < (*parent).key {
-                        (*parent).left = Some(new_node);
-                    } else {
-                        (*parent).right = Some(new_node);
-                    }
-                }
-            }
-            None => {
-                self.root = new_node;
-            }
+        match slot {
+            Slot::Root => self.root = new_node,
+            Slot::Left(p) => unsafe { (*p.as_ptr()).left = Some(new_node) },
+            Slot::Right(p) => unsafe { (*p.as_ptr()).right = Some(new_node) },
         }
 
         self.len += 1;
+        new_node
+    }
+
+    /// Keep only the items for which `f` returns `true`.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.extract_if(|k, v| !f(k, v)).for_each(drop);
+    }
+
+    /// Returns an iterator which removes and yields the items for which `pred` returns `true`.
+    ///
+    /// Items are visited in ascending key order. If the iterator is dropped before
+    /// it's exhausted, the remaining items are kept in the tree.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, K, V, F>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let next = self.min_max_nodes().0;
+        ExtractIf {
+            tree: self,
+            next,
+            pred,
+        }
     }
 
     pub fn delete<Q>(&mut self, key: &Q) -> Option<(K, V)>
@@ -473,6 +1001,7 @@ impl<K, V> BinarySearchTree<K, V> {
                 // `node` has no children or only one.
                 // To remove `node` replace `node` with the it's child or `None`.
                 // For example remove 1, 6, 12, 58 from tree above
+                Self::decrement_sizes_from((*node_ptr).parent);
                 self.replace_subtree(node, v)
             },
             (Some(_), Some(right)) => unsafe {
@@ -480,7 +1009,7 @@ impl<K, V> BinarySearchTree<K, V> {
                 // next largest value in the tree. Since the `node` has right
                 // child it's successor is the minimum of it's right subtree.
                 // (See successor method for more details about it).
-                let min = self.min_of(right);
+                let min = Self::min_of(right);
                 // Now we want to replace `node` with `min`.
                 // There are two cases:
                 //  a) `min` is the right child of `node`,
@@ -494,6 +1023,13 @@ impl<K, V> BinarySearchTree<K, V> {
                 //     `node` with `min`
                 //     for example remove 9 from tree above, min will be 12
 
+                // In both cases the position of `min` is the one which is
+                // actually removed, all nodes above it (including `node`)
+                // lose one node from their subtrees. After the move `min`
+                // takes over the size of `node`.
+                Self::decrement_sizes_from((*min.as_ptr()).parent);
+                (*min.as_ptr()).size = (*node_ptr).size;
+
                 if !ptr::eq(min.as_ptr(), right.as_ptr()) {
                     // b)
                     self.replace_subtree(min, (*min.as_ptr()).right);
@@ -515,6 +1051,67 @@ impl<K, V> BinarySearchTree<K, V> {
         (node.key, node.value)
     }
 
+    /// Decrements the subtree sizes of `node` and all its ancestors.
+    unsafe fn decrement_sizes_from(mut maybe_node: Link<K, V>) {
+        while let Some(node) = maybe_node {
+            unsafe {
+                (*node.as_ptr()).size -= 1;
+                maybe_node = (*node.as_ptr()).parent;
+            }
+        }
+    }
+
+    /// Returns the item with `rank`, that is the `rank`-th smallest item (starting from 0).
+    ///
+    /// O(height)
+    pub fn select(&self, rank: usize) -> Option<(&K, &V)> {
+        if rank >= self.len {
+            return None;
+        }
+
+        let mut rank = rank;
+        let mut node = self.root;
+        loop {
+            let node_ref = unsafe { node.as_ref() };
+            let left_size = Self::size_of(node_ref.left);
+            match rank.cmp(&left_size) {
+                std::cmp::Ordering::Less => node = node_ref.left?,
+                std::cmp::Ordering::Equal => return Some((&node_ref.key, &node_ref.value)),
+                std::cmp::Ordering::Greater => {
+                    rank -= left_size + 1;
+                    node = node_ref.right?;
+                }
+            }
+        }
+    }
+
+    /// Returns the number of items with smaller keys than `key`, if `key` is in the tree.
+    ///
+    /// O(height)
+    pub fn rank<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let node = self.get_raw(key)?;
+        // everything in the left subtree is smaller, and moving up from a right
+        // child, the parent and its left subtree are also smaller
+        let mut rank = Self::size_of(unsafe { node.as_ref().left });
+        let mut node = node;
+        while let Some(parent) = unsafe { node.as_ref().parent } {
+            let parent_ref = unsafe { parent.as_ref() };
+            if parent_ref.right == Some(node) {
+                rank += Self::size_of(parent_ref.left) + 1;
+            }
+            node = parent;
+        }
+        Some(rank)
+    }
+
+    fn size_of(node: Link<K, V>) -> usize {
+        node.map_or(0, |node| unsafe { node.as_ref().size })
+    }
+
     /// Replaces subtree `old` with subtree `new`
     unsafe fn replace_subtree(
         &mut self,
@@ -581,13 +1178,442 @@ impl<K, V> BinarySearchTree<K, V> {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl<K, V> IntoIterator for BinarySearchTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
 
-    #[test]
-    fn test() {
-        let mut tree = BinarySearchTree::new();
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { tree: self }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BinarySearchTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut BinarySearchTree<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Empty position in the tree where a new node can be inserted.
+enum Slot<K, V> {
+    Root,
+    Left(NonNull<Node<K, V>>),
+    Right(NonNull<Node<K, V>>),
+}
+
+/// A view into a single entry of `BinarySearchTree`, created by [`BinarySearchTree::entry`].
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+pub struct OccupiedEntry<'a, K, V> {
+    tree: &'a mut BinarySearchTree<K, V>,
+    node: NonNull<Node<K, V>>,
+    // the key used to look up this entry
+    key: K,
+}
+
+pub struct VacantEntry<'a, K, V> {
+    tree: &'a mut BinarySearchTree<K, V>,
+    slot: Slot<K, V>,
+    key: K,
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// Inserts `default` if the entry is vacant and returns a mutable reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Inserts the result of `default` if the entry is vacant and returns a mutable reference to the value.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` with the value if the entry is occupied.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    /// Returns the key stored in the tree.
+    pub fn key(&self) -> &K {
+        unsafe { &(*self.node.as_ptr()).key }
+    }
+
+    pub fn get(&self) -> &V {
+        unsafe { &(*self.node.as_ptr()).value }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut (*self.node.as_ptr()).value }
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe { &mut (*self.node.as_ptr()).value }
+    }
+
+    /// Replaces the value and returns the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the tree.
+    pub fn remove(self) -> (K, V) {
+        self.tree.delete_core(self.node)
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        // SAFETY: slot was found by descending the tree with `key`
+        let node = unsafe { self.tree.insert_leaf(self.slot, self.key, value) };
+        unsafe { &mut (*node.as_ptr()).value }
+    }
+}
+
+/// In-order iterator over the items of `BinarySearchTree`.
+///
+/// Walks the tree using the parent pointers, so it doesn't need any extra memory.
+pub struct Iter<'a, K, V> {
+    // INVARIANTS:
+    //  * if `len > 0` then `front` and `back` are `Some` and point to the
+    //    next items to be returned from the front and back respectively
+    front: Option<NonNull<Node<K, V>>>,
+    back: Option<NonNull<Node<K, V>>>,
+    len: usize,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.front?;
+        self.len -= 1;
+        unsafe {
+            self.front = BinarySearchTree::successor_core(node);
+            let node = &*node.as_ptr();
+            Some((&node.key, &node.value))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.back?;
+        self.len -= 1;
+        unsafe {
+            self.back = BinarySearchTree::predecessor_core(node);
+            let node = &*node.as_ptr();
+            Some((&node.key, &node.value))
+        }
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            front: self.front,
+            back: self.back,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Mutable in-order iterator over the items of `BinarySearchTree`.
+pub struct IterMut<'a, K, V> {
+    // INVARIANTS: see `Iter`
+    front: Option<NonNull<Node<K, V>>>,
+    back: Option<NonNull<Node<K, V>>>,
+    len: usize,
+    marker: PhantomData<&'a mut Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.front?;
+        self.len -= 1;
+        // SAFETY: front and back never cross, so each node is returned only once
+        unsafe {
+            self.front = BinarySearchTree::successor_core(node);
+            let node = &mut *node.as_ptr();
+            Some((&node.key, &mut node.value))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.back?;
+        self.len -= 1;
+        // SAFETY: front and back never cross, so each node is returned only once
+        unsafe {
+            self.back = BinarySearchTree::predecessor_core(node);
+            let node = &mut *node.as_ptr();
+            Some((&node.key, &mut node.value))
+        }
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+/// Owning in-order iterator over the items of `BinarySearchTree`.
+pub struct IntoIter<K, V> {
+    // Remaining items are dropped by the tree
+    tree: BinarySearchTree<K, V>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tree.pop_first()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.tree.len, Some(self.tree.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.tree.pop_last()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+macro_rules! impl_projection_iter {
+    ($name:ident, $item:ty, $proj:expr) => {
+        impl<'a, K, V> Iterator for $name<'a, K, V> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next().map($proj)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.0.size_hint()
+            }
+        }
+
+        impl<K, V> DoubleEndedIterator for $name<'_, K, V> {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.0.next_back().map($proj)
+            }
+        }
+
+        impl<K, V> ExactSizeIterator for $name<'_, K, V> {}
+    };
+}
+
+/// Iterator over the keys of `BinarySearchTree`, created by [`BinarySearchTree::keys`].
+pub struct Keys<'a, K, V>(Iter<'a, K, V>);
+
+impl_projection_iter!(Keys, &'a K, |(k, _)| k);
+
+/// Iterator over the values of `BinarySearchTree`, created by [`BinarySearchTree::values`].
+pub struct Values<'a, K, V>(Iter<'a, K, V>);
+
+impl_projection_iter!(Values, &'a V, |(_, v)| v);
+
+/// Mutable iterator over the values of `BinarySearchTree`, created by [`BinarySearchTree::values_mut`].
+pub struct ValuesMut<'a, K, V>(IterMut<'a, K, V>);
+
+impl_projection_iter!(ValuesMut, &'a mut V, |(_, v)| v);
+
+/// Iterator which removes items from `BinarySearchTree`, created by [`BinarySearchTree::extract_if`].
+pub struct ExtractIf<'a, K, V, F> {
+    tree: &'a mut BinarySearchTree<K, V>,
+    // next node to test
+    next: Link<K, V>,
+    pred: F,
+}
+
+impl<K, V, F> Iterator for ExtractIf<'_, K, V, F>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.next {
+            // `delete_core` only frees the removed node and relinks the others,
+            // so the successor stays valid and is still the next node in order.
+            self.next = unsafe { BinarySearchTree::successor_core(node) };
+            let node_ref = unsafe { &mut *node.as_ptr() };
+            if (self.pred)(&node_ref.key, &mut node_ref.value) {
+                return Some(self.tree.delete_core(node));
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.tree.len))
+    }
+}
+
+/// Level-order iterator over the items of `BinarySearchTree`,
+/// created by [`BinarySearchTree::breadth_first_iter`].
+pub struct BreadthFirstIter<'a, K, V> {
+    queue: Queue<NonNull<Node<K, V>>>,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for BreadthFirstIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.queue.dequeue()?;
+        let node = unsafe { &*node.as_ptr() };
+        if let Some(l) = node.left {
+            self.queue.enqueue(l);
+        }
+        if let Some(r) = node.right {
+            self.queue.enqueue(r);
+        }
+        Some((&node.key, &node.value))
+    }
+}
+
+/// Iterator over a range of items in `BinarySearchTree`, created by [`BinarySearchTree::range`].
+pub struct Range<'a, K, V> {
+    // INVARIANTS:
+    //  * `front` and `back` are either both `None` (iterator is exhausted)
+    //    or both `Some` and `front.key <= back.key`
+    front: Link<K, V>,
+    back: Link<K, V>,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.front?;
+        if self.back == Some(node) {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.front = unsafe { BinarySearchTree::successor_core(node) };
+        }
+
+        let node = unsafe { &*node.as_ptr() };
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Range<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.back?;
+        if self.front == Some(node) {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.back = unsafe { BinarySearchTree::predecessor_core(node) };
+        }
+
+        let node = unsafe { &*node.as_ptr() };
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> Clone for Range<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            front: self.front,
+            back: self.back,
+            marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let mut tree = BinarySearchTree::new();
         assert!(tree.is_empty());
         tree.insert(12, 12);
         assert_eq!(tree.len(), 1);
@@ -611,7 +1637,7 @@ mod tests {
 
         let mut items = Vec::with_capacity(tree.len());
         tree.inorder_for_each(|k, _| items.push(*k));
-        assert_eq!(&items, &[]);
+        assert!(items.is_empty());
 
         tree.insert(12, 12);
         assert_eq!(tree.len(), 1);
@@ -732,16 +1758,409 @@ mod tests {
         }
     }
 
+    fn tree_from(keys: &[i32]) -> BinarySearchTree<i32, i32> {
+        let mut tree = BinarySearchTree::new();
+        for &k in keys {
+            tree.insert(k, k);
+        }
+        tree
+    }
+
+    #[test]
+    fn iters() {
+        let mut tree = tree_from(&[12, 5, 9, 2, 18, 15, 13, 17, 19]);
+        let sorted = [2, 5, 9, 12, 13, 15, 17, 18, 19];
+
+        assert_eq!(tree.iter().len(), 9);
+        assert!(tree.iter().map(|(k, _)| *k).eq(sorted));
+        assert!(
+            tree.iter()
+                .rev()
+                .map(|(k, _)| *k)
+                .eq(sorted.into_iter().rev())
+        );
+
+        let mut it = tree.iter();
+        assert_eq!(it.next(), Some((&2, &2)));
+        assert_eq!(it.next_back(), Some((&19, &19)));
+        assert_eq!(it.len(), 7);
+        assert!(it.map(|(k, _)| *k).eq(sorted[1..8].iter().copied()));
+
+        for (k, v) in &mut tree {
+            *v = k * 10;
+        }
+        assert!(tree.iter().all(|(k, v)| *v == k * 10));
+
+        let mut it = tree.into_iter();
+        assert_eq!(it.next_back(), Some((19, 190)));
+        assert_eq!(it.next(), Some((2, 20)));
+        assert_eq!(it.len(), 7);
+        assert!(it.map(|(k, _)| k).eq(sorted[1..8].iter().copied()));
+
+        let mut empty = BinarySearchTree::<i32, i32>::new();
+        assert_eq!(empty.iter().next(), None);
+        assert_eq!(empty.iter_mut().next_back(), None);
+        assert_eq!(empty.into_iter().next(), None);
+    }
+
+    #[test]
+    fn range() {
+        let tree = tree_from(&[12, 5, 9, 2, 18, 15, 13, 17, 19]);
+        let keys = |r: Range<'_, i32, i32>| r.map(|(k, _)| *k).collect::<Vec<_>>();
+
+        assert_eq!(keys(tree.range(5..13)), [5, 9, 12]);
+        assert_eq!(keys(tree.range(6..=13)), [9, 12, 13]);
+        assert_eq!(keys(tree.range(..9)), [2, 5]);
+        assert_eq!(keys(tree.range(17..)), [17, 18, 19]);
+        assert_eq!(keys(tree.range(..)).len(), 9);
+        assert_eq!(
+            keys(tree.range((Bound::Excluded(12), Bound::Excluded(17)))),
+            [13, 15]
+        );
+        assert!(keys(tree.range(10..12)).is_empty());
+        assert!(keys(tree.range(20..)).is_empty());
+        assert!(keys(tree.range((Bound::Included(15), Bound::Excluded(13)))).is_empty());
+        assert_eq!(keys(tree.range(12..=12)), [12]);
+        assert!(
+            tree.range(3..18)
+                .rev()
+                .map(|(k, _)| *k)
+                .eq([17, 15, 13, 12, 9, 5])
+        );
+
+        let mut r = tree.range(5..=15);
+        assert_eq!(r.next(), Some((&5, &5)));
+        assert_eq!(r.next_back(), Some((&15, &15)));
+        assert_eq!(keys(r), [9, 12, 13]);
+
+        let empty = BinarySearchTree::<i32, i32>::new();
+        assert!(keys(empty.range(..)).is_empty());
+    }
+
+    #[test]
+    fn entry() {
+        let mut tree = tree_from(&[12, 5, 9]);
+
+        *tree.entry(5).or_insert(0) += 1;
+        assert_eq!(tree.get(&5), Some((&5, &6)));
+        assert_eq!(*tree.entry(7).or_insert(70), 70);
+        assert_eq!(*tree.entry(8).or_insert_with(|| 80), 80);
+        assert_eq!(*tree.entry(20).or_default(), 0);
+        assert_eq!(tree.len(), 6);
+
+        assert_eq!(*tree.entry(9).and_modify(|v| *v *= 2).or_insert(0), 18);
+        assert_eq!(*tree.entry(1).and_modify(|v| *v *= 2).or_insert(10), 10);
+        assert_eq!(tree.entry(30).key(), &30);
+
+        match tree.entry(12) {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.key(), &12);
+                assert_eq!(e.insert(120), 12);
+                assert_eq!(e.get(), &120);
+                assert_eq!(e.remove(), (12, 120));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match tree.entry(12) {
+            Entry::Occupied(_) => unreachable!(),
+            Entry::Vacant(e) => assert_eq!(e.into_key(), 12),
+        }
+
+        assert!(tree.iter().map(|(k, v)| (*k, *v)).eq([
+            (1, 10),
+            (5, 6),
+            (7, 70),
+            (8, 80),
+            (9, 18),
+            (20, 0)
+        ]));
+    }
+
+    #[test]
+    fn traversals() {
+        //       ┌──── 12 ────┐
+        //       │            │
+        //    ┌─ 5 ─┐     ┌── 18 ─┐
+        //    │     │     │       │
+        //    2     9   ┌ 15 ┐    19
+        //              │    │
+        //             13    17
+        let mut tree = tree_from(&[12, 5, 9, 2, 18, 15, 13, 17, 19]);
+
+        let mut items = Vec::new();
+        tree.preorder_for_each(|k, _| items.push(*k));
+        assert_eq!(items, [12, 5, 2, 9, 18, 15, 13, 17, 19]);
+
+        let mut items = Vec::new();
+        tree.postorder_for_each(|k, _| items.push(*k));
+        assert_eq!(items, [2, 9, 5, 13, 17, 15, 19, 18, 12]);
+
+        let items = tree
+            .breadth_first_iter()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        assert_eq!(items, [12, 5, 18, 2, 9, 15, 19, 13, 17]);
+
+        let mut empty = BinarySearchTree::<i32, i32>::new();
+        empty.preorder_for_each(|_, _| unreachable!());
+        empty.postorder_for_each(|_, _| unreachable!());
+        assert_eq!(empty.breadth_first_iter().next(), None);
+    }
+
+    #[test]
+    fn shape_stats() {
+        let empty = BinarySearchTree::<i32, i32>::new();
+        assert_eq!(empty.height(), 0);
+        assert_eq!(empty.leaf_count(), 0);
+        assert_eq!(empty.internal_path_length(), 0);
+        assert_eq!(empty.depth_of(&1), None);
+
+        // see `traversals` test for the shape
+        let tree = tree_from(&[12, 5, 9, 2, 18, 15, 13, 17, 19]);
+        assert_eq!(tree.height(), 4);
+        assert_eq!(tree.leaf_count(), 5);
+        assert_eq!(tree.internal_path_length(), 1 + 1 + 2 * 4 + 3 * 2);
+        assert_eq!(tree.depth_of(&12), Some(0));
+        assert_eq!(tree.depth_of(&9), Some(2));
+        assert_eq!(tree.depth_of(&17), Some(3));
+        assert_eq!(tree.depth_of(&10), None);
+
+        // sorted inserts degenerate into a list
+        let chain = tree_from(&[1, 2, 3, 4, 5]);
+        assert_eq!(chain.height(), 5);
+        assert_eq!(chain.leaf_count(), 1);
+        assert_eq!(chain.internal_path_length(), 1 + 2 + 3 + 4);
+        assert_eq!(chain.depth_of(&5), Some(4));
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        let tree = BinarySearchTree::from_sorted_iter((0..7).map(|k| (k, k)));
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.height(), 3);
+        let items = tree
+            .breadth_first_iter()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        assert_eq!(items, [3, 1, 5, 0, 2, 4, 6]);
+
+        let tree = BinarySearchTree::from_sorted_iter([(1, 'a'), (2, 'b'), (2, 'c'), (3, 'd')]);
+        assert_eq!(tree.len(), 3);
+        assert!(tree.iter().eq([(&1, &'a'), (&2, &'c'), (&3, &'d')]));
+
+        let tree = BinarySearchTree::<i32, i32>::from_sorted_iter([]);
+        assert!(tree.is_empty());
+        assert_eq!(tree.height(), 0);
+    }
+
+    #[test]
+    #[should_panic = "items must be sorted by key"]
+    fn from_sorted_iter_unsorted() {
+        BinarySearchTree::from_sorted_iter([(1, 1), (3, 3), (2, 2)]);
+    }
+
+    #[test]
+    fn retain_extract_if() {
+        let mut tree = tree_from(&[12, 5, 9, 2, 18, 15, 13, 17, 19]);
+
+        {
+            let mut it = tree.extract_if(|k, _| k % 2 == 1);
+            assert_eq!(it.next(), Some((5, 5)));
+            assert_eq!(it.next(), Some((9, 9)));
+        }
+        assert!(tree.iter().map(|(k, _)| *k).eq([2, 12, 13, 15, 17, 18, 19]));
+
+        let removed = tree.extract_if(|k, _| k % 2 == 1).collect::<Vec<_>>();
+        assert_eq!(removed, [(13, 13), (15, 15), (17, 17), (19, 19)]);
+        assert!(tree.iter().map(|(k, _)| *k).eq([2, 12, 18]));
+
+        tree.retain(|k, v| {
+            *v *= 10;
+            *k > 5
+        });
+        assert!(tree.iter().eq([(&12, &120), (&18, &180)]));
+
+        tree.retain(|_, _| false);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn clone_eq() {
+        let tree = tree_from(&[12, 5, 9, 2, 18, 15, 13, 17, 19]);
+        let mut clone = tree.clone();
+        assert_eq!(tree, clone);
+        // structure is preserved
+        assert!(tree.breadth_first_iter().eq(clone.breadth_first_iter()));
+        assert!(
+            clone
+                .iter()
+                .all(|(k, _)| clone.depth_of(k) == tree.depth_of(k))
+        );
+
+        // same items in a different shape are equal
+        let balanced = BinarySearchTree::from_sorted_iter(tree.iter().map(|(k, v)| (*k, *v)));
+        assert!(!tree.breadth_first_iter().eq(balanced.breadth_first_iter()));
+        assert_eq!(tree, balanced);
+
+        *clone.get_mut(&9).unwrap().1 = 90;
+        assert_ne!(tree, clone);
+        clone.delete(&9);
+        assert_ne!(tree, clone);
+
+        let empty = BinarySearchTree::<i32, i32>::new();
+        assert_eq!(empty.clone(), empty);
+        assert_ne!(empty, tree);
+    }
+
+    /// Checks that the subtree sizes of all nodes are correct.
+    fn assert_sizes<K, V>(tree: &BinarySearchTree<K, V>) {
+        fn check<K, V>(node: Link<K, V>) -> usize {
+            match node {
+                Some(node) => {
+                    let node = unsafe { node.as_ref() };
+                    let size = check(node.left) + check(node.right) + 1;
+                    assert_eq!(node.size, size);
+                    size
+                }
+                None => 0,
+            }
+        }
+
+        let root = if tree.is_empty() {
+            None
+        } else {
+            Some(tree.root)
+        };
+        assert_eq!(check(root), tree.len());
+    }
+
+    #[test]
+    fn select_rank() {
+        let mut tree = tree_from(&[12, 5, 9, 2, 18, 15, 13, 17, 19]);
+        assert_sizes(&tree);
+        let sorted = [2, 5, 9, 12, 13, 15, 17, 18, 19];
+        for (i, k) in sorted.iter().enumerate() {
+            assert_eq!(tree.select(i), Some((k, k)));
+            assert_eq!(tree.rank(k), Some(i));
+        }
+        assert_eq!(tree.select(9), None);
+        assert_eq!(tree.rank(&10), None);
+
+        tree.delete(&12);
+        tree.delete(&2);
+        assert_sizes(&tree);
+        assert_eq!(tree.select(0), Some((&5, &5)));
+        assert_eq!(tree.select(2), Some((&13, &13)));
+        assert_eq!(tree.rank(&19), Some(6));
+
+        let balanced = BinarySearchTree::from_sorted_iter(sorted.map(|k| (k, k)));
+        assert_sizes(&balanced);
+        assert_sizes(&balanced.clone());
+        assert_eq!(balanced.select(4), Some((&13, &13)));
+    }
+
+    #[test]
+    fn panic_in_drop() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+        struct D(bool, String);
+
+        impl Drop for D {
+            fn drop(&mut self) {
+                DROP_COUNT.fetch_add(1, Ordering::SeqCst);
+                if self.0 {
+                    panic!("panic from drop")
+                }
+            }
+        }
+
+        let mut tree = BinarySearchTree::new();
+        for k in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            tree.insert(k, D(k == 13, k.to_string()));
+        }
+
+        catch_unwind(AssertUnwindSafe(|| drop(tree))).ok();
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 9)
+    }
+
+    #[test]
+    fn keys_values_pop() {
+        let mut tree = tree_from(&[12, 5, 9, 2, 18]);
+        assert!(tree.keys().eq(&[2, 5, 9, 12, 18]));
+        assert!(tree.keys().rev().eq(&[18, 12, 9, 5, 2]));
+        assert_eq!(tree.values().len(), 5);
+
+        for v in tree.values_mut() {
+            *v *= 10;
+        }
+        assert!(tree.values().eq(&[20, 50, 90, 120, 180]));
+
+        assert_eq!(tree.pop_first(), Some((2, 20)));
+        assert_eq!(tree.pop_last(), Some((18, 180)));
+        assert_eq!(tree.pop_last(), Some((12, 120)));
+        assert_eq!(tree.pop_first(), Some((5, 50)));
+        assert_eq!(tree.pop_first(), Some((9, 90)));
+        assert_eq!(tree.pop_first(), None);
+        assert_eq!(tree.pop_last(), None);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn drop_degenerate_tree() {
+        #[cfg(not(miri))]
+        const SIZE: usize = 200_000;
+        #[cfg(miri)]
+        const SIZE: usize = 100;
+
+        // Inserting sorted keys creates a single right leaning chain, but it's O(n^2),
+        // so build the chains directly.
+        fn chain(len: usize, to_right: bool) -> BinarySearchTree<usize, usize> {
+            let mut tree = BinarySearchTree::new();
+            let mut parent: Option<NonNull<Node<usize, usize>>> = None;
+            for i in 0..len {
+                let key = if to_right { i } else { len - i };
+                let node = Box::new(Node {
+                    key,
+                    value: key,
+                    size: len - i,
+                    parent,
+                    left: None,
+                    right: None,
+                });
+                let node = NonNull::from(Box::leak(node));
+                match parent {
+                    Some(p) if to_right => unsafe { (*p.as_ptr()).right = Some(node) },
+                    Some(p) => unsafe { (*p.as_ptr()).left = Some(node) },
+                    None => tree.root = node,
+                }
+                parent = Some(node);
+                tree.len += 1;
+            }
+            tree
+        }
+
+        drop(chain(SIZE, true));
+        drop(chain(SIZE, false));
+
+        let mut tree = BinarySearchTree::new();
+        for k in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            tree.insert(k, Box::new(k));
+        }
+        drop(tree);
+    }
+
     mod proptests {
-        use std::collections::hash_map::RandomState;
         use std::collections::HashSet;
+        use std::collections::hash_map::RandomState;
 
         use proptest::prelude::*;
-        use rand::seq::SliceRandom;
-        use rand::thread_rng;
 
         use super::*;
 
+        crate::proptest_suite::ordered_map_proptests!(BinarySearchTree::new(), assert_sizes);
+
         #[cfg(not(miri))]
         const MAP_SIZE: usize = 1000;
         #[cfg(miri)]
@@ -755,26 +2174,9 @@ mod tests {
         proptest!(
             #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
 
-            #[test]
-            fn insert_get(
-                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
-                access in proptest::collection::vec(0..10000i32, 0..10)
-            ) {
-                let ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
-                let mut bst = BinarySearchTree::new();
-                for v in &inserts {
-                    bst.insert(*v, *v);
-                }
-
-                inserts.shuffle(&mut thread_rng());
-                for key in inserts.iter().chain(access.iter()) {
-                    assert_eq!(ref_hmap.get_key_value(key), bst.get(key));
-                }
-            }
-
             #[test]
             fn order(
-                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
             ) {
                 let mut bst = BinarySearchTree::new();
                 for v in &inserts {
@@ -788,64 +2190,91 @@ mod tests {
                 let mut items = Vec::with_capacity(bst.len());
                 bst.inorder_for_each(|k, _| items.push(*k));
                 assert_eq!(&items, &inserts);
+
+                assert!(bst.iter().map(|(k, _)| k).eq(inserts.iter()));
+                assert!(bst.iter_mut().rev().map(|(k, _)| k).eq(inserts.iter().rev()));
+                assert!(bst.into_iter().map(|(k, _)| k).eq(inserts.into_iter()));
             }
 
 
             #[test]
-            fn successor(
-                inserts in proptest::collection::hash_set(0..10000i32, 0..MAP_SIZE),
+            fn range(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                start in 0..10000i32,
+                len in 0..5000i32,
             ) {
                 let mut bst = BinarySearchTree::new();
+                let mut expected = std::collections::BTreeMap::new();
                 for v in &inserts {
                     bst.insert(*v, *v);
+                    expected.insert(*v, *v);
                 }
 
-                let mut items: Vec<_> = inserts.into_iter().collect();
-                items.sort();
+                let end = start + len;
+                assert!(bst.range(start..end).eq(expected.range(start..end)));
+                assert!(bst.range(start..=end).rev().eq(expected.range(start..=end).rev()));
+                assert!(bst.range(..end).eq(expected.range(..end)));
+                assert!(bst.range(start..).eq(expected.range(start..)));
+            }
+
+            #[test]
+            fn from_sorted_iter(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                inserts.sort();
+                let mut bst = BinarySearchTree::from_sorted_iter(inserts.iter().map(|k| (*k, *k)));
+                inserts.dedup();
+
+                assert_eq!(bst.len(), inserts.len());
+                assert!(bst.iter().map(|(k, _)| k).eq(inserts.iter()));
+                // perfectly balanced tree has minimal height
+                assert_eq!(bst.height(), (usize::BITS - inserts.len().leading_zeros()) as usize);
 
-                for it in items.windows(2) {
-                    let key = it[0];
-                    let result = it[1];
-                    assert_eq!(bst.successor(&key), Some((&result, &result)));
+                // tree must be usable as any other tree
+                for k in &inserts {
+                    assert_eq!(bst.delete(k), Some((*k, *k)));
                 }
+                assert!(bst.is_empty());
             }
 
             #[test]
-            fn predecessor(
-                inserts in proptest::collection::hash_set(0..10000i32, 0..MAP_SIZE),
+            fn retain(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                modulo in 1..10i32,
             ) {
                 let mut bst = BinarySearchTree::new();
+                let mut expected = std::collections::BTreeMap::new();
                 for v in &inserts {
                     bst.insert(*v, *v);
+                    expected.insert(*v, *v);
                 }
 
-                let mut items: Vec<_> = inserts.into_iter().collect();
-                items.sort();
-
-                for it in items.windows(2) {
-                    let key = it[1];
-                    let result = it[0];
-                    assert_eq!(bst.predecessor(&key), Some((&result, &result)));
-                }
+                bst.retain(|k, _| k % modulo == 0);
+                expected.retain(|k, _| k % modulo == 0);
+                assert_eq!(bst.len(), expected.len());
+                assert!(bst.iter().eq(expected.iter()));
             }
 
-
             #[test]
-            fn delete(
-                mut inserts in proptest::collection::hash_set(0..10000i32, 0..MAP_SIZE),
-                access in proptest::collection::vec(0..10000i32, 0..10)
+            fn select_rank(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                deletes in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
             ) {
-                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
                 let mut bst = BinarySearchTree::new();
-                for v in &inserts {
+                for v in inserts.iter() {
                     bst.insert(*v, *v);
                 }
+                for v in inserts.iter().chain(&deletes).step_by(3) {
+                    bst.delete(v);
+                }
+                assert_sizes(&bst);
 
-                let mut inserts: Vec<_> = inserts.into_iter().collect();
-                inserts.shuffle(&mut thread_rng());
-                for key in inserts.iter().chain(access.iter()) {
-                    assert_eq!(ref_hmap.remove_entry(key), bst.delete(key));
+                let sorted = bst.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+                for (i, k) in sorted.iter().enumerate() {
+                    assert_eq!(bst.select(i), Some((k, k)));
+                    assert_eq!(bst.rank(k), Some(i));
                 }
+                assert_eq!(bst.select(sorted.len()), None);
             }
 
         );
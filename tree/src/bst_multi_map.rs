@@ -0,0 +1,285 @@
+//! Binary search tree based multimap
+
+use core::fmt;
+use std::borrow::Borrow;
+
+use crate::binary_search_tree::{self, BinarySearchTree, Entry};
+
+/// A binary search tree based map which allows multiple values per key.
+///
+/// Each node stores all the values of one key in the order they were inserted,
+/// so the tree itself still has unique keys and the shape is only determined by
+/// the distinct keys.
+pub struct BstMultiMap<K, V> {
+    // INVARIANTS:
+    //  * all value lists in the tree are non-empty
+    //  * `len` is the sum of the lengths of all value lists
+    tree: BinarySearchTree<K, Vec<V>>,
+    len: usize,
+}
+
+impl<K, V> Default for BstMultiMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> BstMultiMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            tree: BinarySearchTree::new(),
+            len: 0,
+        }
+    }
+
+    /// Number of values in the map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of distinct keys in the map.
+    pub fn key_count(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Iterate over all key-value pairs in ascending key order,
+    /// values of equal keys are in insertion order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            tree: self.tree.iter(),
+            current: None,
+            len: self.len,
+        }
+    }
+}
+
+impl<K, V> BstMultiMap<K, V>
+where
+    K: Ord,
+{
+    /// Adds `value` after any existing values of `key`.
+    pub fn insert(&mut self, key: K, value: V) {
+        match self.tree.entry(key) {
+            Entry::Occupied(mut e) => e.get_mut().push(value),
+            Entry::Vacant(e) => {
+                e.insert(vec![value]);
+            }
+        }
+        self.len += 1;
+    }
+
+    /// Iterate over all values of `key` in insertion order.
+    pub fn get_all<Q>(&self, key: &Q) -> std::slice::Iter<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        match self.tree.get(key) {
+            Some((_, values)) => values.iter(),
+            None => [].iter(),
+        }
+    }
+
+    /// Returns the first inserted value of `key`.
+    pub fn get_first<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.get_all(key).next()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.tree.get(key).is_some()
+    }
+
+    /// Number of values stored for `key`.
+    pub fn count<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.get_all(key).len()
+    }
+
+    /// Removes all values of `key` and returns them in insertion order.
+    pub fn remove_all<Q>(&mut self, key: &Q) -> Vec<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Ord,
+    {
+        match self.tree.delete(key) {
+            Some((_, values)) => {
+                self.len -= values.len();
+                values
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+impl<K, V> Clone for BstMultiMap<K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for BstMultiMap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.tree.iter()).finish()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BstMultiMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    tree: binary_search_tree::Iter<'a, K, Vec<V>>,
+    // key and remaining values of the current node
+    current: Option<(&'a K, std::slice::Iter<'a, V>)>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, values)) = &mut self.current {
+                if let Some(v) = values.next() {
+                    self.len -= 1;
+                    return Some((k, v));
+                }
+            }
+
+            let (k, values) = self.tree.next()?;
+            self.current = Some((k, values.iter()));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_get_all() {
+        let mut map = BstMultiMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.get_all(&1).next(), None);
+
+        map.insert(5, 'a');
+        map.insert(2, 'b');
+        map.insert(5, 'c');
+        map.insert(8, 'd');
+        map.insert(5, 'e');
+        map.insert(2, 'f');
+
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.key_count(), 3);
+        assert!(map.get_all(&5).eq(&['a', 'c', 'e']));
+        assert_eq!(map.get_first(&2), Some(&'b'));
+        assert_eq!(map.count(&5), 3);
+        assert_eq!(map.count(&7), 0);
+        assert!(map.contains_key(&8));
+
+        assert_eq!(map.iter().len(), 6);
+        assert!(map.iter().eq([
+            (&2, &'b'),
+            (&2, &'f'),
+            (&5, &'a'),
+            (&5, &'c'),
+            (&5, &'e'),
+            (&8, &'d')
+        ]));
+        assert_eq!(
+            format!("{map:?}"),
+            "{2: ['b', 'f'], 5: ['a', 'c', 'e'], 8: ['d']}"
+        );
+
+        assert_eq!(map.remove_all(&5), ['a', 'c', 'e']);
+        assert!(map.remove_all(&5).is_empty());
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.key_count(), 2);
+        assert!(!map.contains_key(&5));
+    }
+
+    mod proptests {
+        use std::collections::BTreeMap;
+
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_btree_map_of_vecs(
+                inserts in proptest::collection::vec(0..100i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..100i32, 0..10),
+            ) {
+                let mut map = BstMultiMap::new();
+                let mut expected = BTreeMap::<i32, Vec<usize>>::new();
+                for (i, k) in inserts.iter().enumerate() {
+                    map.insert(*k, i);
+                    expected.entry(*k).or_default().push(i);
+                }
+
+                for k in &removes {
+                    assert_eq!(map.remove_all(k), expected.remove(k).unwrap_or_default());
+                }
+
+                assert_eq!(map.len(), expected.values().map(Vec::len).sum::<usize>());
+                assert_eq!(map.key_count(), expected.len());
+                for (k, values) in &expected {
+                    assert!(map.get_all(k).eq(values.iter()));
+                }
+                assert!(map
+                    .iter()
+                    .eq(expected.iter().flat_map(|(k, vs)| vs.iter().map(move |v| (k, v)))));
+            }
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Fenwick tree (binary indexed tree)
+
+use core::fmt;
+use core::ops::{Add, Bound, RangeBounds, Sub};
+
+use vec::Vec2;
+
+/// A Fenwick tree of prefix sums.
+///
+/// Point updates and prefix sums take `O(log n)` time with less memory and
+/// simpler bookkeeping than a [`SegmentTree`](crate::SegmentTree), but it only
+/// supports invertible operations like addition.
+pub struct FenwickTree<T> {
+    // INVARIANTS:
+    //  * `nodes.len() == len + 1`, `nodes[0]` is unused and always `T::default()`
+    //  * `nodes[i]` (1-based) is the sum of items `[i - lowbit(i), i)`
+    //    where `lowbit(i) = i & i.wrapping_neg()`
+    nodes: Vec2<T>,
+}
+
+impl<T> FenwickTree<T>
+where
+    T: Copy + Default + Add<Output = T> + Sub<Output = T>,
+{
+    /// Creates a tree of `len` items all equal to `T::default()`.
+    pub fn new(len: usize) -> Self {
+        let mut nodes = Vec2::with_capacity(len + 1);
+        for _ in 0..=len {
+            nodes.push(T::default());
+        }
+        Self { nodes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds `delta` to the item at `index`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `index >= self.len()`
+    pub fn add(&mut self, index: usize, delta: T) {
+        self.update(index, |node| node + delta);
+    }
+
+    /// Sets the item at `index` to `value`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `index >= self.len()`
+    pub fn set(&mut self, index: usize, value: T) {
+        let old = self.get(index);
+        // Every updated node includes `old`, so subtracting it first doesn't
+        // underflow unsigned `T` if `value` is smaller.
+        self.update(index, |node| node - old + value);
+    }
+
+    /// Applies `f` to every node which includes the item at `index`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `index >= self.len()`
+    fn update<F>(&mut self, index: usize, f: F)
+    where
+        F: Fn(T) -> T,
+    {
+        let len = self.len();
+        assert!(
+            index < len,
+            "index out of bounds: the len is {len} but the index is {index}"
+        );
+
+        let nodes = self.nodes.as_mut_slice();
+        let mut i = index + 1;
+        while i <= len {
+            nodes[i] = f(nodes[i]);
+            i += lowbit(i);
+        }
+    }
+
+    /// Returns the item at `index`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `index >= self.len()`
+    pub fn get(&self, index: usize) -> T {
+        self.range_sum(index..=index)
+    }
+
+    /// Returns the sum of the first `end` items.
+    ///
+    /// # PANICS
+    ///
+    /// * if `end > self.len()`
+    pub fn prefix_sum(&self, end: usize) -> T {
+        assert!(
+            end <= self.len(),
+            "prefix end {end} out of bounds for len {}",
+            self.len()
+        );
+
+        let nodes = self.nodes.as_slice();
+        let mut sum = T::default();
+        let mut i = end;
+        while i > 0 {
+            sum = sum + nodes[i];
+            i -= lowbit(i);
+        }
+        sum
+    }
+
+    /// Returns the sum of items in `range`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `range` is out of bounds or its start is after its end
+    pub fn range_sum<R>(&self, range: R) -> T
+    where
+        R: RangeBounds<usize>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s.checked_add(1).expect("range start to not overflow"),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&e) => e.checked_add(1).expect("range end to not overflow"),
+            Bound::Excluded(&e) => e,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(start <= end, "range start {start} is after its end {end}");
+
+        self.prefix_sum(end) - self.prefix_sum(start)
+    }
+
+    /// Returns the smallest index `i` such that `self.prefix_sum(i + 1) >= target`,
+    /// or `None` if the sum of all items is less than `target`.
+    ///
+    /// The result is only meaningful if all items are non-negative so that the
+    /// prefix sums are non-decreasing.
+    pub fn kth(&self, target: T) -> Option<usize>
+    where
+        T: PartialOrd,
+    {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+
+        // Descend the implicit tree: `pos` is the number of items known to
+        // have prefix sum `< target`, `remaining` is the rest of the target.
+        let nodes = self.nodes.as_slice();
+        let mut pos = 0;
+        let mut remaining = target;
+        let mut step = 1 << (usize::BITS - 1 - len.leading_zeros());
+        while step > 0 {
+            let next = pos + step;
+            if next <= len && nodes[next] < remaining {
+                pos = next;
+                remaining = remaining - nodes[next];
+            }
+            step >>= 1;
+        }
+
+        (pos < len).then_some(pos)
+    }
+}
+
+fn lowbit(i: usize) -> usize {
+    i & i.wrapping_neg()
+}
+
+impl<T> FromIterator<T> for FenwickTree<T>
+where
+    T: Copy + Default + Add<Output = T> + Sub<Output = T>,
+{
+    /// Builds the tree in `O(n)` time.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut nodes = Vec2::new();
+        nodes.push(T::default());
+        for item in iter {
+            nodes.push(item);
+        }
+
+        // propagate each node to its parent
+        let nodes_slice = nodes.as_mut_slice();
+        let len = nodes_slice.len() - 1;
+        for i in 1..=len {
+            let parent = i + lowbit(i);
+            if parent <= len {
+                nodes_slice[parent] = nodes_slice[parent] + nodes_slice[i];
+            }
+        }
+
+        Self { nodes }
+    }
+}
+
+impl<T> fmt::Debug for FenwickTree<T>
+where
+    T: Copy + Default + Add<Output = T> + Sub<Output = T> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries((0..self.len()).map(|i| self.get(i)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums() {
+        let mut tree: FenwickTree<i32> = [3, 1, 4, 1, 5, 9, 2, 6].into_iter().collect();
+        assert_eq!(tree.len(), 8);
+        assert_eq!(tree.prefix_sum(0), 0);
+        assert_eq!(tree.prefix_sum(3), 8);
+        assert_eq!(tree.range_sum(..), 31);
+        assert_eq!(tree.range_sum(2..=5), 19);
+        assert_eq!(tree.get(5), 9);
+
+        tree.add(2, 10);
+        tree.set(7, 0);
+        assert_eq!(tree.range_sum(..), 35);
+        assert_eq!(format!("{tree:?}"), "[3, 1, 14, 1, 5, 9, 2, 0]");
+    }
+
+    #[test]
+    fn kth() {
+        let tree: FenwickTree<u32> = [2, 0, 3, 1, 0, 4].into_iter().collect();
+        assert_eq!(tree.kth(0), Some(0));
+        assert_eq!(tree.kth(1), Some(0));
+        assert_eq!(tree.kth(2), Some(0));
+        assert_eq!(tree.kth(3), Some(2));
+        assert_eq!(tree.kth(5), Some(2));
+        assert_eq!(tree.kth(6), Some(3));
+        assert_eq!(tree.kth(7), Some(5));
+        assert_eq!(tree.kth(10), Some(5));
+        assert_eq!(tree.kth(11), None);
+
+        assert_eq!(FenwickTree::<u32>::new(0).kth(0), None);
+    }
+
+    #[test]
+    fn set_smaller_unsigned() {
+        let mut tree: FenwickTree<u32> = [2, 0, 3, 1, 0, 4].into_iter().collect();
+        tree.set(2, 1);
+        tree.set(5, 0);
+        tree.set(0, 0);
+        assert_eq!(format!("{tree:?}"), "[0, 0, 1, 1, 0, 0]");
+        assert_eq!(tree.range_sum(..), 2);
+        assert_eq!(tree.kth(2), Some(3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn add_out_of_bounds() {
+        let mut tree = FenwickTree::new(3);
+        tree.add(3, 1);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const TREE_SIZE: usize = 200;
+        #[cfg(miri)]
+        const TREE_SIZE: usize = 20;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        fn items_and_updates() -> impl Strategy<Value = (Vec<i64>, Vec<(usize, i64)>)> {
+            proptest::collection::vec(0..100i64, 1..TREE_SIZE).prop_flat_map(|items| {
+                let len = items.len();
+                let updates = proptest::collection::vec((0..len, 0..100i64), 0..50);
+                (Just(items), updates)
+            })
+        }
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_vec((mut items, updates) in items_and_updates()) {
+                let mut tree: FenwickTree<i64> = items.iter().copied().collect();
+                let mut incremental = FenwickTree::new(items.len());
+                for (i, v) in items.iter().enumerate() {
+                    incremental.add(i, *v);
+                }
+
+                for (i, delta) in updates {
+                    tree.add(i, delta);
+                    incremental.add(i, delta);
+                    items[i] += delta;
+                }
+
+                let mut prefix = 0;
+                for end in 0..=items.len() {
+                    assert_eq!(tree.prefix_sum(end), prefix);
+                    assert_eq!(incremental.prefix_sum(end), prefix);
+                    if end < items.len() {
+                        assert_eq!(tree.get(end), items[end]);
+                        prefix += items[end];
+                    }
+                }
+
+                let prefixes: Vec<_> = items
+                    .iter()
+                    .scan(0, |sum, v| {
+                        *sum += v;
+                        Some(*sum)
+                    })
+                    .collect();
+                for target in 0..=prefix + 1 {
+                    let expected = prefixes.partition_point(|sum| *sum < target);
+                    let expected = (expected < items.len()).then_some(expected);
+                    assert_eq!(tree.kth(target), expected);
+                }
+            }
+        );
+    }
+}
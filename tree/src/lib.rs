@@ -3,4 +3,26 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
 pub mod binary_search_tree;
+pub mod bst_multi_map;
+pub mod fenwick_tree;
+pub mod merkle_tree;
+#[cfg(test)]
+mod proptest_suite;
+pub mod radix_trie;
 pub mod red_black_tree;
+pub mod rose_tree;
+pub mod scapegoat_tree;
+pub mod segment_tree;
+pub mod treap;
+pub mod veb_tree;
+
+pub use crate::fenwick_tree::FenwickTree;
+pub use crate::merkle_tree::MerkleTree;
+pub use crate::radix_trie::RadixTrie;
+pub use crate::red_black_tree::interval_tree::IntervalTree;
+pub use crate::red_black_tree::{InvariantError, RbTreeMap};
+pub use crate::rose_tree::Tree;
+pub use crate::scapegoat_tree::ScapegoatTree;
+pub use crate::segment_tree::SegmentTree;
+pub use crate::treap::Treap;
+pub use crate::veb_tree::VebTree;
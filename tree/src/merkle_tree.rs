@@ -0,0 +1,314 @@
+//! Merkle tree with membership proofs
+
+use core::fmt;
+
+use vec::Vec2;
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// A binary hash tree over a sequence of leaves.
+///
+/// Leaves are hashed as `hash(0x00 || data)` and inner nodes as
+/// `hash(0x01 || left || right)`, the distinct prefixes prevent passing an
+/// inner node off as a leaf. If a level has an odd number of nodes, the last
+/// one is promoted to the next level unchanged.
+pub struct MerkleTree<H, F> {
+    // INVARIANTS:
+    //  * `nodes` holds all levels back to back starting from the leaves,
+    //    `levels[i]` is the start of level `i` in `nodes`
+    //  * level 0 has `leaf_count` nodes, each following level has half of the
+    //    previous one rounded up, the last level holds only the root
+    //  * `levels` is empty if there are no leaves
+    nodes: Vec2<H>,
+    levels: Vec<usize>,
+    leaf_count: usize,
+    hash: F,
+}
+
+/// Position of a sibling hash in a [`Proof`] relative to the path node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A proof that a leaf is included in a [`MerkleTree`] with a given root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof<H> {
+    /// Index of the proven leaf.
+    pub index: usize,
+    /// Sibling hashes from the leaf level up to the root.
+    pub siblings: Vec<(Side, H)>,
+}
+
+impl<H, F> MerkleTree<H, F>
+where
+    H: AsRef<[u8]> + Clone,
+    F: Fn(&[u8]) -> H,
+{
+    /// Builds a tree over `leaves` using `hash` as the hash function.
+    pub fn new<I>(leaves: I, hash: F) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut nodes = Vec2::new();
+        for leaf in leaves {
+            nodes.push(hash_leaf(&hash, leaf.as_ref()));
+        }
+        let leaf_count = nodes.len();
+
+        let mut levels = Vec::new();
+        if leaf_count > 0 {
+            levels.push(0);
+        }
+
+        let mut start = 0;
+        let mut len = leaf_count;
+        while len > 1 {
+            levels.push(start + len);
+            for i in (0..len).step_by(2) {
+                let level = &nodes.as_slice()[start..start + len];
+                let parent = match level.get(i + 1) {
+                    Some(right) => hash_nodes(&hash, &level[i], right),
+                    None => level[i].clone(),
+                };
+                nodes.push(parent);
+            }
+            start += len;
+            len = len.div_ceil(2);
+        }
+
+        Self {
+            nodes,
+            levels,
+            leaf_count,
+            hash,
+        }
+    }
+
+    /// Returns the proof of the leaf at `index` or `None` if it's out of bounds.
+    pub fn proof(&self, index: usize) -> Option<Proof<H>> {
+        if index >= self.leaf_count {
+            return None;
+        }
+
+        let nodes = self.nodes.as_slice();
+        let mut siblings = Vec::with_capacity(self.levels.len().saturating_sub(1));
+        let mut i = index;
+        let mut len = self.leaf_count;
+        for &start in &self.levels[..self.levels.len() - 1] {
+            let sibling = i ^ 1;
+            if sibling < len {
+                let side = if sibling < i { Side::Left } else { Side::Right };
+                siblings.push((side, nodes[start + sibling].clone()));
+            }
+            i /= 2;
+            len = len.div_ceil(2);
+        }
+
+        Some(Proof { index, siblings })
+    }
+
+    /// Checks that `proof` proves `leaf` is included in this tree.
+    pub fn verify(&self, leaf: &[u8], proof: &Proof<H>) -> bool
+    where
+        H: PartialEq,
+    {
+        match self.root() {
+            Some(root) => proof.verify(root, leaf, &self.hash),
+            None => false,
+        }
+    }
+}
+
+impl<H, F> MerkleTree<H, F> {
+    /// Returns the root hash or `None` if the tree has no leaves.
+    pub fn root(&self) -> Option<&H> {
+        self.nodes.as_slice().last()
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// Returns the hash of the leaf at `index`.
+    pub fn leaf_hash(&self, index: usize) -> Option<&H> {
+        self.nodes.as_slice()[..self.leaf_count].get(index)
+    }
+}
+
+impl<H> Proof<H>
+where
+    H: AsRef<[u8]> + PartialEq,
+{
+    /// Checks that `leaf` is included in a tree with `root` computed with `hash`.
+    pub fn verify<F>(&self, root: &H, leaf: &[u8], hash: F) -> bool
+    where
+        F: Fn(&[u8]) -> H,
+    {
+        let mut current = hash_leaf(&hash, leaf);
+        for (side, sibling) in &self.siblings {
+            current = match side {
+                Side::Left => hash_nodes(&hash, sibling, &current),
+                Side::Right => hash_nodes(&hash, &current, sibling),
+            };
+        }
+        current == *root
+    }
+}
+
+fn hash_leaf<H>(hash: impl Fn(&[u8]) -> H, data: &[u8]) -> H {
+    let mut buf = Vec::with_capacity(1 + data.len());
+    buf.push(LEAF_PREFIX);
+    buf.extend_from_slice(data);
+    hash(&buf)
+}
+
+fn hash_nodes<H: AsRef<[u8]>>(hash: impl Fn(&[u8]) -> H, left: &H, right: &H) -> H {
+    let (left, right) = (left.as_ref(), right.as_ref());
+    let mut buf = Vec::with_capacity(1 + left.len() + right.len());
+    buf.push(NODE_PREFIX);
+    buf.extend_from_slice(left);
+    buf.extend_from_slice(right);
+    hash(&buf)
+}
+
+impl<H, F> fmt::Debug for MerkleTree<H, F>
+where
+    H: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MerkleTree")
+            .field("leaf_count", &self.leaf_count)
+            .field("root", &self.root())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    use super::*;
+
+    /// Deterministic but not cryptographically secure hash for tests.
+    fn sip(data: &[u8]) -> [u8; 8] {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(data);
+        hasher.finish().to_le_bytes()
+    }
+
+    #[test]
+    fn root_and_proofs() {
+        let leaves = ["a", "b", "c", "d", "e"];
+        let tree = MerkleTree::new(leaves, sip);
+        assert_eq!(tree.leaf_count(), 5);
+        assert_eq!(tree.leaf_hash(1), Some(&hash_leaf(sip, b"b")));
+
+        // ((a b) (c d)) e
+        let ab = hash_nodes(sip, &hash_leaf(sip, b"a"), &hash_leaf(sip, b"b"));
+        let cd = hash_nodes(sip, &hash_leaf(sip, b"c"), &hash_leaf(sip, b"d"));
+        let abcd = hash_nodes(sip, &ab, &cd);
+        let root = hash_nodes(sip, &abcd, &hash_leaf(sip, b"e"));
+        assert_eq!(tree.root(), Some(&root));
+
+        let proof = tree.proof(2).unwrap();
+        assert_eq!(
+            proof.siblings,
+            [
+                (Side::Right, hash_leaf(sip, b"d")),
+                (Side::Left, ab),
+                (Side::Right, hash_leaf(sip, b"e"))
+            ]
+        );
+        assert!(tree.verify(b"c", &proof));
+        assert!(!tree.verify(b"d", &proof));
+
+        // the promoted leaf has a shorter proof
+        let proof = tree.proof(4).unwrap();
+        assert_eq!(proof.siblings, [(Side::Left, abcd)]);
+        assert!(proof.verify(&root, b"e", sip));
+
+        assert_eq!(tree.proof(5), None);
+    }
+
+    #[test]
+    fn small_trees() {
+        let tree = MerkleTree::new(Vec::<&[u8]>::new(), sip);
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), None);
+        assert_eq!(tree.proof(0), None);
+
+        let tree = MerkleTree::new([b"only"], sip);
+        assert_eq!(tree.root(), Some(&hash_leaf(sip, b"only")));
+        let proof = tree.proof(0).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert!(tree.verify(b"only", &proof));
+    }
+
+    #[test]
+    fn inner_node_is_not_a_leaf() {
+        let tree = MerkleTree::new(["a", "b"], sip);
+        let a = hash_leaf(sip, b"a");
+        let b = hash_leaf(sip, b"b");
+        // a leaf with the bytes of the inner node's preimage doesn't hash to the root
+        let forged = [a, b].concat();
+        let proof = Proof {
+            index: 0,
+            siblings: Vec::new(),
+        };
+        assert!(!tree.verify(&forged, &proof));
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const TREE_SIZE: usize = 100;
+        #[cfg(miri)]
+        const TREE_SIZE: usize = 10;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 500;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn proofs_verify(
+                leaves in proptest::collection::vec(proptest::collection::vec(any::<u8>(), 0..8), 1..TREE_SIZE),
+                tamper in any::<u8>(),
+            ) {
+                let tree = MerkleTree::new(&leaves, sip);
+                let root = *tree.root().unwrap();
+
+                let expected_depth = usize::BITS - (leaves.len() - 1).leading_zeros();
+                for (i, leaf) in leaves.iter().enumerate() {
+                    let proof = tree.proof(i).unwrap();
+                    assert!(proof.siblings.len() <= expected_depth as usize);
+                    assert!(proof.verify(&root, leaf, sip));
+
+                    let mut tampered = leaf.clone();
+                    tampered.push(tamper);
+                    assert!(!proof.verify(&root, &tampered, sip));
+                }
+
+                // changing any leaf changes the root
+                let mut changed = leaves.clone();
+                changed[0].push(tamper);
+                assert_ne!(MerkleTree::new(&changed, sip).root(), Some(&root));
+            }
+        );
+    }
+}
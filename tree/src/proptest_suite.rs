@@ -0,0 +1,146 @@
+//! Property tests shared by the ordered maps of this crate
+
+/// Generates the common property tests of an ordered map with `i32` keys and values.
+///
+/// `$new` creates an empty map and `$check` is called with a reference to the
+/// map to assert its internal invariants. The map must have `insert`, `get`,
+/// `delete`, `successor`, `predecessor`, `len`, `iter` and `into_iter` with the
+/// same signatures as the other maps.
+macro_rules! ordered_map_proptests {
+    ($new:expr, $check:expr) => {
+        mod map_suite {
+            use std::collections::HashSet;
+            use std::collections::hash_map::RandomState;
+
+            use proptest::prelude::*;
+            use rand::seq::SliceRandom;
+            use rand::thread_rng;
+
+            use super::*;
+
+            #[cfg(not(miri))]
+            const MAP_SIZE: usize = 1000;
+            #[cfg(miri)]
+            const MAP_SIZE: usize = 50;
+
+            /// `delete` checks the invariants after every delete, which walks the
+            /// whole map, so it uses smaller maps
+            #[cfg(not(miri))]
+            const DELETE_MAP_SIZE: usize = 250;
+            #[cfg(miri)]
+            const DELETE_MAP_SIZE: usize = 50;
+
+            #[cfg(not(miri))]
+            const PROPTEST_CASES: u32 = 1000;
+            #[cfg(miri)]
+            const PROPTEST_CASES: u32 = 10;
+
+            proptest!(
+                #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+                #[test]
+                fn insert_get(
+                    mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                    access in proptest::collection::vec(0..10000i32, 0..10)
+                ) {
+                    let ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
+                    let mut tree = $new;
+                    for v in &inserts {
+                        tree.insert(*v, *v);
+                    }
+                    ($check)(&tree);
+
+                    inserts.shuffle(&mut thread_rng());
+                    for key in inserts.iter().chain(access.iter()) {
+                        assert_eq!(ref_hmap.get_key_value(key), tree.get(key));
+                    }
+                }
+
+                #[test]
+                fn order(
+                    inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                ) {
+                    let mut tree = $new;
+                    for v in &inserts {
+                        tree.insert(*v, *v);
+                    }
+
+                    let unique = HashSet::<_, RandomState>::from_iter(inserts.into_iter());
+                    let mut inserts: Vec<_> = unique.into_iter().collect();
+                    inserts.sort();
+
+                    assert_eq!(tree.len(), inserts.len());
+                    assert!(tree.iter().map(|(k, _)| k).eq(inserts.iter()));
+                    assert!(tree.iter().rev().map(|(k, _)| k).eq(inserts.iter().rev()));
+                    assert!(tree.into_iter().map(|(k, _)| k).eq(inserts.into_iter()));
+                }
+
+                #[test]
+                fn successor(
+                    inserts in proptest::collection::hash_set(0..10000i32, 0..MAP_SIZE),
+                ) {
+                    let mut tree = $new;
+                    for v in &inserts {
+                        tree.insert(*v, *v);
+                    }
+
+                    let mut items: Vec<_> = inserts.into_iter().collect();
+                    items.sort();
+
+                    for it in items.windows(2) {
+                        let key = it[0];
+                        let result = it[1];
+                        assert_eq!(tree.successor(&key), Some((&result, &result)));
+                    }
+                    if let Some(last) = items.last() {
+                        assert_eq!(tree.successor(last), None);
+                    }
+                }
+
+                #[test]
+                fn predecessor(
+                    inserts in proptest::collection::hash_set(0..10000i32, 0..MAP_SIZE),
+                ) {
+                    let mut tree = $new;
+                    for v in &inserts {
+                        tree.insert(*v, *v);
+                    }
+
+                    let mut items: Vec<_> = inserts.into_iter().collect();
+                    items.sort();
+
+                    for it in items.windows(2) {
+                        let key = it[1];
+                        let result = it[0];
+                        assert_eq!(tree.predecessor(&key), Some((&result, &result)));
+                    }
+                    if let Some(first) = items.first() {
+                        assert_eq!(tree.predecessor(first), None);
+                    }
+                }
+
+                #[test]
+                fn delete(
+                    inserts in proptest::collection::hash_set(0..10000i32, 0..DELETE_MAP_SIZE),
+                    access in proptest::collection::vec(0..10000i32, 0..10)
+                ) {
+                    let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::from_iter(inserts.iter().map(|v| (*v, *v)));
+                    let mut tree = $new;
+                    for v in &inserts {
+                        tree.insert(*v, *v);
+                    }
+
+                    let mut inserts: Vec<_> = inserts.into_iter().collect();
+                    inserts.shuffle(&mut thread_rng());
+                    for key in inserts.iter().chain(access.iter()) {
+                        assert_eq!(ref_hmap.remove_entry(key), tree.delete(key));
+                        ($check)(&tree);
+                    }
+                    assert_eq!(tree.len(), ref_hmap.len());
+                }
+            );
+        }
+    };
+}
+
+pub(crate) use ordered_map_proptests;
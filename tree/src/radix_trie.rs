@@ -0,0 +1,527 @@
+//! Path-compressed radix trie for byte-string keys
+//!
+//! Every edge is labeled with a byte string instead of a single byte and nodes
+//! with only one child and no value are merged into their child. So the number of
+//! nodes is at most twice the number of keys regardless of the key lengths,
+//! and a lookup visits at most one node per label instead of one per byte.
+//!
+//! Children are kept in a vector sorted by the first byte of their label, which
+//! is small for sparse nodes and is searched with a binary search.
+
+use core::fmt;
+
+use vec::Stack;
+
+struct Node<V> {
+    /// Bytes on the edge from the parent to this node, empty only for the root
+    label: Box<[u8]>,
+    value: Option<V>,
+    /// Sorted by the first byte of their labels, which are all distinct
+    children: Vec<Node<V>>,
+}
+
+impl<V> Node<V> {
+    fn new(label: &[u8], value: Option<V>) -> Self {
+        Self {
+            label: label.into(),
+            value,
+            children: Vec::new(),
+        }
+    }
+
+    fn child_index(&self, first_byte: u8) -> Result<usize, usize> {
+        self.children
+            .binary_search_by_key(&first_byte, |child| child.label[0])
+    }
+
+    /// Returns the child whose label starts with `key[0]` and if `key` starts with the label.
+    fn find_child(&self, key: &[u8]) -> Option<&Node<V>> {
+        let i = self.child_index(*key.first()?).ok()?;
+        let child = &self.children[i];
+        key.starts_with(&child.label).then_some(child)
+    }
+
+    /// Merges the only child of a value-less node into it.
+    fn merge_with_only_child(&mut self) {
+        debug_assert!(self.value.is_none() && self.children.len() == 1);
+        let child = self
+            .children
+            .pop()
+            .expect("node must have exactly one child");
+        let mut label = Vec::with_capacity(self.label.len() + child.label.len());
+        label.extend_from_slice(&self.label);
+        label.extend_from_slice(&child.label);
+        *self = Node {
+            label: label.into(),
+            value: child.value,
+            children: child.children,
+        };
+    }
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Map from byte strings to `V` backed by a path-compressed radix trie.
+///
+/// Items are iterated in lexicographic order of the keys.
+pub struct RadixTrie<V> {
+    // INVARIANTS:
+    //  * all nodes except the root have non-empty labels
+    //  * all nodes except the root have a value or at least 2 children
+    //  * `len` is the number of values in the trie
+    root: Node<V>,
+    len: usize,
+}
+
+impl<V> Default for RadixTrie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> RadixTrie<V> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::new(&[], None),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.root = Node::new(&[], None);
+        self.len = 0;
+    }
+
+    /// Inserts `value` for `key`, returns the old value if `key` was already present.
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        let mut node = &mut self.root;
+        let mut rest = key;
+        loop {
+            let Some(&first) = rest.first() else {
+                let old = node.value.replace(value);
+                if old.is_none() {
+                    self.len += 1;
+                }
+                return old;
+            };
+
+            let i = match node.child_index(first) {
+                Ok(i) => i,
+                Err(i) => {
+                    node.children.insert(i, Node::new(rest, Some(value)));
+                    self.len += 1;
+                    return None;
+                }
+            };
+
+            let child = &mut node.children[i];
+            let common = common_prefix_len(&child.label, rest);
+            if common < child.label.len() {
+                // `rest` diverges inside the label, split the edge at `common`,
+                // the new node is then handled by the next iteration
+                let mut old_child = std::mem::replace(child, Node::new(&rest[..common], None));
+                old_child.label = old_child.label[common..].into();
+                child.children.push(old_child);
+            }
+            node = child;
+            rest = &rest[common..];
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let mut node = &self.root;
+        let mut rest = key;
+        while !rest.is_empty() {
+            node = node.find_child(rest)?;
+            rest = &rest[node.label.len()..];
+        }
+        node.value.as_ref()
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let mut node = &mut self.root;
+        let mut rest = key;
+        while let Some(&first) = rest.first() {
+            let i = node.child_index(first).ok()?;
+            node = &mut node.children[i];
+            rest = rest.strip_prefix(&*node.label)?;
+        }
+        node.value.as_mut()
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Removes `key` from the trie.
+    ///
+    /// Nodes which are left without a value and with less than 2 children are
+    /// removed or merged with their only child, so the trie stays compressed.
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        /// Removes the value of `rest` below `node`, fixes up the child on the path.
+        fn remove<V>(node: &mut Node<V>, rest: &[u8]) -> Option<V> {
+            let Some(&first) = rest.first() else {
+                return node.value.take();
+            };
+
+            let i = node.child_index(first).ok()?;
+            let child = &mut node.children[i];
+            let rest = rest.strip_prefix(&*child.label)?;
+            let value = remove(child, rest)?;
+
+            if child.value.is_none() {
+                match child.children.len() {
+                    0 => {
+                        node.children.remove(i);
+                    }
+                    1 => child.merge_with_only_child(),
+                    _ => {}
+                }
+            }
+            Some(value)
+        }
+
+        let value = remove(&mut self.root, key)?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Returns the value of the longest key in the trie which is a prefix of `key`.
+    pub fn longest_prefix_of<'a>(&self, key: &'a [u8]) -> Option<(&'a [u8], &V)> {
+        let mut node = &self.root;
+        let mut consumed = 0;
+        let mut found = node.value.as_ref().map(|v| (consumed, v));
+        while let Some(child) = node.find_child(&key[consumed..]) {
+            node = child;
+            consumed += node.label.len();
+            if let Some(v) = &node.value {
+                found = Some((consumed, v));
+            }
+        }
+        found.map(|(len, v)| (&key[..len], v))
+    }
+
+    /// Iterate over all items in lexicographic order of the keys.
+    pub fn iter(&self) -> Iter<'_, V> {
+        Iter::new(&self.root, Vec::new())
+    }
+
+    /// Iterate over all items whose keys start with `prefix` in lexicographic order.
+    pub fn prefix_iter(&self, prefix: &[u8]) -> Iter<'_, V> {
+        if prefix.is_empty() {
+            return self.iter();
+        }
+
+        let mut node = &self.root;
+        let mut consumed = 0;
+        loop {
+            // never empty, if `prefix` ends at the end of a label, the loop returns before
+            let rest = &prefix[consumed..];
+            let Ok(i) = node.child_index(rest[0]) else {
+                return Iter::empty();
+            };
+
+            let child = &node.children[i];
+            if child.label.starts_with(rest) {
+                // all keys in the subtree of `child` start with `prefix`
+                return Iter::new(child, prefix[..consumed].to_vec());
+            }
+            if !rest.starts_with(&child.label) {
+                return Iter::empty();
+            }
+            node = child;
+            consumed += child.label.len();
+        }
+    }
+}
+
+impl<V> fmt::Debug for RadixTrie<V>
+where
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.iter()
+                    .map(|(k, v)| (String::from_utf8_lossy(&k).into_owned(), v)),
+            )
+            .finish()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a RadixTrie<V> {
+    type Item = (Vec<u8>, &'a V);
+    type IntoIter = Iter<'a, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Pre-order iterator over the items of `RadixTrie`, created by [`RadixTrie::iter`] and [`RadixTrie::prefix_iter`].
+///
+/// The keys are not stored in the trie, so every key is built from the labels
+/// and returned as a new `Vec`.
+pub struct Iter<'a, V> {
+    /// Nodes to visit with the length of `key` before their label
+    stack: Stack<(&'a Node<V>, usize)>,
+    /// Key of the last visited node
+    key: Vec<u8>,
+}
+
+impl<'a, V> Iter<'a, V> {
+    /// Iterates over the subtree of `node`, where `key` is the key of its parent.
+    fn new(node: &'a Node<V>, key: Vec<u8>) -> Self {
+        let mut stack = Stack::new();
+        stack.push((node, key.len()));
+        Self { stack, key }
+    }
+
+    fn empty() -> Self {
+        Self {
+            stack: Stack::new(),
+            key: Vec::new(),
+        }
+    }
+}
+
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Parent's key is a prefix of its children's keys and the children are
+        // sorted, so the pre-order is the lexicographic order.
+        while let Some((node, parent_key_len)) = self.stack.pop() {
+            self.key.truncate(parent_key_len);
+            self.key.extend_from_slice(&node.label);
+            for child in node.children.iter().rev() {
+                self.stack.push((child, self.key.len()));
+            }
+
+            if let Some(value) = &node.value {
+                return Some((self.key.clone(), value));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts the structure invariants of `trie`.
+    fn assert_trie<V>(trie: &RadixTrie<V>) {
+        fn check<V>(node: &Node<V>, is_root: bool) -> usize {
+            if !is_root {
+                assert!(!node.label.is_empty());
+                assert!(node.value.is_some() || node.children.len() >= 2);
+            }
+            assert!(
+                node.children
+                    .windows(2)
+                    .all(|w| w[0].label[0] < w[1].label[0])
+            );
+
+            node.value.is_some() as usize
+                + node
+                    .children
+                    .iter()
+                    .map(|child| check(child, false))
+                    .sum::<usize>()
+        }
+
+        assert_eq!(check(&trie.root, true), trie.len());
+    }
+
+    fn keys<V>(iter: Iter<'_, V>) -> Vec<String> {
+        iter.map(|(k, _)| String::from_utf8(k).unwrap()).collect()
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let mut trie = RadixTrie::new();
+        assert!(trie.is_empty());
+        assert_eq!(trie.get(b"a"), None);
+        assert_eq!(trie.remove(b"a"), None);
+
+        for (i, key) in [
+            "romane",
+            "romanus",
+            "romulus",
+            "rubens",
+            "ruber",
+            "rubicon",
+            "rubicundus",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            assert_eq!(trie.insert(key.as_bytes(), i), None);
+            assert_trie(&trie);
+        }
+        assert_eq!(trie.len(), 7);
+        assert_eq!(trie.insert(b"ruber", 10), Some(4));
+        assert_eq!(trie.get(b"ruber"), Some(&10));
+        assert_eq!(trie.get(b"rub"), None);
+        assert_eq!(trie.get(b"rubicundusx"), None);
+        *trie.get_mut(b"romulus").unwrap() += 10;
+        assert_eq!(trie.get(b"romulus"), Some(&12));
+        assert_eq!(trie.get_mut(b"romx"), None);
+
+        // empty key and prefixes of other keys
+        assert_eq!(trie.insert(b"", 100), None);
+        assert_eq!(trie.insert(b"rub", 101), None);
+        assert_eq!(trie.get(b""), Some(&100));
+        assert_eq!(trie.get(b"rub"), Some(&101));
+        assert_trie(&trie);
+
+        assert_eq!(
+            keys(trie.iter()),
+            [
+                "",
+                "romane",
+                "romanus",
+                "romulus",
+                "rub",
+                "rubens",
+                "ruber",
+                "rubicon",
+                "rubicundus"
+            ]
+        );
+
+        assert_eq!(trie.remove(b"rub"), Some(101));
+        assert_eq!(trie.remove(b"rub"), None);
+        assert_eq!(trie.remove(b"romanus"), Some(1));
+        assert_eq!(trie.remove(b"roman"), None);
+        assert_trie(&trie);
+        assert_eq!(trie.remove(b"romane"), Some(0));
+        assert_eq!(trie.remove(b""), Some(100));
+        assert_trie(&trie);
+        assert_eq!(trie.len(), 5);
+        assert_eq!(
+            format!("{trie:?}"),
+            r#"{"romulus": 12, "rubens": 3, "ruber": 10, "rubicon": 5, "rubicundus": 6}"#
+        );
+    }
+
+    #[test]
+    fn prefix_iter() {
+        let mut trie = RadixTrie::new();
+        for key in ["test", "team", "tea", "ten", "toast", "a"] {
+            trie.insert(key.as_bytes(), ());
+        }
+
+        assert_eq!(
+            keys(trie.prefix_iter(b"")),
+            ["a", "tea", "team", "ten", "test", "toast"]
+        );
+        assert_eq!(
+            keys(trie.prefix_iter(b"t")),
+            ["tea", "team", "ten", "test", "toast"]
+        );
+        assert_eq!(
+            keys(trie.prefix_iter(b"te")),
+            ["tea", "team", "ten", "test"]
+        );
+        assert_eq!(keys(trie.prefix_iter(b"tea")), ["tea", "team"]);
+        assert_eq!(keys(trie.prefix_iter(b"team")), ["team"]);
+        assert!(trie.prefix_iter(b"teams").next().is_none());
+        assert_eq!(keys(trie.prefix_iter(b"to")), ["toast"]);
+        assert!(trie.prefix_iter(b"tx").next().is_none());
+        assert!(trie.prefix_iter(b"b").next().is_none());
+    }
+
+    #[test]
+    fn longest_prefix_of() {
+        let mut trie = RadixTrie::new();
+        assert_eq!(trie.longest_prefix_of(b"abc"), None);
+
+        trie.insert(b"10.0", 1);
+        trie.insert(b"10.0.0", 2);
+        trie.insert(b"10.1", 3);
+        assert_eq!(
+            trie.longest_prefix_of(b"10.0.0.1"),
+            Some((&b"10.0.0"[..], &2))
+        );
+        assert_eq!(
+            trie.longest_prefix_of(b"10.0.1.1"),
+            Some((&b"10.0"[..], &1))
+        );
+        assert_eq!(trie.longest_prefix_of(b"10.2"), None);
+
+        trie.insert(b"", 0);
+        assert_eq!(trie.longest_prefix_of(b"10.2"), Some((&b""[..], &0)));
+    }
+
+    mod proptests {
+        use std::collections::BTreeMap;
+
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 500;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 20;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 500;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        // small alphabet so that the keys share a lot of prefixes
+        fn key() -> impl Strategy<Value = Vec<u8>> {
+            proptest::collection::vec(b'a'..b'e', 0..8)
+        }
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_btree_map(
+                ops in proptest::collection::vec((any::<bool>(), key()), 0..MAP_SIZE),
+                prefix in key(),
+            ) {
+                let mut trie = RadixTrie::new();
+                let mut expected = BTreeMap::new();
+                for (i, (insert, key)) in ops.into_iter().enumerate() {
+                    if insert {
+                        assert_eq!(trie.insert(&key, i), expected.insert(key, i));
+                    } else {
+                        assert_eq!(trie.remove(&key), expected.remove(&key));
+                    }
+                }
+                assert_trie(&trie);
+
+                assert_eq!(trie.len(), expected.len());
+                for (k, v) in &expected {
+                    assert_eq!(trie.get(k), Some(v));
+                }
+                assert!(trie.iter().eq(expected.iter().map(|(k, v)| (k.clone(), v))));
+                assert!(trie
+                    .prefix_iter(&prefix)
+                    .eq(expected.iter().filter(|(k, _)| k.starts_with(&prefix)).map(|(k, v)| (k.clone(), v))));
+
+                let longest = (0..=prefix.len())
+                    .rev()
+                    .find_map(|len| expected.get(&prefix[..len]).map(|v| (&prefix[..len], v)));
+                assert_eq!(trie.longest_prefix_of(&prefix), longest);
+            }
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! Interval tree built on top of the red-black tree
+//!
+//! Every node is augmented with the largest endpoint in its subtree (as in CLRS 14.3).
+//! The red-black tree keeps it up to date through inserts, removals and rotations,
+//! which allows searches to skip the subtrees that cannot contain overlapping intervals.
+
+use core::fmt;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use vec::Stack;
+
+use super::{Link, RbTreeMap};
+
+/// Map from closed intervals `[low, high]` to values.
+///
+/// Intervals are ordered by `low` and then by `high`,
+/// two intervals are the same key only if both endpoints are equal.
+pub struct IntervalTree<T, V> {
+    tree: RbTreeMap<(T, T), Entry<T, V>>,
+}
+
+struct Entry<T, V> {
+    value: V,
+    /// Largest `high` in the subtree of this node
+    max: T,
+}
+
+fn update_max<T, V>(
+    key: &(T, T),
+    entry: &mut Entry<T, V>,
+    left: Option<&Entry<T, V>>,
+    right: Option<&Entry<T, V>>,
+) where
+    T: Ord + Clone,
+{
+    let mut max = &key.1;
+    for child in [left, right].into_iter().flatten() {
+        if child.max > *max {
+            max = &child.max;
+        }
+    }
+    entry.max = max.clone();
+}
+
+impl<T, V> Default for IntervalTree<T, V>
+where
+    T: Ord + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, V> IntervalTree<T, V>
+where
+    T: Ord + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            tree: RbTreeMap::with_node_update(update_max),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Inserts `value` for the interval `[low, high]`.
+    ///
+    /// Returns the old value if the interval was already present.
+    ///
+    /// # PANICS
+    ///
+    /// * if `low > high`
+    pub fn insert(&mut self, interval: (T, T), value: V) -> Option<V> {
+        assert!(
+            interval.0 <= interval.1,
+            "interval's low endpoint must not be larger than its high endpoint"
+        );
+
+        // `max` of a replaced entry is recomputed by the node update hook
+        let max = interval.1.clone();
+        self.tree
+            .insert(interval, Entry { value, max })
+            .map(|(_, entry)| entry.value)
+    }
+
+    pub fn get(&self, interval: &(T, T)) -> Option<&V> {
+        self.tree.get(interval).map(|(_, entry)| &entry.value)
+    }
+
+    pub fn get_mut(&mut self, interval: &(T, T)) -> Option<&mut V> {
+        self.tree
+            .get_mut(interval)
+            .map(|(_, entry)| &mut entry.value)
+    }
+
+    pub fn remove(&mut self, interval: &(T, T)) -> Option<V> {
+        self.tree.delete(interval).map(|(_, entry)| entry.value)
+    }
+
+    /// Iterate over all intervals which overlap with `range`.
+    ///
+    /// Intervals are returned in ascending order. Subtrees whose largest endpoint
+    /// is before the start of `range` are skipped and the search stops at the first
+    /// interval which starts after the end of `range`.
+    pub fn find_overlapping<R>(&self, range: R) -> Overlapping<'_, T, V, R>
+    where
+        R: RangeBounds<T>,
+    {
+        let mut iter = Overlapping {
+            stack: Stack::new(),
+            range,
+            marker: PhantomData,
+        };
+        if !self.tree.is_empty() {
+            iter.push_left_spine(Some(self.tree.root));
+        }
+        iter
+    }
+}
+
+impl<T, V> IntervalTree<T, V> {
+    /// Iterate over all intervals in ascending order.
+    pub fn iter(&self) -> Iter<'_, T, V> {
+        Iter(self.tree.iter())
+    }
+}
+
+impl<T, V> fmt::Debug for IntervalTree<T, V>
+where
+    T: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T, V> IntoIterator for &'a IntervalTree<T, V> {
+    type Item = (&'a (T, T), &'a V);
+    type IntoIter = Iter<'a, T, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the intervals of `IntervalTree`, created by [`IntervalTree::iter`].
+pub struct Iter<'a, T, V>(super::Iter<'a, (T, T), Entry<T, V>>);
+
+impl<'a, T, V> Iterator for Iter<'a, T, V> {
+    type Item = (&'a (T, T), &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, e)| (k, &e.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T, V> DoubleEndedIterator for Iter<'_, T, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(k, e)| (k, &e.value))
+    }
+}
+
+impl<T, V> ExactSizeIterator for Iter<'_, T, V> {}
+
+/// Iterator over the intervals overlapping with a range,
+/// created by [`IntervalTree::find_overlapping`].
+pub struct Overlapping<'a, T, V, R> {
+    // INVARIANTS:
+    //  * `stack` contains the nodes still to be visited (in order) together with
+    //    their right subtrees, like in a regular iterative in-order traversal
+    stack: Stack<super::RawNode<(T, T), Entry<T, V>>>,
+    range: R,
+    marker: PhantomData<&'a Entry<T, V>>,
+}
+
+impl<T, V, R> Overlapping<'_, T, V, R>
+where
+    T: Ord,
+    R: RangeBounds<T>,
+{
+    /// Returns `true` if `high` is not before the start of the range.
+    fn ends_after_start(&self, high: &T) -> bool {
+        match self.range.start_bound() {
+            Bound::Included(start) => high >= start,
+            Bound::Excluded(start) => high > start,
+            Bound::Unbounded => true,
+        }
+    }
+
+    /// Returns `true` if `low` is not after the end of the range.
+    fn starts_before_end(&self, low: &T) -> bool {
+        match self.range.end_bound() {
+            Bound::Included(end) => low <= end,
+            Bound::Excluded(end) => low < end,
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn push_left_spine(&mut self, mut link: Link<(T, T), Entry<T, V>>) {
+        while let Some(node) = link {
+            let (_, entry) = unsafe { node.as_refs() };
+            // nothing in this subtree reaches the start of the range
+            if !self.ends_after_start(&entry.max) {
+                break;
+            }
+            self.stack.push(node);
+            link = unsafe { node.left() };
+        }
+    }
+}
+
+impl<'a, T, V, R> Iterator for Overlapping<'a, T, V, R>
+where
+    T: Ord,
+    R: RangeBounds<T>,
+{
+    type Item = (&'a (T, T), &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            let (interval, entry) = unsafe { node.as_refs() };
+            if !self.starts_before_end(&interval.0) {
+                // all remaining intervals start even later
+                self.stack = Stack::new();
+                return None;
+            }
+
+            self.push_left_spine(unsafe { node.right() });
+            if self.ends_after_start(&interval.1) {
+                return Some((interval, &entry.value));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_max(tree: &IntervalTree<i32, i32>) {
+        fn check(node: Link<(i32, i32), Entry<i32, i32>>) -> Option<i32> {
+            let node = node?;
+            let (interval, entry) = unsafe { node.as_refs() };
+            let max = [
+                check(unsafe { node.left() }),
+                check(unsafe { node.right() }),
+            ]
+            .into_iter()
+            .flatten()
+            .fold(interval.1, i32::max);
+            assert_eq!(entry.max, max);
+            Some(max)
+        }
+
+        if !tree.is_empty() {
+            check(Some(tree.tree.root));
+        }
+    }
+
+    fn overlapping<R: RangeBounds<i32>>(
+        tree: &IntervalTree<i32, i32>,
+        range: R,
+    ) -> Vec<(i32, i32)> {
+        tree.find_overlapping(range).map(|(i, _)| *i).collect()
+    }
+
+    #[test]
+    fn find_overlapping() {
+        // example from CLRS figure 14.4
+        let intervals = [
+            (16, 21),
+            (8, 9),
+            (25, 30),
+            (5, 8),
+            (15, 23),
+            (17, 19),
+            (26, 26),
+            (0, 3),
+            (6, 10),
+            (19, 20),
+        ];
+        let mut tree = IntervalTree::new();
+        for (i, interval) in intervals.iter().enumerate() {
+            assert_eq!(tree.insert(*interval, i as i32), None);
+            assert_max(&tree);
+        }
+        assert_eq!(tree.len(), 10);
+
+        assert_eq!(overlapping(&tree, 22..=25), [(15, 23), (25, 30)]);
+        assert!(overlapping(&tree, 11..=14).is_empty());
+        assert_eq!(overlapping(&tree, 8..=8), [(5, 8), (6, 10), (8, 9)]);
+        // excluded bounds don't touch the endpoints
+        assert!(overlapping(&tree, (Bound::Excluded(3), Bound::Excluded(5))).is_empty());
+        assert_eq!(overlapping(&tree, 3..5), [(0, 3)]);
+        assert_eq!(
+            overlapping(&tree, (Bound::Excluded(21), Bound::Unbounded)),
+            [(15, 23), (25, 30), (26, 26)]
+        );
+        assert_eq!(overlapping(&tree, ..=0), [(0, 3)]);
+        assert_eq!(overlapping(&tree, ..).len(), 10);
+
+        assert_eq!(tree.insert((8, 9), 100), Some(1));
+        assert_eq!(tree.get(&(8, 9)), Some(&100));
+        assert_eq!(tree.remove(&(15, 23)), Some(4));
+        assert_eq!(tree.remove(&(15, 23)), None);
+        assert_max(&tree);
+        assert_eq!(overlapping(&tree, 22..=25), [(25, 30)]);
+        assert!(
+            tree.iter()
+                .map(|(i, _)| i.0)
+                .eq([0, 5, 6, 8, 16, 17, 19, 25, 26])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_interval() {
+        let mut tree = IntervalTree::new();
+        tree.insert((5, 3), ());
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 500;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 500;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        fn interval() -> impl Strategy<Value = (i32, i32)> {
+            (0..1000i32, 0..100i32).prop_map(|(low, len)| (low, low + len))
+        }
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn against_brute_force(
+                inserts in proptest::collection::vec(interval(), 0..MAP_SIZE),
+                removes in proptest::collection::vec(interval(), 0..MAP_SIZE),
+                queries in proptest::collection::vec(interval(), 0..20),
+            ) {
+                let mut tree = IntervalTree::new();
+                let mut expected = std::collections::BTreeMap::new();
+                for (i, interval) in inserts.iter().enumerate() {
+                    assert_eq!(tree.insert(*interval, i as i32), expected.insert(*interval, i as i32));
+                }
+                for interval in removes.iter().chain(inserts.iter().step_by(3)) {
+                    assert_eq!(tree.remove(interval), expected.remove(interval));
+                }
+                assert_max(&tree);
+                assert_eq!(tree.len(), expected.len());
+
+                for (low, high) in queries {
+                    let found = overlapping(&tree, low..=high);
+                    let brute_force = expected
+                        .keys()
+                        .filter(|(l, h)| *l <= high && low <= *h)
+                        .copied()
+                        .collect::<Vec<_>>();
+                    assert_eq!(found, brute_force);
+                }
+            }
+        );
+    }
+}
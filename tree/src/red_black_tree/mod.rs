@@ -0,0 +1,3826 @@
+// TODO: remove massive unsafe blocks
+// TODO: add safety comments
+
+use core::fmt;
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop, MaybeUninit};
+use std::ops::{Bound, RangeBounds};
+use std::ptr::{self, NonNull};
+
+use vec::Stack;
+
+pub mod interval_tree;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Color {
+    Red,
+    Black,
+}
+
+impl Color {
+    /// Returns `true` if the color is [`Red`].
+    ///
+    /// [`Red`]: Color::Red
+    #[must_use]
+    fn is_red(&self) -> bool {
+        matches!(self, Self::Red)
+    }
+
+    /// Returns `true` if the color is [`Black`].
+    ///
+    /// [`Black`]: Color::Black
+    #[must_use]
+    fn is_black(&self) -> bool {
+        matches!(self, Self::Black)
+    }
+}
+
+struct Node<K, V> {
+    // key and value are uninit only for sentinel node which is used by the
+    // delete routine, otherwise they must always be valid values
+    key: K,
+    value: V,
+    color: Color,
+    /// Number of nodes in the subtree rooted at this node
+    size: usize,
+    parent: Option<RawNode<K, V>>,
+    left: Option<RawNode<K, V>>,
+    right: Option<RawNode<K, V>>,
+}
+
+impl<K, V> fmt::Debug for Node<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("Node");
+        f.field("key", &self.key)
+            .field("value", &self.value)
+            .field("color", &self.color)
+            .field("size", &self.size);
+
+        let mut dbg_opt_node = |name: &str, node: &Option<RawNode<K, V>>| match node {
+            Some(node) => {
+                let node = unsafe { node.as_ref() };
+                f.field(name, &(&node.key, &node.value, &node.color));
+            }
+            None => {
+                f.field(name, &None::<K>);
+            }
+        };
+        dbg_opt_node("parent", &self.parent);
+        dbg_opt_node("left", &self.left);
+        dbg_opt_node("right", &self.right);
+
+        f.finish()
+    }
+}
+
+/// Wrapper around `NonNull<Node<K, V>>` to provide convenient methods in order
+/// to make the algorithms of RBTree much more readable.
+#[derive(Debug)]
+#[repr(transparent)]
+struct RawNode<K, V> {
+    ptr: NonNull<Node<K, V>>,
+}
+
+type Link<K, V> = Option<RawNode<K, V>>;
+
+impl<K, V> Clone for RawNode<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for RawNode<K, V> {}
+
+impl<K, V> PartialEq for RawNode<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<K, V> Eq for RawNode<K, V> {}
+
+impl<K, V> RawNode<K, V> {
+    fn dangling() -> Self {
+        Self {
+            ptr: NonNull::dangling(),
+        }
+    }
+
+    fn from_node(node: Node<K, V>) -> Self {
+        Self {
+            ptr: unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(node))) },
+        }
+    }
+
+    #[inline]
+    fn as_ptr(&self) -> *mut Node<K, V> {
+        self.ptr.as_ptr()
+    }
+
+    #[inline]
+    unsafe fn as_ref<'a>(&self) -> &'a Node<K, V> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    #[inline]
+    unsafe fn as_mut<'a>(&mut self) -> &'a mut Node<K, V> {
+        unsafe { self.ptr.as_mut() }
+    }
+
+    #[inline]
+    unsafe fn key<'a>(&self) -> &'a K {
+        unsafe { &(*self.as_ptr()).key }
+    }
+
+    #[inline]
+    /// Replaces the key and value, returns the old ones.
+    unsafe fn replace_key_value(&mut self, key: K, value: V) -> (K, V) {
+        let ptr = self.as_ptr();
+        unsafe {
+            (
+                mem::replace(&mut (*ptr).key, key),
+                mem::replace(&mut (*ptr).value, value),
+            )
+        }
+    }
+
+    #[inline]
+    unsafe fn as_refs<'a>(&self) -> (&'a K, &'a V) {
+        let ptr = self.as_ptr();
+        unsafe { (&(*ptr).key, &(*ptr).value) }
+    }
+
+    #[inline]
+    unsafe fn as_muts<'a>(&mut self) -> (&'a K, &'a mut V) {
+        let ptr = self.as_ptr();
+        unsafe { (&(*ptr).key, &mut (*ptr).value) }
+    }
+
+    #[inline]
+    unsafe fn parent(&self) -> Option<RawNode<K, V>> {
+        unsafe { (*self.as_ptr()).parent }
+    }
+
+    #[inline]
+    unsafe fn set_parent(&mut self, new_parent: Option<RawNode<K, V>>) {
+        unsafe {
+            (*self.as_ptr()).parent = new_parent;
+        }
+    }
+
+    #[inline]
+    unsafe fn right(&self) -> Option<RawNode<K, V>> {
+        unsafe { (*self.as_ptr()).right }
+    }
+
+    #[inline]
+    unsafe fn set_right(&mut self, new_right: Option<RawNode<K, V>>) {
+        unsafe {
+            (*self.as_ptr()).right = new_right;
+        }
+    }
+
+    #[inline]
+    unsafe fn left(&self) -> Option<RawNode<K, V>> {
+        unsafe { (*self.as_ptr()).left }
+    }
+
+    #[inline]
+    unsafe fn set_left(&mut self, new_left: Option<RawNode<K, V>>) {
+        unsafe {
+            (*self.as_ptr()).left = new_left;
+        }
+    }
+
+    #[inline]
+    unsafe fn color(&self) -> Color {
+        unsafe { (*self.as_ptr()).color }
+    }
+
+    #[inline]
+    unsafe fn set_color(&mut self, new_color: Color) {
+        unsafe { (*self.as_ptr()).color = new_color }
+    }
+
+    #[inline]
+    unsafe fn size(&self) -> usize {
+        unsafe { (*self.as_ptr()).size }
+    }
+
+    #[inline]
+    unsafe fn set_size(&mut self, new_size: usize) {
+        unsafe { (*self.as_ptr()).size = new_size }
+    }
+
+    #[inline]
+    unsafe fn pos(&self) -> NodePos {
+        let ptr = self.as_ptr();
+        match unsafe { (*ptr).parent } {
+            Some(p) => match unsafe { (p.left(), p.right()) } {
+                (None, None) => unreachable!(),
+                (None, Some(_)) => NodePos::Right,
+                (Some(_), None) => NodePos::Left,
+                (Some(left), Some(right)) => {
+                    if ptr::eq(ptr, left.as_ptr()) {
+                        NodePos::Left
+                    } else {
+                        assert!(ptr::eq(ptr, right.as_ptr()));
+                        NodePos::Right
+                    }
+                }
+            },
+            None => NodePos::Root,
+        }
+    }
+
+    #[inline]
+    unsafe fn grand_parent(&self) -> Option<RawNode<K, V>> {
+        unsafe { self.parent().and_then(|p| p.parent()) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodePos {
+    Root,
+    Left,
+    Right,
+}
+
+/// Ordered map backed by a red-black tree.
+///
+/// Keys are kept in ascending order and the tree is kept balanced, so lookups,
+/// inserts and removals are O(log n).
+pub struct RbTreeMap<K, V> {
+    root: RawNode<K, V>,
+    len: usize,
+    /// Hook to maintain additional per-node data, see `NodeUpdate`
+    update: Option<NodeUpdate<K, V>>,
+    /// Allocations of removed nodes for reuse, see `with_node_pool`
+    pool: Option<NodePool<K, V>>,
+    marker: PhantomData<Box<Node<K, V>>>,
+}
+
+/// Free list of node allocations whose key and value have already been moved out.
+///
+/// The free nodes are linked through their `parent` field, all other fields are
+/// invalid. The allocations are still owned by the pool and freed when it's dropped.
+struct NodePool<K, V> {
+    free: Link<K, V>,
+    len: usize,
+}
+
+impl<K, V> NodePool<K, V> {
+    fn new() -> Self {
+        Self { free: None, len: 0 }
+    }
+
+    /// Returns a free allocation, the node must be initialized with `ptr::write`.
+    fn pop(&mut self) -> Link<K, V> {
+        let node = self.free?;
+        // SAFETY: all nodes in the free list are valid allocations and
+        // their `parent` field is initialized
+        self.free = unsafe { ptr::addr_of!((*node.as_ptr()).parent).read() };
+        self.len -= 1;
+        Some(node)
+    }
+
+    /// # SAFETY
+    ///
+    /// * `node` must be a valid allocation which is not used anywhere else
+    /// * its key and value must have already been moved out or dropped
+    unsafe fn push(&mut self, node: RawNode<K, V>) {
+        // SAFETY: `node` is valid allocation, only the `parent` field is written,
+        // so no other (possibly moved out) fields are dropped
+        unsafe { ptr::addr_of_mut!((*node.as_ptr()).parent).write(self.free) };
+        self.free = Some(node);
+        self.len += 1;
+    }
+}
+
+impl<K, V> Drop for NodePool<K, V> {
+    fn drop(&mut self) {
+        while let Some(node) = self.pop() {
+            // SAFETY: the node was allocated by a `Box` and its key and value
+            // are moved out, so free the memory without dropping the contents
+            drop(unsafe { Box::from_raw(node.as_ptr().cast::<MaybeUninit<Node<K, V>>>()) });
+        }
+    }
+}
+
+/// Called with the key and value of a node and the values of its children
+/// whenever the subtree of the node changes (after inserts, removals and rotations).
+///
+/// Allows to keep augmented data about the subtree in the values,
+/// for example the largest endpoint in the interval tree.
+/// The nodes are updated bottom up, so the children are always up to date.
+type NodeUpdate<K, V> = fn(&K, &mut V, Option<&V>, Option<&V>);
+
+impl<K, V> Drop for RbTreeMap<K, V> {
+    fn drop(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+
+        /// Guard in case `K::drop` or `V::drop` panics.
+        ///
+        /// We try to clean up as much as possible after the panic, eg try to
+        /// drop the remaining nodes.
+        struct Guard<K, V>(Link<K, V>);
+
+        impl<K, V> Guard<K, V> {
+            // Rotate left children up until the current node has no left child
+            // (flattening the tree into a right leaning list), then free the node
+            // and move to its right child. This visits every node without recursion
+            // or extra memory, and the remaining nodes are always reachable from `self.0`.
+            fn drop_nodes(&mut self) {
+                while let Some(mut node) = self.0 {
+                    // SAFETY: all nodes reachable from root are valid and each is freed exactly once,
+                    // parent links are not used, so we don't need to keep them in sync
+                    unsafe {
+                        match node.left() {
+                            Some(mut left) => {
+                                // rotate right: `left` becomes the parent of `node`
+                                node.set_left(left.right());
+                                left.set_right(Some(node));
+                                self.0 = Some(left);
+                            }
+                            None => {
+                                // Move to the next node before dropping this one, so
+                                // if `K::drop` or `V::drop` panics, the guard's
+                                // drop can continue from the next node.
+                                self.0 = node.right();
+                                drop(Box::from_raw(node.as_ptr()));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        impl<K, V> Drop for Guard<K, V> {
+            fn drop(&mut self) {
+                self.drop_nodes()
+            }
+        }
+
+        self.len = 0;
+        let mut guard = Guard(Some(self.root));
+        guard.drop_nodes()
+    }
+}
+
+impl<K, V> fmt::Debug for RbTreeMap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct TreeDebug<'a, K, V> {
+            root: RawNode<K, V>,
+            marker: PhantomData<&'a Node<K, V>>,
+        }
+
+        impl<K, V> fmt::Debug for TreeDebug<'_, K, V>
+        where
+            K: fmt::Debug,
+            V: fmt::Debug,
+        {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut f = f.debug_list();
+
+                let mut func = |node: RawNode<K, V>| {
+                    let node = unsafe { node.as_ref() };
+                    f.entry(&node);
+                };
+
+                unsafe { RbTreeMap::inorder_for_each_core(self.root, &mut func) };
+                f.finish()
+            }
+        }
+
+        let mut f = f.debug_struct("RbTreeMap");
+        f.field("len", &self.len);
+
+        match self.len {
+            0 => {
+                f.field("root", &None::<K>);
+                let nodes: &[K] = &[];
+                f.field("nodes", &nodes);
+            }
+            _ => {
+                f.field("root", &Some(unsafe { self.root.as_ref() }));
+                f.field(
+                    "nodes",
+                    &TreeDebug {
+                        root: self.root,
+                        marker: PhantomData,
+                    },
+                );
+            }
+        }
+
+        f.finish()
+    }
+}
+
+impl<K, V> Default for RbTreeMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> RbTreeMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            root: RawNode::dangling(),
+            len: 0,
+            update: None,
+            pool: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Creates a tree which keeps the allocations of removed nodes and
+    /// reuses them for new nodes.
+    ///
+    /// This cuts the allocator traffic for workloads with a lot of inserts and removals.
+    /// The pool is never shrunk, it holds at most as many nodes as the tree had
+    /// at its largest. Only the nodes removed with `delete` and `pop_first`/`pop_last`
+    /// (and the owning iterator) are reused, `clear` and dropping the tree free them.
+    pub fn with_node_pool() -> Self {
+        let mut tree = Self::new();
+        tree.pool = Some(NodePool::new());
+        tree
+    }
+
+    /// Number of free node allocations kept for reuse, see [`Self::with_node_pool`].
+    pub fn pooled_nodes(&self) -> usize {
+        self.pool.as_ref().map_or(0, |pool| pool.len)
+    }
+
+    /// Allocates a new node, reusing a pooled allocation if possible.
+    fn alloc_node(&mut self, node: Node<K, V>) -> RawNode<K, V> {
+        match self.pool.as_mut().and_then(NodePool::pop) {
+            Some(raw) => {
+                // SAFETY: pooled allocations are valid and hold no values,
+                // so we can overwrite them without dropping
+                unsafe { raw.as_ptr().write(node) };
+                raw
+            }
+            None => RawNode::from_node(node),
+        }
+    }
+
+    /// Moves the key and value out of `node` and frees or pools the allocation.
+    ///
+    /// # SAFETY
+    ///
+    /// * `node` must be a valid node which is not reachable from any tree
+    unsafe fn free_node(&mut self, node: RawNode<K, V>) -> (K, V) {
+        match &mut self.pool {
+            Some(pool) => unsafe {
+                // SAFETY: `node` is valid, we read the key and value exactly once
+                // and the allocation is not dropped, so they are not dropped twice
+                let key = ptr::addr_of!((*node.as_ptr()).key).read();
+                let value = ptr::addr_of!((*node.as_ptr()).value).read();
+                pool.push(node);
+                (key, value)
+            },
+            None => {
+                let node = unsafe { Box::from_raw(node.as_ptr()) };
+                (node.key, node.value)
+            }
+        }
+    }
+
+    /// Creates a tree which calls `update` on every node whose subtree changed.
+    fn with_node_update(update: NodeUpdate<K, V>) -> Self {
+        let mut tree = Self::new();
+        tree.update = Some(update);
+        tree
+    }
+
+    /// Builds a balanced tree from `items` in O(n) if they are already sorted,
+    /// otherwise they are sorted first. If there are duplicate keys, the last one wins.
+    fn bulk_load(mut items: Vec<(K, V)>) -> Self
+    where
+        K: Ord,
+    {
+        // stable sort keeps the order of duplicates, so we can keep the last of them
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut unique: Vec<(K, V)> = Vec::with_capacity(items.len());
+        for (k, v) in items {
+            match unique.last_mut() {
+                Some(last) if last.0 == k => *last = (k, v),
+                _ => unique.push((k, v)),
+            }
+        }
+
+        /// Builds a balanced subtree from the next `len` items of `items`.
+        ///
+        /// Splitting at the midpoint puts all the leaves on the two lowest levels.
+        /// All nodes are black except the ones on the lowest level (`red_depth`),
+        /// so every path from the root to a leaf has the same number of black nodes.
+        /// Recursion depth is `log2(len)` as the tree is balanced.
+        fn build<K, V>(
+            items: &mut std::vec::IntoIter<(K, V)>,
+            len: usize,
+            depth: usize,
+            red_depth: usize,
+        ) -> Link<K, V> {
+            if len == 0 {
+                return None;
+            }
+
+            // consume items in order: left subtree, the midpoint and then the right subtree
+            let left_len = len / 2;
+            let left = build(items, left_len, depth + 1, red_depth);
+            let (key, value) = items.next().expect("`items` must contain `len` items");
+            let right = build(items, len - left_len - 1, depth + 1, red_depth);
+
+            let node = RawNode::from_node(Node {
+                key,
+                value,
+                color: if depth == red_depth {
+                    Color::Red
+                } else {
+                    Color::Black
+                },
+                size: len,
+                parent: None,
+                left,
+                right,
+            });
+            for mut child in [left, right].into_iter().flatten() {
+                unsafe { child.set_parent(Some(node)) };
+            }
+            Some(node)
+        }
+
+        let len = unique.len();
+        let mut tree = Self::new();
+        // root must stay black, so a single node tree has no red nodes
+        let red_depth = match len {
+            0 | 1 => usize::MAX,
+            _ => len.ilog2() as usize,
+        };
+        if let Some(root) = build(&mut unique.into_iter(), len, 0, red_depth) {
+            tree.root = root;
+            tree.len = len;
+        }
+        tree
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn inorder_for_each<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V),
+    {
+        if self.is_empty() {
+            return;
+        }
+
+        let mut f = |mut node: RawNode<K, V>| {
+            let node = unsafe { node.as_mut() };
+            f(&node.key, &mut node.value)
+        };
+        unsafe { Self::inorder_for_each_core(self.root, &mut f) }
+    }
+
+    /// Calls `f` on every node in the subtree of `node` in order.
+    ///
+    /// Walks the parent links instead of recursing, so it doesn't need any
+    /// extra memory and cannot overflow the stack regardless of the tree shape.
+    unsafe fn inorder_for_each_core<F>(node: RawNode<K, V>, f: &mut F)
+    where
+        F: FnMut(RawNode<K, V>),
+    {
+        let end = unsafe { Self::max_of(node) };
+        let mut current = unsafe { Self::min_of(node) };
+        loop {
+            // Find the next node before calling `f`, so `f` is free to modify the node
+            let next = unsafe { Self::successor_core(current) };
+            let is_last = current == end;
+            f(current);
+            match next {
+                Some(next) if !is_last => current = next,
+                _ => break,
+            }
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.get_raw(key).map(|node| unsafe { node.as_refs() })
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.get_raw(key).map(|mut node| unsafe { node.as_muts() })
+    }
+
+    fn get_raw<Q>(&self, key: &Q) -> Option<RawNode<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut x = self.root;
+        loop {
+            match key.cmp(unsafe { (*x.as_ptr()).key.borrow() }) {
+                std::cmp::Ordering::Less => match unsafe { x.left() } {
+                    Some(left) => x = left,
+                    None => break,
+                },
+                std::cmp::Ordering::Equal => return Some(x),
+                std::cmp::Ordering::Greater => match unsafe { x.right() } {
+                    Some(right) => x = right,
+                    None => break,
+                },
+            }
+        }
+
+        None
+    }
+
+    /// Iterate over the items in ascending key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let (front, back) = self.min_max_nodes();
+        Iter {
+            front,
+            back,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate over the items in ascending key order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        let (front, back) = self.min_max_nodes();
+        IterMut {
+            front,
+            back,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate over the keys in ascending order.
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys(self.iter())
+    }
+
+    /// Iterate over the values in ascending key order.
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values(self.iter())
+    }
+
+    /// Iterate over the values in ascending key order.
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut(self.iter_mut())
+    }
+
+    fn min_max_nodes(&self) -> (Link<K, V>, Link<K, V>) {
+        if self.is_empty() {
+            return (None, None);
+        }
+        unsafe { (Some(Self::min_of(self.root)), Some(Self::max_of(self.root))) }
+    }
+
+    /// Iterate over the items whose keys are in `range` in ascending key order.
+    ///
+    /// The first and last items in the range are found by descending the tree,
+    /// everything in between is visited by following the successors.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+        R: RangeBounds<Q>,
+    {
+        let (front, back) = self.range_nodes(&range);
+        Range {
+            front,
+            back,
+            marker: PhantomData,
+        }
+    }
+
+    /// Iterate mutably over the items whose keys are in `range` in ascending key order.
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+        R: RangeBounds<Q>,
+    {
+        let (front, back) = self.range_nodes(&range);
+        RangeMut {
+            front,
+            back,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the first and last node in `range`, or `None`s if the range is empty.
+    fn range_nodes<Q, R>(&self, range: &R) -> (Link<K, V>, Link<K, V>)
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+        R: RangeBounds<Q>,
+    {
+        let front = match range.start_bound() {
+            Bound::Included(start) => self.first_node_where(|k| k.borrow() >= start),
+            Bound::Excluded(start) => self.first_node_where(|k| k.borrow() > start),
+            Bound::Unbounded => self.min_max_nodes().0,
+        };
+        let back = match range.end_bound() {
+            Bound::Included(end) => self.last_node_where(|k| k.borrow() <= end),
+            Bound::Excluded(end) => self.last_node_where(|k| k.borrow() < end),
+            Bound::Unbounded => self.min_max_nodes().1,
+        };
+
+        // `start > end` gives `front` after `back`
+        let is_valid = |front: RawNode<K, V>, back: RawNode<K, V>| {
+            let front: &Q = unsafe { front.key().borrow() };
+            let back: &Q = unsafe { back.key().borrow() };
+            front <= back
+        };
+        match (front, back) {
+            (Some(f), Some(b)) if is_valid(f, b) => (Some(f), Some(b)),
+            _ => (None, None),
+        }
+    }
+
+    /// Returns the first node in key order for which `pred` is `true`.
+    ///
+    /// `pred` must be `false` for some prefix of the keys and `true` for the rest.
+    fn first_node_where<F>(&self, mut pred: F) -> Link<K, V>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut found = None;
+        let mut maybe_node = Some(self.root);
+        while let Some(node) = maybe_node {
+            unsafe {
+                if pred(node.key()) {
+                    // everything in the right subtree is larger, but there may
+                    // be a smaller key which matches in the left subtree
+                    found = Some(node);
+                    maybe_node = node.left();
+                } else {
+                    maybe_node = node.right();
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Returns the last node in key order for which `pred` is `true`.
+    ///
+    /// `pred` must be `true` for some prefix of the keys and `false` for the rest.
+    fn last_node_where<F>(&self, mut pred: F) -> Link<K, V>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut found = None;
+        let mut maybe_node = Some(self.root);
+        while let Some(node) = maybe_node {
+            unsafe {
+                if pred(node.key()) {
+                    found = Some(node);
+                    maybe_node = node.right();
+                } else {
+                    maybe_node = node.left();
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Returns the first item whose key is `>= key`.
+    pub fn lower_bound<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let node = self.first_node_where(|k| k.borrow() >= key)?;
+        Some(unsafe { node.as_refs() })
+    }
+
+    /// Returns the first item whose key is `> key`.
+    pub fn upper_bound<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let node = self.first_node_where(|k| k.borrow() > key)?;
+        Some(unsafe { node.as_refs() })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.get_raw(key).is_some()
+    }
+
+    /// Returns the entry with the smallest key.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.min()
+    }
+
+    /// Returns the entry with the largest key.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.max()
+    }
+
+    /// Removes and returns the entry with the smallest key.
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let min = unsafe { Self::min_of(self.root) };
+        Some(self.delete_core(min))
+    }
+
+    /// Removes and returns the entry with the largest key.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let max = unsafe { Self::max_of(self.root) };
+        Some(self.delete_core(max))
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        // drops the old items
+        drop(self.take_items());
+    }
+
+    /// Moves all items into a new tree, leaving `self` empty.
+    fn take_items(&mut self) -> Self {
+        let mut items = Self::new();
+        items.update = self.update;
+        mem::swap(&mut items.root, &mut self.root);
+        mem::swap(&mut items.len, &mut self.len);
+        items
+    }
+
+    pub fn min(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let min = unsafe { Self::min_of(self.root) };
+        unsafe { Some(min.as_refs()) }
+    }
+
+    unsafe fn min_of(root: RawNode<K, V>) -> RawNode<K, V> {
+        let mut x = root;
+        while let Some(left) = unsafe { x.left() } {
+            x = left;
+        }
+
+        x
+    }
+
+    pub fn max(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let max = unsafe { Self::max_of(self.root) };
+        unsafe { Some(max.as_refs()) }
+    }
+
+    unsafe fn max_of(root: RawNode<K, V>) -> RawNode<K, V> {
+        let mut x = root;
+        while let Some(right) = unsafe { x.right() } {
+            x = right;
+        }
+
+        x
+    }
+
+    pub fn successor<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q> + Eq,
+        Q: Ord,
+    {
+        match self.get_raw(key) {
+            Some(node) => unsafe { Self::successor_core(node).map(|node| node.as_refs()) },
+            None => None,
+        }
+    }
+
+    unsafe fn successor_core(mut node: RawNode<K, V>) -> Option<RawNode<K, V>> {
+        //       ┌────────── 34 ─────────┐
+        //       │                       │
+        // ┌──── 2 ────┐                 58 ────┐
+        // │           │                        │
+        // 1      ┌─── 9 ────┐              ┌── 77 ──┐
+        //        │          │              │        │
+        //     ┌─ 6       ┌─ 20 ─┐      ┌─ 71 ─┐     82
+        //     │          │      │      │      │
+        //     5         12 ─┐   24    67      75
+        //                   │
+        //                   13
+
+        match unsafe { node.right() } {
+            // 9 -> 12, 2 -> 5, 58 -> 67 ...
+            //
+            // A right subtree contains items that are larger than node but smaller than any other larger than node item in the tree --
+            // that is smaller than the successor of node if it didn't have a right subtree.
+            // Thus if a right subtree exists the successor must be in it.
+            //
+            // Another way to think about it would be to consider that a right subtree only exists if we insert > node item
+            // after the node. When we move down the tree and the new item is larger than any other item before the node,
+            // it would end up in their right subtree whereas the node is in it's left subtree. Thus the item that ends up
+            // it the node's right subtree must be larger than node but smaller than any other item that's larger than the node.
+            Some(right) => unsafe { Some(Self::min_of(right)) },
+            _ => {
+                // 6 -> 9, 1 -> 2, 13 -> 20, 24 -> 34 ...
+                // Move up the parents and find the first node which is the left child of it's parent.
+                // The parent of that node is the successor.
+                //
+                // If a node is in the right subtree then it must be > than it's parent.
+                // If parent is also in the right subtree of it's parent then
+                //  node > parent > parent.parent and so on.
+                // Now if the parent is in the left subtree then it must be < than it's parent.
+                // node > parent but parent < parent.parent and node < parent.parent.
+                // Everything in the parent's left subtree must be < node and everything in
+                // the right subtree of parent.parent is > that itself and thus > node.
+                // Thus parent.parent is the smallest item that is larger than node -- it is its successor.
+                let mut node_parent = unsafe { node.parent() };
+                while let Some(parent) = node_parent {
+                    unsafe {
+                        match parent.left() {
+                            Some(left) if ptr::eq(node.as_ptr(), left.as_ptr()) => break,
+                            _ => {}
+                        }
+                    }
+                    node = parent;
+                    node_parent = unsafe { node.parent() };
+                }
+
+                node_parent
+            }
+        }
+    }
+
+    pub fn predecessor<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q> + Eq,
+        Q: Ord,
+    {
+        match self.get_raw(key) {
+            Some(node) => unsafe { Self::predecessor_core(node).map(|node| node.as_refs()) },
+            None => None,
+        }
+    }
+
+    unsafe fn predecessor_core(mut node: RawNode<K, V>) -> Option<RawNode<K, V>> {
+        //       ┌────────── 34 ─────────┐
+        //       │                       │
+        // ┌──── 2 ────┐                 58 ────┐
+        // │           │                        │
+        // 1      ┌─── 9 ────┐              ┌── 77 ──┐
+        //        │          │              │        │
+        //     ┌─ 6       ┌─ 20 ─┐      ┌─ 71 ─┐     82
+        //     │          │      │      │      │
+        //     5         12 ─┐   24    67      75
+        //                   │
+        //                   13
+
+        match unsafe { node.left() } {
+            // 2 -> 1, 9 -> 6, 20 -> 13, 77 -> 75
+            Some(left) => unsafe { Some(Self::max_of(left)) },
+            _ => {
+                // 12 -> 9, 58 -> 34, 67 -> 58
+                // Move up the parents and find the first node which is the right child of it's parent.
+                // The parent of that node is the predecessor.
+                let mut node_parent = unsafe { node.parent() };
+                while let Some(parent) = node_parent {
+                    unsafe {
+                        match parent.right() {
+                            Some(right) if ptr::eq(node.as_ptr(), right.as_ptr()) => break,
+                            _ => {}
+                        }
+                    }
+                    node = parent;
+                    node_parent = unsafe { node.parent() };
+                }
+
+                node_parent
+            }
+        }
+    }
+
+    fn rotate_left(&mut self, mut node: RawNode<K, V>) {
+        //    p                   p
+        //    │                   │
+        // ┌─ n ─┐             ┌─ r ─┐
+        // │     │     ──►     │     │
+        // a  ┌─ r ─┐       ┌─ n ─┐  c
+        //    │     │       │     │
+        //    b     c       a     b
+        // where a, b, c can be any subtrees
+        unsafe {
+            if let Some(mut right) = node.right() {
+                // attach b to node
+                let b = right.left();
+                node.set_right(b);
+                if let Some(mut new_right) = node.right() {
+                    new_right.set_parent(Some(node));
+                }
+
+                // attach right to parent
+                let parent = node.parent();
+                right.set_parent(parent);
+                match node.pos() {
+                    NodePos::Root => self.root = right,
+                    NodePos::Left => parent.unwrap().set_left(Some(right)),
+                    NodePos::Right => parent.unwrap().set_right(Some(right)),
+                }
+
+                // attach node to right
+                right.set_left(Some(node));
+                node.set_parent(Some(right));
+
+                // `right` takes the place of `node`, so it has the same subtree size as `node` had
+                right.set_size(node.size());
+                node.set_size(Self::size_of(node.left()) + Self::size_of(node.right()) + 1);
+                self.update_node(node);
+                self.update_node(right);
+            }
+        }
+    }
+
+    fn rotate_right(&mut self, mut node: RawNode<K, V>) {
+        //       p             p
+        //       |             |
+        //    ┌─ n ─┐       ┌─ l ─┐
+        //    │     │  ──►  │     │
+        // ┌─ l ─┐  c       a  ┌─ n ─┐
+        // │     │             │     │
+        // a     b             b     c
+        // where a, b, c can be any subtrees
+
+        unsafe {
+            if let Some(mut left) = node.left() {
+                // attach b to node
+                let b = left.right();
+                node.set_left(b);
+                if let Some(mut new_left) = node.left() {
+                    new_left.set_parent(Some(node));
+                }
+
+                // attach left to parent
+                let parent = node.parent();
+                left.set_parent(parent);
+                match node.pos() {
+                    NodePos::Root => self.root = left,
+                    NodePos::Left => parent.unwrap().set_left(Some(left)),
+                    NodePos::Right => parent.unwrap().set_right(Some(left)),
+                }
+
+                // attach node to left
+                left.set_right(Some(node));
+                node.set_parent(Some(left));
+
+                left.set_size(node.size());
+                node.set_size(Self::size_of(node.left()) + Self::size_of(node.right()) + 1);
+                self.update_node(node);
+                self.update_node(left);
+            }
+        }
+    }
+
+    /// Inserts `key` and `value` into the tree.
+    ///
+    /// If an equal key already exists, both the key and value are replaced
+    /// and the old entry is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)>
+    where
+        K: Eq + Ord,
+    {
+        let mut new_node = Node {
+            key,
+            value,
+            color: Color::Red,
+            size: 1,
+            parent: None,
+            left: None,
+            right: None,
+        };
+
+        // Move left/right down the tree until we find empty slot
+        let mut parent = None;
+        let mut maybe_node = if self.is_empty() {
+            None
+        } else {
+            Some(self.root)
+        };
+        while let Some(mut node) = maybe_node {
+            parent = maybe_node;
+            unsafe {
+                match (new_node.key).cmp(node.key()) {
+                    std::cmp::Ordering::Less => maybe_node = node.left(),
+                    std::cmp::Ordering::Equal => {
+                        let old = node.replace_key_value(new_node.key, new_node.value);
+                        self.update_path(Some(node));
+                        return Some(old);
+                    }
+                    std::cmp::Ordering::Greater => maybe_node = node.right(),
+                }
+            }
+        }
+
+        new_node.parent = parent;
+        // new_node is a leaf, it cannot have left or right subtrees
+        let new_node = self.alloc_node(new_node);
+        // update parent to point to the new node
+        match parent {
+            Some(mut parent) => unsafe {
+                if new_node.key() < parent.key() {
+                    parent.set_left(Some(new_node));
+                } else {
+                    parent.set_right(Some(new_node));
+                }
+            },
+            None => self.root = new_node,
+        }
+
+        unsafe {
+            self.update_node(new_node);
+            self.add_to_sizes(parent, 1);
+        }
+        self.len += 1;
+        self.insert_fixup(new_node);
+        None
+    }
+
+    /// Fixes the red-black properties after a red `new_node` was linked into the tree.
+    ///
+    /// Returns `true` if the black height of the tree increased.
+    fn insert_fixup(&mut self, new_node: RawNode<K, V>) -> bool {
+        let mut node = new_node;
+        unsafe {
+            while let Some(parent) = node.parent() {
+                if parent.color().is_black() {
+                    break;
+                }
+
+                debug_assert!(node.color().is_red());
+                node = self.insert_fixup_core(parent, node);
+            }
+
+            let grew = self.root.color().is_red();
+            self.root.set_color(Color::Black);
+            grew
+        }
+    }
+
+    /// Fixes red parent having a red child.
+    ///
+    /// Returns a new node which may violate this property.
+    unsafe fn insert_fixup_core(
+        &mut self,
+        mut parent: RawNode<K, V>,
+        mut child: RawNode<K, V>,
+    ) -> RawNode<K, V> {
+        unsafe {
+            debug_assert!(child.color().is_red());
+            debug_assert!(parent.color().is_red());
+
+            // red-black properties are violated because red parent has a red child
+            //
+            // Note that there is only one violation at this point.
+            // At first iteration it's the new_node and it's parent.
+            // If we take the "red uncle" branch then at next iteration it will be
+            // the grand_parent and it's parent that violate the red-black properties.
+            // If we take the other branch, there will be no more iterations as that
+            // will result in a black parent.
+
+            match parent.pos() {
+                NodePos::Root => unreachable!(),
+                NodePos::Left => {
+                    // grand_parent must exist because parent is red and
+                    // thus not root as root is always black
+                    let mut grand_parent = parent.parent().unwrap();
+                    let uncle = grand_parent.right();
+                    debug_assert!(grand_parent.color().is_black());
+
+                    match uncle {
+                        Some(mut uncle) if uncle.color().is_red() => {
+                            //      ┌──── gp:b ───┐                 ┌─── gp:r ───┐
+                            //      │             │                 │            │
+                            //  ┌─ p:r ─┐     ┌─ u:r ─┐   ──►   ┌─ p:b ─┐    ┌─ u:b ─┐
+                            //  │       │     │       │         │       │    │       │
+                            // c:r     1:b   2:b     3:b       c:r     1:b  2:b     3:b
+                            // (1, 2, 3 can be any subtrees)
+                            //
+                            // at first thought we could color new node `n` black but that would
+                            // increase the black height by one on that leaf but not on other leaves
+                            // which is again in violation to red-black properties.
+                            // Instead color parent and uncle black and grandparent red, which keeps
+                            // the black height unchanged.
+                            // Now the grand parent may also have a red parent, but we can simply
+                            // repeat the process as if the grand parent was the new added node.
+                            parent.set_color(Color::Black);
+                            uncle.set_color(Color::Black);
+                            grand_parent.set_color(Color::Red);
+                            grand_parent
+                        }
+                        _ => {
+                            if let NodePos::Right = child.pos() {
+                                //       ┌── gp:b ──┐                 ┌── gp:b ──┐
+                                //       │          │                 │          │
+                                //  ┌── p:r ──┐    u:b  ──►       ┌─ c:r ──┐    u:b
+                                //  │         │                   │        │
+                                // 1:b    ┌─ c:r ─┐           ┌─ p:r ─┐   3:b
+                                //        │       │           │       │
+                                //       2:b     3:b         1:b     2:b
+                                // (1, 2, 3, u can be any subtrees)
+                                //
+                                // left rotate parent and swap node and parent pointers so we match the case below
+                                self.rotate_left(parent);
+                                mem::swap(&mut parent, &mut child);
+                            }
+
+                            //           ┌── gp:b ──┐            ┌───── p:b ─────┐
+                            //           │          │            │               │
+                            //      ┌── p:r ──┐    u:b  ──►   ┌─ c:r ─┐     ┌─ gp:r ─┐
+                            //      │         │               │       │     │        │
+                            //  ┌─ c:r ─┐    3:b             1:b     2:b   3:b      u:b
+                            //  │       │
+                            // 1:b     2:b
+                            //
+                            // (1, 2, 3, u can be any subtrees)
+                            //
+                            // Note that this will fix the one violation we had and thus the whole tree
+                            // is again a proper red-black tree.
+
+                            parent.set_color(Color::Black);
+                            grand_parent.set_color(Color::Red);
+                            self.rotate_right(grand_parent);
+                            child
+                        }
+                    }
+                }
+                NodePos::Right => {
+                    // same as Left branch but left/right are switched
+                    let mut grand_parent = parent.parent().unwrap();
+                    let uncle = grand_parent.left();
+
+                    match uncle {
+                        Some(mut uncle) if uncle.color().is_red() => {
+                            parent.set_color(Color::Black);
+                            uncle.set_color(Color::Black);
+                            grand_parent.set_color(Color::Red);
+                            grand_parent
+                        }
+                        _ => {
+                            if let NodePos::Left = child.pos() {
+                                self.rotate_right(parent);
+                                mem::swap(&mut parent, &mut child);
+                            }
+
+                            parent.set_color(Color::Black);
+                            grand_parent.set_color(Color::Red);
+                            self.rotate_left(grand_parent);
+                            child
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn insert_bst(&mut self, key: K, value: V)
+    where
+        K: Eq + Ord,
+    {
+        let mut new_node = Node {
+            key,
+            value,
+            color: Color::Black,
+            size: 1,
+            parent: None,
+            left: None,
+            right: None,
+        };
+
+        // Move left/right down the tree until we find empty slot
+        let mut parent = None;
+        let mut maybe_node = if self.is_empty() {
+            None
+        } else {
+            Some(self.root)
+        };
+        while let Some(mut node) = maybe_node {
+            parent = maybe_node;
+            unsafe {
+                match (new_node.key).cmp(node.key()) {
+                    std::cmp::Ordering::Less => maybe_node = node.left(),
+                    std::cmp::Ordering::Equal => {
+                        node.replace_key_value(new_node.key, new_node.value);
+                        self.update_path(Some(node));
+                        return;
+                    }
+                    std::cmp::Ordering::Greater => maybe_node = node.right(),
+                }
+            }
+        }
+
+        new_node.parent = parent;
+        // new_node is a left, it cannot have left or right subtrees
+        let new_node = self.alloc_node(new_node);
+        // update parent to point to the new node
+        match parent {
+            Some(mut parent) => unsafe {
+                if new_node.key() < parent.key() {
+                    parent.set_left(Some(new_node));
+                } else {
+                    parent.set_right(Some(new_node));
+                }
+            },
+            None => {
+                self.root = new_node;
+            }
+        }
+
+        unsafe {
+            self.update_node(new_node);
+            self.add_to_sizes(parent, 1);
+        }
+        self.len += 1;
+    }
+
+    pub fn delete<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Ord,
+    {
+        self.get_raw(key).map(|node| self.delete_core(node))
+    }
+
+    fn delete_core(&mut self, node: RawNode<K, V>) -> (K, V) {
+        //       ┌────────── 34 ─────────┐
+        //       │                       │
+        // ┌──── 2 ────┐                 58 ────┐
+        // │           │                        │
+        // 1      ┌─── 9 ────┐              ┌── 77 ──┐
+        //        │          │              │        │
+        //     ┌─ 6       ┌─ 20 ─┐      ┌─ 71 ─┐     82
+        //     │          │      │      │      │
+        //     5         12 ─┐   24    67      75
+        //                   │
+        //                   13
+
+        unsafe {
+            let to_remove = node;
+            let mut to_remove_orig_color = to_remove.color();
+            // Node that replaces the removed node
+            let replacement: Option<RawNode<K, V>>;
+            let mut replacement_parent: Option<RawNode<K, V>>;
+            match (node.left(), node.right()) {
+                (None, v @ Some(_)) | (v @ Some(_), None) | (None, v @ None) => {
+                    // `node` has no children or only one.
+                    // To remove `node` replace `node` with the its child or `None`.
+                    // For example remove 1, 6, 12, 58 from tree above
+
+                    self.replace_subtree(node, v);
+                    replacement = v;
+                    replacement_parent = node.parent();
+                    //println!("1");
+                }
+                (Some(_), Some(right)) => {
+                    //println!("2");
+                    // We want to replace `node` with it's successor, that is the
+                    // next largest value in the tree. Since the `node` has right
+                    // child it's successor is the minimum of it's right subtree.
+                    // (See successor method for more details about it).
+                    //
+                    // This can be effectively seen as removing min and replacing
+                    // node with it. In order to fix up red-black properties
+                    // we need to start from the bottom most changed node.
+                    // That is the location of min. So in short for the purposes of
+                    // delete_fixup we remove min.
+                    let mut min = Self::min_of(right);
+                    to_remove_orig_color = min.color();
+                    replacement = min.right();
+                    replacement_parent = Some(min);
+
+                    // Now we want to replace `node` with `min`.
+                    // There are two cases:
+                    //  a) `min` is the right child of `node`,
+                    //     in which case we simply replace `node` with `min`
+                    //     and reconnect `node.left` to `min.left`
+                    //     for example remove 20, 75, 77 from tree above
+                    //  b) `min` is not the right child of `node`
+                    //     in which case we first replace `min` by it's own right child.
+                    //     Note that `min` cannot have a left child, thus by this replace
+                    //     we are removing ´min´ from the tree so that we could replace
+                    //     `node` with `min`
+                    //     for example remove 9 from tree above, min will be 12
+
+                    if !ptr::eq(min.as_ptr(), right.as_ptr()) {
+                        // b)
+                        self.replace_subtree(min, min.right());
+                        replacement_parent = min.parent();
+                        // min will replace node, so min's right must point to node's right
+                        min.set_right(node.right());
+                        // atm `min.right.parent` points to the `node`, but it must point to `min`
+                        min.right().unwrap().set_parent(Some(min));
+                    }
+
+                    self.replace_subtree(node, Some(min));
+                    // min replaced node, so min's left must point to node's left
+                    min.set_left(node.left());
+                    // atm `min.left.parent` points to the `node`, but it must point to `min`
+                    min.left().unwrap().set_parent(Some(min));
+                    min.set_color(node.color());
+                    min.set_size(node.size());
+                }
+            }
+
+            // One node was physically removed below `replacement_parent`
+            // (if `min` replaced `node`, it's the old position of `min`)
+            self.decrement_sizes_from(replacement_parent);
+
+            if to_remove_orig_color.is_black() {
+                self.delete_fixup(replacement, replacement_parent);
+            }
+
+            self.len -= 1;
+            self.free_node(node)
+        }
+    }
+
+    fn delete_fixup(&mut self, mut x: Option<RawNode<K, V>>, mut parent: Option<RawNode<K, V>>) {
+        // x points to the place where we removed the node which was black
+        // x itself can be either red or black.
+        //
+        // If x is red then we moved one red node up from down the tree and
+        // thus the number of black nodes on subtrees of x didn't change.
+        // But we removed one black node from one of the subtrees of x's parent.
+        // We can simply fix it by coloring x black again.
+        //
+        // If x is black and root then we simply color it black.
+        // If x was not the root we enter the loop ...
+        unsafe {
+            while x.map(|n| n.color()).unwrap_or(Color::Black).is_black() && parent.is_some() {
+                // ...
+                // At this point following holds:
+                // * x is not root
+                // * x is doubly black
+                //   This means that "same black height" property holds but we may
+                //   violate "node must be either red or black", "root is black"
+                //   or "red parent has black children" properties.
+                // * x can be self.sentinel or a proper node
+                // * x must have a sibling
+                //   If x == self.sentinel x.parent has one child,
+                //   otherwise parent must have both children.
+                //
+                //   This is because otherwise the black heights from x.parent
+                //   down to the leaves cannot be equal. The shortest black height
+                //   from x.p -> x is 2 if x == self.sentinel. If the sibling is self.sentinel then
+                //   there is no way for the black height from x.p -> x_sibling to equal 2.
+                //   This violates the "same black height" red-black property (which holds) and
+                //   thus x_sibling cannot be self.sentinel.
+                let mut x_parent = parent.unwrap();
+                debug_assert!(
+                    x_parent.left().is_some() || x_parent.right().is_some(),
+                    "x.parent should have at least one child"
+                );
+
+                let is_x_none = x.is_none();
+                // Note that if is_x_none == true then x.pos() doesn't return the real
+                // position of x but the position of which child the parent has.
+                // So (x.pos() == Right, is_x_none == true) says that x.parent has only a right child.
+                let pos = x.map(|x| x.pos()).unwrap_or_else(|| match x_parent.left() {
+                    Some(_) => NodePos::Left,
+                    None => NodePos::Right,
+                });
+                match (pos, is_x_none) {
+                    (NodePos::Root, _) => unreachable!(),
+                    (NodePos::Left, false) | (NodePos::Right, true) => {
+                        // x in the left child and the parent has both children
+                        // or x is sentinel and parent has only a right child
+                        let mut x_sibling = x_parent.right().unwrap();
+                        if cfg!(debug_assertions) {
+                            if is_x_none {
+                                assert!(x_parent.left().is_none());
+                                assert!(x_parent.right().is_some());
+                            } else {
+                                assert!(x_parent.left().is_some());
+                                assert!(x_parent.right().is_some());
+                                assert!(
+                                    !ptr::eq(x_sibling.as_ptr(), x.unwrap().as_ptr()),
+                                    "w should not be equal to x"
+                                );
+                            }
+                        }
+
+                        if x_sibling.color().is_red() {
+                            //println!("L: case 1");
+                            //
+                            //     ┌─── p:b ───┐                ┌─── p:r ───┐                    ┌─── s:b ───┐
+                            //     │           │                │           │                    │           │
+                            // ┌─ x:b ─┐   ┌─ s:r ─┐   ──►  ┌─ x:b ─┐   ┌─ s:b ─┐   ──►      ┌─ p:r ─┐      d:b
+                            // │       │   │       │        │       │   │       │            │       │
+                            // a       b  c:b     d:b       a       b  c:b     d:b       ┌─ x:b ─┐  c:b
+                            //                                                           │       │
+                            //                                                           a       b
+                            // As a result turns into case 2, 3 or 4 depending on the color of node c's children.
+                            // We haven't created any more issues but all paths through x still have a missing black node.
+                            // However x has gained a red parent and the cases 2, 3 or 4 below will fix the tree.
+
+                            // Parent must be black because we haven't changed the color of parent and sibling yet
+                            // and thus parent must be black to have a red child.
+                            //
+                            // The sibling must have both children to satisfy the "same black height property".
+                            // The argument goes same as the one above the loop, except if the sibling is red then
+                            // the black nodes must be it's children.
+                            debug_assert!(x_parent.color().is_black());
+                            debug_assert!(x_sibling.left().is_some());
+                            debug_assert!(x_sibling.right().is_some());
+                            x_sibling.set_color(Color::Black);
+                            x_parent.set_color(Color::Red);
+                            self.rotate_left(x_parent);
+                            x_sibling = x_parent.right().unwrap();
+                        }
+
+                        debug_assert!(x_sibling.color().is_black());
+
+                        let sibling_left_color =
+                            x_sibling.left().map(|n| n.color()).unwrap_or(Color::Black);
+                        let sibling_right_color =
+                            x_sibling.right().map(|n| n.color()).unwrap_or(Color::Black);
+
+                        if sibling_left_color.is_black() && sibling_right_color.is_black() {
+                            // println!("L: case 2");
+                            // Take off the extra black from x and x's sibling and put it on x's parent.
+                            // That is move the extra black up the tree until we can totally remove it in next iterations.
+                            //
+                            //     ┌─── p:c ───┐                ┌─── p:c ───┐
+                            //     │           │                │           │
+                            // ┌─ x:b ─┐   ┌─ s:b ─┐   ──►  ┌─ x:b ─┐   ┌─ s:r ─┐
+                            // │       │   │       │        │       │   │       │
+                            // a       b  c:b     d:b       a       b  c:b     d:b
+                            //
+                            // If we came here from case 1 then the loop will terminate (also if the parent was just red)
+                            // because parent is red and thus x will be red at next iteration.
+                            // The issue with red parent having a red child will be fixed after the loop
+                            // by coloring x black.
+                            // After coloring parent black all paths going through x will gain an extra black node
+                            // that was taken away by the remove operations. All paths through node s keep the same number
+                            // of black nodes because we took one away from s but added one to p.
+
+                            x_sibling.set_color(Color::Red);
+                            x = Some(x_parent);
+                            parent = x_parent.parent();
+                        } else {
+                            if sibling_left_color.is_red() {
+                                //println!("L: case 3");
+                                //
+                                //    ╭───── p:c ─────┐                ┌───── p:c ─────┐                ┌─── p:c ───┐
+                                //    │               │                │               │                │           │
+                                // ┌─ x:b ─┐      ┌─ s:b ─┐   ──►  ┌─ x:b ─┐       ┌─ s:r ─┐   ──►  ┌─ x:b ─┐   ┌─ c:b ─┐
+                                // │       │      │       │        │       │       │       │        │       │   │       │
+                                // a       b  ┌─ c:r ─┐  d:b       a       b   ┌─ c:b ─┐   d:b      a       b   e   ┌─ s:r ─┐
+                                //            │       │                        │       │                            │       │
+                                //            e       f                        e       f                            f      d:b
+                                //
+                                // Turns into case 4, all paths to leaves keep the same number of black nodes as was before,
+                                // that is paths through x still have one missing black node compared to other paths.
+
+                                // sibling must have a left child because it is red
+                                x_sibling.left().unwrap().set_color(Color::Black);
+                                x_sibling.set_color(Color::Red);
+                                self.rotate_right(x_sibling);
+                                x_sibling = x_parent.right().unwrap();
+                            }
+
+                            // println!("L: case 4");
+                            //
+                            //     ┌─── p:c ───┐                ┌─── p:b ───┐                     ┌── s:c ──┐
+                            //     │           │                │           │                     │         │
+                            // ┌─ x:b ─┐   ┌─ s:b ─┐   ──►  ┌─ x:b ─┐   ┌─ s:c ─┐   ──►       ┌─ p:b ─┐    d:b
+                            // │       │   │       │        │       │   │       │             │       │
+                            // a       b  c:b     d:r       a       b  c:b     d:b       ┌─ x:b ─┐   c:b
+                            //                                                           │       │
+                            //                                                           a       b
+                            //
+                            // This will terminate the loop because the root of the tree is the same color as it was,
+                            // (so it must match with red-black tree properties)
+                            // but x has an extra black ancestor (either p became black or s was added as black grandparent).
+                            // Thus the paths going through x have gained one extra black node which was missing.
+                            // The lost black on paths through d is accounted by recoloring d black. All other path
+                            // keep the number of black nodes.
+
+                            x_sibling.set_color(x_parent.color());
+                            x_parent.set_color(Color::Black);
+                            // sibling must have a right child because it is red
+                            x_sibling.right().unwrap().set_color(Color::Black);
+                            self.rotate_left(x_parent);
+                            break;
+                        }
+                    }
+                    (NodePos::Left, true) | (NodePos::Right, false) => {
+                        // x is the right child and the parent has both children
+                        // or x is sentinel and parent has only a left child
+
+                        let mut x_sibling = x_parent.left().unwrap();
+                        if cfg!(debug_assertions) {
+                            if is_x_none {
+                                assert!(x_parent.left().is_some());
+                                assert!(x_parent.right().is_none());
+                            } else {
+                                assert!(x_parent.left().is_some());
+                                assert!(x_parent.right().is_some());
+                                assert!(!ptr::eq(x_sibling.as_ptr(), x.unwrap().as_ptr()));
+                            }
+                        }
+
+                        if x_sibling.color().is_red() {
+                            //println!("R: case 1");
+                            x_sibling.set_color(Color::Black);
+                            x_parent.set_color(Color::Red);
+                            self.rotate_right(x_parent);
+                            x_sibling = x_parent.left().unwrap();
+                        }
+
+                        let sibling_left_color =
+                            x_sibling.left().map(|n| n.color()).unwrap_or(Color::Black);
+                        let sibling_right_color =
+                            x_sibling.right().map(|n| n.color()).unwrap_or(Color::Black);
+
+                        if sibling_left_color.is_black() && sibling_right_color.is_black() {
+                            // println!("R: case 2");
+                            x_sibling.set_color(Color::Red);
+                            x = Some(x_parent);
+                            parent = x_parent.parent();
+                        } else {
+                            if sibling_left_color.is_black() {
+                                // println!("R: case 3");
+                                x_sibling.right().unwrap().set_color(Color::Black);
+                                x_sibling.set_color(Color::Red);
+                                self.rotate_left(x_sibling);
+                                x_sibling = x_parent.left().unwrap();
+                            }
+
+                            // println!("R:case 4");
+                            x_sibling.set_color(x_parent.color());
+                            x_parent.set_color(Color::Black);
+                            x_sibling.left().unwrap().set_color(Color::Black);
+                            self.rotate_right(x_parent);
+                            break;
+                        }
+                    }
+                }
+            }
+            if let Some(mut x) = x {
+                x.set_color(Color::Black);
+            }
+        }
+    }
+
+    /// Moves all items with keys `>= key` into a new tree.
+    ///
+    /// Implemented by cutting the tree along the search path of `key` and
+    /// joining the pieces back together in O(log n).
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        if self.is_empty() {
+            return Self::new();
+        }
+
+        // Collect the search path first, after this no user code (eg `Q::cmp`)
+        // is called, so we cannot leave the tree in an inconsistent state.
+        let mut path = Stack::new();
+        let mut black_height = self.black_height();
+        let mut maybe_node = Some(self.root);
+        while let Some(node) = maybe_node {
+            unsafe {
+                let goes_right = node.key().borrow() >= key;
+                path.push((node, black_height, goes_right));
+                if node.color().is_black() {
+                    black_height -= 1;
+                }
+                maybe_node = if goes_right {
+                    node.left()
+                } else {
+                    node.right()
+                };
+            }
+        }
+
+        // Walk back up the path. Every node on it, together with its subtree
+        // on the other side of the path, is joined to the matching half.
+        let mut left = Subtree::empty();
+        let mut right = Subtree::empty();
+        while let Some((node, black_height, goes_right)) = path.pop() {
+            unsafe {
+                let child_black_height = black_height - node.color().is_black() as usize;
+                if goes_right {
+                    let r = Subtree::detach(node.right(), child_black_height);
+                    right = Self::join(self.update, right, node, r);
+                } else {
+                    let l = Subtree::detach(node.left(), child_black_height);
+                    left = Self::join(self.update, l, node, left);
+                }
+            }
+        }
+
+        self.root = left.root.unwrap_or_else(RawNode::dangling);
+        self.len = Self::size_of(left.root);
+        Self {
+            root: right.root.unwrap_or_else(RawNode::dangling),
+            len: Self::size_of(right.root),
+            update: self.update,
+            pool: self.pool.as_ref().map(|_| NodePool::new()),
+            marker: PhantomData,
+        }
+    }
+
+    /// Moves all items from `other` into `self`, leaving `other` empty.
+    ///
+    /// If all keys of one tree are smaller than all keys of the other, the trees
+    /// are joined in O(log n). Otherwise the items of `other` are inserted one by one
+    /// and the values of `other` replace the values of equal keys in `self`.
+    pub fn append(&mut self, other: &mut Self)
+    where
+        K: Ord,
+    {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            mem::swap(&mut self.root, &mut other.root);
+            mem::swap(&mut self.len, &mut other.len);
+            return;
+        }
+
+        let (self_min, self_max) = self.min_max_nodes();
+        let (other_min, other_max) = other.min_max_nodes();
+        let (mut low, mut high) = unsafe {
+            if self_max.unwrap().key() < other_min.unwrap().key() {
+                (self.take_items(), other.take_items())
+            } else if other_max.unwrap().key() < self_min.unwrap().key() {
+                (other.take_items(), self.take_items())
+            } else {
+                for (k, v) in other.take_items() {
+                    self.insert(k, v);
+                }
+                return;
+            }
+        };
+
+        let len = low.len + high.len;
+        // The smallest item of `high` is used as the middle node of the join
+        let (key, value) = high.pop_first().unwrap();
+        let mid = self.alloc_node(Node {
+            key,
+            value,
+            color: Color::Red,
+            size: 1,
+            parent: None,
+            left: None,
+            right: None,
+        });
+
+        let low_tree = Subtree::take_from(&mut low);
+        let high_tree = Subtree::take_from(&mut high);
+        let joined = unsafe { Self::join(self.update, low_tree, mid, high_tree) };
+        self.root = joined.root.unwrap();
+        self.len = len;
+    }
+
+    /// Returns the item with `rank`, that is the `rank`-th smallest item (starting from 0).
+    ///
+    /// O(log n)
+    pub fn select(&self, rank: usize) -> Option<(&K, &V)> {
+        if rank >= self.len {
+            return None;
+        }
+
+        let mut rank = rank;
+        let mut node = self.root;
+        loop {
+            unsafe {
+                let left_size = Self::size_of(node.left());
+                match rank.cmp(&left_size) {
+                    std::cmp::Ordering::Less => node = node.left()?,
+                    std::cmp::Ordering::Equal => return Some(node.as_refs()),
+                    std::cmp::Ordering::Greater => {
+                        rank -= left_size + 1;
+                        node = node.right()?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the number of items with smaller keys than `key`, if `key` is in the tree.
+    ///
+    /// O(log n)
+    pub fn rank<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut node = self.get_raw(key)?;
+        unsafe {
+            // everything in the left subtree is smaller, and moving up from a right
+            // child, the parent and its left subtree are also smaller
+            let mut rank = Self::size_of(node.left());
+            while let Some(parent) = node.parent() {
+                if parent.right() == Some(node) {
+                    rank += Self::size_of(parent.left()) + 1;
+                }
+                node = parent;
+            }
+            Some(rank)
+        }
+    }
+
+    fn size_of(node: Link<K, V>) -> usize {
+        node.map_or(0, |node| unsafe { node.size() })
+    }
+
+    /// Adds `n` to the sizes of `from` and all its ancestors.
+    unsafe fn add_to_sizes(&self, mut from: Link<K, V>, n: usize) {
+        while let Some(mut node) = from {
+            unsafe {
+                node.set_size(node.size() + n);
+                self.update_node(node);
+                from = node.parent();
+            }
+        }
+    }
+
+    /// Subtracts one from the sizes of `from` and all its ancestors.
+    unsafe fn decrement_sizes_from(&self, mut from: Link<K, V>) {
+        while let Some(mut node) = from {
+            unsafe {
+                node.set_size(node.size() - 1);
+                self.update_node(node);
+                from = node.parent();
+            }
+        }
+    }
+
+    /// Calls the node update hook on `from` and all its ancestors.
+    unsafe fn update_path(&self, mut from: Link<K, V>) {
+        if self.update.is_none() {
+            return;
+        }
+        while let Some(node) = from {
+            unsafe {
+                self.update_node(node);
+                from = node.parent();
+            }
+        }
+    }
+
+    /// Calls the node update hook on `node`, its children must already be up to date.
+    unsafe fn update_node(&self, mut node: RawNode<K, V>) {
+        if let Some(update) = self.update {
+            unsafe {
+                let left = node.left().map(|l| l.as_refs().1);
+                let right = node.right().map(|r| r.as_refs().1);
+                let (key, value) = node.as_muts();
+                update(key, value, left, right);
+            }
+        }
+    }
+
+    /// Checks that the tree is a valid red-black tree.
+    ///
+    /// Validates the ordering of the keys, the red-black coloring rules,
+    /// that all paths have equal black heights, the parent links, subtree sizes and the length.
+    /// Returns the first violation found.
+    ///
+    /// O(n)
+    pub fn check_invariants(&self) -> Result<(), InvariantError>
+    where
+        K: Ord,
+    {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        unsafe {
+            if self.root.parent().is_some() {
+                return Err(InvariantError::ParentLink { index: None });
+            }
+            if self.root.color().is_red() {
+                return Err(InvariantError::RedRoot);
+            }
+        }
+
+        let mut expected_black_height = None;
+        let mut check_leaf = |black_height: usize| match expected_black_height {
+            None => {
+                expected_black_height = Some(black_height);
+                Ok(())
+            }
+            Some(expected) if expected == black_height => Ok(()),
+            Some(expected) => Err(InvariantError::BlackHeight {
+                expected,
+                found: black_height,
+            }),
+        };
+
+        // Iterative in-order traversal, every node on the stack is
+        // stored with the number of black nodes from the root to it.
+        let mut stack = Stack::new();
+        let mut maybe_node = Some(self.root);
+        let mut black_height = 0;
+        let mut prev: Option<RawNode<K, V>> = None;
+        let mut index = 0;
+        loop {
+            while let Some(node) = maybe_node {
+                black_height += unsafe { node.color().is_black() } as usize;
+                stack.push((node, black_height));
+                maybe_node = unsafe { node.left() };
+            }
+            // `maybe_node` is the empty left child of the last pushed node
+            // or the empty right child of the last visited node
+            check_leaf(black_height)?;
+
+            let Some((node, node_black_height)) = stack.pop() else {
+                break;
+            };
+
+            unsafe {
+                if let Some(prev) = prev {
+                    if prev.key() >= node.key() {
+                        return Err(InvariantError::Order { index });
+                    }
+                }
+                for child in [node.left(), node.right()].into_iter().flatten() {
+                    if child.parent() != Some(node) {
+                        return Err(InvariantError::ParentLink { index: Some(index) });
+                    }
+                    if node.color().is_red() && child.color().is_red() {
+                        return Err(InvariantError::RedChildOfRed { index });
+                    }
+                }
+                let expected_size = Self::size_of(node.left()) + Self::size_of(node.right()) + 1;
+                if node.size() != expected_size {
+                    return Err(InvariantError::Size {
+                        index,
+                        expected: expected_size,
+                        found: node.size(),
+                    });
+                }
+
+                prev = Some(node);
+                index += 1;
+                black_height = node_black_height;
+                maybe_node = node.right();
+            }
+        }
+
+        if index != self.len {
+            return Err(InvariantError::Len {
+                expected: index,
+                found: self.len,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Number of black nodes on any path from the root to a leaf.
+    fn black_height(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+
+        let mut count = 0;
+        let mut maybe_node = Some(self.root);
+        while let Some(node) = maybe_node {
+            unsafe {
+                count += node.color().is_black() as usize;
+                maybe_node = node.left();
+            }
+        }
+        count
+    }
+
+    /// Joins `left`, `mid` and `right` into a single red-black tree.
+    ///
+    /// The middle node is linked into the taller tree at the level where the black
+    /// heights match, which makes it O(|bh(left) - bh(right)| + 1).
+    ///
+    /// # SAFETY
+    ///
+    /// * all keys in `left` must be smaller than `mid.key`, which must be smaller than all keys in `right`
+    /// * `left` and `right` must be valid red-black trees with black roots, no parents
+    ///   and correct black heights
+    /// * `mid` must be a valid node not part of any tree
+    unsafe fn join(
+        update: Option<NodeUpdate<K, V>>,
+        left: Subtree<K, V>,
+        mut mid: RawNode<K, V>,
+        right: Subtree<K, V>,
+    ) -> Subtree<K, V> {
+        // Used for rotations and node updates, it doesn't own the nodes
+        let mut tree = ManuallyDrop::new(Self {
+            root: RawNode::dangling(),
+            len: 0,
+            update,
+            pool: None,
+            marker: PhantomData,
+        });
+
+        unsafe {
+            mid.set_parent(None);
+            if left.black_height == right.black_height {
+                mid.set_color(Color::Black);
+                mid.set_size(Self::size_of(left.root) + Self::size_of(right.root) + 1);
+                Self::link_children(mid, left.root, right.root);
+                tree.update_node(mid);
+                return Subtree {
+                    root: Some(mid),
+                    black_height: left.black_height + 1,
+                };
+            }
+
+            mid.set_color(Color::Red);
+            let taller_is_left = left.black_height > right.black_height;
+            let (taller, shorter) = if taller_is_left {
+                (left, right)
+            } else {
+                (right, left)
+            };
+
+            // Descend the inner spine of the taller tree (right spine if it's on the left)
+            // until we find a black node with the same black height as the shorter tree.
+            // Its place is taken by `mid`, which gets the found node and the shorter tree
+            // as children. This keeps the black heights unchanged,
+            // but `mid` may now be a red child of a red parent.
+            let mut parent = None;
+            let mut maybe_node = taller.root;
+            let mut black_height = taller.black_height;
+            while let Some(node) = maybe_node {
+                if node.color().is_black() {
+                    if black_height == shorter.black_height {
+                        break;
+                    }
+                    black_height -= 1;
+                }
+                parent = Some(node);
+                maybe_node = if taller_is_left {
+                    node.right()
+                } else {
+                    node.left()
+                };
+            }
+
+            // `taller` has larger black height, so its root cannot be the found node
+            let mut parent = parent.unwrap();
+            mid.set_parent(Some(parent));
+            if taller_is_left {
+                Self::link_children(mid, maybe_node, shorter.root);
+                parent.set_right(Some(mid));
+            } else {
+                Self::link_children(mid, shorter.root, maybe_node);
+                parent.set_left(Some(mid));
+            }
+            // `mid` took the place of the found node and the ancestors of `mid` gained
+            // `mid` and the shorter tree, there are as many of them as we descended
+            mid.set_size(Self::size_of(maybe_node) + Self::size_of(shorter.root) + 1);
+            tree.update_node(mid);
+            tree.add_to_sizes(Some(parent), Self::size_of(shorter.root) + 1);
+
+            tree.root = taller.root.unwrap();
+            let grew = tree.insert_fixup(mid);
+            Subtree {
+                root: Some(tree.root),
+                black_height: taller.black_height + grew as usize,
+            }
+        }
+    }
+
+    unsafe fn link_children(mut node: RawNode<K, V>, left: Link<K, V>, right: Link<K, V>) {
+        unsafe {
+            node.set_left(left);
+            node.set_right(right);
+            if let Some(mut left) = left {
+                left.set_parent(Some(node));
+            }
+            if let Some(mut right) = right {
+                right.set_parent(Some(node));
+            }
+        }
+    }
+
+    /// Replaces subtree `old` with subtree `new`
+    unsafe fn replace_subtree(&mut self, old: RawNode<K, V>, new: Option<RawNode<K, V>>) {
+        // We need to do two things:
+        //  a) make the parent of `old` point to `new` instead of `old`,
+        //     if `old` doesn't have parents it must have been the root which
+        //     means that `new` will be the new root
+        //  b) make `new` point to the parent of `old`
+
+        unsafe {
+            // a)
+            match old.pos() {
+                NodePos::Root => {
+                    self.root = match new {
+                        Some(new) => new,
+                        None => RawNode::dangling(),
+                    }
+                }
+                NodePos::Left => old.parent().unwrap().set_left(new),
+                NodePos::Right => old.parent().unwrap().set_right(new),
+            }
+
+            // b)
+            if let Some(mut new) = new {
+                new.set_parent(old.parent());
+            }
+        }
+    }
+}
+
+/// Violation of the red-black tree invariants, returned by [`RbTreeMap::check_invariants`].
+///
+/// `index` is the position of the offending node in key order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    RedRoot,
+    RedChildOfRed {
+        index: usize,
+    },
+    /// Paths from the root to the leaves have different numbers of black nodes
+    BlackHeight {
+        expected: usize,
+        found: usize,
+    },
+    /// Child doesn't point back to its parent, `None` if the root has a parent
+    ParentLink {
+        index: Option<usize>,
+    },
+    /// Key is not larger than the previous key
+    Order {
+        index: usize,
+    },
+    /// Stored subtree size doesn't match the number of nodes in the subtree
+    Size {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// `len` doesn't match the number of nodes in the tree
+    Len {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RedRoot => write!(f, "root is red"),
+            Self::RedChildOfRed { index } => {
+                write!(f, "red node at index {index} has a red child")
+            }
+            Self::BlackHeight { expected, found } => write!(
+                f,
+                "black heights differ, found a path with {found} black nodes, expected {expected}"
+            ),
+            Self::ParentLink { index: Some(index) } => {
+                write!(f, "child of node at index {index} has a wrong parent link")
+            }
+            Self::ParentLink { index: None } => write!(f, "root has a parent"),
+            Self::Order { index } => {
+                write!(
+                    f,
+                    "key at index {index} is not larger than the previous key"
+                )
+            }
+            Self::Size {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "node at index {index} has size {found}, expected {expected}"
+            ),
+            Self::Len { expected, found } => {
+                write!(f, "tree has {expected} nodes but len is {found}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+/// Detached part of a red-black tree used by `split_off` and `append`.
+struct Subtree<K, V> {
+    // INVARIANTS:
+    //  * root is black and has no parent
+    root: Link<K, V>,
+    black_height: usize,
+}
+
+impl<K, V> Subtree<K, V> {
+    fn empty() -> Self {
+        Self {
+            root: None,
+            black_height: 0,
+        }
+    }
+
+    /// Detaches the subtree rooted at `root` from its parent, making the root black if needed.
+    ///
+    /// # SAFETY
+    ///
+    /// * `root` must be a valid node, `black_height` must be its black height
+    unsafe fn detach(root: Link<K, V>, black_height: usize) -> Self {
+        match root {
+            Some(mut root) => unsafe {
+                root.set_parent(None);
+                let black_height = if root.color().is_red() {
+                    root.set_color(Color::Black);
+                    black_height + 1
+                } else {
+                    black_height
+                };
+                Self {
+                    root: Some(root),
+                    black_height,
+                }
+            },
+            None => Self::empty(),
+        }
+    }
+
+    /// Takes all the nodes from `tree` leaving it empty.
+    fn take_from(tree: &mut RbTreeMap<K, V>) -> Self {
+        let black_height = tree.black_height();
+        let root = (!tree.is_empty()).then_some(tree.root);
+        tree.root = RawNode::dangling();
+        tree.len = 0;
+        Self { root, black_height }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> serde::Serialize for RbTreeMap<K, V>
+where
+    K: serde::Serialize,
+    V: serde::Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> serde::Deserialize<'de> for RbTreeMap<K, V>
+where
+    K: serde::Deserialize<'de> + Ord,
+    V: serde::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct MapVisitor<K, V>(PhantomData<(K, V)>);
+
+        impl<'de, K, V> serde::de::Visitor<'de> for MapVisitor<K, V>
+        where
+            K: serde::Deserialize<'de> + Ord,
+            V: serde::Deserialize<'de>,
+        {
+            type Value = RbTreeMap<K, V>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a map")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::MapAccess<'de>,
+            {
+                // Don't trust the size hint blindly, malicious input could make us
+                // allocate huge buffers up front
+                const MAX_PREALLOC: usize = 4096;
+                let cap = map.size_hint().unwrap_or(0).min(MAX_PREALLOC);
+                let mut items = Vec::with_capacity(cap);
+                while let Some(entry) = map.next_entry()? {
+                    items.push(entry);
+                }
+                // Serialized trees are sorted, so this builds the tree in linear time
+                Ok(RbTreeMap::bulk_load(items))
+            }
+        }
+
+        deserializer.deserialize_map(MapVisitor(PhantomData))
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for RbTreeMap<K, V>
+where
+    K: Ord,
+{
+    /// Builds a balanced tree from the items, if there are duplicate keys, the last one wins.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self::bulk_load(iter.into_iter().collect())
+    }
+}
+
+impl<K, V> Extend<(K, V)> for RbTreeMap<K, V>
+where
+    K: Ord,
+{
+    /// Inserts all items, the values of equal keys are replaced.
+    ///
+    /// The items are sorted first. If their keys are all smaller or larger than
+    /// the keys already in the tree, they are built into a balanced tree and
+    /// joined with `self`. Otherwise they are inserted one by one.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let mut items: Vec<(K, V)> = iter.into_iter().collect();
+        if items.is_empty() {
+            return;
+        }
+
+        // stable sort keeps the order of duplicates, so the last one still wins
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        // `bulk_load` doesn't call the node update hook
+        if self.update.is_none() {
+            let disjoint = match (self.first_key_value(), self.last_key_value()) {
+                (Some((min, _)), Some((max, _))) => {
+                    &items[items.len() - 1].0 < min || &items[0].0 > max
+                }
+                _ => true,
+            };
+            if disjoint {
+                let mut other = Self::bulk_load(items);
+                self.append(&mut other);
+                return;
+            }
+        }
+
+        for (k, v) in items {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<'a, K, V> Extend<(&'a K, &'a V)> for RbTreeMap<K, V>
+where
+    K: Ord + Copy,
+    V: Copy,
+{
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(k, v)| (*k, *v)));
+    }
+}
+
+impl<K, V> IntoIterator for RbTreeMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { tree: self }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a RbTreeMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut RbTreeMap<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// In-order iterator over the items of `RbTreeMap`, created by [`RbTreeMap::iter`].
+pub struct Iter<'a, K, V> {
+    // INVARIANTS:
+    //  * if `len > 0` then `front` and `back` are `Some` and point to the
+    //    next items to be returned from the front and back respectively
+    front: Link<K, V>,
+    back: Link<K, V>,
+    len: usize,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.front?;
+        self.len -= 1;
+        unsafe {
+            self.front = RbTreeMap::successor_core(node);
+            Some(node.as_refs())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.back?;
+        self.len -= 1;
+        unsafe {
+            self.back = RbTreeMap::predecessor_core(node);
+            Some(node.as_refs())
+        }
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            front: self.front,
+            back: self.back,
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Mutable in-order iterator over the items of `RbTreeMap`, created by [`RbTreeMap::iter_mut`].
+pub struct IterMut<'a, K, V> {
+    // INVARIANTS: see `Iter`
+    front: Link<K, V>,
+    back: Link<K, V>,
+    len: usize,
+    marker: PhantomData<&'a mut Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let mut node = self.front?;
+        self.len -= 1;
+        // SAFETY: front and back never cross, so each node is returned only once
+        unsafe {
+            self.front = RbTreeMap::successor_core(node);
+            Some(node.as_muts())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let mut node = self.back?;
+        self.len -= 1;
+        // SAFETY: front and back never cross, so each node is returned only once
+        unsafe {
+            self.back = RbTreeMap::predecessor_core(node);
+            Some(node.as_muts())
+        }
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+macro_rules! impl_projection_iter {
+    ($name:ident, $item:ty, $proj:expr) => {
+        impl<'a, K, V> Iterator for $name<'a, K, V> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next().map($proj)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.0.size_hint()
+            }
+        }
+
+        impl<K, V> DoubleEndedIterator for $name<'_, K, V> {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.0.next_back().map($proj)
+            }
+        }
+
+        impl<K, V> ExactSizeIterator for $name<'_, K, V> {}
+    };
+}
+
+/// Iterator over the keys of `RbTreeMap`, created by [`RbTreeMap::keys`].
+pub struct Keys<'a, K, V>(Iter<'a, K, V>);
+
+impl_projection_iter!(Keys, &'a K, |(k, _)| k);
+
+/// Iterator over the values of `RbTreeMap`, created by [`RbTreeMap::values`].
+pub struct Values<'a, K, V>(Iter<'a, K, V>);
+
+impl_projection_iter!(Values, &'a V, |(_, v)| v);
+
+/// Mutable iterator over the values of `RbTreeMap`, created by [`RbTreeMap::values_mut`].
+pub struct ValuesMut<'a, K, V>(IterMut<'a, K, V>);
+
+impl_projection_iter!(ValuesMut, &'a mut V, |(_, v)| v);
+
+/// Owning in-order iterator over the items of `RbTreeMap`.
+///
+/// Every returned item is unlinked from the tree and its node is freed immediately.
+pub struct IntoIter<K, V> {
+    // Remaining items are dropped by the tree
+    tree: RbTreeMap<K, V>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tree.pop_first()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.tree.len, Some(self.tree.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.tree.pop_last()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+/// Iterator over a range of items in `RbTreeMap`, created by [`RbTreeMap::range`].
+pub struct Range<'a, K, V> {
+    // INVARIANTS:
+    //  * `front` and `back` are either both `None` (iterator is exhausted)
+    //    or both `Some` and `front.key <= back.key`
+    front: Link<K, V>,
+    back: Link<K, V>,
+    marker: PhantomData<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.front?;
+        if self.back == Some(node) {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.front = unsafe { RbTreeMap::successor_core(node) };
+        }
+
+        unsafe { Some(node.as_refs()) }
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Range<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.back?;
+        if self.front == Some(node) {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.back = unsafe { RbTreeMap::predecessor_core(node) };
+        }
+
+        unsafe { Some(node.as_refs()) }
+    }
+}
+
+impl<K, V> Clone for Range<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            front: self.front,
+            back: self.back,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Mutable iterator over a range of items in `RbTreeMap`, created by [`RbTreeMap::range_mut`].
+pub struct RangeMut<'a, K, V> {
+    // INVARIANTS: see `Range`
+    front: Link<K, V>,
+    back: Link<K, V>,
+    marker: PhantomData<&'a mut Node<K, V>>,
+}
+
+impl<'a, K, V> Iterator for RangeMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut node = self.front?;
+        if self.back == Some(node) {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.front = unsafe { RbTreeMap::successor_core(node) };
+        }
+
+        // SAFETY: front and back never cross, so each node is returned only once
+        unsafe { Some(node.as_muts()) }
+    }
+}
+
+impl<K, V> DoubleEndedIterator for RangeMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let mut node = self.back?;
+        if self.front == Some(node) {
+            self.front = None;
+            self.back = None;
+        } else {
+            self.back = unsafe { RbTreeMap::predecessor_core(node) };
+        }
+
+        // SAFETY: front and back never cross, so each node is returned only once
+        unsafe { Some(node.as_muts()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestNode {
+        key: i32,
+        parent_k: Option<i32>,
+        left_k: Option<Box<TestNode>>,
+        right_k: Option<Box<TestNode>>,
+    }
+
+    impl TestNode {
+        fn leaf(key: i32, parent: i32) -> Self {
+            Self {
+                key,
+                parent_k: Some(parent),
+                left_k: None,
+                right_k: None,
+            }
+        }
+    }
+
+    impl PartialEq<Node<i32, i32>> for TestNode {
+        fn eq(&self, other: &Node<i32, i32>) -> bool {
+            if self.key != other.key {
+                return false;
+            }
+            match (&other.parent, &self.parent_k) {
+                (None, None) => {}
+                (None, Some(_)) => return false,
+                (Some(_), None) => return false,
+                (Some(actual), Some(expected)) => {
+                    if unsafe { expected != actual.key() } {
+                        return false;
+                    }
+                }
+            }
+
+            match (&other.left, &self.left_k) {
+                (None, None) => {}
+                (None, Some(_)) => return false,
+                (Some(_), None) => return false,
+                (Some(actual), Some(expected)) => {
+                    if unsafe { !expected.eq(actual.as_ref()) } {
+                        return false;
+                    }
+                }
+            }
+
+            match (&other.right, &self.right_k) {
+                (None, None) => {}
+                (None, Some(_)) => return false,
+                (Some(_), None) => return false,
+                (Some(actual), Some(expected)) => {
+                    if unsafe { !expected.eq(actual.as_ref()) } {
+                        return false;
+                    }
+                }
+            }
+
+            true
+        }
+    }
+
+    #[test]
+    fn test() {
+        let mut tree = RbTreeMap::new();
+        assert!(tree.is_empty());
+        tree.insert(12, 12);
+        assert_eq!(tree.len(), 1);
+        tree.insert(15, 15);
+        tree.check_invariants().unwrap();
+        tree.insert(14, 14);
+        tree.check_invariants().unwrap();
+        tree.insert(16, 16);
+        tree.check_invariants().unwrap();
+        println!("{tree:#?}");
+    }
+
+    #[test]
+    fn test_rotate_roundtrip() {
+        let mut tree = RbTreeMap::new();
+        assert!(tree.is_empty());
+        tree.insert_bst(12, 12);
+        tree.insert_bst(9, 9);
+        assert_eq!(tree.len(), 2);
+        tree.insert_bst(15, 15);
+        tree.insert_bst(14, 14);
+        tree.insert_bst(16, 16);
+
+        let expected0 = TestNode {
+            key: 12,
+            parent_k: None,
+            left_k: Some(Box::new(TestNode::leaf(9, 12))),
+            right_k: Some(Box::new(TestNode {
+                key: 15,
+                parent_k: Some(12),
+                left_k: Some(Box::new(TestNode::leaf(14, 15))),
+                right_k: Some(Box::new(TestNode::leaf(16, 15))),
+            })),
+        };
+        assert_eq!(&expected0, unsafe { tree.root.as_ref() });
+
+        tree.rotate_left(tree.root);
+        let expected1 = TestNode {
+            key: 15,
+            parent_k: None,
+            left_k: Some(Box::new(TestNode {
+                key: 12,
+                parent_k: Some(15),
+                left_k: Some(Box::new(TestNode::leaf(9, 12))),
+                right_k: Some(Box::new(TestNode::leaf(14, 12))),
+            })),
+            right_k: Some(Box::new(TestNode::leaf(16, 15))),
+        };
+        assert_eq!(&expected1, unsafe { tree.root.as_ref() });
+
+        tree.rotate_left(tree.root);
+        let expected2 = TestNode {
+            key: 16,
+            parent_k: None,
+            left_k: Some(Box::new(TestNode {
+                key: 15,
+                parent_k: Some(16),
+                left_k: Some(Box::new(TestNode {
+                    key: 12,
+                    parent_k: Some(15),
+                    left_k: Some(Box::new(TestNode::leaf(9, 12))),
+                    right_k: Some(Box::new(TestNode::leaf(14, 12))),
+                })),
+                right_k: None,
+            })),
+            right_k: None,
+        };
+        assert_eq!(&expected2, unsafe { tree.root.as_ref() });
+
+        let node = tree.get_raw(&12).unwrap();
+        tree.rotate_left(node);
+        let expected3 = TestNode {
+            key: 16,
+            parent_k: None,
+            left_k: Some(Box::new(TestNode {
+                key: 15,
+                parent_k: Some(16),
+                left_k: Some(Box::new(TestNode {
+                    key: 14,
+                    parent_k: Some(15),
+                    left_k: Some(Box::new(TestNode {
+                        key: 12,
+                        parent_k: Some(14),
+                        left_k: Some(Box::new(TestNode::leaf(9, 12))),
+                        right_k: None,
+                    })),
+                    right_k: None,
+                })),
+                right_k: None,
+            })),
+            right_k: None,
+        };
+        assert_eq!(&expected3, unsafe { tree.root.as_ref() });
+
+        let node = tree.get_raw(&14).unwrap();
+        tree.rotate_right(node);
+        assert_eq!(&expected2, unsafe { tree.root.as_ref() });
+
+        tree.rotate_right(tree.root);
+        assert_eq!(&expected1, unsafe { tree.root.as_ref() });
+
+        tree.rotate_right(tree.root);
+        assert_eq!(&expected0, unsafe { tree.root.as_ref() });
+    }
+
+    #[test]
+    fn inorder_for_each() {
+        let mut tree = RbTreeMap::new();
+        assert!(tree.is_empty());
+
+        let mut items = Vec::with_capacity(tree.len());
+        tree.inorder_for_each(|k, _| items.push(*k));
+        assert!(items.is_empty());
+
+        tree.insert(12, 12);
+        assert_eq!(tree.len(), 1);
+        tree.insert(5, 5);
+        tree.insert(9, 9);
+        tree.insert(2, 2);
+        tree.insert(18, 18);
+        assert_eq!(tree.len(), 5);
+        tree.insert(15, 15);
+        tree.insert(13, 13);
+        tree.insert(17, 17);
+        tree.insert(19, 19);
+
+        let mut items = Vec::with_capacity(tree.len());
+        tree.inorder_for_each(|k, _| items.push(*k));
+
+        assert_eq!(&items, &[2, 5, 9, 12, 13, 15, 17, 18, 19]);
+    }
+
+    #[test]
+    fn get() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.get(&4), None);
+
+        tree.insert(12, 12);
+        tree.insert(5, 5);
+        tree.insert(9, 9);
+        tree.insert(2, 2);
+        tree.insert(18, 18);
+        tree.insert(15, 15);
+        tree.insert(13, 13);
+        tree.insert(17, 17);
+        tree.insert(19, 19);
+
+        for it in [2, 5, 9, 18, 12, 15, 13, 17, 19] {
+            assert_eq!(tree.get(&it), Some((&it, &it)));
+        }
+    }
+
+    #[test]
+    fn min_max() {
+        let mut tree = RbTreeMap::new();
+        tree.insert(12, 12);
+        tree.insert(5, 5);
+        tree.insert(9, 9);
+        tree.insert(2, 2);
+        tree.insert(18, 18);
+        tree.insert(15, 15);
+        tree.insert(13, 13);
+        tree.insert(17, 17);
+        tree.insert(19, 19);
+
+        assert_eq!(tree.min(), Some((&2, &2)));
+        assert_eq!(tree.max(), Some((&19, &19)));
+    }
+
+    #[test]
+    fn successor() {
+        let mut tree = RbTreeMap::new();
+        tree.insert(12, 12);
+        tree.insert(5, 5);
+        tree.insert(9, 9);
+        tree.insert(2, 2);
+        tree.insert(18, 18);
+        tree.insert(15, 15);
+        tree.insert(13, 13);
+        tree.insert(17, 17);
+        tree.insert(19, 19);
+
+        for it in [2, 5, 9, 12, 13, 15, 17, 18, 19].windows(2) {
+            let key = it[0];
+            let result = it[1];
+            assert_eq!(tree.successor(&key), Some((&result, &result)));
+        }
+
+        assert_eq!(tree.successor(&19), None);
+    }
+
+    #[test]
+    fn predecessor() {
+        let mut tree = RbTreeMap::new();
+        tree.insert(12, 12);
+        tree.insert(5, 5);
+        tree.insert(9, 9);
+        tree.insert(2, 2);
+        tree.insert(18, 18);
+        tree.insert(15, 15);
+        tree.insert(13, 13);
+        tree.insert(17, 17);
+        tree.insert(19, 19);
+
+        for it in [2, 5, 9, 12, 13, 15, 17, 18, 19].windows(2) {
+            let key = it[1];
+            let result = it[0];
+            assert_eq!(tree.predecessor(&key), Some((&result, &result)));
+        }
+
+        assert_eq!(tree.predecessor(&2), None);
+    }
+
+    #[test]
+    fn delete() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.get(&4), None);
+
+        tree.insert(12, 12);
+        tree.insert(5, 5);
+        tree.insert(9, 9);
+        tree.insert(2, 2);
+        tree.insert(18, 18);
+        tree.insert(15, 15);
+        tree.insert(13, 13);
+        tree.insert(17, 17);
+        tree.insert(19, 19);
+
+        for it in [2, 5, 9, 18, 12, 15, 13, 17, 19] {
+            assert_eq!(tree.delete(&it), Some((it, it)));
+            if !tree.is_empty() {
+                tree.check_invariants().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn delete2() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.get(&4), None);
+        let inserts = [26, 81, 303, 0];
+        for i in inserts {
+            tree.insert(i, i);
+        }
+
+        for it in [26, 81, 303, 0] {
+            assert_eq!(tree.delete(&it), Some((it, it)));
+            if !tree.is_empty() {
+                tree.check_invariants().unwrap();
+            }
+
+            //println!("{tree:#?}");
+        }
+    }
+
+    #[test]
+    fn delete3() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.get(&4), None);
+        let inserts = [3836, 3865, 4173, 1635, 4585, 8422, 4412, 2624, 2138, 128];
+        for i in inserts {
+            tree.insert(i, i);
+        }
+
+        for it in inserts {
+            //println!("\n= {it} =\n");
+            assert_eq!(tree.delete(&it), Some((it, it)));
+            //println!("{tree:#?}");
+            if !tree.is_empty() {
+                tree.check_invariants().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn map_api() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.first_key_value(), None);
+        assert_eq!(tree.last_key_value(), None);
+        assert_eq!(tree.pop_first(), None);
+        assert_eq!(tree.pop_last(), None);
+
+        for it in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            tree.insert(it, it * 10);
+        }
+
+        assert_eq!(tree.insert(13, 131), Some((13, 130)));
+        assert_eq!(tree.insert(13, 130), Some((13, 131)));
+        assert_eq!(tree.len(), 9);
+
+        assert!(tree.contains_key(&13));
+        assert!(!tree.contains_key(&14));
+        assert_eq!(tree.first_key_value(), Some((&2, &20)));
+        assert_eq!(tree.last_key_value(), Some((&19, &190)));
+
+        assert_eq!(tree.pop_first(), Some((2, 20)));
+        assert_eq!(tree.pop_last(), Some((19, 190)));
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.first_key_value(), Some((&5, &50)));
+        assert_eq!(tree.last_key_value(), Some((&18, &180)));
+        assert_eq!(tree.len(), 7);
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.first_key_value(), None);
+        tree.insert(1, 1);
+        assert_eq!(tree.pop_last(), Some((1, 1)));
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn lower_upper_bound() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.lower_bound(&0), None);
+        assert_eq!(tree.upper_bound(&0), None);
+
+        for it in [12, 5, 9, 2, 18] {
+            tree.insert(it, it * 10);
+        }
+        assert_eq!(tree.lower_bound(&0), Some((&2, &20)));
+        assert_eq!(tree.upper_bound(&0), Some((&2, &20)));
+        assert_eq!(tree.lower_bound(&9), Some((&9, &90)));
+        assert_eq!(tree.upper_bound(&9), Some((&12, &120)));
+        assert_eq!(tree.lower_bound(&10), Some((&12, &120)));
+        assert_eq!(tree.upper_bound(&10), Some((&12, &120)));
+        assert_eq!(tree.lower_bound(&18), Some((&18, &180)));
+        assert_eq!(tree.upper_bound(&18), None);
+        assert_eq!(tree.lower_bound(&19), None);
+    }
+
+    #[test]
+    fn keys_values() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.keys().next(), None);
+        assert_eq!(tree.values_mut().next_back(), None);
+
+        for it in [12, 5, 9, 2, 18] {
+            tree.insert(it, it);
+        }
+        assert!(tree.keys().eq(&[2, 5, 9, 12, 18]));
+        assert!(tree.keys().rev().eq(&[18, 12, 9, 5, 2]));
+        assert_eq!(tree.values().len(), 5);
+
+        for v in tree.values_mut() {
+            *v *= 10;
+        }
+        assert!(tree.values().eq(&[20, 50, 90, 120, 180]));
+        assert!(
+            tree.values_mut()
+                .rev()
+                .map(|v| *v)
+                .eq([180, 120, 90, 50, 20])
+        );
+    }
+
+    #[test]
+    fn iterators() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.iter_mut().next_back(), None);
+
+        for it in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            tree.insert(it, it);
+        }
+
+        let sorted = [2, 5, 9, 12, 13, 15, 17, 18, 19];
+        assert_eq!(tree.iter().len(), 9);
+        assert!(tree.iter().map(|(k, _)| *k).eq(sorted));
+        assert!(
+            tree.iter()
+                .rev()
+                .map(|(k, _)| *k)
+                .eq(sorted.into_iter().rev())
+        );
+
+        // meet in the middle
+        let mut iter = tree.iter();
+        assert_eq!(iter.next(), Some((&2, &2)));
+        assert_eq!(iter.next_back(), Some((&19, &19)));
+        assert_eq!(iter.len(), 7);
+        assert_eq!(iter.by_ref().rev().take(6).count(), 6);
+        assert_eq!(iter.next(), Some((&5, &5)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        for (k, v) in &mut tree {
+            *v = k * 10;
+        }
+        assert!(tree.iter().map(|(_, v)| *v).eq(sorted.map(|k| k * 10)));
+
+        let mut into_iter = tree.into_iter();
+        assert_eq!(into_iter.next(), Some((2, 20)));
+        assert_eq!(into_iter.next_back(), Some((19, 190)));
+        assert_eq!(into_iter.len(), 7);
+        // remaining items are dropped with the iterator
+    }
+
+    #[test]
+    fn range() {
+        let mut tree = RbTreeMap::new();
+        for it in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            tree.insert(it, it);
+        }
+        let keys = |r: Range<'_, i32, i32>| r.map(|(k, _)| *k).collect::<Vec<_>>();
+
+        assert_eq!(keys(tree.range(5..13)), [5, 9, 12]);
+        assert_eq!(keys(tree.range(6..=13)), [9, 12, 13]);
+        assert_eq!(keys(tree.range(..9)), [2, 5]);
+        assert_eq!(keys(tree.range(17..)), [17, 18, 19]);
+        assert_eq!(keys(tree.range(..)).len(), 9);
+        assert_eq!(
+            keys(tree.range((Bound::Excluded(12), Bound::Excluded(17)))),
+            [13, 15]
+        );
+        assert!(keys(tree.range(10..12)).is_empty());
+        assert!(keys(tree.range(20..)).is_empty());
+        assert!(keys(tree.range((Bound::Included(15), Bound::Excluded(13)))).is_empty());
+        assert_eq!(keys(tree.range(12..=12)), [12]);
+
+        let mut r = tree.range(5..=15);
+        assert_eq!(r.next(), Some((&5, &5)));
+        assert_eq!(r.next_back(), Some((&15, &15)));
+        assert_eq!(keys(r), [9, 12, 13]);
+
+        for (_, v) in tree.range_mut(9..15) {
+            *v *= 10;
+        }
+        assert!(
+            tree.iter()
+                .map(|(_, v)| *v)
+                .eq([2, 5, 90, 120, 130, 15, 17, 18, 19])
+        );
+        let mut r = tree.range_mut(13..);
+        assert_eq!(r.next_back(), Some((&19, &mut 19)));
+        assert_eq!(r.next(), Some((&13, &mut 130)));
+        assert_eq!(r.count(), 3);
+    }
+
+    #[test]
+    fn panic_in_drop() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+        struct D(bool, String);
+
+        impl Drop for D {
+            fn drop(&mut self) {
+                DROP_COUNT.fetch_add(1, Ordering::SeqCst);
+                if self.0 {
+                    panic!("panic from drop")
+                }
+            }
+        }
+
+        let mut tree = RbTreeMap::new();
+        for k in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            tree.insert(k, D(k == 9, k.to_string()));
+        }
+
+        catch_unwind(AssertUnwindSafe(|| drop(tree))).ok();
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), 9)
+    }
+
+    #[test]
+    fn large_degenerate_tree() {
+        #[cfg(not(miri))]
+        const SIZE: usize = 200_000;
+        #[cfg(miri)]
+        const SIZE: usize = 100;
+
+        // Not a valid red-black tree, but it makes sure that the traversals
+        // don't use the stack proportional to the height of the tree.
+        fn chain(len: usize, to_right: bool) -> RbTreeMap<usize, usize> {
+            let mut tree = RbTreeMap::new();
+            let mut parent: Option<RawNode<usize, usize>> = None;
+            for i in 0..len {
+                let key = if to_right { i } else { len - i };
+                let node = RawNode::from_node(Node {
+                    key,
+                    value: key,
+                    color: Color::Black,
+                    size: len - i,
+                    parent,
+                    left: None,
+                    right: None,
+                });
+                match parent {
+                    Some(mut p) if to_right => unsafe { p.set_right(Some(node)) },
+                    Some(mut p) => unsafe { p.set_left(Some(node)) },
+                    None => tree.root = node,
+                }
+                parent = Some(node);
+                tree.len += 1;
+            }
+            tree
+        }
+
+        for to_right in [true, false] {
+            let mut tree = chain(SIZE, to_right);
+
+            let mut count = 0;
+            tree.inorder_for_each(|_, _| count += 1);
+            assert_eq!(count, SIZE);
+
+            use std::io::Write;
+            write!(std::io::sink(), "{tree:?}").unwrap();
+
+            drop(tree);
+        }
+    }
+
+    #[test]
+    fn split_off_append() {
+        let mut tree = RbTreeMap::new();
+        for it in 0..100 {
+            tree.insert(it, it);
+        }
+
+        let mut right = tree.split_off(&60);
+        assert_eq!(tree.len(), 60);
+        assert_eq!(right.len(), 40);
+        assert!(tree.iter().map(|(k, _)| *k).eq(0..60));
+        assert!(right.iter().map(|(k, _)| *k).eq(60..100));
+        tree.check_invariants().unwrap();
+        right.check_invariants().unwrap();
+
+        // split at the ends
+        let empty = right.split_off(&1000);
+        assert!(empty.is_empty());
+        assert_eq!(right.len(), 40);
+        let mut all = right.split_off(&-5);
+        assert!(right.is_empty());
+        assert_eq!(all.len(), 40);
+
+        // trees with very different black heights
+        let mut small = all.split_off(&99);
+        assert_eq!(small.len(), 1);
+        tree.append(&mut small);
+        assert!(small.is_empty());
+        assert_eq!(tree.len(), 61);
+        tree.check_invariants().unwrap();
+
+        // `other` has smaller keys
+        all.append(&mut tree);
+        assert_eq!(all.len(), 100);
+        assert!(tree.is_empty());
+        assert!(all.iter().map(|(k, _)| *k).eq(0..100));
+        all.check_invariants().unwrap();
+
+        // overlapping keys
+        let mut other = RbTreeMap::new();
+        for it in (50..150).step_by(2) {
+            other.insert(it, -it);
+        }
+        all.append(&mut other);
+        assert_eq!(all.len(), 125);
+        assert_eq!(all.get(&50), Some((&50, &-50)));
+        assert_eq!(all.get(&51), Some((&51, &51)));
+        assert_eq!(all.get(&148), Some((&148, &-148)));
+        all.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn select_rank() {
+        let mut tree = RbTreeMap::new();
+        for it in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            tree.insert(it, it);
+        }
+        tree.check_invariants().unwrap();
+        let sorted = [2, 5, 9, 12, 13, 15, 17, 18, 19];
+        for (i, k) in sorted.iter().enumerate() {
+            assert_eq!(tree.select(i), Some((k, k)));
+            assert_eq!(tree.rank(k), Some(i));
+        }
+        assert_eq!(tree.select(9), None);
+        assert_eq!(tree.rank(&10), None);
+
+        tree.delete(&12);
+        tree.delete(&2);
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.select(0), Some((&5, &5)));
+        assert_eq!(tree.select(2), Some((&13, &13)));
+        assert_eq!(tree.rank(&19), Some(6));
+
+        let right = tree.split_off(&15);
+        tree.check_invariants().unwrap();
+        right.check_invariants().unwrap();
+        assert_eq!(right.select(1), Some((&17, &17)));
+    }
+
+    #[test]
+    fn node_update_hook() {
+        // keeps the sum of the keys in the subtree in the value
+        fn update(key: &i32, value: &mut i32, left: Option<&i32>, right: Option<&i32>) {
+            *value = key + left.copied().unwrap_or(0) + right.copied().unwrap_or(0);
+        }
+        fn assert_sums(tree: &RbTreeMap<i32, i32>) {
+            for (k, _) in tree.iter() {
+                let node = tree.get_raw(k).unwrap();
+                let mut expected = 0;
+                unsafe {
+                    RbTreeMap::inorder_for_each_core(node, &mut |n| expected += n.key());
+                }
+                assert_eq!(unsafe { node.as_refs().1 }, &expected);
+            }
+        }
+
+        let mut tree = RbTreeMap::with_node_update(update);
+        for k in 0..50 {
+            tree.insert(k, 0);
+        }
+        assert_sums(&tree);
+        for k in (0..50).step_by(3) {
+            tree.delete(&k);
+        }
+        assert_sums(&tree);
+
+        let mut right = tree.split_off(&25);
+        assert_sums(&tree);
+        assert_sums(&right);
+        right.insert(100, 0);
+        tree.append(&mut right);
+        assert_sums(&tree);
+        tree.clear();
+        tree.insert(1, 0);
+        tree.insert(2, 0);
+        assert_sums(&tree);
+    }
+
+    #[test]
+    fn node_pool() {
+        let mut tree = RbTreeMap::with_node_pool();
+        for i in 0..100 {
+            tree.insert(i, i.to_string());
+        }
+        assert_eq!(tree.pooled_nodes(), 0);
+
+        for i in (0..100).step_by(2) {
+            assert_eq!(tree.delete(&i), Some((i, i.to_string())));
+        }
+        assert_eq!(tree.pop_first(), Some((1, "1".to_string())));
+        assert_eq!(tree.pooled_nodes(), 51);
+        tree.check_invariants().unwrap();
+
+        for i in (0..100).step_by(2) {
+            tree.insert(i, i.to_string());
+        }
+        assert_eq!(tree.pooled_nodes(), 1);
+        assert_eq!(tree.len(), 99);
+        assert!(
+            tree.iter()
+                .map(|(k, v)| (*k, v.clone()))
+                .eq((0..100).filter(|i| *i != 1).map(|i| (i, i.to_string())))
+        );
+        tree.check_invariants().unwrap();
+
+        // split off tree also reuses nodes
+        let mut high = tree.split_off(&50);
+        assert_eq!(high.pooled_nodes(), 0);
+        high.pop_last();
+        assert_eq!(high.pooled_nodes(), 1);
+
+        // trees without the pool free removed nodes immediately
+        let mut tree = RbTreeMap::new();
+        tree.insert(1, 1);
+        tree.delete(&1);
+        assert_eq!(tree.pooled_nodes(), 0);
+    }
+
+    #[test]
+    fn bulk_load() {
+        for len in 0..70 {
+            let tree = RbTreeMap::bulk_load((0..len).map(|k| (k, k)).collect());
+            assert_eq!(tree.len(), len as usize);
+            assert!(tree.iter().map(|(k, _)| *k).eq(0..len));
+            tree.check_invariants().unwrap();
+        }
+
+        // unsorted with duplicates, last one wins
+        let mut tree = RbTreeMap::bulk_load(vec![(3, 1), (1, 2), (3, 3), (2, 4), (1, 5)]);
+        assert!(tree.iter().eq([(&1, &5), (&2, &4), (&3, &3)]));
+        tree.check_invariants().unwrap();
+        tree.insert(0, 6);
+        tree.delete(&2);
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn from_iter_extend() {
+        let mut tree: RbTreeMap<_, _> = [(3, 1), (1, 2), (3, 3), (2, 4)].into_iter().collect();
+        assert!(tree.iter().eq([(&1, &2), (&2, &4), (&3, &3)]));
+        tree.check_invariants().unwrap();
+
+        // disjoint on both sides are joined
+        tree.extend((10..20).rev().map(|k| (k, k)));
+        tree.extend([(-1, 0), (-5, 0), (-1, 1)]);
+        assert_eq!(tree.len(), 15);
+        assert_eq!(tree.first_key_value(), Some((&-5, &0)));
+        assert_eq!(tree.get(&-1), Some((&-1, &1)));
+        tree.check_invariants().unwrap();
+
+        // overlapping are inserted one by one
+        tree.extend([(&2, &20), (&5, &50), (&2, &21)]);
+        assert_eq!(tree.len(), 16);
+        assert_eq!(tree.get(&2), Some((&2, &21)));
+        assert_eq!(tree.get(&5), Some((&5, &50)));
+        tree.check_invariants().unwrap();
+
+        let mut tree = RbTreeMap::with_node_pool();
+        tree.extend([(1, 1), (0, 0)]);
+        tree.pop_first();
+        assert_eq!(tree.pooled_nodes(), 1);
+        tree.extend(std::iter::empty::<(i32, i32)>());
+        assert!(tree.iter().eq([(&1, &1)]));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let mut tree = RbTreeMap::new();
+        for it in [12, 5, 9, 2, 18] {
+            tree.insert(it, it * 10);
+        }
+
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(json, r#"{"2":20,"5":50,"9":90,"12":120,"18":180}"#);
+
+        let de: RbTreeMap<i32, i32> = serde_json::from_str(&json).unwrap();
+        assert!(de.iter().eq(tree.iter()));
+        de.check_invariants().unwrap();
+
+        let unsorted: RbTreeMap<i32, i32> = serde_json::from_str(r#"{"5":1,"1":2,"5":3}"#).unwrap();
+        assert!(unsorted.iter().eq([(&1, &2), (&5, &3)]));
+
+        let empty: RbTreeMap<i32, i32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn check_invariants() {
+        let mut tree = RbTreeMap::new();
+        assert_eq!(tree.check_invariants(), Ok(()));
+        for it in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            tree.insert(it, it);
+        }
+        assert_eq!(tree.check_invariants(), Ok(()));
+
+        unsafe {
+            tree.root.set_color(Color::Red);
+            assert_eq!(tree.check_invariants(), Err(InvariantError::RedRoot));
+            tree.root.set_color(Color::Black);
+
+            let mut min = RbTreeMap::min_of(tree.root);
+            let color = min.color();
+            min.set_color(if color.is_red() {
+                Color::Black
+            } else {
+                Color::Red
+            });
+            assert!(matches!(
+                tree.check_invariants(),
+                Err(InvariantError::BlackHeight { .. } | InvariantError::RedChildOfRed { .. })
+            ));
+            min.set_color(color);
+
+            let mut max = RbTreeMap::max_of(tree.root);
+            max.as_mut().key = 0;
+            assert_eq!(
+                tree.check_invariants(),
+                Err(InvariantError::Order { index: 8 })
+            );
+            max.as_mut().key = 19;
+
+            // parent of `max` is visited first and sees the wrong size of its child
+            max.set_size(2);
+            assert!(matches!(
+                tree.check_invariants(),
+                Err(InvariantError::Size { .. })
+            ));
+            max.set_size(1);
+
+            let parent = max.parent();
+            max.set_parent(None);
+            assert!(matches!(
+                tree.check_invariants(),
+                Err(InvariantError::ParentLink { index: Some(_) })
+            ));
+            max.set_parent(parent);
+        }
+
+        tree.len += 1;
+        let err = tree.check_invariants().unwrap_err();
+        assert_eq!(
+            err,
+            InvariantError::Len {
+                expected: 9,
+                found: 10
+            }
+        );
+        assert_eq!(err.to_string(), "tree has 9 nodes but len is 10");
+        tree.len -= 1;
+        assert_eq!(tree.check_invariants(), Ok(()));
+    }
+
+    mod proptests {
+        use std::collections::hash_map::RandomState;
+
+        use proptest::prelude::*;
+        use rand::seq::SliceRandom;
+        use rand::thread_rng;
+
+        use super::*;
+
+        crate::proptest_suite::ordered_map_proptests!(RbTreeMap::new(), |tree: &RbTreeMap<
+            i32,
+            i32,
+        >| {
+            tree.check_invariants().unwrap()
+        });
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn insert_get(
+                mut inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..10)
+            ) {
+                let mut ref_hmap = std::collections::HashMap::<i32, i32, RandomState>::default();
+                let mut rbt = RbTreeMap::new();
+                for (i, v) in inserts.iter().enumerate() {
+                    let i = i as i32;
+                    assert_eq!(rbt.insert(*v, i), ref_hmap.insert(*v, i).map(|old| (*v, old)));
+                }
+                if !rbt.is_empty() {
+                    rbt.check_invariants().unwrap();
+                }
+
+                inserts.shuffle(&mut thread_rng());
+                for key in inserts.iter().chain(access.iter()) {
+                    assert_eq!(ref_hmap.get_key_value(key), rbt.get(key));
+                }
+            }
+
+            #[test]
+            fn order(
+                inserts in proptest::collection::hash_set(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut rbt = RbTreeMap::new();
+                for v in &inserts {
+                    rbt.insert(*v, *v);
+                }
+
+                let mut inserts: Vec<_> = inserts.into_iter().collect();
+                inserts.sort();
+
+                let mut items = Vec::with_capacity(rbt.len());
+                rbt.inorder_for_each(|k, _| items.push(*k));
+                assert_eq!(&items, &inserts);
+                assert!(rbt.iter().map(|(k, _)| k).eq(inserts.iter()));
+                assert!(rbt.iter().rev().map(|(k, _)| k).eq(inserts.iter().rev()));
+                assert!(rbt.into_iter().map(|(k, _)| k).eq(inserts.into_iter()));
+            }
+
+            #[test]
+            fn extend(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                batches in proptest::collection::vec(proptest::collection::vec(0..10000i32, 0..100), 0..10),
+            ) {
+                let mut reference = std::collections::BTreeMap::new();
+                let mut tree: RbTreeMap<_, _> = inserts.iter().map(|v| (*v, *v)).collect();
+                reference.extend(inserts.iter().map(|v| (*v, *v)));
+                for (i, batch) in batches.iter().enumerate() {
+                    tree.extend(batch.iter().map(|v| (*v, i as i32)));
+                    reference.extend(batch.iter().map(|v| (*v, i as i32)));
+                    tree.check_invariants().unwrap();
+                }
+                assert!(tree.iter().eq(reference.iter()));
+            }
+
+            #[test]
+            fn lower_upper_bound(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                access in proptest::collection::vec(0..10000i32, 0..100),
+            ) {
+                let mut reference = std::collections::BTreeMap::new();
+                let mut tree = RbTreeMap::new();
+                for v in &inserts {
+                    reference.insert(*v, *v);
+                    tree.insert(*v, *v);
+                }
+
+                for key in inserts.iter().chain(access.iter()) {
+                    assert_eq!(tree.lower_bound(key), reference.range(key..).next());
+                    assert_eq!(
+                        tree.upper_bound(key),
+                        reference.range((Bound::Excluded(key), Bound::Unbounded)).next()
+                    );
+                }
+            }
+
+            #[test]
+            fn node_pool(
+                ops in proptest::collection::vec((any::<bool>(), 0..100i32), 0..MAP_SIZE),
+            ) {
+                let mut reference = std::collections::BTreeMap::new();
+                let mut tree = RbTreeMap::with_node_pool();
+                for (insert, key) in ops {
+                    if insert {
+                        reference.insert(key, key.to_string());
+                        tree.insert(key, key.to_string());
+                    } else {
+                        assert_eq!(reference.remove_entry(&key), tree.delete(&key));
+                    }
+                }
+                tree.check_invariants().unwrap();
+                assert!(tree.iter().eq(reference.iter()));
+            }
+
+            #[test]
+            fn range(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                start in 0..10000i32,
+                len in 0..5000i32,
+            ) {
+                let mut tree = RbTreeMap::new();
+                let mut expected = std::collections::BTreeMap::new();
+                for v in &inserts {
+                    tree.insert(*v, *v);
+                    expected.insert(*v, *v);
+                }
+
+                let end = start + len;
+                assert!(tree.range(start..end).eq(expected.range(start..end)));
+                assert!(tree.range(start..=end).rev().eq(expected.range(start..=end).rev()));
+                assert!(tree.range(..end).eq(expected.range(..end)));
+                assert!(tree.range_mut(start..).eq(expected.range_mut(start..)));
+            }
+
+            #[test]
+            fn split_off_append(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                at in 0..10000i32,
+            ) {
+                let mut tree = RbTreeMap::new();
+                let mut expected = std::collections::BTreeMap::new();
+                for v in &inserts {
+                    tree.insert(*v, *v);
+                    expected.insert(*v, *v);
+                }
+
+                let mut right = tree.split_off(&at);
+                let mut expected_right = expected.split_off(&at);
+                assert_eq!(tree.len(), expected.len());
+                assert_eq!(right.len(), expected_right.len());
+                assert!(tree.iter().eq(expected.iter()));
+                assert!(right.iter().eq(expected_right.iter()));
+                for t in [&tree, &right] {
+                    t.check_invariants().unwrap();
+                }
+
+                // both orders must work
+                if at % 2 == 0 {
+                    tree.append(&mut right);
+                } else {
+                    right.append(&mut tree);
+                    tree = right;
+                    right = RbTreeMap::new();
+                }
+                expected.append(&mut expected_right);
+                assert!(right.is_empty());
+                assert_eq!(tree.len(), expected.len());
+                assert!(tree.iter().eq(expected.iter()));
+                tree.check_invariants().unwrap();
+            }
+
+            #[test]
+            fn select_rank(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                removes in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut tree = RbTreeMap::new();
+                let mut expected = std::collections::BTreeMap::new();
+                for v in &inserts {
+                    tree.insert(*v, *v);
+                    expected.insert(*v, *v);
+                }
+                for v in &removes {
+                    tree.delete(v);
+                    expected.remove(v);
+                }
+
+                tree.check_invariants().unwrap();
+                for (i, (k, v)) in expected.iter().enumerate() {
+                    assert_eq!(tree.select(i), Some((k, v)));
+                    assert_eq!(tree.rank(k), Some(i));
+                }
+                assert_eq!(tree.select(expected.len()), None);
+            }
+
+            #[test]
+            fn pop_first_last(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                pops in proptest::collection::vec(any::<bool>(), 0..MAP_SIZE),
+            ) {
+                let mut reference = std::collections::BTreeMap::new();
+                let mut tree = RbTreeMap::new();
+                for v in &inserts {
+                    reference.insert(*v, *v);
+                    tree.insert(*v, *v);
+                }
+
+                for first in pops {
+                    if first {
+                        assert_eq!(reference.pop_first(), tree.pop_first());
+                    } else {
+                        assert_eq!(reference.pop_last(), tree.pop_last());
+                    }
+                    assert_eq!(reference.first_key_value(), tree.first_key_value());
+                    assert_eq!(reference.last_key_value(), tree.last_key_value());
+                }
+                if !tree.is_empty() {
+                    tree.check_invariants().unwrap();
+                }
+            }
+
+        );
+    }
+}
@@ -0,0 +1,431 @@
+//! Tree of arbitrary arity with id based navigation
+
+use core::fmt;
+use core::ops::{Index, IndexMut};
+
+use vec::{Queue, Stack};
+
+/// Identifier of a node in a [`Tree`].
+///
+/// Ids are only meaningful for the tree that returned them, using them with
+/// another tree either panics or returns an unrelated node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(usize);
+
+struct Node<T> {
+    value: T,
+    parent: Option<NodeId>,
+    /// In insertion order
+    children: Vec<NodeId>,
+}
+
+/// A rooted tree where every node can have any number of ordered children.
+///
+/// Nodes are stored in an arena and referred to by their [`NodeId`]s, so
+/// parent and child links are plain indices and navigating in either direction
+/// is `O(1)`.
+pub struct Tree<T> {
+    // INVARIANTS:
+    //  * `nodes[0]` is the root and it's the only node without a parent
+    //  * `b` is in `nodes[a].children` iff `nodes[b].parent == Some(a)`
+    //  * a node's id is its index in `nodes` and nodes are never removed,
+    //    so all ids returned from a tree remain valid
+    nodes: Vec<Node<T>>,
+}
+
+impl<T> Tree<T> {
+    /// Creates a tree with only the root node.
+    pub fn new(root: T) -> Self {
+        Self {
+            nodes: vec![Node {
+                value: root,
+                parent: None,
+                children: Vec::new(),
+            }],
+        }
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// Number of nodes in the tree, it's always at least 1.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.nodes.get(id.0).map(|node| &node.value)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.nodes.get_mut(id.0).map(|node| &mut node.value)
+    }
+
+    /// Adds a new last child to `parent` and returns its id.
+    ///
+    /// # PANICS
+    ///
+    /// * if `parent` is not in the tree
+    pub fn add_child(&mut self, parent: NodeId, value: T) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.node_mut(parent).children.push(id);
+        self.nodes.push(Node {
+            value,
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        id
+    }
+
+    /// # PANICS
+    ///
+    /// * if `id` is not in the tree
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    /// Children of `id` in the order they were added.
+    ///
+    /// # PANICS
+    ///
+    /// * if `id` is not in the tree
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    /// Returns the next child of the parent of `id`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `id` is not in the tree
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self.children(self.parent(id)?);
+        let index = siblings.iter().position(|s| *s == id)?;
+        siblings.get(index + 1).copied()
+    }
+
+    /// Returns the previous child of the parent of `id`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `id` is not in the tree
+    pub fn prev_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let siblings = self.children(self.parent(id)?);
+        let index = siblings.iter().position(|s| *s == id)?;
+        index.checked_sub(1).map(|i| siblings[i])
+    }
+
+    /// Number of edges from the root to `id`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `id` is not in the tree
+    pub fn depth(&self, id: NodeId) -> usize {
+        self.ancestors(id).count()
+    }
+
+    /// Iterate over the ancestors of `id` starting from its parent up to the root.
+    ///
+    /// # PANICS
+    ///
+    /// * if `id` is not in the tree
+    pub fn ancestors(&self, id: NodeId) -> Ancestors<'_, T> {
+        Ancestors {
+            tree: self,
+            next: self.parent(id),
+        }
+    }
+
+    /// Iterate over all nodes in depth-first pre-order starting from the root.
+    pub fn iter(&self) -> Dfs<'_, T> {
+        self.dfs(self.root())
+    }
+
+    /// Iterate over the subtree of `id` in depth-first pre-order,
+    /// children are visited in the order they were added.
+    ///
+    /// # PANICS
+    ///
+    /// * if `id` is not in the tree
+    pub fn dfs(&self, id: NodeId) -> Dfs<'_, T> {
+        self.node(id);
+        let mut stack = Stack::new();
+        stack.push(id);
+        Dfs { tree: self, stack }
+    }
+
+    /// Iterate over the subtree of `id` in breadth-first order,
+    /// children are visited in the order they were added.
+    ///
+    /// # PANICS
+    ///
+    /// * if `id` is not in the tree
+    pub fn bfs(&self, id: NodeId) -> Bfs<'_, T> {
+        self.node(id);
+        let mut queue = Queue::new();
+        queue.enqueue(id);
+        Bfs { tree: self, queue }
+    }
+
+    fn node(&self, id: NodeId) -> &Node<T> {
+        match self.nodes.get(id.0) {
+            Some(node) => node,
+            None => panic!("{id:?} is not in the tree"),
+        }
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node<T> {
+        match self.nodes.get_mut(id.0) {
+            Some(node) => node,
+            None => panic!("{id:?} is not in the tree"),
+        }
+    }
+}
+
+impl<T> Index<NodeId> for Tree<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId) -> &Self::Output {
+        &self.node(id).value
+    }
+}
+
+impl<T> IndexMut<NodeId> for Tree<T> {
+    fn index_mut(&mut self, id: NodeId) -> &mut Self::Output {
+        &mut self.node_mut(id).value
+    }
+}
+
+impl<T> Clone for Tree<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| Node {
+                value: node.value.clone(),
+                parent: node.parent,
+                children: node.children.clone(),
+            })
+            .collect();
+        Self { nodes }
+    }
+}
+
+impl<T> fmt::Debug for Tree<T>
+where
+    T: fmt::Debug,
+{
+    /// Formats the tree as nested lists, `value: [children...]` for nodes
+    /// with children and just `value` for leaves.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Subtree<'a, T>(&'a Tree<T>, NodeId);
+
+        impl<T: fmt::Debug> fmt::Debug for Subtree<'_, T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let Subtree(tree, id) = *self;
+                tree[id].fmt(f)?;
+                let children = tree.children(id);
+                if !children.is_empty() {
+                    f.write_str(": ")?;
+                    f.debug_list()
+                        .entries(children.iter().map(|c| Subtree(tree, *c)))
+                        .finish()?;
+                }
+                Ok(())
+            }
+        }
+
+        Subtree(self, self.root()).fmt(f)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Tree<T> {
+    type Item = (NodeId, &'a T);
+    type IntoIter = Dfs<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Ancestors<'a, T> {
+    tree: &'a Tree<T>,
+    next: Option<NodeId>,
+}
+
+impl<'a, T> Iterator for Ancestors<'a, T> {
+    type Item = (NodeId, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.next?;
+        let node = self.tree.node(id);
+        self.next = node.parent;
+        Some((id, &node.value))
+    }
+}
+
+pub struct Dfs<'a, T> {
+    tree: &'a Tree<T>,
+    stack: Stack<NodeId>,
+}
+
+impl<'a, T> Iterator for Dfs<'a, T> {
+    type Item = (NodeId, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.stack.pop()?;
+        let node = self.tree.node(id);
+        for child in node.children.iter().rev() {
+            self.stack.push(*child);
+        }
+        Some((id, &node.value))
+    }
+}
+
+pub struct Bfs<'a, T> {
+    tree: &'a Tree<T>,
+    queue: Queue<NodeId>,
+}
+
+impl<'a, T> Iterator for Bfs<'a, T> {
+    type Item = (NodeId, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.queue.dequeue()?;
+        let node = self.tree.node(id);
+        for child in &node.children {
+            self.queue.enqueue(*child);
+        }
+        Some((id, &node.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///```text
+    ///        a
+    ///      / | \
+    ///     b  c  d
+    ///    / \    |
+    ///   e   f   g
+    ///```
+    fn sample() -> (Tree<char>, [NodeId; 7]) {
+        let mut tree = Tree::new('a');
+        let a = tree.root();
+        let b = tree.add_child(a, 'b');
+        let c = tree.add_child(a, 'c');
+        let d = tree.add_child(a, 'd');
+        let e = tree.add_child(b, 'e');
+        let f = tree.add_child(b, 'f');
+        let g = tree.add_child(d, 'g');
+        (tree, [a, b, c, d, e, f, g])
+    }
+
+    #[test]
+    fn navigation() {
+        let (mut tree, [a, b, c, d, e, f, g]) = sample();
+        assert_eq!(tree.node_count(), 7);
+        assert_eq!(tree[c], 'c');
+        assert_eq!(tree.parent(a), None);
+        assert_eq!(tree.parent(f), Some(b));
+        assert_eq!(tree.children(a), &[b, c, d]);
+        assert!(tree.children(c).is_empty());
+        assert_eq!(tree.next_sibling(b), Some(c));
+        assert_eq!(tree.next_sibling(d), None);
+        assert_eq!(tree.prev_sibling(f), Some(e));
+        assert_eq!(tree.prev_sibling(a), None);
+        assert_eq!(tree.depth(a), 0);
+        assert_eq!(tree.depth(g), 2);
+        assert!(tree.ancestors(e).map(|(_, v)| *v).eq(['b', 'a']));
+
+        tree[g] = 'G';
+        *tree.get_mut(e).unwrap() = 'E';
+        assert_eq!(tree.get(g), Some(&'G'));
+        assert_eq!(tree.get(NodeId(7)), None);
+        assert_eq!(
+            format!("{tree:?}"),
+            "'a': ['b': ['E', 'f'], 'c', 'd': ['G']]"
+        );
+    }
+
+    #[test]
+    fn traversals() {
+        let (tree, [_, b, ..]) = sample();
+        assert!(tree.iter().map(|(_, v)| *v).eq("abefcdg".chars()));
+        assert!(tree.dfs(b).map(|(_, v)| *v).eq("bef".chars()));
+        assert!(tree.bfs(tree.root()).map(|(_, v)| *v).eq("abcdefg".chars()));
+        assert!(tree.bfs(b).map(|(_, v)| *v).eq("bef".chars()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn foreign_id() {
+        let (_, [.., g]) = sample();
+        let mut other = Tree::new('x');
+        other.add_child(g, 'y');
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const TREE_SIZE: usize = 500;
+        #[cfg(miri)]
+        const TREE_SIZE: usize = 30;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 500;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn traversal_orders(
+                parents in proptest::collection::vec(any::<proptest::sample::Index>(), 0..TREE_SIZE),
+            ) {
+                // node `i + 1` gets a random earlier node as its parent
+                let mut tree = Tree::new(0);
+                let mut ids = vec![tree.root()];
+                for (i, parent) in parents.iter().enumerate() {
+                    let parent = ids[parent.index(ids.len())];
+                    ids.push(tree.add_child(parent, i + 1));
+                }
+
+                let mut visited = vec![false; ids.len()];
+                let mut dfs_pos = vec![0; ids.len()];
+                for (pos, (id, value)) in tree.iter().enumerate() {
+                    assert!(!visited[*value]);
+                    visited[*value] = true;
+                    dfs_pos[*value] = pos;
+                    if let Some(parent) = tree.parent(id) {
+                        assert!(visited[tree[parent]]);
+                    }
+                }
+                assert!(visited.iter().all(|v| *v));
+
+                // subtrees are contiguous in pre-order
+                for id in &ids {
+                    let size = tree.dfs(*id).count();
+                    for (node, value) in tree.dfs(*id) {
+                        assert!(dfs_pos[*value] < dfs_pos[tree[*id]] + size);
+                        assert!(node == *id || tree.ancestors(node).any(|(a, _)| a == *id));
+                    }
+                }
+
+                let depths: Vec<_> = tree.bfs(tree.root()).map(|(id, _)| tree.depth(id)).collect();
+                assert_eq!(depths.len(), ids.len());
+                assert!(depths.windows(2).all(|w| w[0] <= w[1]));
+            }
+        );
+    }
+}
@@ -0,0 +1,741 @@
+//! Scapegoat tree
+//!
+//! Balanced binary search tree without rotations or any per-node balance
+//! information besides the subtree sizes. Instead, when an insert creates a node
+//! deeper than `log(n)` in base `1 / alpha`, the first ancestor which is not
+//! alpha-weight-balanced (the scapegoat) gets its whole subtree rebuilt into a
+//! perfectly balanced one. After enough removals the whole tree is rebuilt.
+//!
+//! A node is alpha-weight-balanced if both of its subtrees have at most
+//! `alpha * size` nodes. Smaller `alpha` keeps the tree more balanced at the cost of more
+//! frequent rebuilds. Inserts and removals are amortized O(log n), lookups are worst case O(log n).
+
+use core::fmt;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::mem;
+
+use vec::Stack;
+
+type Link<K, V> = Option<Box<Node<K, V>>>;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    /// Number of nodes in the subtree rooted at this node
+    size: usize,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+impl<K, V> Node<K, V> {
+    fn update_size(&mut self) {
+        self.size = size_of(&self.left) + size_of(&self.right) + 1;
+    }
+
+    fn is_weight_balanced(&self, alpha: f64) -> bool {
+        let max_child_size = alpha * self.size as f64;
+        size_of(&self.left) as f64 <= max_child_size
+            && size_of(&self.right) as f64 <= max_child_size
+    }
+}
+
+fn size_of<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+/// Ordered map backed by a scapegoat tree.
+pub struct ScapegoatTree<K, V> {
+    // INVARIANTS:
+    //  * keys are in binary search tree order and unique
+    //  * `size` of every node is the number of nodes in its subtree
+    //  * depth of every node is at most `max_depth(max_len)`
+    //  * `len <= max_len`
+    root: Link<K, V>,
+    /// Largest `len` since the whole tree was last rebuilt
+    max_len: usize,
+    alpha: f64,
+}
+
+impl<K, V> Default for ScapegoatTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> ScapegoatTree<K, V> {
+    pub const DEFAULT_ALPHA: f64 = 0.7;
+
+    pub fn new() -> Self {
+        Self::with_alpha(Self::DEFAULT_ALPHA)
+    }
+
+    /// Creates a tree which keeps every subtree at most `alpha` times as large as its parent.
+    ///
+    /// `alpha = 0.5` keeps the tree perfectly balanced, larger values allow more imbalance
+    /// but need fewer rebuilds.
+    ///
+    /// # PANICS
+    ///
+    /// * if `alpha` is not in `[0.5, 1)`
+    pub fn with_alpha(alpha: f64) -> Self {
+        assert!(
+            (0.5..1.0).contains(&alpha),
+            "alpha must be in [0.5, 1), got {alpha}"
+        );
+        Self {
+            root: None,
+            max_len: 0,
+            alpha,
+        }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    pub fn len(&self) -> usize {
+        size_of(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Number of nodes on the longest path from the root to a leaf.
+    pub fn height(&self) -> usize {
+        fn height<K, V>(link: &Link<K, V>) -> usize {
+            match link {
+                Some(node) => 1 + height(&node.left).max(height(&node.right)),
+                None => 0,
+            }
+        }
+
+        height(&self.root)
+    }
+
+    /// Iterate over the items in ascending key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter {
+            front: Stack::new(),
+            back: Stack::new(),
+            len: self.len(),
+        };
+        iter.push_left_spine(self.root.as_deref());
+        iter.push_right_spine(self.root.as_deref());
+        iter
+    }
+
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.max_len = 0;
+    }
+
+    /// Largest allowed depth (number of edges from the root) of a node in a tree with `len` nodes.
+    fn max_depth(&self, len: usize) -> usize {
+        if len <= 1 {
+            return 0;
+        }
+        ((len as f64).ln() / (1.0 / self.alpha).ln()).floor() as usize
+    }
+
+    /// Rebuilds the subtree at `link` into a perfectly balanced tree in O(size).
+    ///
+    /// The nodes are reused, only their links and sizes are changed.
+    fn rebuild(link: &mut Link<K, V>) {
+        let mut nodes = Vec::with_capacity(size_of(link));
+
+        // in-order traversal which detaches the nodes
+        let mut stack = Stack::new();
+        let mut maybe_node = link.take();
+        loop {
+            while let Some(mut node) = maybe_node {
+                maybe_node = node.left.take();
+                stack.push(node);
+            }
+            let Some(mut node) = stack.pop() else {
+                break;
+            };
+            maybe_node = node.right.take();
+            nodes.push(node);
+        }
+
+        /// Builds a balanced subtree from the next `len` nodes of `nodes`.
+        fn build<K, V>(nodes: &mut std::vec::IntoIter<Box<Node<K, V>>>, len: usize) -> Link<K, V> {
+            if len == 0 {
+                return None;
+            }
+
+            let left = build(nodes, len / 2);
+            let mut node = nodes.next().expect("`nodes` must contain `len` nodes");
+            node.left = left;
+            node.right = build(nodes, len - len / 2 - 1);
+            node.size = len;
+            Some(node)
+        }
+
+        let len = nodes.len();
+        *link = build(&mut nodes.into_iter(), len);
+    }
+}
+
+impl<K, V> ScapegoatTree<K, V>
+where
+    K: Ord,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let node = self.get_node(key)?;
+        Some((&node.key, &node.value))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut link = &mut self.root;
+        while let Some(node) = link {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => link = &mut node.left,
+                Ordering::Equal => return Some((&node.key, &mut node.value)),
+                Ordering::Greater => link = &mut node.right,
+            }
+        }
+
+        None
+    }
+
+    fn get_node<Q>(&self, key: &Q) -> Option<&Node<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut maybe_node = self.root.as_deref();
+        while let Some(node) = maybe_node {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => maybe_node = node.left.as_deref(),
+                Ordering::Equal => return Some(node),
+                Ordering::Greater => maybe_node = node.right.as_deref(),
+            }
+        }
+
+        None
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.get_node(key).is_some()
+    }
+
+    /// Returns the item after `key` in key order, if `key` is in the tree.
+    pub fn successor<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut found = None;
+        let mut maybe_node = self.root.as_deref();
+        while let Some(node) = maybe_node {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => {
+                    found = Some(node);
+                    maybe_node = node.left.as_deref();
+                }
+                Ordering::Equal => {
+                    // smallest item in the right subtree or the last ancestor we went left at
+                    let mut next = node.right.as_deref();
+                    while let Some(n) = next {
+                        found = Some(n);
+                        next = n.left.as_deref();
+                    }
+                    return found.map(|node| (&node.key, &node.value));
+                }
+                Ordering::Greater => maybe_node = node.right.as_deref(),
+            }
+        }
+
+        None
+    }
+
+    /// Returns the item before `key` in key order, if `key` is in the tree.
+    pub fn predecessor<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut found = None;
+        let mut maybe_node = self.root.as_deref();
+        while let Some(node) = maybe_node {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => maybe_node = node.left.as_deref(),
+                Ordering::Equal => {
+                    // largest item in the left subtree or the last ancestor we went right at
+                    let mut prev = node.left.as_deref();
+                    while let Some(n) = prev {
+                        found = Some(n);
+                        prev = n.right.as_deref();
+                    }
+                    return found.map(|node| (&node.key, &node.value));
+                }
+                Ordering::Greater => {
+                    found = Some(node);
+                    maybe_node = node.right.as_deref();
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Inserts `key` and `value` into the tree.
+    ///
+    /// If an equal key already exists, both the key and value are replaced
+    /// and the old entry is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        struct Insert<K, V> {
+            new: Option<Box<Node<K, V>>>,
+            max_depth: usize,
+            alpha: f64,
+        }
+
+        /// Inserts a new leaf below `link` at `depth`.
+        ///
+        /// Returns the replaced entry if the key already existed and whether
+        /// the new leaf is too deep and a scapegoat has not been rebuilt yet.
+        fn insert<K: Ord, V>(
+            link: &mut Link<K, V>,
+            depth: usize,
+            ctx: &mut Insert<K, V>,
+        ) -> (Option<(K, V)>, bool) {
+            let Some(node) = link else {
+                *link = ctx.new.take();
+                return (None, depth > ctx.max_depth);
+            };
+
+            let new_key = &ctx
+                .new
+                .as_ref()
+                .expect("new node is inserted only once")
+                .key;
+            let child = match new_key.cmp(&node.key) {
+                Ordering::Less => &mut node.left,
+                Ordering::Greater => &mut node.right,
+                Ordering::Equal => {
+                    let new = *ctx.new.take().expect("new node is inserted only once");
+                    let old = (
+                        mem::replace(&mut node.key, new.key),
+                        mem::replace(&mut node.value, new.value),
+                    );
+                    return (Some(old), false);
+                }
+            };
+
+            let (old, mut too_deep) = insert(child, depth + 1, ctx);
+            if old.is_none() {
+                node.size += 1;
+                // Some ancestor of a too deep node must be unbalanced,
+                // the lowest one on the path is the scapegoat.
+                if too_deep && !node.is_weight_balanced(ctx.alpha) {
+                    ScapegoatTree::rebuild(link);
+                    too_deep = false;
+                }
+            }
+            (old, too_deep)
+        }
+
+        let mut ctx = Insert {
+            new: Some(Box::new(Node {
+                key,
+                value,
+                size: 1,
+                left: None,
+                right: None,
+            })),
+            max_depth: self.max_depth(self.len() + 1),
+            alpha: self.alpha,
+        };
+        let (old, too_deep) = insert(&mut self.root, 0, &mut ctx);
+        debug_assert!(
+            !too_deep,
+            "scapegoat must exist on the path to a too deep node"
+        );
+        if old.is_none() {
+            self.max_len = self.max_len.max(self.len());
+        }
+        old
+    }
+
+    /// Removes `key` from the tree.
+    ///
+    /// The whole tree is rebuilt if it has shrunk below `alpha` times its largest size.
+    pub fn delete<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        /// Removes the leftmost node of the subtree at `link`.
+        fn pop_first<K, V>(link: &mut Link<K, V>) -> Option<(K, V)> {
+            let node = link.as_mut()?;
+            if node.left.is_some() {
+                let item = pop_first(&mut node.left);
+                node.size -= 1;
+                return item;
+            }
+
+            let node = *link.take()?;
+            *link = node.right;
+            Some((node.key, node.value))
+        }
+
+        fn delete<K, V, Q>(link: &mut Link<K, V>, key: &Q) -> Option<(K, V)>
+        where
+            K: Borrow<Q>,
+            Q: Ord,
+        {
+            let node = link.as_mut()?;
+            let item = match key.cmp(node.key.borrow()) {
+                Ordering::Less => delete(&mut node.left, key),
+                Ordering::Greater => delete(&mut node.right, key),
+                Ordering::Equal => {
+                    if node.left.is_none() || node.right.is_none() {
+                        let node = *link.take()?;
+                        *link = node.left.or(node.right);
+                        return Some((node.key, node.value));
+                    }
+
+                    // replace with the successor, which has no left child
+                    let (key, value) = pop_first(&mut node.right)?;
+                    let old = (
+                        mem::replace(&mut node.key, key),
+                        mem::replace(&mut node.value, value),
+                    );
+                    node.update_size();
+                    return Some(old);
+                }
+            };
+            if item.is_some() {
+                node.size -= 1;
+            }
+            item
+        }
+
+        let item = delete(&mut self.root, key)?;
+        if (self.len() as f64) < self.alpha * self.max_len as f64 {
+            Self::rebuild(&mut self.root);
+            self.max_len = self.len();
+        }
+        Some(item)
+    }
+}
+
+impl<K, V> fmt::Debug for ScapegoatTree<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> IntoIterator for ScapegoatTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut items = Vec::with_capacity(self.len());
+        let mut stack = Stack::new();
+        let mut maybe_node = self.root;
+        loop {
+            while let Some(mut node) = maybe_node {
+                maybe_node = node.left.take();
+                stack.push(node);
+            }
+            let Some(node) = stack.pop() else {
+                break;
+            };
+            let node = *node;
+            maybe_node = node.right;
+            items.push((node.key, node.value));
+        }
+
+        IntoIter {
+            items: items.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a ScapegoatTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// In-order iterator over the items of `ScapegoatTree`, created by [`ScapegoatTree::iter`].
+pub struct Iter<'a, K, V> {
+    // Both ends walk the whole tree independently, `len` stops them
+    // before they cross each other.
+    front: Stack<&'a Node<K, V>>,
+    back: Stack<&'a Node<K, V>>,
+    len: usize,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    fn push_left_spine(&mut self, mut maybe_node: Option<&'a Node<K, V>>) {
+        while let Some(node) = maybe_node {
+            self.front.push(node);
+            maybe_node = node.left.as_deref();
+        }
+    }
+
+    fn push_right_spine(&mut self, mut maybe_node: Option<&'a Node<K, V>>) {
+        while let Some(node) = maybe_node {
+            self.back.push(node);
+            maybe_node = node.right.as_deref();
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.front.pop()?;
+        self.push_left_spine(node.right.as_deref());
+        self.len -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.back.pop()?;
+        self.push_right_spine(node.left.as_deref());
+        self.len -= 1;
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+/// Owning in-order iterator over the items of `ScapegoatTree`.
+pub struct IntoIter<K, V> {
+    items: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.items.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.items.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts the order, size and height invariants of `tree`.
+    fn assert_scapegoat<K: Ord, V>(tree: &ScapegoatTree<K, V>) {
+        fn check<K: Ord, V>(link: &Link<K, V>, low: Option<&K>, high: Option<&K>) -> usize {
+            let Some(node) = link else {
+                return 0;
+            };
+            assert!(low.is_none_or(|low| low < &node.key));
+            assert!(high.is_none_or(|high| &node.key < high));
+
+            let size = check(&node.left, low, Some(&node.key))
+                + check(&node.right, Some(&node.key), high)
+                + 1;
+            assert_eq!(node.size, size);
+            size
+        }
+
+        check(&tree.root, None, None);
+        assert!(tree.len() <= tree.max_len);
+        assert!(tree.height() <= tree.max_depth(tree.max_len) + 1);
+    }
+
+    #[test]
+    fn insert_get_delete() {
+        let mut tree = ScapegoatTree::new();
+        assert!(tree.is_empty());
+        assert_eq!(tree.get(&1), None);
+        assert_eq!(tree.delete(&1), None);
+
+        for it in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            assert_eq!(tree.insert(it, it * 10), None);
+            assert_scapegoat(&tree);
+        }
+        assert_eq!(tree.len(), 9);
+        assert_eq!(tree.insert(13, 131), Some((13, 130)));
+        assert_eq!(tree.get(&13), Some((&13, &131)));
+        *tree.get_mut(&13).unwrap().1 = 130;
+        assert!(tree.contains_key(&13));
+        assert!(!tree.contains_key(&14));
+
+        assert_eq!(
+            format!("{tree:?}"),
+            "{2: 20, 5: 50, 9: 90, 12: 120, 13: 130, 15: 150, 17: 170, 18: 180, 19: 190}"
+        );
+        assert_eq!(tree.first_key_value(), Some((&2, &20)));
+        assert_eq!(tree.last_key_value(), Some((&19, &190)));
+        assert_eq!(tree.successor(&13), Some((&15, &150)));
+        assert_eq!(tree.predecessor(&13), Some((&12, &120)));
+
+        assert_eq!(tree.delete(&12), Some((12, 120)));
+        assert_eq!(tree.delete(&12), None);
+        assert_eq!(tree.delete(&2), Some((2, 20)));
+        assert_scapegoat(&tree);
+        assert!(
+            tree.iter()
+                .rev()
+                .map(|(k, _)| *k)
+                .eq([19, 18, 17, 15, 13, 9, 5])
+        );
+        assert!(
+            tree.into_iter()
+                .map(|(k, _)| k)
+                .eq([5, 9, 13, 15, 17, 18, 19])
+        );
+    }
+
+    #[test]
+    fn sorted_inserts_are_balanced() {
+        for alpha in [0.5, 0.6, 0.75, 0.9] {
+            let mut tree = ScapegoatTree::with_alpha(alpha);
+            for it in 0..1000 {
+                tree.insert(it, ());
+                assert!(tree.height() <= tree.max_depth(tree.len()) + 1);
+            }
+            assert_scapegoat(&tree);
+        }
+
+        let mut tree = ScapegoatTree::with_alpha(0.5);
+        for it in 0..1023 {
+            tree.insert(it, ());
+        }
+        // perfectly balanced
+        assert_eq!(tree.height(), 10);
+    }
+
+    #[test]
+    fn delete_rebuilds() {
+        let mut tree = ScapegoatTree::new();
+        for it in 0..100 {
+            tree.insert(it, ());
+        }
+        assert_eq!(tree.max_len, 100);
+
+        for it in 0..30 {
+            tree.delete(&it);
+        }
+        assert_eq!(tree.max_len, 100);
+        // 69 < 0.7 * 100
+        tree.delete(&30);
+        assert_eq!(tree.max_len, 69);
+        assert_eq!(tree.height(), 7);
+        assert_scapegoat(&tree);
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.max_len, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "alpha must be in [0.5, 1)")]
+    fn invalid_alpha() {
+        ScapegoatTree::<i32, i32>::with_alpha(1.0);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        crate::proptest_suite::ordered_map_proptests!(ScapegoatTree::new(), assert_scapegoat);
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 200;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn any_alpha(
+                alpha in 0.5..1.0f64,
+                ops in proptest::collection::vec((any::<bool>(), 0..200i32), 0..MAP_SIZE),
+            ) {
+                let mut tree = ScapegoatTree::with_alpha(alpha);
+                let mut expected = std::collections::BTreeMap::new();
+                for (insert, key) in ops {
+                    if insert {
+                        assert_eq!(tree.insert(key, key), expected.insert(key, key).map(|v| (key, v)));
+                    } else {
+                        assert_eq!(tree.delete(&key), expected.remove_entry(&key));
+                    }
+                    assert_scapegoat(&tree);
+                }
+                assert!(tree.iter().eq(expected.iter()));
+            }
+        );
+    }
+}
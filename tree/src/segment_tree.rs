@@ -0,0 +1,630 @@
+//! Segment tree with lazy propagation
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+
+use vec::Vec2;
+
+/// An associative operation with an identity element.
+///
+/// `combine(identity(), a) == combine(a, identity()) == a` and
+/// `combine(combine(a, b), c) == combine(a, combine(b, c))` must hold.
+pub trait Monoid {
+    type Item: Clone;
+
+    fn identity() -> Self::Item;
+    fn combine(a: &Self::Item, b: &Self::Item) -> Self::Item;
+}
+
+/// An update that can be applied to a whole range of a [`SegmentTree`] at once.
+///
+/// Applying an update to the aggregate of a segment must give the same result
+/// as applying it to each item and combining them afterwards, i.e.
+/// `apply(combine(a, b), len_a + len_b) == combine(apply(a, len_a), apply(b, len_b))`.
+pub trait RangeUpdate<M: Monoid>: Clone {
+    /// Applies the update to an aggregate of `len` items.
+    fn apply(&self, item: &M::Item, len: usize) -> M::Item;
+
+    /// Returns an update equivalent to applying `self` and then `later`.
+    fn compose(&self, later: &Self) -> Self;
+}
+
+/// Numeric types usable with the provided monoids and updates.
+pub trait Number: Copy + PartialOrd {
+    const ZERO: Self;
+    const MIN: Self;
+    const MAX: Self;
+
+    fn add(self, other: Self) -> Self;
+    fn mul_len(self, len: usize) -> Self;
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*; $min:ident, $max:ident) => {
+        $(
+            impl Number for $t {
+                const ZERO: Self = 0 as $t;
+                const MIN: Self = <$t>::$min;
+                const MAX: Self = <$t>::$max;
+
+                fn add(self, other: Self) -> Self {
+                    self + other
+                }
+
+                fn mul_len(self, len: usize) -> Self {
+                    self * len as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize; MIN, MAX);
+impl_number!(f32, f64; NEG_INFINITY, INFINITY);
+
+/// Sum of the items.
+pub struct Sum<T>(PhantomData<T>);
+
+/// Minimum of the items, identity is `T::MIN`.
+pub struct Min<T>(PhantomData<T>);
+
+/// Maximum of the items, identity is `T::MAX`.
+pub struct Max<T>(PhantomData<T>);
+
+impl<T: Number> Monoid for Sum<T> {
+    type Item = T;
+
+    fn identity() -> T {
+        T::ZERO
+    }
+
+    fn combine(a: &T, b: &T) -> T {
+        a.add(*b)
+    }
+}
+
+impl<T: Number> Monoid for Min<T> {
+    type Item = T;
+
+    fn identity() -> T {
+        T::MAX
+    }
+
+    fn combine(a: &T, b: &T) -> T {
+        if b < a { *b } else { *a }
+    }
+}
+
+impl<T: Number> Monoid for Max<T> {
+    type Item = T;
+
+    fn identity() -> T {
+        T::MIN
+    }
+
+    fn combine(a: &T, b: &T) -> T {
+        if b > a { *b } else { *a }
+    }
+}
+
+/// Adds a value to every item in the range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeAdd<T>(pub T);
+
+/// Sets every item in the range to a value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeAssign<T>(pub T);
+
+impl<T: Number> RangeUpdate<Sum<T>> for RangeAdd<T> {
+    fn apply(&self, item: &T, len: usize) -> T {
+        item.add(self.0.mul_len(len))
+    }
+
+    fn compose(&self, later: &Self) -> Self {
+        Self(self.0.add(later.0))
+    }
+}
+
+impl<T: Number> RangeUpdate<Sum<T>> for RangeAssign<T> {
+    fn apply(&self, _item: &T, len: usize) -> T {
+        self.0.mul_len(len)
+    }
+
+    fn compose(&self, later: &Self) -> Self {
+        *later
+    }
+}
+
+macro_rules! impl_min_max_updates {
+    ($($m:ident),*) => {
+        $(
+            impl<T: Number> RangeUpdate<$m<T>> for RangeAdd<T> {
+                fn apply(&self, item: &T, _len: usize) -> T {
+                    item.add(self.0)
+                }
+
+                fn compose(&self, later: &Self) -> Self {
+                    Self(self.0.add(later.0))
+                }
+            }
+
+            impl<T: Number> RangeUpdate<$m<T>> for RangeAssign<T> {
+                fn apply(&self, _item: &T, _len: usize) -> T {
+                    self.0
+                }
+
+                fn compose(&self, later: &Self) -> Self {
+                    *later
+                }
+            }
+        )*
+    };
+}
+
+impl_min_max_updates!(Min, Max);
+
+/// A segment tree over the monoid `M` supporting range updates of type `U`.
+///
+/// Both range queries and range updates take `O(log n)` time. Updates are
+/// applied lazily, that is they are stored at the highest nodes fully covered by
+/// the range and pushed down to the children only when needed.
+pub struct SegmentTree<M: Monoid, U> {
+    // INVARIANTS:
+    //  * tree is stored as an implicit binary tree, root is at index 1 and
+    //    children of node `i` are at `2 * i` and `2 * i + 1`
+    //  * `nodes.len() == lazy.len() == 2 * width`, `width` is a power of two
+    //    and `len <= width`, leaves are at `[width, 2 * width)`
+    //  * leaves past `len` hold `M::identity()` and are never updated
+    //  * `nodes[i]` is the aggregate of its segment including all updates
+    //    stored in `lazy[i]`, but not the updates stored in its ancestors
+    //  * `lazy[i]` is the pending update which has not yet been applied to the
+    //    children of `i`, it's always `None` for leaves
+    nodes: Vec2<M::Item>,
+    lazy: Vec2<Option<U>>,
+    width: usize,
+    len: usize,
+}
+
+impl<M, U> SegmentTree<M, U>
+where
+    M: Monoid,
+    U: RangeUpdate<M>,
+{
+    /// Creates a tree of `len` items all equal to `M::identity()`.
+    pub fn new(len: usize) -> Self {
+        Self::from_items(len, std::iter::repeat_with(M::identity))
+    }
+
+    fn from_items(len: usize, mut items: impl Iterator<Item = M::Item>) -> Self {
+        let width = len.next_power_of_two();
+        let mut nodes = Vec2::with_capacity(2 * width);
+        let mut lazy = Vec2::with_capacity(2 * width);
+        for _ in 0..2 * width {
+            lazy.push(None);
+        }
+
+        // push dummy internal nodes first, they are computed below
+        for _ in 0..width {
+            nodes.push(M::identity());
+        }
+        for _ in 0..len {
+            nodes.push(items.next().expect("iterator to yield `len` items"));
+        }
+        for _ in len..width {
+            nodes.push(M::identity());
+        }
+
+        let mut tree = Self {
+            nodes,
+            lazy,
+            width,
+            len,
+        };
+        for node in (1..width).rev() {
+            tree.pull(node);
+        }
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the item at `index`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `index >= self.len()`
+    pub fn get(&self, index: usize) -> M::Item {
+        self.query(index..=index)
+    }
+
+    /// Returns the combination of all items in `range`, or `M::identity()` for
+    /// an empty range.
+    ///
+    /// # PANICS
+    ///
+    /// * if `range` is out of bounds or its start is after its end
+    pub fn query<R>(&self, range: R) -> M::Item
+    where
+        R: RangeBounds<usize>,
+    {
+        let (start, end) = self.resolve(range);
+        if start == end {
+            return M::identity();
+        }
+        self.query_node(1, 0, self.width, start, end)
+    }
+
+    /// Sets the item at `index` to `value`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `index >= self.len()`
+    pub fn set(&mut self, index: usize, value: M::Item) {
+        assert!(
+            index < self.len,
+            "index out of bounds: the len is {} but the index is {index}",
+            self.len
+        );
+
+        // push pending updates down the path to the leaf
+        let leaf = self.width + index;
+        let height = self.width.trailing_zeros();
+        for shift in (1..=height).rev() {
+            let node = leaf >> shift;
+            self.push(node, 1 << shift);
+        }
+
+        self.nodes.as_mut_slice()[leaf] = value;
+        let mut node = leaf / 2;
+        while node > 0 {
+            self.pull(node);
+            node /= 2;
+        }
+    }
+
+    /// Applies `update` to every item in `range`.
+    ///
+    /// # PANICS
+    ///
+    /// * if `range` is out of bounds or its start is after its end
+    pub fn update<R>(&mut self, range: R, update: U)
+    where
+        R: RangeBounds<usize>,
+    {
+        let (start, end) = self.resolve(range);
+        if start == end {
+            return;
+        }
+        self.update_node(1, 0, self.width, start, end, &update);
+    }
+
+    /// Iterate over all the items in order.
+    ///
+    /// Takes `&mut self` since all pending updates are pushed down to the leaves first.
+    pub fn iter(&mut self) -> std::slice::Iter<'_, M::Item> {
+        for node in 1..self.width {
+            let len = self.width >> (usize::BITS - 1 - node.leading_zeros());
+            self.push(node, len);
+        }
+        self.nodes.as_slice()[self.width..self.width + self.len].iter()
+    }
+
+    fn resolve<R>(&self, range: R) -> (usize, usize)
+    where
+        R: RangeBounds<usize>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s.checked_add(1).expect("range start to not overflow"),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&e) => e.checked_add(1).expect("range end to not overflow"),
+            Bound::Excluded(&e) => e,
+            Bound::Unbounded => self.len,
+        };
+        assert!(start <= end, "range start {start} is after its end {end}");
+        assert!(
+            end <= self.len,
+            "range end {end} out of bounds for len {}",
+            self.len
+        );
+        (start, end)
+    }
+
+    /// Query `[start, end)` in subtree of `node` which covers `[node_start, node_end)`.
+    ///
+    /// The ranges must overlap.
+    fn query_node(
+        &self,
+        node: usize,
+        node_start: usize,
+        node_end: usize,
+        start: usize,
+        end: usize,
+    ) -> M::Item {
+        if start <= node_start && node_end <= end {
+            return self.nodes.as_slice()[node].clone();
+        }
+
+        let mid = node_start + (node_end - node_start) / 2;
+        let result = if end <= mid {
+            self.query_node(2 * node, node_start, mid, start, end)
+        } else if mid <= start {
+            self.query_node(2 * node + 1, mid, node_end, start, end)
+        } else {
+            M::combine(
+                &self.query_node(2 * node, node_start, mid, start, end),
+                &self.query_node(2 * node + 1, mid, node_end, start, end),
+            )
+        };
+
+        // Pending update of this node hasn't been applied to the children yet,
+        // apply it to the part of the range that is in this subtree instead.
+        match &self.lazy.as_slice()[node] {
+            Some(update) => {
+                let overlap = end.min(node_end) - start.max(node_start);
+                update.apply(&result, overlap)
+            }
+            None => result,
+        }
+    }
+
+    fn update_node(
+        &mut self,
+        node: usize,
+        node_start: usize,
+        node_end: usize,
+        start: usize,
+        end: usize,
+        update: &U,
+    ) {
+        if end <= node_start || node_end <= start {
+            return;
+        }
+        if start <= node_start && node_end <= end {
+            self.apply(node, node_end - node_start, update);
+            return;
+        }
+
+        self.push(node, node_end - node_start);
+        let mid = node_start + (node_end - node_start) / 2;
+        self.update_node(2 * node, node_start, mid, start, end, update);
+        self.update_node(2 * node + 1, mid, node_end, start, end, update);
+        self.pull(node);
+    }
+
+    /// Applies `update` to `node` which covers `len` leaves.
+    fn apply(&mut self, node: usize, len: usize, update: &U) {
+        let item = &mut self.nodes.as_mut_slice()[node];
+        *item = update.apply(item, len);
+        if node < self.width {
+            let lazy = &mut self.lazy.as_mut_slice()[node];
+            *lazy = Some(match lazy.take() {
+                Some(pending) => pending.compose(update),
+                None => update.clone(),
+            });
+        }
+    }
+
+    /// Moves the pending update of `node` which covers `len` leaves to its children.
+    fn push(&mut self, node: usize, len: usize) {
+        if let Some(update) = self.lazy.as_mut_slice()[node].take() {
+            self.apply(2 * node, len / 2, &update);
+            self.apply(2 * node + 1, len / 2, &update);
+        }
+    }
+
+    /// Recomputes the aggregate of `node` from its children.
+    fn pull(&mut self, node: usize) {
+        let nodes = self.nodes.as_mut_slice();
+        nodes[node] = M::combine(&nodes[2 * node], &nodes[2 * node + 1]);
+    }
+}
+
+impl<M, U> FromIterator<M::Item> for SegmentTree<M, U>
+where
+    M: Monoid,
+    U: RangeUpdate<M>,
+{
+    fn from_iter<I: IntoIterator<Item = M::Item>>(iter: I) -> Self {
+        let items: Vec<_> = iter.into_iter().collect();
+        Self::from_items(items.len(), items.into_iter())
+    }
+}
+
+impl<M, U> fmt::Debug for SegmentTree<M, U>
+where
+    M: Monoid,
+    M::Item: fmt::Debug,
+    U: RangeUpdate<M>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries((0..self.len).map(|i| self.get(i)))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sum_add() {
+        let mut tree: SegmentTree<Sum<i64>, RangeAdd<i64>> = (1..=10).collect();
+        assert_eq!(tree.len(), 10);
+        assert_eq!(tree.query(..), 55);
+        assert_eq!(tree.query(2..5), 3 + 4 + 5);
+        assert_eq!(tree.query(3..3), 0);
+
+        tree.update(2..=4, RangeAdd(10));
+        assert_eq!(tree.query(..), 85);
+        assert_eq!(tree.query(3..), 85 - 1 - 2 - 13);
+        assert_eq!(tree.get(4), 15);
+
+        tree.set(3, 0);
+        assert_eq!(tree.query(2..5), 13 + 15);
+        assert!(tree.iter().eq(&[1, 2, 13, 0, 15, 6, 7, 8, 9, 10]));
+        assert_eq!(format!("{tree:?}"), "[1, 2, 13, 0, 15, 6, 7, 8, 9, 10]");
+    }
+
+    #[test]
+    fn min_max_assign() {
+        let mut min: SegmentTree<Min<i32>, RangeAssign<i32>> = SegmentTree::new(5);
+        let mut max: SegmentTree<Max<i32>, RangeAssign<i32>> = SegmentTree::new(5);
+        assert_eq!(min.query(..), i32::MAX);
+        assert_eq!(max.query(..), i32::MIN);
+
+        for (i, v) in [4, 2, 7, 1, 9].into_iter().enumerate() {
+            min.set(i, v);
+            max.set(i, v);
+        }
+        assert_eq!(min.query(..), 1);
+        assert_eq!(max.query(..3), 7);
+
+        min.update(1..4, RangeAssign(5));
+        max.update(1..4, RangeAssign(5));
+        assert_eq!(min.query(..), 4);
+        assert_eq!(max.query(1..4), 5);
+        assert_eq!(max.query(..), 9);
+    }
+
+    #[test]
+    fn empty() {
+        let mut tree: SegmentTree<Sum<f64>, RangeAdd<f64>> = SegmentTree::new(0);
+        assert!(tree.is_empty());
+        assert_eq!(tree.query(..), 0.0);
+        tree.update(.., RangeAdd(1.0));
+        assert_eq!(tree.iter().len(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn query_out_of_bounds() {
+        let tree: SegmentTree<Sum<i32>, RangeAdd<i32>> = SegmentTree::new(3);
+        tree.query(1..4);
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const TREE_SIZE: usize = 200;
+        #[cfg(miri)]
+        const TREE_SIZE: usize = 20;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        #[derive(Debug, Clone)]
+        enum Op {
+            Set(usize, i64),
+            Add(usize, usize, i64),
+            Assign(usize, usize, i64),
+            Query(usize, usize),
+        }
+
+        fn ops(len: usize) -> impl Strategy<Value = Vec<Op>> {
+            let range = (0..=len, 0..=len).prop_map(|(a, b)| (a.min(b), a.max(b)));
+            let op = prop_oneof![
+                (0..len.max(1), -100..100i64).prop_map(|(i, v)| Op::Set(i, v)),
+                (range.clone(), -100..100i64).prop_map(|((s, e), v)| Op::Add(s, e, v)),
+                (range.clone(), -100..100i64).prop_map(|((s, e), v)| Op::Assign(s, e, v)),
+                range.prop_map(|(s, e)| Op::Query(s, e)),
+            ];
+            proptest::collection::vec(op, 0..100)
+        }
+
+        fn items_and_ops() -> impl Strategy<Value = (Vec<i64>, Vec<Op>)> {
+            proptest::collection::vec(-100..100i64, 0..TREE_SIZE).prop_flat_map(|items| {
+                let len = items.len();
+                (Just(items), ops(len))
+            })
+        }
+
+        /// Runs `ops` against a `SegmentTree<M, U>` and a plain vector.
+        ///
+        /// `U` can only be one of the updates so `Add` or `Assign` are
+        /// skipped based on `is_add`.
+        fn check<M, U>(
+            mut items: Vec<i64>,
+            ops: &[Op],
+            make_update: impl Fn(i64) -> U,
+            is_add: bool,
+            reference: impl Fn(&[i64]) -> i64,
+        ) where
+            M: Monoid<Item = i64>,
+            U: RangeUpdate<M>,
+        {
+            let mut tree: SegmentTree<M, U> = items.iter().copied().collect();
+            for op in ops {
+                match *op {
+                    Op::Set(i, v) if i < items.len() => {
+                        tree.set(i, v);
+                        items[i] = v;
+                    }
+                    Op::Set(..) => {}
+                    Op::Add(s, e, v) if is_add => {
+                        tree.update(s..e, make_update(v));
+                        items[s..e].iter_mut().for_each(|it| *it += v);
+                    }
+                    Op::Assign(s, e, v) if !is_add => {
+                        tree.update(s..e, make_update(v));
+                        items[s..e].iter_mut().for_each(|it| *it = v);
+                    }
+                    Op::Add(..) | Op::Assign(..) => {}
+                    Op::Query(s, e) => {
+                        assert_eq!(tree.query(s..e), reference(&items[s..e]));
+                    }
+                }
+            }
+            assert!(tree.iter().eq(items.iter()));
+        }
+
+        fn sum(items: &[i64]) -> i64 {
+            items.iter().sum()
+        }
+
+        fn min(items: &[i64]) -> i64 {
+            items.iter().copied().min().unwrap_or(i64::MAX)
+        }
+
+        fn max(items: &[i64]) -> i64 {
+            items.iter().copied().max().unwrap_or(i64::MIN)
+        }
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn sum_against_vec((items, ops) in items_and_ops()) {
+                check::<Sum<i64>, _>(items.clone(), &ops, RangeAdd, true, sum);
+                check::<Sum<i64>, _>(items, &ops, RangeAssign, false, sum);
+            }
+
+            #[test]
+            fn min_against_vec((items, ops) in items_and_ops()) {
+                check::<Min<i64>, _>(items.clone(), &ops, RangeAdd, true, min);
+                check::<Min<i64>, _>(items, &ops, RangeAssign, false, min);
+            }
+
+            #[test]
+            fn max_against_vec((items, ops) in items_and_ops()) {
+                check::<Max<i64>, _>(items.clone(), &ops, RangeAdd, true, max);
+                check::<Max<i64>, _>(items, &ops, RangeAssign, false, max);
+            }
+        );
+    }
+}
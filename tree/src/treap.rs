@@ -0,0 +1,904 @@
+//! Randomized treap
+//!
+//! Binary search tree by keys and a max-heap by randomly assigned priorities.
+//! The random priorities make the shape of the tree the same as if the keys
+//! were inserted in a random order, so the expected height is O(log n) for
+//! any insertion order. Inserts and deletes restore the heap property with rotations.
+//!
+//! Unlike the other trees in this crate, the nodes are owned by their parents
+//! with `Box`es and there are no parent links, so all the operations are safe code.
+//! The operations recurse along a single path, so the recursion depth is the height of the tree.
+
+use core::fmt;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::mem;
+
+use vec::Stack;
+
+type Link<K, V> = Option<Box<Node<K, V>>>;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    priority: u64,
+    /// Number of nodes in the subtree rooted at this node
+    size: usize,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+impl<K, V> Node<K, V> {
+    fn new(key: K, value: V, priority: u64) -> Box<Self> {
+        Box::new(Self {
+            key,
+            value,
+            priority,
+            size: 1,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update_size(&mut self) {
+        self.size = size_of(&self.left) + size_of(&self.right) + 1;
+    }
+}
+
+fn size_of<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+fn priority_of<K, V>(link: &Link<K, V>) -> Option<u64> {
+    link.as_ref().map(|node| node.priority)
+}
+
+/// Ordered map backed by a randomized treap.
+///
+/// Lookups, inserts, removals, `split_off` and `append` are expected O(log n).
+pub struct Treap<K, V> {
+    // INVARIANTS:
+    //  * keys are in binary search tree order and unique
+    //  * priority of every node is >= the priorities of its children
+    //  * `size` of every node is the number of nodes in its subtree
+    root: Link<K, V>,
+    rng: XorShift64,
+}
+
+impl<K, V> Default for Treap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Treap<K, V> {
+    /// Creates an empty treap with randomly seeded priorities.
+    pub fn new() -> Self {
+        let seed = RandomState::new().build_hasher().finish();
+        Self::with_seed(seed)
+    }
+
+    /// Creates an empty treap whose priorities are generated from `seed`,
+    /// so the same sequence of operations always builds the same tree.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            root: None,
+            rng: XorShift64::new(seed),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        size_of(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Number of nodes on the longest path from the root to a leaf.
+    pub fn height(&self) -> usize {
+        fn height<K, V>(link: &Link<K, V>) -> usize {
+            match link {
+                Some(node) => 1 + height(&node.left).max(height(&node.right)),
+                None => 0,
+            }
+        }
+
+        height(&self.root)
+    }
+
+    /// Iterate over the items in ascending key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut iter = Iter {
+            front: Stack::new(),
+            back: Stack::new(),
+            len: self.len(),
+        };
+        iter.push_left_spine(self.root.as_deref());
+        iter.push_right_spine(self.root.as_deref());
+        iter
+    }
+
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = self.root.as_deref()?;
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+        Some((&node.key, &node.value))
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        /// Removes the leftmost node of the subtree at `link`.
+        fn pop_first<K, V>(link: &mut Link<K, V>) -> Option<(K, V)> {
+            let node = link.as_mut()?;
+            if node.left.is_some() {
+                let item = pop_first(&mut node.left);
+                node.size -= 1;
+                return item;
+            }
+
+            let node = *link.take()?;
+            *link = node.right;
+            Some((node.key, node.value))
+        }
+
+        pop_first(&mut self.root)
+    }
+
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        /// Removes the rightmost node of the subtree at `link`.
+        fn pop_last<K, V>(link: &mut Link<K, V>) -> Option<(K, V)> {
+            let node = link.as_mut()?;
+            if node.right.is_some() {
+                let item = pop_last(&mut node.right);
+                node.size -= 1;
+                return item;
+            }
+
+            let node = *link.take()?;
+            *link = node.left;
+            Some((node.key, node.value))
+        }
+
+        pop_last(&mut self.root)
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.root = None;
+    }
+
+    /// Returns the item with `rank`, that is the `rank`-th smallest item (starting from 0).
+    pub fn select(&self, rank: usize) -> Option<(&K, &V)> {
+        let mut rank = rank;
+        let mut node = self.root.as_deref()?;
+        loop {
+            let left_size = size_of(&node.left);
+            match rank.cmp(&left_size) {
+                Ordering::Less => node = node.left.as_deref()?,
+                Ordering::Equal => return Some((&node.key, &node.value)),
+                Ordering::Greater => {
+                    rank -= left_size + 1;
+                    node = node.right.as_deref()?;
+                }
+            }
+        }
+    }
+
+    /// Joins two treaps where all keys in `left` are smaller than all keys in `right`.
+    fn merge(left: Link<K, V>, right: Link<K, V>) -> Link<K, V> {
+        match (left, right) {
+            (None, right) => right,
+            (left, None) => left,
+            (Some(mut left), Some(mut right)) => {
+                // node with the larger priority must be the root
+                if left.priority > right.priority {
+                    left.right = Self::merge(left.right.take(), Some(right));
+                    left.update_size();
+                    Some(left)
+                } else {
+                    right.left = Self::merge(Some(left), right.left.take());
+                    right.update_size();
+                    Some(right)
+                }
+            }
+        }
+    }
+
+    fn rotate_left(link: &mut Link<K, V>) {
+        let mut node = link.take().expect("cannot rotate an empty subtree");
+        let mut right = node
+            .right
+            .take()
+            .expect("right child must exist to rotate left");
+        node.right = right.left.take();
+        node.update_size();
+        right.left = Some(node);
+        right.update_size();
+        *link = Some(right);
+    }
+
+    fn rotate_right(link: &mut Link<K, V>) {
+        let mut node = link.take().expect("cannot rotate an empty subtree");
+        let mut left = node
+            .left
+            .take()
+            .expect("left child must exist to rotate right");
+        node.left = left.right.take();
+        node.update_size();
+        left.right = Some(node);
+        left.update_size();
+        *link = Some(left);
+    }
+
+    /// Removes the root of the subtree at `link`.
+    ///
+    /// The node is rotated down towards the child with the larger priority,
+    /// which keeps the heap property, until it has at most one child and can be unlinked.
+    fn remove_root(link: &mut Link<K, V>) -> Option<(K, V)> {
+        let node = link.as_mut()?;
+        let rotate_right = match (priority_of(&node.left), priority_of(&node.right)) {
+            (None, _) => {
+                let node = *link.take()?;
+                *link = node.right;
+                return Some((node.key, node.value));
+            }
+            (_, None) => {
+                let node = *link.take()?;
+                *link = node.left;
+                return Some((node.key, node.value));
+            }
+            (Some(left), Some(right)) => left > right,
+        };
+
+        let item;
+        if rotate_right {
+            Self::rotate_right(link);
+            let new_root = link.as_mut()?;
+            item = Self::remove_root(&mut new_root.right);
+            new_root.size -= 1;
+        } else {
+            Self::rotate_left(link);
+            let new_root = link.as_mut()?;
+            item = Self::remove_root(&mut new_root.left);
+            new_root.size -= 1;
+        }
+        item
+    }
+}
+
+impl<K, V> Treap<K, V>
+where
+    K: Ord,
+{
+    pub fn get<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let node = self.get_node(key)?;
+        Some((&node.key, &node.value))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<(&K, &mut V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut link = &mut self.root;
+        while let Some(node) = link {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => link = &mut node.left,
+                Ordering::Equal => return Some((&node.key, &mut node.value)),
+                Ordering::Greater => link = &mut node.right,
+            }
+        }
+
+        None
+    }
+
+    fn get_node<Q>(&self, key: &Q) -> Option<&Node<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut maybe_node = self.root.as_deref();
+        while let Some(node) = maybe_node {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => maybe_node = node.left.as_deref(),
+                Ordering::Equal => return Some(node),
+                Ordering::Greater => maybe_node = node.right.as_deref(),
+            }
+        }
+
+        None
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        self.get_node(key).is_some()
+    }
+
+    /// Returns the item after `key` in key order, if `key` is in the treap.
+    pub fn successor<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut found = None;
+        let mut maybe_node = self.root.as_deref();
+        while let Some(node) = maybe_node {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => {
+                    found = Some(node);
+                    maybe_node = node.left.as_deref();
+                }
+                Ordering::Equal => {
+                    // smallest item in the right subtree or the last ancestor we went left at
+                    let mut next = node.right.as_deref();
+                    while let Some(n) = next {
+                        found = Some(n);
+                        next = n.left.as_deref();
+                    }
+                    return found.map(|node| (&node.key, &node.value));
+                }
+                Ordering::Greater => maybe_node = node.right.as_deref(),
+            }
+        }
+
+        None
+    }
+
+    /// Returns the item before `key` in key order, if `key` is in the treap.
+    pub fn predecessor<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut found = None;
+        let mut maybe_node = self.root.as_deref();
+        while let Some(node) = maybe_node {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => maybe_node = node.left.as_deref(),
+                Ordering::Equal => {
+                    // largest item in the left subtree or the last ancestor we went right at
+                    let mut prev = node.left.as_deref();
+                    while let Some(n) = prev {
+                        found = Some(n);
+                        prev = n.right.as_deref();
+                    }
+                    return found.map(|node| (&node.key, &node.value));
+                }
+                Ordering::Greater => {
+                    found = Some(node);
+                    maybe_node = node.right.as_deref();
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the rank of `key`, that is the number of smaller keys in the treap.
+    pub fn rank<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        let mut rank = 0;
+        let mut maybe_node = self.root.as_deref();
+        while let Some(node) = maybe_node {
+            match key.cmp(node.key.borrow()) {
+                Ordering::Less => maybe_node = node.left.as_deref(),
+                Ordering::Equal => return Some(rank + size_of(&node.left)),
+                Ordering::Greater => {
+                    rank += size_of(&node.left) + 1;
+                    maybe_node = node.right.as_deref();
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Inserts `key` and `value` into the treap.
+    ///
+    /// If an equal key already exists, both the key and value are replaced
+    /// and the old entry is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        /// Inserts `new` as a leaf and rotates it up while its priority is
+        /// larger than its parent's.
+        fn insert<K: Ord, V>(link: &mut Link<K, V>, new: Box<Node<K, V>>) -> Option<(K, V)> {
+            let Some(node) = link else {
+                *link = Some(new);
+                return None;
+            };
+
+            match new.key.cmp(&node.key) {
+                Ordering::Equal => {
+                    let new = *new;
+                    Some((
+                        mem::replace(&mut node.key, new.key),
+                        mem::replace(&mut node.value, new.value),
+                    ))
+                }
+                Ordering::Less => {
+                    let old = insert(&mut node.left, new);
+                    if old.is_none() {
+                        node.size += 1;
+                        if priority_of(&node.left) > Some(node.priority) {
+                            Treap::rotate_right(link);
+                        }
+                    }
+                    old
+                }
+                Ordering::Greater => {
+                    let old = insert(&mut node.right, new);
+                    if old.is_none() {
+                        node.size += 1;
+                        if priority_of(&node.right) > Some(node.priority) {
+                            Treap::rotate_left(link);
+                        }
+                    }
+                    old
+                }
+            }
+        }
+
+        let priority = self.rng.next();
+        insert(&mut self.root, Node::new(key, value, priority))
+    }
+
+    /// Removes `key` from the treap.
+    pub fn delete<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        fn delete<K, V, Q>(link: &mut Link<K, V>, key: &Q) -> Option<(K, V)>
+        where
+            K: Borrow<Q>,
+            Q: Ord,
+        {
+            let node = link.as_mut()?;
+            let item = match key.cmp(node.key.borrow()) {
+                Ordering::Less => delete(&mut node.left, key),
+                Ordering::Greater => delete(&mut node.right, key),
+                Ordering::Equal => return Treap::remove_root(link),
+            };
+            if item.is_some() {
+                node.size -= 1;
+            }
+            item
+        }
+
+        delete(&mut self.root, key)
+    }
+
+    /// Moves all items with keys `>= key` into a new treap.
+    ///
+    /// Cuts the treap along the search path of `key` in expected O(log n).
+    pub fn split_off<Q>(&mut self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Ord,
+    {
+        /// Splits the subtree at `link` into the items `< key` and `>= key`.
+        ///
+        /// Every node on the search path keeps its subtree on the other side
+        /// of the path, so both halves remain valid treaps.
+        fn split<K, V, Q>(link: Link<K, V>, key: &Q) -> (Link<K, V>, Link<K, V>)
+        where
+            K: Borrow<Q>,
+            Q: Ord,
+        {
+            let Some(mut node) = link else {
+                return (None, None);
+            };
+
+            if node.key.borrow() < key {
+                let (left, right) = split(node.right.take(), key);
+                node.right = left;
+                node.update_size();
+                (Some(node), right)
+            } else {
+                let (left, right) = split(node.left.take(), key);
+                node.left = right;
+                node.update_size();
+                (left, Some(node))
+            }
+        }
+
+        let (left, right) = split(self.root.take(), key);
+        self.root = left;
+        Self {
+            root: right,
+            rng: XorShift64::new(self.rng.next()),
+        }
+    }
+
+    /// Moves all items from `other` into `self`, leaving `other` empty.
+    ///
+    /// If all keys of one treap are smaller than the keys of the other, they
+    /// are merged in expected O(log n). Otherwise the items of `other` are
+    /// inserted one by one and the values of `other` replace the values of equal keys in `self`.
+    pub fn append(&mut self, other: &mut Self) {
+        let (Some((self_min, _)), Some((self_max, _))) =
+            (self.first_key_value(), self.last_key_value())
+        else {
+            mem::swap(&mut self.root, &mut other.root);
+            return;
+        };
+        let (Some((other_min, _)), Some((other_max, _))) =
+            (other.first_key_value(), other.last_key_value())
+        else {
+            return;
+        };
+
+        if self_max < other_min {
+            self.root = Self::merge(self.root.take(), other.root.take());
+        } else if other_max < self_min {
+            self.root = Self::merge(other.root.take(), self.root.take());
+        } else {
+            while let Some((k, v)) = other.pop_first() {
+                self.insert(k, v);
+            }
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Treap<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> IntoIterator for Treap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { tree: self }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a Treap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Pseudo random generator for the priorities, xorshift64 by George Marsaglia.
+struct XorShift64 {
+    state: u64,
+}
+
+impl XorShift64 {
+    fn new(seed: u64) -> Self {
+        // zero state would only ever produce zeros
+        Self { state: seed.max(1) }
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+}
+
+/// In-order iterator over the items of `Treap`, created by [`Treap::iter`].
+pub struct Iter<'a, K, V> {
+    // Both ends walk the whole tree independently, `len` stops them
+    // before they cross each other.
+    front: Stack<&'a Node<K, V>>,
+    back: Stack<&'a Node<K, V>>,
+    len: usize,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    fn push_left_spine(&mut self, mut maybe_node: Option<&'a Node<K, V>>) {
+        while let Some(node) = maybe_node {
+            self.front.push(node);
+            maybe_node = node.left.as_deref();
+        }
+    }
+
+    fn push_right_spine(&mut self, mut maybe_node: Option<&'a Node<K, V>>) {
+        while let Some(node) = maybe_node {
+            self.back.push(node);
+            maybe_node = node.right.as_deref();
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.front.pop()?;
+        self.push_left_spine(node.right.as_deref());
+        self.len -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let node = self.back.pop()?;
+        self.push_right_spine(node.left.as_deref());
+        self.len -= 1;
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+/// Owning in-order iterator over the items of `Treap`.
+pub struct IntoIter<K, V> {
+    tree: Treap<K, V>,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tree.pop_first()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.tree.len(), Some(self.tree.len()))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.tree.pop_last()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts the order, heap and size invariants of `tree`.
+    fn assert_treap<K: Ord, V>(tree: &Treap<K, V>) {
+        fn check<K: Ord, V>(
+            link: &Link<K, V>,
+            low: Option<&K>,
+            high: Option<&K>,
+            max_priority: u64,
+        ) -> usize {
+            let Some(node) = link else {
+                return 0;
+            };
+            assert!(low.is_none_or(|low| low < &node.key));
+            assert!(high.is_none_or(|high| &node.key < high));
+            assert!(node.priority <= max_priority);
+
+            let size = check(&node.left, low, Some(&node.key), node.priority)
+                + check(&node.right, Some(&node.key), high, node.priority)
+                + 1;
+            assert_eq!(node.size, size);
+            size
+        }
+
+        check(&tree.root, None, None, u64::MAX);
+    }
+
+    #[test]
+    fn insert_get_delete() {
+        let mut tree = Treap::with_seed(42);
+        assert!(tree.is_empty());
+        assert_eq!(tree.get(&1), None);
+        assert_eq!(tree.delete(&1), None);
+
+        for it in [12, 5, 9, 2, 18, 15, 13, 17, 19] {
+            assert_eq!(tree.insert(it, it * 10), None);
+            assert_treap(&tree);
+        }
+        assert_eq!(tree.len(), 9);
+        assert_eq!(tree.insert(13, 131), Some((13, 130)));
+        assert_eq!(tree.get(&13), Some((&13, &131)));
+        *tree.get_mut(&13).unwrap().1 = 130;
+        assert!(tree.contains_key(&13));
+        assert!(!tree.contains_key(&14));
+
+        assert!(
+            tree.iter()
+                .map(|(k, _)| *k)
+                .eq([2, 5, 9, 12, 13, 15, 17, 18, 19])
+        );
+        assert_eq!(
+            format!("{tree:?}"),
+            "{2: 20, 5: 50, 9: 90, 12: 120, 13: 130, 15: 150, 17: 170, 18: 180, 19: 190}"
+        );
+
+        assert_eq!(tree.successor(&13), Some((&15, &150)));
+        assert_eq!(tree.successor(&19), None);
+        assert_eq!(tree.successor(&14), None);
+        assert_eq!(tree.predecessor(&13), Some((&12, &120)));
+        assert_eq!(tree.predecessor(&2), None);
+        assert_eq!(tree.select(4), Some((&13, &130)));
+        assert_eq!(tree.rank(&13), Some(4));
+        assert_eq!(tree.rank(&14), None);
+
+        assert_eq!(tree.delete(&12), Some((12, 120)));
+        assert_eq!(tree.delete(&12), None);
+        assert_treap(&tree);
+        assert_eq!(tree.pop_first(), Some((2, 20)));
+        assert_eq!(tree.pop_last(), Some((19, 190)));
+        assert_treap(&tree);
+        assert_eq!(tree.len(), 6);
+        assert!(
+            tree.into_iter()
+                .rev()
+                .map(|(k, _)| k)
+                .eq([18, 17, 15, 13, 9, 5])
+        );
+    }
+
+    #[test]
+    fn iter() {
+        let mut tree = Treap::new();
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.iter().next_back(), None);
+
+        for it in 0..10 {
+            tree.insert(it, it);
+        }
+
+        // meet in the middle
+        let mut iter = tree.iter();
+        assert_eq!(iter.next(), Some((&0, &0)));
+        assert_eq!(iter.next_back(), Some((&9, &9)));
+        assert_eq!(iter.len(), 8);
+        assert_eq!(iter.by_ref().rev().take(7).count(), 7);
+        assert_eq!(iter.next(), Some((&1, &1)));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn sorted_inserts_are_balanced() {
+        let mut tree = Treap::with_seed(1);
+        for it in 0..10_000 {
+            tree.insert(it, ());
+        }
+        assert_treap(&tree);
+        // expected height is about 3 * log2(n) ~ 40, while a plain BST would have 10000
+        assert!(tree.height() < 100, "height {}", tree.height());
+
+        while tree.pop_first().is_some() {}
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn split_off_append() {
+        let mut tree = Treap::new();
+        for it in 0..20 {
+            tree.insert(it, it);
+        }
+
+        let mut high = tree.split_off(&12);
+        assert_treap(&tree);
+        assert_treap(&high);
+        assert!(tree.iter().map(|(k, _)| *k).eq(0..12));
+        assert!(high.iter().map(|(k, _)| *k).eq(12..20));
+
+        tree.append(&mut high);
+        assert!(high.is_empty());
+        assert_treap(&tree);
+        assert!(tree.iter().map(|(k, _)| *k).eq(0..20));
+
+        // overlapping keys are inserted one by one
+        let mut other = Treap::new();
+        other.insert(5, 50);
+        other.insert(25, 250);
+        tree.append(&mut other);
+        assert!(other.is_empty());
+        assert_treap(&tree);
+        assert_eq!(tree.len(), 21);
+        assert_eq!(tree.get(&5), Some((&5, &50)));
+    }
+
+    mod proptests {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        crate::proptest_suite::ordered_map_proptests!(Treap::new(), assert_treap);
+
+        #[cfg(not(miri))]
+        const MAP_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const MAP_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 1000;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn split_off_append(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+                key in 0..10000i32,
+            ) {
+                let mut tree = Treap::new();
+                let mut expected = std::collections::BTreeMap::new();
+                for v in &inserts {
+                    tree.insert(*v, *v);
+                    expected.insert(*v, *v);
+                }
+
+                let mut high = tree.split_off(&key);
+                let expected_high = expected.split_off(&key);
+                assert_treap(&tree);
+                assert_treap(&high);
+                assert!(tree.iter().eq(expected.iter()));
+                assert!(high.iter().eq(expected_high.iter()));
+
+                tree.append(&mut high);
+                expected.extend(expected_high);
+                assert_treap(&tree);
+                assert!(tree.iter().eq(expected.iter()));
+            }
+
+            #[test]
+            fn select_rank(
+                inserts in proptest::collection::vec(0..10000i32, 0..MAP_SIZE),
+            ) {
+                let mut tree = Treap::new();
+                for v in &inserts {
+                    tree.insert(*v, *v);
+                }
+
+                let sorted = tree.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+                for (i, k) in sorted.iter().enumerate() {
+                    assert_eq!(tree.select(i), Some((k, k)));
+                    assert_eq!(tree.rank(k), Some(i));
+                }
+                assert_eq!(tree.select(sorted.len()), None);
+            }
+        );
+    }
+}
@@ -0,0 +1,551 @@
+//! van Emde Boas tree for integer keys
+//!
+//! A node over a universe of `2^bits` keys splits each key into its high and
+//! low halves of bits. The high half selects a cluster, which is a tree over
+//! the low half, and a summary tree over the high halves keeps track of the
+//! non-empty clusters. Every operation recurses into only one of them, so it
+//! takes `O(log bits) = O(log log U)` steps instead of `O(log n)` comparisons.
+//!
+//! The minimum of a node is not stored in any cluster which makes inserting
+//! into an empty cluster `O(1)`. Clusters are created lazily and kept in a hash
+//! map so that memory use is proportional to the number of keys and not `U`.
+
+use core::fmt;
+use std::collections::HashMap;
+
+/// Unsigned integer types usable as keys of a [`VebTree`].
+pub trait Key: Copy + Ord {
+    const BITS: u32;
+
+    fn to_u64(self) -> u64;
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! impl_key {
+    ($($t:ty),*) => {
+        $(
+            impl Key for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_u64(value: u64) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_key!(u8, u16, u32, u64);
+
+struct Node {
+    // INVARIANTS:
+    //  * all keys are `< 2^bits`, `bits >= 1`
+    //  * `min` and `max` are `None` iff the node is empty
+    //  * `min` is not stored in any cluster, `max` is stored in a cluster
+    //    unless `max == min`
+    //  * `clusters` only contains non-empty nodes with `low_bits(bits)` bits,
+    //    `summary` contains exactly the keys of `clusters` and is `None` if
+    //    `clusters` is empty, it has `bits - low_bits(bits)` bits
+    //  * `bits == 1` nodes have no clusters, `min` and `max` describe them fully
+    bits: u32,
+    min: Option<u64>,
+    max: Option<u64>,
+    summary: Option<Box<Node>>,
+    clusters: HashMap<u64, Node>,
+}
+
+fn low_bits(bits: u32) -> u32 {
+    bits / 2
+}
+
+impl Node {
+    fn new(bits: u32) -> Self {
+        Self {
+            bits,
+            min: None,
+            max: None,
+            summary: None,
+            clusters: HashMap::new(),
+        }
+    }
+
+    fn split(&self, key: u64) -> (u64, u64) {
+        let low = low_bits(self.bits);
+        (key >> low, key & ((1 << low) - 1))
+    }
+
+    fn join(&self, high: u64, low: u64) -> u64 {
+        (high << low_bits(self.bits)) | low
+    }
+
+    fn contains(&self, key: u64) -> bool {
+        if self.min == Some(key) || self.max == Some(key) {
+            return true;
+        }
+        if self.bits == 1 {
+            return false;
+        }
+        let (high, low) = self.split(key);
+        self.clusters
+            .get(&high)
+            .is_some_and(|cluster| cluster.contains(low))
+    }
+
+    /// Inserts `key` which must not be in the node.
+    fn insert(&mut self, mut key: u64) {
+        let Some(min) = self.min else {
+            self.min = Some(key);
+            self.max = Some(key);
+            return;
+        };
+
+        if key < min {
+            // new key becomes the min, push the old one down instead
+            self.min = Some(key);
+            key = min;
+        }
+
+        if self.bits > 1 {
+            let (high, low) = self.split(key);
+            let cluster_bits = low_bits(self.bits);
+            let cluster = self
+                .clusters
+                .entry(high)
+                .or_insert_with(|| Node::new(cluster_bits));
+            if cluster.min.is_none() {
+                // inserting into an empty cluster is O(1), so it's fine to
+                // also recurse into the summary
+                cluster.min = Some(low);
+                cluster.max = Some(low);
+                let summary_bits = self.bits - cluster_bits;
+                self.summary
+                    .get_or_insert_with(|| Box::new(Node::new(summary_bits)))
+                    .insert(high);
+            } else {
+                cluster.insert(low);
+            }
+        }
+
+        if self.max.is_some_and(|max| key > max) {
+            self.max = Some(key);
+        }
+    }
+
+    /// Deletes `key` which must be in the node.
+    fn delete(&mut self, mut key: u64) {
+        if self.min == self.max {
+            self.min = None;
+            self.max = None;
+            return;
+        }
+
+        if self.bits == 1 {
+            // both 0 and 1 are present, the other one remains
+            let other = 1 - key;
+            self.min = Some(other);
+            self.max = Some(other);
+            return;
+        }
+
+        if self.min == Some(key) {
+            // pull up the smallest key from clusters as the new min and
+            // delete it from its cluster instead
+            let summary = self
+                .summary
+                .as_ref()
+                .expect("non-empty node to have a summary");
+            let high = summary.min.expect("summary to be non-empty");
+            let low = self.clusters[&high].min.expect("clusters to be non-empty");
+            key = self.join(high, low);
+            self.min = Some(key);
+        }
+
+        let (high, low) = self.split(key);
+        let cluster = self.clusters.get_mut(&high).expect("key to be in the node");
+        cluster.delete(low);
+
+        if cluster.min.is_none() {
+            self.clusters.remove(&high);
+            let summary = self
+                .summary
+                .as_mut()
+                .expect("non-empty node to have a summary");
+            summary.delete(high);
+            if summary.min.is_none() {
+                self.summary = None;
+            }
+
+            if self.max == Some(key) {
+                self.max = match self.summary.as_ref().and_then(|s| s.max) {
+                    Some(high) => {
+                        let low = self.clusters[&high].max.expect("clusters to be non-empty");
+                        Some(self.join(high, low))
+                    }
+                    None => self.min,
+                };
+            }
+        } else if self.max == Some(key) {
+            let low = cluster.max.expect("cluster to be non-empty");
+            self.max = Some(self.join(high, low));
+        }
+    }
+
+    /// Smallest key `> key`.
+    fn successor(&self, key: u64) -> Option<u64> {
+        let min = self.min?;
+        if key < min {
+            return Some(min);
+        }
+        if self.bits == 1 {
+            return (key == 0 && self.max == Some(1)).then_some(1);
+        }
+
+        let (high, low) = self.split(key);
+        if let Some(cluster) = self.clusters.get(&high) {
+            if cluster.max.is_some_and(|max| low < max) {
+                let low = cluster.successor(low)?;
+                return Some(self.join(high, low));
+            }
+        }
+
+        let high = self.summary.as_ref()?.successor(high)?;
+        let low = self.clusters[&high].min?;
+        Some(self.join(high, low))
+    }
+
+    /// Largest key `< key`.
+    fn predecessor(&self, key: u64) -> Option<u64> {
+        let max = self.max?;
+        if key > max {
+            return Some(max);
+        }
+        if self.bits == 1 {
+            return (key == 1 && self.min == Some(0)).then_some(0);
+        }
+
+        let (high, low) = self.split(key);
+        if let Some(cluster) = self.clusters.get(&high) {
+            if cluster.min.is_some_and(|min| low > min) {
+                let low = cluster.predecessor(low)?;
+                return Some(self.join(high, low));
+            }
+        }
+
+        match self
+            .summary
+            .as_ref()
+            .and_then(|summary| summary.predecessor(high))
+        {
+            Some(high) => {
+                let low = self.clusters[&high].max?;
+                Some(self.join(high, low))
+            }
+            // min is not stored in the clusters
+            None => self.min.filter(|min| *min < key),
+        }
+    }
+}
+
+/// A set of unsigned integers backed by a van Emde Boas tree.
+///
+/// `insert`, `delete`, `contains`, `successor` and `predecessor` take
+/// `O(log log U)` time where `U = 2^K::BITS` is the size of the key universe.
+pub struct VebTree<K> {
+    // INVARIANTS:
+    //  * `root.bits == K::BITS`
+    //  * `len` is the number of keys in `root`
+    root: Node,
+    len: usize,
+    marker: core::marker::PhantomData<K>,
+}
+
+impl<K: Key> Default for VebTree<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Key> VebTree<K> {
+    pub fn new() -> Self {
+        Self {
+            root: Node::new(K::BITS),
+            len: 0,
+            marker: core::marker::PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.root = Node::new(K::BITS);
+        self.len = 0;
+    }
+
+    pub fn contains(&self, key: K) -> bool {
+        self.root.contains(key.to_u64())
+    }
+
+    /// Inserts `key` and returns `true` if it wasn't in the set before.
+    pub fn insert(&mut self, key: K) -> bool {
+        let key = key.to_u64();
+        if self.root.contains(key) {
+            return false;
+        }
+        self.root.insert(key);
+        self.len += 1;
+        true
+    }
+
+    /// Deletes `key` and returns `true` if it was in the set.
+    pub fn delete(&mut self, key: K) -> bool {
+        let key = key.to_u64();
+        if !self.root.contains(key) {
+            return false;
+        }
+        self.root.delete(key);
+        self.len -= 1;
+        true
+    }
+
+    pub fn min(&self) -> Option<K> {
+        self.root.min.map(K::from_u64)
+    }
+
+    pub fn max(&self) -> Option<K> {
+        self.root.max.map(K::from_u64)
+    }
+
+    /// Returns the smallest key strictly greater than `key`.
+    pub fn successor(&self, key: K) -> Option<K> {
+        self.root.successor(key.to_u64()).map(K::from_u64)
+    }
+
+    /// Returns the largest key strictly less than `key`.
+    pub fn predecessor(&self, key: K) -> Option<K> {
+        self.root.predecessor(key.to_u64()).map(K::from_u64)
+    }
+
+    /// Iterate over all keys in ascending order.
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter {
+            tree: self,
+            next: self.min(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: Key> FromIterator<K> for VebTree<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<K: Key> Extend<K> for VebTree<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+impl<K> fmt::Debug for VebTree<K>
+where
+    K: Key + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, K: Key> IntoIterator for &'a VebTree<K> {
+    type Item = K;
+    type IntoIter = Iter<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, K> {
+    tree: &'a VebTree<K>,
+    next: Option<K>,
+    len: usize,
+}
+
+impl<K: Key> Iterator for Iter<'_, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+        self.next = self.tree.successor(current);
+        self.len -= 1;
+        Some(current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K: Key> ExactSizeIterator for Iter<'_, K> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_api() {
+        let mut tree = VebTree::<u32>::new();
+        assert!(tree.is_empty());
+        assert_eq!(tree.min(), None);
+        assert_eq!(tree.successor(0), None);
+        assert_eq!(tree.predecessor(u32::MAX), None);
+
+        for key in [5, 1, u32::MAX, 70_000, 3, 0] {
+            assert!(tree.insert(key));
+        }
+        assert!(!tree.insert(5));
+        assert_eq!(tree.len(), 6);
+        assert!(tree.contains(70_000));
+        assert!(!tree.contains(4));
+        assert_eq!(tree.min(), Some(0));
+        assert_eq!(tree.max(), Some(u32::MAX));
+
+        assert_eq!(tree.successor(3), Some(5));
+        assert_eq!(tree.successor(6), Some(70_000));
+        assert_eq!(tree.successor(u32::MAX), None);
+        assert_eq!(tree.predecessor(70_000), Some(5));
+        assert_eq!(tree.predecessor(0), None);
+        assert!(tree.iter().eq([0, 1, 3, 5, 70_000, u32::MAX]));
+        assert_eq!(format!("{tree:?}"), "{0, 1, 3, 5, 70000, 4294967295}");
+
+        assert!(tree.delete(0));
+        assert!(tree.delete(u32::MAX));
+        assert!(!tree.delete(0));
+        assert_eq!(tree.min(), Some(1));
+        assert_eq!(tree.max(), Some(70_000));
+        assert!(tree.iter().eq([1, 3, 5, 70_000]));
+
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+    }
+
+    #[test]
+    fn full_u8_universe() {
+        let mut tree: VebTree<u8> = (0..=u8::MAX).collect();
+        assert_eq!(tree.len(), 256);
+        assert!(tree.iter().eq(0..=u8::MAX));
+
+        for key in (0..=u8::MAX).filter(|k| k % 3 != 0) {
+            assert!(tree.delete(key));
+        }
+        assert!(tree.iter().eq((0..=u8::MAX).step_by(3)));
+        assert_eq!(tree.successor(4), Some(6));
+        assert_eq!(tree.predecessor(4), Some(3));
+    }
+
+    mod proptests {
+        use std::collections::BTreeSet;
+
+        use proptest::prelude::*;
+
+        use super::*;
+
+        #[cfg(not(miri))]
+        const SET_SIZE: usize = 1000;
+        #[cfg(miri)]
+        const SET_SIZE: usize = 50;
+
+        #[cfg(not(miri))]
+        const PROPTEST_CASES: u32 = 500;
+        #[cfg(miri)]
+        const PROPTEST_CASES: u32 = 10;
+
+        /// Runs inserts, queries and deletes against a `BTreeSet`.
+        fn check<K: Key + fmt::Debug>(inserts: Vec<K>, deletes: Vec<K>, queries: Vec<K>) {
+            let mut tree = VebTree::new();
+            let mut expected = BTreeSet::new();
+            for key in inserts {
+                assert_eq!(tree.insert(key), expected.insert(key));
+            }
+            for key in deletes {
+                assert_eq!(tree.delete(key), expected.remove(&key));
+            }
+
+            assert_eq!(tree.len(), expected.len());
+            assert_eq!(tree.min(), expected.first().copied());
+            assert_eq!(tree.max(), expected.last().copied());
+            assert!(tree.iter().eq(expected.iter().copied()));
+            for key in queries.into_iter().chain(expected.iter().copied()) {
+                assert_eq!(tree.contains(key), expected.contains(&key));
+                assert_eq!(
+                    tree.successor(key),
+                    expected
+                        .range((core::ops::Bound::Excluded(key), core::ops::Bound::Unbounded))
+                        .next()
+                        .copied()
+                );
+                assert_eq!(
+                    tree.predecessor(key),
+                    expected.range(..key).next_back().copied()
+                );
+            }
+        }
+
+        proptest!(
+            #![proptest_config(ProptestConfig::with_cases(PROPTEST_CASES))]
+
+            #[test]
+            fn u16_against_btree_set(
+                inserts in proptest::collection::vec(any::<u16>(), 0..SET_SIZE),
+                deletes in proptest::collection::vec(any::<u16>(), 0..SET_SIZE),
+                queries in proptest::collection::vec(any::<u16>(), 0..100),
+            ) {
+                // deleting inserted keys exercises the min/max bookkeeping
+                let deletes = inserts.iter().step_by(2).copied().chain(deletes).collect();
+                check(inserts, deletes, queries);
+            }
+
+            #[test]
+            fn u32_against_btree_set(
+                inserts in proptest::collection::vec(any::<u32>(), 0..SET_SIZE),
+                queries in proptest::collection::vec(any::<u32>(), 0..100),
+            ) {
+                let deletes = inserts.iter().step_by(2).copied().collect();
+                check(inserts, deletes, queries);
+            }
+
+            #[test]
+            fn u64_against_btree_set(
+                inserts in proptest::collection::vec(any::<u64>(), 0..SET_SIZE),
+                queries in proptest::collection::vec(any::<u64>(), 0..100),
+            ) {
+                let deletes = inserts.iter().step_by(2).copied().collect();
+                check(inserts, deletes, queries);
+            }
+
+            #[test]
+            fn small_u8_range(
+                inserts in proptest::collection::vec(0..16u8, 0..32),
+                deletes in proptest::collection::vec(0..16u8, 0..32),
+                queries in proptest::collection::vec(0..16u8, 0..16),
+            ) {
+                check(inserts, deletes, queries);
+            }
+        );
+    }
+}
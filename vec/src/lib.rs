@@ -2,5 +2,12 @@
 #![deny(rust_2018_idioms)]
 #![deny(unsafe_op_in_unsafe_fn)]
 
+mod queue;
+mod stack;
 mod vec;
 mod vec_deque;
+
+pub use crate::queue::Queue;
+pub use crate::stack::Stack;
+pub use crate::vec::{IntoIter, Vec2};
+pub use crate::vec_deque::VecDeque2;
@@ -0,0 +1,95 @@
+use core::fmt;
+
+use crate::vec_deque::VecDeque2;
+
+/// FIFO queue backed by a [`VecDeque2`].
+///
+/// Only exposes the operations that make sense for a queue, items are
+/// enqueued at the back and dequeued from the front.
+pub struct Queue<T> {
+    buf: VecDeque2<T>,
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        Self {
+            buf: VecDeque2::new(),
+        }
+    }
+
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            buf: VecDeque2::with_capacity(cap),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn enqueue(&mut self, val: T) {
+        self.buf.push_back(val)
+    }
+
+    pub fn dequeue(&mut self) -> Option<T> {
+        self.buf.pop_front()
+    }
+
+    /// Returns a reference to the item that would be dequeued next.
+    pub fn peek(&self) -> Option<&T> {
+        self.buf.get(0)
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Queue<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (right, left) = self.buf.as_slices();
+        f.debug_list().entries(right.iter().chain(left)).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let mut q = Queue::new();
+        assert!(q.is_empty());
+        assert_eq!(q.peek(), None);
+        assert_eq!(q.dequeue(), None);
+
+        q.enqueue(1);
+        q.enqueue(2);
+        q.enqueue(3);
+        assert_eq!(q.len(), 3);
+        assert_eq!(q.peek(), Some(&1));
+        assert_eq!(q.dequeue(), Some(1));
+
+        // wrap around the underlying buffer
+        q.enqueue(4);
+        q.enqueue(5);
+        assert_eq!(format!("{:?}", q), "[2, 3, 4, 5]");
+
+        assert_eq!(q.dequeue(), Some(2));
+        assert_eq!(q.dequeue(), Some(3));
+        assert_eq!(q.peek(), Some(&4));
+        assert_eq!(q.dequeue(), Some(4));
+        assert_eq!(q.dequeue(), Some(5));
+        assert_eq!(q.dequeue(), None);
+        assert!(q.is_empty());
+    }
+}
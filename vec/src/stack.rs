@@ -0,0 +1,91 @@
+use core::fmt;
+
+use crate::vec::Vec2;
+
+/// LIFO stack backed by a [`Vec2`].
+///
+/// Only exposes the operations that make sense for a stack, items are
+/// pushed to and popped from the top.
+pub struct Stack<T> {
+    buf: Vec2<T>,
+}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Self { buf: Vec2::new() }
+    }
+
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            buf: Vec2::with_capacity(cap),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn push(&mut self, val: T) {
+        self.buf.push(val)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.buf.pop()
+    }
+
+    /// Returns a reference to the item that would be popped next.
+    pub fn peek(&self) -> Option<&T> {
+        self.buf.as_slice().last()
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Stack<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // top of the stack first
+        f.debug_list()
+            .entries(self.buf.as_slice().iter().rev())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works() {
+        let mut s = Stack::new();
+        assert!(s.is_empty());
+        assert_eq!(s.peek(), None);
+        assert_eq!(s.pop(), None);
+
+        s.push(1);
+        s.push(2);
+        s.push(3);
+        assert_eq!(s.len(), 3);
+        assert_eq!(s.peek(), Some(&3));
+        assert_eq!(format!("{:?}", s), "[3, 2, 1]");
+
+        assert_eq!(s.pop(), Some(3));
+        s.push(4);
+        assert_eq!(s.pop(), Some(4));
+        assert_eq!(s.pop(), Some(2));
+        assert_eq!(s.peek(), Some(&1));
+        assert_eq!(s.pop(), Some(1));
+        assert_eq!(s.pop(), None);
+        assert!(s.is_empty());
+    }
+}
@@ -7,7 +7,7 @@ use core::{fmt, mem, ptr, slice};
 
 use crate_alloc::alloc;
 
-struct Vec2<T> {
+pub struct Vec2<T> {
     // INVARIANTS:
     //  * `len <= cap <= isize::MAX`
     //  * first `len` elements in `buf` are initialized
@@ -34,6 +34,29 @@ where
     }
 }
 
+impl<T> Default for Vec2<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: `Vec2` owns its items like `Vec` does
+unsafe impl<T: Send> Send for Vec2<T> {}
+unsafe impl<T: Sync> Sync for Vec2<T> {}
+
+impl<T> Clone for Vec2<T>
+where
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        let mut new = Self::with_capacity(self.len);
+        for item in self.as_slice() {
+            new.push(item.clone());
+        }
+        new
+    }
+}
+
 impl<T> Drop for Vec2<T> {
     fn drop(&mut self) {
         if self.cap == 0 {
@@ -99,6 +122,11 @@ impl<T> Vec2<T> {
         self.len == 0
     }
 
+    /// Number of bytes allocated for the buffer, including the unused capacity.
+    pub fn allocated_bytes(&self) -> usize {
+        self.cap * mem::size_of::<T>()
+    }
+
     pub fn as_slice(&self) -> &[T] {
         // SAFETY:
         //  * if `len == cap == 0` then `self.buf == NonNull::dangling`,
@@ -109,6 +137,12 @@ impl<T> Vec2<T> {
         unsafe { slice::from_raw_parts(self.buf.as_ptr().cast_const(), self.len) }
     }
 
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: same as in `as_slice`, additionally the returned slice
+        //  borrows `self` mutably so no other references to items can exist
+        unsafe { slice::from_raw_parts_mut(self.buf.as_ptr(), self.len) }
+    }
+
     pub fn push(&mut self, val: T) {
         if self.len == self.cap {
             self.grow()
@@ -156,6 +190,18 @@ impl<T> Vec2<T> {
         unsafe { Some(&*ptr) }
     }
 
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if !self.is_in_bounds(index) {
+            return None;
+        }
+
+        // SAFETY: index is in bounds (checked above)
+        let ptr = unsafe { self.get_raw_unchecked(index) };
+        // SAFETY: same as in `get`, additionally the returned reference borrows
+        //  `self` mutably so no other references to the item can exist
+        unsafe { Some(&mut *ptr) }
+    }
+
     pub fn remove(&mut self, index: usize) -> Option<T> {
         if !self.is_in_bounds(index) {
             return None;
@@ -226,6 +272,45 @@ impl<T> Vec2<T> {
         Ok(())
     }
 
+    /// Removes the item at `index`, the last item takes its place.
+    ///
+    /// Unlike [`Vec2::remove`] this is O(1) but it doesn't keep the order.
+    pub fn swap_remove(&mut self, index: usize) -> Option<T> {
+        if !self.is_in_bounds(index) {
+            return None;
+        }
+
+        let last = self.len - 1;
+        // SAFETY:
+        //  * index is in bounds (checked above) and no-one has references to it
+        //  * this item will never be read again, only written over
+        let val = unsafe { self.read_at(index) };
+        self.len = last;
+        if index != last {
+            // SAFETY: the last item is moved into the slot at `index` which
+            //  was just read out, thus first `self.len` items are initialized
+            unsafe {
+                let last_val = self.read_at(last);
+                self.write_at(index, last_val);
+            }
+        }
+
+        Some(val)
+    }
+
+    /// Drops all items, the capacity stays the same.
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+
+    /// Reserves capacity for at least `additional` more items.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len.checked_add(additional).expect("capacity overflow");
+        if needed > self.cap {
+            self.grow_to(needed.max(self.cap * 2));
+        }
+    }
+
     /// # SAFETY
     ///
     ///  * first `new_len` elements in `self.buf` must be properly initialized
@@ -239,7 +324,7 @@ impl<T> Vec2<T> {
     ///
     /// This means that:
     /// * `new_buf` is valid pointer to contiguous memory to store `new_cap` `T`s
-    ///    (it can only be `NonNull::dangling` if `new_cap == self.len == 0`)
+    ///   (it can only be `NonNull::dangling` if `new_cap == self.len == 0`)
     /// * first `self.len` elements in `new_buf` must be properly initialized
     /// * `self.len <= new_cap <= isize::MAX`
     unsafe fn set_buf(&mut self, new_buf: NonNull<T>, new_cap: usize) {
@@ -390,6 +475,77 @@ impl<T> Vec2<T> {
     }
 }
 
+impl<T> IntoIterator for Vec2<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        let end = self.len;
+        // SAFETY: the items are owned by `IntoIter` from now on, `Vec2` only
+        //  frees the buffer
+        unsafe { self.set_len(0) };
+        IntoIter {
+            vec: self,
+            start: 0,
+            end,
+        }
+    }
+}
+
+/// Owning iterator over the items of a [`Vec2`]
+pub struct IntoIter<T> {
+    // INVARIANTS: items in `vec.buf[start..end]` are initialized and owned by
+    //  the iterator, `vec.len == 0`
+    vec: Vec2<T>,
+    start: usize,
+    end: usize,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        // SAFETY: `start < end`, the item is initialized and never read again
+        let val = unsafe { self.vec.get_raw_unchecked(self.start).read() };
+        self.start += 1;
+        Some(val)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        // SAFETY: `start <= end`, the item is initialized and never read again
+        let val = unsafe { self.vec.get_raw_unchecked(self.end).read() };
+        Some(val)
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> Drop for IntoIter<T> {
+    fn drop(&mut self) {
+        // SAFETY: `start..end` are the items left, by the invariants. If one of
+        //  them panics on drop the rest are still dropped and `vec` frees the
+        //  buffer.
+        unsafe {
+            let rest = self.vec.get_raw_unchecked(self.start);
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(rest, self.end - self.start));
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::panic::AssertUnwindSafe;
@@ -402,6 +558,15 @@ mod tests {
         a
     }
 
+    #[test]
+    fn allocated_bytes() {
+        let mut v = Vec2::<u32>::new();
+        assert_eq!(v.allocated_bytes(), 0);
+        v.push(1);
+        assert_eq!(v.allocated_bytes(), v.cap * 4);
+        assert_eq!(Vec2::<u64>::with_capacity(10).allocated_bytes(), 80);
+    }
+
     #[test]
     fn it_works() {
         let mut v = Vec2::new();
@@ -452,6 +617,18 @@ mod tests {
         assert_eq!(v.get(3), None);
     }
 
+    #[test]
+    fn get_mut() {
+        let mut v = Vec2::new();
+        v.push(2);
+        v.push(3);
+
+        *v.get_mut(1).unwrap() = 5;
+        assert_eq!(v.get_mut(2), None);
+        v.as_mut_slice()[0] += 10;
+        assert_eq!(v.as_slice(), &[12, 5]);
+    }
+
     #[test]
     fn remove() {
         let mut v = Vec2::new();
@@ -491,6 +668,51 @@ mod tests {
         assert_eq!(v.pop(), None);
     }
 
+    #[test]
+    fn swap_remove() {
+        let mut v = Vec2::new();
+        assert_eq!(v.swap_remove(0), None);
+        for i in 0..5 {
+            v.push(i.to_string());
+        }
+        assert_eq!(v.swap_remove(1).as_deref(), Some("1")); // middle
+        assert_eq!(v.swap_remove(3).as_deref(), Some("3")); // last
+        assert_eq!(v.swap_remove(0).as_deref(), Some("0")); // first
+        assert_eq!(v.as_slice(), &["2", "4"]);
+    }
+
+    #[test]
+    fn clear_reserve_clone() {
+        let mut v = Vec2::new();
+        v.reserve(5);
+        assert_eq!(v.allocated_bytes(), 5 * mem::size_of::<String>());
+        for i in 0..3 {
+            v.push(i.to_string());
+        }
+        let c = v.clone();
+        v.clear();
+        assert!(v.is_empty());
+        assert_eq!(v.allocated_bytes(), 5 * mem::size_of::<String>());
+        assert_eq!(c.as_slice(), &["0", "1", "2"]);
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut v = Vec2::new();
+        for i in 0..5 {
+            v.push(i.to_string());
+        }
+        let mut iter = v.clone().into_iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next().as_deref(), Some("0"));
+        assert_eq!(iter.next_back().as_deref(), Some("4"));
+        assert_eq!(iter.len(), 3);
+        // the rest are dropped with the iterator
+        drop(iter);
+
+        assert!(v.into_iter().eq(["0", "1", "2", "3", "4"]));
+    }
+
     #[test]
     fn panic_in_drop() {
         static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
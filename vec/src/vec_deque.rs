@@ -4,10 +4,11 @@ use core::alloc::Layout;
 use core::marker::PhantomData;
 use core::ptr::NonNull;
 use core::{fmt, mem, ptr, slice};
+use std::io::{self, IoSlice, IoSliceMut};
 
 use crate_alloc::alloc;
 
-struct VecDeque2<T> {
+pub struct VecDeque2<T> {
     // INVARIANTS:
     //  * `len <= cap` and `head < cap` or if `cap == 0` then `head == len == cap == 0`
     //  * `len` contiguous elements are initialized in `buf` starting from `head`
@@ -35,6 +36,12 @@ where
     }
 }
 
+impl<T> Default for VecDeque2<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Drop for VecDeque2<T> {
     fn drop(&mut self) {
         if self.cap == 0 {
@@ -73,6 +80,156 @@ impl<T> Drop for VecDeque2<T> {
     }
 }
 
+#[cfg(feature = "serde")]
+impl<T> serde::Serialize for VecDeque2<T>
+where
+    T: serde::Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        // Serialize in logical order (front to back), the layout of `self.buf` is not observable
+        let (right, left) = self.as_slices();
+        serializer.collect_seq(right.iter().chain(left))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T> serde::Deserialize<'de> for VecDeque2<T>
+where
+    T: serde::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct SeqVisitor<U>(PhantomData<U>);
+
+        impl<'de, U> serde::de::Visitor<'de> for SeqVisitor<U>
+        where
+            U: serde::Deserialize<'de>,
+        {
+            type Value = VecDeque2<U>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a sequence")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                // Don't trust the size hint blindly, malicious input could make us
+                // allocate huge buffers up front
+                const MAX_PREALLOC: usize = 4096;
+                let cap = seq.size_hint().unwrap_or(0).min(MAX_PREALLOC);
+                // Items are pushed to the back of a fresh buffer, thus `head == 0`
+                // and they will be stored contiguously
+                let mut out = VecDeque2::with_capacity(cap);
+                while let Some(val) = seq.next_element()? {
+                    out.push_back(val);
+                }
+                Ok(out)
+            }
+        }
+
+        deserializer.deserialize_seq(SeqVisitor(PhantomData))
+    }
+}
+
+impl VecDeque2<u8> {
+    /// Returns the contents as (at most) two `IoSlice`s in logical order,
+    /// suitable for vectored writes. The second slice is empty if the contents
+    /// are not wrapped around the buffer.
+    pub fn as_io_slices(&self) -> [IoSlice<'_>; 2] {
+        let (right, left) = self.as_slices();
+        [IoSlice::new(right), IoSlice::new(left)]
+    }
+
+    /// Same as [`Self::as_io_slices`] but for mutable access to the contents,
+    /// suitable for vectored reads into already initialized items.
+    pub fn as_io_slices_mut(&mut self) -> [IoSliceMut<'_>; 2] {
+        let (right, left) = self.as_mut_slices();
+        [IoSliceMut::new(right), IoSliceMut::new(left)]
+    }
+
+    /// Remove `count` bytes from the front.
+    ///
+    /// `u8` doesn't need to be dropped, so it's enough to just move the head.
+    fn advance_front(&mut self, count: usize) {
+        debug_assert!(count <= self.len);
+        if count == 0 {
+            return;
+        }
+        self.head = (self.head + count) % self.cap;
+        self.len -= count;
+    }
+
+    /// Append all bytes from `bytes` to the back.
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.reserve(bytes.len());
+
+        // [left] [free] [right] or [free] [items] [free]
+        //        ^- tail           ^- head        ^- tail
+        let tail = (self.head + self.len) % self.cap;
+        let first_count = usize::min(bytes.len(), self.cap - tail);
+        let second_count = bytes.len() - first_count;
+        // SAFETY:
+        //  * we reserved enough space above, so there are at least `bytes.len()`
+        //    free slots after the last item (when wrapping around the buffer)
+        //  * `tail + first_count <= self.cap` and `second_count <= self.head`
+        //    since free space ends at `self.head` when it wraps around
+        //  * `bytes` cannot overlap with our buffer since we hold `&mut self`
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), self.get_raw_unchecked(tail), first_count);
+            ptr::copy_nonoverlapping(
+                bytes.as_ptr().add(first_count),
+                self.buf.as_ptr(),
+                second_count,
+            );
+        }
+        self.len += bytes.len();
+    }
+}
+
+impl io::Read for VecDeque2<u8> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (right, left) = self.as_slices();
+        let right_count = usize::min(right.len(), buf.len());
+        buf[..right_count].copy_from_slice(&right[..right_count]);
+        let left_count = usize::min(left.len(), buf.len() - right_count);
+        buf[right_count..right_count + left_count].copy_from_slice(&left[..left_count]);
+
+        let count = right_count + left_count;
+        self.advance_front(count);
+        Ok(count)
+    }
+}
+
+impl io::Write for VecDeque2<u8> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let total = bufs.iter().map(|b| b.len()).sum();
+        self.reserve(total);
+        for buf in bufs {
+            self.extend_from_slice(buf);
+        }
+        Ok(total)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 impl<T> VecDeque2<T> {
     // Notes:
     //  * On any allocation error we panic for now
@@ -105,6 +262,11 @@ impl<T> VecDeque2<T> {
         self.len == 0
     }
 
+    /// Number of bytes allocated for the buffer, including the unused capacity.
+    pub fn allocated_bytes(&self) -> usize {
+        self.cap * mem::size_of::<T>()
+    }
+
     /// Right and left counts assuming that self is wrapped around.
     ///
     /// It is safe to call while self is not wrapped but the counts are wrong.
@@ -216,6 +378,44 @@ impl<T> VecDeque2<T> {
         }
     }
 
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        if self.cap == 0 {
+            // self.buf is dangling as we haven't initialized it
+            return (&mut [], &mut []);
+        }
+        if self.is_wrapped() {
+            let (right_count, left_count) = self.right_left_counts();
+
+            // SAFETY: `self.cap > 0` is checked above
+            let right_start = unsafe { self.head_ptr() };
+            // SAFETY:
+            //  * same as in `as_slices`
+            //  * the two slices don't overlap since `left_count <= self.head`
+            //  * all previously given out references are bound to a borrow of self,
+            //    by taking `&mut self` none of those can be alive
+            let right = unsafe { slice::from_raw_parts_mut(right_start, right_count) };
+            let left = unsafe { slice::from_raw_parts_mut(self.buf.as_ptr(), left_count) };
+            (right, left)
+        } else {
+            // SAFETY: same as in `as_slices`
+            let right = unsafe { slice::from_raw_parts_mut(self.head_ptr(), self.len) };
+            (right, &mut [])
+        }
+    }
+
+    /// Reserve capacity for at least `additional` more items.
+    ///
+    /// Grows at least by doubling, so repeated small reservations are amortized.
+    pub fn reserve(&mut self, additional: usize) {
+        let required = self.len.checked_add(additional).expect("capacity overflow");
+        if required <= self.cap {
+            return;
+        }
+
+        let new_cap = usize::max(required, self.cap * 2).max(Self::INITIAL_CAP);
+        self.grow_to(new_cap);
+    }
+
     #[inline]
     fn current_layout(&self) -> Layout {
         // This cannot return Err variant as we have already checked it
@@ -397,7 +597,7 @@ impl<T> VecDeque2<T> {
         Some(val)
     }
 
-    pub fn get(&mut self, index: usize) -> Option<&T> {
+    pub fn get(&self, index: usize) -> Option<&T> {
         if !self.is_in_bounds(index) {
             return None;
         }
@@ -527,6 +727,15 @@ mod tests {
         a
     }
 
+    #[test]
+    fn allocated_bytes() {
+        let mut v = VecDeque2::<u32>::new();
+        assert_eq!(v.allocated_bytes(), 0);
+        v.push_front(1);
+        assert_eq!(v.allocated_bytes(), v.cap * 4);
+        assert_eq!(VecDeque2::<u64>::with_capacity(10).allocated_bytes(), 80);
+    }
+
     #[test]
     fn push() {
         let mut v = VecDeque2::new();
@@ -597,6 +806,83 @@ mod tests {
         assert_eq!(v.get(7), None);
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let mut v = VecDeque2::new();
+        v.push_back(2);
+        v.push_front(3);
+        v.push_back(4);
+        v.push_front(5);
+        v.push_front(6);
+        assert!(v.is_wrapped());
+
+        let json = serde_json::to_string(&v).unwrap();
+        assert_eq!(json, "[6,5,3,2,4]");
+
+        let mut de: VecDeque2<i32> = serde_json::from_str(&json).unwrap();
+        assert!(!de.is_wrapped());
+        assert_eq!(de.as_slices(), (&[6, 5, 3, 2, 4][..], &[][..]));
+        assert_eq!(de.pop_front(), Some(6));
+        assert_eq!(de.pop_back(), Some(4));
+
+        let empty: VecDeque2<i32> = serde_json::from_str("[]").unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn as_mut_slices() {
+        let mut v = VecDeque2::new();
+        v.push_back(2);
+        v.push_back(3);
+        v.push_front(1);
+        v.push_front(0);
+        assert!(v.is_wrapped());
+
+        let (right, left) = v.as_mut_slices();
+        right.iter_mut().chain(left).for_each(|x| *x *= 10);
+        assert_eq!(v.pop_front(), Some(0));
+        assert_eq!(v.pop_front(), Some(10));
+        assert_eq!(v.pop_front(), Some(20));
+        assert_eq!(v.pop_front(), Some(30));
+    }
+
+    #[test]
+    fn io_read_write() {
+        use std::io::{Read, Write};
+
+        let mut v = VecDeque2::<u8>::new();
+        let mut out = [0; 8];
+        assert_eq!(v.read(&mut out).unwrap(), 0);
+
+        v.write_all(b"hello").unwrap();
+        assert_eq!(v.read(&mut out[..3]).unwrap(), 3);
+        assert_eq!(&out[..3], b"hel");
+
+        // free space is now both before and after the remaining items
+        v.write_all(b" w").unwrap();
+        assert!(v.is_wrapped());
+        let [a, b] = v.as_io_slices();
+        assert_eq!(&*a, b"lo");
+        assert_eq!(&*b, b" w");
+
+        v.write_all(b"orld").unwrap();
+
+        let written = v
+            .write_vectored(&[IoSlice::new(b"!"), IoSlice::new(b"?")])
+            .unwrap();
+        assert_eq!(written, 2);
+
+        for s in v.as_io_slices_mut().iter_mut() {
+            s.make_ascii_uppercase();
+        }
+
+        let mut rest = Vec::new();
+        v.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"LO WORLD!?");
+        assert!(v.is_empty());
+    }
+
     #[test]
     fn panic_in_drop() {
         static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);